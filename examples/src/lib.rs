@@ -7,6 +7,7 @@ mod animation;
 mod cube;
 mod cube_no_framework;
 mod egui;
+mod many_lights;
 mod scene_viewer;
 mod skinning;
 mod static_gltf;
@@ -28,6 +29,7 @@ const EXAMPLES: &[ExampleDesc] = &[
     ExampleDesc { name: "cube", run: cube::main },
     ExampleDesc { name: "cube-no-framework", run: cube_no_framework::main },
     ExampleDesc { name: "egui", run: egui::main },
+    ExampleDesc { name: "many_lights", run: many_lights::main },
     ExampleDesc { name: "scene_viewer", run: scene_viewer::main },
     ExampleDesc { name: "skinning", run: skinning::main },
     ExampleDesc { name: "static_gltf", run: static_gltf::main },
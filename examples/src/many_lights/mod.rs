@@ -0,0 +1,218 @@
+//! 16 shadow casting spot lights circling over a field of cubes, logging the
+//! average frame time every few seconds. Used to measure the cost of many
+//! shadowed lights, like the per-camera bind groups and buffers recreated
+//! every frame.
+
+use std::time::Duration;
+
+use web_time::Instant;
+
+const LIGHT_GRID: usize = 4;
+const CUBE_GRID: i32 = 8;
+/// How often the average frame time is logged.
+const REPORT_INTERVAL: Duration = Duration::from_secs(5);
+
+struct Report {
+    start: Instant,
+    frames: u32,
+    /// Sum of the cpu time spent recording and submitting every frame.
+    cpu_time: Duration,
+}
+
+pub struct ManyLightsExample {
+    objects: Vec<rend3::types::ObjectHandle>,
+    spot_lights: Vec<rend3::types::SpotLightHandle>,
+    start: Instant,
+    report: Report,
+}
+
+impl Default for ManyLightsExample {
+    fn default() -> Self {
+        let now = Instant::now();
+        Self {
+            objects: Vec::new(),
+            spot_lights: Vec::new(),
+            start: now,
+            report: Report { start: now, frames: 0, cpu_time: Duration::ZERO },
+        }
+    }
+}
+
+/// Position and direction of the given light, `time` seconds in.
+fn light_placement(index: usize, time: f32) -> (glam::Vec3, glam::Vec3) {
+    let x = (index % LIGHT_GRID) as f32 - (LIGHT_GRID - 1) as f32 / 2.0;
+    let z = (index / LIGHT_GRID) as f32 - (LIGHT_GRID - 1) as f32 / 2.0;
+    let angle = time + index as f32;
+    let position = glam::Vec3::new(x * 6.0, 6.0, z * 6.0);
+    let target = position + glam::Vec3::new(angle.cos() * 3.0, -6.0, angle.sin() * 3.0);
+    (position, target - position)
+}
+
+impl rend3_framework::App for ManyLightsExample {
+    const HANDEDNESS: rend3::types::Handedness = rend3::types::Handedness::Left;
+
+    fn sample_count(&self) -> rend3::types::SampleCount {
+        rend3::types::SampleCount::One
+    }
+
+    fn setup(&mut self, context: rend3_framework::SetupContext<'_>) {
+        // Timestamp queries, if supported, also accumulate gpu frame times.
+        context.renderer.reset_accumulated_stats();
+
+        let mesh_handle = context.renderer.add_mesh(crate::cube::create_mesh()).unwrap();
+        let material_handle = context.renderer.add_material(rend3_routine::pbr::PbrMaterial {
+            albedo: rend3_routine::pbr::AlbedoComponent::Value(glam::Vec4::new(0.6, 0.6, 0.6, 1.0)),
+            ..rend3_routine::pbr::PbrMaterial::default()
+        });
+
+        let mut add_cube = |transform| {
+            self.objects.push(context.renderer.add_object(rend3::types::Object {
+                mesh_kind: rend3::types::ObjectMeshKind::Static(mesh_handle.clone()),
+                material: material_handle.clone(),
+                transform,
+                flags: rend3::types::ObjectFlags::default(),
+                layer_mask: rend3::types::ALL_LAYERS,
+            }));
+        };
+
+        // Ground
+        add_cube(glam::Mat4::from_scale_rotation_translation(
+            glam::Vec3::new(30.0, 0.1, 30.0),
+            glam::Quat::IDENTITY,
+            glam::Vec3::new(0.0, -0.1, 0.0),
+        ));
+        for x in -CUBE_GRID / 2..CUBE_GRID / 2 {
+            for z in -CUBE_GRID / 2..CUBE_GRID / 2 {
+                add_cube(glam::Mat4::from_scale_rotation_translation(
+                    glam::Vec3::splat(0.4),
+                    glam::Quat::from_rotation_y(x as f32 + z as f32),
+                    glam::Vec3::new(x as f32 * 3.0 + 1.5, 0.4, z as f32 * 3.0 + 1.5),
+                ));
+            }
+        }
+
+        let view_location = glam::Vec3::new(0.0, 18.0, -22.0);
+        let view = glam::Mat4::look_at_lh(view_location, glam::Vec3::ZERO, glam::Vec3::Y);
+        context.renderer.set_camera_data(rend3::types::Camera {
+            projection: rend3::types::CameraProjection::Perspective { vfov: 60.0, near: 0.1 },
+            view,
+            layer_mask: rend3::types::ALL_LAYERS,
+        });
+
+        for index in 0..LIGHT_GRID * LIGHT_GRID {
+            let (position, direction) = light_placement(index, 0.0);
+            let hue = index as f32 / (LIGHT_GRID * LIGHT_GRID) as f32 * std::f32::consts::TAU;
+            self.spot_lights.push(context.renderer.add_spot_light(rend3::types::SpotLight {
+                position,
+                direction,
+                inner_angle: 20.0,
+                outer_angle: 35.0,
+                range: 20.0,
+                color: glam::Vec3::new(hue.cos(), (hue + 2.1).cos(), (hue + 4.2).cos()) * 0.5 + 0.5,
+                intensity: 40.0,
+                resolution: 512,
+                shadow_bias: 0.0,
+                shadow_slope_bias: 0.0,
+                shadow_normal_offset: 0.0,
+            }));
+        }
+    }
+
+    fn handle_redraw(&mut self, context: rend3_framework::RedrawContext<'_, ()>) {
+        let time = self.start.elapsed().as_secs_f32();
+        for (index, light) in self.spot_lights.iter().enumerate() {
+            let (position, direction) = light_placement(index, time);
+            context.renderer.update_spot_light(
+                light,
+                rend3::types::SpotLightChange {
+                    position: Some(position),
+                    direction: Some(direction),
+                    ..Default::default()
+                },
+            );
+        }
+
+        context.renderer.swap_instruction_buffers();
+        let mut eval_output = context.renderer.evaluate_instructions();
+
+        let pbr_routine = rend3_framework::lock(&context.routines.pbr);
+        let tonemapping_routine = rend3_framework::lock(&context.routines.tonemapping);
+
+        let mut graph = rend3::graph::RenderGraph::new();
+        let frame_handle = graph.add_imported_render_target(
+            context.surface_texture,
+            0..1,
+            0..1,
+            rend3::graph::ViewportRect::from_size(context.resolution),
+        );
+        context.base_rendergraph.add_to_graph(
+            &mut graph,
+            rend3_routine::base::BaseRenderGraphInputs {
+                eval_output: &eval_output,
+                routines: rend3_routine::base::BaseRenderGraphRoutines {
+                    pbr: &pbr_routine,
+                    skybox: None,
+                    tonemapping: &tonemapping_routine,
+                },
+                target: rend3_routine::base::OutputRenderTarget {
+                    handle: frame_handle,
+                    resolution: context.resolution,
+                    samples: context.sample_count,
+                },
+            },
+            rend3_routine::base::BaseRenderGraphSettings {
+                ambient_color: glam::Vec4::new(0.02, 0.02, 0.02, 1.0),
+                ambient_intensity: 1.0,
+                clear_color: glam::Vec4::new(0.02, 0.02, 0.03, 1.0),
+                time,
+                shadow_filter: rend3_routine::uniforms::ShadowFilter::Pcf3x3,
+                background_intensity: 1.0,
+                exposure: 1.0,
+                tonemapping_operator: rend3_routine::tonemapping::TonemappingOperator::None,
+                bloom: None,
+                anti_alias: rend3_routine::fxaa::AntiAlias::None,
+                depth_prepass: false,
+                fog: None,
+                ssao: None,
+                wireframe: false,
+                debug_view: rend3_routine::uniforms::DebugView::None,
+                disable_culling: false,
+                pick: None,
+                read_depth: false,
+            },
+        );
+
+        graph.execute(context.renderer, &mut eval_output);
+
+        let stats = context.renderer.last_frame_stats();
+        self.report.frames += 1;
+        self.report.cpu_time +=
+            stats.instruction_time + stats.resource_update_time + stats.record_time + stats.submit_time;
+
+        let elapsed = self.report.start.elapsed();
+        if elapsed >= REPORT_INTERVAL {
+            let frames = self.report.frames;
+            let gpu = context.renderer.accumulated_stats();
+            log::info!(
+                "{frames} frames with {} shadowed spot lights: {:.3} ms per frame, {:.3} ms of cpu time, {}",
+                self.spot_lights.len(),
+                elapsed.as_secs_f64() * 1000.0 / frames as f64,
+                self.report.cpu_time.as_secs_f64() * 1000.0 / frames as f64,
+                match gpu.frames {
+                    0 => String::from("no gpu timings"),
+                    _ => format!("{:.3} ms of gpu time", gpu.avg_frame_time * 1000.0),
+                },
+            );
+            context.renderer.reset_accumulated_stats();
+            self.report = Report { start: Instant::now(), frames: 0, cpu_time: Duration::ZERO };
+        }
+    }
+}
+
+pub fn main() {
+    let app = ManyLightsExample::default();
+    rend3_framework::start(
+        app,
+        winit::window::WindowBuilder::new().with_title("many-lights-example").with_maximized(true),
+    );
+}
@@ -3,12 +3,12 @@
 use std::{collections::HashMap, future::Future, hash::BuildHasher, path::Path, sync::Arc};
 
 use flume::Receiver;
-use glam::{DVec2, Mat3A, Mat4, UVec2, Vec3, Vec3A};
+use glam::{DVec2, Mat3A, Mat4, UVec2, Vec3, Vec3A, Vec4};
 use pico_args::Arguments;
 use rend3::{
     types::{
         Backend, Camera, CameraProjection, DirectionalLight, DirectionalLightHandle, SampleCount, Texture,
-        TextureFormat,
+        TextureFormat, ALL_LAYERS,
     },
     util::typedefs::{FastHashMap, RendererStatistics},
     Renderer, RendererProfile,
@@ -468,6 +468,13 @@ impl rend3_framework::App for SceneViewer {
                 intensity: self.directional_light_intensity,
                 direction,
                 distance: self.gltf_settings.directional_light_shadow_distance,
+                cascades: 4,
+                max_cascades: 4,
+                cascade_split_lambda: 0.5,
+                cascade_blend: 0.1,
+                shadow_bias: 0.0,
+                shadow_slope_bias: 0.0,
+                shadow_normal_offset: 0.0,
                 resolution: 2048,
             }));
         }
@@ -640,9 +647,11 @@ impl rend3_framework::App for SceneViewer {
         let view = Mat4::from_euler(glam::EulerRot::XYZ, -self.camera_pitch, -self.camera_yaw, 0.0);
         let view = view * Mat4::from_translation((-self.camera_location).into());
 
-        context
-            .renderer
-            .set_camera_data(Camera { projection: CameraProjection::Perspective { vfov: 60.0, near: 0.1 }, view });
+        context.renderer.set_camera_data(Camera {
+            projection: CameraProjection::Perspective { vfov: 60.0, near: 0.1 },
+            view,
+            layer_mask: ALL_LAYERS,
+        });
 
         // Lock all the routines
         let pbr_routine = lock(&context.routines.pbr);
@@ -678,12 +687,28 @@ impl rend3_framework::App for SceneViewer {
                 target: rend3_routine::base::OutputRenderTarget {
                     handle: frame_handle,
                     resolution: context.resolution,
-                    samples: self.samples,
+                    samples: context.sample_count,
                 },
             },
             rend3_routine::base::BaseRenderGraphSettings {
-                ambient_color: Vec3::splat(self.ambient_light_level).extend(1.0),
+                ambient_color: Vec4::ONE,
+                ambient_intensity: self.ambient_light_level,
                 clear_color: glam::Vec4::new(0.0, 0.0, 0.0, 1.0),
+                time: 0.0,
+                shadow_filter: rend3_routine::uniforms::ShadowFilter::Pcf3x3,
+                background_intensity: 1.0,
+                exposure: 1.0,
+                tonemapping_operator: rend3_routine::tonemapping::TonemappingOperator::None,
+                bloom: None,
+                anti_alias: rend3_routine::fxaa::AntiAlias::None,
+                depth_prepass: false,
+                fog: None,
+                ssao: None,
+                wireframe: false,
+                debug_view: rend3_routine::uniforms::DebugView::None,
+                disable_culling: false,
+                pick: None,
+                read_depth: false,
             },
         );
 
@@ -67,6 +67,8 @@ impl rend3_framework::App for StaticGltfExample {
             mesh_kind: rend3::types::ObjectMeshKind::Static(mesh),
             material,
             transform: glam::Mat4::from_scale(glam::Vec3::new(1.0, 1.0, -1.0)),
+            flags: rend3::types::ObjectFlags::default(),
+            layer_mask: rend3::types::ALL_LAYERS,
         };
         // We need to keep the object alive.
         self.object_handle = Some(context.renderer.add_object(object));
@@ -79,6 +81,7 @@ impl rend3_framework::App for StaticGltfExample {
         context.renderer.set_camera_data(rend3::types::Camera {
             projection: rend3::types::CameraProjection::Perspective { vfov: 60.0, near: 0.1 },
             view,
+            layer_mask: rend3::types::ALL_LAYERS,
         });
 
         // Create a single directional light
@@ -90,6 +93,13 @@ impl rend3_framework::App for StaticGltfExample {
             // Direction will be normalized
             direction: glam::Vec3::new(-1.0, -4.0, 2.0),
             distance: 20.0,
+            cascades: 1,
+            max_cascades: 1,
+            cascade_split_lambda: 0.5,
+            cascade_blend: 0.1,
+            shadow_bias: 0.0,
+            shadow_slope_bias: 0.0,
+            shadow_normal_offset: 0.0,
             resolution: 2048,
         }));
     }
@@ -127,12 +137,28 @@ impl rend3_framework::App for StaticGltfExample {
                 target: rend3_routine::base::OutputRenderTarget {
                     handle: frame_handle,
                     resolution: context.resolution,
-                    samples: SAMPLE_COUNT,
+                    samples: context.sample_count,
                 },
             },
             rend3_routine::base::BaseRenderGraphSettings {
                 ambient_color: glam::Vec4::ZERO,
+                ambient_intensity: 1.0,
                 clear_color: glam::Vec4::new(0.10, 0.05, 0.10, 1.0), // Nice scene-referred purple
+                time: 0.0,
+                shadow_filter: rend3_routine::uniforms::ShadowFilter::Pcf3x3,
+                background_intensity: 1.0,
+                exposure: 1.0,
+                tonemapping_operator: rend3_routine::tonemapping::TonemappingOperator::None,
+                bloom: None,
+                anti_alias: rend3_routine::fxaa::AntiAlias::None,
+                depth_prepass: false,
+                fog: None,
+                ssao: None,
+                wireframe: false,
+                debug_view: rend3_routine::uniforms::DebugView::None,
+                disable_culling: false,
+                pick: None,
+                read_depth: false,
             },
         );
         // Dispatch a render using the built up rendergraph!
@@ -20,8 +20,12 @@ pub async fn test_app<A: App<T>, T: 'static>(mut config: TestConfiguration<A>) -
     let iad =
         rend3_test::no_gpu_return!(config.app.create_iad().await).context("InstanceAdapterDevice creation failed")?;
 
-    let renderer =
-        rend3::Renderer::new(iad.clone(), A::HANDEDNESS, Some(config.size.x as f32 / config.size.y as f32)).unwrap();
+    let renderer = rend3::Renderer::new(
+        iad.clone(),
+        rend3::types::CoordinateSystem { handedness: A::HANDEDNESS, up: A::UP_AXIS },
+        Some(config.size.x as f32 / config.size.y as f32),
+    )
+    .unwrap();
 
     let mut spp = rend3::ShaderPreProcessor::new();
     rend3_routine::builtin_shaders(&mut spp);
@@ -73,7 +77,9 @@ pub async fn test_app<A: App<T>, T: 'static>(mut config: TestConfiguration<A>) -
         routines: &routines,
         base_rendergraph: &base_rendergraph,
         surface_texture: &texture,
+        surface_format,
         resolution: config.size,
+        sample_count: renderer.validate_sample_count(config.app.sample_count()),
         control_flow: &mut |_| unreachable!(),
         event_loop_window_target: None,
         delta_t_seconds: 0.0,
@@ -87,6 +87,8 @@ impl rend3_framework::App for TexturedQuadExample {
                 glam::Quat::from_euler(glam::EulerRot::XYZ, 0.0, 0.0, 0.0),
                 glam::Vec3::new(0.0, 0.0, 0.0),
             ),
+            flags: rend3::types::ObjectFlags::default(),
+            layer_mask: rend3::types::ALL_LAYERS,
         };
 
         // Creating an object will hold onto both the mesh and the material
@@ -105,6 +107,7 @@ impl rend3_framework::App for TexturedQuadExample {
                 size: glam::Vec3A::new(context.resolution.x as f32, context.resolution.y as f32, CAMERA_DEPTH),
             },
             view,
+            layer_mask: rend3::types::ALL_LAYERS,
         });
 
         self.data = Some(TexturedQuadExampleData { _object_handle, view })
@@ -119,6 +122,7 @@ impl rend3_framework::App for TexturedQuadExample {
                     size: glam::Vec3A::new(size.x as f32, size.y as f32, CAMERA_DEPTH),
                 },
                 view: self.data.as_ref().unwrap().view,
+                layer_mask: rend3::types::ALL_LAYERS,
             });
         }
     }
@@ -156,12 +160,28 @@ impl rend3_framework::App for TexturedQuadExample {
                 target: rend3_routine::base::OutputRenderTarget {
                     handle: frame_handle,
                     resolution: context.resolution,
-                    samples: SAMPLE_COUNT,
+                    samples: context.sample_count,
                 },
             },
             rend3_routine::base::BaseRenderGraphSettings {
                 ambient_color: glam::Vec4::ZERO,
+                ambient_intensity: 1.0,
                 clear_color: glam::Vec4::new(0.10, 0.05, 0.10, 1.0), // Nice scene-referred purple
+                time: 0.0,
+                shadow_filter: rend3_routine::uniforms::ShadowFilter::Pcf3x3,
+                background_intensity: 1.0,
+                exposure: 1.0,
+                tonemapping_operator: rend3_routine::tonemapping::TonemappingOperator::None,
+                bloom: None,
+                anti_alias: rend3_routine::fxaa::AntiAlias::None,
+                depth_prepass: false,
+                fog: None,
+                ssao: None,
+                wireframe: false,
+                debug_view: rend3_routine::uniforms::DebugView::None,
+                disable_culling: false,
+                pick: None,
+                read_depth: false,
             },
         );
 
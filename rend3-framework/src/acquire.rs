@@ -0,0 +1,43 @@
+use std::time::Duration;
+
+use wgpu::{Surface, SurfaceError, SurfaceTexture};
+
+/// Controls how the framework acquires the next surface texture.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AcquireConfig {
+    /// Amount of times acquiring is retried after it times out. If every
+    /// attempt times out, the frame is skipped.
+    pub max_retries: u32,
+    /// Time to sleep between attempts, so a busy system isn't made busier by
+    /// spinning. Ignored on wasm, where the thread can't sleep.
+    pub retry_delay: Option<Duration>,
+}
+
+impl Default for AcquireConfig {
+    /// Tries once, skipping the frame on timeout.
+    fn default() -> Self {
+        Self { max_retries: 0, retry_delay: None }
+    }
+}
+
+/// Acquires the next texture of the surface, retrying on [`SurfaceError::Timeout`]
+/// as configured. Any other error is returned immediately.
+pub fn acquire_surface_texture(surface: &Surface<'_>, config: &AcquireConfig) -> Result<SurfaceTexture, SurfaceError> {
+    profiling::scope!("acquire_surface_texture");
+
+    let mut attempt = 0;
+    loop {
+        match surface.get_current_texture() {
+            Err(SurfaceError::Timeout) if attempt < config.max_retries => {
+                attempt += 1;
+                log::debug!("Surface acquire timed out, retrying ({attempt}/{})", config.max_retries);
+
+                #[cfg(not(target_arch = "wasm32"))]
+                if let Some(delay) = config.retry_delay {
+                    std::thread::sleep(delay);
+                }
+            }
+            res => return res,
+        }
+    }
+}
@@ -4,7 +4,7 @@ use std::{future::Future, pin::Pin, sync::Arc};
 
 use glam::UVec2;
 use rend3::{
-    types::{Handedness, SampleCount, Surface, TextureFormat},
+    types::{CoordinateSystem, Handedness, SampleCount, Surface, TextureFormat, UpAxis},
     InstanceAdapterDevice, Renderer, ShaderPreProcessor,
 };
 use rend3_routine::base::BaseRenderGraph;
@@ -16,11 +16,15 @@ use winit::{
     window::{Window, WindowBuilder},
 };
 
+mod acquire;
 mod assets;
 mod grab;
+mod pacing;
 
+pub use acquire::*;
 pub use assets::*;
 pub use grab::*;
+pub use pacing::*;
 pub use parking_lot::{Mutex, MutexGuard};
 
 pub struct WindowingSetup<'a, T: 'static = ()> {
@@ -56,7 +60,13 @@ pub struct RedrawContext<'a, T: 'static = ()> {
     pub routines: &'a Arc<DefaultRoutines>,
     pub base_rendergraph: &'a BaseRenderGraph,
     pub surface_texture: &'a wgpu::Texture,
+    /// Format of `surface_texture`, for pipelines rendering to it alongside
+    /// rend3.
+    pub surface_format: rend3::types::TextureFormat,
     pub resolution: UVec2,
+    /// The sample count returned by [`App::sample_count`], or
+    /// [`SampleCount::One`] if the adapter doesn't support it.
+    pub sample_count: SampleCount,
     pub control_flow: &'a mut dyn FnMut(winit::event_loop::ControlFlow),
     pub event_loop_window_target: Option<&'a EventLoopWindowTarget<T>>,
     pub delta_t_seconds: f32,
@@ -65,6 +75,8 @@ pub struct RedrawContext<'a, T: 'static = ()> {
 pub trait App<T: 'static = ()> {
     /// The handedness of the coordinate system of the renderer.
     const HANDEDNESS: Handedness;
+    /// The up axis of view space of the renderer.
+    const UP_AXIS: UpAxis = UpAxis::Y;
 
     fn register_logger(&mut self) {
         #[cfg(target_arch = "wasm32")]
@@ -127,10 +139,30 @@ pub trait App<T: 'static = ()> {
     /// changes.
     fn sample_count(&self) -> SampleCount;
 
+    /// Present mode of the surface. Falls back to
+    /// [`PresentMode::Fifo`](rend3::types::PresentMode::Fifo) if the surface
+    /// doesn't support it.
+    ///
+    /// It is called again whenever the window is resized.
     fn present_mode(&self) -> rend3::types::PresentMode {
         rend3::types::PresentMode::Fifo
     }
 
+    /// Determines how surface textures are acquired. Called every frame.
+    fn acquire_config(&self) -> AcquireConfig {
+        AcquireConfig::default()
+    }
+
+    /// Highest amount of frames per second to render, or `None` to render
+    /// as fast as [`App::handle_redraw_done`] requests redraws. Called after
+    /// every frame.
+    ///
+    /// When set, the next redraw is delayed so frames start at an even
+    /// interval, which saves power on battery powered devices.
+    fn frame_rate_limit(&self) -> Option<f32> {
+        None
+    }
+
     /// Determines the scale factor used
     fn scale_factor(&self) -> f32 {
         1.0
@@ -157,6 +189,17 @@ pub trait App<T: 'static = ()> {
     fn handle_redraw_done(&mut self, window: &Window) {
         window.request_redraw(); // just queue a redraw.
     }
+
+    /// Called once, instead of [`App::handle_redraw`], after the renderer's
+    /// device is lost. See [`Renderer::device_lost`].
+    ///
+    /// The renderer and everything created with it are unusable and nothing is
+    /// rendered anymore. Recovering requires a new renderer, so by default
+    /// this exits the event loop, letting the application start over.
+    fn handle_device_lost(&mut self, event_loop_window_target: &EventLoopWindowTarget<T>, lost: &rend3::DeviceLost) {
+        let _ = lost;
+        event_loop_window_target.exit();
+    }
 }
 
 pub fn lock<T>(lock: &parking_lot::Mutex<T>) -> parking_lot::MutexGuard<'_, T> {
@@ -174,6 +217,33 @@ pub struct DefaultRoutines {
     pub tonemapping: Mutex<rend3_routine::tonemapping::TonemappingRoutine>,
 }
 
+impl DefaultRoutines {
+    /// Recompile the shaders of all routines from `spp`, so shaders can be
+    /// iterated on without restarting. Register the shaders being edited with
+    /// [`ShaderPreProcessor::add_shaders_directory`] to read them from disk.
+    ///
+    /// Routines whose shaders fail to compile keep their previous pipelines
+    /// and the first error is returned. Errors in the handlebars templating of
+    /// a shader still panic. The routines of `base_rendergraph` are not
+    /// reloaded.
+    pub fn reload_shaders(
+        &self,
+        renderer: &Arc<Renderer>,
+        spp: &ShaderPreProcessor,
+        base_rendergraph: &BaseRenderGraph,
+    ) -> Result<(), wgpu::Error> {
+        let interfaces = &base_rendergraph.interfaces;
+
+        let mut data_core = renderer.data_core.lock();
+        let pbr = lock(&self.pbr).reload_shaders(renderer, &mut data_core, spp, interfaces);
+        drop(data_core);
+        let skybox = lock(&self.skybox).reload_shaders(renderer, spp, interfaces);
+        let tonemapping = lock(&self.tonemapping).reload_shaders(renderer, spp, interfaces);
+
+        pbr.and(skybox).and(tonemapping)
+    }
+}
+
 pub async fn async_start<A: App<T> + 'static, T: 'static>(mut app: A, window_builder: WindowBuilder) {
     app.register_logger();
     app.register_panic_hook();
@@ -197,9 +267,12 @@ pub async fn async_start<A: App<T> + 'static, T: 'static>(mut app: A, window_bui
     };
 
     // Make us a renderer.
-    let renderer =
-        rend3::Renderer::new(iad.clone(), A::HANDEDNESS, Some(window_size.width as f32 / window_size.height as f32))
-            .unwrap();
+    let renderer = rend3::Renderer::new(
+        iad.clone(),
+        CoordinateSystem { handedness: A::HANDEDNESS, up: A::UP_AXIS },
+        Some(window_size.width as f32 / window_size.height as f32),
+    )
+    .unwrap();
 
     // Get the preferred format for the surface.
     //
@@ -260,7 +333,7 @@ pub async fn async_start<A: App<T> + 'static, T: 'static>(mut app: A, window_bui
     let mut stored_surface_info = StoredSurfaceInfo {
         size: glam::UVec2::new(window_size.width, window_size.height),
         scale_factor: app.scale_factor(),
-        sample_count: app.sample_count(),
+        sample_count: renderer.validate_sample_count(app.sample_count()),
         present_mode: app.present_mode(),
         requires_reconfigure: true,
     };
@@ -275,6 +348,10 @@ pub async fn async_start<A: App<T> + 'static, T: 'static>(mut app: A, window_bui
     }
 
     let mut previous_time = web_time::Instant::now();
+    let mut frame_limiter = FrameLimiter::new();
+    // Time the next redraw is delayed until, along with the control flow to restore afterwards.
+    let mut delayed_redraw: Option<(web_time::Instant, ControlFlow)> = None;
+    let mut device_lost_handled = false;
 
     // On native this is a result, but on wasm it's a unit type.
     #[allow(clippy::let_unit_value)]
@@ -299,6 +376,16 @@ pub async fn async_start<A: App<T> + 'static, T: 'static>(mut app: A, window_bui
                 _ => {}
             }
 
+            if let Event::AboutToWait = event {
+                if let Some((deadline, previous_control_flow)) = delayed_redraw {
+                    if web_time::Instant::now() >= deadline {
+                        delayed_redraw = None;
+                        event_loop_window_target.set_control_flow(previous_control_flow);
+                        app.handle_redraw_done(&window);
+                    }
+                }
+            }
+
             // Close button was clicked, we should close.
             if let winit::event::Event::WindowEvent { event: winit::event::WindowEvent::CloseRequested, .. } = event {
                 event_loop_window_target.exit();
@@ -311,11 +398,21 @@ pub async fn async_start<A: App<T> + 'static, T: 'static>(mut app: A, window_bui
                     return;
                 }
 
+                if let Some(lost) = renderer.device_lost() {
+                    if !device_lost_handled {
+                        device_lost_handled = true;
+                        app.handle_device_lost(event_loop_window_target, &lost);
+                    }
+                    return;
+                }
+
                 let Some(surface) = surface.as_ref() else {
                     return;
                 };
 
                 if stored_surface_info.requires_reconfigure {
+                    stored_surface_info.present_mode =
+                        rend3::validate_present_mode(surface, &iad.adapter, stored_surface_info.present_mode);
                     rend3::configure_surface(
                         surface,
                         &renderer.device,
@@ -326,7 +423,24 @@ pub async fn async_start<A: App<T> + 'static, T: 'static>(mut app: A, window_bui
                     stored_surface_info.requires_reconfigure = false;
                 }
 
-                let surface_texture = match surface.get_current_texture() {
+                let acquire_config = app.acquire_config();
+                let mut acquired = acquire_surface_texture(surface, &acquire_config);
+
+                // Resizes and gpu resets leave the surface outdated or lost, which reconfiguring it usually fixes,
+                // so retry once before giving up on the frame.
+                if let Err(err @ (SurfaceError::Outdated | SurfaceError::Lost)) = acquired {
+                    log::debug!("Surface texture could not be acquired ({err}), reconfiguring surface");
+                    rend3::configure_surface(
+                        surface,
+                        &renderer.device,
+                        format,
+                        stored_surface_info.size,
+                        stored_surface_info.present_mode,
+                    );
+                    acquired = acquire_surface_texture(surface, &acquire_config);
+                }
+
+                let surface_texture = match acquired {
                     Ok(texture) => texture,
                     Err(SurfaceError::Outdated) => {
                         stored_surface_info.requires_reconfigure = true;
@@ -335,7 +449,9 @@ pub async fn async_start<A: App<T> + 'static, T: 'static>(mut app: A, window_bui
                     Err(SurfaceError::Timeout) => {
                         return;
                     }
-                    Err(SurfaceError::OutOfMemory | SurfaceError::Lost) => panic!("Surface OOM"),
+                    Err(err @ (SurfaceError::OutOfMemory | SurfaceError::Lost)) => {
+                        panic!("Failed to acquire surface texture: {err}")
+                    }
                 };
 
                 let current_time = web_time::Instant::now();
@@ -348,7 +464,9 @@ pub async fn async_start<A: App<T> + 'static, T: 'static>(mut app: A, window_bui
                     routines: &routines,
                     base_rendergraph: &base_rendergraph,
                     surface_texture: &surface_texture.texture,
+                    surface_format: format,
                     resolution: stored_surface_info.size,
+                    sample_count: stored_surface_info.sample_count,
                     control_flow: &mut |c: ControlFlow| {
                         control_flow = c;
                         last_user_control_mode = c;
@@ -359,7 +477,14 @@ pub async fn async_start<A: App<T> + 'static, T: 'static>(mut app: A, window_bui
 
                 surface_texture.present();
 
-                app.handle_redraw_done(&window); // standard action is to redraw, but that can be overridden.
+                match frame_limiter.frame_done(web_time::Instant::now(), app.frame_rate_limit()) {
+                    Some(deadline) => {
+                        let previous_control_flow = event_loop_window_target.control_flow();
+                        event_loop_window_target.set_control_flow(ControlFlow::WaitUntil(deadline));
+                        delayed_redraw = Some((deadline, previous_control_flow));
+                    }
+                    None => app.handle_redraw_done(&window), // standard action is to redraw, but that can be overridden.
+                }
             } else {
                 app.handle_event(
                     EventContext {
@@ -420,7 +545,7 @@ fn handle_surface<A: App<T>, T: 'static>(
 
             surface_info.size = size;
             surface_info.scale_factor = app.scale_factor();
-            surface_info.sample_count = app.sample_count();
+            surface_info.sample_count = renderer.validate_sample_count(app.sample_count());
             surface_info.present_mode = app.present_mode();
             surface_info.requires_reconfigure = true;
 
@@ -0,0 +1,90 @@
+use std::time::Duration;
+
+use web_time::Instant;
+
+/// Schedules frames so they start at an even interval, capping the frame rate.
+///
+/// Frames are scheduled relative to when the previous frame was scheduled,
+/// not when it finished, so the time spent rendering is absorbed into the
+/// wait. When a frame takes longer than the interval, the next one starts
+/// immediately and the schedule restarts from there instead of rendering
+/// several frames back to back to catch up.
+#[derive(Debug, Default)]
+pub struct FrameLimiter {
+    scheduled: Option<Instant>,
+}
+
+impl FrameLimiter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Called once a frame has been presented at `now`. Returns when the next
+    /// frame should start, or `None` if it can start right away.
+    ///
+    /// A `frame_rate` of `None`, one that isn't positive, or one so small that
+    /// its interval can't be represented, leaves the frame rate uncapped.
+    pub fn frame_done(&mut self, now: Instant, frame_rate: Option<f32>) -> Option<Instant> {
+        let next = frame_rate
+            .filter(|&rate| rate > 0.0)
+            .and_then(|rate| Duration::try_from_secs_f32(1.0 / rate).ok())
+            .and_then(|interval| self.scheduled.unwrap_or(now).checked_add(interval));
+        let Some(next) = next else {
+            self.scheduled = None;
+            return None;
+        };
+
+        let next = next.max(now);
+        self.scheduled = Some(next);
+
+        (next > now).then_some(next)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use web_time::Instant;
+
+    use super::FrameLimiter;
+
+    const MS: Duration = Duration::from_millis(1);
+
+    #[test]
+    fn even_schedule() {
+        let mut limiter = FrameLimiter::new();
+        let start = Instant::now();
+
+        // Time spent rendering is absorbed into the wait.
+        assert_eq!(limiter.frame_done(start, Some(100.0)), Some(start + 10 * MS));
+        assert_eq!(limiter.frame_done(start + 13 * MS, Some(100.0)), Some(start + 20 * MS));
+        assert_eq!(limiter.frame_done(start + 21 * MS, Some(100.0)), Some(start + 30 * MS));
+    }
+
+    #[test]
+    fn late_frame_resets_schedule() {
+        let mut limiter = FrameLimiter::new();
+        let start = Instant::now();
+
+        assert_eq!(limiter.frame_done(start, Some(100.0)), Some(start + 10 * MS));
+        // Finishing 25ms late starts the next frame right away instead of catching up.
+        assert_eq!(limiter.frame_done(start + 35 * MS, Some(100.0)), None);
+        assert_eq!(limiter.frame_done(start + 36 * MS, Some(100.0)), Some(start + 45 * MS));
+    }
+
+    #[test]
+    fn uncapped() {
+        let mut limiter = FrameLimiter::new();
+        let start = Instant::now();
+
+        for rate in [None, Some(0.0), Some(-60.0), Some(f32::NAN), Some(f32::MIN_POSITIVE)] {
+            assert_eq!(limiter.frame_done(start, rate), None, "{rate:?}");
+        }
+
+        // Uncapped frames don't leave a stale schedule behind.
+        limiter.frame_done(start, Some(100.0));
+        limiter.frame_done(start + 1000 * MS, None);
+        assert_eq!(limiter.frame_done(start + 2000 * MS, Some(100.0)), Some(start + 2010 * MS));
+    }
+}
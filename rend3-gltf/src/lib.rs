@@ -61,12 +61,16 @@ pub struct MeshPrimitive {
     /// Index into the material vector given by [`load_materials_and_textures`]
     /// or [`LoadedGltfScene::materials`].
     pub material: Option<usize>,
+    /// Amount of morph targets of the primitive.
+    pub morph_target_count: usize,
 }
 
 /// Set of [`MeshPrimitive`]s that make up a logical mesh.
 #[derive(Debug)]
 pub struct Mesh {
     pub primitives: Vec<MeshPrimitive>,
+    /// Default weights of the morph targets of the primitives.
+    pub weights: Vec<f32>,
 }
 
 /// A set of [`SkeletonHandle`]s, one per mesh in the wrapping object, plus the
@@ -92,6 +96,10 @@ pub struct Armature {
 pub struct Object {
     pub primitives: Vec<ObjectHandle>,
     pub armature: Option<Armature>,
+    /// Skeletons blending the morph targets of the primitives which have
+    /// some, whose weights are set with
+    /// [`Renderer::set_skeleton_morph_weights`].
+    pub morph_skeletons: Vec<SkeletonHandle>,
 }
 
 /// Node in the gltf scene tree
@@ -420,6 +428,7 @@ pub fn add_mesh_by_index<E: std::error::Error + 'static>(
 
     let mut primitives = Vec::new();
     let mut skeletons = Vec::new();
+    let mut morph_skeletons = Vec::new();
 
     let skin = if let Some(skin_index) = skin_index {
         let skin = loaded.skins.get(skin_index).ok_or(GltfLoadError::MissingSkin(skin_index))?;
@@ -434,24 +443,41 @@ pub fn add_mesh_by_index<E: std::error::Error + 'static>(
             .map_or_else(|| Some(&loaded.default_material), |mat_idx| loaded.materials.get(mat_idx).map(|m| &m.inner))
             .ok_or_else(|| GltfLoadError::MissingMaterial(mat_idx.expect("Could not find default material")))?;
 
-        let mesh_kind = if let Some(skin) = skin {
+        let mesh_kind = if skin.is_some() || prim.morph_target_count > 0 {
             let skeleton = renderer.add_skeleton(Skeleton {
                 // We don't need to use the inverse bind matrices. At rest pose, every
                 // joint matrix is inv_bind_pose * bind_pose, thus the identity matrix.
-                joint_matrices: vec![Mat4::IDENTITY; skin.inner.inverse_bind_matrices.len()],
+                joint_matrices: skin
+                    .map_or_else(Vec::new, |skin| vec![Mat4::IDENTITY; skin.inner.inverse_bind_matrices.len()]),
+                morph_weights: mesh_handle.inner.weights.clone(),
                 mesh: prim.handle.clone(),
             })?;
-            skeletons.push(skeleton.clone());
+            if skin.is_some() {
+                skeletons.push(skeleton.clone());
+            }
+            if prim.morph_target_count > 0 {
+                morph_skeletons.push(skeleton.clone());
+            }
             ObjectMeshKind::Animated(skeleton)
         } else {
             ObjectMeshKind::Static(prim.handle.clone())
         };
 
-        primitives.push(renderer.add_object(types::Object { mesh_kind, material: mat.clone(), transform }));
+        primitives.push(renderer.add_object(types::Object {
+            mesh_kind,
+            material: mat.clone(),
+            transform,
+            flags: types::ObjectFlags::default(),
+            layer_mask: types::ALL_LAYERS,
+        }));
     }
 
     Ok(Labeled::new(
-        Object { primitives, armature: skin_index.map(|skin_index| Armature { skeletons, skin_index }) },
+        Object {
+            primitives,
+            armature: skin_index.map(|skin_index| Armature { skeletons, skin_index }),
+            morph_skeletons,
+        },
         name,
     ))
 }
@@ -536,6 +562,13 @@ pub fn instance_loaded_scene<E: std::error::Error + 'static>(
                         intensity: light.intensity(),
                         direction,
                         distance: settings.directional_light_shadow_distance,
+                        cascades: 1,
+                        max_cascades: 1,
+                        cascade_split_lambda: 0.5,
+                        cascade_blend: 0.1,
+                        shadow_bias: 0.0,
+                        shadow_slope_bias: 0.0,
+                        shadow_normal_offset: 0.0,
                         resolution: settings.directional_light_resolution,
                     }))
                 }
@@ -626,6 +659,7 @@ pub fn load_meshes<'a, E: std::error::Error + 'static>(
                     .ok_or_else(|| GltfLoadError::MissingPositions(mesh.index()))?
                     .map(Vec3::from)
                     .collect();
+                let vertex_count = vertex_positions.len();
 
                 // glTF models are right handed, so we must flip their winding order
                 let mut builder = types::MeshBuilder::new(vertex_positions, renderer.handedness);
@@ -666,13 +700,25 @@ pub fn load_meshes<'a, E: std::error::Error + 'static>(
                     builder = builder.with_vertex_joint_weights(joint_weights.into_f32().map(Vec4::from).collect())
                 }
 
+                let morph_targets: Vec<_> = reader
+                    .read_morph_targets()
+                    .map(|(positions, normals, _tangents)| types::MorphTarget {
+                        position_offsets: positions
+                            .map_or_else(|| vec![Vec3::ZERO; vertex_count], |p| p.map(Vec3::from).collect()),
+                        normal_offsets: normals.map(|n| n.map(Vec3::from).collect()),
+                    })
+                    .collect();
+                let morph_target_count = morph_targets.len();
+                builder = builder.with_morph_targets(morph_targets);
+
                 let mesh = builder.build().map_err(|valid| GltfLoadError::MeshValidationError(mesh.index(), valid))?;
 
                 let handle = renderer.add_mesh(mesh)?;
 
-                res_prims.push(MeshPrimitive { handle, material: prim.material().index() })
+                res_prims.push(MeshPrimitive { handle, material: prim.material().index(), morph_target_count })
             }
-            Ok(Labeled::new(Mesh { primitives: res_prims }, mesh.name()))
+            let weights = mesh.weights().map_or_else(Vec::new, <[f32]>::to_vec);
+            Ok(Labeled::new(Mesh { primitives: res_prims, weights }, mesh.name()))
         })
         .collect()
 }
@@ -779,7 +825,9 @@ pub fn load_default_material(renderer: &Arc<Renderer>) -> types::MaterialHandle
     renderer.add_material(pbr::PbrMaterial {
         albedo: pbr::AlbedoComponent::Value(Vec4::splat(1.0)),
         transparency: pbr::Transparency::Opaque,
+        blend_mode: pbr::BlendMode::Alpha,
         normal: pbr::NormalTexture::None,
+        normal_map_space: pbr::NormalMapSpace::Tangent,
         aomr_textures: pbr::AoMRTextures::None,
         ao_factor: Some(1.0),
         metallic_factor: Some(1.0),
@@ -794,6 +842,9 @@ pub fn load_default_material(renderer: &Arc<Renderer>) -> types::MaterialHandle
         uv_transform1: Mat3::IDENTITY,
         unlit: false,
         sample_type: pbr::SampleType::Linear,
+        texture_wrap: pbr::TextureWrap::Repeat,
+        double_sided: false,
+        custom_shader: None,
     })
 }
 
@@ -842,6 +893,15 @@ where
             })
             .unwrap_or_default();
 
+        let texture_wrap = albedo
+            .as_ref()
+            .map(|i| match i.texture().sampler().wrap_s() {
+                gltf::texture::WrappingMode::ClampToEdge => pbr::TextureWrap::Clamp,
+                gltf::texture::WrappingMode::MirroredRepeat => pbr::TextureWrap::Mirror,
+                gltf::texture::WrappingMode::Repeat => pbr::TextureWrap::Repeat,
+            })
+            .unwrap_or_default();
+
         let uv_transform = albedo
             .as_ref()
             .and_then(|i| {
@@ -931,6 +991,8 @@ where
             uv_transform1: uv_transform,
             unlit: material.unlit(),
             sample_type: nearest,
+            texture_wrap,
+            double_sided: material.double_sided(),
             ..pbr::PbrMaterial::default()
         });
 
@@ -1,3 +1,4 @@
+{{include "rend3-routine/structures.wgsl"}}
 {{include "rend3-routine/math/color.wgsl"}}
 
 struct VertexOutput {
@@ -15,17 +16,107 @@ fn vs_main(@builtin(vertex_index) id: u32) -> VertexOutput {
 
 @group(0) @binding(0)
 var primary_sampler: sampler;
+@group(0) @binding(3)
+var<uniform> uniforms: UniformData;
 @group(1) @binding(0)
 var source: texture_2d<f32>;
 
+const TONEMAPPING_NONE: u32 = 0u;
+const TONEMAPPING_REINHARD: u32 = 1u;
+const TONEMAPPING_ACES: u32 = 2u;
+const TONEMAPPING_UNCHARTED2: u32 = 3u;
+
+// Narkowicz's fit of the ACES filmic curve.
+fn tonemap_aces(x: vec3<f32>) -> vec3<f32> {
+    let mapped = (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14);
+    return clamp(mapped, vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+fn uncharted2_curve(x: vec3<f32>) -> vec3<f32> {
+    let a = 0.15;
+    let b = 0.50;
+    let c = 0.10;
+    let d = 0.20;
+    let e = 0.02;
+    let f = 0.30;
+    return ((x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f)) - e / f;
+}
+
+// Hable's filmic curve, normalized so the white point maps to 1.
+fn tonemap_uncharted2(x: vec3<f32>) -> vec3<f32> {
+    let white_point = 11.2;
+    let exposure_bias = 2.0;
+    let mapped = uncharted2_curve(x * exposure_bias) / uncharted2_curve(vec3<f32>(white_point));
+    return clamp(mapped, vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+// Samples the hdr source, mapping it to [0, 1] with the selected operator.
+fn tonemapped_source(tex_coords: vec2<f32>) -> vec4<f32> {
+    let sampled = textureSample(source, primary_sampler, tex_coords);
+    // Debug views are shown as they were output.
+    if (uniforms.debug_view != 0u) {
+        return sampled;
+    }
+    let exposed = sampled.rgb * uniforms.exposure;
+    switch uniforms.tonemapping_operator {
+        case TONEMAPPING_REINHARD: {
+            return vec4<f32>(exposed / (1.0 + exposed), sampled.a);
+        }
+        case TONEMAPPING_ACES: {
+            return vec4<f32>(tonemap_aces(exposed), sampled.a);
+        }
+        case TONEMAPPING_UNCHARTED2: {
+            return vec4<f32>(tonemap_uncharted2(exposed), sampled.a);
+        }
+        default: {
+            return vec4<f32>(exposed, sampled.a);
+        }
+    }
+}
+
 @fragment
 fn fs_main_scene(vout: VertexOutput) -> @location(0) vec4<f32> {
-    var sampled = textureSample(source, primary_sampler, vout.tex_coords);
+    let sampled = tonemapped_source(vout.tex_coords);
     return sampled;
 }
 
 @fragment
 fn fs_main_monitor(vout: VertexOutput) -> @location(0) vec4<f32> {
-    var sampled = textureSample(source, primary_sampler, vout.tex_coords);
+    let sampled = tonemapped_source(vout.tex_coords);
     return vec4<f32>(srgb_scene_to_display(sampled.rgb), sampled.a);
-}
\ No newline at end of file
+}
+
+struct ColorGrading {
+    intensity: f32,
+}
+
+@group(1) @binding(1)
+var lut: texture_3d<f32>;
+@group(1) @binding(2)
+var<uniform> grading: ColorGrading;
+
+// Grades display encoded colors, as LUTs are authored against them.
+fn color_grade(display: vec3<f32>) -> vec3<f32> {
+    // Sample at texel centers so the ends of the table map to black and white.
+    let size = vec3<f32>(textureDimensions(lut));
+    let coords = saturate_vec3(display) * ((size - 1.0) / size) + 0.5 / size;
+    let graded = textureSampleLevel(lut, primary_sampler, coords, 0.0).rgb;
+    return mix(display, graded, grading.intensity);
+}
+
+fn saturate_vec3(v: vec3<f32>) -> vec3<f32> {
+    return clamp(v, vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+@fragment
+fn fs_main_scene_graded(vout: VertexOutput) -> @location(0) vec4<f32> {
+    let sampled = tonemapped_source(vout.tex_coords);
+    let graded = color_grade(srgb_scene_to_display(sampled.rgb));
+    return vec4<f32>(srgb_display_to_scene(graded), sampled.a);
+}
+
+@fragment
+fn fs_main_monitor_graded(vout: VertexOutput) -> @location(0) vec4<f32> {
+    let sampled = tonemapped_source(vout.tex_coords);
+    return vec4<f32>(color_grade(srgb_scene_to_display(sampled.rgb)), sampled.a);
+}
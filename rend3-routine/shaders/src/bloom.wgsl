@@ -0,0 +1,83 @@
+{{include "rend3-routine/structures.wgsl"}}
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) id: u32) -> VertexOutput {
+    var output: VertexOutput;
+    output.position = vec4<f32>(f32(id / 2u) * 4.0 - 1.0, f32(id % 2u) * 4.0 - 1.0, 0.0, 1.0);
+    output.tex_coords = vec2<f32>(f32(id / 2u) * 2.0, 1.0 - (f32(id % 2u) * 2.0));
+    return output;
+}
+
+@group(0) @binding(0)
+var primary_sampler: sampler;
+@group(0) @binding(3)
+var<uniform> uniforms: UniformData;
+@group(1) @binding(0)
+var source: texture_2d<f32>;
+
+// The sampler repeats, so keep the taps inside the image to not bleed
+// across opposite edges.
+fn sample_source(tex_coords: vec2<f32>) -> vec3<f32> {
+    let half_texel = 0.5 / vec2<f32>(textureDimensions(source));
+    let coords = clamp(tex_coords, half_texel, 1.0 - half_texel);
+    return textureSampleLevel(source, primary_sampler, coords, 0.0).rgb;
+}
+
+// Halves the resolution, averaging a 4x4 block of texels with four bilinear taps.
+fn downsample(tex_coords: vec2<f32>) -> vec3<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(source));
+    var sum = sample_source(tex_coords + texel * vec2<f32>(-1.0, -1.0));
+    sum += sample_source(tex_coords + texel * vec2<f32>(1.0, -1.0));
+    sum += sample_source(tex_coords + texel * vec2<f32>(-1.0, 1.0));
+    sum += sample_source(tex_coords + texel * vec2<f32>(1.0, 1.0));
+    return sum * 0.25;
+}
+
+// Doubles the resolution with a 3x3 tent filter.
+fn upsample(tex_coords: vec2<f32>) -> vec3<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(source));
+    var sum = sample_source(tex_coords) * 4.0;
+    sum += sample_source(tex_coords + texel * vec2<f32>(-1.0, 0.0)) * 2.0;
+    sum += sample_source(tex_coords + texel * vec2<f32>(1.0, 0.0)) * 2.0;
+    sum += sample_source(tex_coords + texel * vec2<f32>(0.0, -1.0)) * 2.0;
+    sum += sample_source(tex_coords + texel * vec2<f32>(0.0, 1.0)) * 2.0;
+    sum += sample_source(tex_coords + texel * vec2<f32>(-1.0, -1.0));
+    sum += sample_source(tex_coords + texel * vec2<f32>(1.0, -1.0));
+    sum += sample_source(tex_coords + texel * vec2<f32>(-1.0, 1.0));
+    sum += sample_source(tex_coords + texel * vec2<f32>(1.0, 1.0));
+    return sum / 16.0;
+}
+
+// Keeps the part of the color brighter than the threshold, preserving its hue.
+fn threshold(color: vec3<f32>) -> vec3<f32> {
+    let brightness = max(color.r, max(color.g, color.b));
+    let contribution = max(brightness - uniforms.bloom_threshold, 0.0) / max(brightness, 0.0001);
+    return color * contribution;
+}
+
+@fragment
+fn fs_prefilter(vout: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(threshold(downsample(vout.tex_coords)), 1.0);
+}
+
+@fragment
+fn fs_downsample(vout: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(downsample(vout.tex_coords), 1.0);
+}
+
+// Blended additively onto the next larger mip.
+@fragment
+fn fs_upsample(vout: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(upsample(vout.tex_coords), 1.0);
+}
+
+// Blended additively onto the hdr image, leaving its alpha unchanged.
+@fragment
+fn fs_composite(vout: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(upsample(vout.tex_coords) * uniforms.bloom_intensity, 0.0);
+}
@@ -264,18 +264,25 @@ fn textureSampleMin(texture: texture_depth_2d, uv: vec2<f32>, mipmap: f32) -> f3
 fn execute_culling(
     model_view_proj: mat4x4<f32>,
     vertices: TriangleVertices,
+    double_sided: bool,
 ) -> bool {
+    if (per_camera_uniform.flags & PCU_FLAGS_CULLING_DISABLED) != 0u {
+        return true;
+    }
+
     let position0 = model_view_proj * vec4<f32>(vertices[0], 1.0);
     let position1 = model_view_proj * vec4<f32>(vertices[1], 1.0);
     let position2 = model_view_proj * vec4<f32>(vertices[2], 1.0);
 
     let det = determinant(mat3x3<f32>(position0.xyw, position1.xyw, position2.xyw));
 
-    if (per_camera_uniform.flags & PCU_FLAGS_AREA_VISIBLE_MASK) == PCU_FLAGS_POSITIVE_AREA_VISIBLE && det <= 0.0 {
-        return false;
-    }
-    if (per_camera_uniform.flags & PCU_FLAGS_AREA_VISIBLE_MASK) == PCU_FLAGS_NEGATIVE_AREA_VISIBLE && det >= 0.0 {
-        return false;
+    if !double_sided {
+        if (per_camera_uniform.flags & PCU_FLAGS_AREA_VISIBLE_MASK) == PCU_FLAGS_POSITIVE_AREA_VISIBLE && det <= 0.0 {
+            return false;
+        }
+        if (per_camera_uniform.flags & PCU_FLAGS_AREA_VISIBLE_MASK) == PCU_FLAGS_NEGATIVE_AREA_VISIBLE && det >= 0.0 {
+            return false;
+        }
     }
 
     let ndc0 = position0.xyz / position0.w;
@@ -357,7 +364,7 @@ fn cs_main(
 
         let model_view_proj = per_camera_uniform.objects[object_info.object_id].model_view_proj;
 
-        let passes_culling = execute_culling(model_view_proj, triangle.vertices);
+        let passes_culling = execute_culling(model_view_proj, triangle.vertices, object_info.double_sided != 0u);
 
         if object_info.atomic_capable == 1u {
             if passes_culling {
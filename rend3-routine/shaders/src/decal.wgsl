@@ -0,0 +1,106 @@
+{{include "rend3-routine/structures.wgsl"}}
+
+struct Decal {
+    world_to_decal: mat4x4<f32>,
+    decal_to_world: mat4x4<f32>,
+    color: vec4<f32>,
+}
+
+struct DecalBuffer {
+    count: u32,
+    decals: array<Decal>,
+}
+
+@group(0) @binding(3)
+var<uniform> uniforms: UniformData;
+@group(0) @binding(7)
+var primary_clamp_sampler: sampler;
+
+@group(1) @binding(0)
+var<storage> decals: DecalBuffer;
+@group(1) @binding(1)
+{{#if (eq SAMPLES 1)}}
+var depth_tex: texture_depth_2d;
+{{else}}
+var depth_tex: texture_depth_multisampled_2d;
+{{/if}}
+@group(1) @binding(2)
+var decal_tex: texture_2d<f32>;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) @interpolate(flat) decal: u32,
+}
+
+// Corners of the two triangles of a face, as offsets along its two tangent axes.
+const FACE_CORNERS = array<vec2<f32>, 6>(
+    vec2<f32>(-0.5, -0.5),
+    vec2<f32>(0.5, -0.5),
+    vec2<f32>(0.5, 0.5),
+    vec2<f32>(-0.5, -0.5),
+    vec2<f32>(0.5, 0.5),
+    vec2<f32>(-0.5, 0.5),
+);
+
+// Draws the faces of the decal's box which face away from the camera, 6
+// vertices per face. They cover the box on screen, each pixel exactly once,
+// even when the camera is inside the box.
+@vertex
+fn vs_main(@builtin(vertex_index) vertex: u32, @builtin(instance_index) instance: u32) -> VertexOutput {
+    let decal = decals.decals[instance];
+
+    let face = vertex / 6u;
+    let axis = face / 2u;
+    let side = select(0.5, -0.5, face % 2u == 1u);
+    var corners = FACE_CORNERS;
+    let corner = corners[vertex % 6u];
+
+    var local = vec3<f32>(0.0);
+    local[axis] = side;
+    local[(axis + 1u) % 3u] = corner.x;
+    local[(axis + 2u) % 3u] = corner.y;
+
+    // The camera as a homogeneous point: its position for perspective
+    // projections, the direction towards it for orthographic ones.
+    var camera = uniforms.inv_view_proj * vec4<f32>(0.0, 0.0, 1.0, 0.0);
+    if (abs(camera.w) > 1e-6) {
+        camera /= camera.w;
+    }
+    let local_camera = decal.world_to_decal * camera;
+    // Distance of the camera in front of the face's plane, along its outwards normal.
+    let facing = sign(side) * local_camera[axis] - 0.5 * local_camera.w;
+
+    var output: VertexOutput;
+    output.decal = instance;
+    if (facing > 0.0) {
+        // Facing the camera, collapse the face.
+        output.position = vec4<f32>(0.0, 0.0, 0.0, 1.0);
+    } else {
+        output.position = uniforms.view_proj * decal.decal_to_world * vec4<f32>(local, 1.0);
+    }
+    return output;
+}
+
+@fragment
+fn fs_main(vout: VertexOutput) -> @location(0) vec4<f32> {
+    let pixel = vec2<i32>(vout.position.xy);
+    let depth = textureLoad(depth_tex, pixel, 0);
+
+    let uv = (vec2<f32>(pixel) + 0.5) / vec2<f32>(uniforms.resolution);
+    let ndc = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth, 1.0);
+    let world = uniforms.inv_view_proj * ndc;
+
+    let decal = decals.decals[vout.decal];
+    let local = (decal.world_to_decal * vec4<f32>(world.xyz / world.w, 1.0)).xyz;
+    let decal_uv = vec2<f32>(local.x + 0.5, 0.5 - local.y);
+    // Derivatives need uniform control flow, so they are taken before discarding.
+    let uv_dx = dpdx(decal_uv);
+    let uv_dy = dpdy(decal_uv);
+
+    // There is no surface at the far plane.
+    if (depth <= 0.0 || any(abs(local) > vec3<f32>(0.5))) {
+        discard;
+    }
+
+    return textureSampleGrad(decal_tex, primary_clamp_sampler, decal_uv, uv_dx, uv_dy) * decal.color;
+}
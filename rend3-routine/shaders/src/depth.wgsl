@@ -4,8 +4,18 @@
 
 @group(0) @binding(0)
 var primary_sampler: sampler;
+@group(0) @binding(1)
+var nearest_sampler: sampler;
 @group(0) @binding(3)
 var<uniform> uniforms: UniformData;
+@group(0) @binding(7)
+var primary_clamp_sampler: sampler;
+@group(0) @binding(8)
+var primary_mirror_sampler: sampler;
+@group(0) @binding(9)
+var nearest_clamp_sampler: sampler;
+@group(0) @binding(10)
+var nearest_mirror_sampler: sampler;
 
 @group(1) @binding(0)
 var<storage> object_buffer: array<Object>;
@@ -42,10 +52,12 @@ var albedo_tex: texture_2d<f32>;
 }}
 
 struct VertexOutput {
-    @builtin(position) position: vec4<f32>,
+    // Invariant so the depth prepass and forward pass compute exactly the same depth.
+    @builtin(position) @invariant position: vec4<f32>,
     @location(0) coords0: vec2<f32>,
     @location(1) alpha: f32,
     @location(2) @interpolate(flat) material: u32,
+    @location(3) @interpolate(flat) object: u32,
 }
 
 @vertex
@@ -79,7 +91,8 @@ fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
 
     var vs_out: VertexOutput;
     vs_out.material = data.material_index;
-    vs_out.coords0 = vs_in.texture_coords_0;
+    vs_out.object = indices.object;
+    vs_out.coords0 = vs_in.texture_coords_0 * data.material_override.uv_scale + data.material_override.uv_offset;
     vs_out.alpha = vs_in.color_0.a;
     vs_out.position = model_view_proj * position_vec4;
 
@@ -100,8 +113,29 @@ fn has_albedo_texture(material: ptr<function, Material>) -> bool { return bool((
 fn albedo_texture(material: ptr<function, Material>, samp: sampler, coords: vec2<f32>, ddx: vec2<f32>, ddy: vec2<f32>) -> vec4<f32> { return textureSampleGrad(albedo_tex, samp, coords, ddx, ddy); }
 {{/if}}
 
-@fragment
-fn fs_main(vs_out: VertexOutput) {
+// Samples the albedo with the sampler the material asks for.
+fn material_albedo(material: ptr<function, Material>, coords: vec2<f32>, ddx: vec2<f32>, ddy: vec2<f32>) -> vec4<f32> {
+    let flags = (*material).flags;
+    if (extract_material_flag(flags, FLAGS_NEAREST)) {
+        if (extract_material_flag(flags, FLAGS_WRAP_CLAMP)) {
+            return albedo_texture(material, nearest_clamp_sampler, coords, ddx, ddy);
+        } else if (extract_material_flag(flags, FLAGS_WRAP_MIRROR)) {
+            return albedo_texture(material, nearest_mirror_sampler, coords, ddx, ddy);
+        } else {
+            return albedo_texture(material, nearest_sampler, coords, ddx, ddy);
+        }
+    } else {
+        if (extract_material_flag(flags, FLAGS_WRAP_CLAMP)) {
+            return albedo_texture(material, primary_clamp_sampler, coords, ddx, ddy);
+        } else if (extract_material_flag(flags, FLAGS_WRAP_MIRROR)) {
+            return albedo_texture(material, primary_mirror_sampler, coords, ddx, ddy);
+        } else {
+            return albedo_texture(material, primary_sampler, coords, ddx, ddy);
+        }
+    }
+}
+
+fn alpha_test(vs_out: VertexOutput) {
     {{#if discard}}
     var material = materials[vs_out.material];
 
@@ -112,16 +146,28 @@ fn fs_main(vs_out: VertexOutput) {
     var alpha = 1.0;
     if (extract_material_flag(material.flags, FLAGS_ALBEDO_ACTIVE)) {
         if (has_albedo_texture(&material)) {
-            alpha = albedo_texture(&material, primary_sampler, coords, uvdx, uvdy).a;
+            alpha = material_albedo(&material, coords, uvdx, uvdy).a;
         }
         if (extract_material_flag(material.flags, FLAGS_ALBEDO_BLEND)) {
             alpha *= vs_out.alpha;
         }
     }
-    alpha *= material.albedo.a;
+    alpha *= material.albedo.a * object_buffer[vs_out.object].material_override.color.a;
 
     if (alpha < material.alpha_cutout) {
         discard;
     }
     {{/if}}
 }
+
+@fragment
+fn fs_main(vs_out: VertexOutput) {
+    alpha_test(vs_out);
+}
+
+// Writes the index of the object's handle, offset by one so 0 means no object.
+@fragment
+fn fs_object_id(vs_out: VertexOutput) -> @location(0) u32 {
+    alpha_test(vs_out);
+    return vs_out.object + 1u;
+}
@@ -0,0 +1,38 @@
+// Must match DepthReadbackCamera in depth_readback.rs.
+struct DepthReadbackCamera {
+    view: mat4x4<f32>,
+    proj: mat4x4<f32>,
+    inv_proj: mat4x4<f32>,
+    resolution: vec2<u32>,
+}
+
+@group(0) @binding(0)
+{{#if (eq SAMPLES 1)}}
+var depth_tex: texture_depth_2d;
+{{else}}
+var depth_tex: texture_depth_multisampled_2d;
+{{/if}}
+@group(0) @binding(1)
+var<uniform> camera: DepthReadbackCamera;
+@group(0) @binding(2)
+var<storage, read_write> depths: array<f32>;
+
+// Writes the view space depth of every pixel, multisampled depth is read from its first sample.
+@compute @workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    if (any(id.xy >= camera.resolution)) {
+        return;
+    }
+
+    let depth = textureLoad(depth_tex, id.xy, 0);
+
+    // The depth is cleared to 0, the far plane, where nothing was rendered.
+    var linear_depth = 0.0;
+    if (depth != 0.0) {
+        let uv = (vec2<f32>(id.xy) + 0.5) / vec2<f32>(camera.resolution);
+        let view = camera.inv_proj * vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth, 1.0);
+        linear_depth = abs(view.z / view.w);
+    }
+
+    depths[id.y * camera.resolution.x + id.x] = linear_depth;
+}
@@ -0,0 +1,33 @@
+{{include "rend3-routine/structures.wgsl"}}
+
+// Fraction of a surface `distance` away from the camera covered by fog, see FogMode in uniforms.rs.
+fn fog_factor(frame: UniformData, distance: f32) -> f32 {
+    var factor = 0.0;
+    switch (frame.fog_mode) {
+        case 1u: {
+            factor = saturate((distance - frame.fog_start) / max(frame.fog_end - frame.fog_start, 0.0001));
+        }
+        case 2u: {
+            factor = 1.0 - exp(-frame.fog_density * distance);
+        }
+        case 3u: {
+            let thickness = frame.fog_density * distance;
+            factor = 1.0 - exp(-thickness * thickness);
+        }
+        default: {}
+    }
+    return factor;
+}
+
+// Blends the color of a surface `distance` away from the camera into the fog color, keeping its alpha.
+fn apply_fog(frame: UniformData, color: vec4<f32>, distance: f32) -> vec4<f32> {
+    return vec4<f32>(mix(color.rgb, frame.fog_color, fog_factor(frame, distance)), color.a);
+}
+
+// Blends the skybox into the fog color by the configured amount.
+fn apply_skybox_fog(frame: UniformData, color: vec3<f32>) -> vec3<f32> {
+    if (frame.fog_mode == 0u) {
+        return color;
+    }
+    return mix(color, frame.fog_color, frame.skybox_fog);
+}
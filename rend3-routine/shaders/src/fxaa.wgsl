@@ -0,0 +1,87 @@
+{{include "rend3-routine/math/color.wgsl"}}
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) id: u32) -> VertexOutput {
+    var output: VertexOutput;
+    output.position = vec4<f32>(f32(id / 2u) * 4.0 - 1.0, f32(id % 2u) * 4.0 - 1.0, 0.0, 1.0);
+    output.tex_coords = vec2<f32>(f32(id / 2u) * 2.0, 1.0 - (f32(id % 2u) * 2.0));
+    return output;
+}
+
+@group(0) @binding(0)
+var primary_sampler: sampler;
+@group(1) @binding(0)
+var source: texture_2d<f32>;
+
+// Fraction of the average luma of the corners added to the edge direction, so
+// flat areas aren't blurred.
+const FXAA_REDUCE_MUL: f32 = 0.125;
+const FXAA_REDUCE_MIN: f32 = 0.0078125;
+// Distance in texels the blur along an edge can reach.
+const FXAA_SPAN_MAX: f32 = 8.0;
+
+// The sampler repeats, so keep the taps inside the image to not bleed
+// across opposite edges.
+fn sample_source(tex_coords: vec2<f32>) -> vec4<f32> {
+    let half_texel = 0.5 / vec2<f32>(textureDimensions(source));
+    let coords = clamp(tex_coords, half_texel, 1.0 - half_texel);
+    return textureSampleLevel(source, primary_sampler, coords, 0.0);
+}
+
+fn luma(color: vec3<f32>, linear: bool) -> f32 {
+    // Edges are found on perceived brightness, so linear colors are encoded first.
+    let display = select(color, srgb_scene_to_display(color), linear);
+    return dot(display, vec3<f32>(0.299, 0.587, 0.114));
+}
+
+// Blurs the texel along the edge it is on, if any.
+fn fxaa(tex_coords: vec2<f32>, linear: bool) -> vec4<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(source));
+
+    let middle = sample_source(tex_coords);
+    let luma_nw = luma(sample_source(tex_coords + texel * vec2<f32>(-1.0, -1.0)).rgb, linear);
+    let luma_ne = luma(sample_source(tex_coords + texel * vec2<f32>(1.0, -1.0)).rgb, linear);
+    let luma_sw = luma(sample_source(tex_coords + texel * vec2<f32>(-1.0, 1.0)).rgb, linear);
+    let luma_se = luma(sample_source(tex_coords + texel * vec2<f32>(1.0, 1.0)).rgb, linear);
+    let luma_m = luma(middle.rgb, linear);
+
+    let luma_min = min(luma_m, min(min(luma_nw, luma_ne), min(luma_sw, luma_se)));
+    let luma_max = max(luma_m, max(max(luma_nw, luma_ne), max(luma_sw, luma_se)));
+
+    // The edge runs perpendicular to the luma gradient.
+    var dir = vec2<f32>(-((luma_nw + luma_ne) - (luma_sw + luma_se)), (luma_nw + luma_sw) - (luma_ne + luma_se));
+    let dir_reduce = max((luma_nw + luma_ne + luma_sw + luma_se) * 0.25 * FXAA_REDUCE_MUL, FXAA_REDUCE_MIN);
+    let rcp_dir_min = 1.0 / (min(abs(dir.x), abs(dir.y)) + dir_reduce);
+    dir = clamp(dir * rcp_dir_min, vec2<f32>(-FXAA_SPAN_MAX), vec2<f32>(FXAA_SPAN_MAX)) * texel;
+
+    let near = 0.5 * (
+        sample_source(tex_coords + dir * (1.0 / 3.0 - 0.5)).rgb +
+        sample_source(tex_coords + dir * (2.0 / 3.0 - 0.5)).rgb
+    );
+    let far = near * 0.5 + 0.25 * (
+        sample_source(tex_coords + dir * -0.5).rgb +
+        sample_source(tex_coords + dir * 0.5).rgb
+    );
+
+    // The wider blur crossed another edge if it left the local luma range.
+    let luma_far = luma(far, linear);
+    let outside = luma_far < luma_min || luma_far > luma_max;
+    return vec4<f32>(select(far, near, outside), middle.a);
+}
+
+// The source holds linear colors, which are encoded when writing to the srgb output.
+@fragment
+fn fs_main_scene(vout: VertexOutput) -> @location(0) vec4<f32> {
+    return fxaa(vout.tex_coords, true);
+}
+
+// The source already holds display encoded colors.
+@fragment
+fn fs_main_monitor(vout: VertexOutput) -> @location(0) vec4<f32> {
+    return fxaa(vout.tex_coords, false);
+}
@@ -0,0 +1,162 @@
+{{include "rend3-routine/math/brdf.wgsl"}}
+
+// Must match PREFILTERED_MIPS in ibl.rs.
+const PREFILTERED_MIPS: u32 = 6u;
+const PREFILTER_SAMPLES: u32 = 256u;
+const IRRADIANCE_SAMPLES: u32 = 256u;
+const BRDF_LUT_SAMPLES: u32 = 512u;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+    @location(1) @interpolate(flat) layer: u32,
+};
+
+// When rendering cubemaps, the instance index is the face plus six times the mip being rendered.
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32, @builtin(instance_index) layer: u32) -> VertexOutput {
+    var out: VertexOutput;
+    let x = i32(vertex_index) / 2;
+    let y = i32(vertex_index) & 1;
+    let tc = vec2<f32>(
+        f32(x) * 2.0,
+        f32(y) * 2.0
+    );
+    out.position = vec4<f32>(
+        tc.x * 2.0 - 1.0,
+        1.0 - tc.y * 2.0,
+        0.0, 1.0
+    );
+    out.tex_coords = tc;
+    out.layer = layer;
+    return out;
+}
+
+@group(0) @binding(0)
+var environment: texture_cube<f32>;
+@group(0) @binding(1)
+var environment_sampler: sampler;
+
+fn face_direction(face: u32, uv: vec2<f32>) -> vec3<f32> {
+    let u = uv.x * 2.0 - 1.0;
+    let v = uv.y * 2.0 - 1.0;
+    switch face {
+        case 0u: { return vec3<f32>(1.0, -v, -u); }
+        case 1u: { return vec3<f32>(-1.0, -v, u); }
+        case 2u: { return vec3<f32>(u, 1.0, v); }
+        case 3u: { return vec3<f32>(u, -1.0, -v); }
+        case 4u: { return vec3<f32>(u, -v, 1.0); }
+        default: { return vec3<f32>(-u, -v, -1.0); }
+    }
+}
+
+// Low discrepancy point set, spreading the samples evenly.
+fn hammersley(i: u32, count: u32) -> vec2<f32> {
+    return vec2<f32>(f32(i) / f32(count), f32(reverseBits(i)) * 2.3283064365386963e-10);
+}
+
+// Rotates a vector around the z axis to be around n instead.
+fn tangent_to_world(v: vec3<f32>, n: vec3<f32>) -> vec3<f32> {
+    let up = select(vec3<f32>(1.0, 0.0, 0.0), vec3<f32>(0.0, 0.0, 1.0), abs(n.z) < 0.999);
+    let tangent = normalize(cross(up, n));
+    let bitangent = cross(n, tangent);
+    return tangent * v.x + bitangent * v.y + n * v.z;
+}
+
+// Half vector around the z axis, distributed like the GGX distribution with roughness a.
+fn importance_sample_ggx(xi: vec2<f32>, a: f32) -> vec3<f32> {
+    let phi = 2.0 * PI * xi.x;
+    let cos_theta = sqrt((1.0 - xi.y) / (1.0 + (a * a - 1.0) * xi.y));
+    let sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+    return vec3<f32>(cos(phi) * sin_theta, sin(phi) * sin_theta, cos_theta);
+}
+
+// Mip of the environment whose texels cover about the solid angle each sample stands for, so few samples don't alias.
+// Environments without mips are sampled at the base level.
+fn environment_lod(pdf: f32, samples: u32) -> f32 {
+    let size = f32(textureDimensions(environment).x);
+    let texel_solid_angle = 4.0 * PI / (6.0 * size * size);
+    let sample_solid_angle = 1.0 / (f32(samples) * pdf + 0.0001);
+    return max(0.5 * log2(sample_solid_angle / texel_solid_angle) + 1.0, 0.0);
+}
+
+// Radiance reflected towards the normal by a GGX lobe, assuming the view and reflection vectors equal the normal.
+@fragment
+fn fs_prefilter(in: VertexOutput) -> @location(0) vec4<f32> {
+    let n = normalize(face_direction(in.layer % 6u, in.tex_coords));
+    let mip = in.layer / 6u;
+
+    // Sampled before branching, as implicit derivatives need uniform control flow.
+    let mirror = textureSample(environment, environment_sampler, n).rgb;
+    if (mip == 0u) {
+        return vec4<f32>(mirror, 1.0);
+    }
+
+    let perceptual_roughness = f32(mip) / f32(PREFILTERED_MIPS - 1u);
+    let a = perceptual_roughness * perceptual_roughness;
+
+    var color = vec3<f32>(0.0);
+    var weight = 0.0;
+    for (var i = 0u; i < PREFILTER_SAMPLES; i += 1u) {
+        let h = tangent_to_world(importance_sample_ggx(hammersley(i, PREFILTER_SAMPLES), a), n);
+        let l = reflect(-n, h);
+        let nol = dot(n, l);
+        if (nol > 0.0) {
+            // With the view vector equal to the normal, the pdf of l is D * noh / (4 * voh) = D / 4.
+            let pdf = brdf_d_ggx(saturate(dot(n, h)), a) / 4.0;
+            let lod = environment_lod(pdf, PREFILTER_SAMPLES);
+            color += textureSampleLevel(environment, environment_sampler, l, lod).rgb * nol;
+            weight += nol;
+        }
+    }
+
+    return vec4<f32>(color / weight, 1.0);
+}
+
+// Cosine weighted average of the radiance over the hemisphere around the normal, the diffuse light for an albedo of 1.
+@fragment
+fn fs_irradiance(in: VertexOutput) -> @location(0) vec4<f32> {
+    let n = normalize(face_direction(in.layer % 6u, in.tex_coords));
+
+    var color = vec3<f32>(0.0);
+    for (var i = 0u; i < IRRADIANCE_SAMPLES; i += 1u) {
+        let xi = hammersley(i, IRRADIANCE_SAMPLES);
+        let phi = 2.0 * PI * xi.x;
+        let cos_theta = sqrt(1.0 - xi.y);
+        let sin_theta = sqrt(xi.y);
+        let l = tangent_to_world(vec3<f32>(cos(phi) * sin_theta, sin(phi) * sin_theta, cos_theta), n);
+        let lod = environment_lod(cos_theta / PI, IRRADIANCE_SAMPLES);
+        color += textureSampleLevel(environment, environment_sampler, l, lod).rgb;
+    }
+
+    return vec4<f32>(color / f32(IRRADIANCE_SAMPLES), 1.0);
+}
+
+// Scale and bias applied to f0 by the split sum approximation of the specular BRDF, indexed by the cosine of the
+// view angle and the perceptual roughness.
+@fragment
+fn fs_brdf_lut(in: VertexOutput) -> @location(0) vec4<f32> {
+    let nov = max(in.tex_coords.x, 0.0001);
+    let perceptual_roughness = in.tex_coords.y;
+    let a = perceptual_roughness * perceptual_roughness;
+    let v = vec3<f32>(sqrt(1.0 - nov * nov), 0.0, nov);
+
+    var scale = 0.0;
+    var bias = 0.0;
+    for (var i = 0u; i < BRDF_LUT_SAMPLES; i += 1u) {
+        let h = importance_sample_ggx(hammersley(i, BRDF_LUT_SAMPLES), a);
+        let l = reflect(-v, h);
+        let nol = saturate(l.z);
+        if (nol > 0.0) {
+            let noh = max(h.z, 0.0001);
+            let voh = saturate(dot(v, h));
+            // Visibility times the inverse of the pdf of l.
+            let g_vis = brdf_v_smith_ggx_correlated(nov, nol, a) * 4.0 * nol * voh / noh;
+            let fc = pow(1.0 - voh, 5.0);
+            scale += (1.0 - fc) * g_vis;
+            bias += fc * g_vis;
+        }
+    }
+
+    return vec4<f32>(scale, bias, 0.0, f32(BRDF_LUT_SAMPLES)) / f32(BRDF_LUT_SAMPLES);
+}
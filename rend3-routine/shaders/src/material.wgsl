@@ -13,6 +13,9 @@ const FLAGS_CC_GLTF_SPLIT: u32        = 0x0800u;
 const FLAGS_CC_BW_SPLIT: u32          = 0x1000u;
 const FLAGS_UNLIT: u32                = 0x2000u;
 const FLAGS_NEAREST: u32              = 0x4000u;
+const FLAGS_WRAP_CLAMP: u32           = 0x8000u;
+const FLAGS_WRAP_MIRROR: u32          = 0x10000u;
+const FLAGS_OBJECT_SPACE_NORMAL: u32  = 0x20000u;
 
 fn extract_material_flag(data: u32, flag: u32) -> bool {
     return bool(data & flag);
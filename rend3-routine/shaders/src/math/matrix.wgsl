@@ -1,7 +1,9 @@
-fn mat3_inv_scale_squared(transform: mat3x3<f32>) -> vec3<f32> {
-    return vec3<f32>(
-        1.0 / dot(transform[0].xyz, transform[0].xyz),
-        1.0 / dot(transform[1].xyz, transform[1].xyz),
-        1.0 / dot(transform[2].xyz, transform[2].xyz)
+// Transforms normals the same way `transform` transforms positions, even if it contains non-uniform scale or shear.
+fn mat3_inv_transpose(transform: mat3x3<f32>) -> mat3x3<f32> {
+    let cofactor = mat3x3<f32>(
+        cross(transform[1], transform[2]),
+        cross(transform[2], transform[0]),
+        cross(transform[0], transform[1])
     );
+    return cofactor * (1.0 / dot(transform[0], cofactor[0]));
 }
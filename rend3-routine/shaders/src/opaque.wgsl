@@ -5,6 +5,8 @@
 {{include "rend3-routine/math/color.wgsl"}}
 {{include "rend3-routine/math/matrix.wgsl"}}
 {{include "rend3-routine/shadow/pcf.wgsl"}}
+{{include "rend3-routine/shadow/variance.wgsl"}}
+{{include "rend3-routine/fog.wgsl"}}
 
 @group(0) @binding(0)
 var primary_sampler: sampler;
@@ -19,7 +21,27 @@ var<storage> directional_lights: DirectionalLightData;
 @group(0) @binding(5)
 var<storage> point_lights: PointLightData;
 @group(0) @binding(6)
+var<storage> spot_lights: SpotLightData;
+@group(0) @binding(7)
+var primary_clamp_sampler: sampler;
+@group(0) @binding(8)
+var primary_mirror_sampler: sampler;
+@group(0) @binding(9)
+var nearest_clamp_sampler: sampler;
+@group(0) @binding(10)
+var nearest_mirror_sampler: sampler;
+@group(0) @binding(11)
 var shadows: texture_depth_2d;
+@group(0) @binding(12)
+var screen_ambient_occlusion: texture_2d<f32>;
+@group(0) @binding(13)
+var environment_prefiltered: texture_cube<f32>;
+@group(0) @binding(14)
+var environment_irradiance: texture_cube<f32>;
+@group(0) @binding(15)
+var environment_brdf_lut: texture_2d<f32>;
+@group(0) @binding(16)
+var shadow_moments: texture_2d<f32>;
 
 @group(1) @binding(0)
 var<storage> object_buffer: array<Object>;
@@ -77,7 +99,8 @@ var ambient_occlusion_tex: texture_2d<f32>;
 }}
 
 struct VertexOutput {
-    @builtin(position) position: vec4<f32>,
+    // Invariant so the depth prepass and forward pass compute exactly the same depth.
+    @builtin(position) @invariant position: vec4<f32>,
     @location(0) view_position: vec4<f32>,
     @location(1) normal: vec3<f32>,
     @location(2) tangent: vec3<f32>,
@@ -85,6 +108,8 @@ struct VertexOutput {
     @location(4) coords1: vec2<f32>,
     @location(6) color: vec4<f32>,
     @location(7) @interpolate(flat) material: u32,
+    @location(8) @interpolate(flat) object_flags: u32,
+    @location(9) @interpolate(flat) object: u32,
 }
 
 
@@ -119,15 +144,17 @@ fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
     let position_vec4 = vec4<f32>(vs_in.position, 1.0);
     let mv_mat3 = mat3x3<f32>(model_view[0].xyz, model_view[1].xyz, model_view[2].xyz);
 
-    let inv_scale_sq = mat3_inv_scale_squared(mv_mat3);
+    let normal_mat3 = mat3_inv_transpose(mv_mat3);
 
     var vs_out: VertexOutput;
     vs_out.material = data.material_index;
+    vs_out.object_flags = data.flags;
+    vs_out.object = indices.object;
     vs_out.view_position = model_view * position_vec4;
-    vs_out.normal = normalize(mv_mat3 * (inv_scale_sq * vs_in.normal));
-    vs_out.tangent = normalize(mv_mat3 * (inv_scale_sq * vs_in.tangent));
+    vs_out.normal = normalize(normal_mat3 * vs_in.normal);
+    vs_out.tangent = normalize(mv_mat3 * vs_in.tangent);
     vs_out.color = vs_in.color_0;
-    vs_out.coords0 = vs_in.texture_coords_0;
+    vs_out.coords0 = vs_in.texture_coords_0 * data.material_override.uv_scale + data.material_override.uv_offset;
     vs_out.coords1 = vs_in.texture_coords_1;
     vs_out.position = model_view_proj * position_vec4;
 
@@ -226,7 +253,8 @@ fn get_pixel_data_inner(material_arg: Material, s: sampler, vs_out: VertexOutput
     } else {
         pixel.albedo = vec4<f32>(0.0, 0.0, 0.0, 1.0);
     }
-    pixel.albedo *= material.albedo;
+    let material_override = object_buffer[vs_out.object].material_override;
+    pixel.albedo *= material.albedo * material_override.color;
 
     {{#if discard}}
     if (pixel.albedo.a < material.alpha_cutout) {
@@ -263,13 +291,23 @@ fn get_pixel_data_inner(material_arg: Material, s: sampler, vs_out: VertexOutput
         if (extract_material_flag(material.flags, FLAGS_YDOWN_NORMAL)) {
             normal.y = -normal.y;
         }
-        let normal_norm = normalize(vs_out.normal);
-        let tangent_norm = normalize(vs_out.tangent);
-        let bitangent = cross(normal_norm, tangent_norm);
+        if (extract_material_flag(material.flags, FLAGS_OBJECT_SPACE_NORMAL)) {
+            let model_view = per_camera_uniform.objects[vs_out.object].model_view;
+            let mv_mat3 = mat3x3<f32>(model_view[0].xyz, model_view[1].xyz, model_view[2].xyz);
+            pixel.normal = mat3_inv_transpose(mv_mat3) * normal;
+            // Back faces have their vertex normal flipped, follow it.
+            if (dot(pixel.normal, vs_out.normal) < 0.0) {
+                pixel.normal = -pixel.normal;
+            }
+        } else {
+            let normal_norm = normalize(vs_out.normal);
+            let tangent_norm = normalize(vs_out.tangent);
+            let bitangent = cross(normal_norm, tangent_norm);
 
-        let tbn = mat3x3(tangent_norm, bitangent, normal_norm);
+            let tbn = mat3x3(tangent_norm, bitangent, normal_norm);
 
-        pixel.normal = tbn * normal;
+            pixel.normal = tbn * normal;
+        }
     } else {
         pixel.normal = vs_out.normal;
     }
@@ -396,6 +434,7 @@ fn get_pixel_data_inner(material_arg: Material, s: sampler, vs_out: VertexOutput
     } else {
         pixel.emissive = material.emissive;
     }
+    pixel.emissive *= material_override.emissive_scale;
 
     // --- ANISOTROPY ---
 
@@ -423,19 +462,25 @@ fn get_pixel_data_inner(material_arg: Material, s: sampler, vs_out: VertexOutput
     return pixel;
 }
 
-{{#if (eq profile "GpuDriven")}}
 fn get_pixel_data(material: Material, vs_out: VertexOutput) -> PixelData {
     if (extract_material_flag(material.flags, FLAGS_NEAREST)) {
-        return get_pixel_data_inner(material, nearest_sampler, vs_out);
+        if (extract_material_flag(material.flags, FLAGS_WRAP_CLAMP)) {
+            return get_pixel_data_inner(material, nearest_clamp_sampler, vs_out);
+        } else if (extract_material_flag(material.flags, FLAGS_WRAP_MIRROR)) {
+            return get_pixel_data_inner(material, nearest_mirror_sampler, vs_out);
+        } else {
+            return get_pixel_data_inner(material, nearest_sampler, vs_out);
+        }
     } else {
-        return get_pixel_data_inner(material, primary_sampler, vs_out);
+        if (extract_material_flag(material.flags, FLAGS_WRAP_CLAMP)) {
+            return get_pixel_data_inner(material, primary_clamp_sampler, vs_out);
+        } else if (extract_material_flag(material.flags, FLAGS_WRAP_MIRROR)) {
+            return get_pixel_data_inner(material, primary_mirror_sampler, vs_out);
+        } else {
+            return get_pixel_data_inner(material, primary_sampler, vs_out);
+        }
     }
 }
-{{else}}
-fn get_pixel_data(material: Material, vs_out: VertexOutput) -> PixelData {
-    return get_pixel_data_inner(material, primary_sampler, vs_out);
-}
-{{/if}}
 
 fn surface_shading(light_dir: vec3<f32>, intensity: vec3<f32>, pixel: PixelData, view_pos: vec3<f32>, occlusion: f32) -> vec3<f32> {
     let n = pixel.normal;
@@ -467,14 +512,226 @@ fn surface_shading(light_dir: vec3<f32>, intensity: vec3<f32>, pixel: PixelData,
     return (color * intensity) * (light_attenuation * nol * occlusion);
 }
 
+// Index of the shadow cube face that the given light-to-fragment vector falls into.
+fn point_shadow_face(dir: vec3<f32>) -> u32 {
+    let a = abs(dir);
+    if (a.x >= a.y && a.x >= a.z) {
+        return select(0u, 1u, dir.x < 0.0);
+    }
+    if (a.y >= a.z) {
+        return select(2u, 3u, dir.y < 0.0);
+    }
+    return select(4u, 5u, dir.z < 0.0);
+}
+
+// Samples a shadow map rendered with any projection. Fragments outside the frustum are unshadowed.
+fn projected_shadow_value(view_proj: mat4x4<f32>, rect: vec4<f32>, inv_resolution: vec2<f32>, bias: f32, world_position: vec4<f32>) -> f32 {
+    let shadow_clip = view_proj * world_position;
+    if (shadow_clip.w <= 0.0) {
+        return 1.0;
+    }
+    let shadow_ndc = shadow_clip.xyz / shadow_clip.w;
+    if (shadow_ndc.z < 0.0 || shadow_ndc.z > 1.0) {
+        return 1.0;
+    }
+    let shadow_flipped = (shadow_ndc.xy * 0.5) + 0.5;
+    let shadow_local_coords = vec2<f32>(shadow_flipped.x, 1.0 - shadow_flipped.y);
+    if (any(shadow_local_coords < vec2<f32>(0.0)) || any(shadow_local_coords > vec2<f32>(1.0))) {
+        return 1.0;
+    }
+
+    let shadow_coords = rect.xy + rect.zw * shadow_local_coords;
+
+    // Keep the pcf taps from reading the neighbouring maps in the atlas.
+    let shadow_border = inv_resolution * (f32(uniforms.shadow_filter_size / 2u) + 0.5);
+    let clamped_coords = clamp(shadow_coords, rect.xy + shadow_border, rect.xy + rect.zw - shadow_border);
+
+    // Depth is reversed, so the bias moves the fragment towards the light.
+    let depth = shadow_ndc.z + bias;
+    if (uniforms.variance_shadows != 0u) {
+        return shadow_sample_variance(shadow_moments, clamped_coords, depth, uniforms.shadow_bleed_reduction);
+    }
+    return shadow_sample_pcf(shadows, comparison_sampler, clamped_coords, depth, inv_resolution, uniforms.shadow_filter_size);
+}
+
+// Moves the fragment along its normal to keep it from shadowing itself.
+fn shadow_offset_position(world_position: vec4<f32>, world_normal: vec3<f32>, normal_offset: f32) -> vec4<f32> {
+    return vec4<f32>(world_position.xyz + world_normal * normal_offset, world_position.w);
+}
+
+// Constant bias plus the slope bias scaled by the tangent of the angle between the surface and the light.
+// The tangent is capped, as it goes to infinity for surfaces parallel to the light.
+fn shadow_depth_bias(constant_bias: f32, slope_bias: f32, world_normal: vec3<f32>, to_light: vec3<f32>) -> f32 {
+    let cos_angle = clamp(dot(world_normal, to_light), 0.1, 1.0);
+    let tan_angle = sqrt(1.0 - cos_angle * cos_angle) / cos_angle;
+    return constant_bias + slope_bias * tan_angle;
+}
+
+fn point_shadow_value(light_index: i32, world_position: vec4<f32>, world_normal: vec3<f32>) -> f32 {
+    let light = point_lights.data[light_index];
+    if (light.shadowed == 0u) {
+        return 1.0;
+    }
+
+    let offset_position = shadow_offset_position(world_position, world_normal, light.shadow_normal_offset);
+    // The fragment is always inside the frustum of the face it selects.
+    let face = point_shadow_face(offset_position.xyz - light.position.xyz);
+    let to_light = normalize(light.position.xyz - world_position.xyz);
+    return projected_shadow_value(
+        point_lights.data[light_index].shadow_view_proj[face],
+        point_lights.data[light_index].shadow_atlas_rects[face],
+        light.inv_resolution,
+        shadow_depth_bias(light.shadow_bias, light.shadow_slope_bias, world_normal, to_light),
+        offset_position,
+    );
+}
+
+// Cascade of the light covering the given view space depth.
+fn directional_cascade(light_index: i32, view_depth: f32) -> u32 {
+    let light = directional_lights.data[light_index];
+
+    var cascade = 0u;
+    while (cascade + 1u < light.cascade_count && view_depth > light.cascade_ends[cascade]) {
+        cascade += 1u;
+    }
+    return cascade;
+}
+
+// Selects the cascade by view space depth, blending into the next cascade near the end of each one.
+fn directional_shadow_value(light_index: i32, view_depth: f32, world_position: vec4<f32>, world_normal: vec3<f32>) -> f32 {
+    let light = directional_lights.data[light_index];
+    let offset_position = shadow_offset_position(world_position, world_normal, light.shadow_normal_offset);
+    let bias = shadow_depth_bias(light.shadow_bias, light.shadow_slope_bias, world_normal, normalize(-light.direction));
+
+    let cascade = directional_cascade(light_index, view_depth);
+
+    var shadow_value = projected_shadow_value(
+        directional_lights.data[light_index].view_proj[cascade],
+        directional_lights.data[light_index].atlas_rects[cascade],
+        light.inv_resolution,
+        bias,
+        offset_position,
+    );
+
+    if (cascade + 1u < light.cascade_count) {
+        let cascade_start = select(0.0, light.cascade_ends[max(cascade, 1u) - 1u], cascade > 0u);
+        let cascade_end = light.cascade_ends[cascade];
+        let blend_start = cascade_end - (cascade_end - cascade_start) * light.cascade_blend;
+        if (view_depth > blend_start) {
+            let next_value = projected_shadow_value(
+                directional_lights.data[light_index].view_proj[cascade + 1u],
+                directional_lights.data[light_index].atlas_rects[cascade + 1u],
+                light.inv_resolution,
+                bias,
+                offset_position,
+            );
+            shadow_value = mix(shadow_value, next_value, (view_depth - blend_start) / (cascade_end - blend_start));
+        }
+    }
+
+    return shadow_value;
+}
+
+// Light from the environment maps, using the split sum approximation for the specular part.
+fn environment_lighting(pixel: PixelData, v: vec3<f32>, inv_view_mat3: mat3x3<f32>) -> vec3<f32> {
+    let nov = saturate(dot(pixel.normal, v));
+    let world_normal = inv_view_mat3 * pixel.normal;
+    let world_reflection = inv_view_mat3 * reflect(-v, pixel.normal);
+
+    let irradiance = textureSampleLevel(environment_irradiance, primary_clamp_sampler, world_normal, 0.0).rgb;
+
+    // Every mip of the prefiltered map is rougher by the same step of perceptual roughness.
+    let lod = pixel.perceptual_roughness * f32(textureNumLevels(environment_prefiltered) - 1u);
+    let prefiltered = textureSampleLevel(environment_prefiltered, primary_clamp_sampler, world_reflection, lod).rgb;
+    let brdf = textureSampleLevel(environment_brdf_lut, primary_clamp_sampler, vec2<f32>(nov, pixel.perceptual_roughness), 0.0).rg;
+
+    let diffuse = irradiance * pixel.diffuse_color;
+    let specular = prefiltered * (pixel.f0 * brdf.x + brdf.y);
+    return (diffuse + specular) * uniforms.background_intensity;
+}
+
+// Values of uniforms.debug_view, see DebugView.
+const DEBUG_VIEW_NONE: u32 = 0u;
+const DEBUG_VIEW_NORMALS: u32 = 1u;
+const DEBUG_VIEW_ALBEDO: u32 = 2u;
+const DEBUG_VIEW_ROUGHNESS: u32 = 3u;
+const DEBUG_VIEW_METALLIC: u32 = 4u;
+const DEBUG_VIEW_DEPTH: u32 = 5u;
+const DEBUG_VIEW_SHADOW_CASCADES: u32 = 6u;
+const DEBUG_VIEW_OVERDRAW: u32 = 7u;
+
+// Added up by the overdraw pipelines for every fragment.
+const OVERDRAW_STEP: f32 = 0.1;
+
+fn cascade_color(cascade: u32) -> vec3<f32> {
+    switch (cascade) {
+        case 0u: { return vec3<f32>(1.0, 0.0, 0.0); }
+        case 1u: { return vec3<f32>(0.0, 1.0, 0.0); }
+        case 2u: { return vec3<f32>(0.0, 0.0, 1.0); }
+        default: { return vec3<f32>(1.0, 1.0, 0.0); }
+    }
+}
+
+fn debug_view_color(pixel: PixelData, vs_out: VertexOutput, distance: f32) -> vec4<f32> {
+    var color = vec3<f32>(0.0);
+    switch (uniforms.debug_view) {
+        case DEBUG_VIEW_NORMALS: {
+            let inv_view_mat3 = mat3x3<f32>(uniforms.inv_view[0].xyz, uniforms.inv_view[1].xyz, uniforms.inv_view[2].xyz);
+            color = normalize(inv_view_mat3 * pixel.normal) * 0.5 + 0.5;
+        }
+        case DEBUG_VIEW_ALBEDO: {
+            color = pixel.albedo.rgb;
+        }
+        case DEBUG_VIEW_ROUGHNESS: {
+            color = vec3<f32>(pixel.perceptual_roughness);
+        }
+        case DEBUG_VIEW_METALLIC: {
+            color = vec3<f32>(pixel.metallic);
+        }
+        case DEBUG_VIEW_DEPTH: {
+            color = vec3<f32>(1.0 / (1.0 + distance));
+        }
+        case DEBUG_VIEW_SHADOW_CASCADES: {
+            if (directional_lights.count > 0u) {
+                color = cascade_color(directional_cascade(0, abs(vs_out.view_position.z)));
+            }
+        }
+        case DEBUG_VIEW_OVERDRAW: {
+            color = vec3<f32>(OVERDRAW_STEP);
+        }
+        default: {}
+    }
+    return vec4<f32>(color, 1.0);
+}
+
+// Applies fog, unless the object was added without ObjectFlags::RECEIVE_FOG.
+fn apply_object_fog(object_flags: u32, color: vec4<f32>, distance: f32) -> vec4<f32> {
+    if ((object_flags & OBJECT_FLAGS_RECEIVE_FOG) == 0u) {
+        return color;
+    }
+    return apply_fog(uniforms, color, distance);
+}
+
 @fragment
-fn fs_main(vs_out: VertexOutput) -> @location(0) vec4<f32> {
+fn fs_main(input: VertexOutput, @builtin(front_facing) front_facing: bool) -> @location(0) vec4<f32> {
+    // Back faces are only rasterized for double sided materials, and are shaded as if seen from the front.
+    var vs_out = input;
+    if (!front_facing) {
+        vs_out.normal = -vs_out.normal;
+    }
+
     let material = materials[vs_out.material];
 
     let pixel = get_pixel_data(material, vs_out);
 
+    let distance = length(vs_out.view_position.xyz);
+
+    if (uniforms.debug_view != DEBUG_VIEW_NONE) {
+        return debug_view_color(pixel, vs_out, distance);
+    }
+
     if (extract_material_flag(material.flags, FLAGS_UNLIT)) {
-        return pixel.albedo;
+        return apply_object_fog(vs_out.object_flags, pixel.albedo, distance);
     }
 
     // View vector
@@ -483,37 +740,16 @@ fn fs_main(vs_out: VertexOutput) -> @location(0) vec4<f32> {
     // Transform vectors into view space
     let view_mat3 = mat3x3<f32>(uniforms.view[0].xyz, uniforms.view[1].xyz, uniforms.view[2].xyz);
 
+    let world_position = uniforms.inv_view * vs_out.view_position;
+    let inv_view_mat3 = mat3x3<f32>(uniforms.inv_view[0].xyz, uniforms.inv_view[1].xyz, uniforms.inv_view[2].xyz);
+    // The geometric normal, as normal maps would make the offset follow fake detail.
+    let world_normal = normalize(inv_view_mat3 * vs_out.normal);
+
     var color = pixel.emissive.rgb;
     for (var i = 0; i < i32(directional_lights.count); i += 1) {
         let light = directional_lights.data[i];
 
-        // Get the shadow ndc coordinates, then convert to texture sample coordinates
-        let shadow_ndc = (light.view_proj * uniforms.inv_view * vs_out.view_position).xyz;
-        let shadow_flipped = (shadow_ndc.xy * 0.5) + 0.5;
-        let shadow_local_coords = vec2<f32>(shadow_flipped.x, 1.0 - shadow_flipped.y);
-
-        // Texture sample coordinates of 
-        var top_left = light.offset;
-        var top_right = top_left + light.size;
-        let shadow_coords = mix(top_left, top_right, shadow_local_coords);
-
-        // The shadow is stored in an atlas, so we need to make sure we don't linear blend
-        // across atlasses. We move our conditional borders in a half a pixel for standard
-        // linear blending (so we're hitting texel centers on the edge). We move it an additional
-        // pixel in so that our pcf5 offsets don't move off the edge of the atlasses.
-        let shadow_border = light.inv_resolution * 1.5;
-        top_left += shadow_border;
-        top_right -= shadow_border;
-
-        var shadow_value = 1.0;
-        if (
-            any(shadow_flipped >= top_left) && // XY lower
-            any(shadow_flipped <= top_right) && // XY upper
-            shadow_ndc.z >= 0.0 && // Z lower
-            shadow_ndc.z <= 1.0 // Z upper
-        ) {
-            shadow_value = shadow_sample_pcf5(shadows, comparison_sampler, shadow_coords, shadow_ndc.z);
-        }
+        let shadow_value = directional_shadow_value(i, abs(vs_out.view_position.z), world_position, world_normal);
 
         // Calculate light source vector
         let l = normalize(view_mat3 * -light.direction);
@@ -542,10 +778,66 @@ fn fs_main(vs_out: VertexOutput) -> @location(0) vec4<f32> {
         // Calculate light source vector
         let l = delta / d;
 
-        color += max(surface_shading(l, intensity, pixel, v, pixel.ambient_occlusion), vec3<f32>(0.0));
+        let shadow_value = point_shadow_value(i, world_position, world_normal);
+
+        color += max(surface_shading(l, intensity, pixel, v, shadow_value * pixel.ambient_occlusion), vec3<f32>(0.0));
+    }
+
+    for (var i = 0; i < i32(spot_lights.count); i += 1) {
+        let light = spot_lights.data[i];
+
+        // Delta to light
+        let delta = (uniforms.view * light.position).xyz - vs_out.view_position.xyz;
+        let d = length(delta);
+
+        // Same distance attenuation as point lights, using range as the radius.
+        let s = saturate(d / light.range);
+        let s2 = s * s;
+        let inv_s2 = 1.0 - s2;
+        let distance_att = inv_s2 * inv_s2 / (1.0 + s2);
+
+        // Calculate light source vector
+        let l = delta / d;
+
+        // Smooth angular falloff between the inner and outer cone.
+        let cos_angle = dot(-l, normalize(view_mat3 * light.direction));
+        let angular_att = smoothstep(light.cos_outer, light.cos_inner, cos_angle);
+
+        let intensity = light.color * (distance_att * angular_att);
+
+        var shadow_value = 1.0;
+        if (light.shadowed != 0u && angular_att > 0.0) {
+            shadow_value = projected_shadow_value(
+                light.shadow_view_proj,
+                light.shadow_atlas_rect,
+                light.inv_resolution,
+                shadow_depth_bias(light.shadow_bias, light.shadow_slope_bias, world_normal, normalize(light.position.xyz - world_position.xyz)),
+                shadow_offset_position(world_position, world_normal, light.shadow_normal_offset),
+            );
+        }
+
+        color += max(surface_shading(l, intensity, pixel, v, shadow_value * pixel.ambient_occlusion), vec3<f32>(0.0));
     }
 
-    let ambient = uniforms.ambient * pixel.albedo;
     let shaded = vec4<f32>(color, pixel.albedo.a);
-    return max(ambient, shaded);
+    if ((vs_out.object_flags & OBJECT_FLAGS_RECEIVE_AMBIENT) == 0u) {
+        return apply_object_fog(vs_out.object_flags, shaded, distance);
+    }
+    // White when SSAO is disabled.
+    let screen_uv = vs_out.position.xy / vec2<f32>(uniforms.resolution);
+    let screen_occlusion = textureSampleLevel(screen_ambient_occlusion, primary_sampler, screen_uv, 0.0).r;
+    if (uniforms.environment_lighting != 0u) {
+        let ambient = environment_lighting(pixel, v, inv_view_mat3) * (pixel.ambient_occlusion * screen_occlusion);
+        return apply_object_fog(vs_out.object_flags, vec4<f32>(color + ambient, pixel.albedo.a), distance);
+    }
+    let ambient = uniforms.ambient * pixel.albedo * screen_occlusion;
+    return apply_object_fog(vs_out.object_flags, max(ambient, shaded), distance);
+}
+
+// Color of every line when rendering wireframes.
+const WIREFRAME_COLOR: vec4<f32> = vec4<f32>(1.0, 1.0, 1.0, 1.0);
+
+@fragment
+fn fs_wireframe() -> @location(0) vec4<f32> {
+    return WIREFRAME_COLOR;
 }
@@ -0,0 +1,11 @@
+@group(0) @binding(0)
+var object_ids: texture_2d<u32>;
+@group(0) @binding(1)
+var<uniform> pixel: vec2<u32>;
+@group(0) @binding(2)
+var<storage, read_write> picked: u32;
+
+@compute @workgroup_size(1)
+fn main() {
+    picked = textureLoad(object_ids, pixel, 0).r;
+}
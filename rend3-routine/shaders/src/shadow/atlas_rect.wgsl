@@ -0,0 +1,30 @@
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    // Texels of the shadow map, min inclusive and max exclusive.
+    @location(0) @interpolate(flat) rect: vec4<i32>,
+}
+
+struct ShadowRects {
+    atlas_size: vec2<u32>,
+    // Offset and size in texels of every shadow map in the atlas.
+    rects: array<vec4<u32>>,
+}
+
+@group(0) @binding(0)
+var<storage> shadow_rects: ShadowRects;
+
+// Covers the shadow map of the instance with two triangles.
+@vertex
+fn vs_main(@builtin(vertex_index) id: u32, @builtin(instance_index) instance: u32) -> VertexOutput {
+    let rect = shadow_rects.rects[instance];
+    var corners = array<u32, 6>(0u, 1u, 2u, 2u, 1u, 3u);
+    let corner = vec2<u32>(corners[id] & 1u, corners[id] >> 1u);
+
+    let texel = vec2<f32>(rect.xy + corner * rect.zw);
+    let uv = texel / vec2<f32>(shadow_rects.atlas_size);
+
+    var output: VertexOutput;
+    output.position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    output.rect = vec4<i32>(vec2<i32>(rect.xy), vec2<i32>(rect.xy + rect.zw));
+    return output;
+}
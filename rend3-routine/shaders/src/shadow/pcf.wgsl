@@ -1,9 +1,14 @@
-fn shadow_sample_pcf5(tex: texture_depth_2d, samp: sampler_comparison, coords: vec2<f32>, depth: f32) -> f32 {
+// Averages the comparisons of a `kernel_size` x `kernel_size` square of texels around `coords`.
+// A kernel size of 1 is a single hard comparison.
+fn shadow_sample_pcf(tex: texture_depth_2d, samp: sampler_comparison, coords: vec2<f32>, depth: f32, inv_resolution: vec2<f32>, kernel_size: u32) -> f32 {
+    let radius = i32(kernel_size / 2u);
     var result: f32 = 0.0;
-    result = result + textureSampleCompareLevel(tex, samp, coords, depth);
-    result = result + textureSampleCompareLevel(tex, samp, coords, depth, vec2<i32>( 0,  1));
-    result = result + textureSampleCompareLevel(tex, samp, coords, depth, vec2<i32>( 0, -1));
-    result = result + textureSampleCompareLevel(tex, samp, coords, depth, vec2<i32>( 1,  0));
-    result = result + textureSampleCompareLevel(tex, samp, coords, depth, vec2<i32>(-1,  0));
-    return result * 0.2;
+    for (var y = -radius; y <= radius; y += 1) {
+        for (var x = -radius; x <= radius; x += 1) {
+            let offset = vec2<f32>(f32(x), f32(y)) * inv_resolution;
+            result += textureSampleCompareLevel(tex, samp, coords + offset, depth);
+        }
+    }
+    let width = f32(radius * 2 + 1);
+    return result / (width * width);
 }
@@ -0,0 +1,28 @@
+// Floor of the variance, keeping flat occluders from shadowing themselves through rounding errors.
+const MIN_SHADOW_VARIANCE: f32 = 0.00001;
+
+// Bilinearly filtered moments around `coords`. The moments are 32 bit floats, which can't be filtered by the sampler.
+fn shadow_moments_bilinear(tex: texture_2d<f32>, coords: vec2<f32>) -> vec2<f32> {
+    let texel = coords * vec2<f32>(textureDimensions(tex)) - 0.5;
+    let base = vec2<i32>(floor(texel));
+    let weight = fract(texel);
+
+    let top = mix(textureLoad(tex, base, 0).xy, textureLoad(tex, base + vec2<i32>(1, 0), 0).xy, weight.x);
+    let bottom = mix(textureLoad(tex, base + vec2<i32>(0, 1), 0).xy, textureLoad(tex, base + vec2<i32>(1, 1), 0).xy, weight.x);
+    return mix(top, bottom, weight.y);
+}
+
+// Upper bound of the fraction of the blurred occluders behind `depth`, given by Chebyshev's inequality. Depth is
+// reversed, so larger depths are closer to the light. Bounds below `bleed_reduction` are cut off, and the rest is
+// rescaled to 0..1, darkening the light leaking through overlapping occluders.
+fn shadow_sample_variance(tex: texture_2d<f32>, coords: vec2<f32>, depth: f32, bleed_reduction: f32) -> f32 {
+    let moments = shadow_moments_bilinear(tex, coords);
+    if (depth >= moments.x) {
+        return 1.0;
+    }
+
+    let variance = max(moments.y - moments.x * moments.x, MIN_SHADOW_VARIANCE);
+    let distance = depth - moments.x;
+    let bound = variance / (variance + distance * distance);
+    return saturate((bound - bleed_reduction) / max(1.0 - bleed_reduction, 0.0001));
+}
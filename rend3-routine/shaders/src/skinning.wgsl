@@ -22,6 +22,15 @@ struct SkinningInput {
     joint_matrix_base_offset: u32,
     /// Count of vertices in this mesh.
     vertex_count: u32,
+
+    /// Byte offset into vertex buffer of the position offsets of all morph targets.
+    morph_position_offset: u32,
+    /// Byte offset into vertex buffer of the normal offsets of all morph targets.
+    morph_normal_offset: u32,
+    /// Count of morph targets of the mesh, 0 to skip blending them.
+    morph_target_count: u32,
+    /// Index into the morph weight buffer of the weight of the first morph target.
+    morph_weight_base_offset: u32,
 }
 
 @group(0) @binding(0)
@@ -30,10 +39,25 @@ var<storage, read_write> vertex_buffer: array<u32>;
 var<storage> input: SkinningInput;
 @group(0) @binding(2)
 var<storage> joint_matrices: array<mat4x4<f32>>;
+@group(0) @binding(3)
+var<storage> morph_weights: array<f32>;
 
 {{include "rend3/vertex_attributes.wgsl"}}
 {{include "rend3/vertex_attributes_store.wgsl"}}
 
+// Write to output region of buffer
+fn store_deformed(idx: u32, pos: vec3<f32>, normal: vec3<f32>, tangent: vec3<f32>) {
+    if (input.updated_position_offset != 0xFFFFFFFFu) {
+        store_attribute_vec3_f32(input.updated_position_offset, idx, pos);
+    }
+    if (input.updated_normal_offset != 0xFFFFFFFFu) {
+        store_attribute_vec3_f32(input.updated_normal_offset, idx, normal);
+    }
+    if (input.updated_tangent_offset != 0xFFFFFFFFu) {
+        store_attribute_vec3_f32(input.updated_tangent_offset, idx, tangent);
+    }
+}
+
 @compute @workgroup_size(256)
 fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
     let idx = global_id.x;
@@ -42,14 +66,6 @@ fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
         return;
     }
 
-    let joint_indices = extract_attribute_vec4_u16(input.joint_indices_offset, idx);
-    let joint_weights = extract_attribute_vec4_f32(input.joint_weight_offset, idx);
-
-    // Compute the skinned position
-    var pos_acc = vec3<f32>(0.0);
-    var norm_acc = vec3<f32>(0.0);
-    var tang_acc = vec3<f32>(0.0);
-
     var pos = vec3<f32>(0.0);
     var normal = vec3<f32>(0.0);
     var tangent = vec3<f32>(0.0);
@@ -62,7 +78,34 @@ fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
     if (input.base_tangent_offset != 0xFFFFFFFFu) {
         tangent = extract_attribute_vec3_f32(input.base_tangent_offset, idx);
     }
-    
+
+    // Blend the morph targets in before skinning. The offsets of each target are stored one after the other.
+    for (var morph = 0u; morph < input.morph_target_count; morph++) {
+        let weight = morph_weights[input.morph_weight_base_offset + morph];
+
+        if (weight != 0.0) {
+            let offset_index = morph * input.vertex_count + idx;
+            pos += extract_attribute_vec3_f32(input.morph_position_offset, offset_index) * weight;
+            if (input.morph_normal_offset != 0xFFFFFFFFu) {
+                normal += extract_attribute_vec3_f32(input.morph_normal_offset, offset_index) * weight;
+            }
+        }
+    }
+
+    // Meshes deformed only by morph targets have no joints.
+    if (input.joint_indices_offset == 0xFFFFFFFFu) {
+        store_deformed(idx, pos, normalize(normal), normalize(tangent));
+        return;
+    }
+
+    let joint_indices = extract_attribute_vec4_u16(input.joint_indices_offset, idx);
+    let joint_weights = extract_attribute_vec4_f32(input.joint_weight_offset, idx);
+
+    // Compute the skinned position
+    var pos_acc = vec3<f32>(0.0);
+    var norm_acc = vec3<f32>(0.0);
+    var tang_acc = vec3<f32>(0.0);
+
     for (var i = 0; i < 4; i++) {
         let weight = joint_weights[i];
 
@@ -72,23 +115,10 @@ fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
             let joint_matrix3 = mat3x3<f32>(joint_matrix[0].xyz, joint_matrix[1].xyz, joint_matrix[2].xyz);
             pos_acc += (joint_matrix * vec4<f32>(pos, 1.0)).xyz * weight;
             
-            let inv_scale_sq = mat3_inv_scale_squared(joint_matrix3);
-            norm_acc += (joint_matrix3 * (inv_scale_sq * normal)) * weight;
-            tang_acc += (joint_matrix3 * (inv_scale_sq * tangent)) * weight;
+            norm_acc += (mat3_inv_transpose(joint_matrix3) * normal) * weight;
+            tang_acc += (joint_matrix3 * tangent) * weight;
         }
     }
 
-    norm_acc = normalize(norm_acc);
-    tang_acc = normalize(tang_acc);
-    
-    // Write to output region of buffer
-    if (input.updated_position_offset != 0xFFFFFFFFu) {
-        store_attribute_vec3_f32(input.updated_position_offset, idx, pos_acc);
-    }
-    if (input.updated_normal_offset != 0xFFFFFFFFu) {
-        store_attribute_vec3_f32(input.updated_normal_offset, idx, norm_acc);
-    }
-    if (input.updated_tangent_offset != 0xFFFFFFFFu) {
-        store_attribute_vec3_f32(input.updated_tangent_offset, idx, tang_acc);
-    }
-}
\ No newline at end of file
+    store_deformed(idx, pos_acc, normalize(norm_acc), normalize(tang_acc));
+}
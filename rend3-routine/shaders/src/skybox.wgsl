@@ -1,4 +1,5 @@
 {{include "rend3-routine/structures.wgsl"}}
+{{include "rend3-routine/fog.wgsl"}}
 
 struct VertexOutput {
     @builtin(position) pos: vec4<f32>,
@@ -28,7 +29,7 @@ fn fs_main(output: VertexOutput) -> @location(0) vec4<f32> {
     let world = world_undiv.xyz / world_undiv.w;
     let world_dir = normalize(world);
 
-    let background = textureSample(skybox, primary_sampler, world_dir).rgb;
+    let background = textureSample(skybox, primary_sampler, world_dir).rgb * uniforms.background_intensity;
 
-    return vec4<f32>(background, 1.0);
+    return vec4<f32>(apply_skybox_fog(uniforms, background), 1.0);
 }
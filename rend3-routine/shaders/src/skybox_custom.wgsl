@@ -0,0 +1,36 @@
+// -- DO NOT VALIDATE --
+// `skybox_color` is provided by the user, see CustomSkyboxShader.
+
+{{include "rend3-routine/structures.wgsl"}}
+{{include "rend3-routine/fog.wgsl"}}
+
+struct VertexOutput {
+    @builtin(position) pos: vec4<f32>,
+    @location(0) clip_position: vec2<f32>,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) id: u32) -> VertexOutput {
+    let clip_position = vec2<f32>(f32(id / 2u) * 4.0 - 1.0, f32(id % 2u) * 4.0 - 1.0);
+
+    return VertexOutput(vec4<f32>(clip_position, 0.0, 1.0), clip_position);
+}
+
+@group(0) @binding(0)
+var primary_sampler: sampler;
+@group(0) @binding(3)
+var<uniform> uniforms: UniformData;
+
+{{#if fragment}}
+@fragment
+fn fs_main(output: VertexOutput) -> @location(0) vec4<f32> {
+    // See skybox.wgsl for why the near plane is used.
+    let clip = vec4<f32>(output.clip_position, 1.0, 1.0);
+    let world_undiv = uniforms.inv_origin_view_proj * clip;
+    let world = world_undiv.xyz / world_undiv.w;
+    let world_dir = normalize(world);
+
+    let color = skybox_color(world_dir);
+    return vec4<f32>(apply_skybox_fog(uniforms, color.rgb * uniforms.background_intensity), color.a);
+}
+{{/if}}
@@ -0,0 +1,46 @@
+{{include "rend3-routine/structures.wgsl"}}
+{{include "rend3-routine/fog.wgsl"}}
+
+const PI: f32 = 3.14159265358979323846;
+
+struct VertexOutput {
+    @builtin(position) pos: vec4<f32>,
+    @location(0) clip_position: vec2<f32>,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) id: u32) -> VertexOutput {
+    let clip_position = vec2<f32>(f32(id / 2u) * 4.0 - 1.0, f32(id % 2u) * 4.0 - 1.0);
+
+    return VertexOutput(vec4<f32>(clip_position, 0.0, 1.0), clip_position);
+}
+
+@group(0) @binding(0)
+var primary_sampler: sampler;
+@group(0) @binding(3)
+var<uniform> uniforms: UniformData;
+@group(1) @binding(0)
+var skybox: texture_2d<f32>;
+
+@fragment
+fn fs_main(output: VertexOutput) -> @location(0) vec4<f32> {
+    // See skybox.wgsl for why the near plane is used.
+    let clip = vec4<f32>(output.clip_position, 1.0, 1.0);
+    let world_undiv = uniforms.inv_origin_view_proj * clip;
+    let world = world_undiv.xyz / world_undiv.w;
+    let world_dir = normalize(world);
+
+    // Same mapping as the equirect to cubemap conversion, so both look identical.
+    var uv = vec2<f32>(
+        atan2(world_dir.z, world_dir.x) / (2.0 * PI) + 0.5,
+        acos(clamp(world_dir.y, -1.0, 1.0)) / PI,
+    );
+    // The primary sampler repeats, so keep the poles from blending with the opposite edge.
+    let half_texel = 0.5 / f32(textureDimensions(skybox).y);
+    uv.y = clamp(uv.y, half_texel, 1.0 - half_texel);
+
+    // Explicit lod as the derivatives are discontinuous across the seam.
+    let background = textureSampleLevel(skybox, primary_sampler, uv, 0.0).rgb * uniforms.background_intensity;
+
+    return vec4<f32>(apply_skybox_fog(uniforms, background), 1.0);
+}
@@ -0,0 +1,117 @@
+{{include "rend3-routine/structures.wgsl"}}
+{{include "rend3-routine/fog.wgsl"}}
+{{include "rend3-routine/math/consts.wgsl"}}
+
+struct VertexOutput {
+    @builtin(position) pos: vec4<f32>,
+    @location(0) clip_position: vec2<f32>,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) id: u32) -> VertexOutput {
+    let clip_position = vec2<f32>(f32(id / 2u) * 4.0 - 1.0, f32(id % 2u) * 4.0 - 1.0);
+
+    return VertexOutput(vec4<f32>(clip_position, 0.0, 1.0), clip_position);
+}
+
+struct ProceduralSky {
+    ground_albedo: vec3<f32>,
+    turbidity: f32,
+}
+
+@group(0) @binding(3)
+var<uniform> uniforms: UniformData;
+@group(0) @binding(4)
+var<storage> directional_lights: DirectionalLightData;
+@group(1) @binding(0)
+var<uniform> sky: ProceduralSky;
+
+// Perez et al. luminance distribution, for the coefficients of one channel.
+fn perez(cos_theta: f32, gamma: f32, cos_gamma: f32, a: f32, b: f32, c: f32, d: f32, e: f32) -> f32 {
+    return (1.0 + a * exp(b / cos_theta)) * (1.0 + c * exp(d * gamma) + e * cos_gamma * cos_gamma);
+}
+
+// Preetham et al. "A Practical Analytic Model for Daylight", in xyY with Y in kcd/m^2.
+fn preetham_xyy(cos_theta: f32, cos_gamma: f32, theta_s: f32, t: f32) -> vec3<f32> {
+    let gamma = acos(clamp(cos_gamma, -1.0, 1.0));
+    let cos_theta_s = cos(theta_s);
+
+    let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_s);
+    let zenith_y = (4.0453 * t - 4.9710) * tan(chi) - 0.2155 * t + 2.4192;
+
+    let ts = vec3<f32>(theta_s * theta_s * theta_s, theta_s * theta_s, theta_s);
+    let zenith_x = t * t * dot(vec3<f32>(0.00166, -0.00375, 0.00209), ts)
+        + t * (dot(vec3<f32>(-0.02903, 0.06377, -0.03202), ts) + 0.00394)
+        + dot(vec3<f32>(0.11693, -0.21196, 0.06052), ts) + 0.25886;
+    let zenith_yc = t * t * dot(vec3<f32>(0.00275, -0.00610, 0.00317), ts)
+        + t * (dot(vec3<f32>(-0.04214, 0.08970, -0.04153), ts) + 0.00516)
+        + dot(vec3<f32>(0.15346, -0.26756, 0.06670), ts) + 0.26688;
+
+    let y_a = 0.1787 * t - 1.4630;
+    let y_b = -0.3554 * t + 0.4275;
+    let y_c = -0.0227 * t + 5.3251;
+    let y_d = 0.1206 * t - 2.5771;
+    let y_e = -0.0670 * t + 0.3703;
+
+    let x_a = -0.0193 * t - 0.2592;
+    let x_b = -0.0665 * t + 0.0008;
+    let x_c = -0.0004 * t + 0.2125;
+    let x_d = -0.0641 * t - 0.8989;
+    let x_e = -0.0033 * t + 0.0452;
+
+    let yc_a = -0.0167 * t - 0.2608;
+    let yc_b = -0.0950 * t + 0.0092;
+    let yc_c = -0.0079 * t + 0.2102;
+    let yc_d = -0.0441 * t - 1.6537;
+    let yc_e = -0.0109 * t + 0.0529;
+
+    // Each channel is relative to its value at the zenith.
+    let lum = zenith_y * perez(cos_theta, gamma, cos_gamma, y_a, y_b, y_c, y_d, y_e)
+        / perez(1.0, theta_s, cos_theta_s, y_a, y_b, y_c, y_d, y_e);
+    let x = zenith_x * perez(cos_theta, gamma, cos_gamma, x_a, x_b, x_c, x_d, x_e)
+        / perez(1.0, theta_s, cos_theta_s, x_a, x_b, x_c, x_d, x_e);
+    let y = zenith_yc * perez(cos_theta, gamma, cos_gamma, yc_a, yc_b, yc_c, yc_d, yc_e)
+        / perez(1.0, theta_s, cos_theta_s, yc_a, yc_b, yc_c, yc_d, yc_e);
+
+    return vec3<f32>(x, y, lum);
+}
+
+fn xyy_to_linear_srgb(xyy: vec3<f32>) -> vec3<f32> {
+    let xyz = vec3<f32>(xyy.x / xyy.y * xyy.z, xyy.z, (1.0 - xyy.x - xyy.y) / xyy.y * xyy.z);
+    let rgb = mat3x3<f32>(
+        vec3<f32>(3.2406, -0.9689, 0.0557),
+        vec3<f32>(-1.5372, 1.8758, -0.2040),
+        vec3<f32>(-0.4986, 0.0415, 1.0570),
+    ) * xyz;
+    return max(rgb, vec3<f32>(0.0));
+}
+
+@fragment
+fn fs_main(output: VertexOutput) -> @location(0) vec4<f32> {
+    // See skybox.wgsl for why the near plane is used.
+    let clip = vec4<f32>(output.clip_position, 1.0, 1.0);
+    let world_undiv = uniforms.inv_origin_view_proj * clip;
+    let world = world_undiv.xyz / world_undiv.w;
+    let world_dir = normalize(world);
+
+    // The sun is towards the first directional light. Without one, it is at the zenith.
+    var sun_dir = vec3<f32>(0.0, 1.0, 0.0);
+    if directional_lights.count != 0u {
+        sun_dir = normalize(-directional_lights.data[0].direction);
+    }
+    // The model only covers daylight, so the sun is kept above the horizon.
+    let theta_s = min(acos(clamp(sun_dir.y, -1.0, 1.0)), PI_2 - 0.01);
+    let t = clamp(sky.turbidity, 1.7, 10.0);
+
+    // Below the horizon is the ground, lit by the sky at the horizon.
+    let cos_theta = max(world_dir.y, 0.01);
+    let sky_dir = normalize(vec3<f32>(world_dir.x, cos_theta, world_dir.z));
+    let xyy = preetham_xyy(cos_theta, dot(sky_dir, sun_dir), theta_s, t);
+
+    var background = xyy_to_linear_srgb(xyy);
+    if world_dir.y < 0.0 {
+        background *= sky.ground_albedo;
+    }
+
+    return vec4<f32>(apply_skybox_fog(uniforms, background * uniforms.background_intensity), 1.0);
+}
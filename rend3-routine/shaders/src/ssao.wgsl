@@ -0,0 +1,104 @@
+{{include "rend3-routine/structures.wgsl"}}
+{{include "rend3-routine/math/consts.wgsl"}}
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) id: u32) -> VertexOutput {
+    var output: VertexOutput;
+    output.position = vec4<f32>(f32(id / 2u) * 4.0 - 1.0, f32(id % 2u) * 4.0 - 1.0, 0.0, 1.0);
+    return output;
+}
+
+@group(0) @binding(3)
+var<uniform> uniforms: UniformData;
+@group(1) @binding(0)
+{{#if (eq SAMPLES 1)}}
+var depth_tex: texture_depth_2d;
+{{else}}
+var depth_tex: texture_depth_multisampled_2d;
+{{/if}}
+
+// Golden angle in radians, spreading consecutive samples evenly around the normal.
+const GOLDEN_ANGLE: f32 = 2.39996323;
+
+// Loads the depth at the given pixel. Multisampled depth is read from its first sample.
+fn load_depth(pixel: vec2<i32>) -> f32 {
+    let clamped = clamp(pixel, vec2<i32>(0), vec2<i32>(uniforms.resolution) - 1);
+    return textureLoad(depth_tex, clamped, 0);
+}
+
+// Reconstructs the view space position of the surface at the given pixel.
+fn view_position(pixel: vec2<i32>) -> vec3<f32> {
+    let uv = (vec2<f32>(pixel) + 0.5) / vec2<f32>(uniforms.resolution);
+    let ndc = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, load_depth(pixel), 1.0);
+    let world = uniforms.inv_view_proj * ndc;
+    return (uniforms.view * vec4<f32>(world.xyz / world.w, 1.0)).xyz;
+}
+
+// Picks the neighbor closest in depth, so normals don't bend across silhouettes.
+fn closest_delta(center: vec3<f32>, a: vec3<f32>, b: vec3<f32>) -> vec3<f32> {
+    let da = a - center;
+    let db = center - b;
+    return select(db, da, abs(da.z) < abs(db.z));
+}
+
+@fragment
+fn fs_main(vout: VertexOutput) -> @location(0) f32 {
+    let pixel = vec2<i32>(vout.position.xy);
+
+    // The far plane is at infinity, where there is no surface to occlude.
+    if (load_depth(pixel) <= 0.0) {
+        return 1.0;
+    }
+
+    let position = view_position(pixel);
+    let dx = closest_delta(position, view_position(pixel + vec2<i32>(1, 0)), view_position(pixel - vec2<i32>(1, 0)));
+    let dy = closest_delta(position, view_position(pixel + vec2<i32>(0, 1)), view_position(pixel - vec2<i32>(0, 1)));
+    var normal = normalize(cross(dx, dy));
+    // The camera is at the origin, and the normal has to face it.
+    if (dot(normal, position) > 0.0) {
+        normal = -normal;
+    }
+
+    let helper = select(vec3<f32>(1.0, 0.0, 0.0), vec3<f32>(0.0, 1.0, 0.0), abs(normal.x) > 0.9);
+    let tangent = normalize(helper - normal * dot(helper, normal));
+    let bitangent = cross(normal, tangent);
+
+    // Rotate the samples in a 4x4 pattern, which the blur pass averages out.
+    let rotation = f32((pixel.x & 3) + (pixel.y & 3) * 4) / 16.0 * 2.0 * PI;
+
+    let sample_count = max(uniforms.ssao_samples, 1u);
+    var occlusion = 0.0;
+    for (var i = 0u; i < sample_count; i += 1u) {
+        // Spiral over the hemisphere, with samples concentrated near the surface.
+        let fraction = (f32(i) + 0.5) / f32(sample_count);
+        let angle = f32(i) * GOLDEN_ANGLE + rotation;
+        let spread = sqrt(fraction);
+        let direction = (tangent * cos(angle) + bitangent * sin(angle)) * spread + normal * sqrt(1.0 - fraction);
+        let scale = mix(0.1, 1.0, fraction * fraction);
+        let sample_position = position + direction * uniforms.ssao_radius * scale;
+
+        let clip = uniforms.view_proj * uniforms.inv_view * vec4<f32>(sample_position, 1.0);
+        if (clip.w <= 0.0) {
+            continue;
+        }
+        let ndc = clip.xy / clip.w;
+        let uv = vec2<f32>(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5);
+        if (any(uv < vec2<f32>(0.0)) || any(uv > vec2<f32>(1.0))) {
+            continue;
+        }
+
+        // The surface and the sample lie on the same ray from the camera, so their distances compare.
+        let surface = view_position(vec2<i32>(uv * vec2<f32>(uniforms.resolution)));
+        let surface_distance = length(surface);
+        let occluded = select(0.0, 1.0, surface_distance < length(sample_position) - uniforms.ssao_bias);
+        // Surfaces far in front of the sample are unrelated geometry, and fade out.
+        let range = smoothstep(0.0, 1.0, uniforms.ssao_radius / abs(length(position) - surface_distance));
+        occlusion += occluded * range;
+    }
+
+    return 1.0 - occlusion / f32(sample_count);
+}
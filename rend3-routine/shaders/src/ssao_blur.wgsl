@@ -0,0 +1,28 @@
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) id: u32) -> VertexOutput {
+    var output: VertexOutput;
+    output.position = vec4<f32>(f32(id / 2u) * 4.0 - 1.0, f32(id % 2u) * 4.0 - 1.0, 0.0, 1.0);
+    return output;
+}
+
+@group(0) @binding(0)
+var source: texture_2d<f32>;
+
+// Averages the 4x4 block of texels the sample rotations of ssao.wgsl repeat over.
+@fragment
+fn fs_main(vout: VertexOutput) -> @location(0) f32 {
+    let pixel = vec2<i32>(vout.position.xy);
+    let max_pixel = vec2<i32>(textureDimensions(source)) - 1;
+
+    var sum = 0.0;
+    for (var y = -2; y < 2; y += 1) {
+        for (var x = -2; x < 2; x += 1) {
+            sum += textureLoad(source, clamp(pixel + vec2<i32>(x, y), vec2<i32>(0), max_pixel), 0).r;
+        }
+    }
+    return sum / 16.0;
+}
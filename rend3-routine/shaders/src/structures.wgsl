@@ -35,6 +35,41 @@ struct UniformData {
     frustum: Frustum,
     ambient: vec4<f32>,
     resolution: vec2<u32>,
+    time: f32,
+    /// Width of the square of texels averaged when sampling shadow maps.
+    shadow_filter_size: u32,
+    /// Non-zero when shadows are sampled from the blurred moments of variance shadow maps, see shadow/variance.wgsl.
+    variance_shadows: u32,
+    /// Cut off of the variance shadow bound, darkening light bleeding through overlapping occluders.
+    shadow_bleed_reduction: f32,
+    /// Multiplier applied to the color of the skybox.
+    background_intensity: f32,
+    /// Multiplier applied to the hdr color before tonemapping.
+    exposure: f32,
+    /// Operator mapping hdr colors to the output, see blit.wgsl.
+    tonemapping_operator: u32,
+    /// Brightness above which colors bloom, see bloom.wgsl.
+    bloom_threshold: f32,
+    /// Multiplier of the bloom added onto the hdr color.
+    bloom_intensity: f32,
+    /// Distance in world units ambient occlusion is searched in, see ssao.wgsl.
+    ssao_radius: f32,
+    /// Distance a surface has to be in front of a sample to occlude it.
+    ssao_bias: f32,
+    /// Amount of samples taken per pixel.
+    ssao_samples: u32,
+    /// Non-zero when objects are lit by the environment maps instead of the ambient color, see ibl.wgsl.
+    environment_lighting: u32,
+    /// Distance fog, see fog.wgsl. A fog_mode of 0 disables it.
+    fog_color: vec3<f32>,
+    fog_mode: u32,
+    fog_start: f32,
+    fog_end: f32,
+    fog_density: f32,
+    /// Fraction of the fog color blended over the skybox.
+    skybox_fog: f32,
+    /// Channel output instead of the shaded color, see DebugView. 0 shades normally.
+    debug_view: u32,
 }
 
 struct PerCameraUniformObjectData {
@@ -71,20 +106,33 @@ const PCU_FLAGS_MULTISAMPLE_MASK: u32 = 0x2u;
 const PCU_FLAGS_MULTISAMPLE_DISABLED: u32 = 0x0u;
 const PCU_FLAGS_MULTISAMPLE_ENABLED: u32 = 0x2u;
 
+// Culling disabled, every triangle is visible
+const PCU_FLAGS_CULLING_DISABLED: u32 = 0x4u;
+
 struct DirectionalLight {
-    /// View/Projection of directional light. Shadow rendering uses viewports
+    /// View/Projection of each cascade. Shadow rendering uses viewports
     /// so this always outputs [-1, 1] no matter where in the atlast the shadow is.
-    view_proj: mat4x4<f32>,
+    view_proj: array<mat4x4<f32>, 4>,
+    /// [0, 1] offset (xy) and size (zw) of each cascade in the atlas.
+    atlas_rects: array<vec4<f32>, 4>,
+    /// View space depth each cascade ends at.
+    cascade_ends: vec4<f32>,
     /// Color/intensity of the light
     color: vec3<f32>,
+    /// Amount of cascades in use.
+    cascade_count: u32,
     /// Direction of the light
     direction: vec3<f32>,
+    /// Fraction of each cascade which is blended with the next one.
+    cascade_blend: f32,
     /// 1 / resolution of whole shadow map
     inv_resolution: vec2<f32>,
-    /// [0, 1] offset of the shadow map in the atlas.
-    offset: vec2<f32>,
-    /// [0, 1] size of the shadow map in the atlas.
-    size: vec2<f32>,
+    /// Offset added to the depth of fragments before the shadow comparison.
+    shadow_bias: f32,
+    /// World space distance fragments are moved along their normal before sampling the shadow map.
+    shadow_normal_offset: f32,
+    /// Offset added to the bias, scaled by the tangent of the angle between the surface and the light.
+    shadow_slope_bias: f32,
 }
 
 struct DirectionalLightData {
@@ -99,6 +147,20 @@ struct PointLight {
     color: vec3<f32>,
     /// The radius of the light.
     radius: f32,
+    /// View/Projection of each face of the shadow cube, in +X, -X, +Y, -Y, +Z, -Z order.
+    shadow_view_proj: array<mat4x4<f32>, 6>,
+    /// [0, 1] offset (xy) and size (zw) of each face in the shadow atlas.
+    shadow_atlas_rects: array<vec4<f32>, 6>,
+    /// 1 / resolution of whole shadow map
+    inv_resolution: vec2<f32>,
+    /// Non-zero if the light casts shadows.
+    shadowed: u32,
+    /// Offset added to the depth of fragments before the shadow comparison.
+    shadow_bias: f32,
+    /// World space distance fragments are moved along their normal before sampling the shadow map.
+    shadow_normal_offset: f32,
+    /// Offset added to the bias, scaled by the tangent of the angle between the surface and the light.
+    shadow_slope_bias: f32,
 }
 
 struct PointLightData {
@@ -106,6 +168,40 @@ struct PointLightData {
     data: array<PointLight>,
 }
 
+struct SpotLight {
+    /// The position of the light in world space.
+    position: vec4<f32>,
+    /// The direction of the light in world space.
+    direction: vec3<f32>,
+    /// Distance at which the light is fully attenuated.
+    range: f32,
+    // Color/intensity of the light.
+    color: vec3<f32>,
+    /// Cosine of the inner cone angle.
+    cos_inner: f32,
+    /// View/Projection of the shadow camera.
+    shadow_view_proj: mat4x4<f32>,
+    /// [0, 1] offset (xy) and size (zw) of the shadow map in the atlas.
+    shadow_atlas_rect: vec4<f32>,
+    /// 1 / resolution of whole shadow map
+    inv_resolution: vec2<f32>,
+    /// Cosine of the outer cone angle.
+    cos_outer: f32,
+    /// Non-zero if the light casts shadows.
+    shadowed: u32,
+    /// Offset added to the depth of fragments before the shadow comparison.
+    shadow_bias: f32,
+    /// World space distance fragments are moved along their normal before sampling the shadow map.
+    shadow_normal_offset: f32,
+    /// Offset added to the bias, scaled by the tangent of the angle between the surface and the light.
+    shadow_slope_bias: f32,
+}
+
+struct SpotLightData {
+    count: u32,
+    data: array<SpotLight>,
+}
+
 struct PixelData {
     albedo: vec4<f32>,
     diffuse_color: vec3<f32>,
@@ -1,5 +1,13 @@
 {{include "rend3-routine/math/sphere.wgsl"}}
 
+// See MaterialOverride.
+struct MaterialOverride {
+    color: vec4<f32>,
+    uv_offset: vec2<f32>,
+    uv_scale: vec2<f32>,
+    emissive_scale: f32,
+}
+
 struct Object {
     transform: mat4x4<f32>,
     bounding_sphere: Sphere,
@@ -9,8 +17,14 @@ struct Object {
     vertex_attribute_start_offsets: array<u32, {{vertex_array_counts}}>,
     // 1 if enabled, 0 if disabled
     enabled: u32,
+    // Uses OBJECT_FLAGS_* constants
+    flags: u32,
+    material_override: MaterialOverride,
 }
 
+const OBJECT_FLAGS_RECEIVE_FOG: u32 = 0x1u;
+const OBJECT_FLAGS_RECEIVE_AMBIENT: u32 = 0x2u;
+
 struct ObjectCullingInformation {
     invocation_start: u32,
     invocation_end: u32,
@@ -20,6 +34,8 @@ struct ObjectCullingInformation {
     local_region_id: u32,
     previous_global_invocation: u32,
     atomic_capable: u32,
+    // 1 if back faces are kept, 0 if they are culled
+    double_sided: u32,
 }
 
 struct BatchData {
@@ -0,0 +1,18 @@
+{{include "rend3-routine/shadow/atlas_rect.wgsl"}}
+
+@group(0) @binding(1)
+var source: texture_2d<f32>;
+
+// Blurs the moments vertically, within the shadow map of the fragment.
+@fragment
+fn fs_main(vout: VertexOutput) -> @location(0) vec2<f32> {
+    let pixel = vec2<i32>(vout.position.xy);
+
+    var weights = array<f32, 5>(1.0, 4.0, 6.0, 4.0, 1.0);
+    var moments = vec2<f32>(0.0);
+    for (var i = 0; i < 5; i += 1) {
+        let y = clamp(pixel.y + i - 2, vout.rect.y, vout.rect.w - 1);
+        moments += textureLoad(source, vec2<i32>(pixel.x, y), 0).xy * weights[i];
+    }
+    return moments / 16.0;
+}
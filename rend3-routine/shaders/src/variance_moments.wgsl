@@ -0,0 +1,19 @@
+{{include "rend3-routine/shadow/atlas_rect.wgsl"}}
+
+@group(0) @binding(1)
+var shadows: texture_depth_2d;
+
+// Blurs the depth and squared depth horizontally, within the shadow map of the fragment.
+@fragment
+fn fs_main(vout: VertexOutput) -> @location(0) vec2<f32> {
+    let pixel = vec2<i32>(vout.position.xy);
+
+    var weights = array<f32, 5>(1.0, 4.0, 6.0, 4.0, 1.0);
+    var moments = vec2<f32>(0.0);
+    for (var i = 0; i < 5; i += 1) {
+        let x = clamp(pixel.x + i - 2, vout.rect.x, vout.rect.z - 1);
+        let depth = textureLoad(shadows, vec2<i32>(x, pixel.y), 0);
+        moments += vec2<f32>(depth, depth * depth) * weights[i];
+    }
+    return moments / 16.0;
+}
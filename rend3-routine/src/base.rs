@@ -23,33 +23,35 @@ use rend3::{
         RenderTargetHandle, ViewportRect,
     },
     types::{SampleCount, TextureFormat, TextureUsages},
+    util::{buffer_pool::BufferPool, render_target::RenderTarget},
     Renderer, ShaderPreProcessor, INTERNAL_SHADOW_DEPTH_FORMAT,
 };
-use wgpu::{BindGroup, Buffer};
+use wgpu::{BindGroup, Buffer, BufferUsages};
 
 use crate::{
-    clear,
+    bloom, clear,
     common::{self, CameraSpecifier},
-    culling,
+    culling, decal, depth_readback,
     forward::{self, ForwardRoutineArgs},
-    pbr, skinning, uniforms,
+    fxaa, ibl, pbr, picking, post_process, skinning, ssao, uniforms, variance_shadows,
 };
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct DepthTargets {
     pub single_sample_mipped: RenderTargetHandle,
     pub multi_sample: Option<RenderTargetHandle>,
+    pub format: TextureFormat,
 }
 
 impl DepthTargets {
-    pub fn new(graph: &mut RenderGraph<'_>, resolution: UVec2, samples: SampleCount) -> Self {
+    pub fn new(graph: &mut RenderGraph<'_>, resolution: UVec2, samples: SampleCount, format: TextureFormat) -> Self {
         let single_sample_mipped = graph.add_render_target(RenderTargetDescriptor {
             label: Some("hdr depth".into()),
             resolution,
             depth: 1,
             mip_levels: None,
             samples: SampleCount::One,
-            format: TextureFormat::Depth32Float,
+            format,
             usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
         });
 
@@ -60,17 +62,27 @@ impl DepthTargets {
                 depth: 1,
                 mip_levels: Some(1),
                 samples,
-                format: TextureFormat::Depth32Float,
+                format,
                 usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
             })
         });
 
-        Self { single_sample_mipped, multi_sample }
+        Self { single_sample_mipped, multi_sample, format }
     }
 
     pub fn rendering_target(&self) -> RenderTargetHandle {
         self.multi_sample.unwrap_or(self.single_sample_mipped.set_mips(0..1))
     }
+
+    /// Depth target of a renderpass rendering to [`Self::rendering_target`],
+    /// clearing the stencil to 0 if the format has one.
+    pub fn renderpass_target(&self) -> graph::RenderPassDepthTarget {
+        graph::RenderPassDepthTarget {
+            target: self.rendering_target(),
+            depth_clear: Some(0.0),
+            stencil_clear: self.format.has_stencil_aspect().then_some(0),
+        }
+    }
 }
 
 pub struct OutputRenderTarget {
@@ -79,6 +91,13 @@ pub struct OutputRenderTarget {
     pub samples: SampleCount,
 }
 
+impl OutputRenderTarget {
+    /// Renders into an offscreen render target, at its size and sample count.
+    pub fn offscreen<'node>(graph: &mut RenderGraph<'node>, target: &'node RenderTarget) -> Self {
+        Self { handle: target.add_to_graph(graph), resolution: target.size(), samples: target.samples() }
+    }
+}
+
 pub struct BaseRenderGraphRoutines<'node> {
     pub pbr: &'node crate::pbr::PbrRoutine,
     pub skybox: Option<&'node crate::skybox::SkyboxRoutine>,
@@ -91,10 +110,100 @@ pub struct BaseRenderGraphInputs<'a, 'node> {
     pub target: OutputRenderTarget,
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug)]
 pub struct BaseRenderGraphSettings {
+    /// Ambient light, replaced by image based lighting while the skybox has a
+    /// background cubemap, see
+    /// [`SkyboxRoutine::set_background_texture`](crate::skybox::SkyboxRoutine::set_background_texture).
     pub ambient_color: Vec4,
+    /// Multiplier applied to the rgb of `ambient_color`, so the tint and the
+    /// brightness of the ambient light can be set separately. 1.0 leaves the
+    /// color unchanged.
+    pub ambient_intensity: f32,
+    /// Color of the background wherever the skybox doesn't draw one: when
+    /// there is no skybox routine or it has no background, see
+    /// [`SkyboxRoutine::has_background`](crate::skybox::SkyboxRoutine::has_background).
+    /// A skybox background covers it entirely. Unlike the skybox, it is not
+    /// scaled by `background_intensity`.
     pub clear_color: Vec4,
+    /// Time in seconds, available to shaders as `uniforms.time`. Lets
+    /// custom skybox shaders animate.
+    pub time: f32,
+    /// Filtering used when sampling shadow maps.
+    pub shadow_filter: uniforms::ShadowFilter,
+    /// Multiplier applied to the color of the skybox, balancing the
+    /// brightness of the environment against the lights in the scene.
+    /// 1.0 leaves the skybox unchanged.
+    pub background_intensity: f32,
+    /// Multiplier applied to the hdr image before it is tonemapped. 1.0 leaves
+    /// the image unchanged.
+    pub exposure: f32,
+    /// Operator mapping the hdr image to the output.
+    pub tonemapping_operator: crate::tonemapping::TonemappingOperator,
+    /// Bloom added to the hdr image before it is tonemapped. `None` disables
+    /// bloom.
+    pub bloom: Option<crate::bloom::BloomSettings>,
+    /// Anti-aliasing applied to the tonemapped image, in addition to any
+    /// multisampling.
+    pub anti_alias: crate::fxaa::AntiAlias,
+    /// Render the depth of all opaque and cutout objects before shading them,
+    /// so every pixel is only shaded once. This pays off when fragment shading
+    /// is expensive and objects overlap a lot.
+    pub depth_prepass: bool,
+    /// Distance fog applied to every object, and optionally the skybox.
+    /// `None` disables fog.
+    pub fog: Option<uniforms::Fog>,
+    /// Screen space ambient occlusion darkening the ambient light. `None`
+    /// disables it. Enables the depth prepass, which it is computed from.
+    pub ssao: Option<crate::ssao::SsaoSettings>,
+    /// Render only the edges of triangles, in a solid color. Needs
+    /// [`Features::POLYGON_MODE_LINE`](wgpu::Features::POLYGON_MODE_LINE), see
+    /// [`Renderer::validate_wireframe`]. Disables the depth prepass and
+    /// ambient occlusion.
+    pub wireframe: bool,
+    /// Channel shown instead of the shaded image. Views other than
+    /// [`DebugView::None`](uniforms::DebugView::None) skip bloom and
+    /// ambient occlusion.
+    pub debug_view: uniforms::DebugView,
+    /// Draw every object, skipping frustum culling on the cpu and triangle
+    /// culling on the gpu. Comparing the output with culling enabled helps
+    /// find incorrect bounding spheres, and
+    /// [`FrameStatistics::objects_submitted`](rend3::util::stats::FrameStatistics::objects_submitted)
+    /// shows how many more objects are drawn.
+    pub disable_culling: bool,
+    /// Pixel whose opaque or cutout object is picked this frame, read it back
+    /// with [`ObjectPicker::read`](picking::ObjectPicker::read) once the
+    /// frame has been submitted. `None` skips rendering the object ids.
+    pub pick: Option<UVec2>,
+    /// Read back the depth of all opaque and cutout objects this frame, read
+    /// it with [`DepthReader::read`](depth_readback::DepthReader::read) once
+    /// the frame has been submitted.
+    pub read_depth: bool,
+}
+
+impl Default for BaseRenderGraphSettings {
+    fn default() -> Self {
+        Self {
+            ambient_color: Vec4::ZERO,
+            ambient_intensity: 1.0,
+            clear_color: Vec4::ZERO,
+            time: 0.0,
+            shadow_filter: Default::default(),
+            background_intensity: 1.0,
+            exposure: 1.0,
+            tonemapping_operator: Default::default(),
+            bloom: None,
+            anti_alias: Default::default(),
+            depth_prepass: false,
+            fog: None,
+            ssao: None,
+            wireframe: false,
+            debug_view: Default::default(),
+            disable_culling: false,
+            pick: None,
+            read_depth: false,
+        }
+    }
 }
 
 /// Starter RenderGraph.
@@ -105,22 +214,86 @@ pub struct BaseRenderGraph {
     pub samplers: common::Samplers,
     pub gpu_culler: culling::GpuCuller,
     pub gpu_skinner: skinning::GpuSkinner,
+    pub bloom: bloom::BloomRoutine,
+    pub picker: picking::ObjectPicker,
+    pub depth_reader: depth_readback::DepthReader,
+    pub ssao: ssao::SsaoRoutine,
+    pub variance_shadows: variance_shadows::VarianceShadowRoutine,
+    pub decals: decal::DecalRoutine,
+    /// Custom passes run on the tonemapped image, before anti-aliasing.
+    pub post_process: post_process::PostProcessChain,
+    /// Frame uniform buffers, recycled across frames.
+    pub uniform_buffers: BufferPool,
+    /// Environment maps bound when there is no skybox routine.
+    pub empty_environment: ibl::EnvironmentMaps,
 }
 
 impl BaseRenderGraph {
     pub fn new(renderer: &Arc<Renderer>, spp: &ShaderPreProcessor) -> Self {
+        Self::with_depth_format(renderer, spp, common::DEPTH_FORMAT)
+    }
+
+    /// Renders the viewport with a combined depth-stencil buffer, which is
+    /// cleared to 0 every frame. Routines can write and test the stencil to
+    /// mask parts of the frame, like drawing outlines around selected objects.
+    ///
+    /// The routines rendering to the viewport must be created with the
+    /// [`interfaces`](Self::interfaces) of this graph.
+    pub fn with_stencil(renderer: &Arc<Renderer>, spp: &ShaderPreProcessor) -> Self {
+        Self::with_depth_format(renderer, spp, common::depth_stencil_format(renderer.features))
+    }
+
+    fn with_depth_format(renderer: &Arc<Renderer>, spp: &ShaderPreProcessor, depth_format: TextureFormat) -> Self {
         profiling::scope!("DefaultRenderGraphData::new");
 
-        let interfaces = common::WholeFrameInterfaces::new(&renderer.device);
+        let interfaces = common::WholeFrameInterfaces::new(&renderer.device, depth_format);
 
-        let samplers = common::Samplers::new(&renderer.device);
+        let samplers = common::Samplers::new(renderer, 1);
 
         // TODO: Support more materials
         let gpu_culler = culling::GpuCuller::new::<pbr::PbrMaterial>(renderer, spp);
 
         let gpu_skinner = skinning::GpuSkinner::new(&renderer.device, spp);
 
-        Self { interfaces, samplers, gpu_culler, gpu_skinner }
+        let bloom = bloom::BloomRoutine::new(&renderer.device, spp, &interfaces);
+
+        let picker = picking::ObjectPicker::new(&renderer.device, spp);
+
+        let depth_reader = depth_readback::DepthReader::new(&renderer.device, spp);
+
+        let ssao = ssao::SsaoRoutine::new(&renderer.device, spp, &interfaces);
+
+        let variance_shadows = variance_shadows::VarianceShadowRoutine::new(&renderer.device, spp);
+
+        let decals = decal::DecalRoutine::new(&renderer.device, spp, &interfaces);
+
+        let uniform_buffers = BufferPool::new("Frame Uniforms", BufferUsages::UNIFORM);
+
+        let empty_environment = ibl::EnvironmentMaps::empty(&renderer.device);
+
+        Self {
+            interfaces,
+            samplers,
+            gpu_culler,
+            gpu_skinner,
+            bloom,
+            picker,
+            depth_reader,
+            ssao,
+            variance_shadows,
+            decals,
+            post_process: post_process::PostProcessChain::new(),
+            uniform_buffers,
+            empty_environment,
+        }
+    }
+
+    /// Recreates the samplers, filtering material textures with the given
+    /// anisotropy level. Anisotropic filtering is disabled by default.
+    ///
+    /// See [`Samplers::new`](common::Samplers::new) for the supported levels.
+    pub fn set_anisotropy(&mut self, renderer: &Renderer, anisotropy: u16) {
+        self.samplers = common::Samplers::new(renderer, anisotropy);
     }
 
     /// Add this to the rendergraph. This is the function you should start
@@ -133,11 +306,14 @@ impl BaseRenderGraph {
         settings: BaseRenderGraphSettings,
     ) {
         // Create the data and handles for the graph.
-        let mut state = BaseRenderGraphIntermediateState::new(graph, inputs, settings);
+        let mut state = BaseRenderGraphIntermediateState::new(self, graph, inputs, settings);
 
         // Clear the shadow buffers. This, as an explicit node, must be done as a limitation of the graph dependency system.
         state.clear_shadow_buffers();
 
+        // Clear the ambient occlusion to unoccluded, if ambient occlusion is disabled.
+        state.clear_ambient_occlusion();
+
         // Prepare all the uniforms that all shaders need access to.
         state.create_frame_uniforms(self);
 
@@ -152,12 +328,24 @@ impl BaseRenderGraph {
         // Render all the shadows to the shadow map.
         state.pbr_shadow_rendering();
 
+        // Blur the shadow maps into moments, if variance shadow maps are enabled.
+        state.variance_shadows(self);
+
         // Upload the uniforms for the objects in the forward pass.
         state.object_uniform_upload(self);
 
+        // Render the depth of the predicted triangles, if the depth prepass is enabled.
+        state.pbr_depth_prepass_predicted_triangles();
+
+        // Compute the ambient occlusion of the predicted triangles, if enabled.
+        state.ssao(self);
+
         // Do the first pass, rendering the predicted triangles from last frame.
         state.pbr_render_opaque_predicted_triangles();
 
+        // Render the object ids of the predicted triangles, if picking.
+        state.pbr_object_ids_predicted_triangles();
+
         // Create the hi-z buffer.
         state.hi_z();
 
@@ -168,9 +356,29 @@ impl BaseRenderGraph {
         // but were not predicted last frame.
         state.pbr_culling(self);
 
+        // Render the depth of the residual triangles, if the depth prepass is enabled.
+        state.pbr_depth_prepass_residual_triangles();
+
+        // Compute the ambient occlusion again, now including the residual triangles.
+        state.ssao(self);
+
         // Do the second pass, rendering the residual triangles.
         state.pbr_render_opaque_residual_triangles();
 
+        // Render the object ids of the residual triangles and read back the
+        // picked one, if picking.
+        state.pbr_object_ids_residual_triangles();
+        state.pick_object(self);
+
+        // Read back the depth, if enabled in the settings.
+        state.read_depth(self);
+
+        // Render all decals on top of the opaque objects.
+        state.pbr_forward_rendering_decals();
+
+        // Project the decals onto the opaque objects.
+        state.projected_decals(self);
+
         // Render the skybox.
         state.skybox();
 
@@ -180,8 +388,15 @@ impl BaseRenderGraph {
         // considered "residual".
         state.pbr_forward_rendering_transparent();
 
-        // Tonemap the HDR inner buffer to the output buffer.
-        state.tonemapping();
+        // Add bloom to the HDR buffer, if enabled.
+        state.bloom(self);
+
+        // Tonemap the HDR inner buffer to the output buffer, running the
+        // post processing passes on the way.
+        state.tonemapping(self);
+
+        // Render the extra viewports on top of the output.
+        state.extra_viewports(self);
     }
 }
 
@@ -202,14 +417,22 @@ pub struct BaseRenderGraphIntermediateState<'a, 'node> {
     pub forward_uniform_bg: DataHandle<BindGroup>,
 
     pub shadow: RenderTargetHandle,
+    /// Screen space ambient occlusion, a single white pixel when disabled.
+    pub ambient_occlusion: RenderTargetHandle,
+    /// Blurred moments of the shadow maps, a single pixel when variance
+    /// shadow maps are disabled.
+    pub shadow_moments: RenderTargetHandle,
     pub depth: DepthTargets,
     pub primary_renderpass: RenderPassTargets,
+    /// Object id and depth targets, only present when picking.
+    pub object_id_renderpass: Option<RenderPassTargets>,
 
     pub pre_skinning_buffers: DataHandle<skinning::PreSkinningBuffers>,
 }
 impl<'a, 'node> BaseRenderGraphIntermediateState<'a, 'node> {
     /// Create the default setting for all state.
     pub fn new(
+        base: &'node BaseRenderGraph,
         graph: &'a mut RenderGraph<'node>,
         inputs: BaseRenderGraphInputs<'a, 'node>,
         settings: BaseRenderGraphSettings,
@@ -232,6 +455,32 @@ impl<'a, 'node> BaseRenderGraphIntermediateState<'a, 'node> {
             usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
         });
 
+        let shadow_moments = graph.add_render_target(RenderTargetDescriptor {
+            label: Some("shadow moments".into()),
+            resolution: match settings.shadow_filter {
+                uniforms::ShadowFilter::Variance { .. } => inputs.eval_output.shadow_target_size,
+                _ => UVec2::ONE,
+            },
+            depth: 1,
+            mip_levels: Some(1),
+            samples: SampleCount::One,
+            format: variance_shadows::SHADOW_MOMENTS_FORMAT,
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+        });
+
+        let ambient_occlusion = graph.add_render_target(RenderTargetDescriptor {
+            label: Some("ambient occlusion".into()),
+            resolution: match ssao_settings(&settings) {
+                Some(_) => inputs.target.resolution,
+                None => UVec2::ONE,
+            },
+            depth: 1,
+            mip_levels: Some(1),
+            samples: SampleCount::One,
+            format: ssao::AMBIENT_OCCLUSION_FORMAT,
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+        });
+
         // Make the actual render targets we want to render to.
         let color = graph.add_render_target(RenderTargetDescriptor {
             label: Some("hdr color".into()),
@@ -253,16 +502,43 @@ impl<'a, 'node> BaseRenderGraphIntermediateState<'a, 'node> {
                 usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
             })
         });
-        let depth = DepthTargets::new(graph, inputs.target.resolution, inputs.target.samples);
+        let depth =
+            DepthTargets::new(graph, inputs.target.resolution, inputs.target.samples, base.interfaces.depth_format);
         let primary_renderpass = graph::RenderPassTargets {
             targets: vec![graph::RenderPassTarget { color, resolve, clear: settings.clear_color }],
-            depth_stencil: Some(graph::RenderPassDepthTarget {
-                target: depth.rendering_target(),
-                depth_clear: Some(0.0),
-                stencil_clear: None,
-            }),
+            depth_stencil: Some(depth.renderpass_target()),
         };
 
+        // The ids can't be resolved, so they get their own single sampled depth buffer.
+        let object_id_renderpass = settings.pick.map(|_| {
+            let ids = graph.add_render_target(RenderTargetDescriptor {
+                label: Some("object ids".into()),
+                resolution: inputs.target.resolution,
+                depth: 1,
+                mip_levels: Some(1),
+                samples: SampleCount::One,
+                format: TextureFormat::R32Uint,
+                usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+            });
+            let ids_depth = graph.add_render_target(RenderTargetDescriptor {
+                label: Some("object id depth".into()),
+                resolution: inputs.target.resolution,
+                depth: 1,
+                mip_levels: Some(1),
+                samples: SampleCount::One,
+                format: common::DEPTH_FORMAT,
+                usage: TextureUsages::RENDER_ATTACHMENT,
+            });
+            graph::RenderPassTargets {
+                targets: vec![graph::RenderPassTarget { color: ids, resolve: None, clear: Vec4::ZERO }],
+                depth_stencil: Some(graph::RenderPassDepthTarget {
+                    target: ids_depth,
+                    depth_clear: Some(0.0),
+                    stencil_clear: None,
+                }),
+            }
+        });
+
         let pre_skinning_buffers = graph.add_data::<skinning::PreSkinningBuffers>();
 
         let pre_cull = graph.add_data();
@@ -282,8 +558,11 @@ impl<'a, 'node> BaseRenderGraphIntermediateState<'a, 'node> {
             forward_uniform_bg,
 
             shadow,
+            ambient_occlusion,
+            shadow_moments,
             depth,
             primary_renderpass,
+            object_id_renderpass,
 
             pre_skinning_buffers,
         }
@@ -292,6 +571,36 @@ impl<'a, 'node> BaseRenderGraphIntermediateState<'a, 'node> {
     /// Clear the shadow buffers. This, as an explicit node, must be done as a limitation of the graph dependency system.
     fn clear_shadow_buffers(&mut self) {
         clear::add_depth_clear_to_graph(self.graph, self.shadow, 0.0);
+        if !self.variance_shadows_enabled() {
+            clear::add_clear_to_graph(self.graph, self.shadow_moments, Vec4::ZERO);
+        }
+    }
+
+    /// Variance shadow maps are only blurred when there are shadows to blur.
+    fn variance_shadows_enabled(&self) -> bool {
+        self.settings.shadow_filter.bleed_reduction().is_some() && !self.inputs.eval_output.shadows.is_empty()
+    }
+
+    /// Blur the shadow maps into the moments of variance shadow maps, if
+    /// enabled in the settings.
+    pub fn variance_shadows(&mut self, base: &'node BaseRenderGraph) {
+        if !self.variance_shadows_enabled() {
+            return;
+        }
+        base.variance_shadows.add_to_graph(
+            self.graph,
+            self.shadow,
+            self.shadow_moments,
+            self.inputs.eval_output.shadow_target_size,
+            &self.inputs.eval_output.shadows,
+        );
+    }
+
+    /// Clear the ambient occlusion, if it is disabled and never rendered.
+    fn clear_ambient_occlusion(&mut self) {
+        if ssao_settings(&self.settings).is_none() {
+            clear::add_clear_to_graph(self.graph, self.ambient_occlusion, Vec4::ONE);
+        }
     }
 
     /// Create all the uniforms all the shaders in this graph need.
@@ -299,19 +608,47 @@ impl<'a, 'node> BaseRenderGraphIntermediateState<'a, 'node> {
         uniforms::add_to_graph(
             self.graph,
             self.shadow,
+            self.ambient_occlusion,
+            self.shadow_moments,
             uniforms::UniformBindingHandles {
                 interfaces: &base.interfaces,
+                buffer_pool: &base.uniform_buffers,
                 shadow_uniform_bg: self.shadow_uniform_bg,
                 forward_uniform_bg: self.forward_uniform_bg,
             },
-            uniforms::UniformInformation {
-                samplers: &base.samplers,
-                ambient: self.settings.ambient_color,
-                resolution: self.inputs.target.resolution,
-            },
+            self.uniform_information(base, CameraSpecifier::Viewport, self.inputs.target.resolution),
         );
     }
 
+    fn uniform_information(
+        &self,
+        base: &'node BaseRenderGraph,
+        camera: CameraSpecifier,
+        resolution: UVec2,
+    ) -> uniforms::UniformInformation<'node> {
+        uniforms::UniformInformation {
+            samplers: &base.samplers,
+            camera,
+            ambient: (self.settings.ambient_color.truncate() * self.settings.ambient_intensity)
+                .extend(self.settings.ambient_color.w),
+            resolution,
+            time: self.settings.time,
+            shadow_filter: self.settings.shadow_filter,
+            background_intensity: self.settings.background_intensity,
+            exposure: self.settings.exposure,
+            tonemapping_operator: self.settings.tonemapping_operator,
+            bloom: self.settings.bloom,
+            ssao: ssao_settings(&self.settings),
+            environment: self
+                .inputs
+                .routines
+                .skybox
+                .map_or(&base.empty_environment, |skybox| skybox.environment_maps()),
+            fog: self.settings.fog,
+            debug_view: self.settings.debug_view,
+        }
+    }
+
     pub fn shadow_object_uniform_upload(&mut self, base: &'node BaseRenderGraph) {
         for (shadow_index, shadow) in self.inputs.eval_output.shadows.iter().enumerate() {
             base.gpu_culler.add_object_uniform_upload_to_graph::<pbr::PbrMaterial>(
@@ -319,7 +656,8 @@ impl<'a, 'node> BaseRenderGraphIntermediateState<'a, 'node> {
                 CameraSpecifier::Shadow(shadow_index as u32),
                 UVec2::splat(shadow.map.size),
                 SampleCount::One,
-                &format_sso!("Shadow Culling S{}", shadow_index),
+                self.settings.disable_culling,
+                &format_sso!("Shadow Uniform Upload S{}", shadow_index),
             );
         }
     }
@@ -332,6 +670,7 @@ impl<'a, 'node> BaseRenderGraphIntermediateState<'a, 'node> {
                 shadow_culled,
                 self.shadow,
                 CameraSpecifier::Shadow(shadow_index as u32),
+                self.settings.disable_culling,
                 &format_sso!("Shadow Culling S{}", shadow_index),
             );
         }
@@ -347,6 +686,7 @@ impl<'a, 'node> BaseRenderGraphIntermediateState<'a, 'node> {
             CameraSpecifier::Viewport,
             self.inputs.target.resolution,
             self.inputs.target.samples,
+            self.settings.disable_culling,
             "Uniform Bake",
         );
     }
@@ -358,6 +698,7 @@ impl<'a, 'node> BaseRenderGraphIntermediateState<'a, 'node> {
             self.cull,
             self.depth.single_sample_mipped,
             CameraSpecifier::Viewport,
+            self.settings.disable_culling,
             "Primary Culling",
         );
     }
@@ -376,7 +717,9 @@ impl<'a, 'node> BaseRenderGraphIntermediateState<'a, 'node> {
                 }),
             };
 
-            let routines = [&self.inputs.routines.pbr.opaque_depth, &self.inputs.routines.pbr.cutout_depth];
+            let pbr = self.inputs.routines.pbr;
+            let routines =
+                [&pbr.opaque_depth, &pbr.cutout_depth, &pbr.double_sided_opaque_depth, &pbr.double_sided_cutout_depth];
             for routine in routines {
                 routine.add_forward_to_graph(ForwardRoutineArgs {
                     graph: self.graph,
@@ -390,6 +733,9 @@ impl<'a, 'node> BaseRenderGraphIntermediateState<'a, 'node> {
                     culling_source: forward::CullingSource::Residual(*shadow_cull),
                     samples: SampleCount::One,
                     renderpass: renderpass.clone(),
+                    wireframe: false,
+                    overdraw: false,
+                    stencil_reference: 0,
                 });
             }
         }
@@ -407,10 +753,66 @@ impl<'a, 'node> BaseRenderGraphIntermediateState<'a, 'node> {
         }
     }
 
+    /// Render the depth of the predicted triangles, if enabled in the settings.
+    pub fn pbr_depth_prepass_predicted_triangles(&mut self) {
+        self.pbr_depth_prepass("PBR Depth Prepass 1", forward::CullingSource::Predicted);
+    }
+
+    /// Render the depth of the residual triangles, if enabled in the settings.
+    pub fn pbr_depth_prepass_residual_triangles(&mut self) {
+        self.pbr_depth_prepass("PBR Depth Prepass 2", forward::CullingSource::Residual(self.cull));
+    }
+
+    /// The depth prepass is skipped for wireframes, as lines can't be depth
+    /// tested against the filled triangles of the prepass, and when counting
+    /// overdraw, which it would hide.
+    fn depth_prepass_enabled(&self) -> bool {
+        (self.settings.depth_prepass || self.settings.ssao.is_some())
+            && !self.settings.wireframe
+            && self.settings.debug_view != uniforms::DebugView::Overdraw
+    }
+
+    fn pbr_depth_prepass(&mut self, label: &str, culling_source: forward::CullingSource) {
+        if !self.depth_prepass_enabled() {
+            return;
+        }
+
+        let pbr = self.inputs.routines.pbr;
+        let routines = [
+            &pbr.opaque_prepass,
+            &pbr.cutout_prepass,
+            &pbr.double_sided_opaque_prepass,
+            &pbr.double_sided_cutout_prepass,
+        ];
+        for routine in routines {
+            routine.add_forward_to_graph(ForwardRoutineArgs {
+                graph: self.graph,
+                label,
+                camera: CameraSpecifier::Viewport,
+                binding_data: forward::ForwardRoutineBindingData {
+                    whole_frame_uniform_bg: self.shadow_uniform_bg,
+                    per_material_bgl: &pbr.per_material,
+                    extra_bgs: None,
+                },
+                culling_source,
+                samples: self.inputs.target.samples,
+                renderpass: self.primary_renderpass.clone(),
+                wireframe: false,
+                overdraw: false,
+                stencil_reference: 0,
+            });
+        }
+    }
+
+    /// Opaque and cutout routines of the forward passes, which only shade
+    /// visible fragments if the depth prepass ran.
+    fn pbr_opaque_routines(&self) -> Vec<&'node forward::ForwardRoutine<pbr::PbrMaterial>> {
+        self.inputs.routines.pbr.opaque_routines(self.depth_prepass_enabled())
+    }
+
     /// Render the PBR materials.
     pub fn pbr_render_opaque_predicted_triangles(&mut self) {
-        let routines = [&self.inputs.routines.pbr.opaque_routine, &self.inputs.routines.pbr.cutout_routine];
-        for routine in routines {
+        for routine in self.pbr_opaque_routines() {
             routine.add_forward_to_graph(ForwardRoutineArgs {
                 graph: self.graph,
                 label: "PBR Forward Pass 1",
@@ -423,14 +825,16 @@ impl<'a, 'node> BaseRenderGraphIntermediateState<'a, 'node> {
                 culling_source: forward::CullingSource::Predicted,
                 samples: self.inputs.target.samples,
                 renderpass: self.primary_renderpass.clone(),
+                wireframe: self.settings.wireframe,
+                overdraw: self.settings.debug_view == uniforms::DebugView::Overdraw,
+                stencil_reference: 0,
             });
         }
     }
 
     /// Render the PBR materials.
     pub fn pbr_render_opaque_residual_triangles(&mut self) {
-        let routines = [&self.inputs.routines.pbr.opaque_routine, &self.inputs.routines.pbr.cutout_routine];
-        for routine in routines {
+        for routine in self.pbr_opaque_routines() {
             routine.add_forward_to_graph(ForwardRoutineArgs {
                 graph: self.graph,
                 label: "PBR Forward Pass 2",
@@ -443,38 +847,401 @@ impl<'a, 'node> BaseRenderGraphIntermediateState<'a, 'node> {
                 culling_source: forward::CullingSource::Residual(self.cull),
                 samples: self.inputs.target.samples,
                 renderpass: self.primary_renderpass.clone(),
+                wireframe: self.settings.wireframe,
+                overdraw: self.settings.debug_view == uniforms::DebugView::Overdraw,
+                stencil_reference: 0,
             });
         }
     }
 
+    /// Render the PBR decals.
+    pub fn pbr_forward_rendering_decals(&mut self) {
+        let pbr = self.inputs.routines.pbr;
+        for routine in pbr.decal_routines() {
+            routine.add_forward_to_graph(ForwardRoutineArgs {
+                graph: self.graph,
+                label: "PBR Forward Decals",
+                camera: CameraSpecifier::Viewport,
+                binding_data: forward::ForwardRoutineBindingData {
+                    whole_frame_uniform_bg: self.forward_uniform_bg,
+                    per_material_bgl: &pbr.per_material,
+                    extra_bgs: None,
+                },
+                culling_source: forward::CullingSource::Residual(self.cull),
+                samples: self.inputs.target.samples,
+                renderpass: self.primary_renderpass.clone(),
+                wireframe: self.settings.wireframe,
+                overdraw: self.settings.debug_view == uniforms::DebugView::Overdraw,
+                stencil_reference: 0,
+            });
+        }
+    }
+
+    /// Blend the decals added to the renderer onto the opaque objects. They
+    /// are skipped in wireframes and debug views, which they would obscure.
+    pub fn projected_decals(&mut self, base: &'node BaseRenderGraph) {
+        if self.settings.wireframe || self.settings.debug_view != uniforms::DebugView::None {
+            return;
+        }
+
+        let target = &self.primary_renderpass.targets[0];
+        base.decals.add_to_graph(
+            self.graph,
+            target.color,
+            target.resolve,
+            self.depth.rendering_target(),
+            self.inputs.target.samples,
+            self.shadow_uniform_bg,
+        );
+    }
+
     /// Render the PBR materials.
     pub fn pbr_forward_rendering_transparent(&mut self) {
-        self.inputs.routines.pbr.blend_routine.add_forward_to_graph(ForwardRoutineArgs {
-            graph: self.graph,
-            label: "PBR Forward Transparent",
-            camera: CameraSpecifier::Viewport,
-            binding_data: forward::ForwardRoutineBindingData {
-                whole_frame_uniform_bg: self.forward_uniform_bg,
-                per_material_bgl: &self.inputs.routines.pbr.per_material,
-                extra_bgs: None,
-            },
-            culling_source: forward::CullingSource::Residual(self.cull),
-            samples: self.inputs.target.samples,
-            renderpass: self.primary_renderpass.clone(),
-        });
+        let pbr = self.inputs.routines.pbr;
+        for routine in pbr.blend_routines() {
+            routine.add_forward_to_graph(ForwardRoutineArgs {
+                graph: self.graph,
+                label: "PBR Forward Transparent",
+                camera: CameraSpecifier::Viewport,
+                binding_data: forward::ForwardRoutineBindingData {
+                    whole_frame_uniform_bg: self.forward_uniform_bg,
+                    per_material_bgl: &pbr.per_material,
+                    extra_bgs: None,
+                },
+                culling_source: forward::CullingSource::Residual(self.cull),
+                samples: self.inputs.target.samples,
+                renderpass: self.primary_renderpass.clone(),
+                wireframe: self.settings.wireframe,
+                overdraw: self.settings.debug_view == uniforms::DebugView::Overdraw,
+                stencil_reference: 0,
+            });
+        }
+    }
+
+    /// Render the object ids of the predicted triangles, if picking.
+    pub fn pbr_object_ids_predicted_triangles(&mut self) {
+        self.pbr_object_ids("PBR Object Ids 1", forward::CullingSource::Predicted);
+    }
+
+    /// Render the object ids of the residual triangles, if picking.
+    pub fn pbr_object_ids_residual_triangles(&mut self) {
+        self.pbr_object_ids("PBR Object Ids 2", forward::CullingSource::Residual(self.cull));
+    }
+
+    fn pbr_object_ids(&mut self, label: &str, culling_source: forward::CullingSource) {
+        let Some(renderpass) = &self.object_id_renderpass else {
+            return;
+        };
+
+        let pbr = self.inputs.routines.pbr;
+        let routines = [
+            &pbr.opaque_object_id,
+            &pbr.cutout_object_id,
+            &pbr.double_sided_opaque_object_id,
+            &pbr.double_sided_cutout_object_id,
+        ];
+        for routine in routines {
+            routine.add_forward_to_graph(ForwardRoutineArgs {
+                graph: self.graph,
+                label,
+                camera: CameraSpecifier::Viewport,
+                binding_data: forward::ForwardRoutineBindingData {
+                    whole_frame_uniform_bg: self.shadow_uniform_bg,
+                    per_material_bgl: &pbr.per_material,
+                    extra_bgs: None,
+                },
+                culling_source,
+                samples: SampleCount::One,
+                renderpass: renderpass.clone(),
+                wireframe: false,
+                overdraw: false,
+                stencil_reference: 0,
+            });
+        }
+    }
+
+    /// Read back the object at the picked pixel, if picking.
+    pub fn pick_object(&mut self, base: &'node BaseRenderGraph) {
+        let (Some(pixel), Some(renderpass)) = (self.settings.pick, &self.object_id_renderpass) else {
+            return;
+        };
+
+        let pixel = pixel.min(self.inputs.target.resolution.saturating_sub(UVec2::ONE));
+        base.picker.add_to_graph(self.graph, renderpass.targets[0].color, pixel);
+    }
+
+    /// Read back the depth rendered so far, if enabled in the settings.
+    pub fn read_depth(&mut self, base: &'node BaseRenderGraph) {
+        if !self.settings.read_depth {
+            return;
+        }
+
+        base.depth_reader.add_to_graph(
+            self.graph,
+            self.depth.rendering_target(),
+            self.inputs.target.samples,
+            self.inputs.target.resolution,
+        );
+    }
+
+    /// Compute the ambient occlusion from the depth rendered so far, if
+    /// enabled in the settings.
+    pub fn ssao(&mut self, base: &'node BaseRenderGraph) {
+        if ssao_settings(&self.settings).is_none() {
+            return;
+        }
+
+        base.ssao.add_to_graph(
+            self.graph,
+            self.depth.rendering_target(),
+            self.inputs.target.samples,
+            self.ambient_occlusion,
+            self.inputs.target.resolution,
+            self.shadow_uniform_bg,
+        );
     }
 
     pub fn hi_z(&mut self) {
         self.inputs.routines.pbr.hi_z.add_hi_z_to_graph(self.graph, self.depth, self.inputs.target.resolution);
     }
 
-    /// Tonemap onto the given render target.
-    pub fn tonemapping(&mut self) {
-        self.inputs.routines.tonemapping.add_to_graph(
+    /// Add bloom to the hdr image, if enabled in the settings.
+    pub fn bloom(&mut self, base: &'node BaseRenderGraph) {
+        if self.settings.debug_view != uniforms::DebugView::None {
+            return;
+        }
+        if let Some(settings) = self.settings.bloom {
+            base.bloom.add_to_graph(
+                self.graph,
+                self.primary_renderpass.resolved_color(0),
+                self.inputs.target.resolution,
+                self.forward_uniform_bg,
+                settings,
+            );
+        }
+    }
+
+    /// Tonemap onto the output, running the post processing passes and
+    /// anti-aliasing the result if enabled in the settings.
+    pub fn tonemapping(&mut self, base: &'node BaseRenderGraph) {
+        let tonemapping = self.inputs.routines.tonemapping;
+        let fxaa = self.settings.anti_alias == fxaa::AntiAlias::Fxaa;
+
+        // Every step writes an ldr intermediate, except for the last which writes the output.
+        let post_process_output =
+            if fxaa { self.ldr_target("ldr color anti-aliasing source") } else { self.inputs.target.handle };
+        let tonemapping_output =
+            if base.post_process.is_empty() { post_process_output } else { self.ldr_target("ldr color") };
+
+        tonemapping.add_to_graph(
             self.graph,
             self.primary_renderpass.resolved_color(0),
-            self.inputs.target.handle,
+            tonemapping_output,
+            self.forward_uniform_bg,
+        );
+        base.post_process.add_to_graph(
+            self.graph,
+            tonemapping_output,
+            post_process_output,
+            self.inputs.target.resolution,
+            tonemapping.output_format(),
             self.forward_uniform_bg,
         );
+        if fxaa {
+            tonemapping.fxaa().add_to_graph(
+                self.graph,
+                post_process_output,
+                self.inputs.target.handle,
+                self.forward_uniform_bg,
+            );
+        }
+    }
+
+    fn ldr_target(&mut self, label: &'static str) -> RenderTargetHandle {
+        self.graph.add_render_target(RenderTargetDescriptor {
+            label: Some(label.into()),
+            resolution: self.inputs.target.resolution,
+            depth: 1,
+            mip_levels: Some(1),
+            samples: SampleCount::One,
+            format: self.inputs.routines.tonemapping.output_format(),
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+        })
+    }
+
+    /// Render the cameras set with [`Renderer::set_viewports`] into their
+    /// rectangles of the output, on top of what was rendered before.
+    ///
+    /// Every viewport gets its own targets, uniforms and culling, the shadow
+    /// maps are shared. The depth prepass, ambient occlusion, picking,
+    /// projected decals, bloom, post processing and anti-aliasing only apply to
+    /// the viewport camera.
+    pub fn extra_viewports(&mut self, base: &'node BaseRenderGraph) {
+        let viewports = &self.inputs.eval_output.viewports;
+        if viewports.is_empty() {
+            return;
+        }
+
+        let samples = self.inputs.target.samples;
+        let output_size = self.inputs.target.resolution;
+        let pbr = self.inputs.routines.pbr;
+
+        let ambient_occlusion = match ssao_settings(&self.settings) {
+            None => self.ambient_occlusion,
+            Some(_) => {
+                let unoccluded = self.graph.add_render_target(RenderTargetDescriptor {
+                    label: Some("viewport ambient occlusion".into()),
+                    resolution: UVec2::ONE,
+                    depth: 1,
+                    mip_levels: Some(1),
+                    samples: SampleCount::One,
+                    format: ssao::AMBIENT_OCCLUSION_FORMAT,
+                    usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+                });
+                clear::add_clear_to_graph(self.graph, unoccluded, Vec4::ONE);
+                unoccluded
+            }
+        };
+
+        for (index, viewport) in viewports.iter().enumerate() {
+            let camera = CameraSpecifier::Extra(index as u32);
+
+            // Clip the rectangle to the output, skipping it if nothing is left.
+            let offset = viewport.rect.offset.min(output_size);
+            let size = viewport.rect.offset.saturating_add(viewport.rect.size).min(output_size) - offset;
+            if size.cmpeq(UVec2::ZERO).any() {
+                continue;
+            }
+
+            let color = self.graph.add_render_target(RenderTargetDescriptor {
+                label: Some(format_sso!("viewport {index} hdr color")),
+                resolution: size,
+                depth: 1,
+                samples,
+                mip_levels: Some(1),
+                format: TextureFormat::Rgba16Float,
+                usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+            });
+            let resolve = samples.needs_resolve().then(|| {
+                self.graph.add_render_target(RenderTargetDescriptor {
+                    label: Some(format_sso!("viewport {index} hdr resolve")),
+                    resolution: size,
+                    depth: 1,
+                    mip_levels: Some(1),
+                    samples: SampleCount::One,
+                    format: TextureFormat::Rgba16Float,
+                    usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+                })
+            });
+            let depth = DepthTargets::new(self.graph, size, samples, base.interfaces.depth_format);
+            let renderpass = graph::RenderPassTargets {
+                targets: vec![graph::RenderPassTarget { color, resolve, clear: self.settings.clear_color }],
+                depth_stencil: Some(depth.renderpass_target()),
+            };
+
+            let shadow_uniform_bg = self.graph.add_data::<BindGroup>();
+            let forward_uniform_bg = self.graph.add_data::<BindGroup>();
+            uniforms::add_to_graph(
+                self.graph,
+                self.shadow,
+                ambient_occlusion,
+                self.shadow_moments,
+                uniforms::UniformBindingHandles {
+                    interfaces: &base.interfaces,
+                    buffer_pool: &base.uniform_buffers,
+                    shadow_uniform_bg,
+                    forward_uniform_bg,
+                },
+                self.uniform_information(base, camera, size),
+            );
+
+            base.gpu_culler.add_object_uniform_upload_to_graph::<pbr::PbrMaterial>(
+                self.graph,
+                camera,
+                size,
+                samples,
+                self.settings.disable_culling,
+                &format_sso!("Uniform Bake V{index}"),
+            );
+
+            let opaque = pbr.opaque_routines(false);
+            let forward = |state: &mut Self,
+                           routines: &[&'node forward::ForwardRoutine<pbr::PbrMaterial>],
+                           label: &str,
+                           culling_source| {
+                for routine in routines {
+                    routine.add_forward_to_graph(ForwardRoutineArgs {
+                        graph: state.graph,
+                        label,
+                        camera,
+                        binding_data: forward::ForwardRoutineBindingData {
+                            whole_frame_uniform_bg: forward_uniform_bg,
+                            per_material_bgl: &pbr.per_material,
+                            extra_bgs: None,
+                        },
+                        culling_source,
+                        samples,
+                        renderpass: renderpass.clone(),
+                        wireframe: state.settings.wireframe,
+                        overdraw: state.settings.debug_view == uniforms::DebugView::Overdraw,
+                        stencil_reference: 0,
+                    });
+                }
+            };
+
+            forward(self, &opaque, &format!("PBR Forward Pass 1 V{index}"), forward::CullingSource::Predicted);
+
+            pbr.hi_z.add_hi_z_to_graph(self.graph, depth, size);
+            let cull = self.graph.add_data();
+            base.gpu_culler.add_culling_to_graph::<pbr::PbrMaterial>(
+                self.graph,
+                cull,
+                depth.single_sample_mipped,
+                camera,
+                self.settings.disable_culling,
+                &format_sso!("Viewport Culling V{index}"),
+            );
+            let residual = forward::CullingSource::Residual(cull);
+
+            forward(self, &opaque, &format!("PBR Forward Pass 2 V{index}"), residual);
+            let decals = pbr.decal_routines();
+            forward(self, &decals, &format!("PBR Forward Decals V{index}"), residual);
+            if let Some(skybox) = self.inputs.routines.skybox {
+                skybox.add_to_graph(self.graph, renderpass.clone(), forward_uniform_bg, samples);
+            }
+            let transparent = pbr.blend_routines();
+            forward(self, &transparent, &format!("PBR Forward Transparent V{index}"), residual);
+
+            let mut output = self.inputs.target.handle.set_viewport(ViewportRect::new(offset, size));
+            if let Some(scissor) = viewport.scissor {
+                output = output.set_scissor(scissor);
+            }
+            self.inputs.routines.tonemapping.add_to_graph(
+                self.graph,
+                renderpass.resolved_color(0),
+                output,
+                forward_uniform_bg,
+            );
+        }
+    }
+}
+
+/// Ambient occlusion is computed from the depth prepass, so it is disabled
+/// along with it for wireframes. Debug views don't show it either.
+fn ssao_settings(settings: &BaseRenderGraphSettings) -> Option<ssao::SsaoSettings> {
+    settings.ssao.filter(|_| !settings.wireframe && settings.debug_view == uniforms::DebugView::None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BaseRenderGraphSettings;
+
+    #[test]
+    fn default_multipliers() {
+        // Multipliers default to leaving the image unchanged, not to black.
+        let settings = BaseRenderGraphSettings::default();
+        assert_eq!(settings.exposure, 1.0);
+        assert_eq!(settings.background_intensity, 1.0);
+        assert_eq!(settings.ambient_intensity, 1.0);
     }
 }
@@ -0,0 +1,275 @@
+//! Bloom, spreading the light of bright parts of the hdr image onto their
+//! surroundings.
+//!
+//! Colors brighter than [`BloomSettings::threshold`] are extracted into a half
+//! resolution image, which is blurred by downsampling it through a mip chain
+//! and upsampling it back up again. The result is added onto the hdr image
+//! before it is tonemapped.
+//!
+//! The mip chain is a render target of the graph, so it always matches the
+//! resolution of the output.
+
+use std::borrow::Cow;
+
+use glam::{UVec2, Vec4};
+use rend3::{
+    graph::{
+        DataHandle, DeclaredDependency, NodeExecutionContext, NodeResourceUsage, RenderGraph, RenderPassHandle,
+        RenderPassTarget, RenderPassTargets, RenderTargetDescriptor, RenderTargetHandle, ViewportRect,
+    },
+    types::{SampleCount, TextureFormat, TextureUsages},
+    util::bind_merge::{BindGroupBuilder, BindGroupLayoutBuilder},
+    ShaderConfig, ShaderPreProcessor,
+};
+use wgpu::{
+    BindGroup, BindGroupLayout, BindingType, BlendComponent, BlendFactor, BlendOperation, BlendState, ColorTargetState,
+    ColorWrites, Device, Extent3d, FragmentState, MultisampleState, PipelineLayoutDescriptor, PrimitiveState,
+    RenderPipeline, RenderPipelineDescriptor, ShaderModule, ShaderModuleDescriptor, ShaderSource, ShaderStages,
+    TextureDimension, TextureSampleType, TextureViewDimension, VertexState,
+};
+
+use crate::common::WholeFrameInterfaces;
+
+/// Settings of the bloom added to the hdr image.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct BloomSettings {
+    /// Brightness above which colors start to bloom. Only the part of the
+    /// color above the threshold is spread.
+    pub threshold: f32,
+    /// Multiplier of the blurred bright colors added onto the image.
+    pub intensity: f32,
+    /// How far the bloom spreads, as a fraction of the smaller side of the
+    /// image.
+    pub radius: f32,
+}
+
+impl Default for BloomSettings {
+    fn default() -> Self {
+        Self { threshold: 1.0, intensity: 0.1, radius: 0.05 }
+    }
+}
+
+/// Amount of mips in the bloom chain for the given output resolution.
+///
+/// The first mip has half the resolution of the output, and every mip
+/// doubles how far the bloom spreads.
+fn bloom_mip_count(resolution: UVec2, radius: f32) -> u8 {
+    let size = (resolution / 2).max(UVec2::ONE);
+    let max_mips = Extent3d { width: size.x, height: size.y, depth_or_array_layers: 1 }.max_mips(TextureDimension::D2);
+
+    // Mip `i` blurs over roughly 2^(i + 1) pixels of the output.
+    let pixels = radius * resolution.min_element() as f32;
+    let wanted = pixels.max(1.0).log2().ceil() as u32;
+    wanted.saturating_sub(1).clamp(1, max_mips) as u8
+}
+
+fn create_pipeline(
+    device: &Device,
+    module: &ShaderModule,
+    interfaces: &WholeFrameInterfaces,
+    bgl: &BindGroupLayout,
+    entry_point: &str,
+    blend: Option<BlendState>,
+) -> RenderPipeline {
+    let pll = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+        label: Some("bloom pass"),
+        bind_group_layouts: &[&interfaces.forward_uniform_bgl, bgl],
+        push_constant_ranges: &[],
+    });
+
+    device.create_render_pipeline(&RenderPipelineDescriptor {
+        label: Some(entry_point),
+        layout: Some(&pll),
+        vertex: VertexState { module, entry_point: "vs_main", buffers: &[] },
+        primitive: PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: MultisampleState::default(),
+        fragment: Some(FragmentState {
+            module,
+            entry_point,
+            targets: &[Some(ColorTargetState {
+                format: TextureFormat::Rgba16Float,
+                blend,
+                write_mask: ColorWrites::all(),
+            })],
+        }),
+        multiview: None,
+    })
+}
+
+/// Routine rendering bloom.
+///
+/// See module for documentation.
+pub struct BloomRoutine {
+    bgl: BindGroupLayout,
+    prefilter_pipeline: RenderPipeline,
+    downsample_pipeline: RenderPipeline,
+    upsample_pipeline: RenderPipeline,
+    composite_pipeline: RenderPipeline,
+}
+
+impl BloomRoutine {
+    pub fn new(device: &Device, spp: &ShaderPreProcessor, interfaces: &WholeFrameInterfaces) -> Self {
+        profiling::scope!("BloomRoutine::new");
+
+        let bgl = BindGroupLayoutBuilder::new()
+            .append(
+                ShaderStages::FRAGMENT,
+                BindingType::Texture {
+                    sample_type: TextureSampleType::Float { filterable: true },
+                    view_dimension: TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                None,
+            )
+            .build(device, Some("bloom bgl"));
+
+        let module = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("bloom"),
+            source: ShaderSource::Wgsl(Cow::Owned(
+                spp.render_shader("rend3-routine/bloom.wgsl", &ShaderConfig::default(), None).unwrap(),
+            )),
+        });
+
+        let additive = BlendComponent {
+            src_factor: BlendFactor::One,
+            dst_factor: BlendFactor::One,
+            operation: BlendOperation::Add,
+        };
+        // The composite must not change the alpha of the hdr image.
+        let keep = BlendComponent {
+            src_factor: BlendFactor::Zero,
+            dst_factor: BlendFactor::One,
+            operation: BlendOperation::Add,
+        };
+
+        let prefilter_pipeline = create_pipeline(device, &module, interfaces, &bgl, "fs_prefilter", None);
+        let downsample_pipeline = create_pipeline(device, &module, interfaces, &bgl, "fs_downsample", None);
+        let upsample_pipeline = create_pipeline(
+            device,
+            &module,
+            interfaces,
+            &bgl,
+            "fs_upsample",
+            Some(BlendState { color: additive, alpha: additive }),
+        );
+        let composite_pipeline = create_pipeline(
+            device,
+            &module,
+            interfaces,
+            &bgl,
+            "fs_composite",
+            Some(BlendState { color: additive, alpha: keep }),
+        );
+
+        Self { bgl, prefilter_pipeline, downsample_pipeline, upsample_pipeline, composite_pipeline }
+    }
+
+    /// Adds bloom onto `hdr_color`, which has the given resolution.
+    pub fn add_to_graph<'node>(
+        &'node self,
+        graph: &mut RenderGraph<'node>,
+        hdr_color: RenderTargetHandle,
+        resolution: UVec2,
+        forward_uniform_bg: DataHandle<BindGroup>,
+        settings: BloomSettings,
+    ) {
+        let size = (resolution / 2).max(UVec2::ONE);
+        let mips = bloom_mip_count(resolution, settings.radius);
+
+        let chain = graph.add_render_target(RenderTargetDescriptor {
+            label: Some("bloom chain".into()),
+            resolution: size,
+            depth: 1,
+            mip_levels: Some(mips),
+            samples: SampleCount::One,
+            format: TextureFormat::Rgba16Float,
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+        });
+        let mip = |mip: u8| {
+            let mip_size = (size >> mip as u32).max(UVec2::ONE);
+            chain.set_mips(mip..mip + 1).set_viewport(ViewportRect::from_size(mip_size))
+        };
+
+        self.add_pass(graph, "Bloom Prefilter", &self.prefilter_pipeline, hdr_color, mip(0), forward_uniform_bg);
+        for dst_mip in 1..mips {
+            let label = format!("Bloom Downsample {} -> {dst_mip}", dst_mip - 1);
+            self.add_pass(graph, &label, &self.downsample_pipeline, mip(dst_mip - 1), mip(dst_mip), forward_uniform_bg);
+        }
+        for dst_mip in (0..mips - 1).rev() {
+            let label = format!("Bloom Upsample {} -> {dst_mip}", dst_mip + 1);
+            self.add_pass(graph, &label, &self.upsample_pipeline, mip(dst_mip + 1), mip(dst_mip), forward_uniform_bg);
+        }
+        self.add_pass(graph, "Bloom Composite", &self.composite_pipeline, mip(0), hdr_color, forward_uniform_bg);
+    }
+
+    /// Adds a fullscreen pass sampling `src` and rendering to `dst`.
+    fn add_pass<'node>(
+        &'node self,
+        graph: &mut RenderGraph<'node>,
+        label: &str,
+        pipeline: &'node RenderPipeline,
+        src: RenderTargetHandle,
+        dst: RenderTargetHandle,
+        forward_uniform_bg: DataHandle<BindGroup>,
+    ) {
+        let mut builder = graph.add_node(label);
+
+        let src_handle = builder.add_render_target(src, NodeResourceUsage::Input);
+        let rpass_handle = builder.add_renderpass(
+            RenderPassTargets {
+                targets: vec![RenderPassTarget { color: dst, clear: Vec4::ZERO, resolve: None }],
+                depth_stencil: None,
+            },
+            NodeResourceUsage::InputOutput,
+        );
+        let forward_uniform_handle = builder.add_data(forward_uniform_bg, NodeResourceUsage::Input);
+
+        builder.build(move |ctx| {
+            self.draw(ctx, pipeline, rpass_handle, src_handle, forward_uniform_handle);
+        });
+    }
+
+    fn draw<'pass>(
+        &'pass self,
+        mut ctx: NodeExecutionContext<'_, 'pass, '_>,
+        pipeline: &'pass RenderPipeline,
+        rpass_handle: DeclaredDependency<RenderPassHandle>,
+        src_handle: DeclaredDependency<RenderTargetHandle>,
+        forward_uniform_handle: DeclaredDependency<DataHandle<BindGroup>>,
+    ) {
+        let rpass = ctx.encoder_or_pass.take_rpass(rpass_handle);
+        let forward_uniform_bg = ctx.graph_data.get_data(ctx.temps, forward_uniform_handle).unwrap();
+        let src = ctx.graph_data.get_render_target(src_handle);
+
+        let bg = ctx.temps.add(BindGroupBuilder::new().append_texture_view(src).build(
+            &ctx.renderer.device,
+            Some("bloom src bg"),
+            &self.bgl,
+        ));
+
+        rpass.set_pipeline(pipeline);
+        rpass.set_bind_group(0, forward_uniform_bg, &[]);
+        rpass.set_bind_group(1, bg, &[]);
+        rpass.draw(0..3, 0..1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::UVec2;
+
+    use super::bloom_mip_count;
+
+    #[test]
+    fn mip_count() {
+        // 5% of 1080 pixels is 54 pixels, which the first 5 mips cover.
+        assert_eq!(bloom_mip_count(UVec2::new(1920, 1080), 0.05), 5);
+        assert_eq!(bloom_mip_count(UVec2::new(1920, 1080), 0.1), 6);
+        // There is always at least one mip.
+        assert_eq!(bloom_mip_count(UVec2::new(1920, 1080), 0.0), 1);
+        // The chain is limited by the resolution.
+        assert_eq!(bloom_mip_count(UVec2::new(16, 16), 100.0), 4);
+        assert_eq!(bloom_mip_count(UVec2::new(1, 1), 1.0), 1);
+    }
+}
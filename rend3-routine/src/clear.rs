@@ -1,4 +1,7 @@
-use rend3::graph::{NodeResourceUsage, RenderGraph, RenderPassDepthTarget, RenderPassTargets, RenderTargetHandle};
+use glam::Vec4;
+use rend3::graph::{
+    NodeResourceUsage, RenderGraph, RenderPassDepthTarget, RenderPassTarget, RenderPassTargets, RenderTargetHandle,
+};
 
 /// Due to limitations of how we auto-clear buffers, we need to explicitly clear the shadow depth buffer.
 pub fn add_depth_clear_to_graph(graph: &mut RenderGraph<'_>, depth: RenderTargetHandle, depth_clear: f32) {
@@ -18,3 +21,15 @@ pub fn add_depth_clear_to_graph(graph: &mut RenderGraph<'_>, depth: RenderTarget
 
     builder.build(|_| ())
 }
+
+/// Clears a color target which is only referenced by bind groups, and never rendered to otherwise.
+pub fn add_clear_to_graph(graph: &mut RenderGraph<'_>, color: RenderTargetHandle, clear: Vec4) {
+    let mut builder = graph.add_node("Clear Color");
+
+    let _rpass_handle = builder.add_renderpass(
+        RenderPassTargets { targets: vec![RenderPassTarget { color, clear, resolve: None }], depth_stencil: None },
+        NodeResourceUsage::Output,
+    );
+
+    builder.build(|_| ())
+}
@@ -1,8 +1,13 @@
+use rend3::{graph::InstructionEvaluationOutput, managers::CameraState, RendererDataCore};
+
 /// Specifier representing which camera we're referring to.
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum CameraSpecifier {
     Viewport,
     Shadow(u32),
+    /// Camera of one of the extra viewports, indexing
+    /// [`InstructionEvaluationOutput::viewports`].
+    Extra(u32),
 }
 
 impl CameraSpecifier {
@@ -14,6 +19,14 @@ impl CameraSpecifier {
         matches!(self, Self::Viewport)
     }
 
+    /// Returns `true` if the camera specifier is [`Extra`].
+    ///
+    /// [`Extra`]: CameraSpecifier::Extra
+    #[must_use]
+    pub fn is_extra(&self) -> bool {
+        matches!(self, Self::Extra(..))
+    }
+
     /// Returns `true` if the camera specifier is [`Shadow`].
     ///
     /// [`Shadow`]: CameraSpecifier::Shadow
@@ -22,15 +35,28 @@ impl CameraSpecifier {
         matches!(self, Self::Shadow(..))
     }
 
-    /// Returns a shader compatible index for the camera, using u32::MAX for the viewport camera.
+    /// Returns a shader compatible index for the camera, using u32::MAX for the viewport cameras.
     #[must_use]
     pub fn to_shader_index(&self) -> u32 {
         match *self {
-            Self::Viewport => u32::MAX,
+            Self::Viewport | Self::Extra(_) => u32::MAX,
             Self::Shadow(index) => {
                 assert_ne!(index, u32::MAX, "Shadow camera index cannot be 0xFFFF_FFFF");
                 index
             }
         }
     }
+
+    /// Returns the state of the camera this specifier refers to.
+    pub fn state<'a>(
+        &self,
+        data_core: &'a RendererDataCore,
+        eval_output: &'a InstructionEvaluationOutput,
+    ) -> &'a CameraState {
+        match *self {
+            Self::Viewport => &data_core.viewport_camera_state,
+            Self::Shadow(index) => &eval_output.shadows[index as usize].camera,
+            Self::Extra(index) => &eval_output.viewports[index as usize].camera,
+        }
+    }
 }
@@ -2,15 +2,31 @@ use std::{marker::PhantomData, mem, num::NonZeroU64};
 
 use glam::{Mat4, Vec3};
 use rend3::{
-    managers::{DirectionalLightManager, PointLightManager},
+    managers::{DirectionalLightManager, PointLightManager, SpotLightManager},
     types::Material,
     util::bind_merge::BindGroupLayoutBuilder,
 };
 use wgpu::{
-    BindGroupLayout, BindingType, BufferBindingType, Device, ShaderStages, TextureSampleType, TextureViewDimension,
+    BindGroupLayout, BindingType, BufferBindingType, Device, Features, ShaderStages, TextureFormat, TextureSampleType,
+    TextureViewDimension,
 };
 
-use crate::{common::samplers::Samplers, uniforms::FrameUniforms};
+use crate::{common::samplers::Samplers, ibl::EnvironmentMaps, uniforms::FrameUniforms};
+
+/// Format of the depth buffer of the viewport, unless it has a stencil.
+pub const DEPTH_FORMAT: TextureFormat = TextureFormat::Depth32Float;
+
+/// Combined depth-stencil format for the viewport:
+/// [`TextureFormat::Depth32FloatStencil8`] if the device has
+/// [`Features::DEPTH32FLOAT_STENCIL8`], [`TextureFormat::Depth24PlusStencil8`]
+/// otherwise.
+pub fn depth_stencil_format(features: Features) -> TextureFormat {
+    if features.contains(Features::DEPTH32FLOAT_STENCIL8) {
+        TextureFormat::Depth32FloatStencil8
+    } else {
+        TextureFormat::Depth24PlusStencil8
+    }
+}
 
 /// Interfaces which are used throughout the whole frame.
 ///
@@ -22,10 +38,14 @@ pub struct WholeFrameInterfaces {
     pub depth_uniform_bgl: BindGroupLayout,
     /// Includes everything.
     pub forward_uniform_bgl: BindGroupLayout,
+    /// Format of the depth buffer of the viewport, which all pipelines
+    /// rendering to it must use. Either [`DEPTH_FORMAT`] or a
+    /// [`depth_stencil_format`].
+    pub depth_format: TextureFormat,
 }
 
 impl WholeFrameInterfaces {
-    pub fn new(device: &Device) -> Self {
+    pub fn new(device: &Device, depth_format: TextureFormat) -> Self {
         profiling::scope!("ShaderInterfaces::new");
 
         let mut uniform_bglb = BindGroupLayoutBuilder::new();
@@ -44,6 +64,8 @@ impl WholeFrameInterfaces {
 
         DirectionalLightManager::add_to_bgl(&mut uniform_bglb);
         PointLightManager::add_to_bgl(&mut uniform_bglb);
+        SpotLightManager::add_to_bgl(&mut uniform_bglb);
+        Samplers::add_wrap_to_bgl(&mut uniform_bglb);
 
         let shadow_uniform_bgl = uniform_bglb.build(device, Some("shadow uniform bgl"));
 
@@ -58,9 +80,34 @@ impl WholeFrameInterfaces {
             None,
         );
 
+        // Screen space ambient occlusion
+        uniform_bglb.append(
+            ShaderStages::FRAGMENT,
+            BindingType::Texture {
+                sample_type: TextureSampleType::Float { filterable: true },
+                view_dimension: TextureViewDimension::D2,
+                multisampled: false,
+            },
+            None,
+        );
+
+        // Image based lighting
+        EnvironmentMaps::add_to_bgl(&mut uniform_bglb);
+
+        // Blurred moments of the shadow maps, for variance shadow maps
+        uniform_bglb.append(
+            ShaderStages::FRAGMENT,
+            BindingType::Texture {
+                sample_type: TextureSampleType::Float { filterable: false },
+                view_dimension: TextureViewDimension::D2,
+                multisampled: false,
+            },
+            None,
+        );
+
         let forward_uniform_bgl = uniform_bglb.build(device, Some("forward uniform bgl"));
 
-        Self { depth_uniform_bgl: shadow_uniform_bgl, forward_uniform_bgl }
+        Self { depth_uniform_bgl: shadow_uniform_bgl, forward_uniform_bgl, depth_format }
     }
 }
 
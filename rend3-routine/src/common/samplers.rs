@@ -1,29 +1,74 @@
-use rend3::util::bind_merge::{BindGroupBuilder, BindGroupLayoutBuilder};
+use rend3::{
+    util::bind_merge::{BindGroupBuilder, BindGroupLayoutBuilder},
+    Renderer,
+};
 use wgpu::{
-    AddressMode, BindingType, CompareFunction, Device, FilterMode, Sampler, SamplerBindingType, SamplerDescriptor,
-    ShaderStages,
+    AddressMode, BindingType, CompareFunction, DownlevelFlags, FilterMode, Sampler, SamplerBindingType,
+    SamplerDescriptor, ShaderStages,
 };
 
+/// Highest anisotropy level samplers support.
+pub const MAX_ANISOTROPY: u16 = 16;
+
 /// Container holding a variety of samplers.
 pub struct Samplers {
-    /// Aniso 16 sampler
+    /// Linear sampler with the requested anisotropy
     pub linear: Sampler,
     /// Nearest neighbor sampler
     pub nearest: Sampler,
     /// Bilinear greater-or-equal comparison sampler
     pub shadow: Sampler,
+    /// Linear sampler with the requested anisotropy, clamping to the edge
+    pub linear_clamp: Sampler,
+    /// Linear sampler with the requested anisotropy, mirroring every other
+    /// repetition
+    pub linear_mirror: Sampler,
+    /// Nearest neighbor sampler clamping to the edge
+    pub nearest_clamp: Sampler,
+    /// Nearest neighbor sampler mirroring every other repetition
+    pub nearest_mirror: Sampler,
 }
 
 impl Samplers {
-    /// Create a new set of samplers with this device.
-    pub fn new(device: &Device) -> Self {
+    /// Create a new set of samplers for this renderer.
+    ///
+    /// The linear material samplers filter with the given `anisotropy` level,
+    /// sharpening textures viewed at grazing angles. It is rounded down to
+    /// the closest of 1, 2, 4, 8 and 16, and ignored when the device doesn't
+    /// support anisotropic filtering. Materials with nearest filtering are
+    /// never filtered anisotropically.
+    pub fn new(renderer: &Renderer, anisotropy: u16) -> Self {
         profiling::scope!("Samplers::new");
 
-        let linear = create_sampler(device, FilterMode::Linear, None);
-        let nearest = create_sampler(device, FilterMode::Nearest, None);
-        let shadow = create_sampler(device, FilterMode::Linear, Some(CompareFunction::GreaterEqual));
+        let aniso =
+            fit_anisotropy(anisotropy, renderer.downlevel.flags.contains(DownlevelFlags::ANISOTROPIC_FILTERING));
+
+        let sampler = |label, filter, address_mode, anisotropy_clamp, compare| {
+            renderer.device.create_sampler(&SamplerDescriptor {
+                label: Some(label),
+                address_mode_u: address_mode,
+                address_mode_v: address_mode,
+                address_mode_w: address_mode,
+                mag_filter: filter,
+                min_filter: filter,
+                mipmap_filter: filter,
+                lod_min_clamp: 0.0,
+                lod_max_clamp: 100.0,
+                compare,
+                anisotropy_clamp,
+                border_color: None,
+            })
+        };
+
+        let linear = sampler("linear", FilterMode::Linear, AddressMode::Repeat, aniso, None);
+        let nearest = sampler("nearest", FilterMode::Nearest, AddressMode::Repeat, 1, None);
+        let shadow = sampler("shadow", FilterMode::Linear, AddressMode::Repeat, 1, Some(CompareFunction::GreaterEqual));
+        let linear_clamp = sampler("linear clamp", FilterMode::Linear, AddressMode::ClampToEdge, aniso, None);
+        let linear_mirror = sampler("linear mirror", FilterMode::Linear, AddressMode::MirrorRepeat, aniso, None);
+        let nearest_clamp = sampler("nearest clamp", FilterMode::Nearest, AddressMode::ClampToEdge, 1, None);
+        let nearest_mirror = sampler("nearest mirror", FilterMode::Nearest, AddressMode::MirrorRepeat, 1, None);
 
-        Self { linear, nearest, shadow }
+        Self { linear, nearest, shadow, linear_clamp, linear_mirror, nearest_clamp, nearest_mirror }
     }
 
     /// Add the samplers to the given bind group layout builder.
@@ -37,21 +82,50 @@ impl Samplers {
     pub fn add_to_bg<'a>(&'a self, bgb: &mut BindGroupBuilder<'a>) {
         bgb.append_sampler(&self.linear).append_sampler(&self.nearest).append_sampler(&self.shadow);
     }
+
+    /// Add the samplers with non-repeating wrap modes to the given bind group
+    /// layout builder.
+    ///
+    /// They follow the lights, so the bindings of the other samplers don't
+    /// move.
+    pub fn add_wrap_to_bgl(bglb: &mut BindGroupLayoutBuilder) {
+        bglb.append(ShaderStages::FRAGMENT, BindingType::Sampler(SamplerBindingType::Filtering), None)
+            .append(ShaderStages::FRAGMENT, BindingType::Sampler(SamplerBindingType::Filtering), None)
+            .append(ShaderStages::FRAGMENT, BindingType::Sampler(SamplerBindingType::NonFiltering), None)
+            .append(ShaderStages::FRAGMENT, BindingType::Sampler(SamplerBindingType::NonFiltering), None);
+    }
+
+    /// Add the samplers with non-repeating wrap modes to the given bind group
+    /// builder.
+    pub fn add_wrap_to_bg<'a>(&'a self, bgb: &mut BindGroupBuilder<'a>) {
+        bgb.append_sampler(&self.linear_clamp)
+            .append_sampler(&self.linear_mirror)
+            .append_sampler(&self.nearest_clamp)
+            .append_sampler(&self.nearest_mirror);
+    }
 }
 
-fn create_sampler(device: &Device, filter: FilterMode, compare: Option<CompareFunction>) -> Sampler {
-    device.create_sampler(&SamplerDescriptor {
-        label: Some("linear"),
-        address_mode_u: AddressMode::Repeat,
-        address_mode_v: AddressMode::Repeat,
-        address_mode_w: AddressMode::Repeat,
-        mag_filter: filter,
-        min_filter: filter,
-        mipmap_filter: filter,
-        lod_min_clamp: 0.0,
-        lod_max_clamp: 100.0,
-        compare,
-        anisotropy_clamp: 1,
-        border_color: None,
-    })
+/// Rounds the requested anisotropy down to a supported level.
+fn fit_anisotropy(requested: u16, supported: bool) -> u16 {
+    if !supported || requested <= 1 {
+        return 1;
+    }
+    let level = requested.min(MAX_ANISOTROPY);
+    1 << (u16::BITS - 1 - level.leading_zeros())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::fit_anisotropy;
+
+    #[test]
+    fn anisotropy_levels() {
+        assert_eq!(fit_anisotropy(0, true), 1);
+        assert_eq!(fit_anisotropy(1, true), 1);
+        assert_eq!(fit_anisotropy(2, true), 2);
+        assert_eq!(fit_anisotropy(7, true), 4);
+        assert_eq!(fit_anisotropy(16, true), 16);
+        assert_eq!(fit_anisotropy(64, true), 16);
+        assert_eq!(fit_anisotropy(16, false), 1);
+    }
 }
@@ -0,0 +1,60 @@
+//! Routine which dispatches a user provided compute shader as part of the frame.
+//!
+//! This is the simplest way to run custom gpu work (particle simulation,
+//! custom culling, image processing) in line with the rest of the rendergraph.
+//! The pipeline and bind groups are created by the user with the renderer's
+//! [`Device`](wgpu::Device), so they can reference any buffer or texture the
+//! user has access to.
+//!
+//! The node is declared as having a side effect, so it always runs, in the
+//! order it was added relative to the other nodes in the graph.
+
+use std::sync::Arc;
+
+use glam::UVec3;
+use rend3::graph::RenderGraph;
+use wgpu::{BindGroup, Buffer, ComputePassDescriptor, ComputePipeline};
+
+/// How many workgroups a [`ComputePass`] dispatches.
+#[derive(Debug, Clone)]
+pub enum ComputeDispatch {
+    /// Dispatch the given amount of workgroups in each dimension.
+    Direct(UVec3),
+    /// Read the workgroup counts from a [`wgpu::util::DispatchIndirectArgs`]
+    /// at `offset` in `buffer`.
+    Indirect { buffer: Arc<Buffer>, offset: u64 },
+}
+
+/// A user provided compute shader dispatch.
+pub struct ComputePass<'node> {
+    pub label: &'static str,
+    pub pipeline: &'node ComputePipeline,
+    /// Bind groups that get bound in order, starting at group 0.
+    pub bind_groups: Vec<&'node BindGroup>,
+    pub dispatch: ComputeDispatch,
+}
+
+impl<'node> ComputePass<'node> {
+    /// Adds the dispatch to the given rendergraph.
+    pub fn add_to_graph(self, graph: &mut RenderGraph<'node>) {
+        let mut builder = graph.add_node(self.label);
+        builder.add_side_effect();
+
+        builder.build(move |mut ctx| {
+            let encoder = ctx.encoder_or_pass.take_encoder();
+
+            profiling::scope!("User Compute Pass");
+
+            let mut cpass =
+                encoder.begin_compute_pass(&ComputePassDescriptor { label: Some(self.label), timestamp_writes: None });
+            cpass.set_pipeline(self.pipeline);
+            for (idx, bg) in self.bind_groups.iter().enumerate() {
+                cpass.set_bind_group(idx as u32, bg, &[]);
+            }
+            match self.dispatch {
+                ComputeDispatch::Direct(count) => cpass.dispatch_workgroups(count.x, count.y, count.z),
+                ComputeDispatch::Indirect { ref buffer, offset } => cpass.dispatch_workgroups_indirect(buffer, offset),
+            }
+        });
+    }
+}
@@ -17,6 +17,12 @@ use super::{BATCH_SIZE, WORKGROUP_SIZE};
 pub struct ShaderBatchDatas {
     pub(super) regions: Vec<JobSubRegion>,
     pub(super) jobs: Vec<ShaderBatchData>,
+    /// Amount of objects inside the camera's frustum.
+    pub(super) visible_objects: u32,
+    /// Amount of objects skipped for being outside the camera's frustum.
+    pub(super) frustum_culled_objects: u32,
+    /// Amount of triangles of all visible objects.
+    pub(super) triangles: u64,
 }
 
 #[derive(Debug)]
@@ -97,6 +103,8 @@ pub(super) struct ShaderObjectCullingInformation {
     pub local_region_id: u32,
     pub previous_global_invocation: u32,
     pub atomic_capable: u32,
+    /// Non-zero if back faces of the object are kept.
+    pub double_sided: u32,
 }
 
 /// Map containing the previous invocation of each object.
@@ -122,6 +130,7 @@ pub(super) fn batch_objects<M: Material>(
     previous_invocation_map_handle: &GraphDataHandle<PerCameraPreviousInvocationsMap>,
     camera: &CameraState,
     camera_specifier: CameraSpecifier,
+    disable_culling: bool,
 ) -> ShaderBatchDatas {
     profiling::scope!("Batch Objects");
 
@@ -129,7 +138,13 @@ pub(super) fn batch_objects<M: Material>(
     let previous_invocation_map = per_camera_previous_invocation_map.get_and_reset_camera(camera_specifier);
     let mut current_invocation_map = FastHashMap::default();
 
-    let mut jobs = ShaderBatchDatas { jobs: Vec::new(), regions: Vec::new() };
+    let mut jobs = ShaderBatchDatas {
+        jobs: Vec::new(),
+        regions: Vec::new(),
+        visible_objects: 0,
+        frustum_culled_objects: 0,
+        triangles: 0,
+    };
 
     let objects = match ctx.data_core.object_manager.enumerated_objects::<M>() {
         Some(o) => o,
@@ -138,23 +153,36 @@ pub(super) fn batch_objects<M: Material>(
 
     let material_archetype = ctx.data_core.material_manager.archetype_view::<M>();
 
+    // Shadows are sorted by their distance to the viewport camera.
+    let sort_origin = match camera_specifier {
+        CameraSpecifier::Shadow(_) => ctx.data_core.viewport_camera_state.location(),
+        CameraSpecifier::Viewport | CameraSpecifier::Extra(_) => camera.location(),
+    };
+
     let mut sorted_objects = Vec::with_capacity(objects.len());
     {
         profiling::scope!("Sort Key Creation");
         for (handle, object) in objects {
+            if !object.visible || object.layer_mask & camera.get_data().layer_mask == 0 {
+                continue;
+            }
+
             // Frustum culling
-            if !camera.world_frustum().contains_sphere(object.inner.bounding_sphere) {
+            if !disable_culling && !camera.world_frustum().contains_sphere(object.inner.bounding_sphere) {
+                jobs.frustum_culled_objects += 1;
                 continue;
             }
+            jobs.visible_objects += 1;
+            jobs.triangles += (object.inner.index_count / 3) as u64;
 
             let material = material_archetype.material(*object.material_handle);
             let bind_group_index = material.bind_group_index.map_gpu(|_| TextureBindGroupIndex::DUMMY).into_common();
 
             let material_key = material.inner.key();
             let sorting = material.inner.sorting();
+            let double_sided = material.inner.double_sided();
 
-            let mut distance_sq =
-                ctx.data_core.viewport_camera_state.location().distance_squared(object.location.into());
+            let mut distance_sq = sort_origin.distance_squared(object.location.into());
             if sorting.order == SortingOrder::BackToFront {
                 distance_sq = -distance_sq;
             }
@@ -166,13 +194,14 @@ pub(super) fn batch_objects<M: Material>(
                 },
                 handle,
                 object,
+                double_sided,
             ))
         }
     }
 
     {
         profiling::scope!("Sorting");
-        sorted_objects.sort_unstable_by_key(|(k, _, _)| *k);
+        sorted_objects.sort_unstable_by_key(|(k, _, _, _)| *k);
     }
 
     if !sorted_objects.is_empty() {
@@ -188,7 +217,7 @@ pub(super) fn batch_objects<M: Material>(
 
         let max_dispatch_count = ctx.renderer.limits.max_compute_workgroups_per_dimension;
 
-        for (ShaderJobSortingKey { job_key: key, sorting_reason, .. }, handle, object) in sorted_objects {
+        for (ShaderJobSortingKey { job_key: key, sorting_reason, .. }, handle, object, double_sided) in sorted_objects {
             let invocation_count = object.inner.index_count / 3;
 
             let key_difference = key != current_key;
@@ -225,6 +254,7 @@ pub(super) fn batch_objects<M: Material>(
                 local_region_id: current_region_object_index,
                 previous_global_invocation: previous_invocation_map.get(&handle).copied().unwrap_or(u32::MAX),
                 atomic_capable: matches!(sorting_reason, SortingReason::Optimization) as u32,
+                double_sided: double_sided as u32,
             };
 
             current_invocation_map.insert(handle, current_invocation + current_base_invocation);
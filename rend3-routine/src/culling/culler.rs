@@ -5,24 +5,32 @@ use std::{
     num::NonZeroU64,
     ops::Range,
     sync::Arc,
+    time::Duration,
 };
 
 use encase::{ShaderSize, ShaderType, StorageBuffer};
 use glam::{Mat4, UVec2, Vec2};
+use parking_lot::Mutex;
 use rend3::{
     format_sso,
     graph::{DataHandle, DeclaredDependency, NodeExecutionContext, NodeResourceUsage, RenderGraph, RenderTargetHandle},
     managers::{CameraState, ShaderObject, TextureBindGroupIndex},
     types::{GraphDataHandle, Material, MaterialArray, SampleCount, VERTEX_ATTRIBUTE_POSITION},
-    util::{frustum::Frustum, math::IntegerExt, typedefs::FastHashMap},
+    util::{
+        bind_group_cache::BindGroupCache,
+        buffer_pool::BufferPool,
+        frustum::Frustum,
+        math::IntegerExt,
+        typedefs::{FastHashMap, RendererStatistics, SsoString},
+    },
     Renderer, ShaderPreProcessor, ShaderVertexBufferConfig,
 };
 use wgpu::{
-    self, AddressMode, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
-    BindGroupLayoutEntry, BindingResource, BindingType, Buffer, BufferBinding, BufferBindingType, BufferDescriptor,
-    BufferUsages, CommandEncoder, ComputePassDescriptor, ComputePipeline, ComputePipelineDescriptor, Device,
-    FilterMode, PipelineLayoutDescriptor, Queue, Sampler, SamplerBindingType, SamplerDescriptor,
-    ShaderModuleDescriptor, ShaderStages, TextureSampleType, TextureViewDimension,
+    self, AddressMode, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry,
+    BindingResource, BindingType, Buffer, BufferBinding, BufferBindingType, BufferDescriptor, BufferUsages,
+    CommandEncoder, ComputePassDescriptor, ComputePipeline, ComputePipelineDescriptor, Device, FilterMode,
+    PipelineLayoutDescriptor, Queue, Sampler, SamplerBindingType, SamplerDescriptor, ShaderModuleDescriptor,
+    ShaderStages, TextureAspect, TextureSampleType, TextureViewDimension,
 };
 
 use crate::{
@@ -36,13 +44,49 @@ use crate::{
 
 #[derive(Debug)]
 pub struct DrawCallSet {
-    pub culling_data_buffer: Buffer,
+    pub culling_data_buffer: Arc<Buffer>,
     pub per_camera_uniform: Arc<Buffer>,
     pub draw_calls: Vec<DrawCall>,
     /// Range of draw calls in the draw call array corresponding to a given material key.
     pub material_key_ranges: HashMap<u64, Range<usize>>,
 }
 
+/// Time spent culling for a single camera.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CullingTiming {
+    pub camera: CameraSpecifier,
+    /// Label of the culling node. This is also the label of its gpu timer scope.
+    pub label: SsoString,
+    /// Cpu time spent batching objects and recording the culling dispatches.
+    pub cpu_time: Duration,
+    /// Gpu time spent in the culling node in seconds, if it was found in the
+    /// given statistics.
+    pub gpu_time: Option<f64>,
+}
+
+/// Amount of objects the cpu frustum culled for a single camera.
+///
+/// Visible objects are still culled per triangle on the gpu afterwards.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CullingCounts {
+    pub camera: CameraSpecifier,
+    /// Label of the culling node.
+    pub label: SsoString,
+    /// Amount of objects at least partially inside the camera's frustum.
+    pub visible_objects: u32,
+    /// Amount of objects entirely outside the camera's frustum, which were
+    /// skipped before building the culling buffers.
+    pub frustum_culled_objects: u32,
+}
+
+/// Results of the most recent culling of a camera.
+struct CullingRecord {
+    label: SsoString,
+    cpu_time: Duration,
+    visible_objects: u32,
+    frustum_culled_objects: u32,
+}
+
 #[derive(Debug, Clone)]
 pub struct DrawCall {
     pub bind_group_index: TextureBindGroupIndex,
@@ -152,6 +196,7 @@ bitflags::bitflags! {
     struct PerCameraUniformFlags: u32 {
         const POSTIIVE_AREA_VISIBLE = 1 << 0;
         const MULTISAMPLED = 1 << 1;
+        const CULLING_DISABLED = 1 << 2;
     }
 }
 
@@ -193,6 +238,13 @@ pub struct GpuCuller {
     per_material_buffer_handle: GraphDataHandle<HashMap<CameraSpecifier, Arc<Buffer>>>,
     pub culling_buffer_map_handle: GraphDataHandle<CullingBufferMap>,
     previous_invocation_map_handle: GraphDataHandle<PerCameraPreviousInvocationsMap>,
+    /// Results of the most recent culling of each camera.
+    records: Mutex<FastHashMap<CameraSpecifier, CullingRecord>>,
+    /// Culling job data of every camera, recycled across frames.
+    culling_data_buffers: BufferPool,
+    /// Uniform preparation and culling bind groups of every camera, reused
+    /// across frames.
+    bind_groups: BindGroupCache,
 }
 
 impl GpuCuller {
@@ -421,9 +473,53 @@ impl GpuCuller {
             per_material_buffer_handle,
             culling_buffer_map_handle,
             previous_invocation_map_handle,
+            records: Mutex::new(FastHashMap::default()),
+            culling_data_buffers: BufferPool::new("Culling Data Buffer", BufferUsages::STORAGE),
+            bind_groups: BindGroupCache::new(),
         }
     }
 
+    /// Returns the time spent culling each camera, shadow cameras first.
+    ///
+    /// Cpu times are those of the most recently executed frame. Gpu times are
+    /// looked up by label in `stats`, which are those returned from
+    /// [`RenderGraph::execute`], and lag a few frames behind.
+    pub fn culling_timings(&self, stats: Option<&RendererStatistics>) -> Vec<CullingTiming> {
+        let mut timings: Vec<_> = self
+            .records
+            .lock()
+            .iter()
+            .map(|(&camera, record)| CullingTiming {
+                camera,
+                label: record.label.clone(),
+                cpu_time: record.cpu_time,
+                gpu_time: stats
+                    .and_then(|stats| stats.iter().find(|query| query.label == record.label.as_str()))
+                    .map(|query| query.time.end - query.time.start),
+            })
+            .collect();
+        timings.sort_by_key(|timing| timing.camera.to_shader_index());
+        timings
+    }
+
+    /// Returns the amount of objects visible to and frustum culled from each
+    /// camera in the most recently executed frame, shadow cameras first.
+    pub fn culling_counts(&self) -> Vec<CullingCounts> {
+        let mut counts: Vec<_> = self
+            .records
+            .lock()
+            .iter()
+            .map(|(&camera, record)| CullingCounts {
+                camera,
+                label: record.label.clone(),
+                visible_objects: record.visible_objects,
+                frustum_culled_objects: record.frustum_culled_objects,
+            })
+            .collect();
+        counts.sort_by_key(|counts| counts.camera.to_shader_index());
+        counts
+    }
+
     pub fn object_uniform_upload<M>(
         &self,
         ctx: &mut NodeExecutionContext,
@@ -431,6 +527,7 @@ impl GpuCuller {
         camera_specifier: CameraSpecifier,
         resolution: UVec2,
         samples: SampleCount,
+        disable_culling: bool,
     ) where
         M: Material,
     {
@@ -476,7 +573,7 @@ impl GpuCuller {
 
         let culling = match camera_specifier {
             CameraSpecifier::Shadow(_) => wgpu::Face::Front,
-            CameraSpecifier::Viewport => wgpu::Face::Back,
+            CameraSpecifier::Viewport | CameraSpecifier::Extra(_) => wgpu::Face::Back,
         };
 
         {
@@ -495,6 +592,7 @@ impl GpuCuller {
                         TriangleVisibility::from_winding_and_face(self.winding, culling).is_positive(),
                     );
                     flags.set(PerCameraUniformFlags::MULTISAMPLED, samples != SampleCount::One);
+                    flags.set(PerCameraUniformFlags::CULLING_DISABLED, disable_culling);
                     flags.bits()
                 },
                 object_count: max_object_count as u32,
@@ -507,14 +605,16 @@ impl GpuCuller {
         let Some(object_manager_buffer) = ctx.data_core.object_manager.buffer::<M>() else {
             return;
         };
-        let prep_bg = ctx.renderer.device.create_bind_group(&BindGroupDescriptor {
-            label: Some(&format_sso!("UniformPrep {type_name} BG")),
-            layout: &self.prep_bgl,
-            entries: &[
+        let prep_bg = self.bind_groups.get(
+            &ctx.renderer.device,
+            ctx.eval_output.frame,
+            Some(&format_sso!("UniformPrep {type_name} BG")),
+            &self.prep_bgl,
+            &[
                 BindGroupEntry { binding: 0, resource: object_manager_buffer.as_entire_binding() },
                 BindGroupEntry { binding: 1, resource: buffer.as_entire_binding() },
             ],
-        });
+        );
 
         profiling::scope!("Command Encoding");
 
@@ -575,27 +675,24 @@ impl GpuCuller {
         let culling_data_buffer = {
             profiling::scope!("Culling Job Data Upload");
 
-            let culling_data_buffer = ctx.renderer.device.create_buffer(&wgpu::BufferDescriptor {
-                label: Some("Culling Data Buffer"),
-                size: jobs.jobs.size().get(),
-                usage: wgpu::BufferUsages::STORAGE,
-                mapped_at_creation: true,
-            });
+            // The draw call set keeps it until the frame is submitted, so no other camera reuses it this frame.
+            let culling_data_buffer = self.culling_data_buffers.get(&ctx.renderer.device, jobs.jobs.size().get());
 
-            let mut mapping = culling_data_buffer.slice(..).get_mapped_range_mut();
-            StorageBuffer::new(&mut *mapping).write(&jobs.jobs).unwrap();
-            drop(mapping);
-            culling_data_buffer.unmap();
+            let mut data = StorageBuffer::new(Vec::new());
+            data.write(&jobs.jobs).unwrap();
+            ctx.renderer.queue.write_buffer(&culling_data_buffer, 0, &data.into_inner());
 
             culling_data_buffer
         };
 
         let hi_z_buffer = ctx.graph_data.get_render_target(depth_handle);
 
-        let culling_bg = ctx.renderer.device.create_bind_group(&BindGroupDescriptor {
-            label: Some(&format_sso!("GpuCuller {type_name} BG")),
-            layout: &self.culling_bgl,
-            entries: &[
+        let culling_bg = self.bind_groups.get(
+            &ctx.renderer.device,
+            ctx.eval_output.frame,
+            Some(&format_sso!("GpuCuller {type_name} BG")),
+            &self.culling_bgl,
+            &[
                 BindGroupEntry { binding: 0, resource: ctx.eval_output.mesh_buffer.as_entire_binding() },
                 BindGroupEntry {
                     binding: 1,
@@ -616,7 +713,7 @@ impl GpuCuller {
                 BindGroupEntry { binding: 7, resource: BindingResource::TextureView(hi_z_buffer) },
                 BindGroupEntry { binding: 8, resource: BindingResource::Sampler(&self.sampler) },
             ],
-        });
+        );
 
         profiling::scope!("Command Encoding");
         let mut draw_calls = Vec::with_capacity(jobs.jobs.len());
@@ -664,18 +761,16 @@ impl GpuCuller {
         camera_specifier: CameraSpecifier,
         resolution: UVec2,
         samples: SampleCount,
+        disable_culling: bool,
         name: &str,
     ) {
         let mut node = graph.add_node(name);
         node.add_side_effect();
 
         node.build(move |mut ctx| {
-            let camera = match camera_specifier {
-                CameraSpecifier::Shadow(i) => &ctx.eval_output.shadows[i as usize].camera,
-                CameraSpecifier::Viewport => &ctx.data_core.viewport_camera_state,
-            };
+            let camera = camera_specifier.state(ctx.data_core, ctx.eval_output);
 
-            self.object_uniform_upload::<M>(&mut ctx, camera, camera_specifier, resolution, samples);
+            self.object_uniform_upload::<M>(&mut ctx, camera, camera_specifier, resolution, samples, disable_culling);
         });
     }
 
@@ -685,30 +780,57 @@ impl GpuCuller {
         draw_calls_hdl: DataHandle<Arc<DrawCallSet>>,
         depth_handle: RenderTargetHandle,
         camera_specifier: CameraSpecifier,
+        disable_culling: bool,
         name: &str,
     ) {
+        let label = SsoString::from(name);
         let mut node = graph.add_node(name);
         let output = node.add_data(draw_calls_hdl, NodeResourceUsage::Output);
         let depth_handle = node.add_render_target(
-            depth_handle,
+            depth_handle.set_aspect(TextureAspect::DepthOnly),
             if camera_specifier.is_shadow() { NodeResourceUsage::Reference } else { NodeResourceUsage::Input },
         );
 
         node.build(move |mut ctx| {
-            let camera = match camera_specifier {
-                CameraSpecifier::Shadow(i) => &ctx.eval_output.shadows[i as usize].camera,
-                CameraSpecifier::Viewport => &ctx.data_core.viewport_camera_state,
-            };
+            let start = web_time::Instant::now();
 
-            let jobs = batch_objects::<M>(&mut ctx, &self.previous_invocation_map_handle, camera, camera_specifier);
+            let camera = camera_specifier.state(ctx.data_core, ctx.eval_output);
 
-            if jobs.jobs.is_empty() {
-                return;
-            }
+            let jobs = batch_objects::<M>(
+                &mut ctx,
+                &self.previous_invocation_map_handle,
+                camera,
+                camera_specifier,
+                disable_culling,
+            );
+            let (visible_objects, frustum_culled_objects) = (jobs.visible_objects, jobs.frustum_culled_objects);
+            let (draw_calls, triangles) = (jobs.regions.len() as u32, jobs.triangles);
 
-            let draw_calls = self.cull::<M>(&mut ctx, jobs, depth_handle, camera_specifier);
+            if !jobs.jobs.is_empty() {
+                let draw_calls = self.cull::<M>(&mut ctx, jobs, depth_handle, camera_specifier);
 
-            ctx.graph_data.set_data(output, Some(Arc::new(draw_calls)));
+                ctx.graph_data.set_data(output, Some(Arc::new(draw_calls)));
+            }
+
+            let shadow_count = ctx.eval_output.shadows.len() as u32;
+            let extra_count = ctx.eval_output.viewports.len() as u32;
+            let mut records = self.records.lock();
+            // Forget shadow and extra cameras which no longer exist.
+            records.retain(|camera, _| match *camera {
+                CameraSpecifier::Shadow(i) => i < shadow_count,
+                CameraSpecifier::Extra(i) => i < extra_count,
+                CameraSpecifier::Viewport => true,
+            });
+            let cpu_time = start.elapsed();
+            records
+                .insert(camera_specifier, CullingRecord { label, cpu_time, visible_objects, frustum_culled_objects });
+
+            let mut stats = ctx.eval_output.stats.lock();
+            stats.objects_submitted += visible_objects;
+            stats.objects_culled += frustum_culled_objects;
+            stats.draw_calls += draw_calls;
+            stats.triangles += triangles;
+            stats.culling_time += cpu_time;
         });
     }
 }
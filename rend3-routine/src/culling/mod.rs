@@ -6,5 +6,5 @@ mod culler;
 mod suballoc;
 
 pub use batching::{ShaderBatchData, ShaderBatchDatas};
-pub use culler::{CullingBufferMap, DrawCall, DrawCallSet, GpuCuller};
+pub use culler::{CullingBufferMap, CullingTiming, DrawCall, DrawCallSet, GpuCuller};
 pub use suballoc::{InputOutputBuffer, InputOutputPartition};
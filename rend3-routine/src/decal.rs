@@ -0,0 +1,184 @@
+//! Decals projected onto the opaque geometry, see
+//! [`Decal`](rend3::types::Decal).
+//!
+//! Every decal draws the faces of its box facing away from the camera, which
+//! cover the box on screen. The world space position of every pixel is
+//! reconstructed from the depth buffer, and pixels whose surface lies inside
+//! the box are blended with the decal's texture. This runs after all opaque
+//! objects and decal meshes are shaded, before the skybox and transparent
+//! objects.
+
+use std::borrow::Cow;
+
+use glam::Vec4;
+use rend3::{
+    graph::{DataHandle, NodeResourceUsage, RenderGraph, RenderPassTarget, RenderPassTargets, RenderTargetHandle},
+    managers::DecalManager,
+    types::SampleCount,
+    util::bind_merge::{BindGroupBuilder, BindGroupLayoutBuilder},
+    ShaderPreProcessor,
+};
+use wgpu::{
+    BindGroup, BindGroupLayout, BindingType, BlendState, ColorTargetState, ColorWrites, Device, FragmentState,
+    MultisampleState, PipelineLayoutDescriptor, PrimitiveState, RenderPipeline, RenderPipelineDescriptor,
+    ShaderModuleDescriptor, ShaderSource, ShaderStages, TextureAspect, TextureFormat, TextureSampleType,
+    TextureViewDimension, VertexState,
+};
+
+use crate::common::WholeFrameInterfaces;
+
+/// Vertices drawn per decal, two triangles for each face of its box.
+const DECAL_VERTEX_COUNT: u32 = 36;
+
+struct DecalPipeline {
+    bgl: BindGroupLayout,
+    pipeline: RenderPipeline,
+}
+
+impl DecalPipeline {
+    fn new(device: &Device, spp: &ShaderPreProcessor, interfaces: &WholeFrameInterfaces, samples: SampleCount) -> Self {
+        let mut bglb = BindGroupLayoutBuilder::new();
+        DecalManager::add_to_bgl(&mut bglb);
+        let bgl = bglb
+            .append(
+                ShaderStages::FRAGMENT,
+                BindingType::Texture {
+                    sample_type: TextureSampleType::Depth,
+                    view_dimension: TextureViewDimension::D2,
+                    multisampled: samples != SampleCount::One,
+                },
+                None,
+            )
+            .append(
+                ShaderStages::FRAGMENT,
+                BindingType::Texture {
+                    sample_type: TextureSampleType::Float { filterable: true },
+                    view_dimension: TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                None,
+            )
+            .build(device, Some("decal bgl"));
+
+        let source = spp
+            .render_shader("rend3-routine/decal.wgsl", &serde_json::json!({"SAMPLES": samples as u8}), None)
+            .unwrap();
+        let module = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("decal"),
+            source: ShaderSource::Wgsl(Cow::Owned(source)),
+        });
+
+        let pll = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("decal"),
+            bind_group_layouts: &[&interfaces.depth_uniform_bgl, &bgl],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("decal"),
+            layout: Some(&pll),
+            vertex: VertexState { module: &module, entry_point: "vs_main", buffers: &[] },
+            // Faces are picked in the vertex shader, so winding doesn't matter.
+            primitive: PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: MultisampleState { count: samples as u32, ..Default::default() },
+            fragment: Some(FragmentState {
+                module: &module,
+                entry_point: "fs_main",
+                targets: &[Some(ColorTargetState {
+                    format: TextureFormat::Rgba16Float,
+                    blend: Some(BlendState::ALPHA_BLENDING),
+                    write_mask: ColorWrites::COLOR,
+                })],
+            }),
+            multiview: None,
+        });
+
+        Self { bgl, pipeline }
+    }
+}
+
+/// Routine rendering the decals added with
+/// [`Renderer::add_decal`](rend3::Renderer::add_decal).
+///
+/// See module for documentation.
+pub struct DecalRoutine {
+    decal_s1: DecalPipeline,
+    decal_s4: DecalPipeline,
+}
+
+impl DecalRoutine {
+    pub fn new(device: &Device, spp: &ShaderPreProcessor, interfaces: &WholeFrameInterfaces) -> Self {
+        profiling::scope!("DecalRoutine::new");
+
+        Self {
+            decal_s1: DecalPipeline::new(device, spp, interfaces, SampleCount::One),
+            decal_s4: DecalPipeline::new(device, spp, interfaces, SampleCount::Four),
+        }
+    }
+
+    /// Blends the decals into `color`, projecting them onto the surfaces in
+    /// `depth`. Both have the given sample count, and `color` is resolved into
+    /// `resolve` if multisampled.
+    pub fn add_to_graph<'node>(
+        &'node self,
+        graph: &mut RenderGraph<'node>,
+        color: RenderTargetHandle,
+        resolve: Option<RenderTargetHandle>,
+        depth: RenderTargetHandle,
+        samples: SampleCount,
+        shadow_uniform_bg: DataHandle<BindGroup>,
+    ) {
+        let decal = match samples {
+            SampleCount::One => &self.decal_s1,
+            SampleCount::Four => &self.decal_s4,
+        };
+
+        let mut builder = graph.add_node("Decals");
+        let depth_handle =
+            builder.add_render_target(depth.set_aspect(TextureAspect::DepthOnly), NodeResourceUsage::Input);
+        let rpass_handle = builder.add_renderpass(
+            RenderPassTargets {
+                targets: vec![RenderPassTarget { color, resolve, clear: Vec4::ZERO }],
+                depth_stencil: None,
+            },
+            NodeResourceUsage::InputOutput,
+        );
+        let shadow_uniform_handle = builder.add_data(shadow_uniform_bg, NodeResourceUsage::Input);
+
+        builder.build(move |mut ctx| {
+            let rpass = ctx.encoder_or_pass.take_rpass(rpass_handle);
+            let shadow_uniform_bg = ctx.graph_data.get_data(ctx.temps, shadow_uniform_handle).unwrap();
+            let depth = ctx.graph_data.get_render_target(depth_handle);
+
+            profiling::scope!("decals");
+
+            let textures = ctx.data_core.decal_manager.textures();
+            if textures.is_empty() {
+                return;
+            }
+
+            rpass.set_pipeline(&decal.pipeline);
+            rpass.set_bind_group(0, shadow_uniform_bg, &[]);
+
+            // Consecutive decals with the same texture share a bind group and draw call.
+            let mut start = 0;
+            while start < textures.len() {
+                let texture = textures[start];
+                let end = start + textures[start..].iter().take_while(|&&other| other == texture).count();
+
+                let mut bgb = BindGroupBuilder::new();
+                ctx.data_core.decal_manager.add_to_bg(&mut bgb);
+                let bg = bgb
+                    .append_texture_view(depth)
+                    .append_texture_view(ctx.data_core.d2_texture_manager.get_view(texture))
+                    .build(&ctx.renderer.device, Some("decal bg"), &decal.bgl);
+                let bg = ctx.temps.add(bg);
+
+                rpass.set_bind_group(1, bg, &[]);
+                rpass.draw(0..DECAL_VERTEX_COUNT, start as u32..end as u32);
+                start = end;
+            }
+        });
+    }
+}
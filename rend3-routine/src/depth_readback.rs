@@ -0,0 +1,311 @@
+//! Reading the depth of the rendered frame back to cpu memory, for effects
+//! like baking contact shadows, projecting decals or debug views.
+//!
+//! Multisampled depth textures can't be copied to a buffer, and the depth of
+//! a perspective camera isn't linear. The [`DepthReader`] instead runs a
+//! compute shader which writes the view space depth of every pixel into a
+//! tightly packed buffer, so there is no row padding to remove either. The
+//! view and projection of the camera are read back along with it, so world
+//! positions can be reconstructed with [`DepthReadback::world_position`].
+//!
+//! The depth is read once all opaque and cutout objects are rendered, so
+//! decals, the skybox and transparent objects are not part of it.
+
+use std::{borrow::Cow, sync::Arc};
+
+use encase::{ShaderSize, ShaderType, UniformBuffer};
+use glam::{Mat4, UVec2, Vec2, Vec3};
+use parking_lot::Mutex;
+use rend3::{
+    graph::{NodeResourceUsage, RenderGraph, RenderTargetHandle},
+    types::SampleCount,
+    util::{
+        bind_merge::{BindGroupBuilder, BindGroupLayoutBuilder},
+        math::div_round_up,
+        readback::{read_buffer, ReadbackError},
+    },
+    Renderer, ShaderPreProcessor,
+};
+use wgpu::{
+    BindGroupLayout, BindingType, Buffer, BufferBindingType, BufferDescriptor, BufferUsages, ComputePassDescriptor,
+    ComputePipeline, ComputePipelineDescriptor, Device, PipelineLayoutDescriptor, ShaderModuleDescriptor, ShaderSource,
+    ShaderStages, TextureAspect, TextureSampleType, TextureViewDimension,
+};
+
+const WORKGROUP_SIZE: u32 = 8;
+
+/// Camera of the frame whose depth is read, the header of the readback buffer.
+#[derive(Debug, Copy, Clone, ShaderType)]
+struct DepthReadbackCamera {
+    view: Mat4,
+    proj: Mat4,
+    inv_proj: Mat4,
+    resolution: UVec2,
+}
+
+/// Depth of a frame read back to cpu memory.
+#[derive(Debug, Clone)]
+pub struct DepthReadback {
+    pub resolution: UVec2,
+    /// View matrix of the camera the frame was rendered with.
+    pub view: Mat4,
+    /// Projection matrix of the camera the frame was rendered with.
+    pub proj: Mat4,
+    /// Distance of every pixel in front of the camera along its view
+    /// direction, row by row from the top left. 0 where nothing was rendered.
+    pub depths: Vec<f32>,
+}
+
+impl DepthReadback {
+    /// View space depth at `pixel`, or `None` if nothing was rendered there or
+    /// the pixel is outside of the image.
+    pub fn depth(&self, pixel: UVec2) -> Option<f32> {
+        if pixel.x >= self.resolution.x || pixel.y >= self.resolution.y {
+            return None;
+        }
+        let depth = self.depths[(pixel.y * self.resolution.x + pixel.x) as usize];
+        (depth != 0.0).then_some(depth)
+    }
+
+    /// World space position of the surface at the center of `pixel`, or
+    /// `None` if nothing was rendered there.
+    pub fn world_position(&self, pixel: UVec2) -> Option<Vec3> {
+        let depth = self.depth(pixel)?;
+
+        let uv = (pixel.as_vec2() + 0.5) / self.resolution.as_vec2();
+        let ndc = Vec2::new(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0);
+
+        // Two points on the ray through the pixel, in view space. Depth is
+        // reversed, so the near plane is at 1.
+        let inv_proj = self.proj.inverse();
+        let near = inv_proj.project_point3(ndc.extend(1.0));
+        let middle = inv_proj.project_point3(ndc.extend(0.5));
+
+        // The view space depth changes linearly along the ray, whether the
+        // projection is perspective or orthographic.
+        let t = (depth - near.z.abs()) / (middle.z.abs() - near.z.abs());
+        let view_position = near + (middle - near) * t;
+
+        Some(self.view.inverse().transform_point3(view_position))
+    }
+}
+
+/// Buffers of one output resolution.
+struct DepthReadbackBuffers {
+    resolution: UVec2,
+    camera: Buffer,
+    depths: Buffer,
+    /// Holds the camera, followed by the depths.
+    readback: Arc<Buffer>,
+}
+
+impl DepthReadbackBuffers {
+    fn new(device: &Device, resolution: UVec2) -> Self {
+        let depths_size = resolution.x as u64 * resolution.y as u64 * 4;
+
+        let camera = device.create_buffer(&BufferDescriptor {
+            label: Some("depth readback camera"),
+            size: DepthReadbackCamera::SHADER_SIZE.get(),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST | BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let depths = device.create_buffer(&BufferDescriptor {
+            label: Some("depth readback depths"),
+            size: depths_size,
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let readback = Arc::new(device.create_buffer(&BufferDescriptor {
+            label: Some("depth readback"),
+            size: DepthReadbackCamera::SHADER_SIZE.get() + depths_size,
+            usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        }));
+
+        Self { resolution, camera, depths, readback }
+    }
+}
+
+/// Routine reading back the linearized depth of the viewport camera.
+///
+/// See module for documentation.
+pub struct DepthReader {
+    bgl_s1: BindGroupLayout,
+    pipeline_s1: ComputePipeline,
+    bgl_s4: BindGroupLayout,
+    pipeline_s4: ComputePipeline,
+    buffers: Mutex<Option<DepthReadbackBuffers>>,
+}
+
+fn depth_pipeline(
+    device: &Device,
+    spp: &ShaderPreProcessor,
+    samples: SampleCount,
+) -> (BindGroupLayout, ComputePipeline) {
+    let bgl = BindGroupLayoutBuilder::new()
+        .append(
+            ShaderStages::COMPUTE,
+            BindingType::Texture {
+                sample_type: TextureSampleType::Depth,
+                view_dimension: TextureViewDimension::D2,
+                multisampled: samples != SampleCount::One,
+            },
+            None,
+        )
+        .append_buffer(ShaderStages::COMPUTE, BufferBindingType::Uniform, false, DepthReadbackCamera::SHADER_SIZE.get())
+        .append_buffer(ShaderStages::COMPUTE, BufferBindingType::Storage { read_only: false }, false, 4)
+        .build(device, Some("depth readback bgl"));
+
+    let module = device.create_shader_module(ShaderModuleDescriptor {
+        label: Some("depth readback"),
+        source: ShaderSource::Wgsl(Cow::Owned(
+            spp.render_shader(
+                "rend3-routine/depth_readback.wgsl",
+                &serde_json::json!({"SAMPLES": samples as u8}),
+                None,
+            )
+            .unwrap(),
+        )),
+    });
+
+    let pll = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+        label: Some("depth readback"),
+        bind_group_layouts: &[&bgl],
+        push_constant_ranges: &[],
+    });
+
+    let pipeline = device.create_compute_pipeline(&ComputePipelineDescriptor {
+        label: Some("depth readback"),
+        layout: Some(&pll),
+        module: &module,
+        entry_point: "main",
+    });
+
+    (bgl, pipeline)
+}
+
+impl DepthReader {
+    pub fn new(device: &Device, spp: &ShaderPreProcessor) -> Self {
+        profiling::scope!("DepthReader::new");
+
+        let (bgl_s1, pipeline_s1) = depth_pipeline(device, spp, SampleCount::One);
+        let (bgl_s4, pipeline_s4) = depth_pipeline(device, spp, SampleCount::Four);
+
+        Self { bgl_s1, pipeline_s1, bgl_s4, pipeline_s4, buffers: Mutex::new(None) }
+    }
+
+    /// Copies the linearized `depth`, which has the given resolution and
+    /// sample count, into the readback buffer.
+    pub fn add_to_graph<'node>(
+        &'node self,
+        graph: &mut RenderGraph<'node>,
+        depth: RenderTargetHandle,
+        samples: SampleCount,
+        resolution: UVec2,
+    ) {
+        let mut builder = graph.add_node("Depth Readback");
+        builder.add_side_effect();
+
+        let depth_handle =
+            builder.add_render_target(depth.set_aspect(TextureAspect::DepthOnly), NodeResourceUsage::Input);
+
+        builder.build(move |mut ctx| {
+            let encoder = ctx.encoder_or_pass.take_encoder();
+            let depth = ctx.graph_data.get_render_target(depth_handle);
+
+            profiling::scope!("depth readback");
+
+            let mut buffers = self.buffers.lock();
+            let buffers = match &mut *buffers {
+                Some(buffers) if buffers.resolution == resolution => buffers,
+                buffers => buffers.insert(DepthReadbackBuffers::new(&ctx.renderer.device, resolution)),
+            };
+
+            let camera_state = &ctx.data_core.viewport_camera_state;
+            let camera = DepthReadbackCamera {
+                view: camera_state.view(),
+                proj: camera_state.proj(),
+                inv_proj: camera_state.proj().inverse(),
+                resolution,
+            };
+            let mut camera_data = UniformBuffer::new(Vec::new());
+            camera_data.write(&camera).unwrap();
+            ctx.renderer.queue.write_buffer(&buffers.camera, 0, &camera_data.into_inner());
+
+            let (bgl, pipeline) = match samples {
+                SampleCount::One => (&self.bgl_s1, &self.pipeline_s1),
+                SampleCount::Four => (&self.bgl_s4, &self.pipeline_s4),
+            };
+            let bg = BindGroupBuilder::new()
+                .append_texture_view(depth)
+                .append_buffer(&buffers.camera)
+                .append_buffer(&buffers.depths)
+                .build(&ctx.renderer.device, Some("depth readback bg"), bgl);
+
+            let mut cpass = encoder
+                .begin_compute_pass(&ComputePassDescriptor { label: Some("Depth Readback"), timestamp_writes: None });
+            cpass.set_pipeline(pipeline);
+            cpass.set_bind_group(0, &bg, &[]);
+            cpass.dispatch_workgroups(
+                div_round_up(resolution.x, WORKGROUP_SIZE),
+                div_round_up(resolution.y, WORKGROUP_SIZE),
+                1,
+            );
+            drop(cpass);
+
+            let camera_size = DepthReadbackCamera::SHADER_SIZE.get();
+            encoder.copy_buffer_to_buffer(&buffers.camera, 0, &buffers.readback, 0, camera_size);
+            encoder.copy_buffer_to_buffer(&buffers.depths, 0, &buffers.readback, camera_size, buffers.depths.size());
+        });
+    }
+
+    /// Reads back the depth of the last submitted frame which read it, or
+    /// `None` if no frame has.
+    pub async fn read(&self, renderer: &Renderer) -> Result<Option<DepthReadback>, ReadbackError> {
+        let Some(readback) = self.buffers.lock().as_ref().map(|buffers| Arc::clone(&buffers.readback)) else {
+            return Ok(None);
+        };
+        let data = read_buffer(&renderer.device, &readback).await?;
+
+        let (camera_data, depth_data) = data.split_at(DepthReadbackCamera::SHADER_SIZE.get() as usize);
+        let camera: DepthReadbackCamera = UniformBuffer::new(camera_data).create().unwrap();
+
+        Ok(Some(DepthReadback {
+            resolution: camera.resolution,
+            view: camera.view,
+            proj: camera.proj,
+            depths: depth_data.chunks_exact(4).map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap())).collect(),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{Mat4, UVec2, Vec3};
+
+    use super::DepthReadback;
+
+    fn readback(view: Mat4, proj: Mat4, depth: f32) -> DepthReadback {
+        DepthReadback { resolution: UVec2::ONE, view, proj, depths: vec![depth] }
+    }
+
+    #[test]
+    fn world_position() {
+        let view = Mat4::look_at_lh(Vec3::new(1.0, 2.0, 3.0), Vec3::new(1.0, 2.0, 10.0), Vec3::Y);
+        let perspective = Mat4::perspective_infinite_reverse_lh(1.0, 1.0, 0.1);
+        let orthographic = Mat4::orthographic_lh(-1.0, 1.0, -1.0, 1.0, 100.0, 0.0);
+
+        for proj in [perspective, orthographic] {
+            let position = readback(view, proj, 5.0).world_position(UVec2::ZERO).unwrap();
+            assert!(position.abs_diff_eq(Vec3::new(1.0, 2.0, 8.0), 1e-4), "{position}");
+        }
+    }
+
+    #[test]
+    fn nothing_rendered() {
+        let readback = readback(Mat4::IDENTITY, Mat4::perspective_infinite_reverse_lh(1.0, 1.0, 0.1), 0.0);
+        assert_eq!(readback.depth(UVec2::ZERO), None);
+        assert_eq!(readback.world_position(UVec2::ZERO), None);
+        assert_eq!(readback.depth(UVec2::ONE), None);
+    }
+}
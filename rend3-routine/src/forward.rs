@@ -2,26 +2,26 @@
 //!
 //! Will default to the PBR shader code if custom code is not specified.
 
-use std::{marker::PhantomData, sync::Arc};
+use std::{collections::HashMap, marker::PhantomData, ops::Range, sync::Arc};
 
 use arrayvec::ArrayVec;
 use encase::ShaderSize;
 use rend3::{
     graph::{self, DataHandle, NodeResourceUsage, RenderGraph, RenderPassTargets},
     types::{GraphDataHandle, Material, SampleCount},
-    util::{bind_merge::BindGroupBuilder, typedefs::FastHashMap},
-    ProfileData, Renderer, RendererDataCore, RendererProfile, ShaderPreProcessor,
+    util::{bind_group_cache::BindGroupCache, bind_merge::BindGroupBuilder, typedefs::FastHashMap},
+    ProfileData, Renderer, RendererDataCore, RendererProfile, ShaderPreProcessor, INTERNAL_SHADOW_DEPTH_FORMAT,
 };
 use serde::Serialize;
 use wgpu::{
-    BindGroup, BindGroupLayout, ColorTargetState, ColorWrites, CompareFunction, DepthBiasState, DepthStencilState,
-    FragmentState, IndexFormat, MultisampleState, PipelineLayoutDescriptor, PolygonMode, PrimitiveState,
-    PrimitiveTopology, RenderPipeline, RenderPipelineDescriptor, ShaderModule, StencilState, TextureFormat,
-    VertexState,
+    BindGroup, BindGroupLayout, BlendComponent, BlendFactor, BlendOperation, BlendState, ColorTargetState, ColorWrites,
+    CompareFunction, DepthBiasState, DepthStencilState, Features, FragmentState, IndexFormat, MultisampleState,
+    PipelineLayoutDescriptor, PolygonMode, PrimitiveState, PrimitiveTopology, RenderPipeline, RenderPipelineDescriptor,
+    ShaderModule, StencilState, TextureFormat, VertexState,
 };
 
 use crate::{
-    common::{CameraSpecifier, PerMaterialArchetypeInterface, WholeFrameInterfaces},
+    common::{CameraSpecifier, PerMaterialArchetypeInterface, WholeFrameInterfaces, DEPTH_FORMAT},
     culling::{self, CullingBufferMap, DrawCall, DrawCallSet, InputOutputPartition},
 };
 
@@ -31,10 +31,20 @@ struct ForwardPreprocessingArguments {
     vertex_array_counts: u32,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum RoutineType {
+    /// Depth only, rendering shadow maps.
     Depth,
+    /// Depth only, rendering the viewport's depth before the forward pass so
+    /// it only shades visible fragments.
+    DepthPrepass,
     Forward,
+    /// Forward rendering onto the depth written by a [`RoutineType::DepthPrepass`].
+    /// Only fragments exactly at the prepass depth are shaded.
+    ForwardPrepassed,
+    /// Writes the index of every object's handle into an `R32Uint` target, for
+    /// picking objects.
+    ObjectId,
 }
 
 pub struct ShaderModulePair<'a> {
@@ -59,6 +69,7 @@ impl DeclaredCullingOutput {
     }
 }
 
+#[derive(Clone, Copy)]
 pub enum CullingSource {
     /// We are rendering the first pass with the predicted triangles from last frame.
     ///
@@ -92,13 +103,28 @@ pub struct ForwardRoutineCreateArgs<'a, M> {
     pub interfaces: &'a WholeFrameInterfaces,
     pub per_material: &'a PerMaterialArchetypeInterface<M>,
     pub material_key: u64,
+    /// Bits of material keys ignored when matching them against
+    /// `material_key`, letting one routine render several kinds of materials.
+    /// The ignored bits must be the lowest ones, so the matching materials are
+    /// drawn consecutively. Usually 0.
+    pub ignored_key_bits: u64,
 
     pub routine_type: RoutineType,
     pub shaders: ShaderModulePair<'a>,
+    /// Stencil test and operations of the pipelines. Only used when the
+    /// viewport has a stencil, see
+    /// [`BaseRenderGraph::with_stencil`](crate::base::BaseRenderGraph::with_stencil),
+    /// and ignored by shadow and object id routines. Usually
+    /// `StencilState::default()`, which leaves the stencil untouched.
+    pub stencil: StencilState,
 
     pub culling_buffer_map_handle: GraphDataHandle<CullingBufferMap>,
 
     pub extra_bgls: &'a [&'a BindGroupLayout],
+    /// Fragment entry point of `shaders.fs_module` outputting a solid color,
+    /// used by the wireframe pipelines. Wireframe pipelines are only created if
+    /// this is set and the device supports [`Features::POLYGON_MODE_LINE`].
+    pub wireframe_fs_entry: Option<&'a str>,
     #[allow(clippy::type_complexity)]
     pub descriptor_callback: Option<&'a dyn Fn(&mut RenderPipelineDescriptor<'_>, &mut [Option<ColorTargetState>])>,
 }
@@ -129,15 +155,32 @@ pub struct ForwardRoutineArgs<'a, 'node, M> {
     pub culling_source: CullingSource,
     pub samples: SampleCount,
     pub renderpass: RenderPassTargets,
+    /// Render only the edges of triangles. Falls back to filled triangles if
+    /// the routine has no wireframe pipelines.
+    pub wireframe: bool,
+    /// Add up the fragments of every triangle regardless of depth, for
+    /// [`DebugView::Overdraw`](crate::uniforms::DebugView::Overdraw). Only
+    /// forward routines have overdraw pipelines, others ignore this.
+    pub overdraw: bool,
+    /// Value the stencil is tested against and written with, see
+    /// [`ForwardRoutineCreateArgs::stencil`].
+    pub stencil_reference: u32,
 }
 
 /// A set of pipelines for rendering a specific combination of a material.
 pub struct ForwardRoutine<M: Material> {
     pipeline_s1: RenderPipeline,
     pipeline_s4: RenderPipeline,
+    wireframe_s1: Option<RenderPipeline>,
+    wireframe_s4: Option<RenderPipeline>,
+    overdraw_s1: Option<RenderPipeline>,
+    overdraw_s4: Option<RenderPipeline>,
     material_key: u64,
+    ignored_key_bits: u64,
     culling_buffer_map_handle: GraphDataHandle<CullingBufferMap>,
     draw_call_set_cache_handle: GraphDataHandle<FastHashMap<CameraSpecifier, Arc<DrawCallSet>>>,
+    /// Per-material bind groups of every camera, reused across frames.
+    per_material_bgs: BindGroupCache,
     _phantom: PhantomData<M>,
 }
 impl<M: Material> ForwardRoutine<M> {
@@ -161,8 +204,10 @@ impl<M: Material> ForwardRoutine<M> {
 
         let mut bgls: ArrayVec<&BindGroupLayout, 8> = ArrayVec::new();
         bgls.push(match args.routine_type {
-            RoutineType::Depth => &args.interfaces.depth_uniform_bgl,
-            RoutineType::Forward => &args.interfaces.forward_uniform_bgl,
+            RoutineType::Depth | RoutineType::DepthPrepass | RoutineType::ObjectId => {
+                &args.interfaces.depth_uniform_bgl
+            }
+            RoutineType::Forward | RoutineType::ForwardPrepassed => &args.interfaces.forward_uniform_bgl,
         });
         bgls.push(&args.per_material.bgl);
         if args.renderer.profile == RendererProfile::GpuDriven {
@@ -178,11 +223,23 @@ impl<M: Material> ForwardRoutine<M> {
             push_constant_ranges: &[],
         });
 
+        let wireframe =
+            args.wireframe_fs_entry.is_some() && args.renderer.features.contains(Features::POLYGON_MODE_LINE);
+
+        let overdraw = args.routine_type == RoutineType::Forward;
+
+        let build = |samples, variant| build_forward_pipeline_inner(&pll, &args, samples, variant);
         Self {
-            pipeline_s1: build_forward_pipeline_inner(&pll, &args, SampleCount::One),
-            pipeline_s4: build_forward_pipeline_inner(&pll, &args, SampleCount::Four),
+            pipeline_s1: build(SampleCount::One, PipelineVariant::Shaded),
+            pipeline_s4: build(SampleCount::Four, PipelineVariant::Shaded),
+            wireframe_s1: wireframe.then(|| build(SampleCount::One, PipelineVariant::Wireframe)),
+            wireframe_s4: wireframe.then(|| build(SampleCount::Four, PipelineVariant::Wireframe)),
+            overdraw_s1: overdraw.then(|| build(SampleCount::One, PipelineVariant::Overdraw)),
+            overdraw_s4: overdraw.then(|| build(SampleCount::Four, PipelineVariant::Overdraw)),
             material_key: args.material_key,
+            ignored_key_bits: args.ignored_key_bits,
             draw_call_set_cache_handle: args.renderer.add_graph_data(FastHashMap::default()),
+            per_material_bgs: BindGroupCache::new(),
             culling_buffer_map_handle: args.culling_buffer_map_handle,
             _phantom: PhantomData,
         }
@@ -260,18 +317,30 @@ impl<M: Material> ForwardRoutine<M> {
                     .append_buffer(&ctx.eval_output.mesh_buffer)
                     .append_buffer(&draw_call_set.per_camera_uniform)
                     .append_buffer(ctx.data_core.material_manager.archetype_view::<M>().buffer())
-                    .build(&ctx.renderer.device, Some("Per-Material BG"), &args.binding_data.per_material_bgl.bgl),
+                    .build_cached(
+                        &self.per_material_bgs,
+                        &ctx.renderer.device,
+                        ctx.eval_output.frame,
+                        Some("Per-Material BG"),
+                        &args.binding_data.per_material_bgl.bgl,
+                    ),
             );
 
-            let pipeline = match args.samples {
-                SampleCount::One => &self.pipeline_s1,
-                SampleCount::Four => &self.pipeline_s4,
+            let (pipeline, wireframe_pipeline, overdraw_pipeline) = match args.samples {
+                SampleCount::One => (&self.pipeline_s1, &self.wireframe_s1, &self.overdraw_s1),
+                SampleCount::Four => (&self.pipeline_s4, &self.wireframe_s4, &self.overdraw_s4),
+            };
+            let pipeline = match (wireframe_pipeline, overdraw_pipeline) {
+                (Some(wireframe_pipeline), _) if args.wireframe => wireframe_pipeline,
+                (_, Some(overdraw_pipeline)) if args.overdraw => overdraw_pipeline,
+                _ => pipeline,
             };
             rpass.set_index_buffer(
                 index_buffer.slice(culling_buffers.index_buffer.partition_slice(partition)),
                 IndexFormat::Uint32,
             );
             rpass.set_pipeline(pipeline);
+            rpass.set_stencil_reference(args.stencil_reference);
             rpass.set_bind_group(0, whole_frame_uniform_bg, &[]);
             if let Some(v) = args.binding_data.extra_bgs {
                 for (idx, bg) in v.iter().enumerate() {
@@ -283,18 +352,31 @@ impl<M: Material> ForwardRoutine<M> {
             }
 
             // If there are no draw calls for this material, just bail.
-            let Some(range) = draw_call_set.material_key_ranges.get(&self.material_key) else {
+            let Some(range) =
+                matching_draw_calls(&draw_call_set.material_key_ranges, self.material_key, self.ignored_key_bits)
+            else {
                 return;
             };
 
-            for (range_relative_idx, call) in draw_call_set.draw_calls[range.clone()].iter().enumerate() {
+            let cpu_driven = ctx.renderer.profile.is_cpu_driven();
+            let draw_calls = &draw_call_set.draw_calls[range.clone()];
+            // Consecutive draw calls sharing all their bindings are issued as a single multi draw.
+            let runs = if ctx.renderer.features.contains(Features::MULTI_DRAW_INDIRECT) {
+                shared_binding_runs(
+                    draw_calls.iter().map(|call| (call.batch_index, cpu_driven.then_some(call.bind_group_index))),
+                )
+            } else {
+                (0..draw_calls.len()).map(|idx| idx..idx + 1).collect()
+            };
+
+            for run in runs {
                 // Help RA out
-                let call: &DrawCall = call;
+                let call: &DrawCall = &draw_calls[run.start];
                 // Add the base of the range to the index to get the actual index
-                let idx = range_relative_idx + range.start;
+                let idx = run.start + range.start;
 
                 // If we're in cpu driven mode, we need to update the texture bind group.
-                if ctx.renderer.profile.is_cpu_driven() {
+                if cpu_driven {
                     rpass.set_bind_group(
                         2,
                         ctx.data_core.material_manager.texture_bind_group(call.bind_group_index),
@@ -306,27 +388,75 @@ impl<M: Material> ForwardRoutine<M> {
                     per_material_bg,
                     &[call.batch_index * culling::ShaderBatchData::SHADER_SIZE.get() as u32],
                 );
-                rpass.draw_indexed_indirect(
-                    draw_call_buffer,
-                    culling_buffers.draw_call_buffer.element_offset(partition, idx as u64),
-                );
+                let offset = culling_buffers.draw_call_buffer.element_offset(partition, idx as u64);
+                if run.len() == 1 {
+                    rpass.draw_indexed_indirect(draw_call_buffer, offset);
+                } else {
+                    rpass.multi_draw_indexed_indirect(draw_call_buffer, offset, run.len() as u32);
+                }
             }
         });
     }
 }
 
+/// Range of the draw calls of all materials whose key equals `key`, apart from
+/// the `ignored_bits`. Draw calls are sorted by key, so the range covers
+/// exactly the matching materials when the ignored bits are the lowest ones.
+fn matching_draw_calls(ranges: &HashMap<u64, Range<usize>>, key: u64, ignored_bits: u64) -> Option<Range<usize>> {
+    if ignored_bits == 0 {
+        return ranges.get(&key).cloned();
+    }
+    ranges
+        .iter()
+        .filter(|&(&material_key, _)| material_key & !ignored_bits == key)
+        .map(|(_, range)| range.clone())
+        .reduce(|a, b| a.start.min(b.start)..a.end.max(b.end))
+}
+
+/// Splits a list of draw calls into runs of consecutive calls with equal
+/// bindings, which can be drawn with a single multi draw.
+fn shared_binding_runs<K: PartialEq>(bindings: impl Iterator<Item = K>) -> Vec<Range<usize>> {
+    let mut runs: Vec<Range<usize>> = Vec::new();
+    let mut previous = None;
+    for (idx, binding) in bindings.enumerate() {
+        match runs.last_mut() {
+            Some(run) if previous.as_ref() == Some(&binding) => run.end = idx + 1,
+            _ => runs.push(idx..idx + 1),
+        }
+        previous = Some(binding);
+    }
+    runs
+}
+
+/// Which of a routine's pipelines to build.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PipelineVariant {
+    Shaded,
+    Wireframe,
+    /// Fragments are added up and never depth tested.
+    Overdraw,
+}
+
 fn build_forward_pipeline_inner<M: Material>(
     pll: &wgpu::PipelineLayout,
     args: &ForwardRoutineCreateArgs<'_, M>,
     samples: SampleCount,
+    variant: PipelineVariant,
 ) -> RenderPipeline {
+    let wireframe = variant == PipelineVariant::Wireframe;
     let mut render_targets: ArrayVec<_, 1> = ArrayVec::new();
-    if matches!(args.routine_type, RoutineType::Forward) {
-        render_targets.push(Some(ColorTargetState {
+    match args.routine_type {
+        RoutineType::Forward | RoutineType::ForwardPrepassed => render_targets.push(Some(ColorTargetState {
             format: TextureFormat::Rgba16Float,
             blend: None,
             write_mask: ColorWrites::all(),
-        }));
+        })),
+        RoutineType::ObjectId => render_targets.push(Some(ColorTargetState {
+            format: TextureFormat::R32Uint,
+            blend: None,
+            write_mask: ColorWrites::all(),
+        })),
+        RoutineType::Depth | RoutineType::DepthPrepass => {}
     }
     let mut desc = RenderPipelineDescriptor {
         label: Some(args.name),
@@ -338,27 +468,51 @@ fn build_forward_pipeline_inner<M: Material>(
             front_face: args.renderer.handedness.into(),
             cull_mode: Some(match args.routine_type {
                 RoutineType::Depth => wgpu::Face::Front,
-                RoutineType::Forward => wgpu::Face::Back,
+                RoutineType::DepthPrepass
+                | RoutineType::Forward
+                | RoutineType::ForwardPrepassed
+                | RoutineType::ObjectId => wgpu::Face::Back,
             }),
             unclipped_depth: false,
-            polygon_mode: PolygonMode::Fill,
+            polygon_mode: if wireframe { PolygonMode::Line } else { PolygonMode::Fill },
             conservative: false,
         },
         depth_stencil: Some(DepthStencilState {
-            format: TextureFormat::Depth32Float,
-            depth_write_enabled: true,
-            depth_compare: CompareFunction::GreaterEqual,
-            stencil: StencilState::default(),
+            format: match args.routine_type {
+                RoutineType::Depth => INTERNAL_SHADOW_DEPTH_FORMAT,
+                RoutineType::ObjectId => DEPTH_FORMAT,
+                RoutineType::DepthPrepass | RoutineType::Forward | RoutineType::ForwardPrepassed => {
+                    args.interfaces.depth_format
+                }
+            },
+            // The prepass already wrote the depth.
+            depth_write_enabled: !matches!(args.routine_type, RoutineType::ForwardPrepassed),
+            depth_compare: match args.routine_type {
+                RoutineType::ForwardPrepassed => CompareFunction::Equal,
+                _ => CompareFunction::GreaterEqual,
+            },
+            stencil: match args.routine_type {
+                RoutineType::Depth | RoutineType::ObjectId => StencilState::default(),
+                RoutineType::DepthPrepass | RoutineType::Forward | RoutineType::ForwardPrepassed => {
+                    args.stencil.clone()
+                }
+            },
             bias: match args.routine_type {
                 // TODO: figure out what to put here
                 RoutineType::Depth => DepthBiasState { constant: 0, slope_scale: 0.0, clamp: 0.0 },
-                RoutineType::Forward => DepthBiasState::default(),
+                RoutineType::DepthPrepass
+                | RoutineType::Forward
+                | RoutineType::ForwardPrepassed
+                | RoutineType::ObjectId => DepthBiasState::default(),
             },
         }),
         multisample: MultisampleState { count: samples as u32, ..Default::default() },
         fragment: Some(FragmentState {
             module: args.shaders.fs_module,
-            entry_point: args.shaders.fs_entry,
+            entry_point: match args.wireframe_fs_entry {
+                Some(entry) if wireframe => entry,
+                _ => args.shaders.fs_entry,
+            },
             targets: &[],
         }),
         multiview: None,
@@ -366,6 +520,45 @@ fn build_forward_pipeline_inner<M: Material>(
     if let Some(desc_callback) = args.descriptor_callback {
         desc_callback(&mut desc, &mut render_targets);
     }
+    if variant == PipelineVariant::Overdraw {
+        // Applied after the callback so blended materials count the same as opaque ones.
+        for target in render_targets.iter_mut().flatten() {
+            let add = BlendComponent {
+                src_factor: BlendFactor::One,
+                dst_factor: BlendFactor::One,
+                operation: BlendOperation::Add,
+            };
+            target.blend = Some(BlendState { color: add, alpha: add });
+        }
+        if let Some(depth_stencil) = &mut desc.depth_stencil {
+            depth_stencil.depth_write_enabled = true;
+            depth_stencil.depth_compare = CompareFunction::Always;
+        }
+    }
     desc.fragment.as_mut().unwrap().targets = &render_targets;
     args.renderer.device.create_render_pipeline(&desc)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{matching_draw_calls, shared_binding_runs};
+
+    #[test]
+    fn ignored_key_bits() {
+        let ranges = HashMap::from([(0x100, 0..2), (0x101, 2..5), (0x102, 5..6), (0x200, 6..8)]);
+        assert_eq!(matching_draw_calls(&ranges, 0x100, 0), Some(0..2));
+        assert_eq!(matching_draw_calls(&ranges, 0x100, 0xFF), Some(0..6));
+        assert_eq!(matching_draw_calls(&ranges, 0x200, 0xFF), Some(6..8));
+        assert_eq!(matching_draw_calls(&ranges, 0x300, 0xFF), None);
+    }
+
+    #[test]
+    fn multi_draw_runs() {
+        assert_eq!(shared_binding_runs(std::iter::empty::<u32>()), []);
+        assert_eq!(shared_binding_runs([0, 0, 0].into_iter()), vec![0..3]);
+        assert_eq!(shared_binding_runs([0, 0, 1, 0, 0].into_iter()), [0..2, 2..3, 3..5]);
+        assert_eq!(shared_binding_runs([(0, Some(1)), (0, Some(1)), (0, Some(2))].into_iter()), [0..2, 2..3]);
+    }
+}
@@ -0,0 +1,151 @@
+//! Fast approximate anti-aliasing, a cheap alternative to multisampling.
+//!
+//! FXAA runs on the tonemapped image, blurring pixels along the edges it finds
+//! in the image's brightness. It costs a single fullscreen pass regardless of
+//! scene complexity, but also softens textures slightly.
+//!
+//! Every [`TonemappingRoutine`](crate::tonemapping::TonemappingRoutine) holds
+//! an [`FxaaRoutine`] for its output format. The base graph tonemaps into an
+//! intermediate image and runs it when
+//! [`BaseRenderGraphSettings::anti_alias`](crate::base::BaseRenderGraphSettings::anti_alias)
+//! is [`AntiAlias::Fxaa`]. It can be combined with multisampling, but is
+//! usually used instead of it.
+
+use std::borrow::Cow;
+
+use glam::Vec4;
+use rend3::{
+    graph::{DataHandle, NodeResourceUsage, RenderGraph, RenderPassTarget, RenderPassTargets, RenderTargetHandle},
+    util::bind_merge::{BindGroupBuilder, BindGroupLayoutBuilder},
+    ShaderConfig, ShaderPreProcessor,
+};
+use wgpu::{
+    BindGroup, BindGroupLayout, BindingType, ColorTargetState, ColorWrites, Device, FragmentState, MultisampleState,
+    PipelineLayoutDescriptor, PrimitiveState, RenderPipeline, RenderPipelineDescriptor, ShaderModuleDescriptor,
+    ShaderSource, ShaderStages, TextureFormat, TextureSampleType, TextureViewDimension, VertexState,
+};
+
+use crate::common::WholeFrameInterfaces;
+
+/// Anti-aliasing applied to the tonemapped image.
+///
+/// This is unrelated to multisampling, which is chosen with the sample count
+/// of the output.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum AntiAlias {
+    #[default]
+    None,
+    /// Fast approximate anti-aliasing.
+    Fxaa,
+}
+
+/// Routine applying FXAA.
+///
+/// See module for documentation.
+pub struct FxaaRoutine {
+    bgl: BindGroupLayout,
+    pipeline: RenderPipeline,
+}
+
+impl FxaaRoutine {
+    /// Creates the routine, both reading and writing images of `output_format`.
+    pub fn new(
+        device: &Device,
+        spp: &ShaderPreProcessor,
+        interfaces: &WholeFrameInterfaces,
+        output_format: TextureFormat,
+    ) -> Self {
+        profiling::scope!("FxaaRoutine::new");
+
+        let bgl = BindGroupLayoutBuilder::new()
+            .append(
+                ShaderStages::FRAGMENT,
+                BindingType::Texture {
+                    sample_type: TextureSampleType::Float { filterable: true },
+                    view_dimension: TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                None,
+            )
+            .build(device, Some("fxaa bgl"));
+
+        let module = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("fxaa"),
+            source: ShaderSource::Wgsl(Cow::Owned(
+                spp.render_shader("rend3-routine/fxaa.wgsl", &ShaderConfig::default(), None).unwrap(),
+            )),
+        });
+
+        // Sampling an srgb image decodes it, so the shader needs to know to encode it again.
+        let fs_entry_point = if output_format.is_srgb() { "fs_main_scene" } else { "fs_main_monitor" };
+
+        let pll = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("fxaa pass"),
+            bind_group_layouts: &[&interfaces.forward_uniform_bgl, &bgl],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("fxaa pass"),
+            layout: Some(&pll),
+            vertex: VertexState { module: &module, entry_point: "vs_main", buffers: &[] },
+            primitive: PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: MultisampleState::default(),
+            fragment: Some(FragmentState {
+                module: &module,
+                entry_point: fs_entry_point,
+                targets: &[Some(ColorTargetState {
+                    format: output_format,
+                    blend: None,
+                    write_mask: ColorWrites::all(),
+                })],
+            }),
+            multiview: None,
+        });
+
+        Self { bgl, pipeline }
+    }
+
+    /// Anti-aliases `src` into `dst`, which must have the same resolution.
+    pub fn add_to_graph<'node>(
+        &'node self,
+        graph: &mut RenderGraph<'node>,
+        src: RenderTargetHandle,
+        dst: RenderTargetHandle,
+        forward_uniform_bg: DataHandle<BindGroup>,
+    ) {
+        let mut builder = graph.add_node("FXAA");
+
+        let input_handle = builder.add_render_target(src, NodeResourceUsage::Input);
+
+        let rpass_handle = builder.add_renderpass(
+            RenderPassTargets {
+                targets: vec![RenderPassTarget { color: dst, clear: Vec4::ZERO, resolve: None }],
+                depth_stencil: None,
+            },
+            NodeResourceUsage::InputOutput,
+        );
+
+        let forward_uniform_handle = builder.add_data(forward_uniform_bg, NodeResourceUsage::Input);
+
+        builder.build(move |mut ctx| {
+            let rpass = ctx.encoder_or_pass.take_rpass(rpass_handle);
+            let forward_uniform_bg = ctx.graph_data.get_data(ctx.temps, forward_uniform_handle).unwrap();
+            let src = ctx.graph_data.get_render_target(input_handle);
+
+            profiling::scope!("fxaa");
+
+            let src_bg = ctx.temps.add(BindGroupBuilder::new().append_texture_view(src).build(
+                &ctx.renderer.device,
+                Some("fxaa src bg"),
+                &self.bgl,
+            ));
+
+            rpass.set_pipeline(&self.pipeline);
+            rpass.set_bind_group(0, forward_uniform_bg, &[]);
+            rpass.set_bind_group(1, src_bg, &[]);
+            rpass.draw(0..3, 0..1);
+        });
+    }
+}
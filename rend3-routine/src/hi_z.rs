@@ -12,8 +12,8 @@ use wgpu::{
     BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry,
     BindingResource, BindingType, CompareFunction, DepthBiasState, DepthStencilState, Extent3d, FragmentState,
     MultisampleState, PipelineLayoutDescriptor, PrimitiveState, RenderPipeline, RenderPipelineDescriptor,
-    ShaderModuleDescriptor, ShaderStages, StencilState, TextureDimension, TextureFormat, TextureSampleType,
-    TextureViewDimension, VertexState,
+    ShaderModuleDescriptor, ShaderStages, StencilState, TextureAspect, TextureDimension, TextureFormat,
+    TextureSampleType, TextureViewDimension, VertexState,
 };
 
 use crate::base::DepthTargets;
@@ -26,7 +26,7 @@ pub struct HiZRoutine {
 }
 
 impl HiZRoutine {
-    pub fn new(renderer: &Renderer, spp: &ShaderPreProcessor) -> Self {
+    pub fn new(renderer: &Renderer, spp: &ShaderPreProcessor, depth_format: TextureFormat) -> Self {
         let resolve_source = spp
             .render_shader("rend3-routine/resolve_depth_min.wgsl", &serde_json::json!({"SAMPLES": 4}), None)
             .unwrap();
@@ -87,7 +87,7 @@ impl HiZRoutine {
             vertex: VertexState { module: &resolve_sm, entry_point: "vs_main", buffers: &[] },
             primitive: PrimitiveState::default(),
             depth_stencil: Some(DepthStencilState {
-                format: TextureFormat::Depth32Float,
+                format: depth_format,
                 depth_write_enabled: true,
                 depth_compare: CompareFunction::Always,
                 stencil: StencilState::default(),
@@ -104,7 +104,7 @@ impl HiZRoutine {
             vertex: VertexState { module: &downscale_sm, entry_point: "vs_main", buffers: &[] },
             primitive: PrimitiveState::default(),
             depth_stencil: Some(DepthStencilState {
-                format: TextureFormat::Depth32Float,
+                format: depth_format,
                 depth_write_enabled: true,
                 depth_compare: CompareFunction::Always,
                 stencil: StencilState::default(),
@@ -172,7 +172,8 @@ impl HiZRoutine {
         if let Some(multi_sample) = depth_targets.multi_sample {
             let mut node = graph.add_node("HiZ Resolve");
 
-            let source = node.add_render_target(multi_sample, NodeResourceUsage::Output);
+            let source =
+                node.add_render_target(multi_sample.set_aspect(TextureAspect::DepthOnly), NodeResourceUsage::Output);
 
             let rpass_handle = node.add_renderpass(
                 RenderPassTargets {
@@ -209,7 +210,8 @@ impl HiZRoutine {
                 depth_targets
                     .single_sample_mipped
                     .set_mips(src_mip..src_mip + 1)
-                    .set_viewport(ViewportRect::from_size(UVec2::new(src_extent.width, src_extent.height))),
+                    .set_viewport(ViewportRect::from_size(UVec2::new(src_extent.width, src_extent.height)))
+                    .set_aspect(TextureAspect::DepthOnly),
                 NodeResourceUsage::Input,
             );
 
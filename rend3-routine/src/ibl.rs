@@ -0,0 +1,330 @@
+//! Image based lighting from the background cubemap of the skybox.
+//!
+//! The environment is prefiltered once into a cubemap whose mips hold its
+//! reflection off increasingly rough surfaces, and into an irradiance cubemap
+//! holding the diffuse light. Together with a lookup table of the split-sum
+//! BRDF, they replace the flat ambient color on objects receiving ambient
+//! light.
+
+use std::borrow::Cow;
+
+use rend3::{
+    util::bind_merge::{BindGroupBuilder, BindGroupLayoutBuilder},
+    Renderer, ShaderConfig, ShaderPreProcessor,
+};
+use wgpu::{
+    AddressMode, BindGroup, BindGroupLayout, BindingType, Color, ColorTargetState, ColorWrites, CommandEncoder,
+    CommandEncoderDescriptor, Device, Extent3d, FilterMode, FragmentState, FrontFace, LoadOp, MultisampleState,
+    Operations, PipelineLayout, PipelineLayoutDescriptor, PolygonMode, PrimitiveState, PrimitiveTopology,
+    RenderPassColorAttachment, RenderPassDescriptor, RenderPipeline, RenderPipelineDescriptor, Sampler,
+    SamplerBindingType, SamplerDescriptor, ShaderModule, ShaderModuleDescriptor, ShaderSource, ShaderStages, StoreOp,
+    Texture, TextureDescriptor, TextureDimension, TextureFormat, TextureSampleType, TextureUsages, TextureView,
+    TextureViewDescriptor, TextureViewDimension, VertexState,
+};
+
+/// Width and height of the faces of the prefiltered cubemap.
+pub const PREFILTERED_SIZE: u32 = 128;
+/// Mips of the prefiltered cubemap. Mip 0 reflects like a mirror, the last
+/// mip like a surface with a perceptual roughness of 1. Must match ibl.wgsl.
+pub const PREFILTERED_MIPS: u32 = 6;
+/// Width and height of the faces of the irradiance cubemap.
+pub const IRRADIANCE_SIZE: u32 = 32;
+/// Width and height of the BRDF lookup table.
+pub const BRDF_LUT_SIZE: u32 = 64;
+
+const CUBEMAP_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+const BRDF_LUT_FORMAT: TextureFormat = TextureFormat::Rg16Float;
+
+/// Textures the forward shaders sample image based lighting from.
+pub struct EnvironmentMaps {
+    /// Cubemap of the environment reflected off surfaces, rougher in every mip.
+    pub prefiltered: TextureView,
+    /// Cubemap of the diffuse light coming from the environment.
+    pub irradiance: TextureView,
+    /// Scale and bias of the specular reflectance, by the cosine of the view
+    /// angle and the perceptual roughness.
+    pub brdf_lut: TextureView,
+    /// False while there is no environment in the maps, in which case objects
+    /// are lit by the ambient color instead.
+    pub enabled: bool,
+}
+
+impl EnvironmentMaps {
+    /// Maps without an environment, used when there is no skybox.
+    pub fn empty(device: &Device) -> Self {
+        let prefiltered = create_cubemap(device, "empty prefiltered environment", 1, 1);
+        let irradiance = create_cubemap(device, "empty irradiance", 1, 1);
+        let brdf_lut = create_brdf_lut(device, 1);
+
+        Self {
+            prefiltered: cube_view(&prefiltered),
+            irradiance: cube_view(&irradiance),
+            brdf_lut: brdf_lut.create_view(&TextureViewDescriptor::default()),
+            enabled: false,
+        }
+    }
+
+    /// Add the prefiltered cubemap, the irradiance cubemap and the BRDF lookup
+    /// table to the given bind group layout builder.
+    pub fn add_to_bgl(bglb: &mut BindGroupLayoutBuilder) {
+        for view_dimension in [TextureViewDimension::Cube, TextureViewDimension::Cube, TextureViewDimension::D2] {
+            bglb.append(
+                ShaderStages::FRAGMENT,
+                BindingType::Texture {
+                    sample_type: TextureSampleType::Float { filterable: true },
+                    view_dimension,
+                    multisampled: false,
+                },
+                None,
+            );
+        }
+    }
+
+    /// Add the maps to the given bind group builder.
+    pub fn add_to_bg<'a>(&'a self, bgb: &mut BindGroupBuilder<'a>) {
+        bgb.append_texture_view(&self.prefiltered)
+            .append_texture_view(&self.irradiance)
+            .append_texture_view(&self.brdf_lut);
+    }
+}
+
+/// Prefilters environment cubemaps into [`EnvironmentMaps`].
+pub struct IblRoutine {
+    bgl: BindGroupLayout,
+    sampler: Sampler,
+    prefilter_pipeline: RenderPipeline,
+    irradiance_pipeline: RenderPipeline,
+    prefiltered: Texture,
+    irradiance: Texture,
+    maps: EnvironmentMaps,
+}
+
+impl IblRoutine {
+    /// Create the routine, computing the BRDF lookup table.
+    pub fn new(renderer: &Renderer, spp: &ShaderPreProcessor) -> Self {
+        profiling::scope!("IblRoutine::new");
+
+        let device = &renderer.device;
+
+        let bgl = BindGroupLayoutBuilder::new()
+            .append(
+                ShaderStages::FRAGMENT,
+                BindingType::Texture {
+                    sample_type: TextureSampleType::Float { filterable: true },
+                    view_dimension: TextureViewDimension::Cube,
+                    multisampled: false,
+                },
+                None,
+            )
+            .append(ShaderStages::FRAGMENT, BindingType::Sampler(SamplerBindingType::Filtering), None)
+            .build(device, Some("ibl environment bgl"));
+
+        let sampler = device.create_sampler(&SamplerDescriptor {
+            label: Some("ibl environment sampler"),
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            address_mode_w: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            mipmap_filter: FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let sm = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("ibl"),
+            source: ShaderSource::Wgsl(Cow::Owned(
+                spp.render_shader("rend3-routine/ibl.wgsl", &ShaderConfig::default(), None).unwrap(),
+            )),
+        });
+
+        let environment_pll = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("ibl environment pll"),
+            bind_group_layouts: &[&bgl],
+            push_constant_ranges: &[],
+        });
+        let lut_pll = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("brdf lut pll"),
+            bind_group_layouts: &[],
+            push_constant_ranges: &[],
+        });
+
+        let prefilter_pipeline = create_pipeline(device, &sm, &environment_pll, "fs_prefilter", CUBEMAP_FORMAT);
+        let irradiance_pipeline = create_pipeline(device, &sm, &environment_pll, "fs_irradiance", CUBEMAP_FORMAT);
+        let lut_pipeline = create_pipeline(device, &sm, &lut_pll, "fs_brdf_lut", BRDF_LUT_FORMAT);
+
+        let prefiltered = create_cubemap(device, "prefiltered environment", PREFILTERED_SIZE, PREFILTERED_MIPS);
+        let irradiance = create_cubemap(device, "irradiance", IRRADIANCE_SIZE, 1);
+        let brdf_lut = create_brdf_lut(device, BRDF_LUT_SIZE);
+
+        // The lookup table doesn't depend on the environment, so it is only computed once.
+        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor { label: Some("brdf lut") });
+        let lut_view = brdf_lut.create_view(&TextureViewDescriptor::default());
+        draw_to_view(&mut encoder, &lut_view, &lut_pipeline, None, 0);
+        renderer.queue.submit(Some(encoder.finish()));
+
+        let maps = EnvironmentMaps {
+            prefiltered: cube_view(&prefiltered),
+            irradiance: cube_view(&irradiance),
+            brdf_lut: lut_view,
+            enabled: false,
+        };
+
+        Self { bgl, sampler, prefilter_pipeline, irradiance_pipeline, prefiltered, irradiance, maps }
+    }
+
+    /// Prefilter the given cubemap into the maps and enable them.
+    ///
+    /// Environments with a full mip chain give smoother results, as the
+    /// samples of rough reflections are taken from lower resolution mips.
+    pub fn prefilter(&mut self, renderer: &Renderer, environment: &TextureView) {
+        profiling::scope!("Prefilter Environment");
+
+        let bg = BindGroupBuilder::new().append_texture_view(environment).append_sampler(&self.sampler).build(
+            &renderer.device,
+            Some("ibl environment bg"),
+            &self.bgl,
+        );
+
+        let mut encoder =
+            renderer.device.create_command_encoder(&CommandEncoderDescriptor { label: Some("prefilter environment") });
+
+        for mip in 0..PREFILTERED_MIPS {
+            for face in 0..6 {
+                let view = face_view(&self.prefiltered, mip, face);
+                draw_to_view(&mut encoder, &view, &self.prefilter_pipeline, Some(&bg), face + 6 * mip);
+            }
+        }
+        for face in 0..6 {
+            let view = face_view(&self.irradiance, 0, face);
+            draw_to_view(&mut encoder, &view, &self.irradiance_pipeline, Some(&bg), face);
+        }
+
+        renderer.queue.submit(Some(encoder.finish()));
+
+        self.maps.enabled = true;
+    }
+
+    /// Disable the maps, until an environment is prefiltered again.
+    pub fn disable(&mut self) {
+        self.maps.enabled = false;
+    }
+
+    pub fn maps(&self) -> &EnvironmentMaps {
+        &self.maps
+    }
+}
+
+fn create_pipeline(
+    device: &Device,
+    sm: &ShaderModule,
+    pll: &PipelineLayout,
+    fs_entry: &str,
+    format: TextureFormat,
+) -> RenderPipeline {
+    device.create_render_pipeline(&RenderPipelineDescriptor {
+        label: Some(fs_entry),
+        layout: Some(pll),
+        vertex: VertexState { module: sm, entry_point: "vs_main", buffers: &[] },
+        primitive: PrimitiveState {
+            topology: PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: FrontFace::Cw,
+            cull_mode: None,
+            unclipped_depth: false,
+            polygon_mode: PolygonMode::Fill,
+            conservative: false,
+        },
+        depth_stencil: None,
+        multisample: MultisampleState::default(),
+        fragment: Some(FragmentState {
+            module: sm,
+            entry_point: fs_entry,
+            targets: &[Some(ColorTargetState { format, blend: None, write_mask: ColorWrites::all() })],
+        }),
+        multiview: None,
+    })
+}
+
+/// Draws a fullscreen triangle into the view. The instance index tells the
+/// shader which face and mip of a cubemap is being rendered.
+fn draw_to_view(
+    encoder: &mut CommandEncoder,
+    view: &TextureView,
+    pipeline: &RenderPipeline,
+    bg: Option<&BindGroup>,
+    instance: u32,
+) {
+    let mut rpass = encoder.begin_render_pass(&RenderPassDescriptor {
+        label: None,
+        color_attachments: &[Some(RenderPassColorAttachment {
+            view,
+            resolve_target: None,
+            ops: Operations { load: LoadOp::Clear(Color::BLACK), store: StoreOp::Store },
+        })],
+        depth_stencil_attachment: None,
+        timestamp_writes: None,
+        occlusion_query_set: None,
+    });
+
+    rpass.set_pipeline(pipeline);
+    if let Some(bg) = bg {
+        rpass.set_bind_group(0, bg, &[]);
+    }
+    rpass.draw(0..3, instance..instance + 1);
+}
+
+fn create_cubemap(device: &Device, label: &str, size: u32, mips: u32) -> Texture {
+    device.create_texture(&TextureDescriptor {
+        label: Some(label),
+        size: Extent3d { width: size, height: size, depth_or_array_layers: 6 },
+        mip_level_count: mips,
+        sample_count: 1,
+        dimension: TextureDimension::D2,
+        format: CUBEMAP_FORMAT,
+        usage: TextureUsages::TEXTURE_BINDING | TextureUsages::RENDER_ATTACHMENT,
+        view_formats: &[],
+    })
+}
+
+fn create_brdf_lut(device: &Device, size: u32) -> Texture {
+    device.create_texture(&TextureDescriptor {
+        label: Some("brdf lut"),
+        size: Extent3d { width: size, height: size, depth_or_array_layers: 1 },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: TextureDimension::D2,
+        format: BRDF_LUT_FORMAT,
+        usage: TextureUsages::TEXTURE_BINDING | TextureUsages::RENDER_ATTACHMENT,
+        view_formats: &[],
+    })
+}
+
+fn cube_view(texture: &Texture) -> TextureView {
+    texture.create_view(&TextureViewDescriptor { dimension: Some(TextureViewDimension::Cube), ..Default::default() })
+}
+
+fn face_view(texture: &Texture, mip: u32, face: u32) -> TextureView {
+    texture.create_view(&TextureViewDescriptor {
+        dimension: Some(TextureViewDimension::D2),
+        base_mip_level: mip,
+        mip_level_count: Some(1),
+        base_array_layer: face,
+        array_layer_count: Some(1),
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use rend3::ShaderPreProcessor;
+
+    use super::PREFILTERED_MIPS;
+
+    #[test]
+    fn prefiltered_mips_match_shader() {
+        let mut spp = ShaderPreProcessor::new();
+        crate::builtin_shaders(&mut spp);
+        let source = spp.get("rend3-routine/ibl.wgsl").unwrap();
+        assert!(source.contains(&format!("const PREFILTERED_MIPS: u32 = {PREFILTERED_MIPS}u;")));
+    }
+}
@@ -19,16 +19,27 @@
 //! too much user side boilerplate.
 
 pub mod base;
+pub mod bloom;
 pub mod clear;
 pub mod common;
+pub mod compute;
 pub mod culling;
+pub mod decal;
+pub mod depth_readback;
 pub mod forward;
+pub mod fxaa;
 pub mod hi_z;
+pub mod ibl;
+pub mod overlay;
 pub mod pbr;
+pub mod picking;
+pub mod post_process;
 mod shaders;
 pub mod skinning;
 pub mod skybox;
+pub mod ssao;
 pub mod tonemapping;
 pub mod uniforms;
+pub mod variance_shadows;
 
 pub use shaders::builtin_shaders;
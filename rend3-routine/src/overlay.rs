@@ -0,0 +1,56 @@
+//! Routine which lets an external renderer draw on top of the finished frame.
+//!
+//! This is the integration point for 2D UI libraries like egui-wgpu. Add the
+//! overlay after [`BaseRenderGraph::add_to_graph`](crate::base::BaseRenderGraph::add_to_graph),
+//! with the same output, and it runs after all scene passes, tonemapping and
+//! anti-aliasing. The paint callback records into the command encoder of the
+//! frame, so its uploads and render passes are submitted together with the
+//! rest of the frame by [`RenderGraph::execute`], before the surface is
+//! presented.
+
+use glam::UVec2;
+use rend3::{
+    graph::{NodeResourceUsage, RenderGraph, RenderTargetHandle},
+    types::TextureFormat,
+    Renderer,
+};
+use wgpu::{CommandEncoder, TextureView};
+
+/// Arguments of the paint callback of [`add_overlay_to_graph`].
+pub struct OverlayContext<'a> {
+    pub renderer: &'a Renderer,
+    /// Encoder of the frame. Copies recorded into it run before the render
+    /// passes recorded after them, so buffers can be uploaded first.
+    pub encoder: &'a mut CommandEncoder,
+    /// View of the output, holding the finished frame. Render passes should
+    /// load it instead of clearing it.
+    pub view: &'a TextureView,
+    pub resolution: UVec2,
+    pub format: TextureFormat,
+}
+
+/// Adds a node calling `paint` to draw on top of `output`, which has the given
+/// resolution and format.
+///
+/// The node runs in the order it was added relative to the other nodes
+/// writing `output`.
+pub fn add_overlay_to_graph<'node>(
+    graph: &mut RenderGraph<'node>,
+    label: &str,
+    output: RenderTargetHandle,
+    resolution: UVec2,
+    format: TextureFormat,
+    paint: impl FnOnce(OverlayContext<'_>) + 'node,
+) {
+    let mut builder = graph.add_node(label);
+    let output_handle = builder.add_render_target(output, NodeResourceUsage::InputOutput);
+
+    builder.build(move |mut ctx| {
+        let view = ctx.graph_data.get_render_target(output_handle);
+        let encoder = ctx.encoder_or_pass.take_encoder();
+
+        profiling::scope!("Overlay");
+
+        paint(OverlayContext { renderer: ctx.renderer, encoder, view, resolution, format });
+    });
+}
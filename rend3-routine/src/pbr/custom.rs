@@ -0,0 +1,167 @@
+//! Shaders replacing the standard pbr shader for some materials.
+
+use naga::{
+    front::wgsl::ParseError,
+    valid::{Capabilities, ValidationError, ValidationFlags, Validator},
+    ShaderStage, WithSpan,
+};
+use rend3::{RendererProfile, ShaderPreProcessor, ShaderVertexBufferConfig};
+use thiserror::Error;
+
+use crate::{forward::ForwardRoutine, pbr::PbrMaterial};
+
+/// Fragment entry point a [`CustomPbrShader`] must define.
+pub const CUSTOM_PBR_FRAGMENT_ENTRY: &str = "fs_custom";
+/// Vertex entry point a [`CustomPbrShader`] may define. The standard vertex
+/// shader is used if it doesn't.
+pub const CUSTOM_PBR_VERTEX_ENTRY: &str = "vs_custom";
+
+/// A user provided shader rendering the materials whose
+/// [`PbrMaterial::custom_shader`] refers to it, for effects like dissolving,
+/// force fields or toon shading.
+///
+/// The source is WGSL appended to the standard pbr shader, opaque.wgsl, so it
+/// sees the same bind groups, structures and helper functions:
+///
+/// - Group 0 is the forward uniform bind group: the samplers, `uniforms`, the
+///   directional, point and spot lights, the shadow atlas, the screen space
+///   ambient occlusion and the image based lighting maps.
+/// - Group 1 holds the object data in `object_buffer`, `batch_data`, the
+///   `vertex_buffer`, the `per_camera_uniform` and the `materials`.
+/// - Group 2 holds the material textures, a binding array indexed through the
+///   material on [`RendererProfile::GpuDriven`] and the textures of the
+///   current material on [`RendererProfile::CpuDriven`].
+///
+/// It must define `@fragment fn fs_custom(input: VertexOutput, ...)`, which
+/// can use `get_pixel_data(material, input)` to read the material,
+/// `surface_shading` to light it and `apply_fog` to fog it, like `fs_main`
+/// does. It may define a
+/// `@vertex fn vs_custom(...) -> VertexOutput` too. Shadows, the depth prepass
+/// and object ids are rendered with the standard vertex shader, so a custom
+/// vertex shader must not move the vertices.
+pub struct CustomPbrShader<'a> {
+    pub label: &'a str,
+    pub source: &'a str,
+}
+
+/// Error returned when a [`CustomPbrShader`] is invalid.
+#[derive(Debug, Error)]
+pub enum CustomPbrShaderError {
+    #[error("Failed to parse custom pbr shader")]
+    Parse(#[source] ParseError),
+    #[error("Custom pbr shader must define a `fs_custom` fragment entry point")]
+    MissingEntryPoint,
+    #[error("Custom pbr shader failed validation")]
+    Validation(#[source] Box<WithSpan<ValidationError>>),
+}
+
+/// Forward routines of the materials using one custom shader.
+pub struct CustomPbrRoutines {
+    pub opaque_routine: ForwardRoutine<PbrMaterial>,
+    pub cutout_routine: ForwardRoutine<PbrMaterial>,
+    /// One for each [`BlendMode`](super::BlendMode), in the order of
+    /// [`BlendMode::ALL`](super::BlendMode::ALL).
+    pub blend_routine: [ForwardRoutine<PbrMaterial>; 4],
+    pub decal_routine: ForwardRoutine<PbrMaterial>,
+    pub double_sided_opaque_routine: ForwardRoutine<PbrMaterial>,
+    pub double_sided_cutout_routine: ForwardRoutine<PbrMaterial>,
+    pub double_sided_blend_routine: [ForwardRoutine<PbrMaterial>; 4],
+    pub double_sided_decal_routine: ForwardRoutine<PbrMaterial>,
+    pub prepassed_opaque_routine: ForwardRoutine<PbrMaterial>,
+    pub prepassed_cutout_routine: ForwardRoutine<PbrMaterial>,
+    pub double_sided_prepassed_opaque_routine: ForwardRoutine<PbrMaterial>,
+    pub double_sided_prepassed_cutout_routine: ForwardRoutine<PbrMaterial>,
+}
+
+/// Source of a custom shader, along with whether it defines its own vertex
+/// shader.
+pub(super) struct CustomPbrSource {
+    pub source: String,
+    pub custom_vertex: bool,
+}
+
+/// Appends the user's source to opaque.wgsl, making sure it defines
+/// [`CUSTOM_PBR_FRAGMENT_ENTRY`] and validates.
+pub(super) fn build_custom_pbr_source(
+    spp: &ShaderPreProcessor,
+    profile: RendererProfile,
+    discard: bool,
+    user_source: &str,
+) -> Result<CustomPbrSource, CustomPbrShaderError> {
+    let template = spp
+        .render_shader(
+            "rend3-routine/opaque.wgsl",
+            &super::routine::BlendModeWrapper { profile, discard },
+            Some(&ShaderVertexBufferConfig::from_material::<PbrMaterial>()),
+        )
+        .unwrap();
+    let source = format!("{template}\n{user_source}");
+
+    let module = naga::front::wgsl::parse_str(&source).map_err(CustomPbrShaderError::Parse)?;
+    let has_entry = |name, stage| module.entry_points.iter().any(|ep| ep.name == name && ep.stage == stage);
+    if !has_entry(CUSTOM_PBR_FRAGMENT_ENTRY, ShaderStage::Fragment) {
+        return Err(CustomPbrShaderError::MissingEntryPoint);
+    }
+    let custom_vertex = has_entry(CUSTOM_PBR_VERTEX_ENTRY, ShaderStage::Vertex);
+
+    // The device checks the capabilities the shader needs when it is compiled.
+    Validator::new(ValidationFlags::all(), Capabilities::all())
+        .validate(&module)
+        .map_err(|e| CustomPbrShaderError::Validation(Box::new(e)))?;
+
+    Ok(CustomPbrSource { source, custom_vertex })
+}
+
+#[cfg(test)]
+mod tests {
+    use rend3::{RendererProfile, ShaderPreProcessor};
+
+    use super::{build_custom_pbr_source, CustomPbrShaderError, CustomPbrSource};
+
+    fn build(source: &str) -> Result<CustomPbrSource, CustomPbrShaderError> {
+        let mut spp = ShaderPreProcessor::new();
+        crate::builtin_shaders(&mut spp);
+        for profile in [RendererProfile::CpuDriven, RendererProfile::GpuDriven] {
+            build_custom_pbr_source(&spp, profile, true, source)?;
+        }
+        build_custom_pbr_source(&spp, RendererProfile::CpuDriven, false, source)
+    }
+
+    #[test]
+    fn valid() {
+        let source = "@fragment
+        fn fs_custom(input: VertexOutput) -> @location(0) vec4<f32> {
+            let pixel = get_pixel_data(materials[input.material], input);
+            let bands = floor(max(dot(pixel.normal, vec3<f32>(0.0, 0.0, 1.0)), 0.0) * 3.0) / 3.0;
+            return vec4<f32>(pixel.albedo.rgb * bands, pixel.albedo.a);
+        }";
+        assert!(!build(source).unwrap().custom_vertex);
+    }
+
+    #[test]
+    fn custom_vertex() {
+        let source = "@vertex
+        fn vs_custom() -> VertexOutput {
+            var vs_out: VertexOutput;
+            vs_out.position = vec4<f32>(0.0);
+            return vs_out;
+        }
+
+        @fragment
+        fn fs_custom(input: VertexOutput) -> @location(0) vec4<f32> {
+            return input.color;
+        }";
+        assert!(build(source).unwrap().custom_vertex);
+    }
+
+    #[test]
+    fn missing_entry_point() {
+        let source = "fn fs_custom(input: VertexOutput) -> vec4<f32> { return input.color; }";
+        assert!(matches!(build(source), Err(CustomPbrShaderError::MissingEntryPoint)));
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(matches!(build("@fragment fn fs_custom("), Err(CustomPbrShaderError::Parse(_))));
+    }
+}
@@ -7,6 +7,7 @@ use rend3::types::{
     VERTEX_ATTRIBUTE_NORMAL, VERTEX_ATTRIBUTE_POSITION, VERTEX_ATTRIBUTE_TANGENT,
     VERTEX_ATTRIBUTE_TEXTURE_COORDINATES_0, VERTEX_ATTRIBUTE_TEXTURE_COORDINATES_1,
 };
+use wgpu::{BlendComponent, BlendFactor, BlendOperation, BlendState};
 
 bitflags::bitflags! {
     /// Flags which shaders use to determine properties of a material
@@ -27,6 +28,9 @@ bitflags::bitflags! {
         const CC_BW_SPLIT =         0b0001_0000_0000_0000;
         const UNLIT =               0b0010_0000_0000_0000;
         const NEAREST =             0b0100_0000_0000_0000;
+        const WRAP_CLAMP =          0b1000_0000_0000_0000;
+        const WRAP_MIRROR =         0b1_0000_0000_0000_0000;
+        const OBJECT_SPACE_NORMAL = 0b10_0000_0000_0000_0000;
     }
 }
 
@@ -230,6 +234,18 @@ impl NormalTexture {
     }
 }
 
+/// Space the normals of a normal texture are in.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum NormalMapSpace {
+    /// Relative to the surface, transformed by the vertex normal and tangent.
+    #[default]
+    Tangent,
+    /// In the space of the mesh, transformed by the object's transform only.
+    /// Vertex tangents are not needed. Back faces of double sided materials
+    /// flip normals pointing away from them.
+    Object,
+}
+
 /// How the Ambient Occlusion, Metalic, and Roughness values should be
 /// determined.
 #[derive(Debug, Clone)]
@@ -367,7 +383,8 @@ impl Default for ClearcoatTextures {
     }
 }
 
-/// How textures should be sampled.
+/// How textures should be sampled, both within a mip level and between
+/// them.
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum SampleType {
     Nearest,
@@ -379,6 +396,53 @@ impl Default for SampleType {
     }
 }
 
+/// What happens to texture coordinates outside of the 0-1 range.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum TextureWrap {
+    /// The texture tiles.
+    #[default]
+    Repeat,
+    /// The edge texels of the texture are stretched outwards.
+    Clamp,
+    /// The texture tiles, flipping every other repetition.
+    Mirror,
+}
+
+/// How [`Transparency::Blend`] materials are combined with the image behind
+/// them.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum BlendMode {
+    /// The color is blended by its alpha.
+    #[default]
+    Alpha,
+    /// The color was already multiplied by its alpha, as in composited
+    /// sprites, and is added to the image behind scaled by one minus alpha.
+    PremultipliedAlpha,
+    /// The color, scaled by its alpha, is added to the image behind. Useful
+    /// for particles and fire.
+    Additive,
+    /// The image behind is multiplied by the color. Alpha is ignored.
+    Multiply,
+}
+
+impl BlendMode {
+    pub const ALL: [Self; 4] = [Self::Alpha, Self::PremultipliedAlpha, Self::Additive, Self::Multiply];
+
+    pub fn to_blend_state(self) -> BlendState {
+        let color = |src_factor, dst_factor| BlendComponent { src_factor, dst_factor, operation: BlendOperation::Add };
+        match self {
+            Self::Alpha => BlendState::ALPHA_BLENDING,
+            Self::PremultipliedAlpha => BlendState::PREMULTIPLIED_ALPHA_BLENDING,
+            Self::Additive => {
+                BlendState { color: color(BlendFactor::SrcAlpha, BlendFactor::One), alpha: BlendComponent::OVER }
+            }
+            Self::Multiply => {
+                BlendState { color: color(BlendFactor::Dst, BlendFactor::Zero), alpha: BlendComponent::OVER }
+            }
+        }
+    }
+}
+
 /// The type of transparency in a material.
 #[repr(u8)]
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
@@ -389,6 +453,8 @@ pub enum TransparencyType {
     Cutout,
     /// Alpha is blended.
     Blend,
+    /// Alpha is blended over the surface behind the decal.
+    Decal,
 }
 impl From<Transparency> for TransparencyType {
     fn from(t: Transparency) -> Self {
@@ -396,6 +462,7 @@ impl From<Transparency> for TransparencyType {
             Transparency::Opaque => Self::Opaque,
             Transparency::Cutout { .. } => Self::Cutout,
             Transparency::Blend => Self::Blend,
+            Transparency::Decal => Self::Decal,
         }
     }
 }
@@ -405,13 +472,14 @@ impl TransparencyType {
             TransparencyType::Opaque => "opaque",
             TransparencyType::Cutout => "cutout",
             TransparencyType::Blend => "blend",
+            TransparencyType::Decal => "decal",
         }
     }
 
     pub fn to_sorting(self) -> Sorting {
         match self {
             Self::Opaque | Self::Cutout => Sorting::OPAQUE,
-            Self::Blend => Sorting::BLENDING,
+            Self::Blend | Self::Decal => Sorting::BLENDING,
         }
     }
 }
@@ -439,6 +507,24 @@ pub enum Transparency {
     Cutout { cutout: f32 },
     /// Alpha is blended.
     Blend,
+    /// The object is a decal on the surface it is coplanar with, like a bullet
+    /// hole or a puddle.
+    ///
+    /// Decals are rendered after all opaque objects, blended by alpha, with a
+    /// depth bias that pulls them in front of the receiving surface. They test
+    /// against the depth buffer but don't write to it, and don't cast shadows.
+    ///
+    /// Unlike deferred decals, these are plain geometry with their own
+    /// material:
+    /// - The mesh has to be built to follow the receiving surface, usually a
+    ///   quad placed on it.
+    /// - The decal is shaded with its own normals and material values, it can't
+    ///   change only some properties (like roughness) of the surface below.
+    /// - Parts of the mesh which reach past the edge of the receiving surface
+    ///   are still rendered, they are not clipped to its depth.
+    /// - Decals on surfaces at grazing angles may still z-fight, as the bias is
+    ///   fixed.
+    Decal,
 }
 impl Default for Transparency {
     fn default() -> Self {
@@ -455,7 +541,16 @@ impl Default for Transparency {
 pub struct PbrMaterial {
     pub albedo: AlbedoComponent,
     pub transparency: Transparency,
+    /// How the material is blended, if `transparency` is
+    /// [`Transparency::Blend`].
+    ///
+    /// Each mode is rendered by separate routines, so objects are only sorted
+    /// back to front among the objects with the same mode. The modes are drawn
+    /// in the order of [`BlendMode::ALL`].
+    pub blend_mode: BlendMode,
     pub normal: NormalTexture,
+    /// Space the normals of `normal` are in.
+    pub normal_map_space: NormalMapSpace,
     pub aomr_textures: AoMRTextures,
     pub ao_factor: Option<f32>,
     pub metallic_factor: Option<f32>,
@@ -471,6 +566,58 @@ pub struct PbrMaterial {
     // TODO: Make unlit a different shader entirely.
     pub unlit: bool,
     pub sample_type: SampleType,
+    /// Wrap mode of all textures of the material.
+    pub texture_wrap: TextureWrap,
+    /// Renders both faces of each triangle, for thin surfaces like foliage and
+    /// cloth. Back faces are shaded with the normal flipped.
+    ///
+    /// Double sided materials are rendered by separate routines, so blended
+    /// double sided objects are drawn after all blended single sided objects,
+    /// not sorted together with them.
+    pub double_sided: bool,
+    /// Shader replacing the standard one in the forward passes, see
+    /// [`PbrRoutine::add_custom_shader`](crate::pbr::PbrRoutine::add_custom_shader).
+    ///
+    /// Like double sided materials, blended materials with a custom shader are
+    /// drawn after the other blended materials.
+    pub custom_shader: Option<CustomShaderId>,
+}
+
+/// Identifies a custom shader added to a [`PbrRoutine`](crate::pbr::PbrRoutine).
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct CustomShaderId(pub(crate) u32);
+
+/// Bit of the material key which is set for double sided materials.
+const DOUBLE_SIDED_KEY: u64 = 1 << 40;
+
+/// First bit of the material key holding the blend mode of blended materials.
+const BLEND_MODE_KEY_SHIFT: u64 = 41;
+
+/// Bits of the material key holding the custom shader. They are the lowest
+/// bits, so materials differing only in their shader have adjacent keys.
+pub const CUSTOM_SHADER_KEY_BITS: u64 = u32::MAX as u64;
+
+/// Material key of pbr materials, which selects the routine that renders them.
+///
+/// `blend_mode` is ignored unless `transparency` is
+/// [`TransparencyType::Blend`].
+pub fn pbr_material_key(
+    transparency: TransparencyType,
+    blend_mode: BlendMode,
+    double_sided: bool,
+    custom_shader: Option<CustomShaderId>,
+) -> u64 {
+    let mut key = (transparency as u64) << 32;
+    if transparency == TransparencyType::Blend {
+        key |= (blend_mode as u64) << BLEND_MODE_KEY_SHIFT;
+    }
+    if double_sided {
+        key |= DOUBLE_SIDED_KEY;
+    }
+    if let Some(CustomShaderId(id)) = custom_shader {
+        key |= id as u64 + 1;
+    }
+    key
 }
 
 impl Material for PbrMaterial {
@@ -495,13 +642,17 @@ impl Material for PbrMaterial {
     }
 
     fn key(&self) -> u64 {
-        TransparencyType::from(self.transparency) as u64
+        pbr_material_key(self.transparency.into(), self.blend_mode, self.double_sided, self.custom_shader)
     }
 
     fn sorting(&self) -> Sorting {
         TransparencyType::from(self.transparency).to_sorting()
     }
 
+    fn double_sided(&self) -> bool {
+        self.double_sided
+    }
+
     fn to_textures(&self) -> Self::TextureArrayType {
         [
             self.albedo.to_texture(),
@@ -576,8 +727,92 @@ impl ShaderMaterial {
                         SampleType::Linear => false,
                     },
                 );
+                flags.set(MaterialFlags::WRAP_CLAMP, material.texture_wrap == TextureWrap::Clamp);
+                flags.set(MaterialFlags::WRAP_MIRROR, material.texture_wrap == TextureWrap::Mirror);
+                flags.set(MaterialFlags::OBJECT_SPACE_NORMAL, material.normal_map_space == NormalMapSpace::Object);
                 flags.bits()
             },
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use rend3::types::Material;
+
+    use super::{
+        BlendMode, CustomShaderId, MaterialFlags, NormalMapSpace, PbrMaterial, ShaderMaterial, TextureWrap,
+        Transparency, CUSTOM_SHADER_KEY_BITS,
+    };
+
+    #[test]
+    fn double_sided_key() {
+        let transparencies =
+            [Transparency::Opaque, Transparency::Cutout { cutout: 0.5 }, Transparency::Blend, Transparency::Decal];
+
+        let mut keys: Vec<u64> = transparencies
+            .iter()
+            .flat_map(|&transparency| {
+                [false, true].map(|double_sided| PbrMaterial { transparency, double_sided, ..Default::default() }.key())
+            })
+            .collect();
+        keys.sort_unstable();
+        keys.dedup();
+
+        assert_eq!(keys.len(), transparencies.len() * 2);
+    }
+
+    #[test]
+    fn custom_shader_key() {
+        let key = |double_sided, custom_shader| {
+            PbrMaterial { transparency: Transparency::Blend, double_sided, custom_shader, ..Default::default() }.key()
+        };
+
+        let standard = key(false, None);
+        let custom = key(false, Some(CustomShaderId(0)));
+        assert_ne!(standard, custom);
+        assert_ne!(custom, key(false, Some(CustomShaderId(1))));
+        assert_eq!(standard, custom & !CUSTOM_SHADER_KEY_BITS);
+        assert_eq!(key(true, None), key(true, Some(CustomShaderId(7))) & !CUSTOM_SHADER_KEY_BITS);
+    }
+
+    #[test]
+    fn blend_mode_key() {
+        let key = |transparency, blend_mode| PbrMaterial { transparency, blend_mode, ..Default::default() }.key();
+
+        let mut blended: Vec<u64> = BlendMode::ALL.iter().map(|&mode| key(Transparency::Blend, mode)).collect();
+        blended.sort_unstable();
+        blended.dedup();
+        assert_eq!(blended.len(), BlendMode::ALL.len());
+
+        // Only blended materials are affected.
+        for transparency in [Transparency::Opaque, Transparency::Cutout { cutout: 0.5 }, Transparency::Decal] {
+            assert_eq!(key(transparency, BlendMode::Alpha), key(transparency, BlendMode::Additive));
+        }
+    }
+
+    #[test]
+    fn texture_wrap_flags() {
+        let flags = |texture_wrap| {
+            let material = PbrMaterial { texture_wrap, ..Default::default() };
+            MaterialFlags::from_bits_truncate(ShaderMaterial::from_material(&material).material_flags)
+        };
+
+        assert!(!flags(TextureWrap::Repeat).intersects(MaterialFlags::WRAP_CLAMP | MaterialFlags::WRAP_MIRROR));
+        assert!(!flags(TextureWrap::Clamp).contains(MaterialFlags::WRAP_MIRROR));
+        assert!(flags(TextureWrap::Clamp).contains(MaterialFlags::WRAP_CLAMP));
+        assert!(flags(TextureWrap::Mirror).contains(MaterialFlags::WRAP_MIRROR));
+        assert!(!flags(TextureWrap::Mirror).contains(MaterialFlags::WRAP_CLAMP));
+    }
+
+    #[test]
+    fn normal_map_space_flags() {
+        let flags = |normal_map_space| {
+            let material = PbrMaterial { normal_map_space, ..Default::default() };
+            MaterialFlags::from_bits_truncate(ShaderMaterial::from_material(&material).material_flags)
+        };
+
+        assert!(!flags(NormalMapSpace::Tangent).contains(MaterialFlags::OBJECT_SPACE_NORMAL));
+        assert!(flags(NormalMapSpace::Object).contains(MaterialFlags::OBJECT_SPACE_NORMAL));
+    }
+}
@@ -1,7 +1,9 @@
 //! Realism-focused PBR rendering routines and material.
 
+mod custom;
 mod material;
 mod routine;
 
+pub use custom::*;
 pub use material::*;
 pub use routine::*;
@@ -1,23 +1,32 @@
 use std::{borrow::Cow, sync::Arc};
 
 use rend3::{
-    types::GraphDataHandle, Renderer, RendererDataCore, RendererProfile, ShaderPreProcessor, ShaderVertexBufferConfig,
+    types::GraphDataHandle, util::error_scope::ValidationErrorScope, Renderer, RendererDataCore, RendererProfile,
+    ShaderPreProcessor, ShaderVertexBufferConfig,
 };
 use serde::Serialize;
-use wgpu::{BlendState, ShaderModuleDescriptor, ShaderSource};
+use wgpu::{BlendState, DepthBiasState, ShaderModuleDescriptor, ShaderSource, StencilState};
 
 use crate::{
     common::{PerMaterialArchetypeInterface, WholeFrameInterfaces},
     culling::CullingBufferMap,
     forward::{ForwardRoutine, ForwardRoutineCreateArgs, RoutineType, ShaderModulePair},
     hi_z::HiZRoutine,
-    pbr::{PbrMaterial, TransparencyType},
+    pbr::{
+        custom::build_custom_pbr_source, pbr_material_key, BlendMode, CustomPbrRoutines, CustomPbrShader,
+        CustomPbrShaderError, CustomShaderId, PbrMaterial, TransparencyType, CUSTOM_PBR_FRAGMENT_ENTRY,
+        CUSTOM_PBR_VERTEX_ENTRY, CUSTOM_SHADER_KEY_BITS,
+    },
 };
 
+/// Depth bias applied to [`Transparency::Decal`](crate::pbr::Transparency::Decal)
+/// objects, pulling them towards the camera.
+pub const DECAL_DEPTH_BIAS: DepthBiasState = DepthBiasState { constant: 2, slope_scale: 2.0, clamp: 0.0 };
+
 #[derive(Serialize)]
-struct BlendModeWrapper {
-    profile: RendererProfile,
-    discard: bool,
+pub(super) struct BlendModeWrapper {
+    pub profile: RendererProfile,
+    pub discard: bool,
 }
 
 /// Render routine that renders the using PBR materials
@@ -26,9 +35,43 @@ pub struct PbrRoutine {
     pub cutout_depth: ForwardRoutine<PbrMaterial>,
     pub opaque_routine: ForwardRoutine<PbrMaterial>,
     pub cutout_routine: ForwardRoutine<PbrMaterial>,
-    pub blend_routine: ForwardRoutine<PbrMaterial>,
+    /// One for each [`BlendMode`], in the order of [`BlendMode::ALL`].
+    pub blend_routine: [ForwardRoutine<PbrMaterial>; 4],
+    pub decal_routine: ForwardRoutine<PbrMaterial>,
+    /// Same as the routines above, without back face culling, for
+    /// [`PbrMaterial::double_sided`] materials.
+    pub double_sided_opaque_depth: ForwardRoutine<PbrMaterial>,
+    pub double_sided_cutout_depth: ForwardRoutine<PbrMaterial>,
+    pub double_sided_opaque_routine: ForwardRoutine<PbrMaterial>,
+    pub double_sided_cutout_routine: ForwardRoutine<PbrMaterial>,
+    pub double_sided_blend_routine: [ForwardRoutine<PbrMaterial>; 4],
+    pub double_sided_decal_routine: ForwardRoutine<PbrMaterial>,
+    /// Depth prepass for opaque and cutout objects, see
+    /// [`BaseRenderGraphSettings::depth_prepass`](crate::base::BaseRenderGraphSettings::depth_prepass).
+    pub opaque_prepass: ForwardRoutine<PbrMaterial>,
+    pub cutout_prepass: ForwardRoutine<PbrMaterial>,
+    pub double_sided_opaque_prepass: ForwardRoutine<PbrMaterial>,
+    pub double_sided_cutout_prepass: ForwardRoutine<PbrMaterial>,
+    /// Forward routines shading only what the depth prepass left visible.
+    ///
+    /// Cutout objects don't discard again, as the prepass already decided
+    /// which of their fragments are visible.
+    pub prepassed_opaque_routine: ForwardRoutine<PbrMaterial>,
+    pub prepassed_cutout_routine: ForwardRoutine<PbrMaterial>,
+    pub double_sided_prepassed_opaque_routine: ForwardRoutine<PbrMaterial>,
+    pub double_sided_prepassed_cutout_routine: ForwardRoutine<PbrMaterial>,
+    /// Object id routines for opaque and cutout objects, see
+    /// [`ObjectPicker`](crate::picking::ObjectPicker).
+    pub opaque_object_id: ForwardRoutine<PbrMaterial>,
+    pub cutout_object_id: ForwardRoutine<PbrMaterial>,
+    pub double_sided_opaque_object_id: ForwardRoutine<PbrMaterial>,
+    pub double_sided_cutout_object_id: ForwardRoutine<PbrMaterial>,
     pub hi_z: HiZRoutine,
     pub per_material: PerMaterialArchetypeInterface<PbrMaterial>,
+    /// Routines of the shaders added with [`Self::add_custom_shader`], indexed
+    /// by their [`CustomShaderId`].
+    pub custom_shaders: Vec<CustomPbrRoutines>,
+    culling_buffer_map_handle: GraphDataHandle<CullingBufferMap>,
 }
 
 impl PbrRoutine {
@@ -94,41 +137,343 @@ impl PbrRoutine {
             )),
         });
 
-        let mut inner = |routine_type, module, transparency| {
-            ForwardRoutine::new(ForwardRoutineCreateArgs {
-                name: &format!("pbr {routine_type:?} {transparency:?}"),
-                renderer,
-                data_core,
-                spp,
-                interfaces,
-                per_material: &per_material,
-                material_key: transparency as u64,
+        let mut blended = |double_sided| {
+            BlendMode::ALL.map(|blend_mode| {
+                create_routine(
+                    RoutineArgs {
+                        name: "pbr",
+                        renderer,
+                        data_core: &mut *data_core,
+                        spp,
+                        interfaces,
+                        per_material: &per_material,
+                        culling_buffer_map_handle,
+                    },
+                    RoutineType::Forward,
+                    ShaderModulePair {
+                        vs_entry: "vs_main",
+                        vs_module: &pbr_forward,
+                        fs_entry: "fs_main",
+                        fs_module: &pbr_forward,
+                    },
+                    TransparencyType::Blend,
+                    blend_mode,
+                    double_sided,
+                    None,
+                )
+            })
+        };
+        let blend_routine = blended(false);
+        let double_sided_blend_routine = blended(true);
+
+        let mut inner = |routine_type, module, transparency, double_sided| {
+            let fs_entry = match routine_type {
+                RoutineType::ObjectId => "fs_object_id",
+                _ => "fs_main",
+            };
+            create_routine(
+                RoutineArgs {
+                    name: "pbr",
+                    renderer,
+                    data_core,
+                    spp,
+                    interfaces,
+                    per_material: &per_material,
+                    culling_buffer_map_handle,
+                },
                 routine_type,
-                shaders: ShaderModulePair {
-                    vs_entry: "vs_main",
+                ShaderModulePair { vs_entry: "vs_main", vs_module: module, fs_entry, fs_module: module },
+                transparency,
+                BlendMode::Alpha,
+                double_sided,
+                None,
+            )
+        };
+
+        Self {
+            opaque_depth: inner(RoutineType::Depth, &pbr_depth, TransparencyType::Opaque, false),
+            cutout_depth: inner(RoutineType::Depth, &pbr_depth_cutout, TransparencyType::Cutout, false),
+            opaque_routine: inner(RoutineType::Forward, &pbr_forward, TransparencyType::Opaque, false),
+            cutout_routine: inner(RoutineType::Forward, &pbr_cutout, TransparencyType::Cutout, false),
+            blend_routine,
+            decal_routine: inner(RoutineType::Forward, &pbr_forward, TransparencyType::Decal, false),
+            double_sided_opaque_depth: inner(RoutineType::Depth, &pbr_depth, TransparencyType::Opaque, true),
+            double_sided_cutout_depth: inner(RoutineType::Depth, &pbr_depth_cutout, TransparencyType::Cutout, true),
+            double_sided_opaque_routine: inner(RoutineType::Forward, &pbr_forward, TransparencyType::Opaque, true),
+            double_sided_cutout_routine: inner(RoutineType::Forward, &pbr_cutout, TransparencyType::Cutout, true),
+            double_sided_blend_routine,
+            double_sided_decal_routine: inner(RoutineType::Forward, &pbr_forward, TransparencyType::Decal, true),
+            opaque_prepass: inner(RoutineType::DepthPrepass, &pbr_depth, TransparencyType::Opaque, false),
+            cutout_prepass: inner(RoutineType::DepthPrepass, &pbr_depth_cutout, TransparencyType::Cutout, false),
+            double_sided_opaque_prepass: inner(RoutineType::DepthPrepass, &pbr_depth, TransparencyType::Opaque, true),
+            double_sided_cutout_prepass: inner(
+                RoutineType::DepthPrepass,
+                &pbr_depth_cutout,
+                TransparencyType::Cutout,
+                true,
+            ),
+            prepassed_opaque_routine: inner(
+                RoutineType::ForwardPrepassed,
+                &pbr_forward,
+                TransparencyType::Opaque,
+                false,
+            ),
+            prepassed_cutout_routine: inner(
+                RoutineType::ForwardPrepassed,
+                &pbr_forward,
+                TransparencyType::Cutout,
+                false,
+            ),
+            double_sided_prepassed_opaque_routine: inner(
+                RoutineType::ForwardPrepassed,
+                &pbr_forward,
+                TransparencyType::Opaque,
+                true,
+            ),
+            double_sided_prepassed_cutout_routine: inner(
+                RoutineType::ForwardPrepassed,
+                &pbr_forward,
+                TransparencyType::Cutout,
+                true,
+            ),
+            opaque_object_id: inner(RoutineType::ObjectId, &pbr_depth, TransparencyType::Opaque, false),
+            cutout_object_id: inner(RoutineType::ObjectId, &pbr_depth_cutout, TransparencyType::Cutout, false),
+            double_sided_opaque_object_id: inner(RoutineType::ObjectId, &pbr_depth, TransparencyType::Opaque, true),
+            double_sided_cutout_object_id: inner(
+                RoutineType::ObjectId,
+                &pbr_depth_cutout,
+                TransparencyType::Cutout,
+                true,
+            ),
+            hi_z: HiZRoutine::new(renderer, spp, interfaces.depth_format),
+            per_material,
+            custom_shaders: Vec::new(),
+            culling_buffer_map_handle: culling_buffer_map_handle.clone(),
+        }
+    }
+
+    /// Recompile the standard pbr shaders from `spp`.
+    ///
+    /// Shaders added with [`Self::add_custom_shader`] keep their ids and their
+    /// pipelines, as their source isn't stored. If the shaders fail to
+    /// compile, the error is returned and the routine is left unchanged.
+    pub fn reload_shaders(
+        &mut self,
+        renderer: &Arc<Renderer>,
+        data_core: &mut RendererDataCore,
+        spp: &ShaderPreProcessor,
+        interfaces: &WholeFrameInterfaces,
+    ) -> Result<(), wgpu::Error> {
+        profiling::scope!("PbrRenderRoutine::reload_shaders");
+
+        let scope = ValidationErrorScope::new(&renderer.device);
+        let mut reloaded = Self::new(renderer, data_core, spp, interfaces, &self.culling_buffer_map_handle);
+        scope.end()?;
+
+        reloaded.custom_shaders = std::mem::take(&mut self.custom_shaders);
+        *self = reloaded;
+
+        Ok(())
+    }
+
+    /// Add a shader which renders the materials whose
+    /// [`PbrMaterial::custom_shader`] is set to the returned id, instead of
+    /// the standard shader.
+    ///
+    /// The shader is validated before it is compiled, so an invalid shader
+    /// returns an error instead of causing a wgpu validation error.
+    pub fn add_custom_shader(
+        &mut self,
+        renderer: &Arc<Renderer>,
+        data_core: &mut RendererDataCore,
+        spp: &ShaderPreProcessor,
+        interfaces: &WholeFrameInterfaces,
+        shader: CustomPbrShader<'_>,
+    ) -> Result<CustomShaderId, CustomPbrShaderError> {
+        profiling::scope!("Add Custom Pbr Shader");
+
+        let id = CustomShaderId(self.custom_shaders.len() as u32);
+
+        let forward = build_custom_pbr_source(spp, renderer.profile, false, shader.source)?;
+        let cutout = build_custom_pbr_source(spp, renderer.profile, true, shader.source)?;
+        let module = |source: String| {
+            renderer.device.create_shader_module(ShaderModuleDescriptor {
+                label: Some(shader.label),
+                source: ShaderSource::Wgsl(Cow::Owned(source)),
+            })
+        };
+        let vs_entry = if forward.custom_vertex { CUSTOM_PBR_VERTEX_ENTRY } else { "vs_main" };
+        let forward_module = module(forward.source);
+        let cutout_module = module(cutout.source);
+
+        let mut inner = |routine_type, module, transparency, blend_mode, double_sided| {
+            create_routine(
+                RoutineArgs {
+                    name: shader.label,
+                    renderer,
+                    data_core: &mut *data_core,
+                    spp,
+                    interfaces,
+                    per_material: &self.per_material,
+                    culling_buffer_map_handle: &self.culling_buffer_map_handle,
+                },
+                routine_type,
+                ShaderModulePair {
+                    vs_entry,
                     vs_module: module,
-                    fs_entry: "fs_main",
+                    fs_entry: CUSTOM_PBR_FRAGMENT_ENTRY,
                     fs_module: module,
                 },
-                extra_bgls: &[],
-                descriptor_callback: Some(&|desc, targets| {
-                    if transparency == TransparencyType::Blend {
-                        desc.depth_stencil.as_mut().unwrap().depth_write_enabled = false;
-                        targets[0].as_mut().unwrap().blend = Some(BlendState::ALPHA_BLENDING)
-                    }
-                }),
-                culling_buffer_map_handle: culling_buffer_map_handle.clone(),
-            })
+                transparency,
+                blend_mode,
+                double_sided,
+                Some(id),
+            )
         };
 
-        Self {
-            opaque_depth: inner(RoutineType::Depth, &pbr_depth, TransparencyType::Opaque),
-            cutout_depth: inner(RoutineType::Depth, &pbr_depth_cutout, TransparencyType::Cutout),
-            opaque_routine: inner(RoutineType::Forward, &pbr_forward, TransparencyType::Opaque),
-            cutout_routine: inner(RoutineType::Forward, &pbr_cutout, TransparencyType::Cutout),
-            blend_routine: inner(RoutineType::Forward, &pbr_forward, TransparencyType::Blend),
-            hi_z: HiZRoutine::new(renderer, spp),
-            per_material,
+        let forward = RoutineType::Forward;
+        let prepassed = RoutineType::ForwardPrepassed;
+        let mut blended = |double_sided| {
+            BlendMode::ALL
+                .map(|blend_mode| inner(forward, &forward_module, TransparencyType::Blend, blend_mode, double_sided))
+        };
+        let blend_routine = blended(false);
+        let double_sided_blend_routine = blended(true);
+        let mut inner = |routine_type, module, transparency, double_sided| {
+            inner(routine_type, module, transparency, BlendMode::Alpha, double_sided)
+        };
+        let routines = CustomPbrRoutines {
+            opaque_routine: inner(forward, &forward_module, TransparencyType::Opaque, false),
+            cutout_routine: inner(forward, &cutout_module, TransparencyType::Cutout, false),
+            blend_routine,
+            decal_routine: inner(forward, &forward_module, TransparencyType::Decal, false),
+            double_sided_opaque_routine: inner(forward, &forward_module, TransparencyType::Opaque, true),
+            double_sided_cutout_routine: inner(forward, &cutout_module, TransparencyType::Cutout, true),
+            double_sided_blend_routine,
+            double_sided_decal_routine: inner(forward, &forward_module, TransparencyType::Decal, true),
+            prepassed_opaque_routine: inner(prepassed, &forward_module, TransparencyType::Opaque, false),
+            prepassed_cutout_routine: inner(prepassed, &forward_module, TransparencyType::Cutout, false),
+            double_sided_prepassed_opaque_routine: inner(prepassed, &forward_module, TransparencyType::Opaque, true),
+            double_sided_prepassed_cutout_routine: inner(prepassed, &forward_module, TransparencyType::Cutout, true),
+        };
+        self.custom_shaders.push(routines);
+
+        Ok(id)
+    }
+
+    /// Opaque and cutout forward routines, including those of custom shaders.
+    /// The prepassed routines only shade the fragments left visible by the
+    /// depth prepass.
+    pub fn opaque_routines(&self, prepassed: bool) -> Vec<&ForwardRoutine<PbrMaterial>> {
+        let mut routines = if prepassed {
+            vec![
+                &self.prepassed_opaque_routine,
+                &self.prepassed_cutout_routine,
+                &self.double_sided_prepassed_opaque_routine,
+                &self.double_sided_prepassed_cutout_routine,
+            ]
+        } else {
+            vec![
+                &self.opaque_routine,
+                &self.cutout_routine,
+                &self.double_sided_opaque_routine,
+                &self.double_sided_cutout_routine,
+            ]
+        };
+        for custom in &self.custom_shaders {
+            if prepassed {
+                routines.extend([
+                    &custom.prepassed_opaque_routine,
+                    &custom.prepassed_cutout_routine,
+                    &custom.double_sided_prepassed_opaque_routine,
+                    &custom.double_sided_prepassed_cutout_routine,
+                ]);
+            } else {
+                routines.extend([
+                    &custom.opaque_routine,
+                    &custom.cutout_routine,
+                    &custom.double_sided_opaque_routine,
+                    &custom.double_sided_cutout_routine,
+                ]);
+            }
         }
+        routines
+    }
+
+    /// Decal routines, including those of custom shaders.
+    pub fn decal_routines(&self) -> Vec<&ForwardRoutine<PbrMaterial>> {
+        let custom = self.custom_shaders.iter().flat_map(|c| [&c.decal_routine, &c.double_sided_decal_routine]);
+        [&self.decal_routine, &self.double_sided_decal_routine].into_iter().chain(custom).collect()
     }
+
+    /// Blended routines, including those of custom shaders.
+    pub fn blend_routines(&self) -> Vec<&ForwardRoutine<PbrMaterial>> {
+        let custom =
+            self.custom_shaders.iter().flat_map(|c| c.blend_routine.iter().chain(&c.double_sided_blend_routine));
+        self.blend_routine.iter().chain(&self.double_sided_blend_routine).chain(custom).collect()
+    }
+}
+
+/// Everything routines share, regardless of their shader and material key.
+struct RoutineArgs<'a> {
+    name: &'a str,
+    renderer: &'a Arc<Renderer>,
+    data_core: &'a mut RendererDataCore,
+    spp: &'a ShaderPreProcessor,
+    interfaces: &'a WholeFrameInterfaces,
+    per_material: &'a PerMaterialArchetypeInterface<PbrMaterial>,
+    culling_buffer_map_handle: &'a GraphDataHandle<CullingBufferMap>,
+}
+
+fn create_routine(
+    args: RoutineArgs<'_>,
+    routine_type: RoutineType,
+    shaders: ShaderModulePair<'_>,
+    transparency: TransparencyType,
+    blend_mode: BlendMode,
+    double_sided: bool,
+    custom_shader: Option<CustomShaderId>,
+) -> ForwardRoutine<PbrMaterial> {
+    let sides = if double_sided { " double sided" } else { "" };
+    let mode = if transparency == TransparencyType::Blend { format!(" {blend_mode:?}") } else { String::new() };
+    let wireframe_fs_entry = matches!(routine_type, RoutineType::Forward).then_some("fs_wireframe");
+    // Only shading differs between custom shaders, so the depth-only routines render all of them.
+    let ignored_key_bits = match routine_type {
+        RoutineType::Depth | RoutineType::DepthPrepass | RoutineType::ObjectId => CUSTOM_SHADER_KEY_BITS,
+        RoutineType::Forward | RoutineType::ForwardPrepassed => 0,
+    };
+    ForwardRoutine::new(ForwardRoutineCreateArgs {
+        name: &format!("{} {routine_type:?} {transparency:?}{mode}{sides}", args.name),
+        renderer: args.renderer,
+        data_core: args.data_core,
+        spp: args.spp,
+        interfaces: args.interfaces,
+        per_material: args.per_material,
+        material_key: pbr_material_key(transparency, blend_mode, double_sided, custom_shader),
+        ignored_key_bits,
+        routine_type,
+        shaders,
+        stencil: StencilState::default(),
+        extra_bgls: &[],
+        wireframe_fs_entry,
+        descriptor_callback: Some(&|desc, targets| {
+            if double_sided {
+                desc.primitive.cull_mode = None;
+            }
+            match transparency {
+                TransparencyType::Blend => {
+                    desc.depth_stencil.as_mut().unwrap().depth_write_enabled = false;
+                    targets[0].as_mut().unwrap().blend = Some(blend_mode.to_blend_state())
+                }
+                TransparencyType::Decal => {
+                    let depth_stencil = desc.depth_stencil.as_mut().unwrap();
+                    depth_stencil.depth_write_enabled = false;
+                    depth_stencil.bias = DECAL_DEPTH_BIAS;
+                    targets[0].as_mut().unwrap().blend = Some(BlendState::ALPHA_BLENDING)
+                }
+                TransparencyType::Opaque | TransparencyType::Cutout => {}
+            }
+        }),
+        culling_buffer_map_handle: args.culling_buffer_map_handle.clone(),
+    })
 }
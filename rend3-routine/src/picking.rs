@@ -0,0 +1,148 @@
+//! Object picking, finding which object covers a pixel of the output.
+//!
+//! The opaque and cutout objects are rendered into a separate `R32Uint` image
+//! holding the handle index of the closest object plus one, leaving zero where
+//! there is no object. The [`ObjectPicker`] copies the value at a single pixel
+//! into a buffer which can be read back once the frame has been submitted.
+//!
+//! The id image is a render target of the graph, so it always matches the
+//! resolution of the output. Transparent objects are not pickable.
+
+use std::borrow::Cow;
+
+use glam::UVec2;
+use rend3::{
+    graph::{NodeResourceUsage, RenderGraph, RenderTargetHandle},
+    types::RawObjectHandle,
+    util::{
+        bind_merge::{BindGroupBuilder, BindGroupLayoutBuilder},
+        readback::{read_buffer, ReadbackError},
+    },
+    Renderer, ShaderConfig, ShaderPreProcessor,
+};
+use wgpu::{
+    BindGroupLayout, BindingType, Buffer, BufferBindingType, BufferDescriptor, BufferUsages, ComputePassDescriptor,
+    ComputePipeline, ComputePipelineDescriptor, Device, PipelineLayoutDescriptor, ShaderModuleDescriptor, ShaderSource,
+    ShaderStages, TextureSampleType, TextureViewDimension,
+};
+
+/// Routine reading back the object at a pixel of the object id image.
+///
+/// See module for documentation.
+pub struct ObjectPicker {
+    bgl: BindGroupLayout,
+    pipeline: ComputePipeline,
+    pixel: Buffer,
+    picked: Buffer,
+    readback: Buffer,
+}
+
+impl ObjectPicker {
+    pub fn new(device: &Device, spp: &ShaderPreProcessor) -> Self {
+        profiling::scope!("ObjectPicker::new");
+
+        let bgl = BindGroupLayoutBuilder::new()
+            .append(
+                ShaderStages::COMPUTE,
+                BindingType::Texture {
+                    sample_type: TextureSampleType::Uint,
+                    view_dimension: TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                None,
+            )
+            .append_buffer(ShaderStages::COMPUTE, BufferBindingType::Uniform, false, 8)
+            .append_buffer(ShaderStages::COMPUTE, BufferBindingType::Storage { read_only: false }, false, 4)
+            .build(device, Some("object picking bgl"));
+
+        let module = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("object picking"),
+            source: ShaderSource::Wgsl(Cow::Owned(
+                spp.render_shader("rend3-routine/pick.wgsl", &ShaderConfig::default(), None).unwrap(),
+            )),
+        });
+
+        let pll = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("object picking"),
+            bind_group_layouts: &[&bgl],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_compute_pipeline(&ComputePipelineDescriptor {
+            label: Some("object picking"),
+            layout: Some(&pll),
+            module: &module,
+            entry_point: "main",
+        });
+
+        let pixel = device.create_buffer(&BufferDescriptor {
+            label: Some("picked pixel"),
+            size: 8,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let picked = device.create_buffer(&BufferDescriptor {
+            label: Some("picked object"),
+            size: 4,
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let readback = device.create_buffer(&BufferDescriptor {
+            label: Some("picked object readback"),
+            size: 4,
+            usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        Self { bgl, pipeline, pixel, picked, readback }
+    }
+
+    /// Copies the object id at `pixel` of `object_ids` into the readback
+    /// buffer. The pixel must be inside of the image.
+    pub fn add_to_graph<'node>(
+        &'node self,
+        graph: &mut RenderGraph<'node>,
+        object_ids: RenderTargetHandle,
+        pixel: UVec2,
+    ) {
+        let mut builder = graph.add_node("Object Picking");
+        builder.add_side_effect();
+
+        let ids_handle = builder.add_render_target(object_ids, NodeResourceUsage::Input);
+
+        builder.build(move |mut ctx| {
+            let encoder = ctx.encoder_or_pass.take_encoder();
+            let object_ids = ctx.graph_data.get_render_target(ids_handle);
+
+            profiling::scope!("object picking");
+
+            ctx.renderer.queue.write_buffer(&self.pixel, 0, bytemuck::bytes_of(&pixel));
+
+            let bg = BindGroupBuilder::new()
+                .append_texture_view(object_ids)
+                .append_buffer(&self.pixel)
+                .append_buffer(&self.picked)
+                .build(&ctx.renderer.device, Some("object picking bg"), &self.bgl);
+
+            let mut cpass = encoder
+                .begin_compute_pass(&ComputePassDescriptor { label: Some("Object Picking"), timestamp_writes: None });
+            cpass.set_pipeline(&self.pipeline);
+            cpass.set_bind_group(0, &bg, &[]);
+            cpass.dispatch_workgroups(1, 1, 1);
+            drop(cpass);
+
+            encoder.copy_buffer_to_buffer(&self.picked, 0, &self.readback, 0, 4);
+        });
+    }
+
+    /// Reads back the object picked by the last submitted frame which picked
+    /// one, or `None` if there was no object at the pixel or it has since
+    /// been removed.
+    ///
+    /// Check the handle with [`Renderer::is_alive`] before using it later.
+    pub async fn read(&self, renderer: &Renderer) -> Result<Option<RawObjectHandle>, ReadbackError> {
+        let data = read_buffer(&renderer.device, &self.readback).await?;
+        let id = u32::from_le_bytes(data[..4].try_into().unwrap());
+        Ok(id.checked_sub(1).and_then(|idx| renderer.live_handle(idx as usize)))
+    }
+}
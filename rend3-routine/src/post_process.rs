@@ -0,0 +1,149 @@
+//! Custom passes run on the tonemapped image, before anti-aliasing.
+//!
+//! Register passes on [`BaseRenderGraph::post_process`](crate::base::BaseRenderGraph::post_process).
+//! Every pass has a name, which identifies it when removing it, and an order.
+//! Passes run in ascending order, and passes with the same order run in the
+//! order they were added. Each pass reads the output of the previous one, the
+//! first reads the tonemapped image, and the last writes the image that is
+//! anti-aliased or written to the output.
+
+use glam::UVec2;
+use rend3::{
+    graph::{DataHandle, RenderGraph, RenderTargetDescriptor, RenderTargetHandle},
+    types::{SampleCount, TextureFormat, TextureUsages, WasmNotSend, WasmNotSync},
+    util::typedefs::SsoString,
+};
+use wgpu::BindGroup;
+
+/// Arguments of [`PostProcessPass::add_to_graph`].
+pub struct PostProcessArgs<'a, 'node> {
+    pub graph: &'a mut RenderGraph<'node>,
+    /// Name the pass was registered with.
+    pub label: &'a str,
+    /// Image to read, single sampled with a resolution of `resolution`.
+    pub input: RenderTargetHandle,
+    /// Target to write, with the same resolution and format as `input`. All of
+    /// it must be written.
+    pub output: RenderTargetHandle,
+    pub resolution: UVec2,
+    pub format: TextureFormat,
+    pub forward_uniform_bg: DataHandle<BindGroup>,
+}
+
+/// A pass in the post processing chain.
+pub trait PostProcessPass: WasmNotSend + WasmNotSync {
+    /// Adds the nodes reading `args.input` and writing `args.output`.
+    fn add_to_graph<'node>(&'node self, args: PostProcessArgs<'_, 'node>);
+}
+
+struct PostProcessEntry {
+    name: SsoString,
+    order: i32,
+    pass: Box<dyn PostProcessPass>,
+}
+
+/// Ordered list of post processing passes.
+///
+/// See module for documentation.
+#[derive(Default)]
+pub struct PostProcessChain {
+    /// Sorted by order, then by the time they were added.
+    passes: Vec<PostProcessEntry>,
+}
+
+impl PostProcessChain {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a pass, running after all passes with a lower or equal `order`.
+    pub fn add(&mut self, name: impl Into<SsoString>, order: i32, pass: impl PostProcessPass + 'static) {
+        let index = self.passes.partition_point(|entry| entry.order <= order);
+        self.passes.insert(index, PostProcessEntry { name: name.into(), order, pass: Box::new(pass) });
+    }
+
+    /// Removes all passes with the given name. Returns whether there were any.
+    pub fn remove(&mut self, name: &str) -> bool {
+        let count = self.passes.len();
+        self.passes.retain(|entry| entry.name != name);
+        self.passes.len() != count
+    }
+
+    /// Names and orders of the passes, in the order they run.
+    pub fn passes(&self) -> impl Iterator<Item = (&str, i32)> {
+        self.passes.iter().map(|entry| (entry.name.as_str(), entry.order))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.passes.is_empty()
+    }
+
+    /// Runs all passes, from `input` to `output`. Intermediate images are
+    /// created with the given resolution and format.
+    ///
+    /// Does nothing when there are no passes, so `output` isn't written.
+    pub fn add_to_graph<'node>(
+        &'node self,
+        graph: &mut RenderGraph<'node>,
+        input: RenderTargetHandle,
+        output: RenderTargetHandle,
+        resolution: UVec2,
+        format: TextureFormat,
+        forward_uniform_bg: DataHandle<BindGroup>,
+    ) {
+        let mut input = input;
+        for (index, entry) in self.passes.iter().enumerate() {
+            let output = if index + 1 == self.passes.len() {
+                output
+            } else {
+                graph.add_render_target(RenderTargetDescriptor {
+                    label: Some(entry.name.clone()),
+                    resolution,
+                    depth: 1,
+                    mip_levels: Some(1),
+                    samples: SampleCount::One,
+                    format,
+                    usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+                })
+            };
+
+            entry.pass.add_to_graph(PostProcessArgs {
+                graph,
+                label: &entry.name,
+                input,
+                output,
+                resolution,
+                format,
+                forward_uniform_bg,
+            });
+            input = output;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PostProcessArgs, PostProcessChain, PostProcessPass};
+
+    struct Noop;
+
+    impl PostProcessPass for Noop {
+        fn add_to_graph<'node>(&'node self, _args: PostProcessArgs<'_, 'node>) {}
+    }
+
+    #[test]
+    fn ordering() {
+        let mut chain = PostProcessChain::new();
+        chain.add("vignette", 10, Noop);
+        chain.add("blur", 0, Noop);
+        chain.add("grain", 10, Noop);
+        chain.add("sharpen", -5, Noop);
+
+        let names: Vec<_> = chain.passes().map(|(name, _)| name).collect();
+        assert_eq!(names, ["sharpen", "blur", "vignette", "grain"]);
+
+        assert!(chain.remove("blur"));
+        assert!(!chain.remove("blur"));
+        assert_eq!(chain.passes().count(), 3);
+    }
+}
@@ -1,7 +1,8 @@
-use std::{borrow::Cow, mem};
+use std::borrow::Cow;
 
 use encase::{ShaderSize, ShaderType};
 use glam::Mat4;
+use parking_lot::Mutex;
 use rend3::{
     graph::{NodeExecutionContext, RenderGraph},
     types::{
@@ -10,13 +11,14 @@ use rend3::{
     },
     util::{
         bind_merge::{BindGroupBuilder, BindGroupLayoutBuilder},
+        buffer::WrappedPotBuffer,
         math::div_round_up,
     },
     ShaderPreProcessor,
 };
 use wgpu::{
-    BindGroupLayout, Buffer, BufferBindingType, BufferDescriptor, BufferUsages, CommandEncoder, ComputePassDescriptor,
-    ComputePipeline, ComputePipelineDescriptor, PipelineLayoutDescriptor, ShaderModuleDescriptor, ShaderStages,
+    BindGroupLayout, BufferBindingType, BufferUsages, CommandEncoder, ComputePassDescriptor, ComputePipeline,
+    ComputePipelineDescriptor, Device, PipelineLayoutDescriptor, ShaderModuleDescriptor, ShaderStages,
 };
 
 /// The per-skeleton data, as uploaded to the GPU compute shader.
@@ -43,46 +45,54 @@ pub struct GpuSkinningInput {
     joint_matrix_base_offset: u32,
     /// Count of vertices in this mesh.
     vertex_count: u32,
-}
 
-/// The two buffers uploaded to the GPU during pre-skinning.
-pub struct PreSkinningBuffers {
-    gpu_skinning_inputs: Buffer,
-    joint_matrices: Buffer,
+    /// Byte offset into vertex buffer of the position offsets of all morph targets.
+    morph_position_offset: u32,
+    /// Byte offset into vertex buffer of the normal offsets of all morph targets.
+    morph_normal_offset: u32,
+    /// Count of morph targets of the mesh, 0 to skip blending them.
+    morph_target_count: u32,
+    /// Index into the morph weight buffer of the weight of the first morph target.
+    morph_weight_base_offset: u32,
 }
 
-fn build_gpu_skinning_input_buffers(ctx: &NodeExecutionContext) -> PreSkinningBuffers {
-    profiling::scope!("Building GPU Skinning Input Data");
+/// A [`GpuSkinningInput`] padded to the alignment of dynamic storage buffer
+/// offsets, so the input of every skeleton can be bound at its own offset.
+#[derive(Copy, Clone, ShaderType)]
+struct AlignedSkinningInput {
+    #[size(256)]
+    input: GpuSkinningInput,
+}
 
-    let skinning_inputs_size =
-        ctx.data_core.skeleton_manager.skeletons().len() as u64 * GpuSkinningInput::SHADER_SIZE.get();
-    let gpu_skinning_inputs = ctx.renderer.device.create_buffer(&BufferDescriptor {
-        label: Some("skinning inputs"),
-        size: skinning_inputs_size,
-        usage: BufferUsages::STORAGE,
-        mapped_at_creation: true,
-    });
+/// The three buffers uploaded to the GPU during pre-skinning.
+///
+/// They are kept across frames, as joint matrices and morph weights of
+/// animated skeletons change every frame. They are only reallocated when the skeletons or joints
+/// outgrow them or shrink to a fraction of their size.
+pub struct PreSkinningBuffers {
+    gpu_skinning_inputs: WrappedPotBuffer<Vec<AlignedSkinningInput>>,
+    joint_matrices: WrappedPotBuffer<Vec<Mat4>>,
+    morph_weights: WrappedPotBuffer<Vec<f32>>,
+}
 
-    let joint_matrices = ctx.renderer.device.create_buffer(&BufferDescriptor {
-        label: Some("joint matrices"),
-        size: (ctx.data_core.skeleton_manager.global_joint_count() * mem::size_of::<Mat4>()) as u64,
-        usage: BufferUsages::STORAGE,
-        mapped_at_creation: true,
-    });
+impl PreSkinningBuffers {
+    pub fn new(device: &Device) -> Self {
+        Self {
+            gpu_skinning_inputs: WrappedPotBuffer::new(device, BufferUsages::STORAGE, "skinning inputs"),
+            joint_matrices: WrappedPotBuffer::new(device, BufferUsages::STORAGE, "joint matrices"),
+            morph_weights: WrappedPotBuffer::new(device, BufferUsages::STORAGE, "morph weights"),
+        }
+    }
 
-    let mut skinning_input_range = gpu_skinning_inputs.slice(..).get_mapped_range_mut();
-    let mut skinning_input_data = encase::DynamicStorageBuffer::new(&mut *skinning_input_range);
-    let mut joint_matrices_data = joint_matrices.slice(..).get_mapped_range_mut();
+    fn update(&mut self, ctx: &NodeExecutionContext) {
+        profiling::scope!("Building GPU Skinning Input Data");
 
-    // Skeletons have a variable number of joints, so we need to keep track of
-    // the global index here.
-    let mut joint_matrix_idx = 0;
+        let skeletons = ctx.data_core.skeleton_manager.skeletons();
+        let mut inputs = Vec::with_capacity(skeletons.len());
+        let mut joint_matrices = Vec::with_capacity(ctx.data_core.skeleton_manager.global_joint_count());
+        let mut morph_weights = Vec::with_capacity(ctx.data_core.skeleton_manager.global_morph_weight_count());
 
-    // Iterate over the skeletons, fill the buffers
-    for skeleton in ctx.data_core.skeleton_manager.skeletons() {
-        // SAFETY: We are always accessing elements in bounds and all accesses are
-        // aligned
-        unsafe {
+        for skeleton in skeletons {
             let mut input = GpuSkinningInput {
                 base_position_offset: u32::MAX,
                 base_normal_offset: u32::MAX,
@@ -92,10 +102,24 @@ fn build_gpu_skinning_input_buffers(ctx: &NodeExecutionContext) -> PreSkinningBu
                 updated_position_offset: u32::MAX,
                 updated_normal_offset: u32::MAX,
                 updated_tangent_offset: u32::MAX,
-                joint_matrix_base_offset: joint_matrix_idx,
+                // Skeletons have a variable number of joints, so their matrices
+                // start after those of all the previous skeletons.
+                joint_matrix_base_offset: joint_matrices.len() as u32,
                 vertex_count: skeleton.vertex_count,
+                morph_position_offset: u32::MAX,
+                morph_normal_offset: u32::MAX,
+                morph_target_count: 0,
+                morph_weight_base_offset: morph_weights.len() as u32,
             };
 
+            if let Some(morph_targets) = &skeleton.morph_targets {
+                input.morph_position_offset = morph_targets.position_range.start as u32;
+                if let Some(range) = &morph_targets.normal_range {
+                    input.morph_normal_offset = range.start as u32;
+                }
+                input.morph_target_count = morph_targets.count;
+            }
+
             for (attribute, range) in &skeleton.source_attribute_ranges {
                 match attribute {
                     a if *a == *VERTEX_ATTRIBUTE_POSITION => input.base_position_offset = range.start as u32,
@@ -116,32 +140,25 @@ fn build_gpu_skinning_input_buffers(ctx: &NodeExecutionContext) -> PreSkinningBu
                 }
             }
 
-            skinning_input_data.write(&input).unwrap();
-
-            let joint_matrices_ptr = joint_matrices_data.as_mut_ptr() as *mut [[f32; 4]; 4];
-            for joint_matrix in &skeleton.joint_matrices {
-                // Here, the access can't be OOB either: The joint_matrix_idx
-                // will get incremented once for every joint matrix, and the
-                // length of the buffer is exactly the sum of all joint matrix
-                // vector lengths.
-                joint_matrices_ptr.add(joint_matrix_idx as usize).write_unaligned(joint_matrix.to_cols_array_2d());
-                joint_matrix_idx += 1;
-            }
+            inputs.push(AlignedSkinningInput { input });
+            joint_matrices.extend_from_slice(&skeleton.joint_matrices);
+            morph_weights.extend_from_slice(&skeleton.morph_weights);
         }
-    }
-
-    drop(skinning_input_range);
-    drop(joint_matrices_data);
-    gpu_skinning_inputs.unmap();
-    joint_matrices.unmap();
 
-    PreSkinningBuffers { gpu_skinning_inputs, joint_matrices }
+        let device = &ctx.renderer.device;
+        let queue = &ctx.renderer.queue;
+        // The inputs only change when skeletons are added or removed.
+        self.gpu_skinning_inputs.write_changed_to_buffer(device, queue, &inputs);
+        self.joint_matrices.write_to_buffer(device, queue, &joint_matrices);
+        self.morph_weights.write_to_buffer(device, queue, &morph_weights);
+    }
 }
 
 /// Holds the necessary wgpu data structures for the GPU skinning compute pass
 pub struct GpuSkinner {
     pub pipeline: ComputePipeline,
     pub bgl: BindGroupLayout,
+    buffers: Mutex<PreSkinningBuffers>,
 }
 
 impl GpuSkinner {
@@ -153,6 +170,7 @@ impl GpuSkinner {
             .append_buffer(ShaderStages::COMPUTE, BufferBindingType::Storage { read_only: false }, false, 4) // Vertices
             .append_buffer(ShaderStages::COMPUTE, BufferBindingType::Storage { read_only: true }, true, GpuSkinningInput::SHADER_SIZE.get()) // Inputs
             .append_buffer(ShaderStages::COMPUTE, BufferBindingType::Storage { read_only: true }, false, Mat4::SHADER_SIZE.get()) // Matrices
+            .append_buffer(ShaderStages::COMPUTE, BufferBindingType::Storage { read_only: true }, false, f32::SHADER_SIZE.get()) // Morph weights
             .build(device, Some("Gpu skinning mesh data"));
 
         let layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
@@ -175,7 +193,7 @@ impl GpuSkinner {
             entry_point: "main",
         });
 
-        Self { bgl, pipeline }
+        Self { bgl, pipeline, buffers: Mutex::new(PreSkinningBuffers::new(device)) }
     }
 
     pub fn execute_pass(&self, ctx: &NodeExecutionContext, encoder: &mut CommandEncoder, buffers: &PreSkinningBuffers) {
@@ -183,13 +201,14 @@ impl GpuSkinner {
             .append_buffer(&ctx.eval_output.mesh_buffer)
             .append_buffer_with_size(&buffers.gpu_skinning_inputs, GpuSkinningInput::SHADER_SIZE.get())
             .append_buffer(&buffers.joint_matrices)
+            .append_buffer(&buffers.morph_weights)
             .build(&ctx.renderer.device, Some("GPU skinning inputs"), &self.bgl);
 
         let mut cpass =
             encoder.begin_compute_pass(&ComputePassDescriptor { label: Some("GPU Skinning"), timestamp_writes: None });
         cpass.set_pipeline(&self.pipeline);
         for (i, skel) in ctx.data_core.skeleton_manager.skeletons().enumerate() {
-            let offset = (i as u64 * GpuSkinningInput::SHADER_SIZE.get()) as u32;
+            let offset = (i as u64 * AlignedSkinningInput::SHADER_SIZE.get()) as u32;
             cpass.set_bind_group(0, &bg, &[offset]);
 
             let num_workgroups = div_round_up(skel.vertex_count, Self::WORKGROUP_SIZE);
@@ -215,12 +234,26 @@ pub fn add_skinning_to_graph<'node>(graph: &mut RenderGraph<'node>, gpu_skinner:
     builder.build(move |mut ctx| {
         let encoder = ctx.encoder_or_pass.take_encoder();
 
-        let skinning_input = build_gpu_skinning_input_buffers(&ctx);
-
         // Avoid running the compute pass if there are no skeletons. This
         // prevents binding an empty buffer
         if ctx.data_core.skeleton_manager.skeletons().len() > 0 {
-            gpu_skinner.execute_pass(&ctx, encoder, &skinning_input);
+            let mut buffers = gpu_skinner.buffers.lock();
+            buffers.update(&ctx);
+            gpu_skinner.execute_pass(&ctx, encoder, &buffers);
         }
     });
 }
+
+#[cfg(test)]
+mod tests {
+    use encase::ShaderSize;
+
+    use super::{AlignedSkinningInput, GpuSkinningInput};
+
+    #[test]
+    fn inputs_aligned_to_dynamic_offsets() {
+        // The maximum value of `min_storage_buffer_offset_alignment`, valid on every device.
+        assert_eq!(AlignedSkinningInput::SHADER_SIZE.get(), 256);
+        assert!(GpuSkinningInput::SHADER_SIZE.get() <= 256);
+    }
+}
@@ -1,25 +1,122 @@
-//! Routine that renders a cubemap as a skybox.
+//! Routine that renders a cubemap, an equirectangular texture, a procedural
+//! sky or a user provided shader as a skybox.
 
 use std::borrow::Cow;
 
+use encase::{ShaderSize, ShaderType, UniformBuffer};
+use glam::Vec3;
+use naga::{
+    front::wgsl::ParseError,
+    valid::{Capabilities, ValidationError, ValidationFlags, Validator},
+    Scalar, TypeInner, VectorSize, WithSpan,
+};
 use rend3::{
     graph::{DataHandle, NodeResourceUsage, RenderGraph, RenderPassTargets},
-    types::{SampleCount, TextureCubeHandle},
-    util::bind_merge::{BindGroupBuilder, BindGroupLayoutBuilder},
+    types::{SampleCount, Texture2DHandle, TextureCubeHandle},
+    util::{
+        bind_merge::{BindGroupBuilder, BindGroupLayoutBuilder},
+        error_scope::ValidationErrorScope,
+    },
     Renderer, ShaderConfig, ShaderPreProcessor,
 };
+use serde::Serialize;
+use thiserror::Error;
 use wgpu::{
-    BindGroup, BindGroupLayout, BindingType, ColorTargetState, ColorWrites, CompareFunction, DepthBiasState,
-    DepthStencilState, Face, FragmentState, FrontFace, MultisampleState, PipelineLayoutDescriptor, PolygonMode,
-    PrimitiveState, PrimitiveTopology, RenderPipeline, RenderPipelineDescriptor, ShaderModuleDescriptor, ShaderSource,
-    ShaderStages, StencilState, TextureFormat, TextureSampleType, TextureViewDimension, VertexState,
+    BindGroup, BindGroupLayout, BindingType, Buffer, BufferBindingType, BufferDescriptor, BufferUsages,
+    ColorTargetState, ColorWrites, CompareFunction, DepthBiasState, DepthStencilState, Face, FragmentState, FrontFace,
+    MultisampleState, PipelineLayoutDescriptor, PolygonMode, PrimitiveState, PrimitiveTopology, RenderPipeline,
+    RenderPipelineDescriptor, ShaderModule, ShaderModuleDescriptor, ShaderSource, ShaderStages, StencilState,
+    TextureFormat, TextureSampleType, TextureViewDimension, VertexState,
+};
+
+use crate::{
+    common::WholeFrameInterfaces,
+    ibl::{EnvironmentMaps, IblRoutine},
 };
 
-use crate::common::WholeFrameInterfaces;
+/// Name of the function a [`CustomSkyboxShader`] must define.
+pub const CUSTOM_SKYBOX_FUNCTION: &str = "skybox_color";
+
+/// A user provided shader which computes the color of the sky.
+///
+/// The source is WGSL which must define
+/// `fn skybox_color(direction: vec3<f32>) -> vec4<f32>`, returning the hdr
+/// color of the sky in the given normalized world space direction. Like the
+/// background texture, the color is scaled by
+/// [`BaseRenderGraphSettings::background_intensity`](crate::base::BaseRenderGraphSettings::background_intensity).
+///
+/// The shader can use the frame uniforms as `uniforms`, which includes the
+/// time in `uniforms.time`, and `primary_sampler`. If `uniforms` is given, the
+/// shader declares it as `@group(1) @binding(0) var<uniform>`.
+pub struct CustomSkyboxShader<'a> {
+    pub label: &'a str,
+    pub source: &'a str,
+    pub uniforms: Option<&'a Buffer>,
+}
+
+/// Error returned when a [`CustomSkyboxShader`] is invalid.
+#[derive(Debug, Error)]
+pub enum SkyboxShaderError {
+    #[error("Failed to parse custom skybox shader")]
+    Parse(#[source] ParseError),
+    #[error("Custom skybox shader must define `fn skybox_color(direction: vec3<f32>) -> vec4<f32>`")]
+    MissingFunction,
+    #[error("Custom skybox shader failed validation")]
+    Validation(#[source] Box<WithSpan<ValidationError>>),
+}
+
+/// Parameters of the built-in procedural sky.
+///
+/// The sky follows the Preetham daylight model, with the sun in the direction
+/// the first directional light comes from. Like the background texture, the
+/// color is scaled by
+/// [`BaseRenderGraphSettings::background_intensity`](crate::base::BaseRenderGraphSettings::background_intensity).
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ProceduralSky {
+    /// Haziness of the atmosphere, from 2 for a clear sky to 10 for a hazy one.
+    pub turbidity: f32,
+    /// Color of the ground below the horizon, as a fraction of the light of the sky
+    /// at the horizon.
+    pub ground_albedo: Vec3,
+}
+
+impl Default for ProceduralSky {
+    fn default() -> Self {
+        Self { turbidity: 3.0, ground_albedo: Vec3::splat(0.3) }
+    }
+}
+
+#[derive(ShaderType)]
+struct ShaderProceduralSky {
+    ground_albedo: Vec3,
+    turbidity: f32,
+}
+
+struct StoredProceduralSky {
+    pipelines: SkyboxPipelines,
+    buffer: Buffer,
+    bg: BindGroup,
+    sky: Option<ProceduralSky>,
+}
+
+#[derive(Serialize)]
+struct CustomSkyboxArgs {
+    fragment: bool,
+}
+
+enum BackgroundHandle {
+    Cube(TextureCubeHandle),
+    Equirect(Texture2DHandle),
+}
 
 struct StoredSkybox {
     bg: Option<BindGroup>,
-    handle: Option<TextureCubeHandle>,
+    handle: Option<BackgroundHandle>,
+}
+
+struct CustomSkybox {
+    pipelines: SkyboxPipelines,
+    bg: BindGroup,
 }
 
 /// Skybox rendering routine.
@@ -28,55 +125,256 @@ struct StoredSkybox {
 pub struct SkyboxRoutine {
     pipelines: SkyboxPipelines,
     bgl: BindGroupLayout,
+    equirect_pipelines: SkyboxPipelines,
+    equirect_bgl: BindGroupLayout,
     current_skybox: StoredSkybox,
+    procedural_sky: StoredProceduralSky,
+    custom_skybox: Option<CustomSkybox>,
+    ibl: IblRoutine,
 }
 
 impl SkyboxRoutine {
     /// Create the routine.
     pub fn new(renderer: &Renderer, spp: &ShaderPreProcessor, interfaces: &WholeFrameInterfaces) -> Self {
-        let bgl = BindGroupLayoutBuilder::new()
+        let texture_bgl = |view_dimension, label| {
+            BindGroupLayoutBuilder::new()
+                .append(
+                    ShaderStages::FRAGMENT,
+                    BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: true },
+                        view_dimension,
+                        multisampled: false,
+                    },
+                    None,
+                )
+                .build(&renderer.device, Some(label))
+        };
+        let bgl = texture_bgl(TextureViewDimension::Cube, "skybox bgl");
+        let equirect_bgl = texture_bgl(TextureViewDimension::D2, "equirect skybox bgl");
+
+        let pipelines = SkyboxPipelines::new(renderer, spp, interfaces, &bgl);
+        let equirect_pipelines = SkyboxPipelines::from_shader(
+            renderer,
+            spp,
+            interfaces,
+            &equirect_bgl,
+            "rend3-routine/skybox_equirect.wgsl",
+        );
+
+        let procedural_bgl = BindGroupLayoutBuilder::new()
             .append(
                 ShaderStages::FRAGMENT,
-                BindingType::Texture {
-                    sample_type: TextureSampleType::Float { filterable: true },
-                    view_dimension: TextureViewDimension::Cube,
-                    multisampled: false,
+                BindingType::Buffer {
+                    ty: BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: Some(ShaderProceduralSky::min_size()),
                 },
                 None,
             )
-            .build(&renderer.device, Some("skybox bgl"));
+            .build(&renderer.device, Some("procedural skybox bgl"));
+        let procedural_buffer = renderer.device.create_buffer(&BufferDescriptor {
+            label: Some("procedural skybox buffer"),
+            size: ShaderProceduralSky::SHADER_SIZE.get(),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let procedural_bg = BindGroupBuilder::new().append_buffer(&procedural_buffer).build(
+            &renderer.device,
+            Some("procedural skybox"),
+            &procedural_bgl,
+        );
+        let procedural_pipelines = SkyboxPipelines::from_shader(
+            renderer,
+            spp,
+            interfaces,
+            &procedural_bgl,
+            "rend3-routine/skybox_procedural.wgsl",
+        );
 
-        let pipelines = SkyboxPipelines::new(renderer, spp, interfaces, &bgl);
+        Self {
+            current_skybox: StoredSkybox { bg: None, handle: None },
+            procedural_sky: StoredProceduralSky {
+                pipelines: procedural_pipelines,
+                buffer: procedural_buffer,
+                bg: procedural_bg,
+                sky: None,
+            },
+            bgl,
+            pipelines,
+            equirect_bgl,
+            equirect_pipelines,
+            custom_skybox: None,
+            ibl: IblRoutine::new(renderer, spp),
+        }
+    }
 
-        Self { current_skybox: StoredSkybox { bg: None, handle: None }, bgl, pipelines }
+    /// Recompile the shaders of the routine from `spp`, keeping the background
+    /// and custom shader. The environment maps are prefiltered again the next
+    /// time the routine is evaluated.
+    ///
+    /// If the shaders fail to compile, the error is returned and the routine is
+    /// left unchanged.
+    pub fn reload_shaders(
+        &mut self,
+        renderer: &Renderer,
+        spp: &ShaderPreProcessor,
+        interfaces: &WholeFrameInterfaces,
+    ) -> Result<(), wgpu::Error> {
+        profiling::scope!("Reload Skybox Shaders");
+
+        let scope = ValidationErrorScope::new(&renderer.device);
+        let mut reloaded = Self::new(renderer, spp, interfaces);
+        scope.end()?;
+
+        reloaded.current_skybox.handle = self.current_skybox.handle.take();
+        reloaded.procedural_sky.sky = self.procedural_sky.sky;
+        reloaded.custom_skybox = self.custom_skybox.take();
+        *self = reloaded;
+
+        Ok(())
     }
 
     /// Set the current background texture. Bad things will happen if this isn't
     /// a cube texture.
+    ///
+    /// The texture also lights objects receiving ambient light, replacing the
+    /// ambient color. It is prefiltered for that once, the next time the
+    /// routine is evaluated.
     pub fn set_background_texture(&mut self, texture: Option<TextureCubeHandle>) {
-        self.current_skybox.handle = texture;
+        self.current_skybox.handle = texture.map(BackgroundHandle::Cube);
+        self.current_skybox.bg = None;
+        self.ibl.disable();
+    }
+
+    /// Set an equirectangular 2D texture, like a panorama, as the background
+    /// texture. Replaces the texture given to [`Self::set_background_texture`].
+    ///
+    /// The texture is sampled directly every frame, without mipmaps. Converting
+    /// it with [`Renderer::equirect_to_cubemap`] once is cheaper to sample, and
+    /// lights objects like a cube texture does.
+    pub fn set_background_equirect(&mut self, texture: Option<Texture2DHandle>) {
+        self.current_skybox.handle = texture.map(BackgroundHandle::Equirect);
         self.current_skybox.bg = None;
+        self.ibl.disable();
+    }
+
+    /// Set the procedural sky rendered when there is no background texture.
+    /// Passing `None` turns it off.
+    pub fn set_procedural_sky(&mut self, sky: Option<ProceduralSky>) {
+        self.procedural_sky.sky = sky;
+    }
+
+    /// Set a shader to compute the background with, instead of the background
+    /// texture. Passing `None` goes back to using the background texture.
+    ///
+    /// The shader is validated before it is compiled, so an invalid shader
+    /// returns an error instead of causing a wgpu validation error.
+    pub fn set_custom_shader(
+        &mut self,
+        renderer: &Renderer,
+        spp: &ShaderPreProcessor,
+        interfaces: &WholeFrameInterfaces,
+        shader: Option<CustomSkyboxShader<'_>>,
+    ) -> Result<(), SkyboxShaderError> {
+        profiling::scope!("Set Custom Skybox");
+
+        let Some(shader) = shader else {
+            self.custom_skybox = None;
+            return Ok(());
+        };
+
+        let source = build_custom_skybox_source(spp, shader.source)?;
+
+        let sm = renderer.device.create_shader_module(ShaderModuleDescriptor {
+            label: Some(shader.label),
+            source: ShaderSource::Wgsl(Cow::Owned(source)),
+        });
+
+        let mut bglb = BindGroupLayoutBuilder::new();
+        let mut bgb = BindGroupBuilder::new();
+        if let Some(uniforms) = shader.uniforms {
+            bglb.append(
+                ShaderStages::FRAGMENT,
+                BindingType::Buffer {
+                    ty: BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                None,
+            );
+            bgb.append_buffer(uniforms);
+        }
+        let bgl = bglb.build(&renderer.device, Some(shader.label));
+        let bg = bgb.build(&renderer.device, Some(shader.label), &bgl);
+
+        let pipelines = SkyboxPipelines::from_module(renderer, interfaces, &bgl, &sm);
+
+        self.custom_skybox = Some(CustomSkybox { pipelines, bg });
+
+        Ok(())
     }
 
     /// Evaluate any changes that have happened to the skybox routine.
     pub fn evaluate(&mut self, renderer: &Renderer) {
         let data_core = renderer.data_core.lock();
-        let d2c_texture_manager = &data_core.d2c_texture_manager;
 
         profiling::scope!("Update Skybox");
 
+        if let Some(sky) = self.procedural_sky.sky {
+            let mut data = UniformBuffer::new(Vec::new());
+            data.write(&ShaderProceduralSky { ground_albedo: sky.ground_albedo, turbidity: sky.turbidity }).unwrap();
+            renderer.queue.write_buffer(&self.procedural_sky.buffer, 0, &data.into_inner());
+        }
+
         if let Some(ref handle) = self.current_skybox.handle {
             if self.current_skybox.bg.is_none() {
-                let bg = BindGroupBuilder::new()
-                    .append_texture_view(d2c_texture_manager.get_view(handle.get_raw()))
-                    .build(&renderer.device, Some("skybox"), &self.bgl);
+                let (view, bgl) = match handle {
+                    BackgroundHandle::Cube(handle) => {
+                        let view = data_core.d2c_texture_manager.get_view(handle.get_raw());
+                        self.ibl.prefilter(renderer, view);
+                        (view, &self.bgl)
+                    }
+                    BackgroundHandle::Equirect(handle) => {
+                        (data_core.d2_texture_manager.get_view(handle.get_raw()), &self.equirect_bgl)
+                    }
+                };
+                let bg = BindGroupBuilder::new().append_texture_view(view).build(&renderer.device, Some("skybox"), bgl);
 
                 self.current_skybox.bg = Some(bg)
             }
         }
     }
 
-    /// Add rendering the skybox to the given rendergraph.
+    /// Maps lighting objects with the background cubemap. They are disabled
+    /// while there is no background cubemap.
+    pub fn environment_maps(&self) -> &EnvironmentMaps {
+        self.ibl.maps()
+    }
+
+    /// Returns true if the skybox draws a background, either from a custom
+    /// shader, a background texture, or the procedural sky. When it doesn't,
+    /// the clear color of the renderpass is left as the background.
+    pub fn has_background(&self) -> bool {
+        self.background().is_some()
+    }
+
+    fn background(&self) -> Option<(&SkyboxPipelines, &BindGroup)> {
+        if let Some(ref custom) = self.custom_skybox {
+            return Some((&custom.pipelines, &custom.bg));
+        }
+
+        let pipelines = match self.current_skybox.handle {
+            Some(BackgroundHandle::Equirect(_)) => &self.equirect_pipelines,
+            _ => &self.pipelines,
+        };
+        match self.current_skybox.bg {
+            Some(ref bg) => Some((pipelines, bg)),
+            None => self.procedural_sky.sky.map(|_| (&self.procedural_sky.pipelines, &self.procedural_sky.bg)),
+        }
+    }
+
+    /// Add rendering the skybox to the given rendergraph. Nothing is added if
+    /// there is no background to draw, see [`Self::has_background`].
     pub fn add_to_graph<'node>(
         &'node self,
         graph: &mut RenderGraph<'node>,
@@ -84,6 +382,10 @@ impl SkyboxRoutine {
         forward_uniform_bg: DataHandle<BindGroup>,
         samples: SampleCount,
     ) {
+        let Some((pipelines, bg)) = self.background() else {
+            return;
+        };
+
         let mut builder = graph.add_node("Skybox");
 
         let rpass_handle = builder.add_renderpass(renderpass, NodeResourceUsage::InputOutput);
@@ -95,17 +397,15 @@ impl SkyboxRoutine {
 
             let forward_uniform_bg = ctx.graph_data.get_data(ctx.temps, forward_uniform_handle).unwrap();
 
-            if let Some(ref bg) = self.current_skybox.bg {
-                let pipeline = match samples {
-                    SampleCount::One => &self.pipelines.pipeline_s1,
-                    SampleCount::Four => &self.pipelines.pipeline_s4,
-                };
+            let pipeline = match samples {
+                SampleCount::One => &pipelines.pipeline_s1,
+                SampleCount::Four => &pipelines.pipeline_s4,
+            };
 
-                rpass.set_pipeline(pipeline);
-                rpass.set_bind_group(0, forward_uniform_bg, &[]);
-                rpass.set_bind_group(1, bg, &[]);
-                rpass.draw(0..3, 0..1);
-            }
+            rpass.set_pipeline(pipeline);
+            rpass.set_bind_group(0, forward_uniform_bg, &[]);
+            rpass.set_bind_group(1, bg, &[]);
+            rpass.draw(0..3, 0..1);
         });
     }
 }
@@ -121,15 +421,34 @@ impl SkyboxPipelines {
         spp: &ShaderPreProcessor,
         interfaces: &WholeFrameInterfaces,
         bgl: &BindGroupLayout,
+    ) -> Self {
+        Self::from_shader(renderer, spp, interfaces, bgl, "rend3-routine/skybox.wgsl")
+    }
+
+    fn from_shader(
+        renderer: &Renderer,
+        spp: &ShaderPreProcessor,
+        interfaces: &WholeFrameInterfaces,
+        bgl: &BindGroupLayout,
+        shader: &str,
     ) -> Self {
         profiling::scope!("build skybox pipeline");
         let skybox_sm = renderer.device.create_shader_module(ShaderModuleDescriptor {
             label: Some("skybox vert"),
-            source: ShaderSource::Wgsl(Cow::Owned(
-                spp.render_shader("rend3-routine/skybox.wgsl", &ShaderConfig::default(), None).unwrap(),
-            )),
+            source: ShaderSource::Wgsl(Cow::Owned(spp.render_shader(shader, &ShaderConfig::default(), None).unwrap())),
         });
 
+        Self::from_module(renderer, interfaces, bgl, &skybox_sm)
+    }
+
+    /// Builds the pipelines from a module with `vs_main` and `fs_main` entry
+    /// points.
+    fn from_module(
+        renderer: &Renderer,
+        interfaces: &WholeFrameInterfaces,
+        bgl: &BindGroupLayout,
+        skybox_sm: &ShaderModule,
+    ) -> Self {
         let pll = renderer.device.create_pipeline_layout(&PipelineLayoutDescriptor {
             label: Some("skybox pass"),
             bind_group_layouts: &[&interfaces.forward_uniform_bgl, bgl],
@@ -140,7 +459,7 @@ impl SkyboxPipelines {
             renderer.device.create_render_pipeline(&RenderPipelineDescriptor {
                 label: Some("skybox pass"),
                 layout: Some(&pll),
-                vertex: VertexState { module: &skybox_sm, entry_point: "vs_main", buffers: &[] },
+                vertex: VertexState { module: skybox_sm, entry_point: "vs_main", buffers: &[] },
                 primitive: PrimitiveState {
                     topology: PrimitiveTopology::TriangleList,
                     strip_index_format: None,
@@ -151,7 +470,7 @@ impl SkyboxPipelines {
                     conservative: false,
                 },
                 depth_stencil: Some(DepthStencilState {
-                    format: TextureFormat::Depth32Float,
+                    format: interfaces.depth_format,
                     depth_write_enabled: true,
                     depth_compare: CompareFunction::GreaterEqual,
                     stencil: StencilState::default(),
@@ -159,7 +478,7 @@ impl SkyboxPipelines {
                 }),
                 multisample: MultisampleState { count: samples as u32, ..Default::default() },
                 fragment: Some(FragmentState {
-                    module: &skybox_sm,
+                    module: skybox_sm,
                     entry_point: "fs_main",
                     targets: &[Some(ColorTargetState {
                         format: TextureFormat::Rgba16Float,
@@ -174,3 +493,80 @@ impl SkyboxPipelines {
         Self { pipeline_s1: inner(SampleCount::One), pipeline_s4: inner(SampleCount::Four) }
     }
 }
+
+/// Combines the user's source with the skybox template, making sure it
+/// defines [`CUSTOM_SKYBOX_FUNCTION`] and validates.
+fn build_custom_skybox_source(spp: &ShaderPreProcessor, user_source: &str) -> Result<String, SkyboxShaderError> {
+    let render = |fragment| {
+        let template =
+            spp.render_shader("rend3-routine/skybox_custom.wgsl", &CustomSkyboxArgs { fragment }, None).unwrap();
+        format!("{template}\n{user_source}")
+    };
+
+    // Check the signature first, as without the function the entry point fails to parse with a less helpful error.
+    let declarations = naga::front::wgsl::parse_str(&render(false)).map_err(SkyboxShaderError::Parse)?;
+    let has_function = declarations.functions.iter().any(|(_, function)| {
+        let is_vector = |ty, size| declarations.types[ty].inner == TypeInner::Vector { size, scalar: Scalar::F32 };
+
+        function.name.as_deref() == Some(CUSTOM_SKYBOX_FUNCTION)
+            && matches!(function.arguments.as_slice(), [arg] if is_vector(arg.ty, VectorSize::Tri))
+            && matches!(function.result, Some(ref result) if is_vector(result.ty, VectorSize::Quad))
+    });
+    if !has_function {
+        return Err(SkyboxShaderError::MissingFunction);
+    }
+
+    let source = render(true);
+    let module = naga::front::wgsl::parse_str(&source).map_err(SkyboxShaderError::Parse)?;
+    Validator::new(ValidationFlags::all(), Capabilities::default())
+        .validate(&module)
+        .map_err(|e| SkyboxShaderError::Validation(Box::new(e)))?;
+
+    Ok(source)
+}
+
+#[cfg(test)]
+mod tests {
+    use rend3::ShaderPreProcessor;
+
+    use super::{build_custom_skybox_source, SkyboxShaderError};
+
+    fn build(source: &str) -> Result<String, SkyboxShaderError> {
+        let mut spp = ShaderPreProcessor::new();
+        crate::builtin_shaders(&mut spp);
+        build_custom_skybox_source(&spp, source)
+    }
+
+    #[test]
+    fn valid() {
+        let source = "fn skybox_color(direction: vec3<f32>) -> vec4<f32> {
+            return vec4<f32>(direction * sin(uniforms.time), 1.0);
+        }";
+        build(source).unwrap();
+    }
+
+    #[test]
+    fn user_uniforms() {
+        let source = "@group(1) @binding(0)
+        var<uniform> sky_tint: vec4<f32>;
+
+        fn skybox_color(direction: vec3<f32>) -> vec4<f32> {
+            return sky_tint;
+        }";
+        build(source).unwrap();
+    }
+
+    #[test]
+    fn missing_function() {
+        let source = "fn sky(direction: vec3<f32>) -> vec4<f32> { return vec4<f32>(1.0); }";
+        assert!(matches!(build(source), Err(SkyboxShaderError::MissingFunction)));
+
+        let source = "fn skybox_color(direction: vec2<f32>) -> vec4<f32> { return vec4<f32>(1.0); }";
+        assert!(matches!(build(source), Err(SkyboxShaderError::MissingFunction)));
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(matches!(build("fn skybox_color("), Err(SkyboxShaderError::Parse(_))));
+    }
+}
@@ -0,0 +1,242 @@
+//! Screen space ambient occlusion, darkening the ambient light in creases and
+//! corners.
+//!
+//! The view space position of every pixel is reconstructed from the depth
+//! buffer, and a hemisphere of samples around its normal is tested against the
+//! depth of the surfaces in front of it. The noisy result is blurred into the
+//! ambient occlusion target, which the forward pass multiplies with the
+//! ambient light.
+//!
+//! This needs the depth of the opaque objects before they are shaded, so it
+//! runs after both halves of the depth prepass, which is always enabled along
+//! with it. The occlusion of the predicted triangles is computed before they
+//! are shaded, and then again once the residual triangles are added.
+
+use std::borrow::Cow;
+
+use glam::{UVec2, Vec4};
+use rend3::{
+    graph::{
+        DataHandle, NodeResourceUsage, RenderGraph, RenderPassTarget, RenderPassTargets, RenderTargetDescriptor,
+        RenderTargetHandle,
+    },
+    types::{SampleCount, TextureFormat, TextureUsages},
+    util::bind_merge::{BindGroupBuilder, BindGroupLayoutBuilder},
+    ShaderPreProcessor,
+};
+use wgpu::{
+    BindGroup, BindGroupLayout, BindingType, ColorTargetState, ColorWrites, Device, FragmentState, MultisampleState,
+    PipelineLayoutDescriptor, PrimitiveState, RenderPipeline, RenderPipelineDescriptor, ShaderModuleDescriptor,
+    ShaderSource, ShaderStages, TextureAspect, TextureSampleType, TextureViewDimension, VertexState,
+};
+
+use crate::common::WholeFrameInterfaces;
+
+/// Format of the ambient occlusion image, sampled by the forward pass.
+pub const AMBIENT_OCCLUSION_FORMAT: TextureFormat = TextureFormat::R8Unorm;
+
+/// Settings of the screen space ambient occlusion.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SsaoSettings {
+    /// Distance in world units around every pixel in which surfaces occlude
+    /// it.
+    pub radius: f32,
+    /// Distance in world units a surface has to be in front of a sample to
+    /// occlude it. Raise it to remove self occlusion on flat surfaces.
+    pub bias: f32,
+    /// Amount of samples taken per pixel.
+    pub samples: u32,
+}
+
+impl Default for SsaoSettings {
+    fn default() -> Self {
+        Self { radius: 0.5, bias: 0.025, samples: 16 }
+    }
+}
+
+struct SsaoPipeline {
+    depth_bgl: BindGroupLayout,
+    pipeline: RenderPipeline,
+}
+
+fn fullscreen_pipeline(
+    device: &Device,
+    label: &str,
+    source: String,
+    bind_group_layouts: &[&BindGroupLayout],
+) -> RenderPipeline {
+    let module = device.create_shader_module(ShaderModuleDescriptor {
+        label: Some(label),
+        source: ShaderSource::Wgsl(Cow::Owned(source)),
+    });
+
+    let pll = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+        label: Some(label),
+        bind_group_layouts,
+        push_constant_ranges: &[],
+    });
+
+    device.create_render_pipeline(&RenderPipelineDescriptor {
+        label: Some(label),
+        layout: Some(&pll),
+        vertex: VertexState { module: &module, entry_point: "vs_main", buffers: &[] },
+        primitive: PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: MultisampleState::default(),
+        fragment: Some(FragmentState {
+            module: &module,
+            entry_point: "fs_main",
+            targets: &[Some(ColorTargetState {
+                format: AMBIENT_OCCLUSION_FORMAT,
+                blend: None,
+                write_mask: ColorWrites::all(),
+            })],
+        }),
+        multiview: None,
+    })
+}
+
+impl SsaoPipeline {
+    fn new(device: &Device, spp: &ShaderPreProcessor, interfaces: &WholeFrameInterfaces, samples: SampleCount) -> Self {
+        let depth_bgl = BindGroupLayoutBuilder::new()
+            .append(
+                ShaderStages::FRAGMENT,
+                BindingType::Texture {
+                    sample_type: TextureSampleType::Depth,
+                    view_dimension: TextureViewDimension::D2,
+                    multisampled: samples != SampleCount::One,
+                },
+                None,
+            )
+            .build(device, Some("ssao depth bgl"));
+
+        let source =
+            spp.render_shader("rend3-routine/ssao.wgsl", &serde_json::json!({"SAMPLES": samples as u8}), None).unwrap();
+        let pipeline = fullscreen_pipeline(device, "ssao", source, &[&interfaces.depth_uniform_bgl, &depth_bgl]);
+
+        Self { depth_bgl, pipeline }
+    }
+}
+
+/// Routine rendering screen space ambient occlusion.
+///
+/// See module for documentation.
+pub struct SsaoRoutine {
+    ssao_s1: SsaoPipeline,
+    ssao_s4: SsaoPipeline,
+    blur_bgl: BindGroupLayout,
+    blur_pipeline: RenderPipeline,
+}
+
+impl SsaoRoutine {
+    pub fn new(device: &Device, spp: &ShaderPreProcessor, interfaces: &WholeFrameInterfaces) -> Self {
+        profiling::scope!("SsaoRoutine::new");
+
+        let blur_bgl = BindGroupLayoutBuilder::new()
+            .append(
+                ShaderStages::FRAGMENT,
+                BindingType::Texture {
+                    sample_type: TextureSampleType::Float { filterable: true },
+                    view_dimension: TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                None,
+            )
+            .build(device, Some("ssao blur bgl"));
+
+        let blur_source = spp.render_shader("rend3-routine/ssao_blur.wgsl", &(), None).unwrap();
+        let blur_pipeline = fullscreen_pipeline(device, "ssao blur", blur_source, &[&blur_bgl]);
+
+        Self {
+            ssao_s1: SsaoPipeline::new(device, spp, interfaces, SampleCount::One),
+            ssao_s4: SsaoPipeline::new(device, spp, interfaces, SampleCount::Four),
+            blur_bgl,
+            blur_pipeline,
+        }
+    }
+
+    /// Renders the ambient occlusion of `depth` into `ambient_occlusion`, which
+    /// both have the given resolution.
+    pub fn add_to_graph<'node>(
+        &'node self,
+        graph: &mut RenderGraph<'node>,
+        depth: RenderTargetHandle,
+        samples: SampleCount,
+        ambient_occlusion: RenderTargetHandle,
+        resolution: UVec2,
+        shadow_uniform_bg: DataHandle<BindGroup>,
+    ) {
+        let noisy = graph.add_render_target(RenderTargetDescriptor {
+            label: Some("ssao noisy".into()),
+            resolution,
+            depth: 1,
+            mip_levels: Some(1),
+            samples: SampleCount::One,
+            format: AMBIENT_OCCLUSION_FORMAT,
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+        });
+
+        let ssao = match samples {
+            SampleCount::One => &self.ssao_s1,
+            SampleCount::Four => &self.ssao_s4,
+        };
+
+        let mut builder = graph.add_node("SSAO");
+        let depth_handle =
+            builder.add_render_target(depth.set_aspect(TextureAspect::DepthOnly), NodeResourceUsage::Input);
+        let rpass_handle = builder.add_renderpass(
+            RenderPassTargets {
+                targets: vec![RenderPassTarget { color: noisy, clear: Vec4::ONE, resolve: None }],
+                depth_stencil: None,
+            },
+            NodeResourceUsage::InputOutput,
+        );
+        let shadow_uniform_handle = builder.add_data(shadow_uniform_bg, NodeResourceUsage::Input);
+
+        builder.build(move |mut ctx| {
+            let rpass = ctx.encoder_or_pass.take_rpass(rpass_handle);
+            let shadow_uniform_bg = ctx.graph_data.get_data(ctx.temps, shadow_uniform_handle).unwrap();
+            let depth = ctx.graph_data.get_render_target(depth_handle);
+
+            profiling::scope!("ssao");
+
+            let depth_bg = ctx.temps.add(BindGroupBuilder::new().append_texture_view(depth).build(
+                &ctx.renderer.device,
+                Some("ssao depth bg"),
+                &ssao.depth_bgl,
+            ));
+
+            rpass.set_pipeline(&ssao.pipeline);
+            rpass.set_bind_group(0, shadow_uniform_bg, &[]);
+            rpass.set_bind_group(1, depth_bg, &[]);
+            rpass.draw(0..3, 0..1);
+        });
+
+        let mut builder = graph.add_node("SSAO Blur");
+        let noisy_handle = builder.add_render_target(noisy, NodeResourceUsage::Input);
+        let rpass_handle = builder.add_renderpass(
+            RenderPassTargets {
+                targets: vec![RenderPassTarget { color: ambient_occlusion, clear: Vec4::ONE, resolve: None }],
+                depth_stencil: None,
+            },
+            NodeResourceUsage::InputOutput,
+        );
+
+        builder.build(move |mut ctx| {
+            let rpass = ctx.encoder_or_pass.take_rpass(rpass_handle);
+            let noisy = ctx.graph_data.get_render_target(noisy_handle);
+
+            profiling::scope!("ssao blur");
+
+            let noisy_bg = ctx.temps.add(BindGroupBuilder::new().append_texture_view(noisy).build(
+                &ctx.renderer.device,
+                Some("ssao blur bg"),
+                &self.blur_bgl,
+            ));
+
+            rpass.set_pipeline(&self.blur_pipeline);
+            rpass.set_bind_group(0, noisy_bg, &[]);
+            rpass.draw(0..3, 0..1);
+        });
+    }
+}
@@ -1,8 +1,18 @@
 //! Tonemapper which blits an image while applying a tonemapping operator.
 //!
-//! As of right now there is no tonemapping applied as we don't have
-//! auto-exposure yet. Once we have auto-exposure, we can do proper tonemapping,
-//! and will offer a variety of tonemapping operators.
+//! The hdr image is scaled by
+//! [`BaseRenderGraphSettings::exposure`](crate::base::BaseRenderGraphSettings::exposure),
+//! then mapped with the [`TonemappingOperator`] in
+//! [`BaseRenderGraphSettings::tonemapping_operator`](crate::base::BaseRenderGraphSettings::tonemapping_operator).
+//! There is no auto-exposure, so the exposure has to be chosen for the scene.
+//! Both are read from the frame uniforms, so changing them doesn't rebuild
+//! any pipeline.
+//!
+//! A color grading lookup table can be applied to the tonemapped image with
+//! [`TonemappingRoutine::set_color_grading_lut`].
+//!
+//! The routine also holds the [`FxaaRoutine`] for its output format, which
+//! anti-aliases the tonemapped image.
 //!
 //! When creating the tonemapping, ensure you use the correct format for the
 //! output. Each TonemappingRoutine instance only has a single pipeline, so if
@@ -11,20 +21,25 @@
 
 use std::borrow::Cow;
 
+use encase::{ShaderSize, ShaderType, UniformBuffer};
 use glam::Vec4;
 use rend3::{
     graph::{DataHandle, NodeResourceUsage, RenderGraph, RenderPassTarget, RenderPassTargets, RenderTargetHandle},
-    util::bind_merge::{BindGroupBuilder, BindGroupLayoutBuilder},
+    types::Texture3DHandle,
+    util::{
+        bind_merge::{BindGroupBuilder, BindGroupLayoutBuilder},
+        error_scope::ValidationErrorScope,
+    },
     Renderer, ShaderConfig, ShaderPreProcessor,
 };
 use wgpu::{
-    BindGroup, BindGroupLayout, BindingType, ColorTargetState, ColorWrites, Device, FragmentState, FrontFace,
-    MultisampleState, PipelineLayoutDescriptor, PolygonMode, PrimitiveState, PrimitiveTopology, RenderPipeline,
-    RenderPipelineDescriptor, ShaderModuleDescriptor, ShaderSource, ShaderStages, TextureFormat, TextureSampleType,
-    TextureViewDimension, VertexState,
+    BindGroup, BindGroupLayout, BindingType, Buffer, BufferBindingType, BufferDescriptor, BufferUsages,
+    ColorTargetState, ColorWrites, Device, FragmentState, FrontFace, MultisampleState, PipelineLayoutDescriptor,
+    PolygonMode, PrimitiveState, PrimitiveTopology, RenderPipeline, RenderPipelineDescriptor, ShaderModuleDescriptor,
+    ShaderSource, ShaderStages, TextureFormat, TextureSampleType, TextureViewDimension, VertexState,
 };
 
-use crate::common::WholeFrameInterfaces;
+use crate::{common::WholeFrameInterfaces, fxaa::FxaaRoutine};
 
 fn create_pipeline(
     device: &Device,
@@ -32,6 +47,7 @@ fn create_pipeline(
     interfaces: &WholeFrameInterfaces,
     bgl: &BindGroupLayout,
     output_format: TextureFormat,
+    graded: bool,
 ) -> RenderPipeline {
     profiling::scope!("TonemappingPass::new");
     let module = device.create_shader_module(ShaderModuleDescriptor {
@@ -41,7 +57,12 @@ fn create_pipeline(
         )),
     });
 
-    let fs_entry_point = if output_format.is_srgb() { "fs_main_scene" } else { "fs_main_monitor" };
+    let fs_entry_point = match (output_format.is_srgb(), graded) {
+        (true, false) => "fs_main_scene",
+        (false, false) => "fs_main_monitor",
+        (true, true) => "fs_main_scene_graded",
+        (false, true) => "fs_main_monitor_graded",
+    };
 
     let pll = device.create_pipeline_layout(&PipelineLayoutDescriptor {
         label: Some("tonemapping pass"),
@@ -73,12 +94,63 @@ fn create_pipeline(
     })
 }
 
+/// Operator mapping hdr colors to the [0, 1] range of the output.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum TonemappingOperator {
+    /// Colors are passed through unchanged, clipping everything above 1.
+    #[default]
+    None,
+    /// `color / (1 + color)`, which desaturates bright colors.
+    Reinhard,
+    /// Fit of the ACES filmic curve, with more contrast than Reinhard.
+    Aces,
+    /// John Hable's filmic curve from Uncharted 2, with a softer shoulder than
+    /// ACES and a white point of 11.2.
+    Uncharted2,
+}
+
+impl TonemappingOperator {
+    /// Value identifying the operator in the blit shader.
+    pub(crate) fn shader_value(self) -> u32 {
+        match self {
+            Self::None => 0,
+            Self::Reinhard => 1,
+            Self::Aces => 2,
+            Self::Uncharted2 => 3,
+        }
+    }
+}
+
+/// Color grading lookup table applied to the tonemapped image.
+///
+/// The 3D texture maps display encoded (sRGB) colors, with red along the
+/// width, green along the height and blue along the depth, to the graded color.
+/// A 32x32x32 table is typical.
+#[derive(Debug, Clone)]
+pub struct ColorGradingLut {
+    pub texture: Texture3DHandle,
+    /// How much of the graded color is used, from 0 for the original color to 1
+    /// for only the graded color.
+    pub intensity: f32,
+}
+
+#[derive(ShaderType)]
+struct ShaderColorGrading {
+    intensity: f32,
+}
+
 /// HDR tonemapping routine.
 ///
 /// See module for documentation.
 pub struct TonemappingRoutine {
     bgl: BindGroupLayout,
     pipeline: RenderPipeline,
+    graded_bgl: BindGroupLayout,
+    graded_pipeline: RenderPipeline,
+    grading_buffer: Buffer,
+    lut: Option<ColorGradingLut>,
+    output_format: TextureFormat,
+    fxaa: FxaaRoutine,
 }
 
 impl TonemappingRoutine {
@@ -100,9 +172,93 @@ impl TonemappingRoutine {
             )
             .build(&renderer.device, Some("bind bgl"));
 
-        let pipeline = create_pipeline(&renderer.device, spp, interfaces, &bgl, output_format);
+        let graded_bgl = BindGroupLayoutBuilder::new()
+            .append(
+                ShaderStages::FRAGMENT,
+                BindingType::Texture {
+                    sample_type: TextureSampleType::Float { filterable: true },
+                    view_dimension: TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                None,
+            )
+            .append(
+                ShaderStages::FRAGMENT,
+                BindingType::Texture {
+                    sample_type: TextureSampleType::Float { filterable: true },
+                    view_dimension: TextureViewDimension::D3,
+                    multisampled: false,
+                },
+                None,
+            )
+            .append(
+                ShaderStages::FRAGMENT,
+                BindingType::Buffer {
+                    ty: BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: Some(ShaderColorGrading::min_size()),
+                },
+                None,
+            )
+            .build(&renderer.device, Some("graded blit bgl"));
+
+        let pipeline = create_pipeline(&renderer.device, spp, interfaces, &bgl, output_format, false);
+        let graded_pipeline = create_pipeline(&renderer.device, spp, interfaces, &graded_bgl, output_format, true);
+
+        let grading_buffer = renderer.device.create_buffer(&BufferDescriptor {
+            label: Some("color grading buffer"),
+            size: ShaderColorGrading::SHADER_SIZE.get(),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let fxaa = FxaaRoutine::new(&renderer.device, spp, interfaces, output_format);
+
+        Self { bgl, pipeline, graded_bgl, graded_pipeline, grading_buffer, lut: None, output_format, fxaa }
+    }
+
+    /// Recompile the shaders of the routine from `spp`, keeping the color
+    /// grading lookup table.
+    ///
+    /// If the shaders fail to compile, the error is returned and the routine is
+    /// left unchanged.
+    pub fn reload_shaders(
+        &mut self,
+        renderer: &Renderer,
+        spp: &ShaderPreProcessor,
+        interfaces: &WholeFrameInterfaces,
+    ) -> Result<(), wgpu::Error> {
+        profiling::scope!("Reload Tonemapping Shaders");
+
+        let scope = ValidationErrorScope::new(&renderer.device);
+        let mut reloaded = Self::new(renderer, spp, interfaces, self.output_format);
+        scope.end()?;
+
+        reloaded.set_color_grading_lut(renderer, self.lut.take());
+        *self = reloaded;
+
+        Ok(())
+    }
+
+    /// Format of the image the routine tonemaps into.
+    pub fn output_format(&self) -> TextureFormat {
+        self.output_format
+    }
+
+    /// FXAA routine reading and writing images of [`Self::output_format`].
+    pub fn fxaa(&self) -> &FxaaRoutine {
+        &self.fxaa
+    }
 
-        Self { bgl, pipeline }
+    /// Set the lookup table the tonemapped image is graded with. Passing `None`
+    /// turns color grading off, which costs nothing.
+    pub fn set_color_grading_lut(&mut self, renderer: &Renderer, lut: Option<ColorGradingLut>) {
+        if let Some(ref lut) = lut {
+            let mut data = UniformBuffer::new(Vec::new());
+            data.write(&ShaderColorGrading { intensity: lut.intensity }).unwrap();
+            renderer.queue.write_buffer(&self.grading_buffer, 0, &data.into_inner());
+        }
+        self.lut = lut;
     }
 
     pub fn add_to_graph<'node>(
@@ -133,13 +289,28 @@ impl TonemappingRoutine {
 
             profiling::scope!("tonemapping");
 
-            let blit_src_bg = ctx.temps.add(BindGroupBuilder::new().append_texture_view(hdr_color).build(
-                &ctx.renderer.device,
-                Some("blit src bg"),
-                &self.bgl,
-            ));
+            let (pipeline, blit_src_bg) = match self.lut {
+                Some(ref lut) => {
+                    let lut_view = ctx.data_core.d3_texture_manager.get_view(lut.texture.get_raw());
+                    let bg = BindGroupBuilder::new()
+                        .append_texture_view(hdr_color)
+                        .append_texture_view(lut_view)
+                        .append_buffer(&self.grading_buffer)
+                        .build(&ctx.renderer.device, Some("graded blit src bg"), &self.graded_bgl);
+                    (&self.graded_pipeline, bg)
+                }
+                None => {
+                    let bg = BindGroupBuilder::new().append_texture_view(hdr_color).build(
+                        &ctx.renderer.device,
+                        Some("blit src bg"),
+                        &self.bgl,
+                    );
+                    (&self.pipeline, bg)
+                }
+            };
+            let blit_src_bg = ctx.temps.add(blit_src_bg);
 
-            rpass.set_pipeline(&self.pipeline);
+            rpass.set_pipeline(pipeline);
             rpass.set_bind_group(0, forward_uniform_bg, &[]);
             rpass.set_bind_group(1, blit_src_bg, &[]);
             rpass.draw(0..3, 0..1);
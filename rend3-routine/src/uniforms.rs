@@ -2,15 +2,21 @@
 //! shadows.
 
 use encase::{ShaderSize, ShaderType, UniformBuffer};
-use glam::{Mat4, UVec2, Vec4};
+use glam::{Mat4, UVec2, Vec3, Vec4};
 use rend3::{
     graph::{DataHandle, NodeResourceUsage, RenderGraph, RenderTargetHandle},
     managers::CameraState,
-    util::{bind_merge::BindGroupBuilder, frustum::Frustum},
+    util::{bind_merge::BindGroupBuilder, buffer_pool::BufferPool, frustum::Frustum},
 };
-use wgpu::{BindGroup, BufferUsages};
+use wgpu::BindGroup;
 
-use crate::common::{Samplers, WholeFrameInterfaces};
+use crate::{
+    bloom::BloomSettings,
+    common::{CameraSpecifier, Samplers, WholeFrameInterfaces},
+    ibl::EnvironmentMaps,
+    ssao::SsaoSettings,
+    tonemapping::TonemappingOperator,
+};
 
 /// Set of uniforms that are useful for the whole frame.
 #[derive(Debug, Copy, Clone, ShaderType)]
@@ -24,6 +30,26 @@ pub struct FrameUniforms {
     pub frustum: Frustum,
     pub ambient: Vec4,
     pub resolution: UVec2,
+    pub time: f32,
+    pub shadow_filter_size: u32,
+    pub variance_shadows: u32,
+    pub shadow_bleed_reduction: f32,
+    pub background_intensity: f32,
+    pub exposure: f32,
+    pub tonemapping_operator: u32,
+    pub bloom_threshold: f32,
+    pub bloom_intensity: f32,
+    pub ssao_radius: f32,
+    pub ssao_bias: f32,
+    pub ssao_samples: u32,
+    pub environment_lighting: u32,
+    pub fog_color: Vec3,
+    pub fog_mode: u32,
+    pub fog_start: f32,
+    pub fog_end: f32,
+    pub fog_density: f32,
+    pub skybox_fog: f32,
+    pub debug_view: u32,
 }
 impl FrameUniforms {
     /// Use the given camera to generate these uniforms.
@@ -34,6 +60,12 @@ impl FrameUniforms {
         let view_proj = camera.view_proj();
         let origin_view_proj = camera.origin_view_proj();
 
+        let (fog_start, fog_end, fog_density) = match info.fog.map(|fog| fog.mode) {
+            Some(FogMode::Linear { start, end }) => (start, end, 0.0),
+            Some(FogMode::Exponential { density } | FogMode::ExponentialSquared { density }) => (0.0, 0.0, density),
+            None => (0.0, 0.0, 0.0),
+        };
+
         Self {
             view,
             view_proj,
@@ -44,6 +76,26 @@ impl FrameUniforms {
             frustum: Frustum::from_matrix(camera.proj()),
             ambient: info.ambient,
             resolution: info.resolution,
+            time: info.time,
+            shadow_filter_size: info.shadow_filter.kernel_size(),
+            variance_shadows: info.shadow_filter.bleed_reduction().is_some() as u32,
+            shadow_bleed_reduction: info.shadow_filter.bleed_reduction().unwrap_or(0.0),
+            background_intensity: info.background_intensity,
+            exposure: info.exposure,
+            tonemapping_operator: info.tonemapping_operator.shader_value(),
+            bloom_threshold: info.bloom.map_or(0.0, |bloom| bloom.threshold),
+            bloom_intensity: info.bloom.map_or(0.0, |bloom| bloom.intensity),
+            ssao_radius: info.ssao.map_or(0.0, |ssao| ssao.radius),
+            ssao_bias: info.ssao.map_or(0.0, |ssao| ssao.bias),
+            ssao_samples: info.ssao.map_or(0, |ssao| ssao.samples),
+            environment_lighting: info.environment.enabled as u32,
+            fog_color: info.fog.map_or(Vec3::ZERO, |fog| fog.color),
+            fog_mode: info.fog.map_or(0, |fog| fog.mode.shader_value()),
+            fog_start,
+            fog_end,
+            fog_density,
+            skybox_fog: info.fog.map_or(0.0, |fog| fog.skybox_amount),
+            debug_view: info.debug_view.shader_value(),
         }
     }
 }
@@ -52,15 +104,165 @@ impl FrameUniforms {
 pub struct UniformInformation<'node> {
     /// Struct containing the default set of samplers.
     pub samplers: &'node Samplers,
+    /// Camera the uniforms are built for.
+    pub camera: CameraSpecifier,
     /// Ambient light color.
     pub ambient: Vec4,
     /// Resolution of the viewport.
     pub resolution: UVec2,
+    /// Time in seconds, used to animate shaders.
+    pub time: f32,
+    /// Filtering used when sampling shadow maps.
+    pub shadow_filter: ShadowFilter,
+    /// Multiplier applied to the color of the skybox.
+    pub background_intensity: f32,
+    /// Multiplier applied to the hdr color before tonemapping.
+    pub exposure: f32,
+    /// Operator mapping hdr colors to the output.
+    pub tonemapping_operator: TonemappingOperator,
+    /// Bloom added to the hdr color, if any.
+    pub bloom: Option<BloomSettings>,
+    /// Screen space ambient occlusion, if any.
+    pub ssao: Option<SsaoSettings>,
+    /// Image based lighting replacing the ambient color, when enabled.
+    pub environment: &'node EnvironmentMaps,
+    /// Distance fog, if any.
+    pub fog: Option<Fog>,
+    /// Channel shown instead of the shaded color.
+    pub debug_view: DebugView,
+}
+
+/// Filtering done when sampling shadow maps. Larger percentage-closer filtering
+/// kernels give softer shadow edges at the cost of more texture samples.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub enum ShadowFilter {
+    /// A single comparison, giving hard, aliased edges.
+    Hard,
+    /// Average of a 3x3 square of comparisons.
+    #[default]
+    Pcf3x3,
+    /// Average of a 5x5 square of comparisons.
+    Pcf5x5,
+    /// Variance shadow maps. The depth and squared depth of the shadow maps
+    /// are blurred with a 5x5 kernel after rendering, and each sample bounds
+    /// the fraction of occluders in front of the fragment, giving smooth edges
+    /// for a single filtered sample.
+    ///
+    /// Where occluders overlap, light bleeds into the shadow of the closer
+    /// one. Bounds below `bleed_reduction`, between 0 and 1, are cut off to
+    /// hide it, at the cost of tightening the soft edges. 0.2 to 0.4 work
+    /// well for most scenes.
+    Variance { bleed_reduction: f32 },
+}
+
+impl ShadowFilter {
+    /// Width of the square of texels averaged. Variance shadow maps are
+    /// blurred beforehand, and sampled once.
+    pub fn kernel_size(self) -> u32 {
+        match self {
+            Self::Hard | Self::Variance { .. } => 1,
+            Self::Pcf3x3 => 3,
+            Self::Pcf5x5 => 5,
+        }
+    }
+
+    /// Light bleeding reduction of variance shadow maps, `None` for the
+    /// percentage-closer filters.
+    pub fn bleed_reduction(self) -> Option<f32> {
+        match self {
+            Self::Variance { bleed_reduction } => Some(bleed_reduction),
+            Self::Hard | Self::Pcf3x3 | Self::Pcf5x5 => None,
+        }
+    }
+}
+
+/// Distance fog, blending surfaces into a color the further they are from the
+/// camera.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Fog {
+    /// How the fog thickens with the distance.
+    pub mode: FogMode,
+    /// Color fogged surfaces fade into, in linear hdr color.
+    pub color: Vec3,
+    /// Fraction of the fog color blended over the skybox, from 0 leaving the
+    /// skybox clear to 1 hiding it behind the fog color.
+    pub skybox_amount: f32,
+}
+
+/// How [`Fog`] thickens with the distance to the camera.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum FogMode {
+    /// No fog closer than `start`, increasing linearly to full fog at `end`.
+    Linear { start: f32, end: f32 },
+    /// Fog covering `1 - e^(-density * distance)` of a surface.
+    Exponential { density: f32 },
+    /// Fog covering `1 - e^(-(density * distance)^2)` of a surface, staying
+    /// thin for longer than [`FogMode::Exponential`] before closing in.
+    ExponentialSquared { density: f32 },
+}
+
+impl FogMode {
+    /// Value of `fog_mode` in the uniforms, see fog.wgsl. 0 disables fog.
+    pub fn shader_value(self) -> u32 {
+        match self {
+            Self::Linear { .. } => 1,
+            Self::Exponential { .. } => 2,
+            Self::ExponentialSquared { .. } => 3,
+        }
+    }
+}
+
+/// Channel the forward pass outputs instead of the shaded color, for
+/// inspecting materials and lighting. Tonemapping, bloom, and fog are skipped
+/// so the values reach the output unchanged.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum DebugView {
+    /// Regular shading.
+    #[default]
+    None,
+    /// World space normals after normal mapping, mapped from -1..1 to 0..1.
+    Normals,
+    /// Base color, including vertex colors and textures.
+    Albedo,
+    /// Perceptual roughness, in grayscale.
+    Roughness,
+    /// Metallicness, in grayscale.
+    Metallic,
+    /// Distance to the camera, fading from white to black as `1 / (1 +
+    /// distance)`.
+    Depth,
+    /// Shadow cascade of the first directional light used by each pixel, red,
+    /// green, blue, and yellow from the nearest to the furthest. Black without
+    /// directional lights.
+    ShadowCascades,
+    /// Number of fragments shaded for each pixel, each adding 0.1 to every
+    /// channel so ten layers are white. Disables the depth prepass and depth
+    /// testing.
+    Overdraw,
+}
+
+impl DebugView {
+    /// Value of `debug_view` in the uniforms, see opaque.wgsl. 0 shades
+    /// normally.
+    pub fn shader_value(self) -> u32 {
+        match self {
+            Self::None => 0,
+            Self::Normals => 1,
+            Self::Albedo => 2,
+            Self::Roughness => 3,
+            Self::Metallic => 4,
+            Self::Depth => 5,
+            Self::ShadowCascades => 6,
+            Self::Overdraw => 7,
+        }
+    }
 }
 
 pub struct UniformBindingHandles<'node> {
     /// Interfaces containing the bind group layouts for the uniform bind groups.
     pub interfaces: &'node WholeFrameInterfaces,
+    /// Pool the uniform buffer is taken from, recycling it across frames.
+    pub buffer_pool: &'node BufferPool,
     /// The output bind group handle for the shadow uniform data. This does not
     /// include the shadow map texture, preventing a cycle.
     pub shadow_uniform_bg: DataHandle<BindGroup>,
@@ -73,6 +275,8 @@ pub struct UniformBindingHandles<'node> {
 pub fn add_to_graph<'node>(
     graph: &mut RenderGraph<'node>,
     shadow_target: RenderTargetHandle,
+    ambient_occlusion: RenderTargetHandle,
+    shadow_moments: RenderTargetHandle,
     binding_handles: UniformBindingHandles<'node>,
     info: UniformInformation<'node>,
 ) {
@@ -80,38 +284,45 @@ pub fn add_to_graph<'node>(
     let shadow_handle = builder.add_data(binding_handles.shadow_uniform_bg, NodeResourceUsage::Output);
     let forward_handle = builder.add_data(binding_handles.forward_uniform_bg, NodeResourceUsage::Output);
 
-    // Get the shadow target and declare it a dependency of the forward_uniform_bg
+    // Get the shadow, ambient occlusion, and shadow moment targets and declare them dependencies of the forward_uniform_bg
     let shadow_target_handle = builder.add_render_target(shadow_target, NodeResourceUsage::Reference);
-    builder.add_dependencies_to_render_targets(binding_handles.forward_uniform_bg, [shadow_target]);
+    let ambient_occlusion_handle = builder.add_render_target(ambient_occlusion, NodeResourceUsage::Reference);
+    let shadow_moments_handle = builder.add_render_target(shadow_moments, NodeResourceUsage::Reference);
+    builder.add_dependencies_to_render_targets(
+        binding_handles.forward_uniform_bg,
+        [shadow_target, ambient_occlusion, shadow_moments],
+    );
 
     builder.build(move |ctx| {
         let shadow_target = ctx.graph_data.get_render_target(shadow_target_handle);
+        let ambient_occlusion = ctx.graph_data.get_render_target(ambient_occlusion_handle);
+        let shadow_moments = ctx.graph_data.get_render_target(shadow_moments_handle);
 
         let mut bgb = BindGroupBuilder::new();
 
         info.samplers.add_to_bg(&mut bgb);
 
-        let uniforms = FrameUniforms::new(&ctx.data_core.viewport_camera_state, &info);
-        let uniform_buffer = ctx.renderer.device.create_buffer(&wgpu::BufferDescriptor {
-            label: Some("Frame Uniforms"),
-            size: FrameUniforms::SHADER_SIZE.get(),
-            usage: BufferUsages::UNIFORM,
-            mapped_at_creation: true,
-        });
-        let mut mapping = uniform_buffer.slice(..).get_mapped_range_mut();
-        UniformBuffer::new(&mut *mapping).write(&uniforms).unwrap();
-        drop(mapping);
-        uniform_buffer.unmap();
+        let uniforms = FrameUniforms::new(info.camera.state(ctx.data_core, ctx.eval_output), &info);
+        let uniform_buffer =
+            ctx.temps.add(binding_handles.buffer_pool.get(&ctx.renderer.device, FrameUniforms::SHADER_SIZE.get()));
+        let mut data = UniformBuffer::new(Vec::new());
+        data.write(&uniforms).unwrap();
+        ctx.renderer.queue.write_buffer(uniform_buffer, 0, &data.into_inner());
 
-        bgb.append_buffer(&uniform_buffer);
+        bgb.append_buffer_with_size(uniform_buffer, FrameUniforms::SHADER_SIZE.get());
 
         ctx.data_core.directional_light_manager.add_to_bg(&mut bgb);
         ctx.data_core.point_light_manager.add_to_bg(&mut bgb);
+        ctx.data_core.spot_light_manager.add_to_bg(&mut bgb);
+        info.samplers.add_wrap_to_bg(&mut bgb);
 
         let shadow_uniform_bg =
             bgb.build(&ctx.renderer.device, Some("shadow uniform bg"), &binding_handles.interfaces.depth_uniform_bgl);
 
         bgb.append_texture_view(shadow_target);
+        bgb.append_texture_view(ambient_occlusion);
+        info.environment.add_to_bg(&mut bgb);
+        bgb.append_texture_view(shadow_moments);
 
         let forward_uniform_bg = bgb.build(
             &ctx.renderer.device,
@@ -0,0 +1,209 @@
+//! Variance shadow maps, letting shadows be sampled with smooth edges from a
+//! single filtered tap.
+//!
+//! After the shadow maps are rendered, the depth and squared depth of every
+//! shadow map are blurred into the moments target, first horizontally and then
+//! vertically. The blur is clamped to the map each texel belongs to, so maps
+//! don't bleed into their neighbours in the atlas. The forward pass bounds the
+//! fraction of occluders in front of every fragment from the mean and variance
+//! of the depth around it, see
+//! [`ShadowFilter::Variance`](crate::uniforms::ShadowFilter::Variance).
+
+use std::borrow::Cow;
+
+use glam::{UVec2, UVec4, Vec4};
+use rend3::{
+    graph::{
+        NodeResourceUsage, RenderGraph, RenderPassTarget, RenderPassTargets, RenderTargetDescriptor, RenderTargetHandle,
+    },
+    managers::ShadowDesc,
+    types::{SampleCount, TextureFormat, TextureUsages},
+    util::bind_merge::{BindGroupBuilder, BindGroupLayoutBuilder},
+    ShaderPreProcessor,
+};
+use wgpu::{
+    util::{BufferInitDescriptor, DeviceExt},
+    BindGroupLayout, BindingType, BufferBindingType, BufferUsages, ColorTargetState, ColorWrites, Device,
+    FragmentState, MultisampleState, PipelineLayoutDescriptor, PrimitiveState, RenderPipeline,
+    RenderPipelineDescriptor, ShaderModuleDescriptor, ShaderSource, ShaderStages, TextureSampleType,
+    TextureViewDimension, VertexState,
+};
+
+/// Format of the blurred depth and squared depth, sampled by the forward pass.
+/// Half floats lack the precision for the squared depth.
+pub const SHADOW_MOMENTS_FORMAT: TextureFormat = TextureFormat::Rg32Float;
+
+struct BlurPass {
+    bgl: BindGroupLayout,
+    pipeline: RenderPipeline,
+}
+
+impl BlurPass {
+    fn new(
+        device: &Device,
+        spp: &ShaderPreProcessor,
+        label: &str,
+        shader: &str,
+        sample_type: TextureSampleType,
+    ) -> Self {
+        let bgl = BindGroupLayoutBuilder::new()
+            .append(
+                ShaderStages::VERTEX,
+                BindingType::Buffer {
+                    ty: BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                None,
+            )
+            .append(
+                ShaderStages::FRAGMENT,
+                BindingType::Texture { sample_type, view_dimension: TextureViewDimension::D2, multisampled: false },
+                None,
+            )
+            .build(device, Some(label));
+
+        let module = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some(label),
+            source: ShaderSource::Wgsl(Cow::Owned(spp.render_shader(shader, &(), None).unwrap())),
+        });
+
+        let pll = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some(label),
+            bind_group_layouts: &[&bgl],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some(label),
+            layout: Some(&pll),
+            vertex: VertexState { module: &module, entry_point: "vs_main", buffers: &[] },
+            primitive: PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: MultisampleState::default(),
+            fragment: Some(FragmentState {
+                module: &module,
+                entry_point: "fs_main",
+                targets: &[Some(ColorTargetState {
+                    format: SHADOW_MOMENTS_FORMAT,
+                    blend: None,
+                    write_mask: ColorWrites::all(),
+                })],
+            }),
+            multiview: None,
+        });
+
+        Self { bgl, pipeline }
+    }
+
+    fn add_to_graph<'node>(
+        &'node self,
+        graph: &mut RenderGraph<'node>,
+        label: &str,
+        source: RenderTargetHandle,
+        target: RenderTargetHandle,
+        rects: &[UVec4],
+    ) {
+        let mut builder = graph.add_node(label);
+        let source_handle = builder.add_render_target(source, NodeResourceUsage::Input);
+        let rpass_handle = builder.add_renderpass(
+            RenderPassTargets {
+                targets: vec![RenderPassTarget { color: target, clear: Vec4::ZERO, resolve: None }],
+                depth_stencil: None,
+            },
+            NodeResourceUsage::InputOutput,
+        );
+
+        let rects = rects.to_vec();
+        builder.build(move |mut ctx| {
+            let rpass = ctx.encoder_or_pass.take_rpass(rpass_handle);
+            let source = ctx.graph_data.get_render_target(source_handle);
+
+            profiling::scope!("variance shadow blur");
+
+            let rect_buffer = ctx.temps.add(ctx.renderer.device.create_buffer_init(&BufferInitDescriptor {
+                label: Some("variance shadow rects"),
+                contents: bytemuck::cast_slice(&rects),
+                usage: BufferUsages::STORAGE,
+            }));
+
+            let bg =
+                ctx.temps.add(BindGroupBuilder::new().append_buffer(rect_buffer).append_texture_view(source).build(
+                    &ctx.renderer.device,
+                    Some("variance shadow blur bg"),
+                    &self.bgl,
+                ));
+
+            rpass.set_pipeline(&self.pipeline);
+            rpass.set_bind_group(0, bg, &[]);
+            rpass.draw(0..6, 0..rects.len() as u32 - 1);
+        });
+    }
+}
+
+/// Routine blurring the shadow maps into the moments of variance shadow maps.
+///
+/// See module for documentation.
+pub struct VarianceShadowRoutine {
+    moments: BlurPass,
+    blur: BlurPass,
+}
+
+impl VarianceShadowRoutine {
+    pub fn new(device: &Device, spp: &ShaderPreProcessor) -> Self {
+        profiling::scope!("VarianceShadowRoutine::new");
+
+        Self {
+            moments: BlurPass::new(
+                device,
+                spp,
+                "variance shadow moments",
+                "rend3-routine/variance_moments.wgsl",
+                TextureSampleType::Depth,
+            ),
+            blur: BlurPass::new(
+                device,
+                spp,
+                "variance shadow blur",
+                "rend3-routine/variance_blur.wgsl",
+                TextureSampleType::Float { filterable: false },
+            ),
+        }
+    }
+
+    /// Blurs the depth of the `shadows` atlas into `moments`, which both have
+    /// the given resolution. Only the texels of the given shadow maps are
+    /// written.
+    pub fn add_to_graph<'node>(
+        &'node self,
+        graph: &mut RenderGraph<'node>,
+        shadow: RenderTargetHandle,
+        moments: RenderTargetHandle,
+        resolution: UVec2,
+        shadows: &[ShadowDesc],
+    ) {
+        let horizontal = graph.add_render_target(RenderTargetDescriptor {
+            label: Some("shadow moments horizontal".into()),
+            resolution,
+            depth: 1,
+            mip_levels: Some(1),
+            samples: SampleCount::One,
+            format: SHADOW_MOMENTS_FORMAT,
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+        });
+
+        let rects = shadow_rects(resolution, shadows);
+        self.moments.add_to_graph(graph, "Variance Shadow Moments", shadow, horizontal, &rects);
+        self.blur.add_to_graph(graph, "Variance Shadow Blur", horizontal, moments, &rects);
+    }
+}
+
+/// Contents of the rect buffer of the shaders: the atlas size, followed by the
+/// offset and size of every shadow map.
+fn shadow_rects(resolution: UVec2, shadows: &[ShadowDesc]) -> Vec<UVec4> {
+    std::iter::once(UVec4::new(resolution.x, resolution.y, 0, 0))
+        .chain(
+            shadows.iter().map(|desc| UVec4::new(desc.map.offset.x, desc.map.offset.y, desc.map.size, desc.map.size)),
+        )
+        .collect()
+}
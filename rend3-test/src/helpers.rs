@@ -30,6 +30,13 @@ impl TestRunner {
             color: glam::Vec3::ONE,
             resolution: 256,
             distance: 5.0,
+            cascades: 1,
+            max_cascades: 1,
+            cascade_split_lambda: 0.5,
+            cascade_blend: 0.1,
+            shadow_bias: 0.0,
+            shadow_slope_bias: 0.0,
+            shadow_normal_offset: 0.0,
             intensity: 1.0,
             direction,
         })
@@ -70,6 +77,8 @@ impl TestRunner {
             mesh_kind: rend3::types::ObjectMeshKind::Static(self.add_mesh(mesh).unwrap()),
             material,
             transform,
+            flags: rend3::types::ObjectFlags::default(),
+            layer_mask: rend3::types::ALL_LAYERS,
         })
     }
 
@@ -126,6 +135,8 @@ impl TestRunner {
             mesh_kind: rend3::types::ObjectMeshKind::Static(self.add_mesh(mesh).unwrap()),
             material,
             transform,
+            flags: rend3::types::ObjectFlags::default(),
+            layer_mask: rend3::types::ALL_LAYERS,
         })
     }
 }
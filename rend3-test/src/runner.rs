@@ -10,9 +10,7 @@ use rend3::{
     Renderer,
 };
 use rend3_routine::{base::BaseRenderGraph, pbr::PbrRoutine, tonemapping::TonemappingRoutine};
-use wgpu::{
-    Extent3d, ImageCopyBuffer, ImageDataLayout, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages,
-};
+use wgpu::{Extent3d, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages};
 
 use crate::{helpers::CaptureDropGuard, ThresholdSet};
 
@@ -20,11 +18,13 @@ use crate::{helpers::CaptureDropGuard, ThresholdSet};
 pub struct FrameRenderSettings {
     size: u32,
     samples: SampleCount,
+    depth_prepass: bool,
+    fog: Option<rend3_routine::uniforms::Fog>,
 }
 
 impl FrameRenderSettings {
     pub fn new() -> Self {
-        Self { size: 64, samples: SampleCount::One }
+        Self { size: 64, samples: SampleCount::One, depth_prepass: false, fog: None }
     }
 
     pub fn size(mut self, size: u32) -> Result<Self> {
@@ -37,6 +37,16 @@ impl FrameRenderSettings {
         self.samples = samples;
         self
     }
+
+    pub fn depth_prepass(mut self, depth_prepass: bool) -> Self {
+        self.depth_prepass = depth_prepass;
+        self
+    }
+
+    pub fn fog(mut self, fog: Option<rend3_routine::uniforms::Fog>) -> Self {
+        self.fog = fog;
+        self
+    }
 }
 
 impl Default for FrameRenderSettings {
@@ -49,6 +59,7 @@ impl Default for FrameRenderSettings {
 pub struct TestRunnerBuilder {
     handness: Option<Handedness>,
     iad: Option<rend3::InstanceAdapterDevice>,
+    stencil: bool,
 }
 
 impl TestRunnerBuilder {
@@ -66,6 +77,12 @@ impl TestRunnerBuilder {
         self
     }
 
+    /// Render with a combined depth-stencil buffer.
+    pub fn stencil(mut self, stencil: bool) -> Self {
+        self.stencil = stencil;
+        self
+    }
+
     pub async fn build(self) -> Result<TestRunner> {
         let _ = env_logger::try_init();
 
@@ -81,7 +98,10 @@ impl TestRunnerBuilder {
         let mut spp = rend3::ShaderPreProcessor::new();
         rend3_routine::builtin_shaders(&mut spp);
 
-        let base_rendergraph = BaseRenderGraph::new(&renderer, &spp);
+        let base_rendergraph = match self.stencil {
+            true => BaseRenderGraph::with_stencil(&renderer, &spp),
+            false => BaseRenderGraph::new(&renderer, &spp),
+        };
 
         let pbr = PbrRoutine::new(
             &renderer,
@@ -159,7 +179,23 @@ impl TestRunner {
             },
             rend3_routine::base::BaseRenderGraphSettings {
                 ambient_color: glam::Vec4::ZERO,
+                ambient_intensity: 1.0,
                 clear_color: glam::Vec4::ZERO,
+                time: 0.0,
+                shadow_filter: rend3_routine::uniforms::ShadowFilter::Pcf3x3,
+                background_intensity: 1.0,
+                exposure: 1.0,
+                tonemapping_operator: rend3_routine::tonemapping::TonemappingOperator::None,
+                bloom: None,
+                anti_alias: rend3_routine::fxaa::AntiAlias::None,
+                depth_prepass: settings.depth_prepass,
+                fog: settings.fog,
+                ssao: None,
+                wireframe: false,
+                debug_view: rend3_routine::uniforms::DebugView::None,
+                disable_culling: false,
+                pick: None,
+                read_depth: false,
             },
         );
 
@@ -191,37 +227,9 @@ pub async fn download_image(
     texture: wgpu::Texture,
     size: UVec2,
 ) -> anyhow::Result<image::RgbaImage> {
-    assert_eq!(size.x % 64, 0, "Image width must be a multiple of 64");
-
-    let buffer = renderer.device.create_buffer(&wgpu::BufferDescriptor {
-        label: Some("Test output buffer"),
-        size: (size.x * size.y * 4) as u64,
-        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
-        mapped_at_creation: false,
-    });
-
-    let mut encoder =
-        renderer.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Test output encoder") });
-    encoder.copy_texture_to_buffer(
-        texture.as_image_copy(),
-        ImageCopyBuffer {
-            buffer: &buffer,
-            layout: ImageDataLayout { offset: 0, bytes_per_row: Some(size.x * 4), rows_per_image: None },
-        },
-        Extent3d { width: size.x, height: size.y, depth_or_array_layers: 1 },
-    );
-
-    let submit_index = renderer.queue.submit(Some(encoder.finish()));
-
-    let (sender, receiver) = flume::bounded(1);
-    buffer.slice(..).map_async(wgpu::MapMode::Read, move |_| sender.send(()).unwrap());
-    renderer.device.poll(wgpu::Maintain::WaitForSubmissionIndex(submit_index));
-
-    receiver.recv_async().await.context("Failed to recieve message from map_async")?;
-
-    let mapping = buffer.slice(..).get_mapped_range();
+    let data = renderer.read_texture(&texture).await.context("Failed to read back texture")?;
 
-    image::RgbaImage::from_raw(size.x, size.y, mapping.to_vec()).context("Failed to create image from mapping")
+    image::RgbaImage::from_raw(size.x, size.y, data).context("Failed to create image from mapping")
 }
 
 pub fn compare_image_to_path(
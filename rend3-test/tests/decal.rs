@@ -0,0 +1,54 @@
+use anyhow::Context;
+use glam::{Mat4, Quat, UVec2, Vec3, Vec4};
+use rend3::types::{Camera, Decal, Handedness, MipmapCount, MipmapSource, Texture, TextureFormat, ALL_LAYERS};
+use rend3_test::{no_gpu_return, test_attr, FrameRenderSettings, TestRunner, Threshold};
+
+/// An opaque white decal covers a black plane entirely, only where the plane is.
+#[test_attr]
+pub async fn projected_decal() -> anyhow::Result<()> {
+    let iad = no_gpu_return!(rend3::create_iad(None, None, None, None).await)
+        .context("InstanceAdapterDevice creation failed")?;
+
+    let Ok(runner) = TestRunner::builder().iad(iad).handedness(Handedness::Left).build().await else {
+        return Ok(());
+    };
+
+    runner.set_camera_data(Camera {
+        projection: rend3::types::CameraProjection::Raw(Mat4::IDENTITY),
+        view: Mat4::IDENTITY,
+        layer_mask: ALL_LAYERS,
+    });
+
+    // In front of the far plane, where decals aren't projected.
+    let material = runner.add_unlit_material(Vec4::new(0.0, 0.0, 0.0, 1.0));
+    let _plane = runner.plane(
+        material,
+        Mat4::from_scale_rotation_translation(Vec3::new(-0.25, 0.25, 0.25), Quat::IDENTITY, Vec3::new(-0.5, 0.0, 0.5)),
+    );
+
+    let texture = runner.add_texture_2d(Texture {
+        label: None,
+        data: vec![255; 4],
+        format: TextureFormat::Rgba8UnormSrgb,
+        size: UVec2::ONE,
+        mip_count: MipmapCount::ONE,
+        mip_source: MipmapSource::Uploaded,
+    })?;
+
+    // The box is larger than the plane, the pixels around it have no surface to project onto.
+    let _decal = runner.add_decal(Decal {
+        transform: Mat4::from_scale_rotation_translation(Vec3::splat(0.75), Quat::IDENTITY, Vec3::new(-0.5, 0.0, 0.5)),
+        texture,
+        color: Vec4::ONE,
+    });
+
+    runner
+        .render_and_compare(
+            FrameRenderSettings::new(),
+            "tests/results/object/duplicate-object-retain-left.png",
+            Threshold::Mean(0.0),
+        )
+        .await?;
+
+    Ok(())
+}
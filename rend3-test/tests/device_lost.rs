@@ -0,0 +1,34 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use anyhow::Context;
+use rend3_test::{no_gpu_return, test_attr, TestRunner};
+use wgpu::{DeviceLostReason, Maintain};
+
+/// Destroying the device marks the renderer as lost and notifies the callback.
+#[test_attr]
+pub async fn destroyed_device_is_lost() -> anyhow::Result<()> {
+    let iad = no_gpu_return!(rend3::create_iad(None, None, None, None).await)
+        .context("InstanceAdapterDevice creation failed")?;
+
+    let Ok(runner) = TestRunner::builder().iad(iad).build().await else {
+        return Ok(());
+    };
+
+    let notified = Arc::new(AtomicBool::new(false));
+    let notified_clone = Arc::clone(&notified);
+    runner.set_device_lost_callback(move |_| notified_clone.store(true, Ordering::Release));
+
+    assert!(runner.device_lost().is_none());
+
+    runner.device.destroy();
+    runner.device.poll(Maintain::Wait);
+
+    let lost = runner.device_lost().context("device wasn't marked as lost")?;
+    assert!(matches!(lost.reason, DeviceLostReason::Destroyed));
+    assert!(notified.load(Ordering::Acquire));
+
+    Ok(())
+}
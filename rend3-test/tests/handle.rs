@@ -0,0 +1,65 @@
+use anyhow::Context;
+use glam::{Mat4, Vec4};
+use rend3::types::Handedness;
+use rend3_test::{no_gpu_return, test_attr, TestRunner};
+
+/// Handles are allocated per-renderer, so the same sequence of adds must yield the same handles,
+/// no matter what other renderers have allocated.
+#[test_attr]
+pub async fn deterministic_handles() -> anyhow::Result<()> {
+    let iad = no_gpu_return!(rend3::create_iad(None, None, None, None).await)
+        .context("InstanceAdapterDevice creation failed")?;
+
+    let Ok(runner1) = TestRunner::builder().iad(iad.clone()).handedness(Handedness::Left).build().await else {
+        return Ok(());
+    };
+    let Ok(runner2) = TestRunner::builder().iad(iad.clone()).handedness(Handedness::Left).build().await else {
+        return Ok(());
+    };
+
+    let record = |runner: &TestRunner| {
+        let material = runner.add_unlit_material(Vec4::ONE);
+        let objects: Vec<_> = (0..4).map(|_| runner.plane(material.clone(), Mat4::IDENTITY)).collect();
+        let mut indices: Vec<_> = objects.iter().map(|o| o.get_raw().idx).collect();
+        indices.push(material.get_raw().idx);
+        (objects, indices)
+    };
+
+    // Allocate in the first renderer so the second renderer's counters would differ if they were shared.
+    let (_objects1, indices1) = record(&runner1);
+    let (_objects2, indices2) = record(&runner2);
+
+    assert_eq!(indices1, indices2);
+    assert_eq!(indices1, [0, 1, 2, 3, 0]);
+
+    Ok(())
+}
+
+/// Freed handles are reused most recently freed first.
+#[test_attr]
+pub async fn handle_reuse_order() -> anyhow::Result<()> {
+    let iad = no_gpu_return!(rend3::create_iad(None, None, None, None).await)
+        .context("InstanceAdapterDevice creation failed")?;
+
+    let Ok(runner) = TestRunner::builder().iad(iad.clone()).handedness(Handedness::Left).build().await else {
+        return Ok(());
+    };
+
+    let material1 = runner.add_unlit_material(Vec4::ONE);
+    let material2 = runner.add_unlit_material(Vec4::ONE);
+    let material3 = runner.add_unlit_material(Vec4::ONE);
+    assert_eq!(material3.get_raw().idx, 2);
+
+    drop(material1);
+    drop(material2);
+
+    // Indices are only freed once the deletions have been processed.
+    runner.swap_instruction_buffers();
+    let _ = runner.evaluate_instructions();
+
+    assert_eq!(runner.add_unlit_material(Vec4::ONE).get_raw().idx, 1);
+    assert_eq!(runner.add_unlit_material(Vec4::ONE).get_raw().idx, 0);
+    assert_eq!(runner.add_unlit_material(Vec4::ONE).get_raw().idx, 3);
+
+    Ok(())
+}
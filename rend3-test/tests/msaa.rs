@@ -1,6 +1,6 @@
 use anyhow::Context;
 use glam::{Mat4, Vec3, Vec4};
-use rend3::types::{Camera, Handedness, MeshBuilder, Object, ObjectMeshKind, SampleCount};
+use rend3::types::{Camera, Handedness, MeshBuilder, Object, ObjectFlags, ObjectMeshKind, SampleCount, ALL_LAYERS};
 use rend3_test::{no_gpu_return, test_attr, FrameRenderSettings, TestRunner, Threshold};
 
 #[test_attr]
@@ -22,13 +22,19 @@ pub async fn triangle() -> anyhow::Result<()> {
 
     let mesh_hdl = runner.add_mesh(mesh).unwrap();
     let material_hdl = runner.add_unlit_material(Vec4::new(0.25, 0.5, 0.75, 1.0));
-    let object =
-        Object { mesh_kind: ObjectMeshKind::Static(mesh_hdl), material: material_hdl, transform: Mat4::IDENTITY };
+    let object = Object {
+        mesh_kind: ObjectMeshKind::Static(mesh_hdl),
+        material: material_hdl,
+        transform: Mat4::IDENTITY,
+        flags: ObjectFlags::default(),
+        layer_mask: ALL_LAYERS,
+    };
     let _object_hdl = runner.add_object(object);
 
     runner.set_camera_data(Camera {
         projection: rend3::types::CameraProjection::Raw(Mat4::IDENTITY),
         view: Mat4::IDENTITY,
+        layer_mask: ALL_LAYERS,
     });
 
     runner
@@ -71,6 +77,7 @@ pub async fn sample_coverage() -> anyhow::Result<()> {
     runner.set_camera_data(Camera {
         projection: rend3::types::CameraProjection::Raw(Mat4::orthographic_lh(0.0, 64.0, 64.0, 0.0, 0.0, 1.0)),
         view: Mat4::IDENTITY,
+        layer_mask: ALL_LAYERS,
     });
 
     for samples in SampleCount::ARRAY {
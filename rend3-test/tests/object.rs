@@ -1,8 +1,14 @@
 use anyhow::Context;
-use glam::{Mat4, Quat, Vec3, Vec4};
+use glam::{Mat4, Quat, UVec2, Vec3, Vec4};
 use rend3::{
-    types::{Camera, Handedness, ObjectChange},
-    util::freelist::FreelistDerivedBuffer,
+    types::{
+        Camera, Handedness, MaterialOverride, Object, ObjectChange, ObjectFlags, SampleCount, TextureFormat, ALL_LAYERS,
+    },
+    util::{freelist::FreelistDerivedBuffer, frustum::Aabb},
+};
+use rend3_routine::{
+    pbr::{AlbedoComponent, PbrMaterial},
+    uniforms::{Fog, FogMode},
 };
 use rend3_test::{no_gpu_return, test_attr, FrameRenderSettings, TestRunner, Threshold};
 
@@ -19,6 +25,7 @@ pub async fn deuplicate_object_retain() -> anyhow::Result<()> {
     runner.set_camera_data(Camera {
         projection: rend3::types::CameraProjection::Raw(Mat4::IDENTITY),
         view: Mat4::IDENTITY,
+        layer_mask: ALL_LAYERS,
     });
 
     let material = runner.add_unlit_material(Vec4::ONE);
@@ -59,6 +66,56 @@ pub async fn deuplicate_object_retain() -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Cameras only render the objects sharing one of their layers.
+#[test_attr]
+pub async fn layer_mask() -> anyhow::Result<()> {
+    let iad = no_gpu_return!(rend3::create_iad(None, None, None, None).await)
+        .context("InstanceAdapterDevice creation failed")?;
+
+    let Ok(runner) = TestRunner::builder().iad(iad.clone()).handedness(Handedness::Left).build().await else {
+        return Ok(());
+    };
+
+    let material = runner.add_unlit_material(Vec4::ONE);
+    let plane = runner.plane(
+        material,
+        Mat4::from_scale_rotation_translation(Vec3::new(-0.25, 0.25, 0.25), Quat::IDENTITY, Vec3::new(-0.5, 0.0, 0.0)),
+    );
+    let _left = runner.duplicate_object(&plane, ObjectChange { layer_mask: Some(0b01), ..Default::default() });
+    let _right = runner.duplicate_object(
+        &plane,
+        ObjectChange {
+            transform: Some(Mat4::from_scale_rotation_translation(
+                Vec3::new(-0.25, 0.25, 0.25),
+                Quat::IDENTITY,
+                Vec3::new(0.5, 0.0, 0.0),
+            )),
+            layer_mask: Some(0b10),
+            ..Default::default()
+        },
+    );
+    drop(plane);
+
+    let tests = [
+        (0b01, "tests/results/object/duplicate-object-retain-left.png"),
+        (0b10, "tests/results/object/duplicate-object-retain-right.png"),
+    ];
+    for (layer_mask, file_name) in tests {
+        runner.set_camera_data(Camera {
+            projection: rend3::types::CameraProjection::Raw(Mat4::IDENTITY),
+            view: Mat4::IDENTITY,
+            layer_mask,
+        });
+
+        runner
+            .render_and_compare(FrameRenderSettings::new(), file_name, Threshold::Mean(0.0))
+            .await
+            .with_context(|| format!("Comparison failed with layer mask {layer_mask:#b}"))?;
+    }
+
+    Ok(())
+}
+
 /// There was a bug in the culling implementation where the per-material buffer
 /// was never resized to fit the number of objects in the scene once it was initially
 /// created. This manifested as objects above the initial frame count would get all-zero
@@ -81,6 +138,7 @@ pub async fn multi_frame_add() -> anyhow::Result<()> {
     runner.set_camera_data(Camera {
         projection: rend3::types::CameraProjection::Raw(Mat4::orthographic_lh(0.0, 2.0, 16.0, 0.0, 0.0, 1.0)),
         view: Mat4::IDENTITY,
+        layer_mask: ALL_LAYERS,
     });
 
     // We use the starting size amount of objects for each column, ensuring that the buffer
@@ -107,3 +165,437 @@ pub async fn multi_frame_add() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+/// Ensure hidden objects aren't drawn, and are drawn again once shown.
+#[test_attr]
+pub async fn object_visibility() -> anyhow::Result<()> {
+    let iad = no_gpu_return!(rend3::create_iad(None, None, None, None).await)
+        .context("InstanceAdapterDevice creation failed")?;
+
+    let Ok(runner) = TestRunner::builder().iad(iad.clone()).handedness(Handedness::Left).build().await else {
+        return Ok(());
+    };
+
+    runner.set_camera_data(Camera {
+        projection: rend3::types::CameraProjection::Raw(Mat4::IDENTITY),
+        view: Mat4::IDENTITY,
+        layer_mask: ALL_LAYERS,
+    });
+
+    let material = runner.add_unlit_material(Vec4::ONE);
+    let left = runner.plane(
+        material.clone(),
+        Mat4::from_scale_rotation_translation(Vec3::new(-0.25, 0.25, 0.25), Quat::IDENTITY, Vec3::new(-0.5, 0.0, 0.0)),
+    );
+    let right = runner.plane(
+        material,
+        Mat4::from_scale_rotation_translation(Vec3::new(-0.25, 0.25, 0.25), Quat::IDENTITY, Vec3::new(0.5, 0.0, 0.0)),
+    );
+
+    runner.set_object_visibility(&right, false);
+    runner
+        .render_and_compare(
+            FrameRenderSettings::new(),
+            "tests/results/object/duplicate-object-retain-left.png",
+            Threshold::Mean(0.0),
+        )
+        .await?;
+
+    runner.set_object_visibility(&left, false);
+    runner.set_object_visibility(&right, true);
+    runner
+        .render_and_compare(
+            FrameRenderSettings::new(),
+            "tests/results/object/duplicate-object-retain-right.png",
+            Threshold::Mean(0.0),
+        )
+        .await?;
+
+    Ok(())
+}
+
+/// Ensure instructions in a batch are evaluated in the order they were recorded.
+#[test_attr]
+pub async fn batch_order() -> anyhow::Result<()> {
+    let iad = no_gpu_return!(rend3::create_iad(None, None, None, None).await)
+        .context("InstanceAdapterDevice creation failed")?;
+
+    let Ok(runner) = TestRunner::builder().iad(iad.clone()).handedness(Handedness::Left).build().await else {
+        return Ok(());
+    };
+
+    runner.set_camera_data(Camera {
+        projection: rend3::types::CameraProjection::Raw(Mat4::IDENTITY),
+        view: Mat4::IDENTITY,
+        layer_mask: ALL_LAYERS,
+    });
+
+    let template = runner.plane(runner.add_unlit_material(Vec4::ZERO), Mat4::IDENTITY);
+    runner.set_object_visibility(&template, false);
+
+    let mut batch = runner.batch();
+    let material = batch.add_material(PbrMaterial {
+        albedo: AlbedoComponent::Value(Vec4::ONE),
+        unlit: true,
+        ..Default::default()
+    });
+    let left = batch.duplicate_object(&template, ObjectChange { material: Some(material), ..Default::default() });
+    let right = batch.duplicate_object(&left, ObjectChange::default());
+    batch.set_object_transform(
+        &left,
+        Mat4::from_scale_rotation_translation(Vec3::new(-0.25, 0.25, 0.25), Quat::IDENTITY, Vec3::new(-0.5, 0.0, 0.0)),
+    );
+    batch.set_object_transform(
+        &right,
+        Mat4::from_scale_rotation_translation(Vec3::new(-0.25, 0.25, 0.25), Quat::IDENTITY, Vec3::new(0.5, 0.0, 0.0)),
+    );
+    batch.set_object_visibility(&left, false);
+    batch.set_object_visibility(&left, true);
+    batch.set_object_visibility(&right, false);
+    runner.submit_batch(batch);
+
+    runner
+        .render_and_compare(
+            FrameRenderSettings::new(),
+            "tests/results/object/duplicate-object-retain-left.png",
+            Threshold::Mean(0.0),
+        )
+        .await?;
+
+    Ok(())
+}
+
+/// Ensure children follow their parent's transform.
+#[test_attr]
+pub async fn object_parent() -> anyhow::Result<()> {
+    let iad = no_gpu_return!(rend3::create_iad(None, None, None, None).await)
+        .context("InstanceAdapterDevice creation failed")?;
+
+    let Ok(runner) = TestRunner::builder().iad(iad.clone()).handedness(Handedness::Left).build().await else {
+        return Ok(());
+    };
+
+    runner.set_camera_data(Camera {
+        projection: rend3::types::CameraProjection::Raw(Mat4::IDENTITY),
+        view: Mat4::IDENTITY,
+        layer_mask: ALL_LAYERS,
+    });
+
+    let material = runner.add_unlit_material(Vec4::ONE);
+    let parent = runner.plane(material.clone(), Mat4::from_translation(Vec3::new(-0.5, 0.0, 0.0)));
+    runner.set_object_visibility(&parent, false);
+    let child = runner.plane(material, Mat4::from_scale(Vec3::new(-0.25, 0.25, 0.25)));
+    runner.set_object_parent(&child, Some(&parent));
+
+    runner
+        .render_and_compare(
+            FrameRenderSettings::new(),
+            "tests/results/object/duplicate-object-retain-left.png",
+            Threshold::Mean(0.0),
+        )
+        .await?;
+
+    runner.set_object_transform(&parent, Mat4::from_translation(Vec3::new(0.5, 0.0, 0.0)));
+    runner
+        .render_and_compare(
+            FrameRenderSettings::new(),
+            "tests/results/object/duplicate-object-retain-right.png",
+            Threshold::Mean(0.0),
+        )
+        .await?;
+
+    // Once detached, the child's transform is relative to the world again.
+    runner.set_object_parent(&child, None);
+    runner.set_object_transform(
+        &child,
+        Mat4::from_scale_rotation_translation(Vec3::new(-0.25, 0.25, 0.25), Quat::IDENTITY, Vec3::new(-0.5, 0.0, 0.0)),
+    );
+    runner
+        .render_and_compare(
+            FrameRenderSettings::new(),
+            "tests/results/object/duplicate-object-retain-left.png",
+            Threshold::Mean(0.0),
+        )
+        .await?;
+
+    Ok(())
+}
+
+#[test_attr]
+pub async fn stale_handle() -> anyhow::Result<()> {
+    let iad = no_gpu_return!(rend3::create_iad(None, None, None, None).await)
+        .context("InstanceAdapterDevice creation failed")?;
+
+    let Ok(runner) = TestRunner::builder().iad(iad.clone()).handedness(Handedness::Left).build().await else {
+        return Ok(());
+    };
+
+    let material = runner.add_unlit_material(Vec4::ONE);
+    let object = runner.plane(material.clone(), Mat4::IDENTITY);
+    let stale = object.get_raw();
+    assert!(runner.is_alive(stale));
+
+    drop(object);
+    runner.evaluate_instructions();
+    assert!(!runner.is_alive(stale));
+    // Object indices are only reused once the frame after the removal is evaluated.
+    runner.evaluate_instructions();
+
+    // The new object reuses the index, but the old handle stays stale.
+    let object = runner.plane(material, Mat4::IDENTITY);
+    assert_eq!(object.idx, stale.idx);
+    assert!(runner.is_alive(object.get_raw()));
+    assert!(!runner.is_alive(stale));
+    assert_eq!(runner.live_handle::<Object>(stale.idx).map(|live| live.generation), Some(object.generation));
+
+    Ok(())
+}
+
+#[test_attr]
+pub async fn uploads_done() -> anyhow::Result<()> {
+    let iad = no_gpu_return!(rend3::create_iad(None, None, None, None).await)
+        .context("InstanceAdapterDevice creation failed")?;
+
+    let Ok(runner) = TestRunner::builder().iad(iad.clone()).handedness(Handedness::Left).build().await else {
+        return Ok(());
+    };
+
+    runner.set_camera_data(Camera {
+        projection: rend3::types::CameraProjection::Raw(Mat4::IDENTITY),
+        view: Mat4::IDENTITY,
+        layer_mask: ALL_LAYERS,
+    });
+
+    let material = runner.add_unlit_material(Vec4::ONE);
+    let _plane = runner.plane(
+        material,
+        Mat4::from_scale_rotation_translation(Vec3::new(-0.25, 0.25, 0.25), Quat::IDENTITY, Vec3::new(-0.5, 0.0, 0.0)),
+    );
+    let done = runner.uploads_done();
+
+    // Reading back the frame waits for the gpu, completing the uploads.
+    runner
+        .render_and_compare(
+            FrameRenderSettings::new(),
+            "tests/results/object/duplicate-object-retain-left.png",
+            Threshold::Mean(0.0),
+        )
+        .await?;
+    done.await;
+
+    Ok(())
+}
+
+#[test_attr]
+pub async fn render_target() -> anyhow::Result<()> {
+    let iad = no_gpu_return!(rend3::create_iad(None, None, None, None).await)
+        .context("InstanceAdapterDevice creation failed")?;
+
+    let Ok(runner) = TestRunner::builder().iad(iad.clone()).handedness(Handedness::Left).build().await else {
+        return Ok(());
+    };
+
+    let mut target = runner.add_render_target(UVec2::splat(32), TextureFormat::Rgba8UnormSrgb, SampleCount::Four)?;
+    assert_eq!(target.size(), UVec2::splat(32));
+
+    target.resize(&runner, UVec2::splat(64))?;
+    assert_eq!(target.size(), UVec2::splat(64));
+    assert_eq!(target.texture().size().width, 64);
+    assert_eq!(target.format(), TextureFormat::Rgba8UnormSrgb);
+    assert_eq!(target.samples(), SampleCount::Four);
+
+    // Depth formats can't be sampled by materials.
+    assert!(runner.add_render_target(UVec2::splat(32), TextureFormat::Depth32Float, SampleCount::One).is_err());
+
+    runner.add_material(PbrMaterial {
+        albedo: AlbedoComponent::Texture(target.handle().clone()),
+        unlit: true,
+        ..Default::default()
+    });
+    runner.swap_instruction_buffers();
+    runner.evaluate_instructions();
+
+    Ok(())
+}
+
+#[test_attr]
+pub async fn object_bounds() -> anyhow::Result<()> {
+    let iad = no_gpu_return!(rend3::create_iad(None, None, None, None).await)
+        .context("InstanceAdapterDevice creation failed")?;
+
+    let Ok(runner) = TestRunner::builder().iad(iad.clone()).handedness(Handedness::Left).build().await else {
+        return Ok(());
+    };
+
+    let material = runner.add_unlit_material(Vec4::ONE);
+    let plane = runner.plane(material, Mat4::from_translation(Vec3::new(2.0, 0.0, 0.0)));
+
+    // Nothing is known about the object until its instructions are evaluated.
+    assert_eq!(runner.object_bounds(&plane), None);
+    runner.swap_instruction_buffers();
+    runner.evaluate_instructions();
+    assert_eq!(
+        runner.object_bounds(&plane),
+        Some(Aabb { min: Vec3::new(1.0, -1.0, 0.0), max: Vec3::new(3.0, 1.0, 0.0) })
+    );
+
+    runner.set_object_transform(&plane, Mat4::from_scale(Vec3::splat(2.0)));
+    runner.swap_instruction_buffers();
+    runner.evaluate_instructions();
+    assert_eq!(
+        runner.object_bounds(&plane),
+        Some(Aabb { min: Vec3::new(-2.0, -2.0, 0.0), max: Vec3::new(2.0, 2.0, 0.0) })
+    );
+
+    Ok(())
+}
+
+#[test_attr]
+pub async fn scene_queries() -> anyhow::Result<()> {
+    let iad = no_gpu_return!(rend3::create_iad(None, None, None, None).await)
+        .context("InstanceAdapterDevice creation failed")?;
+
+    let Ok(runner) = TestRunner::builder().iad(iad.clone()).handedness(Handedness::Left).build().await else {
+        return Ok(());
+    };
+
+    let material = runner.add_unlit_material(Vec4::ONE);
+    let transform = Mat4::from_translation(Vec3::new(2.0, 0.0, 0.0));
+    let plane1 = runner.plane(material.clone(), transform);
+    let plane2 = runner.plane(material.clone(), Mat4::IDENTITY);
+
+    // Nothing is known about the objects until their instructions are evaluated.
+    assert_eq!(runner.object_count(), 0);
+    assert!(runner.material(material.get_raw()).is_none());
+    runner.swap_instruction_buffers();
+    runner.evaluate_instructions();
+
+    assert_eq!(runner.object_count(), 2);
+    let mut objects = runner.objects();
+    objects.sort_by_key(|handle| handle.idx);
+    assert_eq!(objects, [plane1.get_raw(), plane2.get_raw()]);
+
+    let snapshot = runner.object(plane1.get_raw()).unwrap();
+    assert_eq!(snapshot.material, material.get_raw());
+    assert_eq!(snapshot.transform, transform);
+    assert!(snapshot.visible);
+
+    let material_snapshot = runner.material(snapshot.material).unwrap();
+    assert!(material_snapshot.type_name.ends_with("PbrMaterial"));
+    assert!(material_snapshot.textures.iter().all(Option::is_none));
+
+    let plane2_raw = plane2.get_raw();
+    drop(plane2);
+    runner.swap_instruction_buffers();
+    runner.evaluate_instructions();
+    assert_eq!(runner.object_count(), 1);
+    assert_eq!(runner.objects(), [plane1.get_raw()]);
+    assert!(runner.object(plane2_raw).is_none());
+
+    Ok(())
+}
+
+/// Material overrides are multiplied with the material, and kept by duplicates.
+#[test_attr]
+pub async fn material_override() -> anyhow::Result<()> {
+    let iad = no_gpu_return!(rend3::create_iad(None, None, None, None).await)
+        .context("InstanceAdapterDevice creation failed")?;
+
+    let Ok(runner) = TestRunner::builder().iad(iad.clone()).handedness(Handedness::Left).build().await else {
+        return Ok(());
+    };
+
+    runner.set_camera_data(Camera {
+        projection: rend3::types::CameraProjection::Raw(Mat4::IDENTITY),
+        view: Mat4::IDENTITY,
+        layer_mask: ALL_LAYERS,
+    });
+
+    let material = runner.add_unlit_material(Vec4::new(0.5, 0.5, 0.5, 1.0));
+    let object1 = runner.plane(
+        material,
+        Mat4::from_scale_rotation_translation(Vec3::new(-0.25, 0.25, 0.25), Quat::IDENTITY, Vec3::new(-0.5, 0.0, 0.0)),
+    );
+    let material_override = MaterialOverride { color: Vec4::new(2.0, 2.0, 2.0, 1.0), ..MaterialOverride::IDENTITY };
+    runner.set_object_material_override(&object1, material_override);
+
+    runner
+        .render_and_compare(
+            FrameRenderSettings::new(),
+            "tests/results/object/duplicate-object-retain-left.png",
+            Threshold::Mean(0.0),
+        )
+        .await?;
+    assert_eq!(runner.object(object1.get_raw()).unwrap().material_override, material_override);
+
+    let _object2 = runner.duplicate_object(
+        &object1,
+        ObjectChange {
+            transform: Some(Mat4::from_scale_rotation_translation(
+                Vec3::new(-0.25, 0.25, 0.25),
+                Quat::IDENTITY,
+                Vec3::new(0.5, 0.0, 0.0),
+            )),
+            ..Default::default()
+        },
+    );
+    drop(object1);
+
+    runner
+        .render_and_compare(
+            FrameRenderSettings::new(),
+            "tests/results/object/duplicate-object-retain-right.png",
+            Threshold::Mean(0.0),
+        )
+        .await?;
+
+    Ok(())
+}
+
+/// Fog only covers objects with `ObjectFlags::RECEIVE_FOG`.
+#[test_attr]
+pub async fn receive_fog() -> anyhow::Result<()> {
+    let iad = no_gpu_return!(rend3::create_iad(None, None, None, None).await)
+        .context("InstanceAdapterDevice creation failed")?;
+
+    let Ok(runner) = TestRunner::builder().iad(iad.clone()).handedness(Handedness::Left).build().await else {
+        return Ok(());
+    };
+
+    runner.set_camera_data(Camera {
+        projection: rend3::types::CameraProjection::Raw(Mat4::IDENTITY),
+        view: Mat4::IDENTITY,
+        layer_mask: ALL_LAYERS,
+    });
+
+    let material = runner.add_unlit_material(Vec4::ONE);
+    let fogged = runner.plane(
+        material,
+        Mat4::from_scale_rotation_translation(Vec3::new(-0.25, 0.25, 0.25), Quat::IDENTITY, Vec3::new(-0.5, 0.0, 0.0)),
+    );
+    let _clear = runner.duplicate_object(
+        &fogged,
+        ObjectChange {
+            transform: Some(Mat4::from_scale_rotation_translation(
+                Vec3::new(-0.25, 0.25, 0.25),
+                Quat::IDENTITY,
+                Vec3::new(0.5, 0.0, 0.0),
+            )),
+            flags: Some(ObjectFlags::RECEIVE_AMBIENT),
+            ..Default::default()
+        },
+    );
+
+    // The fog is thick enough to turn the fogged plane black like the background, leaving only the
+    // plane on the right.
+    let fog = Fog { mode: FogMode::Exponential { density: 1000.0 }, color: Vec3::ZERO, skybox_amount: 0.0 };
+    runner
+        .render_and_compare(
+            FrameRenderSettings::new().fog(Some(fog)),
+            "tests/results/object/duplicate-object-retain-right.png",
+            Threshold::Mean(0.0),
+        )
+        .await?;
+
+    Ok(())
+}
@@ -1,4 +1,8 @@
+mod decal;
+mod device_lost;
+mod handle;
 mod msaa;
 mod object;
 mod shadow;
 mod simple;
+mod texture;
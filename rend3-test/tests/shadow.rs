@@ -2,7 +2,7 @@ use std::f32::consts::FRAC_PI_2;
 
 use anyhow::Context;
 use glam::{Mat4, Quat, Vec3, Vec3A, Vec4};
-use rend3::types::{Camera, Handedness};
+use rend3::types::{Camera, DirectionalLightChange, Handedness, ALL_LAYERS};
 use rend3_test::{no_gpu_return, test_attr, FrameRenderSettings, TestRunner, Threshold};
 
 #[test_attr]
@@ -14,7 +14,7 @@ pub async fn shadows() -> anyhow::Result<()> {
         return Ok(());
     };
 
-    let _light = runner.add_directional_light(Vec3::new(-1.0, -1.0, 1.0));
+    let light = runner.add_directional_light(Vec3::new(-1.0, -1.0, 1.0));
 
     let material1 = runner.add_lit_material(Vec4::new(0.25, 0.5, 0.75, 1.0));
 
@@ -23,6 +23,7 @@ pub async fn shadows() -> anyhow::Result<()> {
     runner.set_camera_data(Camera {
         projection: rend3::types::CameraProjection::Orthographic { size: Vec3A::new(2.5, 2.5, 5.0) },
         view: Mat4::look_at_lh(Vec3::new(0.0, 1.0, -1.0), Vec3::ZERO, Vec3::Y),
+        layer_mask: ALL_LAYERS,
     });
 
     let file_name = "tests/results/shadow/plane.png";
@@ -50,5 +51,23 @@ pub async fn shadows() -> anyhow::Result<()> {
         )
         .await?;
 
+    // Changing the color or intensity alone keeps every other field.
+    runner.update_directional_light(
+        &light,
+        DirectionalLightChange { intensity: Some(0.5), ..DirectionalLightChange::default() },
+    );
+    runner.render_frame(FrameRenderSettings::new().size(256)?).await?;
+    runner.update_directional_light(
+        &light,
+        DirectionalLightChange { color: Some(Vec3::splat(2.0)), ..DirectionalLightChange::default() },
+    );
+    runner
+        .render_and_compare(
+            FrameRenderSettings::new().size(256)?,
+            file_name,
+            Threshold::Percentile { percentile: 0.5, threshold: 0.04 },
+        )
+        .await?;
+
     Ok(())
 }
@@ -1,6 +1,10 @@
 use anyhow::Context;
-use glam::{Mat4, Vec3, Vec4};
-use rend3::types::{Camera, Handedness, MeshBuilder, Object, ObjectMeshKind};
+use glam::{Mat4, Quat, UVec2, Vec3, Vec4};
+use rend3::{
+    graph::ViewportRect,
+    managers::Viewport,
+    types::{Camera, Handedness, MeshBuilder, Object, ObjectFlags, ObjectMeshKind, SampleCount, ALL_LAYERS},
+};
 use rend3_test::{no_gpu_return, test_attr, FrameRenderSettings, TestRunner, Threshold};
 use wgpu::FrontFace;
 
@@ -16,6 +20,7 @@ pub async fn empty() -> anyhow::Result<()> {
     runner.set_camera_data(Camera {
         projection: rend3::types::CameraProjection::Raw(Mat4::IDENTITY),
         view: Mat4::IDENTITY,
+        layer_mask: ALL_LAYERS,
     });
 
     runner
@@ -59,13 +64,19 @@ pub async fn triangle() -> anyhow::Result<()> {
 
         let mesh_hdl = runner.add_mesh(mesh).unwrap();
         let material_hdl = runner.add_unlit_material(Vec4::new(0.25, 0.5, 0.75, 1.0));
-        let object =
-            Object { mesh_kind: ObjectMeshKind::Static(mesh_hdl), material: material_hdl, transform: Mat4::IDENTITY };
+        let object = Object {
+            mesh_kind: ObjectMeshKind::Static(mesh_hdl),
+            material: material_hdl,
+            transform: Mat4::IDENTITY,
+            flags: ObjectFlags::default(),
+            layer_mask: ALL_LAYERS,
+        };
         let _object_hdl = runner.add_object(object);
 
         runner.set_camera_data(Camera {
             projection: rend3::types::CameraProjection::Raw(Mat4::IDENTITY),
             view: Mat4::IDENTITY,
+            layer_mask: ALL_LAYERS,
         });
 
         let file_name = match visible {
@@ -84,6 +95,105 @@ pub async fn triangle() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[test_attr]
+pub async fn triangle_stencil() -> anyhow::Result<()> {
+    let iad = no_gpu_return!(rend3::create_iad(None, None, None, None).await)
+        .context("InstanceAdapterDevice creation failed")?;
+
+    let Ok(runner) = TestRunner::builder().iad(iad.clone()).handedness(Handedness::Left).stencil(true).build().await
+    else {
+        return Ok(());
+    };
+
+    // Clockwise triangle
+    let mesh = MeshBuilder::new(
+        vec![Vec3::new(0.5, -0.5, 0.0), Vec3::new(-0.5, -0.5, 0.0), Vec3::new(0.0, 0.5, 0.0)],
+        Handedness::Left,
+    )
+    .build()
+    .context("Failed to create mesh")?;
+
+    let mesh_hdl = runner.add_mesh(mesh).unwrap();
+    let material_hdl = runner.add_unlit_material(Vec4::new(0.25, 0.5, 0.75, 1.0));
+    let object = Object {
+        mesh_kind: ObjectMeshKind::Static(mesh_hdl),
+        material: material_hdl,
+        transform: Mat4::IDENTITY,
+        flags: ObjectFlags::default(),
+        layer_mask: ALL_LAYERS,
+    };
+    let _object_hdl = runner.add_object(object);
+
+    runner.set_camera_data(Camera {
+        projection: rend3::types::CameraProjection::Raw(Mat4::IDENTITY),
+        view: Mat4::IDENTITY,
+        layer_mask: ALL_LAYERS,
+    });
+
+    // The stencil doesn't change the image.
+    runner
+        .render_and_compare(FrameRenderSettings::new(), "tests/results/simple/triangle.png", Threshold::Mean(0.0))
+        .await?;
+    runner
+        .render_and_compare(
+            FrameRenderSettings::new().samples(SampleCount::Four),
+            "tests/results/msaa/four.png",
+            Threshold::Mean(0.0),
+        )
+        .await?;
+
+    Ok(())
+}
+
+#[test_attr]
+pub async fn depth_prepass() -> anyhow::Result<()> {
+    let iad = no_gpu_return!(rend3::create_iad(None, None, None, None).await)
+        .context("InstanceAdapterDevice creation failed")?;
+
+    let Ok(runner) = TestRunner::builder().iad(iad.clone()).handedness(Handedness::Left).build().await else {
+        return Ok(());
+    };
+
+    // Clockwise triangle
+    let mesh = MeshBuilder::new(
+        vec![Vec3::new(0.5, -0.5, 0.0), Vec3::new(-0.5, -0.5, 0.0), Vec3::new(0.0, 0.5, 0.0)],
+        Handedness::Left,
+    )
+    .build()
+    .context("Failed to create mesh")?;
+
+    let mesh_hdl = runner.add_mesh(mesh).unwrap();
+    let material_hdl = runner.add_unlit_material(Vec4::new(0.25, 0.5, 0.75, 1.0));
+    let object = Object {
+        mesh_kind: ObjectMeshKind::Static(mesh_hdl),
+        material: material_hdl,
+        transform: Mat4::IDENTITY,
+        flags: ObjectFlags::default(),
+        layer_mask: ALL_LAYERS,
+    };
+    let _object_hdl = runner.add_object(object);
+
+    runner.set_camera_data(Camera {
+        projection: rend3::types::CameraProjection::Raw(Mat4::IDENTITY),
+        view: Mat4::IDENTITY,
+        layer_mask: ALL_LAYERS,
+    });
+
+    // The first frame renders the residual triangles, the second the predicted ones.
+    for frame in 0..2 {
+        runner
+            .render_and_compare(
+                FrameRenderSettings::new().depth_prepass(true),
+                "tests/results/simple/triangle.png",
+                Threshold::Mean(0.0),
+            )
+            .await
+            .with_context(|| format!("Comparison failed on frame {frame}"))?;
+    }
+
+    Ok(())
+}
+
 #[test_attr]
 pub async fn coordinate_space() -> anyhow::Result<()> {
     let tests = [
@@ -120,8 +230,13 @@ pub async fn coordinate_space() -> anyhow::Result<()> {
 
         let mesh_hdl = runner.add_mesh(mesh).unwrap();
         let material_hdl = runner.add_unlit_material(color.extend(1.0));
-        let object =
-            Object { mesh_kind: ObjectMeshKind::Static(mesh_hdl), material: material_hdl, transform: Mat4::IDENTITY };
+        let object = Object {
+            mesh_kind: ObjectMeshKind::Static(mesh_hdl),
+            material: material_hdl,
+            transform: Mat4::IDENTITY,
+            flags: ObjectFlags::default(),
+            layer_mask: ALL_LAYERS,
+        };
         runner.add_object(object)
     });
 
@@ -129,6 +244,7 @@ pub async fn coordinate_space() -> anyhow::Result<()> {
         runner.set_camera_data(Camera {
             projection: rend3::types::CameraProjection::Raw(Mat4::IDENTITY),
             view: Mat4::look_at_lh(camera_vector, Vec3::ZERO, up_vector),
+            layer_mask: ALL_LAYERS,
         });
 
         let file_name = format!("tests/results/simple/coordinate-space-{name}.png");
@@ -140,3 +256,100 @@ pub async fn coordinate_space() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[test_attr]
+pub async fn extra_viewport() -> anyhow::Result<()> {
+    let iad = no_gpu_return!(rend3::create_iad(None, None, None, None).await)
+        .context("InstanceAdapterDevice creation failed")?;
+
+    let Ok(runner) = TestRunner::builder().iad(iad.clone()).handedness(Handedness::Left).build().await else {
+        return Ok(());
+    };
+
+    // The viewport camera looks away from the plane.
+    runner.set_camera_data(Camera {
+        projection: rend3::types::CameraProjection::Raw(Mat4::IDENTITY),
+        view: Mat4::from_translation(Vec3::new(10.0, 0.0, 0.0)),
+        layer_mask: ALL_LAYERS,
+    });
+
+    let material = runner.add_unlit_material(Vec4::ONE);
+    let _plane = runner.plane(
+        material,
+        Mat4::from_scale_rotation_translation(Vec3::new(-0.25, 0.25, 0.25), Quat::IDENTITY, Vec3::new(-0.5, 0.0, 0.0)),
+    );
+
+    let camera = Camera {
+        projection: rend3::types::CameraProjection::Raw(Mat4::IDENTITY),
+        view: Mat4::IDENTITY,
+        layer_mask: ALL_LAYERS,
+    };
+    runner.set_viewports(vec![Viewport { camera, rect: ViewportRect::from_size(UVec2::splat(64)), scissor: None }]);
+    runner
+        .render_and_compare(
+            FrameRenderSettings::new(),
+            "tests/results/object/duplicate-object-retain-left.png",
+            Threshold::Mean(0.0),
+        )
+        .await?;
+
+    // Viewports entirely outside of the output are skipped.
+    runner.set_viewports(vec![Viewport {
+        camera,
+        rect: ViewportRect::new(UVec2::new(64, 0), UVec2::splat(64)),
+        scissor: None,
+    }]);
+    runner
+        .render_and_compare(FrameRenderSettings::new(), "tests/results/simple/empty.png", Threshold::Mean(0.0))
+        .await?;
+
+    Ok(())
+}
+
+/// The scissor of a viewport hides the right plane, which the viewport covers.
+#[test_attr]
+pub async fn extra_viewport_scissor() -> anyhow::Result<()> {
+    let iad = no_gpu_return!(rend3::create_iad(None, None, None, None).await)
+        .context("InstanceAdapterDevice creation failed")?;
+
+    let Ok(runner) = TestRunner::builder().iad(iad.clone()).handedness(Handedness::Left).build().await else {
+        return Ok(());
+    };
+
+    // The viewport camera looks away from the planes.
+    runner.set_camera_data(Camera {
+        projection: rend3::types::CameraProjection::Raw(Mat4::IDENTITY),
+        view: Mat4::from_translation(Vec3::new(10.0, 0.0, 0.0)),
+        layer_mask: ALL_LAYERS,
+    });
+
+    let material = runner.add_unlit_material(Vec4::ONE);
+    let _left = runner.plane(
+        material.clone(),
+        Mat4::from_scale_rotation_translation(Vec3::new(-0.25, 0.25, 0.25), Quat::IDENTITY, Vec3::new(-0.5, 0.0, 0.0)),
+    );
+    let _right = runner.plane(
+        material,
+        Mat4::from_scale_rotation_translation(Vec3::new(-0.25, 0.25, 0.25), Quat::IDENTITY, Vec3::new(0.5, 0.0, 0.0)),
+    );
+
+    let camera = Camera {
+        projection: rend3::types::CameraProjection::Raw(Mat4::IDENTITY),
+        view: Mat4::IDENTITY,
+        layer_mask: ALL_LAYERS,
+    };
+    runner.set_viewports(vec![Viewport {
+        camera,
+        rect: ViewportRect::from_size(UVec2::splat(64)),
+        scissor: Some(ViewportRect::from_size(UVec2::new(32, 64))),
+    }]);
+    runner
+        .render_and_compare(
+            FrameRenderSettings::new(),
+            "tests/results/object/duplicate-object-retain-left.png",
+            Threshold::Mean(0.0),
+        )
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,37 @@
+use anyhow::Context;
+use glam::UVec3;
+use rend3::{
+    managers::TextureCreationError,
+    types::{MipmapCount, Texture3D, TextureFormat},
+};
+use rend3_test::{no_gpu_return, test_attr, TestRunner};
+
+/// 3D textures outside the device's size limits are rejected before they reach wgpu.
+#[test_attr]
+pub async fn texture_3d_size_limit() -> anyhow::Result<()> {
+    let iad = no_gpu_return!(rend3::create_iad(None, None, None, None).await)
+        .context("InstanceAdapterDevice creation failed")?;
+
+    let Ok(runner) = TestRunner::builder().iad(iad).build().await else {
+        return Ok(());
+    };
+
+    let texture = |size: UVec3| Texture3D {
+        label: None,
+        data: vec![0; (size.x * size.y * size.z.max(1)) as usize],
+        format: TextureFormat::R8Unorm,
+        size,
+        mip_count: MipmapCount::ONE,
+    };
+
+    let max = runner.limits.max_texture_dimension_3d;
+    assert!(runner.add_texture_3d(texture(UVec3::new(max, 1, 1))).is_ok());
+    for size in [UVec3::new(max + 1, 1, 1), UVec3::new(1, 1, max + 1), UVec3::new(4, 4, 0)] {
+        assert!(
+            matches!(runner.add_texture_3d(texture(size)), Err(TextureCreationError::TextureSizeInvalid { .. })),
+            "{size}"
+        );
+    }
+
+    Ok(())
+}
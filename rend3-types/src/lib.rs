@@ -20,7 +20,7 @@ use std::{
 use bytemuck::Zeroable;
 /// Reexport of the glam version rend3 is using.
 pub use glam;
-use glam::{Mat4, UVec2, Vec2, Vec3, Vec3A, Vec4};
+use glam::{Mat4, UVec2, UVec3, Vec2, Vec3, Vec3A, Vec4};
 use thiserror::Error;
 
 mod attribute;
@@ -33,20 +33,29 @@ pub use attribute::*;
 pub struct RawResourceHandle<T> {
     /// Underlying value of the handle.
     pub idx: usize,
+    /// How many times the index was freed before this handle was allocated.
+    /// Handles compare by index only, the generation is used to tell if the
+    /// resource behind a handle was removed, see `Renderer::is_alive`.
+    pub generation: u32,
     _phantom: PhantomData<T>,
 }
 
 impl<T> RawResourceHandle<T> {
     /// Creates a new handle with the given value
     pub const fn new(idx: usize) -> Self {
-        Self { idx, _phantom: PhantomData }
+        Self::with_generation(idx, 0)
+    }
+
+    /// Creates a new handle with the given value and generation.
+    pub const fn with_generation(idx: usize, generation: u32) -> Self {
+        Self { idx, generation, _phantom: PhantomData }
     }
 }
 
 // Need Debug/Copy/Clone impls that don't require T: Trait.
 impl<T> Debug for RawResourceHandle<T> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("RawResourceHandle").field("idx", &self.idx).finish()
+        f.debug_struct("RawResourceHandle").field("idx", &self.idx).field("generation", &self.generation).finish()
     }
 }
 
@@ -125,15 +134,14 @@ impl<T> Hash for ResourceHandle<T> {
 }
 
 impl<T> ResourceHandle<T> {
-    /// Create a new resource handle from an index.
+    /// Create a new resource handle from a raw handle.
     ///
     /// Part of rend3's internal interface, use `Renderer::add_*` instead.
-    pub fn new(destroy_fn: impl Fn(RawResourceHandle<T>) + WasmNotSend + WasmNotSync + 'static, idx: usize) -> Self {
-        Self {
-            refcount: Arc::new(destroy_fn),
-            raw: RawResourceHandle { idx, _phantom: PhantomData },
-            _phantom: PhantomData,
-        }
+    pub fn new(
+        destroy_fn: impl Fn(RawResourceHandle<T>) + WasmNotSend + WasmNotSync + 'static,
+        raw: RawResourceHandle<T>,
+    ) -> Self {
+        Self { refcount: Arc::new(destroy_fn), raw, _phantom: PhantomData }
     }
 
     /// Gets the equivalent raw handle for this owning handle.
@@ -158,6 +166,9 @@ pub struct Texture2DTag;
 /// Tag type for differentiating TextureCubes on the type level.
 #[doc(hidden)]
 pub struct TextureCubeTag;
+/// Tag type for differentiating Texture3Ds on the type level.
+#[doc(hidden)]
+pub struct Texture3DTag;
 /// Tag type for differentiating Materials on the type level.
 #[doc(hidden)]
 pub struct MaterialTag;
@@ -171,6 +182,8 @@ pub type MeshHandle = ResourceHandle<Mesh>;
 pub type Texture2DHandle = ResourceHandle<Texture2DTag>;
 /// Refcounted handle to a TextureCube
 pub type TextureCubeHandle = ResourceHandle<TextureCubeTag>;
+/// Refcounted handle to a Texture3D
+pub type Texture3DHandle = ResourceHandle<Texture3DTag>;
 /// Refcounted handle to a Material
 pub type MaterialHandle = ResourceHandle<MaterialTag>;
 /// Refcounted handle to an Object
@@ -179,6 +192,10 @@ pub type ObjectHandle = ResourceHandle<Object>;
 pub type DirectionalLightHandle = ResourceHandle<DirectionalLight>;
 /// Refcounted handle to a PointLight
 pub type PointLightHandle = ResourceHandle<PointLight>;
+/// Refcounted handle to a SpotLight
+pub type SpotLightHandle = ResourceHandle<SpotLight>;
+/// Refcounted handle to a Decal
+pub type DecalHandle = ResourceHandle<Decal>;
 /// Refcounted handle to a Skeleton
 pub type SkeletonHandle = ResourceHandle<Skeleton>;
 /// Refcounted handle to an instance of GraphData with the type erased
@@ -204,6 +221,8 @@ pub type RawMeshHandle = RawResourceHandle<Mesh>;
 pub type RawTexture2DHandle = RawResourceHandle<Texture2DTag>;
 /// Internal non-owning handle to a TextureCube
 pub type RawTextureCubeHandle = RawResourceHandle<TextureCubeTag>;
+/// Internal non-owning handle to a Texture3D
+pub type RawTexture3DHandle = RawResourceHandle<Texture3DTag>;
 /// Internal non-owning handle to a Material
 pub type RawMaterialHandle = RawResourceHandle<MaterialTag>;
 /// Internal non-owning handle to an Object
@@ -212,6 +231,10 @@ pub type RawObjectHandle = RawResourceHandle<Object>;
 pub type RawDirectionalLightHandle = RawResourceHandle<DirectionalLight>;
 /// Internal non-owning handle to a PointLight
 pub type RawPointLightHandle = RawResourceHandle<PointLight>;
+/// Internal non-owning handle to a SpotLight
+pub type RawSpotLightHandle = RawResourceHandle<SpotLight>;
+/// Internal non-owning handle to a Decal
+pub type RawDecalHandle = RawResourceHandle<Decal>;
 /// Internal non-owning handle to a Skeleton
 pub type RawSkeletonHandle = RawResourceHandle<Skeleton>;
 /// Internal non-owning handle to an instance of GraphData with the type erased
@@ -279,6 +302,8 @@ pub enum MeshValidationError {
         "Index at position {index} has the value {value} which is out of bounds for vertex buffers of {max} length"
     )]
     IndexOutOfBounds { index: usize, value: u32, max: u32 },
+    #[error("Morph target {target} has {actual} vertices but the position buffer has {expected}")]
+    MismatchedMorphTargetVertexCount { target: usize, expected: usize, actual: usize },
 }
 
 #[derive(Debug)]
@@ -339,11 +364,14 @@ pub struct MeshBuilder {
     vertex_count: usize,
 
     indices: Option<Vec<u32>>,
+    morph_targets: Vec<MorphTarget>,
     without_validation: bool,
 
     handedness: Handedness,
     flip_winding_order: bool,
     double_sided: bool,
+    without_tangents: bool,
+    recalculate_normals: bool,
 }
 impl MeshBuilder {
     /// Create a new [`MeshBuilder`] with a given set of positions.
@@ -426,6 +454,13 @@ impl MeshBuilder {
         self.with_attribute(&VERTEX_ATTRIBUTE_JOINT_WEIGHTS, joint_weights)
     }
 
+    /// Add morph targets to the given mesh. They are blended by the weights of
+    /// the [`Skeleton`] deforming the mesh.
+    pub fn with_morph_targets(mut self, morph_targets: Vec<MorphTarget>) -> Self {
+        self.morph_targets = morph_targets;
+        self
+    }
+
     /// Add indices to the given mesh.
     ///
     /// # Panic
@@ -452,12 +487,28 @@ impl MeshBuilder {
         self
     }
 
+    /// Replace the provided normals with smooth normals calculated from the
+    /// triangles, for meshes whose normals can't be trusted.
+    pub fn with_recalculated_normals(mut self) -> Self {
+        self.recalculate_normals = true;
+        self
+    }
+
+    /// Don't calculate tangents if they weren't provided. Only meshes rendered
+    /// with normal maps need tangents, so this saves the time and vertex
+    /// memory for other meshes.
+    pub fn without_tangent_generation(mut self) -> Self {
+        self.without_tangents = true;
+        self
+    }
+
     /// Doesn't run validation on the mesh.
     ///
     /// # Safety
     ///
     /// This asserts the following are true about the mesh:
     /// - All vertex arrays are the same length.
+    /// - All morph targets are the same length as the vertex arrays.
     /// - There is a non-zero count of vertices.
     /// - The count of vertices is less than [`MAX_VERTEX_COUNT`].
     /// - All indexes are in bounds for the given vertex arrays.
@@ -470,8 +521,11 @@ impl MeshBuilder {
 
     /// Build a mesh, adding whatever components weren't provided.
     ///
-    /// If normals weren't provided, they will be calculated. If mesh
-    /// is right handed, will be converted to left handed.
+    /// If normals weren't provided or [`Self::with_recalculated_normals`] was
+    /// called, they will be calculated, weighting each triangle by its area.
+    /// Tangents are calculated from the normals and first texture coordinates
+    /// unless provided or disabled with [`Self::without_tangent_generation`].
+    /// If mesh is right handed, will be converted to left handed.
     ///
     /// All others will be filled with defaults.
     pub fn build(self) -> Result<Mesh, MeshValidationError> {
@@ -479,6 +533,7 @@ impl MeshBuilder {
             attributes: self.vertex_attributes,
             vertex_count: self.vertex_count,
             indices: self.indices.unwrap_or_else(|| (0..self.vertex_count as u32).collect()),
+            morph_targets: self.morph_targets,
         };
 
         if self.double_sided {
@@ -498,12 +553,12 @@ impl MeshBuilder {
             mesh.flip_winding_order();
         }
 
-        if !has_normals {
+        if !has_normals || self.recalculate_normals {
             // SAFETY: We've validated this mesh or had its validity unsafely asserted.
-            unsafe { mesh.calculate_normals(self.handedness, true) };
+            unsafe { mesh.calculate_normals(self.handedness, !has_normals) };
         }
 
-        if !has_tangents {
+        if !has_tangents && !self.without_tangents {
             // SAFETY: We've validated this mesh or had its validity unsafely asserted.
             unsafe { mesh.calculate_tangents(true) };
         }
@@ -526,6 +581,10 @@ pub struct Mesh {
     pub vertex_count: usize,
 
     pub indices: Vec<u32>,
+
+    /// Offsets blended into the vertices of objects animated by a
+    /// [`Skeleton`], weighted by [`Skeleton::morph_weights`].
+    pub morph_targets: Vec<MorphTarget>,
 }
 
 impl Mesh {
@@ -549,6 +608,19 @@ impl Mesh {
             }
         }
 
+        for (target, morph_target) in self.morph_targets.iter().enumerate() {
+            let normals = morph_target.normal_offsets.as_ref().map_or(position_length, Vec::len);
+            for actual in [morph_target.position_offsets.len(), normals] {
+                if actual != position_length {
+                    return Err(MeshValidationError::MismatchedMorphTargetVertexCount {
+                        target,
+                        expected: position_length,
+                        actual,
+                    });
+                }
+            }
+        }
+
         if indices_length % 3 != 0 {
             return Err(MeshValidationError::IndexCountNotMultipleOfThree { count: indices_length });
         }
@@ -909,6 +981,9 @@ pub enum MipmapSource {
     /// mip levels.
     Uploaded,
     /// rend3 will generate the mipmaps for you. Upload only mip level 0.
+    ///
+    /// Compressed formats and formats which can't be rendered to can't have
+    /// their mipmaps generated. These textures only get mip level 0.
     Generated,
 }
 
@@ -923,6 +998,20 @@ pub struct Texture {
     pub mip_source: MipmapSource,
 }
 
+/// A volume of texels used as a data source for a 3D texture.
+///
+/// The data contains each depth slice of the first mip in turn, followed by
+/// the slices of the other mips. Mipmaps can't be generated for 3D textures, so
+/// all mips in `mip_count` must be provided.
+#[derive(Debug, Clone)]
+pub struct Texture3D {
+    pub label: Option<String>,
+    pub data: Vec<u8>,
+    pub format: TextureFormat,
+    pub size: UVec3,
+    pub mip_count: MipmapCount,
+}
+
 /// Describes a texture made from the mipmaps of another texture.
 #[derive(Debug, Clone)]
 pub struct TextureFromTexture {
@@ -1050,6 +1139,12 @@ pub trait Material: WasmNotSend + WasmNotSync + 'static {
     /// How objects with this material should be sorted.
     fn sorting(&self) -> Sorting;
 
+    /// If both faces of triangles with this material are rendered. Otherwise
+    /// back faces are culled.
+    fn double_sided(&self) -> bool {
+        false
+    }
+
     /// The array of textures that should be bound. Rend3 supports up to 32.
     fn to_textures(&self) -> Self::TextureArrayType;
 
@@ -1062,38 +1157,149 @@ pub trait Material: WasmNotSend + WasmNotSync + 'static {
 pub enum ObjectMeshKind {
     Animated(SkeletonHandle),
     Static(MeshHandle),
+    /// One of several meshes, chosen every frame by the distance to the camera.
+    Lod(LodGroup),
+}
+
+/// A mesh of a [`LodGroup`].
+#[derive(Clone, Debug)]
+pub struct LodLevel {
+    pub mesh: MeshHandle,
+    /// The level is used once the camera is at least this far from the center
+    /// of the object's bounding sphere.
+    pub min_distance: f32,
 }
 
+/// Meshes of decreasing detail used for the same object.
+///
+/// The level with the largest `min_distance` the camera is past is used for
+/// culling and drawing, including in shadow maps. All meshes must be usable
+/// with the object's material.
+#[derive(Clone, Debug)]
+pub struct LodGroup {
+    /// Levels sorted by increasing `min_distance`. The first level is used
+    /// when the camera is closer than any `min_distance`. Must not be empty.
+    pub levels: Vec<LodLevel>,
+    /// Fraction of a level's `min_distance` the camera must move past it before
+    /// the level changes, so objects at the boundary don't flicker between levels.
+    pub hysteresis: f32,
+}
+
+bitflags::bitflags! {
+    /// Flags which control how an object takes part in scene-wide effects.
+    ///
+    /// The default enables all of them.
+    #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+    pub struct ObjectFlags : u32 {
+        /// The object is affected by fog.
+        const RECEIVE_FOG =     0b01;
+        /// The object is affected by the ambient light.
+        const RECEIVE_AMBIENT = 0b10;
+    }
+}
+
+impl Default for ObjectFlags {
+    fn default() -> Self {
+        Self::RECEIVE_FOG | Self::RECEIVE_AMBIENT
+    }
+}
+
+/// Per-object adjustments applied on top of its material, letting objects
+/// share a material while being tinted or scrolling their textures, like team
+/// colors or animated water. Set with `Renderer::set_object_material_override`,
+/// which only updates the object's data, so it is cheap to animate.
+///
+/// Only the PBR material applies it.
+#[derive(Debug, Copy, Clone, PartialEq, encase::ShaderType)]
+pub struct MaterialOverride {
+    /// Multiplied with the base color, alpha included.
+    pub color: Vec4,
+    /// Added to the first set of texture coordinates after scaling them, before
+    /// the material's own uv transform.
+    pub uv_offset: Vec2,
+    /// Multiplied with the first set of texture coordinates.
+    pub uv_scale: Vec2,
+    /// Multiplied with the emissive color.
+    pub emissive_scale: f32,
+}
+
+impl MaterialOverride {
+    /// Leaves the material unchanged.
+    pub const IDENTITY: Self =
+        Self { color: Vec4::ONE, uv_offset: Vec2::ZERO, uv_scale: Vec2::ONE, emissive_scale: 1.0 };
+}
+
+impl Default for MaterialOverride {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+/// Layer mask with every layer set, see [`Object::layer_mask`].
+pub const ALL_LAYERS: u32 = u32::MAX;
+
 changeable_struct! {
     /// An object in the world that is composed of a [`Mesh`] and [`Material`].
     pub struct Object <- ObjectChange {
         pub mesh_kind: ObjectMeshKind,
         pub material: MaterialHandle,
         pub transform: Mat4,
+        pub flags: ObjectFlags,
+        /// Layers the object is in, one per bit. Cameras only render objects
+        /// sharing a layer with their [`Camera::layer_mask`]. Shadows are
+        /// rendered with all layers. Usually [`ALL_LAYERS`].
+        pub layer_mask: u32,
     }
 }
 
 /// Describes how the camera should look at the scene.
-#[derive(Debug, Default, Copy, Clone)]
+#[derive(Debug, Copy, Clone)]
 pub struct Camera {
     pub projection: CameraProjection,
-    /// View matrix
+    /// View matrix, transforming into view space as described by the
+    /// renderer's [`CoordinateSystem`].
     pub view: Mat4,
+    /// Layers of the objects the camera renders, see [`Object::layer_mask`].
+    pub layer_mask: u32,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self { projection: CameraProjection::default(), view: Mat4::default(), layer_mask: ALL_LAYERS }
+    }
 }
 
 /// Describes how the world should be projected into the camera.
+///
+/// Depth is reversed for precision: the near plane maps to a depth of 1 and
+/// the far plane to 0, depth is cleared to 0 and closer fragments have a
+/// greater depth.
 #[derive(Debug, Copy, Clone)]
 pub enum CameraProjection {
     Orthographic {
         /// Size assumes the location is at the center of the camera area.
         size: Vec3A,
     },
+    /// Orthographic projection looking along the view direction, for CAD,
+    /// 2D or isometric views. The width of the visible area follows the aspect
+    /// ratio, like [`Self::Perspective`].
+    OrthographicViewport {
+        /// Height of the visible area in world units.
+        height: f32,
+        /// Near plane distance.
+        near: f32,
+        /// Far plane distance.
+        far: f32,
+    },
     Perspective {
         /// Vertical field of view in degrees.
         vfov: f32,
         /// Near plane distance. All projection uses a infinite far plane.
         near: f32,
     },
+    /// Projection matrix used as is. It must reverse depth like the other
+    /// projections, for example
+    /// [`Mat4::perspective_infinite_reverse_lh`](glam::Mat4::perspective_infinite_reverse_lh).
     Raw(Mat4),
 }
 
@@ -1108,7 +1314,14 @@ changeable_struct! {
     pub struct DirectionalLight <- DirectionalLightChange {
         /// Color of the light.
         pub color: Vec3,
-        /// Resolution of the shadow map cascades (in pix)
+        /// Resolution of the shadow map cascades (in pix). Rounded down to a
+        /// power of two which fits in the device's
+        /// [`max_texture_dimension_2d`](wgt::Limits::max_texture_dimension_2d).
+        /// The shadow atlas is reallocated whenever this changes.
+        ///
+        /// All shadow maps share a single atlas. If they don't fit in it, the
+        /// maps of every light are shrunk by the same factor, so give the
+        /// important lights a larger resolution.
         pub resolution: u16,
         /// Constant multiplier for the light.
         pub intensity: f32,
@@ -1116,9 +1329,47 @@ changeable_struct! {
         pub direction: Vec3,
         /// Distance from the camera that shadows should be calculated.
         pub distance: f32,
+        /// Amount of shadow cascades the view frustum is split into, from 1 to
+        /// [`MAX_SHADOW_CASCADES`]. Each cascade gets its own shadow map with
+        /// the given resolution.
+        ///
+        /// With a single cascade, the shadow map covers a cube of `distance`
+        /// around the camera.
+        ///
+        /// Changing it within `1..=max_cascades` doesn't reallocate the shadow
+        /// atlas, so it can be adjusted every frame, for example lowered while
+        /// the camera looks at the sky.
+        pub cascades: u8,
+        /// Amount of shadow maps reserved in the atlas for the cascades, from
+        /// 1 to [`MAX_SHADOW_CASCADES`]. Raised to `cascades` if lower. Only
+        /// the first `cascades` maps are rendered.
+        pub max_cascades: u8,
+        /// How the view frustum is split into cascades. 0 splits it uniformly,
+        /// 1 splits it logarithmically, giving close cascades more detail.
+        pub cascade_split_lambda: f32,
+        /// Fraction of the depth range of each cascade which is blended with
+        /// the next cascade, hiding the seam between them.
+        pub cascade_blend: f32,
+        /// Constant offset added to the depth of fragments before it's compared
+        /// with the shadow map, in [0, 1] shadow map depth. Raise it to remove
+        /// shadow acne.
+        pub shadow_bias: f32,
+        /// Offset added on top of `shadow_bias`, scaled by the tangent of the
+        /// angle between the surface normal and the light. Removes acne on
+        /// surfaces at grazing angles to the light while leaving surfaces
+        /// facing it, where a large bias would detach shadows from their
+        /// casters, unchanged.
+        pub shadow_slope_bias: f32,
+        /// Distance (in world units) fragments are moved along their normal
+        /// before the shadow map is sampled. Removes acne on surfaces at
+        /// grazing angles to the light.
+        pub shadow_normal_offset: f32,
     }
 }
 
+/// Maximum amount of shadow cascades a [`DirectionalLight`] can have.
+pub const MAX_SHADOW_CASCADES: u8 = 4;
+
 changeable_struct! {
     /// Describes how point lights and their shadows should be processed.
     pub struct PointLight <- PointLightChange {
@@ -1133,6 +1384,89 @@ changeable_struct! {
 
         /// Constant multiplier for the light.
         pub intensity: f32,
+
+        /// Resolution of each face of the shadow cube (in pix). Zero disables
+        /// shadows for this light.
+        pub resolution: u16,
+
+        /// Constant offset added to the depth of fragments before it's
+        /// compared with the shadow map. See [`DirectionalLight::shadow_bias`].
+        pub shadow_bias: f32,
+
+        /// Offset added on top of `shadow_bias` on surfaces at grazing angles
+        /// to the light. See [`DirectionalLight::shadow_slope_bias`].
+        pub shadow_slope_bias: f32,
+
+        /// Distance (in world units) fragments are moved along their normal
+        /// before the shadow map is sampled.
+        pub shadow_normal_offset: f32,
+    }
+}
+
+changeable_struct! {
+    /// Describes how spot lights and their shadows should be processed.
+    pub struct SpotLight <- SpotLightChange {
+        /// The position of the light in the world.
+        pub position: Vec3,
+
+        /// The direction the light is pointing in.
+        pub direction: Vec3,
+
+        /// Angle from the direction (in degrees) at which the light starts to
+        /// fall off.
+        pub inner_angle: f32,
+
+        /// Angle from the direction (in degrees) at which the light is fully
+        /// attenuated.
+        pub outer_angle: f32,
+
+        /// Distance from the light at which it is fully attenuated.
+        pub range: f32,
+
+        /// The color of the light.
+        pub color: Vec3,
+
+        /// Constant multiplier for the light.
+        pub intensity: f32,
+
+        /// Resolution of the shadow map (in pix). Zero disables shadows for
+        /// this light.
+        pub resolution: u16,
+
+        /// Constant offset added to the depth of fragments before it's
+        /// compared with the shadow map. See [`DirectionalLight::shadow_bias`].
+        pub shadow_bias: f32,
+
+        /// Offset added on top of `shadow_bias` on surfaces at grazing angles
+        /// to the light. See [`DirectionalLight::shadow_slope_bias`].
+        pub shadow_slope_bias: f32,
+
+        /// Distance (in world units) fragments are moved along their normal
+        /// before the shadow map is sampled.
+        pub shadow_normal_offset: f32,
+    }
+}
+
+changeable_struct! {
+    /// Describes a texture projected onto the opaque geometry inside a box,
+    /// like a bullet hole, a footprint or graffiti.
+    ///
+    /// Decals are drawn after the opaque objects, reconstructing the position
+    /// of the surfaces from the depth buffer, and are not lit: their color is
+    /// blended over the shaded surface by its alpha. Use
+    /// `Transparency::Decal` meshes for decals which need lighting or normals.
+    pub struct Decal <- DecalChange {
+        /// Transform of the box the decal is projected in. The box is the unit
+        /// cube centered at the origin, projected along its local z axis. The
+        /// texture's u axis points along local +x, and its v axis along local
+        /// -y.
+        pub transform: Mat4,
+
+        /// Texture projected onto the surfaces in the box.
+        pub texture: Texture2DHandle,
+
+        /// Multiplier of the texture's color and alpha.
+        pub color: Vec4,
     }
 }
 
@@ -1202,7 +1536,62 @@ impl Default for Handedness {
     }
 }
 
-/// A Skeleton stores the necessary data to do vertex skinning for an [Object].
+/// Axis pointing up in view space, the space the view matrix of a [`Camera`]
+/// transforms into.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum UpAxis {
+    /// +Y up, +X right. The camera looks along +Z when left handed and -Z
+    /// when right handed.
+    #[default]
+    Y,
+    /// +Z up, +X right. The camera looks along -Y when left handed and +Y
+    /// when right handed. This is the +Y up space rotated so +Y becomes +Z.
+    Z,
+}
+
+/// Conventions of the transforms given to the renderer. Set on creation with
+/// `Renderer::new`.
+///
+/// The up axis only changes view space, so view matrices of engines with +Z
+/// up cameras can be used as is. World space directions, like the faces of
+/// the skybox cubemap, are unchanged.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct CoordinateSystem {
+    pub handedness: Handedness,
+    pub up: UpAxis,
+}
+
+impl CoordinateSystem {
+    /// Transform from view space of this coordinate system into +Y up view
+    /// space with the same handedness.
+    pub fn view_to_y_up(self) -> Mat4 {
+        match self.up {
+            UpAxis::Y => Mat4::IDENTITY,
+            // Rotates +Z onto +Y, and +Y onto -Z.
+            UpAxis::Z => Mat4::from_cols(Vec4::X, Vec4::NEG_Z, Vec4::Y, Vec4::W),
+        }
+    }
+}
+
+impl From<Handedness> for CoordinateSystem {
+    fn from(handedness: Handedness) -> Self {
+        Self { handedness, up: UpAxis::Y }
+    }
+}
+
+/// A set of offsets added to the vertices of a [`Mesh`], such as a facial
+/// expression. Every offset is scaled by the weight of the morph target in the
+/// deforming [`Skeleton`].
+#[derive(Debug, Clone, Default)]
+pub struct MorphTarget {
+    /// Offset of every vertex position.
+    pub position_offsets: Vec<Vec3>,
+    /// Offset of every vertex normal, if the morph target changes normals.
+    pub normal_offsets: Option<Vec<Vec3>>,
+}
+
+/// A Skeleton stores the necessary data to do vertex skinning and morph
+/// target blending for an [Object].
 #[derive(Debug, Clone)]
 pub struct Skeleton {
     /// Stores one transformation matrix for each joint. These are the
@@ -1213,6 +1602,8 @@ pub struct Skeleton {
     /// The `Skeleton::form_joint_transforms` constructor can be used to create
     /// a Skeleton with the joint transform matrices instead.
     pub joint_matrices: Vec<Mat4>,
+    /// Weight of each of the mesh's [`MorphTarget`]s. Missing weights are 0.
+    pub morph_weights: Vec<f32>,
     pub mesh: MeshHandle,
 }
 
@@ -1225,7 +1616,7 @@ impl Skeleton {
         inverse_bind_transforms: &[Mat4],
     ) -> Skeleton {
         let joint_matrices = Self::compute_joint_matrices(joint_global_transforms, inverse_bind_transforms);
-        Skeleton { joint_matrices, mesh }
+        Skeleton { joint_matrices, morph_weights: Vec::new(), mesh }
     }
 
     /// Given a list of joint global positions and another one with inverse bind
@@ -1264,3 +1655,61 @@ macro_rules! trait_supertrait_alias {
         impl<T: $($supertraits)*> $name for T {}
     };
 }
+
+#[cfg(test)]
+mod tests {
+    use glam::{Vec2, Vec3};
+
+    use super::{Handedness, Mesh, MeshBuilder, VERTEX_ATTRIBUTE_NORMAL, VERTEX_ATTRIBUTE_TANGENT};
+
+    fn normals(mesh: &Mesh) -> &[Vec3] {
+        let index = mesh.find_attribute_index(&VERTEX_ATTRIBUTE_NORMAL).unwrap();
+        mesh.attributes[index].typed_data(&VERTEX_ATTRIBUTE_NORMAL).unwrap()
+    }
+
+    fn assert_normals(mesh: &Mesh, expected: &[Vec3]) {
+        for (normal, expected) in normals(mesh).iter().zip(expected) {
+            assert!(normal.abs_diff_eq(*expected, 1e-6), "{normal} != {expected}");
+        }
+    }
+
+    #[test]
+    fn recalculated_normals() {
+        // A quad folded along the edge from vertex 0 to 1. The triangle in the
+        // xy plane has twice the area of the one in the xz plane.
+        let positions = vec![Vec3::ZERO, Vec3::X, Vec3::new(0.0, 2.0, 0.0), Vec3::Z];
+        let mesh = MeshBuilder::new(positions, Handedness::Left)
+            .with_vertex_normals(vec![Vec3::X; 4])
+            .with_indices(vec![0, 1, 2, 0, 3, 1])
+            .with_recalculated_normals()
+            .build()
+            .unwrap();
+
+        let shared = Vec3::new(0.0, 1.0, 2.0).normalize();
+        assert_normals(&mesh, &[shared, shared, Vec3::Z, Vec3::Y]);
+    }
+
+    #[test]
+    fn non_indexed() {
+        let positions = vec![Vec3::ZERO, Vec3::X, Vec3::Y, Vec3::ZERO, Vec3::Z, Vec3::X];
+        let mesh = MeshBuilder::new(positions, Handedness::Left).build().unwrap();
+
+        assert_eq!(mesh.indices, [0, 1, 2, 3, 4, 5]);
+        assert_normals(&mesh, &[Vec3::Z, Vec3::Z, Vec3::Z, Vec3::Y, Vec3::Y, Vec3::Y]);
+    }
+
+    #[test]
+    fn tangent_generation() {
+        let builder = || {
+            MeshBuilder::new(vec![Vec3::ZERO, Vec3::X, Vec3::Y], Handedness::Left)
+                .with_vertex_texture_coordinates_0(vec![Vec2::ZERO, Vec2::X, Vec2::Y])
+        };
+
+        let mesh = builder().build().unwrap();
+        assert!(mesh.find_attribute_index(&VERTEX_ATTRIBUTE_TANGENT).is_some());
+
+        let mesh = builder().without_tangent_generation().build().unwrap();
+        assert!(mesh.find_attribute_index(&VERTEX_ATTRIBUTE_TANGENT).is_none());
+        assert!(mesh.find_attribute_index(&VERTEX_ATTRIBUTE_NORMAL).is_some());
+    }
+}
@@ -0,0 +1,56 @@
+const PI: f32 = 3.14159265358979323846;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+    @location(1) @interpolate(flat) face: u32,
+};
+
+// The cube face being rendered is passed through the instance index.
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32, @builtin(instance_index) face: u32) -> VertexOutput {
+    var out: VertexOutput;
+    let x = i32(vertex_index) / 2;
+    let y = i32(vertex_index) & 1;
+    let tc = vec2<f32>(
+        f32(x) * 2.0,
+        f32(y) * 2.0
+    );
+    out.position = vec4<f32>(
+        tc.x * 2.0 - 1.0,
+        1.0 - tc.y * 2.0,
+        0.0, 1.0
+    );
+    out.tex_coords = tc;
+    out.face = face;
+    return out;
+}
+
+@group(0) @binding(0)
+var r_equirect: texture_2d<f32>;
+@group(1) @binding(0)
+var r_sampler: sampler;
+
+fn face_direction(face: u32, uv: vec2<f32>) -> vec3<f32> {
+    let u = uv.x * 2.0 - 1.0;
+    let v = uv.y * 2.0 - 1.0;
+    switch face {
+        case 0u: { return vec3<f32>(1.0, -v, -u); }
+        case 1u: { return vec3<f32>(-1.0, -v, u); }
+        case 2u: { return vec3<f32>(u, 1.0, v); }
+        case 3u: { return vec3<f32>(u, -1.0, -v); }
+        case 4u: { return vec3<f32>(u, -v, 1.0); }
+        default: { return vec3<f32>(-u, -v, -1.0); }
+    }
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let dir = normalize(face_direction(in.face, in.tex_coords));
+    let equirect_uv = vec2<f32>(
+        atan2(dir.z, dir.x) / (2.0 * PI) + 0.5,
+        acos(clamp(dir.y, -1.0, 1.0)) / PI,
+    );
+    // Explicit lod as the derivatives are discontinuous across the seam.
+    return vec4<f32>(textureSampleLevel(r_equirect, r_sampler, equirect_uv, 0.0).rgb, 1.0);
+}
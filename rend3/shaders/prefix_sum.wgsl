@@ -0,0 +1,62 @@
+// Parallel exclusive prefix sum (scan) of u32s.
+//
+// `scan_blocks` scans every block of 256 values on its own, writing the sum
+// of each block to `block_sums`. Those sums are then scanned themselves and
+// `add_block_offsets` adds them back to every value of their block. The
+// length of the scan is the length `output` is bound with.
+
+@group(0) @binding(0)
+var<storage, read> input: array<u32>;
+@group(0) @binding(1)
+var<storage, read_write> output: array<u32>;
+@group(0) @binding(2)
+var<storage, read_write> block_sums: array<u32>;
+
+var<workgroup> scratch: array<u32, 256>;
+
+@compute @workgroup_size(256)
+fn scan_blocks(
+    @builtin(global_invocation_id) gid: vec3<u32>,
+    @builtin(local_invocation_index) lid: u32,
+    @builtin(workgroup_id) wid: vec3<u32>,
+) {
+    let count = arrayLength(&output);
+
+    var value = 0u;
+    if (gid.x < count) {
+        value = input[gid.x];
+    }
+    scratch[lid] = value;
+    workgroupBarrier();
+
+    // Inclusive Hillis-Steele scan of the block.
+    for (var offset = 1u; offset < 256u; offset *= 2u) {
+        var sum = scratch[lid];
+        if (lid >= offset) {
+            sum += scratch[lid - offset];
+        }
+        workgroupBarrier();
+        scratch[lid] = sum;
+        workgroupBarrier();
+    }
+
+    if (gid.x < count) {
+        output[gid.x] = scratch[lid] - value;
+    }
+    if (lid == 255u) {
+        block_sums[wid.x] = scratch[255];
+    }
+}
+
+// `input` is bound to the scanned block sums.
+@compute @workgroup_size(256)
+fn add_block_offsets(
+    @builtin(global_invocation_id) gid: vec3<u32>,
+    @builtin(workgroup_id) wid: vec3<u32>,
+) {
+    if (gid.x >= arrayLength(&output)) {
+        return;
+    }
+
+    output[gid.x] += input[wid.x];
+}
@@ -3,15 +3,18 @@ use std::{
     cell::{RefCell, UnsafeCell},
     collections::hash_map::Entry,
     marker::PhantomData,
+    mem,
     ops::Range,
-    sync::Arc,
+    sync::{atomic::Ordering, Arc},
 };
 
 use glam::UVec2;
+use parking_lot::Mutex;
+use web_time::Instant;
 use wgpu::{
     Buffer, CommandBuffer, CommandEncoder, CommandEncoderDescriptor, LoadOp, Operations, RenderPass,
     RenderPassColorAttachment, RenderPassDepthStencilAttachment, RenderPassDescriptor, StoreOp, SurfaceTexture,
-    Texture, TextureView, TextureViewDescriptor,
+    Texture, TextureAspect, TextureView, TextureViewDescriptor,
 };
 
 use super::ViewportRect;
@@ -21,19 +24,35 @@ use crate::{
         RenderGraphEncoderOrPass, RenderGraphEncoderOrPassInner, RenderGraphNode, RenderGraphNodeBuilder,
         RenderPassTargets, RenderTargetDescriptor, RenderTargetHandle, RpassTemporaryPool, TextureRegion,
     },
-    managers::{ShadowDesc, TextureManagerEvaluateOutput},
-    util::typedefs::{FastHashMap, FastHashSet, RendererStatistics, SsoString},
+    managers::{ShadowDesc, TextureManagerEvaluateOutput, ViewportDesc},
+    util::{
+        stats::FrameStatistics,
+        typedefs::{FastHashMap, FastHashSet, RendererStatistics, SsoString},
+    },
     Renderer,
 };
 
 /// Result of evaluating all instructions.
 pub struct InstructionEvaluationOutput {
+    /// Index of the frame, counting the calls to
+    /// [`Renderer::evaluate_instructions`](crate::Renderer::evaluate_instructions).
+    pub frame: u64,
     pub cmd_bufs: Vec<CommandBuffer>,
     pub d2_texture: TextureManagerEvaluateOutput,
     pub d2c_texture: TextureManagerEvaluateOutput,
+    pub d3_texture: TextureManagerEvaluateOutput,
     pub shadow_target_size: UVec2,
     pub shadows: Vec<ShadowDesc>,
+    /// Cameras rendering into rectangles of the output, in addition to the
+    /// viewport camera.
+    pub viewports: Vec<ViewportDesc>,
     pub mesh_buffer: Arc<Buffer>,
+    /// Statistics of the frame being rendered. Nodes add their counts and
+    /// timings to this.
+    pub stats: Mutex<FrameStatistics>,
+    /// Senders of the futures returned by [`Renderer::uploads_done`], notified
+    /// once this frame's work completes on the gpu.
+    pub(crate) upload_fences: Vec<flume::Sender<()>>,
 }
 
 pub trait AsTextureReference {
@@ -112,6 +131,8 @@ impl<'node> RenderGraph<'node> {
                 mip_start: 0,
                 mip_end: desc.to_core().mip_count(),
                 viewport: ViewportRect { offset: UVec2::ZERO, size: desc.resolution },
+                scissor: None,
+                aspect: TextureAspect::All,
             }),
         };
         self.targets.push(desc);
@@ -135,6 +156,8 @@ impl<'node> RenderGraph<'node> {
                 mip_start: mips.start,
                 mip_end: mips.end,
                 viewport,
+                scissor: None,
+                aspect: TextureAspect::All,
             }),
         }
     }
@@ -166,6 +189,13 @@ impl<'node> RenderGraph<'node> {
     ) -> Option<RendererStatistics> {
         profiling::scope!("RenderGraph::execute");
 
+        // Nothing can be submitted to a lost device. The recorded work is dropped with the graph.
+        if renderer.device_lost().is_some() {
+            return None;
+        }
+
+        let record_start = Instant::now();
+
         // Because data handles have dependencies, we flatten the inputs and outputs ahead of time to simplify things.
         // We do it in place to save a bunch of allocations.
         for node in &mut self.nodes {
@@ -330,6 +360,7 @@ impl<'node> RenderGraph<'node> {
                             array_layer_count: Some(region.layer_end - region.layer_start),
                             base_mip_level: region.mip_start as u32,
                             mip_level_count: Some((region.mip_end - region.mip_start) as u32),
+                            aspect: region.aspect,
                             ..TextureViewDescriptor::default()
                         });
                         vacant.insert(view);
@@ -343,6 +374,7 @@ impl<'node> RenderGraph<'node> {
                                 array_layer_count: Some(region.layer_end - region.layer_start),
                                 base_mip_level: region.mip_start as u32,
                                 mip_level_count: Some((region.mip_end - region.mip_start) as u32),
+                                aspect: region.aspect,
                                 ..TextureViewDescriptor::default()
                             });
                         vacant.insert(view);
@@ -430,14 +462,16 @@ impl<'node> RenderGraph<'node> {
                     Some(ref mut rpass) => {
                         let rpass_desc = node.rpass.unwrap();
 
-                        let viewport = rpass_desc
-                            .targets
-                            .first()
-                            .map_or_else(
-                                || rpass_desc.depth_stencil.as_ref().unwrap().target.to_region(),
-                                |t| t.color.to_region(),
-                            )
-                            .viewport;
+                        let region = rpass_desc.targets.first().map_or_else(
+                            || rpass_desc.depth_stencil.as_ref().unwrap().target.to_region(),
+                            |t| t.color.to_region(),
+                        );
+                        let viewport = region.viewport;
+                        // Always set, so the scissor of a previous node in the same renderpass doesn't leak.
+                        let scissor = region.scissor.map_or(viewport, |scissor| {
+                            ViewportRect::new(viewport.offset.saturating_add(scissor.offset), scissor.size)
+                                .intersection(viewport)
+                        });
 
                         rpass.set_viewport(
                             viewport.offset.x as f32,
@@ -447,6 +481,7 @@ impl<'node> RenderGraph<'node> {
                             0.0,
                             1.0,
                         );
+                        rpass.set_scissor_rect(scissor.offset.x, scissor.offset.y, scissor.size.x, scissor.size.y);
 
                         RenderGraphEncoderOrPassInner::RenderPass(rpass)
                     }
@@ -507,14 +542,38 @@ impl<'node> RenderGraph<'node> {
         data_core.profiler.try_lock().unwrap().resolve_queries(&mut resolve_encoder);
         eval_output.cmd_bufs.push(resolve_encoder.finish());
 
+        let record_time = record_start.elapsed();
+        let submit_start = Instant::now();
+
+        renderer.frames_in_flight.fetch_add(1, Ordering::AcqRel);
         renderer.queue.submit(eval_output.cmd_bufs.drain(..));
 
+        let stats = eval_output.stats.get_mut();
+        stats.record_time = record_time;
+        stats.submit_time = submit_start.elapsed();
+        renderer.stats_accumulator.lock().accumulate_frame(stats);
+        *renderer.last_frame_stats.lock() = stats.clone();
+
+        let frames_in_flight = Arc::clone(&renderer.frames_in_flight);
+        let upload_fences = mem::take(&mut eval_output.upload_fences);
+        renderer.queue.on_submitted_work_done(move || {
+            frames_in_flight.fetch_sub(1, Ordering::AcqRel);
+            for fence in upload_fences {
+                // The receiver is gone if the future was dropped.
+                let _ = fence.send(());
+            }
+        });
+
         data_core.profiler.try_lock().unwrap().end_frame().unwrap();
 
         // This variable seems superfluous, but solves borrow checker issues with the borrow of data_core.
         let timers =
             data_core.profiler.try_lock().unwrap().process_finished_frame(renderer.queue.get_timestamp_period());
 
+        if let Some(ref timers) = timers {
+            renderer.stats_accumulator.lock().accumulate(timers);
+        }
+
         timers
     }
 
@@ -47,7 +47,7 @@ use std::ops::Range;
 
 use glam::{UVec2, Vec4};
 use rend3_types::{SampleCount, TextureFormat, TextureUsages};
-use wgpu::{Extent3d, TextureDimension, TextureView};
+use wgpu::{Extent3d, TextureAspect, TextureDimension, TextureView};
 
 use crate::util::typedefs::SsoString;
 
@@ -144,6 +144,8 @@ pub(super) struct TextureRegion {
     mip_start: u8,
     mip_end: u8,
     viewport: ViewportRect,
+    scissor: Option<ViewportRect>,
+    aspect: TextureAspect,
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
@@ -179,6 +181,14 @@ impl ViewportRect {
     pub fn from_size(size: UVec2) -> Self {
         Self::new(UVec2::ZERO, size)
     }
+
+    /// Pixels inside of both rectangles. Empty if they don't overlap, with
+    /// the offset clamped to the end of the overlap.
+    pub fn intersection(self, other: Self) -> Self {
+        let end = self.offset.saturating_add(self.size).min(other.offset.saturating_add(other.size));
+        let offset = self.offset.max(other.offset).min(end);
+        Self::new(offset, end - offset)
+    }
 }
 
 /// Handle to a graph-stored render target.
@@ -238,6 +248,33 @@ impl RenderTargetHandle {
         }
         self
     }
+
+    /// Restricts rendering to a rectangle of the viewport, relative to its
+    /// offset. Pixels of the scissor outside of the viewport are never
+    /// rendered to. Like the viewport, this only applies when the target is
+    /// the first attachment of a renderpass.
+    pub fn set_scissor(mut self, scissor: ViewportRect) -> Self {
+        match &mut self.resource {
+            GraphSubResource::ImportedTexture(region) | GraphSubResource::Texture(region) => {
+                region.scissor = Some(scissor);
+            }
+            _ => unreachable!(),
+        }
+        self
+    }
+
+    /// Sets the aspect of the texture view nodes get for this target. Depth
+    /// targets with a stencil must be bound as [`TextureAspect::DepthOnly`]
+    /// to sample their depth, but must be attached with all aspects.
+    pub fn set_aspect(mut self, aspect: TextureAspect) -> Self {
+        match &mut self.resource {
+            GraphSubResource::ImportedTexture(region) | GraphSubResource::Texture(region) => {
+                region.aspect = aspect;
+            }
+            _ => unreachable!(),
+        }
+        self
+    }
 }
 
 /// Targets that make up a renderpass.
@@ -3,14 +3,17 @@ use std::{mem, panic::Location};
 use glam::Mat4;
 use parking_lot::Mutex;
 use rend3_types::{
-    trait_supertrait_alias, ObjectChange, PointLight, PointLightChange, RawDirectionalLightHandle,
-    RawGraphDataHandleUntyped, RawMaterialHandle, RawMeshHandle, RawPointLightHandle, RawSkeletonHandle,
-    RawTexture2DHandle, RawTextureCubeHandle, TextureFromTexture, WasmNotSend, WasmNotSync,
+    trait_supertrait_alias, Decal, DecalChange, MaterialOverride, ObjectChange, PointLight, PointLightChange,
+    RawDecalHandle, RawDirectionalLightHandle, RawGraphDataHandleUntyped, RawMaterialHandle, RawMeshHandle,
+    RawPointLightHandle, RawSkeletonHandle, RawSpotLightHandle, RawTexture2DHandle, RawTexture3DHandle,
+    RawTextureCubeHandle, SpotLight, SpotLightChange, TextureFromTexture, WasmNotSend, WasmNotSync,
 };
 use wgpu::{CommandBuffer, Device};
 
 use crate::{
-    managers::{GraphStorage, InternalSkeleton, InternalTexture, MaterialManager, TextureManager},
+    managers::{
+        GraphStorage, InternalSkeleton, InternalTexture, MaterialManager, TextureManager, TextureStream, Viewport,
+    },
     types::{Camera, DirectionalLight, DirectionalLightChange, Object, RawObjectHandle},
     RendererProfile,
 };
@@ -37,6 +40,10 @@ pub enum InstructionKind {
         internal_texture: InternalTexture,
         cmd_buf: Option<CommandBuffer>,
     },
+    AddTexture2DStreaming {
+        handle: RawTexture2DHandle,
+        stream: TextureStream,
+    },
     AddTexture2DFromTexture {
         handle: RawTexture2DHandle,
         texture: TextureFromTexture,
@@ -46,6 +53,15 @@ pub enum InstructionKind {
         internal_texture: InternalTexture,
         cmd_buf: Option<CommandBuffer>,
     },
+    AddTexture3D {
+        handle: RawTexture3DHandle,
+        internal_texture: InternalTexture,
+    },
+    AddTextureCubeFromEquirect {
+        handle: RawTextureCubeHandle,
+        src: RawTexture2DHandle,
+        face_size: u32,
+    },
     AddMaterial {
         handle: RawMaterialHandle,
         fill_invoke: Box<dyn AddMaterialFillInvoke>,
@@ -62,6 +78,14 @@ pub enum InstructionKind {
         handle: RawPointLightHandle,
         light: PointLight,
     },
+    AddSpotLight {
+        handle: RawSpotLightHandle,
+        light: SpotLight,
+    },
+    AddDecal {
+        handle: RawDecalHandle,
+        decal: Decal,
+    },
     AddGraphData {
         add_invoke: Box<dyn AddGraphDataAddInvoke>,
     },
@@ -77,6 +101,14 @@ pub enum InstructionKind {
         handle: RawPointLightHandle,
         change: PointLightChange,
     },
+    ChangeSpotLight {
+        handle: RawSpotLightHandle,
+        change: SpotLightChange,
+    },
+    ChangeDecal {
+        handle: RawDecalHandle,
+        change: DecalChange,
+    },
     DeleteMesh {
         handle: RawMeshHandle,
     },
@@ -89,6 +121,9 @@ pub enum InstructionKind {
     DeleteTextureCube {
         handle: RawTextureCubeHandle,
     },
+    DeleteTexture3D {
+        handle: RawTexture3DHandle,
+    },
     DeleteMaterial {
         handle: RawMaterialHandle,
     },
@@ -101,6 +136,12 @@ pub enum InstructionKind {
     DeletePointLight {
         handle: RawPointLightHandle,
     },
+    DeleteSpotLight {
+        handle: RawSpotLightHandle,
+    },
+    DeleteDecal {
+        handle: RawDecalHandle,
+    },
     DeleteGraphData {
         handle: RawGraphDataHandleUntyped,
     },
@@ -108,16 +149,38 @@ pub enum InstructionKind {
         handle: RawObjectHandle,
         transform: Mat4,
     },
+    SetObjectVisibility {
+        handle: RawObjectHandle,
+        visible: bool,
+    },
+    SetObjectMaterialOverride {
+        handle: RawObjectHandle,
+        material_override: MaterialOverride,
+    },
+    SetObjectParent {
+        child: RawObjectHandle,
+        parent: Option<RawObjectHandle>,
+    },
     SetSkeletonJointDeltas {
         handle: RawSkeletonHandle,
         joint_matrices: Vec<Mat4>,
     },
+    SetSkeletonMorphWeights {
+        handle: RawSkeletonHandle,
+        morph_weights: Vec<f32>,
+    },
     SetAspectRatio {
         ratio: f32,
     },
     SetCameraData {
         data: Camera,
     },
+    SetViewports {
+        viewports: Vec<Viewport>,
+    },
+    UploadFence {
+        sender: flume::Sender<()>,
+    },
     DuplicateObject {
         src_handle: RawObjectHandle,
         dst_handle: RawObjectHandle,
@@ -144,6 +207,15 @@ impl InstructionStreamPair {
     pub fn push(&self, kind: InstructionKind, location: Location<'static>) {
         self.producer.lock().push(Instruction { kind, location })
     }
+
+    pub fn push_batch(&self, instructions: Vec<Instruction>) {
+        let mut produce = self.producer.lock();
+        if produce.is_empty() {
+            *produce = instructions;
+        } else {
+            produce.extend(instructions);
+        }
+    }
 }
 
 /// Allows RawResourceHandle<T> to be turned into a delete instruction.
@@ -175,6 +247,12 @@ impl DeletableRawResourceHandle for RawTextureCubeHandle {
     }
 }
 
+impl DeletableRawResourceHandle for RawTexture3DHandle {
+    fn into_delete_instruction_kind(self) -> InstructionKind {
+        InstructionKind::DeleteTexture3D { handle: self }
+    }
+}
+
 impl DeletableRawResourceHandle for RawMaterialHandle {
     fn into_delete_instruction_kind(self) -> InstructionKind {
         InstructionKind::DeleteMaterial { handle: self }
@@ -199,6 +277,18 @@ impl DeletableRawResourceHandle for RawPointLightHandle {
     }
 }
 
+impl DeletableRawResourceHandle for RawSpotLightHandle {
+    fn into_delete_instruction_kind(self) -> InstructionKind {
+        InstructionKind::DeleteSpotLight { handle: self }
+    }
+}
+
+impl DeletableRawResourceHandle for RawDecalHandle {
+    fn into_delete_instruction_kind(self) -> InstructionKind {
+        InstructionKind::DeleteDecal { handle: self }
+    }
+}
+
 impl DeletableRawResourceHandle for RawGraphDataHandleUntyped {
     fn into_delete_instruction_kind(self) -> InstructionKind {
         InstructionKind::DeleteGraphData { handle: self }
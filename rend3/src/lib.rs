@@ -98,17 +98,21 @@ mod renderer;
 /// Managers for various type of resources.
 pub mod managers {
     mod camera;
+    mod decal;
     mod directional;
     mod graph_storage;
     mod handle_alloc;
+    mod hierarchy;
     mod material;
     mod mesh;
     mod object;
     mod point;
     mod skeleton;
+    mod spot;
     mod texture;
 
     pub use camera::*;
+    pub use decal::*;
     pub use directional::*;
     pub use graph_storage::*;
     pub(crate) use handle_alloc::*;
@@ -117,6 +121,7 @@ pub mod managers {
     pub use object::*;
     pub use point::*;
     pub use skeleton::*;
+    pub use spot::*;
     pub use texture::*;
 }
 
@@ -128,8 +133,11 @@ pub mod types {
 }
 /// Utilities and isolated bits of functionality that need a home.
 pub mod util {
+    pub mod bind_group_cache;
     pub mod bind_merge;
     pub mod buffer;
+    pub mod buffer_pool;
+    pub mod equirect;
     pub mod error_scope;
     pub mod frustum;
     pub mod freelist {
@@ -143,7 +151,11 @@ pub mod util {
     pub mod math;
     pub mod mipmap;
     pub mod output;
+    pub mod prefix_sum;
+    pub mod readback;
+    pub mod render_target;
     pub mod scatter_copy;
+    pub mod stats;
     pub mod sync;
     pub mod typedefs;
     pub mod upload;
@@ -151,13 +163,20 @@ pub mod util {
 
 pub mod graph;
 mod instruction;
+/// Loaders for image formats which need special handling.
+pub mod loaders {
+    #[cfg(feature = "hdr")]
+    pub mod hdr;
+}
 mod profile;
 mod setup;
 mod shader;
 mod surface;
 
 pub use profile::*;
-pub use renderer::{error::*, Renderer, RendererDataCore};
+pub use renderer::{
+    device_lost::DeviceLost, error::*, AllocatedResource, InstructionBatch, Renderer, RendererDataCore,
+};
 pub use setup::*;
 pub use shader::*;
 pub use surface::*;
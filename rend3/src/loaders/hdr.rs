@@ -0,0 +1,319 @@
+//! Loader for Radiance (.hdr) images.
+//!
+//! Decodes RGBE encoded images, both flat and new-style run length encoded,
+//! into floating point texture data. The resulting texture is suitable for use
+//! as an equirectangular environment map.
+
+use glam::UVec2;
+use thiserror::Error;
+
+use crate::types::{MipmapCount, MipmapSource, Texture, TextureFormat};
+
+const SIGNATURES: [&[u8]; 2] = [b"#?RADIANCE", b"#?RGBE"];
+
+/// Error returned when a Radiance image fails to decode.
+#[derive(Debug, Error)]
+pub enum HdrLoadError {
+    #[error("File is missing the Radiance signature")]
+    MissingSignature,
+    #[error("Unexpected end of file while reading the header")]
+    UnterminatedHeader,
+    #[error("Unsupported pixel format {0:?}, only 32-bit_rle_rgbe is supported")]
+    UnsupportedFormat(String),
+    #[error("Malformed or unsupported resolution string {0:?}, only \"-Y <height> +X <width>\" is supported")]
+    MalformedResolution(String),
+    #[error("Unexpected end of file while reading scanline {scanline}")]
+    UnexpectedEof { scanline: u32 },
+    #[error("Run length encoded data in scanline {scanline} overruns the scanline")]
+    CorruptScanline { scanline: u32 },
+}
+
+/// Floating point format to decode the image into.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum HdrTextureFormat {
+    /// Decode into [`TextureFormat::Rgba16Float`]. Filterable everywhere and
+    /// half the size, but loses precision.
+    #[default]
+    Rgba16Float,
+    /// Decode into [`TextureFormat::Rgba32Float`]. Requires
+    /// `FLOAT32_FILTERABLE` to be filtered.
+    Rgba32Float,
+}
+
+impl HdrTextureFormat {
+    pub fn to_texture_format(self) -> TextureFormat {
+        match self {
+            Self::Rgba16Float => TextureFormat::Rgba16Float,
+            Self::Rgba32Float => TextureFormat::Rgba32Float,
+        }
+    }
+}
+
+/// Decodes a Radiance .hdr file into a single-mip texture with the given format.
+///
+/// Alpha is always set to 1.0.
+pub fn load_hdr(bytes: &[u8], format: HdrTextureFormat) -> Result<Texture, HdrLoadError> {
+    profiling::scope!("load_hdr");
+
+    let mut reader = Reader { bytes, offset: 0 };
+
+    let size = read_header(&mut reader)?;
+
+    let bytes_per_pixel = match format {
+        HdrTextureFormat::Rgba16Float => 8,
+        HdrTextureFormat::Rgba32Float => 16,
+    };
+    let mut data = Vec::with_capacity(size.x as usize * size.y as usize * bytes_per_pixel);
+
+    let mut scanline = vec![[0_u8; 4]; size.x as usize];
+    for y in 0..size.y {
+        read_scanline(&mut reader, &mut scanline, y)?;
+
+        for &rgbe in &scanline {
+            let rgb = rgbe_to_rgb(rgbe);
+            match format {
+                HdrTextureFormat::Rgba16Float => {
+                    for channel in [rgb[0], rgb[1], rgb[2], 1.0] {
+                        data.extend_from_slice(&f32_to_f16(channel).to_le_bytes());
+                    }
+                }
+                HdrTextureFormat::Rgba32Float => {
+                    for channel in [rgb[0], rgb[1], rgb[2], 1.0] {
+                        data.extend_from_slice(&channel.to_le_bytes());
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(Texture {
+        label: None,
+        data,
+        format: format.to_texture_format(),
+        size,
+        mip_count: MipmapCount::ONE,
+        mip_source: MipmapSource::Uploaded,
+    })
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn read_line(&mut self) -> Option<&'a [u8]> {
+        let remaining = self.bytes.get(self.offset..)?;
+        let end = remaining.iter().position(|&b| b == b'\n')?;
+        self.offset += end + 1;
+        Some(&remaining[..end])
+    }
+
+    fn read_bytes<const N: usize>(&mut self) -> Option<[u8; N]> {
+        let bytes = self.bytes.get(self.offset..self.offset + N)?;
+        self.offset += N;
+        Some(bytes.try_into().unwrap())
+    }
+
+    fn read_byte(&mut self) -> Option<u8> {
+        self.read_bytes::<1>().map(|[b]| b)
+    }
+}
+
+fn read_header(reader: &mut Reader<'_>) -> Result<UVec2, HdrLoadError> {
+    let signature = reader.read_line().ok_or(HdrLoadError::MissingSignature)?;
+    if !SIGNATURES.contains(&signature) {
+        return Err(HdrLoadError::MissingSignature);
+    }
+
+    // Header variables are terminated by an empty line.
+    loop {
+        let line = reader.read_line().ok_or(HdrLoadError::UnterminatedHeader)?;
+        if line.is_empty() {
+            break;
+        }
+        if let Some(value) = line.strip_prefix(b"FORMAT=") {
+            if value != b"32-bit_rle_rgbe" {
+                return Err(HdrLoadError::UnsupportedFormat(String::from_utf8_lossy(value).into_owned()));
+            }
+        }
+    }
+
+    let resolution = reader.read_line().ok_or(HdrLoadError::UnterminatedHeader)?;
+    let resolution_str = String::from_utf8_lossy(resolution);
+    let malformed = || HdrLoadError::MalformedResolution(resolution_str.clone().into_owned());
+
+    let mut parts = resolution_str.split_ascii_whitespace();
+    let (Some("-Y"), Some(height), Some("+X"), Some(width), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return Err(malformed());
+    };
+    let height: u32 = height.parse().map_err(|_| malformed())?;
+    let width: u32 = width.parse().map_err(|_| malformed())?;
+    if width == 0 || height == 0 {
+        return Err(malformed());
+    }
+
+    Ok(UVec2::new(width, height))
+}
+
+fn read_scanline(reader: &mut Reader<'_>, output: &mut [[u8; 4]], scanline: u32) -> Result<(), HdrLoadError> {
+    let eof = || HdrLoadError::UnexpectedEof { scanline };
+
+    let width = output.len();
+    let first = reader.read_bytes::<4>().ok_or_else(eof)?;
+
+    // New style RLE scanlines start with 2, 2, and the big endian width. Anything else is a flat scanline.
+    let is_rle = (8..0x8000).contains(&width)
+        && first[0] == 2
+        && first[1] == 2
+        && usize::from(u16::from_be_bytes([first[2], first[3]])) == width;
+    if !is_rle {
+        output[0] = first;
+        for pixel in &mut output[1..] {
+            *pixel = reader.read_bytes::<4>().ok_or_else(eof)?;
+        }
+        return Ok(());
+    }
+
+    // Each channel is run length encoded separately.
+    for channel in 0..4 {
+        let mut x = 0;
+        while x < width {
+            let count = reader.read_byte().ok_or_else(eof)?;
+            if count > 128 {
+                let count = usize::from(count - 128);
+                let value = reader.read_byte().ok_or_else(eof)?;
+                let run = output.get_mut(x..x + count).ok_or(HdrLoadError::CorruptScanline { scanline })?;
+                for pixel in run {
+                    pixel[channel] = value;
+                }
+                x += count;
+            } else {
+                let count = usize::from(count);
+                if count == 0 {
+                    return Err(HdrLoadError::CorruptScanline { scanline });
+                }
+                let run = output.get_mut(x..x + count).ok_or(HdrLoadError::CorruptScanline { scanline })?;
+                for pixel in run {
+                    pixel[channel] = reader.read_byte().ok_or_else(eof)?;
+                }
+                x += count;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn rgbe_to_rgb([r, g, b, e]: [u8; 4]) -> [f32; 3] {
+    if e == 0 {
+        return [0.0; 3];
+    }
+    // Equivalent to ldexp(1.0, e - (128 + 8)).
+    let scale = 2.0_f32.powi(i32::from(e) - 136);
+    [(f32::from(r) + 0.5) * scale, (f32::from(g) + 0.5) * scale, (f32::from(b) + 0.5) * scale]
+}
+
+/// Converts a non-negative f32 to the bits of an IEEE half, rounding to nearest
+/// even and saturating to infinity.
+fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xFF) as i32;
+    let mantissa = bits & 0x007F_FFFF;
+
+    if exponent == 0xFF {
+        // Inf or NaN
+        let nan_bit = if mantissa != 0 { 0x0200 } else { 0 };
+        return sign | 0x7C00 | nan_bit;
+    }
+
+    let half_exponent = exponent - 127 + 15;
+    if half_exponent >= 0x1F {
+        return sign | 0x7C00;
+    }
+
+    if half_exponent <= 0 {
+        // Subnormal or zero
+        if half_exponent < -10 {
+            return sign;
+        }
+        let mantissa = mantissa | 0x0080_0000;
+        let shift = (14 - half_exponent) as u32;
+        let half_mantissa = mantissa >> shift;
+        let round_bit = 1 << (shift - 1);
+        let round = (mantissa & round_bit) != 0 && (mantissa & (3 * round_bit - 1)) != 0;
+        return sign | (half_mantissa as u16 + round as u16);
+    }
+
+    let half = sign | ((half_exponent as u16) << 10) | (mantissa >> 13) as u16;
+    let round = (mantissa & 0x1000) != 0 && (mantissa & 0x2FFF) != 0;
+    // A carry out of the mantissa correctly bumps the exponent.
+    half + round as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{f32_to_f16, load_hdr, HdrLoadError, HdrTextureFormat};
+
+    fn header(width: u32, height: u32) -> Vec<u8> {
+        format!("#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {height} +X {width}\n").into_bytes()
+    }
+
+    #[test]
+    fn flat_scanlines() {
+        let mut file = header(2, 1);
+        // 1.0 is encoded as mantissa 128 with exponent 129.
+        file.extend_from_slice(&[128, 128, 128, 129, 0, 0, 0, 0]);
+
+        let texture = load_hdr(&file, HdrTextureFormat::Rgba32Float).unwrap();
+        assert_eq!(texture.size.to_array(), [2, 1]);
+
+        let floats: Vec<f32> =
+            texture.data.chunks_exact(4).map(|c| f32::from_le_bytes(c.try_into().unwrap())).collect();
+        assert!((floats[0] - 1.0).abs() < 0.01);
+        assert_eq!(floats[3], 1.0);
+        assert_eq!(&floats[4..7], &[0.0; 3]);
+    }
+
+    #[test]
+    fn rle_scanlines() {
+        let mut file = header(8, 1);
+        file.extend_from_slice(&[2, 2, 0, 8]);
+        // Each channel is a single run of 8.
+        for value in [128, 64, 32, 129] {
+            file.extend_from_slice(&[128 + 8, value]);
+        }
+
+        let texture = load_hdr(&file, HdrTextureFormat::Rgba16Float).unwrap();
+        assert_eq!(texture.data.len(), 8 * 8);
+        assert_eq!(&texture.data[0..2], &f32_to_f16((128.0 + 0.5) / 128.0).to_le_bytes());
+    }
+
+    #[test]
+    fn malformed() {
+        assert!(matches!(load_hdr(b"P6\n", HdrTextureFormat::Rgba32Float), Err(HdrLoadError::MissingSignature)));
+
+        let file = b"#?RADIANCE\n\n+Y 1 +X 1\n";
+        assert!(matches!(load_hdr(file, HdrTextureFormat::Rgba32Float), Err(HdrLoadError::MalformedResolution(_))));
+
+        let mut file = header(2, 2);
+        file.extend_from_slice(&[0; 12]);
+        assert!(matches!(
+            load_hdr(&file, HdrTextureFormat::Rgba32Float),
+            Err(HdrLoadError::UnexpectedEof { scanline: 1 })
+        ));
+    }
+
+    #[test]
+    fn half_conversion() {
+        assert_eq!(f32_to_f16(0.0), 0x0000);
+        assert_eq!(f32_to_f16(1.0), 0x3C00);
+        assert_eq!(f32_to_f16(0.5), 0x3800);
+        assert_eq!(f32_to_f16(65504.0), 0x7BFF);
+        assert_eq!(f32_to_f16(1.0e6), 0x7C00);
+        assert_eq!(f32_to_f16(2.0_f32.powi(-24)), 0x0001);
+    }
+}
@@ -1,7 +1,8 @@
-use glam::{Mat4, Vec3};
-use rend3_types::Handedness;
+use glam::{Mat4, UVec2, Vec3};
+use rend3_types::{CoordinateSystem, Handedness};
 
 use crate::{
+    graph::ViewportRect,
     types::{Camera, CameraProjection},
     util::frustum::Frustum,
 };
@@ -9,7 +10,9 @@ use crate::{
 /// Manages the camera's location and projection settings.
 #[derive(Debug, Clone)]
 pub struct CameraState {
-    handedness: Handedness,
+    coordinate_system: CoordinateSystem,
+    /// View matrix of `data`, converted into +Y up view space.
+    view: Mat4,
     orig_view: Mat4,
     proj: Mat4,
     inv_view: Mat4,
@@ -20,16 +23,27 @@ pub struct CameraState {
 impl CameraState {
     /// Builds a new camera, using the given aspect ratio. If no aspect ratio is
     /// given it is assumed that no aspect ratio scaling should be done.
-    pub fn new(data: Camera, handedness: Handedness, aspect_ratio: Option<f32>) -> Self {
+    pub fn new(data: Camera, coordinate_system: impl Into<CoordinateSystem>, aspect_ratio: Option<f32>) -> Self {
         profiling::scope!("CameraState::new");
 
+        let coordinate_system = coordinate_system.into();
         let aspect_ratio = aspect_ratio.unwrap_or(1.0);
-        let proj = compute_projection_matrix(data, handedness, aspect_ratio);
-        let orig_view = compute_origin_matrix(data);
-
-        let frustum = Frustum::from_matrix(proj * data.view);
-
-        Self { handedness, orig_view, proj, inv_view: data.view.inverse(), world_frustum: frustum, data, aspect_ratio }
+        let proj = compute_projection_matrix(data, coordinate_system.handedness, aspect_ratio);
+        let view = coordinate_system.view_to_y_up() * data.view;
+        let orig_view = compute_origin_matrix(view);
+
+        let frustum = Frustum::from_matrix(proj * view);
+
+        Self {
+            coordinate_system,
+            view,
+            orig_view,
+            proj,
+            inv_view: view.inverse(),
+            world_frustum: frustum,
+            data,
+            aspect_ratio,
+        }
     }
 
     /// Sets the camera data, rebuilding the using the given aspect ratio. If no
@@ -44,10 +58,11 @@ impl CameraState {
     }
 
     pub fn set_aspect_data(&mut self, data: Camera, aspect_ratio: f32) {
-        self.proj = compute_projection_matrix(data, self.handedness, aspect_ratio);
-        self.orig_view = compute_origin_matrix(data);
-        self.inv_view = data.view.inverse();
-        self.world_frustum = Frustum::from_matrix(self.proj * data.view);
+        self.proj = compute_projection_matrix(data, self.coordinate_system.handedness, aspect_ratio);
+        self.view = self.coordinate_system.view_to_y_up() * data.view;
+        self.orig_view = compute_origin_matrix(self.view);
+        self.inv_view = self.view.inverse();
+        self.world_frustum = Frustum::from_matrix(self.proj * self.view);
         self.data = data;
         self.aspect_ratio = aspect_ratio;
     }
@@ -57,15 +72,21 @@ impl CameraState {
     }
 
     pub fn handedness(&self) -> Handedness {
-        self.handedness
+        self.coordinate_system.handedness
+    }
+
+    pub fn coordinate_system(&self) -> CoordinateSystem {
+        self.coordinate_system
     }
 
+    /// View matrix of the camera, transforming into +Y up view space whatever
+    /// the up axis of the coordinate system is.
     pub fn view(&self) -> Mat4 {
-        self.data.view
+        self.view
     }
 
     pub fn view_proj(&self) -> Mat4 {
-        self.proj * self.data.view
+        self.proj * self.view
     }
 
     pub fn origin_view_proj(&self) -> Mat4 {
@@ -80,11 +101,46 @@ impl CameraState {
         self.world_frustum
     }
 
+    pub fn aspect_ratio(&self) -> f32 {
+        self.aspect_ratio
+    }
+
     pub fn location(&self) -> Vec3 {
         self.inv_view.w_axis.truncate()
     }
 }
 
+/// Camera rendering into a rectangle of the output, in addition to the
+/// viewport camera. Set with [`Renderer::set_viewports`](crate::Renderer::set_viewports).
+#[derive(Debug, Copy, Clone)]
+pub struct Viewport {
+    pub camera: Camera,
+    /// Pixels of the output the camera renders to. The aspect ratio of the
+    /// camera follows the size of the rectangle.
+    pub rect: ViewportRect,
+    /// Pixels of `rect` the camera's image is written to, relative to its
+    /// offset. `None` writes all of them.
+    pub scissor: Option<ViewportRect>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ViewportDesc {
+    pub rect: ViewportRect,
+    pub scissor: Option<ViewportRect>,
+    pub camera: CameraState,
+}
+
+impl ViewportDesc {
+    pub fn new(viewport: Viewport, coordinate_system: CoordinateSystem) -> Self {
+        let size = viewport.rect.size.max(UVec2::ONE).as_vec2();
+        Self {
+            rect: viewport.rect,
+            scissor: viewport.scissor,
+            camera: CameraState::new(viewport.camera, coordinate_system, Some(size.x / size.y)),
+        }
+    }
+}
+
 fn compute_projection_matrix(data: Camera, handedness: Handedness, aspect_ratio: f32) -> Mat4 {
     match data.projection {
         CameraProjection::Orthographic { size } => {
@@ -95,6 +151,16 @@ fn compute_projection_matrix(data: Camera, handedness: Handedness, aspect_ratio:
                 Mat4::orthographic_rh(-half.x, half.x, -half.y, half.y, half.z, -half.z)
             }
         }
+        CameraProjection::OrthographicViewport { height, near, far } => {
+            let half_height = height * 0.5;
+            let half_width = half_height * aspect_ratio;
+            // Depth is reversed like with perspective, so the far plane is at 0.
+            if handedness == Handedness::Left {
+                Mat4::orthographic_lh(-half_width, half_width, -half_height, half_height, far, near)
+            } else {
+                Mat4::orthographic_rh(-half_width, half_width, -half_height, half_height, far, near)
+            }
+        }
         CameraProjection::Perspective { vfov, near } => {
             if handedness == Handedness::Left {
                 Mat4::perspective_infinite_reverse_lh(vfov.to_radians(), aspect_ratio, near)
@@ -106,9 +172,83 @@ fn compute_projection_matrix(data: Camera, handedness: Handedness, aspect_ratio:
     }
 }
 
-fn compute_origin_matrix(data: Camera) -> Mat4 {
-    let mut view = data.view;
-
+fn compute_origin_matrix(mut view: Mat4) -> Mat4 {
     view.w_axis = glam::Vec4::W;
     view
 }
+
+#[cfg(test)]
+mod tests {
+    use glam::{Mat4, Vec3, Vec4};
+    use rend3_types::{Camera, CameraProjection, CoordinateSystem, Handedness, UpAxis, ALL_LAYERS};
+
+    use super::CameraState;
+    use crate::util::frustum::BoundingSphere;
+
+    fn contains(camera: &CameraState, center: Vec3) -> bool {
+        camera.world_frustum().contains_sphere(BoundingSphere { center, radius: 0.1 })
+    }
+
+    #[test]
+    fn orthographic_viewport() {
+        for handedness in [Handedness::Left, Handedness::Right] {
+            let forward = if handedness == Handedness::Left { Vec3::Z } else { Vec3::NEG_Z };
+            let camera = CameraState::new(
+                Camera {
+                    projection: CameraProjection::OrthographicViewport { height: 2.0, near: 1.0, far: 10.0 },
+                    view: Mat4::IDENTITY,
+                    layer_mask: ALL_LAYERS,
+                },
+                handedness,
+                Some(2.0),
+            );
+
+            // Reversed depth, the near plane is at 1 and the far plane at 0.
+            let near = camera.proj() * (forward * 1.0).extend(1.0);
+            let far = camera.proj() * (forward * 10.0).extend(1.0);
+            assert!((near.z / near.w - 1.0).abs() < 1e-5);
+            assert!((far.z / far.w).abs() < 1e-5);
+            assert_eq!(
+                camera.proj() * Vec4::new(2.0, 1.0, 0.0, 1.0) - camera.proj() * Vec4::W,
+                Vec4::new(1.0, 1.0, 0.0, 0.0)
+            );
+
+            // The width follows the aspect ratio, with no perspective narrowing it. Culling has no far
+            // plane, so objects past it are clipped instead.
+            assert!(contains(&camera, forward * 5.0 + Vec3::new(1.9, 0.9, 0.0)));
+            assert!(contains(&camera, forward * 1000.0 + Vec3::new(1.9, 0.9, 0.0)));
+            assert!(!contains(&camera, forward * 5.0 + Vec3::new(2.5, 0.0, 0.0)));
+            assert!(!contains(&camera, forward * 5.0 + Vec3::new(0.0, -1.5, 0.0)));
+            // Behind the near plane.
+            assert!(!contains(&camera, forward * 0.5));
+        }
+    }
+
+    #[test]
+    fn z_up() {
+        for handedness in [Handedness::Left, Handedness::Right] {
+            let forward = if handedness == Handedness::Left { Vec3::NEG_Y } else { Vec3::Y };
+            let camera = CameraState::new(
+                Camera {
+                    projection: CameraProjection::Perspective { vfov: 90.0, near: 0.1 },
+                    view: Mat4::from_translation(Vec3::new(0.0, 0.0, -1.0)),
+                    layer_mask: ALL_LAYERS,
+                },
+                CoordinateSystem { handedness, up: UpAxis::Z },
+                None,
+            );
+
+            // Up is +Z, in front of the camera is along the forward axis.
+            let above = camera.view_proj() * (forward * 5.0 + Vec3::new(0.0, 0.0, 3.0)).extend(1.0);
+            assert!(above.y / above.w > 0.1);
+            let right = camera.view_proj() * (forward * 5.0 + Vec3::new(2.0, 0.0, 1.0)).extend(1.0);
+            assert!(right.x / right.w > 0.1);
+            assert!((camera.location() - Vec3::Z).length() < 1e-5);
+
+            // Culling uses the same convention.
+            assert!(contains(&camera, forward * 5.0 + Vec3::Z));
+            assert!(!contains(&camera, -forward * 5.0 + Vec3::Z));
+            assert!(!contains(&camera, Vec3::new(0.0, 0.0, 10.0)));
+        }
+    }
+}
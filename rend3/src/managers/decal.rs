@@ -0,0 +1,105 @@
+use encase::{ArrayLength, ShaderType};
+use glam::{Mat4, Vec4};
+use rend3_types::{Decal, DecalChange, RawDecalHandle, RawTexture2DHandle};
+use wgpu::{BufferUsages, Device, ShaderStages};
+
+use crate::{
+    util::{
+        bind_merge::{BindGroupBuilder, BindGroupLayoutBuilder},
+        buffer::WrappedPotBuffer,
+    },
+    Renderer,
+};
+
+#[derive(Debug, Clone, ShaderType)]
+struct ShaderDecalBuffer {
+    count: ArrayLength,
+    #[size(runtime)]
+    array: Vec<ShaderDecal>,
+}
+
+#[derive(Debug, Copy, Clone, ShaderType)]
+struct ShaderDecal {
+    /// Transform from world space into the unit cube of the decal.
+    pub world_to_decal: Mat4,
+    pub decal_to_world: Mat4,
+    pub color: Vec4,
+}
+
+/// Manages decals projected onto the opaque geometry.
+pub struct DecalManager {
+    data: Vec<Option<Decal>>,
+    data_buffer: WrappedPotBuffer<ShaderDecalBuffer>,
+    /// Texture of every decal in the buffer, in the same order.
+    textures: Vec<RawTexture2DHandle>,
+}
+
+impl DecalManager {
+    pub fn new(device: &Device) -> Self {
+        Self {
+            data: Vec::new(),
+            data_buffer: WrappedPotBuffer::new(device, BufferUsages::STORAGE, "decal buffer"),
+            textures: Vec::new(),
+        }
+    }
+
+    pub fn add(&mut self, handle: RawDecalHandle, decal: Decal) {
+        if handle.idx >= self.data.len() {
+            self.data.resize(handle.idx + 1, None);
+        }
+
+        self.data[handle.idx] = Some(decal);
+    }
+
+    pub fn update(&mut self, handle: RawDecalHandle, change: DecalChange) {
+        self.data[handle.idx].as_mut().unwrap().update_from_changes(change);
+    }
+
+    pub fn remove(&mut self, handle: RawDecalHandle) {
+        self.data[handle.idx].take().unwrap();
+    }
+
+    pub fn evaluate(&mut self, renderer: &Renderer) {
+        profiling::scope!("DecalManager::evaluate");
+
+        let decals: Vec<_> = self.data.iter().flatten().collect();
+
+        self.textures = decals.iter().map(|decal| decal.texture.get_raw()).collect();
+
+        let buffer = ShaderDecalBuffer {
+            count: ArrayLength,
+            array: decals
+                .iter()
+                .map(|decal| ShaderDecal {
+                    world_to_decal: decal.transform.inverse(),
+                    decal_to_world: decal.transform,
+                    color: decal.color,
+                })
+                .collect(),
+        };
+
+        self.data_buffer.write_to_buffer(&renderer.device, &renderer.queue, &buffer);
+    }
+
+    /// Texture of every decal in the buffer, in the same order, as of the
+    /// last call to [`Self::evaluate`].
+    pub fn textures(&self) -> &[RawTexture2DHandle] {
+        &self.textures
+    }
+
+    pub fn add_to_bgl(bglb: &mut BindGroupLayoutBuilder) {
+        bglb.append(
+            ShaderStages::VERTEX_FRAGMENT,
+            wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: true },
+                has_dynamic_offset: false,
+                min_binding_size: Some(ShaderDecalBuffer::min_size()),
+            },
+            None,
+        );
+    }
+
+    pub fn add_to_bg<'a>(&'a self, bgb: &mut BindGroupBuilder<'a>) {
+        bgb.append_buffer(&self.data_buffer);
+    }
+}
@@ -1,13 +1,15 @@
 use encase::{ArrayLength, ShaderType};
-use glam::{Mat4, UVec2, Vec2, Vec3};
-use rend3_types::{DirectionalLightChange, RawDirectionalLightHandle};
+use glam::{Mat4, UVec2, Vec2, Vec3, Vec4};
+use rend3_types::{
+    DirectionalLightChange, RawDirectionalLightHandle, RawPointLightHandle, RawSpotLightHandle, MAX_SHADOW_CASCADES,
+};
 use wgpu::{
     BindingType, BufferBindingType, BufferUsages, Device, Extent3d, ShaderStages, TextureDescriptor, TextureDimension,
     TextureUsages, TextureView, TextureViewDescriptor,
 };
 
 use crate::{
-    managers::CameraState,
+    managers::{CameraState, PointLightManager, SpotLightManager},
     types::DirectionalLight,
     util::{
         bind_merge::{BindGroupBuilder, BindGroupLayoutBuilder},
@@ -35,21 +37,73 @@ struct ShaderDirectionalLightBuffer {
     array: Vec<ShaderDirectionalLight>,
 }
 
+const MAX_CASCADES: usize = MAX_SHADOW_CASCADES as usize;
+
 #[derive(Debug, Copy, Clone, ShaderType)]
 struct ShaderDirectionalLight {
-    /// View/Projection of directional light. Shadow rendering uses viewports
+    /// View/Projection of each cascade. Shadow rendering uses viewports
     /// so this always outputs [-1, 1] no matter where in the atlast the shadow is.
-    pub view_proj: Mat4,
+    pub view_proj: [Mat4; MAX_CASCADES],
+    /// [0, 1] offset (xy) and size (zw) of each cascade in the atlas.
+    pub atlas_rects: [Vec4; MAX_CASCADES],
+    /// View space depth each cascade ends at.
+    pub cascade_ends: Vec4,
     /// Color/intensity of the light
     pub color: Vec3,
+    /// Amount of cascades in use.
+    pub cascade_count: u32,
     /// Direction of the light
     pub direction: Vec3,
+    /// Fraction of each cascade which is blended with the next one.
+    pub cascade_blend: f32,
     /// 1 / resolution of whole shadow map
     pub inv_resolution: Vec2,
-    /// [0, 1] offset of the shadow map in the atlas.
-    pub atlas_offset: Vec2,
-    /// [0, 1] size of the shadow map in the atlas.
-    pub atlas_size: Vec2,
+    /// Offset added to the depth of fragments before the shadow comparison.
+    pub shadow_bias: f32,
+    /// World space distance fragments are moved along their normal before sampling the shadow map.
+    pub shadow_normal_offset: f32,
+    /// Offset added to the bias, scaled by the tangent of the angle between the surface and the light.
+    pub shadow_slope_bias: f32,
+}
+
+/// The light a shadow map in the shadow atlas belongs to.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ShadowSource {
+    /// One cascade of a directional light, with the closest cascade first.
+    Directional {
+        handle: RawDirectionalLightHandle,
+        cascade: u8,
+    },
+    /// One face of a point light's shadow cube. Faces are in the order +X, -X, +Y, -Y, +Z, -Z.
+    PointFace {
+        handle: RawPointLightHandle,
+        face: u8,
+    },
+    Spot(RawSpotLightHandle),
+}
+
+/// Area covered by one cascade of a directional light's shadow, as of the last
+/// time instructions were evaluated.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShadowCascadeInfo {
+    pub light: RawDirectionalLightHandle,
+    /// Index of the cascade, with the closest cascade first.
+    pub cascade: u8,
+    /// View space depth the cascade starts at.
+    pub near: f32,
+    /// View space depth the cascade ends at. A light with a single cascade isn't
+    /// split along the frustum, so this is [`f32::MAX`].
+    pub far: f32,
+    /// World space corners of the slice of the view frustum the cascade covers,
+    /// with the four near corners first. `None` for single cascades and
+    /// non-perspective cameras.
+    pub frustum_corners: Option<[Vec3; 8]>,
+    /// World space center of the area the shadow map covers.
+    pub center: Vec3,
+    /// Width and height of the shadow map's coverage, in world units.
+    pub size: f32,
+    /// Size of a single shadow texel, in world units.
+    pub texel_size: f32,
 }
 
 #[derive(Debug, Clone)]
@@ -65,6 +119,8 @@ pub struct DirectionalLightManager {
 
     texture_size: UVec2,
     texture_view: TextureView,
+
+    cascade_info: Vec<ShadowCascadeInfo>,
 }
 impl DirectionalLightManager {
     pub fn new(device: &Device) -> Self {
@@ -78,6 +134,7 @@ impl DirectionalLightManager {
             data_buffer: WrappedPotBuffer::new(device, BufferUsages::STORAGE, "shadow data buffer"),
             texture_size,
             texture_view,
+            cascade_info: Vec::new(),
         }
     }
 
@@ -96,14 +153,60 @@ impl DirectionalLightManager {
         self.data[handle.idx].take().unwrap();
     }
 
-    pub fn evaluate(&mut self, renderer: &Renderer, user_camera: &CameraState) -> (UVec2, Vec<ShadowDesc>) {
+    /// Coverage of every cascade of every light, as of the last evaluation.
+    pub fn cascade_info(&self) -> &[ShadowCascadeInfo] {
+        &self.cascade_info
+    }
+
+    /// Allocates the shadow atlas for all directional lights and all shadowed
+    /// point and spot lights, then uploads the directional light data.
+    pub fn evaluate(
+        &mut self,
+        renderer: &Renderer,
+        user_camera: &CameraState,
+        point_lights: &PointLightManager,
+        spot_lights: &SpotLightManager,
+    ) -> (UVec2, Vec<ShadowDesc>) {
         profiling::scope!("DirectionalLightManager::evaluate");
 
+        self.cascade_info = self
+            .data
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, light)| Some((RawDirectionalLightHandle::new(idx), light.as_ref()?)))
+            .flat_map(|(handle, light)| {
+                let count = shadow_camera::cascade_count(light, user_camera);
+                let splits = shadow_camera::light_cascade_splits(light, user_camera);
+                (0..count).map(move |cascade| {
+                    let (near, far) = (splits[cascade as usize], splits[cascade as usize + 1]);
+                    let (center, size) = shadow_camera::cascade_bounds(light, user_camera, cascade);
+                    ShadowCascadeInfo {
+                        light: handle,
+                        cascade,
+                        near,
+                        far,
+                        frustum_corners: (count > 1)
+                            .then(|| shadow_camera::frustum_slice_corners(user_camera, near, far))
+                            .flatten(),
+                        center,
+                        size,
+                        texel_size: size / light.inner.resolution as f32,
+                    }
+                })
+            })
+            .collect();
+
         let shadow_maps: Vec<_> = self
             .data
             .iter()
             .enumerate()
-            .filter_map(|(idx, light)| Some((RawDirectionalLightHandle::new(idx), light.as_ref()?.inner.resolution)))
+            .filter_map(|(idx, light)| Some((RawDirectionalLightHandle::new(idx), light.as_ref()?)))
+            .flat_map(|(handle, light)| {
+                (0..shadow_camera::reserved_cascades(light))
+                    .map(move |cascade| (ShadowSource::Directional { handle, cascade }, light.inner.resolution))
+            })
+            .chain(point_lights.shadow_maps())
+            .chain(spot_lights.shadow_maps())
             .collect();
         let shadow_atlas = shadow_alloc::allocate_shadow_atlas(shadow_maps, renderer.limits.max_texture_dimension_2d);
 
@@ -123,35 +226,95 @@ impl DirectionalLightManager {
             None => return (new_shadow_map_size, Vec::new()),
         };
 
+        // Maps may have been shrunk to fit in the atlas.
+        for map in &coordinates {
+            if let ShadowSource::Directional { handle, cascade } = map.handle {
+                if let Some(info) =
+                    self.cascade_info.iter_mut().find(|info| info.light == handle && info.cascade == cascade)
+                {
+                    info.texel_size = info.size / map.size as f32;
+                }
+            }
+        }
+
+        // Maps reserved for cascades the light doesn't use this frame aren't rendered.
         let shadow_data: Vec<_> = coordinates
             .into_iter()
+            .filter(|map| match map.handle {
+                ShadowSource::Directional { handle, cascade } => {
+                    cascade < shadow_camera::cascade_count(self.data[handle.idx].as_ref().unwrap(), user_camera)
+                }
+                ShadowSource::PointFace { .. } | ShadowSource::Spot(_) => true,
+            })
             .map(|map| {
-                let camera = shadow_camera::shadow_camera(self.data[map.handle.idx].as_ref().unwrap(), user_camera);
+                let camera = match map.handle {
+                    ShadowSource::Directional { handle, cascade } => shadow_camera::shadow_camera(
+                        self.data[handle.idx].as_ref().unwrap(),
+                        user_camera,
+                        cascade,
+                        map.size,
+                    ),
+                    ShadowSource::PointFace { handle, face } => {
+                        point_lights.shadow_camera(handle, face, user_camera.handedness())
+                    }
+                    ShadowSource::Spot(handle) => spot_lights.shadow_camera(handle, user_camera.handedness()),
+                };
 
                 ShadowDesc { map, camera }
             })
             .collect();
 
+        // Shadow map of every cascade of each light, indexed by handle.
+        let mut light_cascades = vec![[None; MAX_CASCADES]; self.data.len()];
+        for desc in &shadow_data {
+            if let ShadowSource::Directional { handle, cascade } = desc.map.handle {
+                light_cascades[handle.idx][cascade as usize] = Some(desc);
+            }
+        }
+
         let buffer = ShaderDirectionalLightBuffer {
             count: ArrayLength,
-            array: shadow_data
+            array: self
+                .data
                 .iter()
-                .map(|desc| {
-                    let light = &self.data[desc.map.handle.idx].as_ref().unwrap().inner;
+                .zip(&light_cascades)
+                .filter_map(|(light, cascades)| Some((light.as_ref()?, cascades)))
+                .map(|(light, cascades)| {
+                    let splits = shadow_camera::light_cascade_splits(light, user_camera);
 
-                    ShaderDirectionalLight {
-                        view_proj: desc.camera.view_proj(),
-                        color: light.color * light.intensity,
-                        direction: light.direction,
+                    let mut shader_light = ShaderDirectionalLight {
+                        view_proj: [Mat4::IDENTITY; MAX_CASCADES],
+                        atlas_rects: [Vec4::ZERO; MAX_CASCADES],
+                        cascade_ends: Vec4::ZERO,
+                        color: light.inner.color * light.inner.intensity,
+                        cascade_count: shadow_camera::cascade_count(light, user_camera) as u32,
+                        direction: light.inner.direction,
+                        cascade_blend: light.inner.cascade_blend.clamp(0.0, 1.0),
                         inv_resolution: 1.0 / new_shadow_map_size_f32,
-                        atlas_offset: desc.map.offset.as_vec2() / new_shadow_map_size_f32,
-                        atlas_size: desc.map.size as f32 / new_shadow_map_size_f32,
+                        shadow_bias: light.inner.shadow_bias,
+                        shadow_normal_offset: light.inner.shadow_normal_offset,
+                        shadow_slope_bias: light.inner.shadow_slope_bias,
+                    };
+
+                    for (cascade, desc) in cascades.iter().enumerate() {
+                        let Some(desc) = desc else {
+                            continue;
+                        };
+                        let offset = desc.map.offset.as_vec2() / new_shadow_map_size_f32;
+                        let size = desc.map.size as f32 / new_shadow_map_size_f32;
+                        shader_light.view_proj[cascade] = desc.camera.view_proj();
+                        shader_light.atlas_rects[cascade] = Vec4::new(offset.x, offset.y, size.x, size.y);
+                        shader_light.cascade_ends[cascade] = splits[cascade + 1];
                     }
+
+                    shader_light
                 })
                 .collect(),
         };
 
-        self.data_buffer.write_to_buffer(&renderer.device, &renderer.queue, &buffer);
+        // Shadow cameras only move with the camera, so light changes which
+        // don't touch them, like the intensity, only upload a few bytes.
+        self.data_buffer.write_changed_to_buffer(&renderer.device, &renderer.queue, &buffer);
 
         (new_shadow_map_size, shadow_data)
     }
@@ -1,22 +1,17 @@
 use std::{array, cmp::Reverse, collections::VecDeque};
 
+use super::ShadowSource;
 use glam::UVec2;
-use rend3_types::RawDirectionalLightHandle;
 
 #[cfg_attr(test, derive(Debug, PartialEq))]
-enum ShadowNode {
+enum ShadowNode<K> {
     Vacant,
-    Leaf(RawDirectionalLightHandle),
+    Leaf(K),
     Children([usize; 4]),
 }
 
-impl ShadowNode {
-    fn try_alloc(
-        nodes: &mut Vec<ShadowNode>,
-        node_idx: usize,
-        relative_order: u32,
-        handle: RawDirectionalLightHandle,
-    ) -> bool {
+impl<K: Copy> ShadowNode<K> {
+    fn try_alloc(nodes: &mut Vec<ShadowNode<K>>, node_idx: usize, relative_order: u32, handle: K) -> bool {
         let this = &mut nodes[node_idx];
         match *this {
             ShadowNode::Vacant => {
@@ -44,22 +39,30 @@ impl ShadowNode {
     }
 }
 
-pub(super) struct ShadowAtlas {
+pub(super) struct ShadowAtlas<K> {
     pub texture_dimensions: UVec2,
-    pub maps: Vec<ShadowMap>,
+    pub maps: Vec<ShadowMap<K>>,
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
-pub struct ShadowMap {
+pub struct ShadowMap<K = ShadowSource> {
     pub offset: UVec2,
     pub size: u32,
-    pub handle: RawDirectionalLightHandle,
+    pub handle: K,
 }
 
-pub(super) fn allocate_shadow_atlas(
-    mut maps: Vec<(RawDirectionalLightHandle, u16)>,
-    max_dimension: u32,
-) -> Option<ShadowAtlas> {
+/// Rounds the resolution of a shadow map down to a power of two which fits in
+/// a texture of `max_dimension`.
+fn fit_resolution(resolution: u16, max_dimension: u32) -> u16 {
+    let resolution = (resolution as u32).min(max_dimension);
+    1 << resolution.ilog2()
+}
+
+/// Packs all maps into a single atlas. If they don't all fit in a texture of
+/// `max_dimension`, every map is halved until they do, so lights keep their
+/// resolution relative to each other.
+pub(super) fn allocate_shadow_atlas<K: Copy>(mut maps: Vec<(K, u16)>, max_dimension: u32) -> Option<ShadowAtlas<K>> {
+    maps.retain(|&(_, res)| res != 0);
     if maps.is_empty() {
         return None;
     }
@@ -67,8 +70,30 @@ pub(super) fn allocate_shadow_atlas(
         return None;
     }
 
+    for (_, res) in &mut maps {
+        *res = fit_resolution(*res, max_dimension);
+    }
+
     maps.sort_by_key(|(_idx, res)| Reverse(*res));
 
+    loop {
+        if let Some(atlas) = pack_shadow_atlas(&maps, max_dimension) {
+            return Some(atlas);
+        }
+        // Even single texel maps don't fit.
+        if maps[0].1 == 1 {
+            return None;
+        }
+
+        for (_, res) in &mut maps {
+            *res = (*res / 2).max(1);
+        }
+    }
+}
+
+/// Packs maps sorted from largest to smallest, returning `None` if the atlas
+/// would be taller than `max_dimension`.
+fn pack_shadow_atlas<K: Copy>(maps: &[(K, u16)], max_dimension: u32) -> Option<ShadowAtlas<K>> {
     let root_size = maps.first().unwrap().1 as u32;
     let min_leading_zeros = (root_size as u16).leading_zeros();
 
@@ -78,7 +103,7 @@ pub(super) fn allocate_shadow_atlas(
     nodes.push(ShadowNode::Vacant);
     roots.push(0);
 
-    for (handle, resolution) in maps {
+    for &(handle, resolution) in maps {
         debug_assert!(resolution.is_power_of_two());
         debug_assert_ne!(resolution, 0);
         let order = resolution.leading_zeros() - min_leading_zeros;
@@ -98,6 +123,9 @@ pub(super) fn allocate_shadow_atlas(
     let root_count = roots.len() as f32;
     let rows_needed = f32::ceil(root_count / available_columns as f32);
     let columns_needed = f32::ceil(root_count / rows_needed) as u32;
+    if rows_needed as u32 * root_size > max_dimension {
+        return None;
+    }
 
     let texture_dimensions = UVec2::new(columns_needed, rows_needed as u32) * root_size;
 
@@ -223,6 +251,42 @@ mod tests {
         );
     }
 
+    #[test]
+    fn allocate_oversized() {
+        // Too large and non power of two maps are shrunk, empty maps are skipped.
+        // Both don't fit in a 32x32 texture, so they are halved again.
+        let maps = vec![(RDLH::new(0), 64), (RDLH::new(1), 24), (RDLH::new(2), 0)];
+
+        let res = allocate_shadow_atlas(maps, 32).unwrap();
+        assert_eq!(res.texture_dimensions, UVec2::new(32, 16));
+        assert_eq!(
+            res.maps,
+            &[
+                ShadowMap { offset: UVec2::splat(0), size: 16, handle: RDLH::new(0) },
+                ShadowMap { offset: UVec2::new(16, 0), size: 8, handle: RDLH::new(1) },
+            ]
+        );
+    }
+
+    #[test]
+    fn allocate_many() {
+        // 32 maps of 16 would need a 64x128 texture, so they are shrunk to 8.
+        let maps: Vec<_> = (0..32).map(|idx| (RDLH::new(idx), 16)).collect();
+
+        let res = allocate_shadow_atlas(maps, 64).unwrap();
+        assert_eq!(res.texture_dimensions, UVec2::new(64, 32));
+        assert_eq!(res.maps.len(), 32);
+        assert!(res.maps.iter().all(|map| map.size == 8));
+        assert_eq!(res.maps[31], ShadowMap { offset: UVec2::new(56, 24), size: 8, handle: RDLH::new(31) });
+    }
+
+    #[test]
+    fn allocate_too_many() {
+        let maps: Vec<_> = (0..5).map(|idx| (RDLH::new(idx), 16)).collect();
+
+        assert!(allocate_shadow_atlas(maps, 2).is_none());
+    }
+
     #[test]
     fn allocate_single() {
         let maps = vec![(RDLH::new(0), 16)];
@@ -1,20 +1,144 @@
+use arrayvec::ArrayVec;
 use glam::{Mat4, Vec3, Vec3A};
-use rend3_types::{Camera, CameraProjection, Handedness};
+use rend3_types::{Camera, CameraProjection, Handedness, ALL_LAYERS, MAX_SHADOW_CASCADES};
 
 use crate::managers::{CameraState, InternalDirectionalLight};
 
-pub(super) fn shadow_camera(l: &InternalDirectionalLight, user_camera: &CameraState) -> CameraState {
-    let camera_location = user_camera.location();
+/// View space depth the cascades start and end at. Cascade `i` covers
+/// `splits[i]..splits[i + 1]`.
+pub(super) type CascadeSplits = ArrayVec<f32, { MAX_SHADOW_CASCADES as usize + 1 }>;
 
-    let shadow_texel_size = l.inner.distance / l.inner.resolution as f32;
+/// Amount of cascades the light actually uses. Lights seen by a non-perspective
+/// camera only have a single cascade, as there is no frustum to split.
+pub(super) fn cascade_count(l: &InternalDirectionalLight, user_camera: &CameraState) -> u8 {
+    match user_camera.get_data().projection {
+        CameraProjection::Perspective { .. } => l.inner.cascades.clamp(1, MAX_SHADOW_CASCADES),
+        _ => 1,
+    }
+}
+
+/// Amount of shadow maps reserved in the atlas for the light's cascades. The
+/// atlas doesn't change as long as this doesn't, whatever the cascade count.
+pub(super) fn reserved_cascades(l: &InternalDirectionalLight) -> u8 {
+    l.inner.max_cascades.max(l.inner.cascades).clamp(1, MAX_SHADOW_CASCADES)
+}
+
+/// Splits `near..far` into `count` ranges, blending between uniform (`lambda ==
+/// 0`) and logarithmic (`lambda == 1`) splits.
+pub(super) fn cascade_splits(near: f32, far: f32, count: u8, lambda: f32) -> CascadeSplits {
+    let lambda = lambda.clamp(0.0, 1.0);
+
+    (0..=count)
+        .map(|i| {
+            let fraction = i as f32 / count as f32;
+            let uniform = near + (far - near) * fraction;
+            let logarithmic = near * (far / near).powf(fraction);
+            lambda * logarithmic + (1.0 - lambda) * uniform
+        })
+        .collect()
+}
+
+/// View space depth ranges of all cascades of the light.
+pub(super) fn light_cascade_splits(l: &InternalDirectionalLight, user_camera: &CameraState) -> CascadeSplits {
+    match user_camera.get_data().projection {
+        CameraProjection::Perspective { near, .. } if cascade_count(l, user_camera) > 1 => {
+            let far = l.inner.distance.max(near);
+            cascade_splits(near, far, cascade_count(l, user_camera), l.inner.cascade_split_lambda)
+        }
+        // The single cascade covers everything within the shadow cube.
+        _ => [0.0, f32::MAX].into_iter().collect(),
+    }
+}
+
+/// World space corners of the slice of a perspective camera's frustum between the
+/// given view space depths. The four near corners come first.
+pub(super) fn frustum_slice_corners(user_camera: &CameraState, near: f32, far: f32) -> Option<[Vec3; 8]> {
+    let CameraProjection::Perspective { vfov, .. } = user_camera.get_data().projection else {
+        return None;
+    };
+
+    let half_height = (vfov.to_radians() * 0.5).tan();
+    let half_width = half_height * user_camera.aspect_ratio();
+    let forward = view_forward(user_camera.handedness());
+    let inv_view = user_camera.view().inverse();
+
+    let mut corners = [Vec3::ZERO; 8];
+    for (idx, corner) in corners.iter_mut().enumerate() {
+        let depth = if idx < 4 { near } else { far };
+        let x = if idx & 1 == 0 { -half_width } else { half_width };
+        let y = if idx & 2 == 0 { -half_height } else { half_height };
+        *corner = inv_view.transform_point3(Vec3::new(x * depth, y * depth, 0.0) + forward * depth);
+    }
+    Some(corners)
+}
+
+/// World space center and edge length of the area the cascade's shadow map covers.
+pub(super) fn cascade_bounds(l: &InternalDirectionalLight, user_camera: &CameraState, cascade: u8) -> (Vec3, f32) {
+    if cascade_count(l, user_camera) == 1 {
+        return (user_camera.location(), l.inner.distance);
+    }
+
+    let CameraProjection::Perspective { vfov, .. } = user_camera.get_data().projection else {
+        unreachable!("only perspective cameras have multiple cascades")
+    };
+    let splits = light_cascade_splits(l, user_camera);
+    let (near, far) = (splits[cascade as usize], splits[cascade as usize + 1]);
+
+    // Bound the slice of the view frustum with a sphere. The sphere doesn't change
+    // as the camera rotates, so the shadow doesn't shimmer.
+    let tan_half_vfov = (vfov.to_radians() * 0.5).tan();
+    let diagonal_slope_sq = tan_half_vfov * tan_half_vfov * (1.0 + user_camera.aspect_ratio().powi(2));
+    // Distance along the view direction of the center of the sphere through both
+    // the near and far corners of the slice, limited to the far plane.
+    let center_depth = (0.5 * (near + far) * (1.0 + diagonal_slope_sq)).min(far);
+    let radius = ((far - center_depth).powi(2) + far * far * diagonal_slope_sq).sqrt();
+    // Round the radius up so small changes of the frustum don't change the texel size.
+    let radius = (radius * 16.0).ceil() / 16.0;
+
+    let forward = view_forward(user_camera.handedness());
+    let center = user_camera.view().inverse().transform_point3(forward * center_depth);
 
-    let look_at = match user_camera.handedness() {
+    (center, radius * 2.0)
+}
+
+/// Camera rendering the cascade into a shadow map of `resolution`, which may be
+/// smaller than the light's if the atlas had to be shrunk.
+pub(super) fn shadow_camera(
+    l: &InternalDirectionalLight,
+    user_camera: &CameraState,
+    cascade: u8,
+    resolution: u32,
+) -> CameraState {
+    let (center, size) = cascade_bounds(l, user_camera, cascade);
+    shadow_camera_around(l, center, size, resolution, user_camera.handedness())
+}
+
+/// Direction the camera looks in view space.
+fn view_forward(handedness: Handedness) -> Vec3 {
+    match handedness {
+        Handedness::Left => Vec3::Z,
+        Handedness::Right => Vec3::NEG_Z,
+    }
+}
+
+/// Shadow camera covering a cube of `size` around `center`, snapped to the texels
+/// of the shadow map.
+fn shadow_camera_around(
+    l: &InternalDirectionalLight,
+    center: Vec3,
+    size: f32,
+    resolution: u32,
+    handedness: Handedness,
+) -> CameraState {
+    let shadow_texel_size = size / resolution as f32;
+
+    let look_at = match handedness {
         Handedness::Left => Mat4::look_at_lh,
         Handedness::Right => Mat4::look_at_rh,
     };
 
     let origin_view = look_at(Vec3::ZERO, l.inner.direction, Vec3::Y);
-    let camera_origin_view = origin_view.transform_point3(camera_location);
+    let camera_origin_view = origin_view.transform_point3(center);
 
     let offset = camera_origin_view.truncate() % shadow_texel_size;
     let shadow_location = camera_origin_view - Vec3::from((offset, 0.0));
@@ -24,10 +148,80 @@ pub(super) fn shadow_camera(l: &InternalDirectionalLight, user_camera: &CameraSt
 
     CameraState::new(
         Camera {
-            projection: CameraProjection::Orthographic { size: Vec3A::splat(l.inner.distance) },
+            // Casters outside the cascade can still shadow it, so the depth range always covers the whole distance.
+            projection: CameraProjection::Orthographic { size: Vec3A::new(size, size, size.max(l.inner.distance)) },
             view: look_at(new_shadow_location, new_shadow_location + l.inner.direction, Vec3::Y),
+            layer_mask: ALL_LAYERS,
         },
-        user_camera.handedness(),
+        handedness,
         None,
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use glam::{Mat4, Vec3};
+    use rend3_types::{Camera, CameraProjection, DirectionalLight, Handedness, ALL_LAYERS};
+
+    use super::{cascade_splits, frustum_slice_corners, reserved_cascades};
+    use crate::managers::{CameraState, InternalDirectionalLight};
+
+    #[test]
+    fn slice_corners() {
+        let camera = CameraState::new(
+            Camera {
+                projection: CameraProjection::Perspective { vfov: 90.0, near: 0.1 },
+                view: Mat4::look_at_rh(Vec3::ZERO, Vec3::NEG_Z, Vec3::Y),
+                layer_mask: ALL_LAYERS,
+            },
+            Handedness::Right,
+            Some(2.0),
+        );
+
+        let corners = frustum_slice_corners(&camera, 1.0, 10.0).unwrap();
+        assert!(corners[0].abs_diff_eq(Vec3::new(-2.0, -1.0, -1.0), 1e-5));
+        assert!(corners[7].abs_diff_eq(Vec3::new(20.0, 10.0, -10.0), 1e-5));
+    }
+
+    #[test]
+    fn uniform_splits() {
+        assert_eq!(cascade_splits(1.0, 5.0, 4, 0.0).as_slice(), [1.0, 2.0, 3.0, 4.0, 5.0]);
+    }
+
+    #[test]
+    fn logarithmic_splits() {
+        assert_eq!(cascade_splits(1.0, 16.0, 4, 1.0).as_slice(), [1.0, 2.0, 4.0, 8.0, 16.0]);
+    }
+
+    #[test]
+    fn blended_splits() {
+        let splits = cascade_splits(1.0, 16.0, 2, 0.5);
+        assert_eq!(splits.as_slice(), [1.0, (4.0 + 8.5) / 2.0, 16.0]);
+    }
+
+    #[test]
+    fn reserved() {
+        let light = |cascades, max_cascades| InternalDirectionalLight {
+            inner: DirectionalLight {
+                color: Vec3::ONE,
+                resolution: 256,
+                intensity: 1.0,
+                direction: Vec3::NEG_Y,
+                distance: 100.0,
+                cascades,
+                max_cascades,
+                cascade_split_lambda: 0.5,
+                cascade_blend: 0.1,
+                shadow_bias: 0.0,
+                shadow_slope_bias: 0.0,
+                shadow_normal_offset: 0.0,
+            },
+        };
+
+        // The reservation doesn't follow the cascade count below the maximum.
+        assert_eq!(reserved_cascades(&light(1, 3)), 3);
+        assert_eq!(reserved_cascades(&light(3, 3)), 3);
+        assert_eq!(reserved_cascades(&light(2, 0)), 2);
+        assert_eq!(reserved_cascades(&light(1, 9)), 4);
+    }
+}
@@ -12,12 +12,26 @@ use rend3_types::{RawResourceHandle, ResourceHandle};
 
 use crate::{instruction::DeletableRawResourceHandle, Renderer};
 
+/// Generation of an index and whether it is currently allocated.
+#[derive(Debug, Default, Copy, Clone)]
+struct Slot {
+    generation: u32,
+    alive: bool,
+}
+
+/// Per-renderer allocator for the indices of a single type of handle.
+///
+/// Allocation is deterministic: new indices are handed out sequentially and
+/// freed indices are reused in LIFO order. Every time an index is freed its
+/// generation goes up, so handles to removed resources can be told apart from
+/// handles to the resource which reused the index.
 pub(crate) struct HandleAllocator<T>
 where
     RawResourceHandle<T>: DeletableRawResourceHandle,
 {
     max_allocated: AtomicUsize,
     freelist: Mutex<Vec<usize>>,
+    slots: Mutex<Vec<Slot>>,
     /// We want the render routines to be able to rely on deleted handles being valid for at
     /// least one frame.
     ///
@@ -38,25 +52,41 @@ where
         Self {
             max_allocated: AtomicUsize::new(0),
             freelist: Mutex::new(Vec::new()),
+            slots: Mutex::new(Vec::new()),
             delay_list: delay_handle_reclaimation.then(|| Mutex::new(Vec::new())),
             _phantom: PhantomData,
         }
     }
 
     pub fn allocate(&self, renderer: &Arc<Renderer>) -> ResourceHandle<T> {
-        let maybe_idx = self.freelist.lock().pop();
-        let idx = maybe_idx.unwrap_or_else(|| self.max_allocated.fetch_add(1, Ordering::Relaxed));
-
         let renderer = Arc::clone(renderer);
         let destroy_fn = move |handle: RawResourceHandle<T>| {
             renderer.instructions.push(handle.into_delete_instruction_kind(), *Location::caller())
         };
 
-        ResourceHandle::new(destroy_fn, idx)
+        ResourceHandle::new(destroy_fn, self.allocate_raw())
+    }
+
+    fn allocate_raw(&self) -> RawResourceHandle<T> {
+        let maybe_idx = self.freelist.lock().pop();
+        let idx = maybe_idx.unwrap_or_else(|| self.max_allocated.fetch_add(1, Ordering::Relaxed));
+
+        let mut slots = self.slots.lock();
+        if slots.len() <= idx {
+            slots.resize(idx + 1, Slot::default());
+        }
+        slots[idx].alive = true;
+
+        RawResourceHandle::with_generation(idx, slots[idx].generation)
     }
 
     pub fn deallocate(&self, handle: RawResourceHandle<T>) {
         let idx = handle.idx;
+        {
+            let slot = &mut self.slots.lock()[idx];
+            slot.alive = false;
+            slot.generation = slot.generation.wrapping_add(1);
+        }
         if let Some(ref delay_list) = self.delay_list {
             delay_list.lock().push(idx);
         } else {
@@ -64,6 +94,17 @@ where
         }
     }
 
+    /// Returns the handle currently allocated at `idx`, if any.
+    pub fn live_handle(&self, idx: usize) -> Option<RawResourceHandle<T>> {
+        let slot = *self.slots.lock().get(idx)?;
+        slot.alive.then(|| RawResourceHandle::with_generation(idx, slot.generation))
+    }
+
+    /// Returns true if the resource behind `handle` has not been removed.
+    pub fn is_alive(&self, handle: RawResourceHandle<T>) -> bool {
+        self.live_handle(handle.idx).is_some_and(|live| live.generation == handle.generation)
+    }
+
     pub fn reclaim_delayed_handles(&self) -> Vec<RawResourceHandle<T>> {
         if let Some(ref delay_list) = self.delay_list {
             let mut locked_delay_list = delay_list.lock();
@@ -75,3 +116,41 @@ where
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use rend3_types::Object;
+
+    use super::HandleAllocator;
+
+    #[test]
+    fn stale_handles() {
+        let allocator = HandleAllocator::<Object>::new(false);
+        let first = allocator.allocate_raw();
+        assert!(allocator.is_alive(first));
+
+        allocator.deallocate(first);
+        assert!(!allocator.is_alive(first));
+        assert_eq!(allocator.live_handle(first.idx), None);
+
+        // The index is reused with a new generation.
+        let second = allocator.allocate_raw();
+        assert_eq!(second.idx, first.idx);
+        assert!(allocator.is_alive(second));
+        assert!(!allocator.is_alive(first));
+        assert_eq!(allocator.live_handle(first.idx).map(|live| live.generation), Some(second.generation));
+    }
+
+    #[test]
+    fn delayed_reclaim() {
+        let allocator = HandleAllocator::<Object>::new(true);
+        let first = allocator.allocate_raw();
+        allocator.deallocate(first);
+        assert!(!allocator.is_alive(first));
+
+        // The index isn't reused until reclaimed.
+        assert_ne!(allocator.allocate_raw().idx, first.idx);
+        allocator.reclaim_delayed_handles();
+        assert_eq!(allocator.allocate_raw().idx, first.idx);
+    }
+}
@@ -0,0 +1,206 @@
+use std::mem;
+
+use glam::Mat4;
+use rend3_types::RawObjectHandle;
+
+use crate::util::typedefs::{FastHashMap, FastHashSet};
+
+/// Parent-child relationships between objects.
+///
+/// Objects with a parent store their transform relative to the parent here,
+/// their world transform is derived from it when the hierarchy is propagated.
+#[derive(Default)]
+pub(super) struct ObjectHierarchy {
+    parents: FastHashMap<RawObjectHandle, RawObjectHandle>,
+    children: FastHashMap<RawObjectHandle, Vec<RawObjectHandle>>,
+    /// Transform relative to the parent of every object which has a parent.
+    local_transforms: FastHashMap<RawObjectHandle, Mat4>,
+    /// Objects whose descendants, and they themselves if they have a parent,
+    /// need their world transform recomputed.
+    dirty: FastHashSet<RawObjectHandle>,
+}
+
+impl ObjectHierarchy {
+    pub fn parent(&self, handle: RawObjectHandle) -> Option<RawObjectHandle> {
+        self.parents.get(&handle).copied()
+    }
+
+    pub fn local_transform(&self, handle: RawObjectHandle) -> Option<Mat4> {
+        self.local_transforms.get(&handle).copied()
+    }
+
+    /// Attaches `child` to `parent`, or detaches it if `parent` is None.
+    ///
+    /// `transform` becomes the transform of `child` relative to its new parent.
+    /// Returns false without changing anything if the parent is the child
+    /// itself or one of its descendants.
+    pub fn set_parent(&mut self, child: RawObjectHandle, parent: Option<RawObjectHandle>, transform: Mat4) -> bool {
+        if let Some(parent) = parent {
+            let mut ancestor = Some(parent);
+            while let Some(current) = ancestor {
+                if current == child {
+                    return false;
+                }
+                ancestor = self.parent(current);
+            }
+        }
+
+        self.detach(child);
+
+        if let Some(parent) = parent {
+            self.parents.insert(child, parent);
+            self.children.entry(parent).or_default().push(child);
+            self.local_transforms.insert(child, transform);
+            self.dirty.insert(child);
+        }
+
+        true
+    }
+
+    /// Records a new transform for `handle`. Returns true if it is relative to
+    /// a parent and was stored as such, false if it is a world transform.
+    pub fn set_transform(&mut self, handle: RawObjectHandle, transform: Mat4) -> bool {
+        let has_parent = match self.local_transforms.get_mut(&handle) {
+            Some(local) => {
+                *local = transform;
+                true
+            }
+            None => false,
+        };
+        if has_parent || self.children.contains_key(&handle) {
+            self.dirty.insert(handle);
+        }
+        has_parent
+    }
+
+    /// Removes `handle` from the hierarchy. Its children keep their current
+    /// world transform and no longer have a parent.
+    pub fn remove(&mut self, handle: RawObjectHandle) {
+        self.detach(handle);
+        for child in self.children.remove(&handle).unwrap_or_default() {
+            self.parents.remove(&child);
+            self.local_transforms.remove(&child);
+            self.dirty.remove(&child);
+        }
+        self.dirty.remove(&handle);
+    }
+
+    fn detach(&mut self, child: RawObjectHandle) {
+        let Some(parent) = self.parents.remove(&child) else {
+            return;
+        };
+        self.local_transforms.remove(&child);
+        if let Some(siblings) = self.children.get_mut(&parent) {
+            siblings.retain(|&sibling| sibling != child);
+            if siblings.is_empty() {
+                self.children.remove(&parent);
+            }
+        }
+    }
+
+    /// Returns every object whose world transform is out of date along with its
+    /// parent, ordered so parents come before their children.
+    pub fn take_dirty(&mut self) -> Vec<(RawObjectHandle, RawObjectHandle)> {
+        if self.dirty.is_empty() {
+            return Vec::new();
+        }
+
+        let dirty = mem::take(&mut self.dirty);
+        let mut roots: Vec<_> = dirty.into_iter().map(|handle| (self.depth(handle), handle)).collect();
+        roots.sort_unstable_by_key(|&(depth, handle)| (depth, handle.idx));
+
+        let mut visited = FastHashSet::default();
+        let mut ordered = Vec::new();
+        let mut stack = Vec::new();
+        for (_, root) in roots {
+            if !visited.insert(root) {
+                continue;
+            }
+            if let Some(parent) = self.parent(root) {
+                ordered.push((root, parent));
+            }
+            stack.push(root);
+            while let Some(parent) = stack.pop() {
+                for &child in self.children.get(&parent).into_iter().flatten() {
+                    visited.insert(child);
+                    ordered.push((child, parent));
+                    stack.push(child);
+                }
+            }
+        }
+        ordered
+    }
+
+    fn depth(&self, handle: RawObjectHandle) -> usize {
+        let mut depth = 0;
+        let mut ancestor = self.parent(handle);
+        while let Some(current) = ancestor {
+            depth += 1;
+            ancestor = self.parent(current);
+        }
+        depth
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Mat4;
+    use rend3_types::RawObjectHandle;
+
+    use super::ObjectHierarchy;
+
+    fn handle(idx: usize) -> RawObjectHandle {
+        RawObjectHandle::new(idx)
+    }
+
+    #[test]
+    fn rejects_cycles() {
+        let mut hierarchy = ObjectHierarchy::default();
+        assert!(hierarchy.set_parent(handle(1), Some(handle(0)), Mat4::IDENTITY));
+        assert!(hierarchy.set_parent(handle(2), Some(handle(1)), Mat4::IDENTITY));
+
+        assert!(!hierarchy.set_parent(handle(0), Some(handle(0)), Mat4::IDENTITY));
+        assert!(!hierarchy.set_parent(handle(0), Some(handle(2)), Mat4::IDENTITY));
+        assert_eq!(hierarchy.parent(handle(0)), None);
+
+        // Moving a subtree somewhere else is fine.
+        assert!(hierarchy.set_parent(handle(1), Some(handle(3)), Mat4::IDENTITY));
+        assert!(hierarchy.set_parent(handle(0), Some(handle(2)), Mat4::IDENTITY));
+    }
+
+    #[test]
+    fn parents_before_children() {
+        let mut hierarchy = ObjectHierarchy::default();
+        hierarchy.set_parent(handle(3), Some(handle(2)), Mat4::IDENTITY);
+        hierarchy.set_parent(handle(2), Some(handle(1)), Mat4::IDENTITY);
+        hierarchy.set_parent(handle(1), Some(handle(0)), Mat4::IDENTITY);
+        hierarchy.set_parent(handle(4), Some(handle(0)), Mat4::IDENTITY);
+
+        assert_eq!(
+            hierarchy.take_dirty(),
+            [(handle(1), handle(0)), (handle(2), handle(1)), (handle(3), handle(2)), (handle(4), handle(0))]
+        );
+        assert!(hierarchy.take_dirty().is_empty());
+
+        // Moving the root updates everything below it.
+        assert!(!hierarchy.set_transform(handle(0), Mat4::IDENTITY));
+        assert_eq!(hierarchy.take_dirty().len(), 4);
+
+        assert!(hierarchy.set_transform(handle(2), Mat4::IDENTITY));
+        assert_eq!(hierarchy.take_dirty(), [(handle(2), handle(1)), (handle(3), handle(2))]);
+    }
+
+    #[test]
+    fn removal_orphans_children() {
+        let mut hierarchy = ObjectHierarchy::default();
+        hierarchy.set_parent(handle(1), Some(handle(0)), Mat4::IDENTITY);
+        hierarchy.set_parent(handle(2), Some(handle(1)), Mat4::IDENTITY);
+        hierarchy.take_dirty();
+
+        hierarchy.remove(handle(1));
+        assert_eq!(hierarchy.parent(handle(2)), None);
+        assert_eq!(hierarchy.local_transform(handle(2)), None);
+        assert!(!hierarchy.set_transform(handle(0), Mat4::IDENTITY));
+        assert!(hierarchy.take_dirty().is_empty());
+    }
+}
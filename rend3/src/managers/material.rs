@@ -1,11 +1,13 @@
 use std::{
-    any::TypeId,
+    any::{self, TypeId},
     mem,
     num::{NonZeroU32, NonZeroU64},
 };
 
 use encase::{ShaderSize, ShaderType};
-use rend3_types::{Material, MaterialArray, RawMaterialHandle, RawTexture2DHandle, VertexAttributeId, WasmVecAny};
+use rend3_types::{
+    Material, MaterialArray, RawMaterialHandle, RawTexture2DHandle, Sorting, VertexAttributeId, WasmVecAny,
+};
 use wgpu::{BindGroup, BindGroupLayout, BindingType, Buffer, BufferBindingType, CommandEncoder, Device, ShaderStages};
 
 use crate::{
@@ -34,6 +36,21 @@ struct CpuPoweredShaderWrapper<M: Material> {
     texture_enable: u32,
 }
 
+/// Properties shared by all types of materials, copied from a material. See
+/// [`Renderer::material`](crate::Renderer::material).
+#[derive(Debug, Clone)]
+pub struct MaterialSnapshot {
+    /// Type of the material.
+    pub type_id: TypeId,
+    /// Name of the type of the material, for display only.
+    pub type_name: &'static str,
+    pub key: u64,
+    pub sorting: Sorting,
+    pub double_sided: bool,
+    /// Textures the material binds. They don't keep the textures alive.
+    pub textures: Vec<Option<RawTexture2DHandle>>,
+}
+
 /// Internal representation of a material.
 pub struct InternalMaterial<M> {
     pub bind_group_index: ProfileData<TextureBindGroupIndex, ()>,
@@ -58,6 +75,7 @@ struct MaterialArchetype {
     #[allow(clippy::type_complexity)]
     get_attributes: fn(&mut dyn FnMut(&[&'static VertexAttributeId], &[&'static VertexAttributeId])),
     object_add_callback_wrapper: fn(&WasmVecAny, usize, ObjectAddCallbackArgs<'_>),
+    snapshot: fn(&WasmVecAny, usize) -> MaterialSnapshot,
 }
 
 pub struct MaterialArchetypeView<'a, M: Material> {
@@ -125,6 +143,7 @@ impl MaterialManager {
             apply_data_gpu: apply_buffer_gpu::<M>,
             get_attributes: get_attributes::<M>,
             object_add_callback_wrapper: object_add_callback_wrapper::<M>,
+            snapshot: snapshot::<M>,
         })
     }
 
@@ -188,6 +207,19 @@ impl MaterialManager {
         internal.inner = material;
     }
 
+    /// Updates the texture bind groups of the CpuDriven profile using textures
+    /// which finished streaming.
+    pub fn refresh_textures(
+        &mut self,
+        device: &Device,
+        texture_manager_2d: &TextureManager<crate::types::Texture2DTag>,
+        handles: &[RawTexture2DHandle],
+    ) {
+        if !handles.is_empty() {
+            self.texture_deduplicator.refresh(device, texture_manager_2d, handles);
+        }
+    }
+
     pub fn remove(&mut self, handle: RawMaterialHandle) {
         let type_id = self.handle_to_typeid.remove(&handle).unwrap();
 
@@ -259,6 +291,13 @@ impl MaterialManager {
         (archetype.object_add_callback_wrapper)(&archetype.data_vec, handle.idx, args);
     }
 
+    /// Copy of the material's properties, or `None` if it was removed.
+    pub fn snapshot(&self, handle: RawMaterialHandle) -> Option<MaterialSnapshot> {
+        let archetype = &self.archetypes[self.handle_to_typeid.get(&handle)?];
+
+        Some((archetype.snapshot)(&archetype.data_vec, handle.idx))
+    }
+
     pub fn get_bind_group_layout_cpu<M: Material>(&self) -> &BindGroupLayout {
         self.texture_deduplicator
             .get_bgl(<M::TextureArrayType as MaterialArray<Option<RawTexture2DHandle>>>::COUNT as usize)
@@ -348,3 +387,18 @@ fn object_add_callback_wrapper<M: Material>(vec_any: &WasmVecAny, idx: usize, ar
 
     object_add_callback(material, args)
 }
+
+fn snapshot<M: Material>(vec_any: &WasmVecAny, idx: usize) -> MaterialSnapshot {
+    let data_vec = vec_any.downcast_slice::<Option<InternalMaterial<M>>>().unwrap();
+
+    let material = &data_vec[idx].as_ref().unwrap().inner;
+
+    MaterialSnapshot {
+        type_id: TypeId::of::<M>(),
+        type_name: any::type_name::<M>(),
+        key: material.key(),
+        sorting: material.sorting(),
+        double_sided: material.double_sided(),
+        textures: material.to_textures().as_ref().to_vec(),
+    }
+}
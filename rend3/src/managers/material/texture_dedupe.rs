@@ -82,25 +82,7 @@ impl TextureDeduplicator {
             return index;
         }
 
-        let entries: ArrayVec<_, 32> = array
-            .iter()
-            .enumerate()
-            .map(|(idx, handle)| {
-                let view = if let Some(handle) = *handle {
-                    texture_manager_2d.get_view(handle)
-                } else {
-                    texture_manager_2d.get_null_view()
-                };
-
-                BindGroupEntry { binding: idx as u32, resource: BindingResource::TextureView(view) }
-            })
-            .collect();
-
-        let bg = device.create_bind_group(&BindGroupDescriptor {
-            label: None,
-            layout: &self.bgls[array.len()],
-            entries: &entries,
-        });
+        let bg = create_bind_group(device, &self.bgls[array.len()], texture_manager_2d, array);
 
         let index = self.storage.push(StoredBindGroup { refcount: 1, inner: bg });
         let index = TextureBindGroupIndex(index);
@@ -110,6 +92,22 @@ impl TextureDeduplicator {
         index
     }
 
+    /// Recreates the bind groups using any of the given textures, as their
+    /// views changed when they finished streaming.
+    pub fn refresh(
+        &mut self,
+        device: &Device,
+        texture_manager_2d: &TextureManager<crate::types::Texture2DTag>,
+        handles: &[RawTexture2DHandle],
+    ) {
+        for (array, index) in &self.deduplication_map {
+            if array.iter().flatten().any(|handle| handles.contains(handle)) {
+                self.storage[index.0].inner =
+                    create_bind_group(device, &self.bgls[array.len()], texture_manager_2d, array);
+            }
+        }
+    }
+
     pub fn remove(&mut self, index: TextureBindGroupIndex) {
         let refcount = &mut self.storage[index.0].refcount;
         *refcount = refcount.checked_sub(1).unwrap();
@@ -125,6 +123,29 @@ impl TextureDeduplicator {
     }
 }
 
+fn create_bind_group(
+    device: &Device,
+    layout: &BindGroupLayout,
+    texture_manager_2d: &TextureManager<crate::types::Texture2DTag>,
+    array: &[Option<RawTexture2DHandle>],
+) -> BindGroup {
+    let entries: ArrayVec<_, 32> = array
+        .iter()
+        .enumerate()
+        .map(|(idx, handle)| {
+            let view = if let Some(handle) = *handle {
+                texture_manager_2d.get_view(handle)
+            } else {
+                texture_manager_2d.get_null_view()
+            };
+
+            BindGroupEntry { binding: idx as u32, resource: BindingResource::TextureView(view) }
+        })
+        .collect();
+
+    device.create_bind_group(&BindGroupDescriptor { label: None, layout, entries: &entries })
+}
+
 impl Index<TextureBindGroupIndex> for TextureDeduplicator {
     type Output = BindGroup;
 
@@ -1,9 +1,10 @@
 use std::{
-    mem,
+    mem::{self, size_of_val},
     ops::{Index, Range},
     sync::Arc,
 };
 
+use glam::Vec3;
 use parking_lot::{Mutex, MutexGuard};
 use range_alloc::RangeAllocator;
 use rend3_types::{RawMeshHandle, VertexAttributeId, VERTEX_ATTRIBUTE_JOINT_INDICES, VERTEX_ATTRIBUTE_POSITION};
@@ -15,7 +16,12 @@ use wgpu::{
 
 use crate::{
     types::{Mesh, MeshHandle},
-    util::{error_scope::AllocationErrorScope, frustum::BoundingSphere, sync::WaitGroup, upload::UploadChainer},
+    util::{
+        error_scope::AllocationErrorScope,
+        frustum::{Aabb, BoundingSphere},
+        sync::WaitGroup,
+        upload::UploadChainer,
+    },
 };
 
 /// Vertex buffer slot for object indices
@@ -38,6 +44,22 @@ pub struct InternalMesh {
     pub required_joint_count: Option<u16>,
     /// The bounding sphere of this mesh. Used for culling.
     pub bounding_sphere: BoundingSphere,
+    /// The bounding box of this mesh.
+    pub aabb: Aabb,
+    /// Location of the morph target offsets, if the mesh has morph targets.
+    pub morph_targets: Option<InternalMorphTargets>,
+}
+
+/// Location of the morph target offsets of a mesh in the mesh data buffer.
+#[derive(Debug, Clone)]
+pub struct InternalMorphTargets {
+    /// Position offsets of every morph target, one target after the other.
+    pub position_range: Range<u64>,
+    /// Normal offsets of every morph target, one target after the other. Only
+    /// present if some target has normal offsets, the others being zero.
+    pub normal_range: Option<Range<u64>>,
+    /// Amount of morph targets.
+    pub count: u32,
 }
 
 impl InternalMesh {
@@ -49,6 +71,8 @@ impl InternalMesh {
             index_range: 0..0,
             required_joint_count: None,
             bounding_sphere: BoundingSphere::from_mesh(&[]),
+            aabb: Aabb::from_mesh(&[]),
+            morph_targets: None,
         }
     }
 
@@ -139,6 +163,21 @@ impl MeshManager {
             required_joint_count = Some(joint_indices.iter().flatten().max().map_or(0, |v| v + 1));
         }
 
+        // The offsets of all morph targets are uploaded together, so the shader
+        // finds those of a target at a multiple of the vertex count.
+        let morph_positions: Vec<Vec3> =
+            mesh.morph_targets.iter().flat_map(|target| target.position_offsets.iter().copied()).collect();
+        let morph_normals: Option<Vec<Vec3>> =
+            mesh.morph_targets.iter().any(|target| target.normal_offsets.is_some()).then(|| {
+                mesh.morph_targets
+                    .iter()
+                    .flat_map(|target| match &target.normal_offsets {
+                        Some(offsets) => offsets.clone(),
+                        None => vec![Vec3::ZERO; vertex_count],
+                    })
+                    .collect()
+            });
+
         let mut vertex_attribute_ranges = Vec::with_capacity(mesh.attributes.len());
         let mut upload = UploadChainer::new();
 
@@ -152,6 +191,25 @@ impl MeshManager {
             vertex_attribute_ranges.push((*attribute.id(), range));
         }
 
+        let morph_targets = if morph_positions.is_empty() {
+            None
+        } else {
+            let position_range =
+                self.allocate_range_impl(device, buffer_state, size_of_val(&*morph_positions) as u64)?;
+            upload.add(position_range.start, bytemuck::cast_slice(&morph_positions));
+
+            let normal_range = match &morph_normals {
+                Some(normals) => {
+                    let range = self.allocate_range_impl(device, buffer_state, size_of_val(&**normals) as u64)?;
+                    upload.add(range.start, bytemuck::cast_slice(normals));
+                    Some(range)
+                }
+                None => None,
+            };
+
+            Some(InternalMorphTargets { position_range, normal_range, count: mesh.morph_targets.len() as u32 })
+        };
+
         let index_range = self.allocate_range_impl(device, buffer_state, index_count as u64 * 4)?;
         upload.add(index_range.start, bytemuck::cast_slice(&mesh.indices));
         upload.create_staging_buffer(device).map_err(|e| MeshCreationError::BufferWriteFailed { inner: e })?;
@@ -166,13 +224,14 @@ impl MeshManager {
         drop(staging_guard);
 
         // We can cheat here as we know vertex positions are always the first attribute as they must exist.
-        let bounding_sphere = BoundingSphere::from_mesh(
-            mesh.attributes
-                .first()
-                .expect("Meshes first attributes must always exist")
-                .typed_data(&VERTEX_ATTRIBUTE_POSITION)
-                .expect("Meshes must have positions"),
-        );
+        let positions = mesh
+            .attributes
+            .first()
+            .expect("Meshes first attributes must always exist")
+            .typed_data(&VERTEX_ATTRIBUTE_POSITION)
+            .expect("Meshes must have positions");
+        let bounding_sphere = BoundingSphere::from_mesh(positions);
+        let aabb = Aabb::from_mesh(positions);
 
         Ok(InternalMesh {
             vertex_attribute_ranges,
@@ -180,6 +239,8 @@ impl MeshManager {
             index_range,
             required_joint_count,
             bounding_sphere,
+            aabb,
+            morph_targets,
         })
     }
 
@@ -194,6 +255,14 @@ impl MeshManager {
         drop(data_guard);
     }
 
+    /// Object space bounding sphere of the mesh's vertex positions, or `None`
+    /// if the mesh has been removed.
+    ///
+    /// Objects transform this into world space whenever they are added or moved.
+    pub fn get_bounds(&self, handle: RawMeshHandle) -> Option<BoundingSphere> {
+        self.data.lock().get(handle.idx)?.as_ref().map(|mesh| mesh.bounding_sphere)
+    }
+
     pub fn remove(&self, object_id: RawMeshHandle) {
         let mesh = self.data.lock()[object_id.idx].take().unwrap();
 
@@ -204,6 +273,12 @@ impl MeshManager {
             }
             buffer_state.allocator.free_range(range);
         }
+        if let Some(morph_targets) = mesh.morph_targets {
+            Self::free_range_impl(&mut buffer_state, morph_targets.position_range);
+            if let Some(range) = morph_targets.normal_range {
+                Self::free_range_impl(&mut buffer_state, range);
+            }
+        }
         if mesh.index_range.is_empty() {
             return;
         }
@@ -4,17 +4,20 @@ use bytemuck::Zeroable;
 use encase::ShaderType;
 use glam::{Mat4, Vec3A};
 use rend3_types::{
-    Material, MaterialArray, MaterialHandle, ObjectChange, ObjectMeshKind, RawObjectHandle, VertexAttributeId,
-    WasmVecAny,
+    LodGroup, Material, MaterialArray, MaterialHandle, MaterialOverride, ObjectChange, ObjectFlags, ObjectMeshKind,
+    RawMaterialHandle, RawObjectHandle, VertexAttributeId, WasmVecAny,
 };
 use wgpu::{Buffer, CommandEncoder, Device};
 
-use super::SkeletonManager;
+use super::{hierarchy::ObjectHierarchy, SkeletonManager};
 use crate::{
     managers::{InternalMesh, MaterialManager, MeshManager},
     types::Object,
     util::{
-        freelist::FreelistDerivedBuffer, frustum::BoundingSphere, iter::ExactSizerIterator, scatter_copy::ScatterCopy,
+        freelist::FreelistDerivedBuffer,
+        frustum::{Aabb, BoundingSphere},
+        iter::ExactSizerIterator,
+        scatter_copy::ScatterCopy,
         typedefs::FastHashMap,
     },
 };
@@ -33,6 +36,9 @@ pub struct ShaderObject<M: Material> {
         <M::SupportedAttributeArrayType as MaterialArray<&'static VertexAttributeId>>::U32Array,
     // 1 if enabled, 0 if disabled
     pub enabled: u32,
+    /// Bits of [`ObjectFlags`].
+    pub flags: u32,
+    pub material_override: MaterialOverride,
 }
 
 impl<M: Material> Default for ShaderObject<M> {
@@ -45,6 +51,8 @@ impl<M: Material> Default for ShaderObject<M> {
             material_index: Default::default(),
             vertex_attribute_start_offsets: Zeroable::zeroed(),
             enabled: Default::default(),
+            flags: Default::default(),
+            material_override: Default::default(),
         }
     }
 }
@@ -59,6 +67,44 @@ impl<M: Material> Clone for ShaderObject<M> {
     }
 }
 
+type AttributeOffsets<M> =
+    <<M as Material>::SupportedAttributeArrayType as MaterialArray<&'static VertexAttributeId>>::U32Array;
+
+/// A single level of an object's [`LodGroup`].
+pub struct InternalLodLevel<M: Material> {
+    pub min_distance: f32,
+    pub mesh_bounding_sphere: BoundingSphere,
+    pub mesh_aabb: Aabb,
+    pub first_index: u32,
+    pub index_count: u32,
+    pub vertex_attribute_start_offsets: AttributeOffsets<M>,
+}
+
+// Manual impl so that M: !Copy
+impl<M: Material> Copy for InternalLodLevel<M> {}
+
+// Manual impl so that M: !Clone
+impl<M: Material> Clone for InternalLodLevel<M> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+/// Level of detail state of an object using a [`LodGroup`].
+pub struct InternalLodGroup<M: Material> {
+    pub levels: Vec<InternalLodLevel<M>>,
+    pub hysteresis: f32,
+    /// Index of the level currently in [`InternalObject::inner`].
+    pub current: usize,
+}
+
+// Manual impl so that M: !Clone
+impl<M: Material> Clone for InternalLodGroup<M> {
+    fn clone(&self) -> Self {
+        Self { levels: self.levels.clone(), hysteresis: self.hysteresis, current: self.current }
+    }
+}
+
 /// Internal representation of a Object.
 pub struct InternalObject<M: Material> {
     pub mesh_kind: ObjectMeshKind,
@@ -67,17 +113,94 @@ pub struct InternalObject<M: Material> {
     /// World space
     pub location: Vec3A,
     pub mesh_bounding_sphere: BoundingSphere,
+    /// Model space bounding box of the mesh.
+    pub mesh_aabb: Aabb,
+    /// Hidden objects are skipped by culling, so they are never drawn.
+    pub visible: bool,
+    /// Cameras whose layer mask shares no layer with it skip it while culling.
+    pub layer_mask: u32,
+    /// Only set for objects using [`ObjectMeshKind::Lod`].
+    pub lod: Option<InternalLodGroup<M>>,
     pub inner: ShaderObject<M>,
 }
 
+impl<M: Material> InternalObject<M> {
+    /// Switches to the level of detail for a camera at `camera_location`,
+    /// returning true if the level changed.
+    fn update_lod(&mut self, camera_location: Vec3A) -> bool {
+        let Some(lod) = &mut self.lod else {
+            return false;
+        };
+
+        let distance = camera_location.distance(self.location);
+        let level = select_lod_level(
+            lod.levels.len(),
+            |level| lod.levels[level].min_distance,
+            lod.current,
+            distance,
+            lod.hysteresis,
+        );
+        if level == lod.current {
+            return false;
+        }
+        lod.current = level;
+
+        let level = lod.levels[level];
+        self.mesh_bounding_sphere = level.mesh_bounding_sphere;
+        self.mesh_aabb = level.mesh_aabb;
+        self.inner.bounding_sphere = level.mesh_bounding_sphere.apply_transform(self.inner.transform);
+        self.inner.first_index = level.first_index;
+        self.inner.index_count = level.index_count;
+        self.inner.vertex_attribute_start_offsets = level.vertex_attribute_start_offsets;
+        true
+    }
+}
+
+/// Picks which of `count` levels of detail to use at `distance`, given the
+/// `min_distance` of every level. The level only changes once `distance` is
+/// `hysteresis` (relative to the threshold) past the threshold between levels.
+fn select_lod_level(
+    count: usize,
+    threshold: impl Fn(usize) -> f32,
+    current: usize,
+    distance: f32,
+    hysteresis: f32,
+) -> usize {
+    let mut level = current.min(count.saturating_sub(1));
+    while level + 1 < count && distance >= threshold(level + 1) * (1.0 + hysteresis) {
+        level += 1;
+    }
+    while level > 0 && distance < threshold(level) * (1.0 - hysteresis) {
+        level -= 1;
+    }
+    level
+}
+
+/// Copy of the state of an object, see
+/// [`Renderer::object`](crate::Renderer::object).
+#[derive(Debug, Clone)]
+pub struct ObjectSnapshot {
+    pub mesh_kind: ObjectMeshKind,
+    pub material: RawMaterialHandle,
+    /// World space transform, including the transforms of its parents.
+    pub transform: Mat4,
+    pub visible: bool,
+    pub layer_mask: u32,
+    pub material_override: MaterialOverride,
+}
+
 // Manual impl so that M: !Clone
 impl<M: Material> Clone for InternalObject<M> {
     fn clone(&self) -> Self {
         Self {
             mesh_kind: self.mesh_kind.clone(),
             mesh_bounding_sphere: self.mesh_bounding_sphere,
+            mesh_aabb: self.mesh_aabb,
             material_handle: self.material_handle.clone(),
             location: self.location,
+            visible: self.visible,
+            layer_mask: self.layer_mask,
+            lod: self.lod.clone(),
             inner: self.inner,
         }
     }
@@ -87,23 +210,35 @@ struct ObjectArchetype {
     /// Inner type is Option<InternalObject<M>>
     data_vec: WasmVecAny,
     object_count: usize,
+    /// Amount of objects using a [`LodGroup`].
+    lod_object_count: usize,
     buffer: FreelistDerivedBuffer,
+    object_transform: fn(&WasmVecAny, usize) -> Mat4,
+    object_bounds: fn(&WasmVecAny, usize) -> Option<Aabb>,
+    object_snapshot: fn(&WasmVecAny, usize) -> Option<ObjectSnapshot>,
     set_object_transform: fn(&mut WasmVecAny, &mut FreelistDerivedBuffer, usize, Mat4),
+    set_object_visibility: fn(&mut WasmVecAny, usize, bool),
+    set_object_material_override: fn(&mut WasmVecAny, &mut FreelistDerivedBuffer, usize, MaterialOverride),
     duplicate_object: fn(&WasmVecAny, usize, ObjectChange) -> Object,
     remove: fn(&mut ObjectArchetype, usize),
-    evaluate: fn(&mut ObjectArchetype, &Device, &mut CommandEncoder, &ScatterCopy, &[RawObjectHandle]),
+    evaluate: fn(&mut ObjectArchetype, &Device, &mut CommandEncoder, &ScatterCopy, &[RawObjectHandle], Vec3A),
 }
 
 /// Manages objects. That's it. ¯\\\_(ツ)\_/¯
 pub struct ObjectManager {
     archetype: FastHashMap<TypeId, ObjectArchetype>,
     handle_to_typeid: FastHashMap<RawObjectHandle, TypeId>,
+    hierarchy: ObjectHierarchy,
 }
 impl ObjectManager {
     pub fn new() -> Self {
         profiling::scope!("ObjectManager::new");
 
-        Self { archetype: FastHashMap::default(), handle_to_typeid: FastHashMap::default() }
+        Self {
+            archetype: FastHashMap::default(),
+            handle_to_typeid: FastHashMap::default(),
+            hierarchy: ObjectHierarchy::default(),
+        }
     }
 
     fn ensure_archetype<M: Material>(&mut self, device: &Device) -> &mut ObjectArchetype {
@@ -111,8 +246,14 @@ impl ObjectManager {
         self.archetype.entry(type_id).or_insert_with(|| ObjectArchetype {
             data_vec: WasmVecAny::new::<Option<InternalObject<M>>>(),
             object_count: 0,
+            lod_object_count: 0,
             buffer: FreelistDerivedBuffer::new::<ShaderObject<M>>(device),
+            object_transform: object_transform::<M>,
+            object_bounds: object_bounds::<M>,
+            object_snapshot: object_snapshot::<M>,
             set_object_transform: set_object_transform::<M>,
+            set_object_visibility: set_object_visibility::<M>,
+            set_object_material_override: set_object_material_override::<M>,
             duplicate_object: duplicate_object::<M>,
             remove: remove::<M>,
             evaluate: evaluate::<M>,
@@ -129,25 +270,90 @@ impl ObjectManager {
         material_manager: &mut MaterialManager,
     ) {
         let mesh_manager_guard = mesh_manager.lock_internal_data();
-        let (internal_mesh, skeleton_ranges) = match &object.mesh_kind {
+        let (internal_mesh, skeleton_ranges, lod_meshes) = match &object.mesh_kind {
             ObjectMeshKind::Animated(skeleton) => {
                 let skeleton = skeleton_manager.internal_data(**skeleton);
                 let mesh = &mesh_manager_guard[*skeleton.mesh_handle];
-                (mesh, &*skeleton.overridden_attribute_ranges)
+                (mesh, &*skeleton.overridden_attribute_ranges, Vec::new())
             }
             ObjectMeshKind::Static(mesh) => {
                 let mesh = &mesh_manager_guard[**mesh];
-                (mesh, &[][..])
+                (mesh, &[][..], Vec::new())
+            }
+            ObjectMeshKind::Lod(LodGroup { levels, .. }) => {
+                assert!(!levels.is_empty(), "LodGroup must have at least one level");
+                let lod_meshes: Vec<_> = levels.iter().map(|level| &mesh_manager_guard[*level.mesh]).collect();
+                (lod_meshes[0], &[][..], lod_meshes)
             }
         };
 
         material_manager.call_object_add_callback(
             *object.material,
-            ObjectAddCallbackArgs { device, manager: self, internal_mesh, skeleton_ranges, handle, object },
+            ObjectAddCallbackArgs { device, manager: self, internal_mesh, skeleton_ranges, lod_meshes, handle, object },
         );
     }
 
+    /// Sets the transform of the object, relative to its parent if it has one.
     pub fn set_object_transform(&mut self, handle: RawObjectHandle, transform: Mat4) {
+        if !self.hierarchy.set_transform(handle, transform) {
+            self.set_world_transform(handle, transform);
+        }
+    }
+
+    /// Attaches the object to `parent`, so it follows the parent's transform,
+    /// or detaches it if `parent` is None. The object's current transform
+    /// becomes its transform relative to the new parent.
+    ///
+    /// Parenting an object to itself or one of its descendants is rejected
+    /// with a warning.
+    pub fn set_object_parent(&mut self, child: RawObjectHandle, parent: Option<RawObjectHandle>) {
+        let transform = self.hierarchy.local_transform(child).unwrap_or_else(|| self.world_transform(child));
+        if !self.hierarchy.set_parent(child, parent, transform) {
+            log::warn!("Cannot parent object {:?} to {:?}, as it would form a cycle", child, parent);
+            return;
+        }
+        if parent.is_none() {
+            self.set_world_transform(child, transform);
+        }
+    }
+
+    fn world_transform(&self, handle: RawObjectHandle) -> Mat4 {
+        let archetype = &self.archetype[&self.handle_to_typeid[&handle]];
+
+        (archetype.object_transform)(&archetype.data_vec, handle.idx)
+    }
+
+    /// World space bounding box of the object's mesh, or `None` if the object
+    /// was removed.
+    ///
+    /// Skinned meshes are bounded in their bind pose.
+    pub fn bounds(&self, handle: RawObjectHandle) -> Option<Aabb> {
+        let archetype = &self.archetype[self.handle_to_typeid.get(&handle)?];
+
+        (archetype.object_bounds)(&archetype.data_vec, handle.idx)
+    }
+
+    /// Copy of the object's state, or `None` if the object was removed.
+    pub fn snapshot(&self, handle: RawObjectHandle) -> Option<ObjectSnapshot> {
+        let archetype = &self.archetype[self.handle_to_typeid.get(&handle)?];
+
+        (archetype.object_snapshot)(&archetype.data_vec, handle.idx)
+    }
+
+    /// Handles of all objects which weren't removed, in no particular order.
+    pub fn handles(&self) -> impl Iterator<Item = RawObjectHandle> + '_ {
+        // Handles of removed objects stay in the map until they are reused.
+        self.handle_to_typeid.iter().map(|(&handle, _)| handle).filter(|&handle| self.is_alive(handle))
+    }
+
+    fn is_alive(&self, handle: RawObjectHandle) -> bool {
+        let archetype = &self.archetype[&self.handle_to_typeid[&handle]];
+
+        // Removed objects have no bounds.
+        (archetype.object_bounds)(&archetype.data_vec, handle.idx).is_some()
+    }
+
+    fn set_world_transform(&mut self, handle: RawObjectHandle, transform: Mat4) {
         let type_id = self.handle_to_typeid[&handle];
 
         let archetype = self.archetype.get_mut(&type_id).unwrap();
@@ -155,7 +361,32 @@ impl ObjectManager {
         (archetype.set_object_transform)(&mut archetype.data_vec, &mut archetype.buffer, handle.idx, transform);
     }
 
+    pub fn set_object_visibility(&mut self, handle: RawObjectHandle, visible: bool) {
+        let type_id = self.handle_to_typeid[&handle];
+
+        let archetype = self.archetype.get_mut(&type_id).unwrap();
+
+        (archetype.set_object_visibility)(&mut archetype.data_vec, handle.idx, visible);
+    }
+
+    pub fn set_object_material_override(&mut self, handle: RawObjectHandle, material_override: MaterialOverride) {
+        let type_id = self.handle_to_typeid[&handle];
+
+        let archetype = self.archetype.get_mut(&type_id).unwrap();
+
+        (archetype.set_object_material_override)(
+            &mut archetype.data_vec,
+            &mut archetype.buffer,
+            handle.idx,
+            material_override,
+        );
+    }
+
     pub fn remove(&mut self, handle: RawObjectHandle) {
+        // Children keep the world transform they had when the parent was removed, so bring it up to date.
+        self.propagate_transforms();
+        self.hierarchy.remove(handle);
+
         let type_id = self.handle_to_typeid[&handle];
 
         let archetype = self.archetype.get_mut(&type_id).unwrap();
@@ -169,9 +400,28 @@ impl ObjectManager {
         encoder: &mut CommandEncoder,
         scatter: &ScatterCopy,
         deferred_removals: &[RawObjectHandle],
+        camera_location: Vec3A,
     ) {
+        self.propagate_transforms();
+
         for archetype in self.archetype.values_mut() {
-            (archetype.evaluate)(archetype, device, encoder, scatter, deferred_removals);
+            (archetype.evaluate)(archetype, device, encoder, scatter, deferred_removals, camera_location);
+        }
+    }
+
+    /// Recomputes the world transforms of all objects whose parent or own
+    /// relative transform changed.
+    fn propagate_transforms(&mut self) {
+        let dirty = self.hierarchy.take_dirty();
+        if dirty.is_empty() {
+            return;
+        }
+
+        profiling::scope!("Transform Propagation");
+        for (child, parent) in dirty {
+            let local = self.hierarchy.local_transform(child).unwrap();
+            let world = self.world_transform(parent) * local;
+            self.set_world_transform(child, world);
         }
     }
 
@@ -208,13 +458,19 @@ impl ObjectManager {
         skeleton_manager: &SkeletonManager,
         material_manager: &mut MaterialManager,
     ) {
+        // The duplicate starts out at the source's current world transform.
+        self.propagate_transforms();
+
         let type_id = self.handle_to_typeid[&src_handle];
 
         let archetype = self.archetype.get_mut(&type_id).unwrap();
 
         let dst_obj = (archetype.duplicate_object)(&mut archetype.data_vec, src_handle.idx, change);
+        let material_override =
+            self.snapshot(src_handle).map_or(MaterialOverride::IDENTITY, |src| src.material_override);
 
         self.add(device, dst_handle, dst_obj, mesh_manager, skeleton_manager, material_manager);
+        self.set_object_material_override(dst_handle, material_override);
     }
 }
 
@@ -229,19 +485,25 @@ pub(super) struct ObjectAddCallbackArgs<'a> {
     manager: &'a mut ObjectManager,
     internal_mesh: &'a InternalMesh,
     skeleton_ranges: &'a [(VertexAttributeId, Range<u64>)],
+    /// Mesh of every level of detail, empty if the object doesn't use a [`LodGroup`].
+    lod_meshes: Vec<&'a InternalMesh>,
     handle: RawObjectHandle,
     object: Object,
 }
 
-pub(super) fn object_add_callback<M: Material>(_material: &M, args: ObjectAddCallbackArgs<'_>) {
+/// Offsets and index range of an object drawn with the given mesh.
+fn mesh_level<M: Material>(
+    internal_mesh: &InternalMesh,
+    skeleton_ranges: &[(VertexAttributeId, Range<u64>)],
+    min_distance: f32,
+) -> InternalLodLevel<M> {
     // Make sure all required attributes are in the mesh and the supported attribute list.
     for &required_attribute in M::required_attributes().into_iter() {
         // We can just directly use the internal mesh, as every attribute in the skeleton is also in the mesh.
-        let found_in_mesh = args.internal_mesh.vertex_attribute_ranges.iter().any(|&(id, _)| id == required_attribute);
+        let found_in_mesh = internal_mesh.vertex_attribute_ranges.iter().any(|&(id, _)| id == required_attribute);
 
         // Check that our required attributes are in the supported one.
-        let found_in_supported =
-            args.internal_mesh.vertex_attribute_ranges.iter().any(|&(id, _)| id == required_attribute);
+        let found_in_supported = internal_mesh.vertex_attribute_ranges.iter().any(|&(id, _)| id == required_attribute);
 
         assert!(found_in_mesh);
         assert!(found_in_supported);
@@ -250,36 +512,73 @@ pub(super) fn object_add_callback<M: Material>(_material: &M, args: ObjectAddCal
     let vertex_attribute_start_offsets = M::supported_attributes().map_to_u32(|&supported_attribute| {
         // We first check the skeleton for the attribute's base offset.
         let found_start_offset =
-            args.skeleton_ranges.iter().find_map(|(id, range)| (*id == supported_attribute).then_some(range.start));
+            skeleton_ranges.iter().find_map(|(id, range)| (*id == supported_attribute).then_some(range.start));
 
         if let Some(start_offset) = found_start_offset {
             return start_offset as u32;
         }
 
         // After the skeleton, check the mesh for non-overriden attributes.
-        match args.internal_mesh.get_attribute(&supported_attribute) {
+        match internal_mesh.get_attribute(&supported_attribute) {
             Some(range) => range.start as u32,
             // If the attribute isn't there, push u32::MAX.
             None => u32::MAX,
         }
     });
 
+    let index_range = internal_mesh.index_range.clone();
+
+    InternalLodLevel {
+        min_distance,
+        mesh_bounding_sphere: internal_mesh.bounding_sphere,
+        mesh_aabb: internal_mesh.aabb,
+        first_index: (index_range.start / 4) as u32,
+        index_count: ((index_range.end - index_range.start) / 4) as u32,
+        vertex_attribute_start_offsets,
+    }
+}
+
+pub(super) fn object_add_callback<M: Material>(_material: &M, args: ObjectAddCallbackArgs<'_>) {
+    let lod = match &args.object.mesh_kind {
+        ObjectMeshKind::Lod(group) => Some(InternalLodGroup {
+            levels: group
+                .levels
+                .iter()
+                .zip(&args.lod_meshes)
+                .map(|(level, mesh)| mesh_level::<M>(mesh, &[], level.min_distance))
+                .collect(),
+            hysteresis: group.hysteresis,
+            current: 0,
+        }),
+        _ => None,
+    };
+    // Objects start out with the first level of detail, it is updated when the archetype is evaluated.
+    let level = match &lod {
+        Some(lod) => lod.levels[0],
+        None => mesh_level::<M>(args.internal_mesh, args.skeleton_ranges, 0.0),
+    };
+
     // Transform the bounding sphere from model to world space.
-    let mesh_bounding_sphere = args.internal_mesh.bounding_sphere;
+    let mesh_bounding_sphere = level.mesh_bounding_sphere;
     let bounding_sphere = mesh_bounding_sphere.apply_transform(args.object.transform);
-    let index_range = args.internal_mesh.index_range.clone();
 
     let internal_object = InternalObject::<M> {
         location: bounding_sphere.center.into(),
         mesh_bounding_sphere,
+        mesh_aabb: level.mesh_aabb,
+        visible: true,
+        layer_mask: args.object.layer_mask,
+        lod,
         inner: ShaderObject {
             material_index: args.object.material.idx as u32,
             transform: args.object.transform,
             bounding_sphere,
-            first_index: (index_range.start / 4) as u32,
-            index_count: ((index_range.end - index_range.start) / 4) as u32,
-            vertex_attribute_start_offsets,
+            first_index: level.first_index,
+            index_count: level.index_count,
+            vertex_attribute_start_offsets: level.vertex_attribute_start_offsets,
             enabled: true as u32,
+            flags: args.object.flags.bits(),
+            material_override: MaterialOverride::IDENTITY,
         },
         material_handle: args.object.material,
         mesh_kind: args.object.mesh_kind,
@@ -294,11 +593,40 @@ pub(super) fn object_add_callback<M: Material>(_material: &M, args: ObjectAddCal
     if args.handle.idx >= data_vec.len() {
         data_vec.resize_with((args.handle.idx + 1).next_power_of_two(), || None);
     }
+    archetype.lod_object_count += internal_object.lod.is_some() as usize;
     data_vec[args.handle.idx] = Some(internal_object);
     archetype.object_count += 1;
     archetype.buffer.use_index(args.handle.idx);
 }
 
+fn object_transform<M: Material>(data: &WasmVecAny, idx: usize) -> Mat4 {
+    let data_vec = data.downcast_slice::<Option<InternalObject<M>>>().unwrap();
+
+    data_vec[idx].as_ref().unwrap().inner.transform
+}
+
+fn object_bounds<M: Material>(data: &WasmVecAny, idx: usize) -> Option<Aabb> {
+    let data_vec = data.downcast_slice::<Option<InternalObject<M>>>().unwrap();
+
+    let object = data_vec.get(idx)?.as_ref()?;
+    // Removed objects are kept around disabled until the next evaluation.
+    (object.inner.enabled != 0).then(|| object.mesh_aabb.apply_transform(object.inner.transform))
+}
+
+fn object_snapshot<M: Material>(data: &WasmVecAny, idx: usize) -> Option<ObjectSnapshot> {
+    let data_vec = data.downcast_slice::<Option<InternalObject<M>>>().unwrap();
+
+    let object = data_vec.get(idx)?.as_ref()?;
+    (object.inner.enabled != 0).then(|| ObjectSnapshot {
+        mesh_kind: object.mesh_kind.clone(),
+        material: *object.material_handle,
+        transform: object.inner.transform,
+        visible: object.visible,
+        layer_mask: object.layer_mask,
+        material_override: object.inner.material_override,
+    })
+}
+
 fn set_object_transform<M: Material>(
     data: &mut WasmVecAny,
     buffer: &mut FreelistDerivedBuffer,
@@ -315,6 +643,26 @@ fn set_object_transform<M: Material>(
     buffer.use_index(idx);
 }
 
+fn set_object_visibility<M: Material>(data: &mut WasmVecAny, idx: usize, visible: bool) {
+    let data_vec = data.downcast_slice_mut::<Option<InternalObject<M>>>().unwrap();
+
+    // Only the cpu side culling reads this, so the gpu buffer doesn't need updating.
+    data_vec[idx].as_mut().unwrap().visible = visible;
+}
+
+fn set_object_material_override<M: Material>(
+    data: &mut WasmVecAny,
+    buffer: &mut FreelistDerivedBuffer,
+    idx: usize,
+    material_override: MaterialOverride,
+) {
+    let data_vec = data.downcast_slice_mut::<Option<InternalObject<M>>>().unwrap();
+
+    data_vec[idx].as_mut().unwrap().inner.material_override = material_override;
+
+    buffer.use_index(idx);
+}
+
 fn duplicate_object<M: Material>(data: &WasmVecAny, idx: usize, change: ObjectChange) -> Object {
     let data_vec = data.downcast_slice::<Option<InternalObject<M>>>().unwrap();
 
@@ -324,6 +672,8 @@ fn duplicate_object<M: Material>(data: &WasmVecAny, idx: usize, change: ObjectCh
         mesh_kind: change.mesh_kind.unwrap_or_else(|| src_obj.mesh_kind.clone()),
         material: change.material.unwrap_or_else(|| src_obj.material_handle.clone()),
         transform: change.transform.unwrap_or(src_obj.inner.transform),
+        flags: change.flags.unwrap_or_else(|| ObjectFlags::from_bits_truncate(src_obj.inner.flags)),
+        layer_mask: change.layer_mask.unwrap_or(src_obj.layer_mask),
     }
 }
 
@@ -347,6 +697,7 @@ fn evaluate<M: Material>(
     encoder: &mut CommandEncoder,
     scatter: &ScatterCopy,
     deferred_removals: &[RawObjectHandle],
+    camera_location: Vec3A,
 ) {
     let data_vec = archetype.data_vec.downcast_slice_mut::<Option<InternalObject<M>>>().unwrap();
 
@@ -355,10 +706,50 @@ fn evaluate<M: Material>(
         // so if we have it, we can be sure it's ours.
         let removed_obj = data_vec[removal.idx].take();
 
-        if removed_obj.is_some() {
+        if let Some(removed_obj) = removed_obj {
             archetype.object_count -= 1;
+            archetype.lod_object_count -= removed_obj.lod.is_some() as usize;
+        }
+    }
+
+    if archetype.lod_object_count != 0 {
+        profiling::scope!("LOD Selection");
+        for (idx, object) in data_vec.iter_mut().enumerate() {
+            if let Some(object) = object {
+                if object.update_lod(camera_location) {
+                    archetype.buffer.use_index(idx);
+                }
+            }
         }
     }
 
     archetype.buffer.apply(device, encoder, scatter, |idx| data_vec[idx].as_ref().map(|o| o.inner).unwrap_or_default())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::select_lod_level;
+
+    const THRESHOLDS: [f32; 3] = [0.0, 10.0, 20.0];
+
+    fn select(current: usize, distance: f32) -> usize {
+        select_lod_level(THRESHOLDS.len(), |level| THRESHOLDS[level], current, distance, 0.1)
+    }
+
+    #[test]
+    fn lod_thresholds() {
+        assert_eq!(select(0, 5.0), 0);
+        assert_eq!(select(0, 15.0), 1);
+        assert_eq!(select(0, 100.0), 2);
+        assert_eq!(select(2, 5.0), 0);
+    }
+
+    #[test]
+    fn lod_hysteresis() {
+        // Within 10% of the threshold, the current level is kept.
+        assert_eq!(select(0, 10.5), 0);
+        assert_eq!(select(1, 9.5), 1);
+        assert_eq!(select(0, 11.5), 1);
+        assert_eq!(select(1, 8.5), 0);
+    }
+}
@@ -1,9 +1,12 @@
 use encase::{ArrayLength, ShaderType};
-use glam::{Vec3, Vec4};
-use rend3_types::{PointLight, PointLightChange, RawPointLightHandle};
+use glam::{Mat4, UVec2, Vec2, Vec3, Vec4};
+use rend3_types::{
+    Camera, CameraProjection, Handedness, PointLight, PointLightChange, RawPointLightHandle, ALL_LAYERS,
+};
 use wgpu::{BufferUsages, Device, ShaderStages};
 
 use crate::{
+    managers::{CameraState, ShadowDesc, ShadowSource},
     util::{
         bind_merge::{BindGroupBuilder, BindGroupLayoutBuilder},
         buffer::WrappedPotBuffer,
@@ -23,8 +26,35 @@ struct ShaderPointLight {
     pub position: Vec4,
     pub color: Vec3,
     pub radius: f32,
+    /// View/Projection of each face of the shadow cube, in [`POINT_SHADOW_FACES`] order.
+    pub shadow_view_proj: [Mat4; 6],
+    /// [0, 1] offset (xy) and size (zw) of each face in the shadow atlas.
+    pub shadow_atlas_rects: [Vec4; 6],
+    /// 1 / resolution of whole shadow map
+    pub inv_resolution: Vec2,
+    /// Non-zero if the light casts shadows.
+    pub shadowed: u32,
+    /// Offset added to the depth of fragments before the shadow comparison.
+    pub shadow_bias: f32,
+    /// World space distance fragments are moved along their normal before sampling the shadow map.
+    pub shadow_normal_offset: f32,
+    /// Offset added to the bias, scaled by the tangent of the angle between the surface and the light.
+    pub shadow_slope_bias: f32,
 }
 
+/// Look direction and up vector of each face of a point light's shadow cube.
+const POINT_SHADOW_FACES: [(Vec3, Vec3); 6] = [
+    (Vec3::X, Vec3::Y),
+    (Vec3::NEG_X, Vec3::Y),
+    (Vec3::Y, Vec3::Z),
+    (Vec3::NEG_Y, Vec3::NEG_Z),
+    (Vec3::Z, Vec3::Y),
+    (Vec3::NEG_Z, Vec3::Y),
+];
+
+/// Near plane of the shadow cameras as a fraction of the light's radius.
+const POINT_SHADOW_NEAR_FRACTION: f32 = 0.001;
+
 /// Manages point lights and their associated shadow maps.
 pub struct PointLightManager {
     data: Vec<Option<PointLight>>,
@@ -55,17 +85,87 @@ impl PointLightManager {
         self.data[handle.idx].take().unwrap();
     }
 
-    pub fn evaluate(&mut self, renderer: &Renderer) {
+    /// Shadow maps needed by all point lights which cast shadows, six per light.
+    pub(super) fn shadow_maps(&self) -> impl Iterator<Item = (ShadowSource, u16)> + '_ {
+        self.data.iter().enumerate().flat_map(|(idx, light)| {
+            let resolution = light.as_ref().map_or(0, |light| light.resolution);
+            let faces = if resolution == 0 { 0..0 } else { 0..POINT_SHADOW_FACES.len() as u8 };
+            faces.map(move |face| (ShadowSource::PointFace { handle: RawPointLightHandle::new(idx), face }, resolution))
+        })
+    }
+
+    /// Camera which renders the given face of the light's shadow cube.
+    pub(super) fn shadow_camera(&self, handle: RawPointLightHandle, face: u8, handedness: Handedness) -> CameraState {
+        let light = self.data[handle.idx].as_ref().unwrap();
+        let (direction, up) = POINT_SHADOW_FACES[face as usize];
+
+        let look_at = match handedness {
+            Handedness::Left => Mat4::look_at_lh,
+            Handedness::Right => Mat4::look_at_rh,
+        };
+
+        CameraState::new(
+            Camera {
+                projection: CameraProjection::Perspective {
+                    vfov: 90.0,
+                    near: light.radius * POINT_SHADOW_NEAR_FRACTION,
+                },
+                view: look_at(light.position, light.position + direction, up),
+                layer_mask: ALL_LAYERS,
+            },
+            handedness,
+            None,
+        )
+    }
+
+    pub fn evaluate(&mut self, renderer: &Renderer, shadow_target_size: UVec2, shadows: &[ShadowDesc]) {
+        profiling::scope!("PointLightManager::evaluate");
+
+        let shadow_target_size_f32 = shadow_target_size.as_vec2();
+
+        // Faces of each light's shadow cube, indexed by handle. Empty if no light casts shadows.
+        let mut faces: Vec<[Option<&ShadowDesc>; 6]> = Vec::new();
+        for desc in shadows {
+            if let ShadowSource::PointFace { handle, face } = desc.map.handle {
+                if handle.idx >= faces.len() {
+                    faces.resize(handle.idx + 1, [None; 6]);
+                }
+                faces[handle.idx][face as usize] = Some(desc);
+            }
+        }
+
         let buffer = ShaderPointLightBuffer {
             count: ArrayLength,
             array: self
                 .data
                 .iter()
-                .flatten()
-                .map(|light| ShaderPointLight {
-                    position: light.position.extend(1.0),
-                    color: light.color * light.intensity,
-                    radius: light.radius,
+                .enumerate()
+                .filter_map(|(idx, light)| Some((faces.get(idx), light.as_ref()?)))
+                .map(|(faces, light)| {
+                    let mut shader_light = ShaderPointLight {
+                        position: light.position.extend(1.0),
+                        color: light.color * light.intensity,
+                        radius: light.radius,
+                        shadow_view_proj: [Mat4::IDENTITY; 6],
+                        shadow_atlas_rects: [Vec4::ZERO; 6],
+                        inv_resolution: 1.0 / shadow_target_size_f32,
+                        shadowed: 0,
+                        shadow_bias: light.shadow_bias,
+                        shadow_normal_offset: light.shadow_normal_offset,
+                        shadow_slope_bias: light.shadow_slope_bias,
+                    };
+
+                    if let Some(faces) = faces.filter(|faces| faces.iter().all(Option::is_some)) {
+                        shader_light.shadowed = 1;
+                        for (face, desc) in faces.iter().flatten().enumerate() {
+                            let offset = desc.map.offset.as_vec2() / shadow_target_size_f32;
+                            let size = desc.map.size as f32 / shadow_target_size_f32;
+                            shader_light.shadow_view_proj[face] = desc.camera.view_proj();
+                            shader_light.shadow_atlas_rects[face] = Vec4::new(offset.x, offset.y, size.x, size.y);
+                        }
+                    }
+
+                    shader_light
                 })
                 .collect(),
         };
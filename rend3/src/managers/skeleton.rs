@@ -10,7 +10,7 @@ use thiserror::Error;
 use wgpu::Device;
 
 use crate::{
-    managers::{MeshCreationError, MeshManager},
+    managers::{InternalMorphTargets, MeshCreationError, MeshManager},
     util::iter::ExactSizerIterator,
 };
 
@@ -20,10 +20,15 @@ pub struct InternalSkeleton {
     /// A handle to the mesh this skeleton deforms.
     pub mesh_handle: MeshHandle,
     /// The list of per-joint transformation matrices that will be applied to
-    /// vertices.
+    /// vertices. Empty if the mesh has no joints.
     pub joint_matrices: Vec<Mat4>,
+    /// The weight of every morph target of the mesh.
+    pub morph_weights: Vec<f32>,
+    /// Location of the morph target offsets of the mesh.
+    pub morph_targets: Option<InternalMorphTargets>,
     /// There are 5 different ranges we need to store here:
-    /// Position, Normals, Tangent, Joint Index, Joint Weight
+    /// Position, Normals, Tangent, Joint Index, Joint Weight. The joint ranges
+    /// are missing if the mesh is only deformed by morph targets.
     pub source_attribute_ranges: ArrayVec<(VertexAttributeId, Range<u64>), 5>,
     /// There are three attributes that we can possibly override here:
     /// Position, Normals, and Tangent
@@ -36,7 +41,7 @@ pub struct InternalSkeleton {
 pub enum SkeletonCreationError {
     #[error("Failed to create needed resources in the mesh manager")]
     MeshFailure(#[from] MeshCreationError),
-    #[error("Mesh must have joint indices to be used in a skeleton")]
+    #[error("Mesh must have joint indices or morph targets to be used in a skeleton")]
     MissingAttributesJointIndices,
     #[error("Mesh must have joint weights to be used in a skeleton")]
     MissingAttributesJointWeights,
@@ -56,12 +61,14 @@ pub struct SkeletonManager {
     skeleton_count: usize,
     /// The number of joints of all the skeletons in this manager
     global_joint_count: usize,
+    /// The number of morph weights of all the skeletons in this manager
+    global_morph_weight_count: usize,
 }
 impl SkeletonManager {
     pub fn new() -> Self {
         profiling::scope!("SkeletonManager::new");
 
-        Self { data: Vec::new(), skeleton_count: 0, global_joint_count: 0 }
+        Self { data: Vec::new(), skeleton_count: 0, global_joint_count: 0, global_morph_weight_count: 0 }
     }
 
     pub fn validate_skeleton(
@@ -70,15 +77,24 @@ impl SkeletonManager {
         skeleton: Skeleton,
     ) -> Result<InternalSkeleton, SkeletonCreationError> {
         let internal_mesh = &mesh_manager.lock_internal_data()[skeleton.mesh.get_raw()];
-        let required_joint_count =
-            internal_mesh.required_joint_count.ok_or(SkeletonCreationError::MissingAttributesJointIndices)?;
 
-        let joint_weight_range = internal_mesh
-            .get_attribute(&VERTEX_ATTRIBUTE_JOINT_WEIGHTS)
-            .ok_or(SkeletonCreationError::MissingAttributesJointWeights)?;
-        let joint_indices_range = internal_mesh
-            .get_attribute(&VERTEX_ATTRIBUTE_JOINT_INDICES)
-            .ok_or(SkeletonCreationError::MissingAttributesJointIndices)?;
+        let mut source_attribute_ranges: ArrayVec<_, 5> = ArrayVec::new();
+
+        // Meshes with morph targets can be deformed without joints.
+        let required_joint_count = match internal_mesh.required_joint_count {
+            Some(count) => count,
+            None if internal_mesh.morph_targets.is_some() => 0,
+            None => return Err(SkeletonCreationError::MissingAttributesJointIndices),
+        };
+
+        if let Some(joint_indices_range) = internal_mesh.get_attribute(&VERTEX_ATTRIBUTE_JOINT_INDICES) {
+            let joint_weight_range = internal_mesh
+                .get_attribute(&VERTEX_ATTRIBUTE_JOINT_WEIGHTS)
+                .ok_or(SkeletonCreationError::MissingAttributesJointWeights)?;
+            source_attribute_ranges.push((*VERTEX_ATTRIBUTE_JOINT_WEIGHTS.id(), joint_weight_range));
+            source_attribute_ranges.push((*VERTEX_ATTRIBUTE_JOINT_INDICES.id(), joint_indices_range));
+        }
+        let joint_attribute_count = source_attribute_ranges.len();
 
         // Converts the following assert to an error
         if required_joint_count as usize > skeleton.joint_matrices.len() {
@@ -90,10 +106,6 @@ impl SkeletonManager {
 
         let overridden_attributes = [&VERTEX_ATTRIBUTE_POSITION, &VERTEX_ATTRIBUTE_NORMAL, &VERTEX_ATTRIBUTE_TANGENT];
 
-        let mut source_attribute_ranges: ArrayVec<_, 5> = ArrayVec::new();
-        source_attribute_ranges.push((*VERTEX_ATTRIBUTE_JOINT_WEIGHTS.id(), joint_weight_range));
-        source_attribute_ranges.push((*VERTEX_ATTRIBUTE_JOINT_INDICES.id(), joint_indices_range));
-
         let mut overridden_attribute_ranges: ArrayVec<_, 3> = ArrayVec::new();
         for attribute in overridden_attributes {
             let original_range = match internal_mesh.get_attribute(attribute) {
@@ -106,8 +118,8 @@ impl SkeletonManager {
         // We split this for loop into two parts so that because we need &mut on the mesh manager
         // the original loop needs & on the mesh manager to call get_attribute.
         //
-        // We skip the first two as those are always the joint* attributes.
-        for (attribute_id, original_range) in &source_attribute_ranges[2..] {
+        // We skip the joint* attributes, which always come first.
+        for (attribute_id, original_range) in &source_attribute_ranges[joint_attribute_count..] {
             let skeleton_range = mesh_manager.allocate_range(device, original_range.end - original_range.start)?;
             overridden_attribute_ranges.push((*attribute_id, skeleton_range));
         }
@@ -116,8 +128,14 @@ impl SkeletonManager {
         let mut joint_matrices = skeleton.joint_matrices;
         joint_matrices.truncate(required_joint_count as _);
 
+        let morph_targets = internal_mesh.morph_targets.clone();
+        let mut morph_weights = skeleton.morph_weights;
+        morph_weights.resize(morph_targets.as_ref().map_or(0, |targets| targets.count as usize), 0.0);
+
         Ok(InternalSkeleton {
             joint_matrices,
+            morph_weights,
+            morph_targets,
             mesh_handle: skeleton.mesh,
             source_attribute_ranges,
             overridden_attribute_ranges,
@@ -127,6 +145,7 @@ impl SkeletonManager {
 
     pub fn add(&mut self, handle: RawSkeletonHandle, internal: InternalSkeleton) {
         self.global_joint_count += internal.joint_matrices.len();
+        self.global_morph_weight_count += internal.morph_weights.len();
 
         if handle.idx >= self.data.len() {
             self.data.resize_with(handle.idx + 1, || None);
@@ -139,6 +158,7 @@ impl SkeletonManager {
     pub fn remove(&mut self, mesh_manager: &MeshManager, handle: RawSkeletonHandle) {
         let skeleton = self.data[handle.idx].take().unwrap();
         self.global_joint_count -= skeleton.joint_matrices.len();
+        self.global_morph_weight_count -= skeleton.morph_weights.len();
 
         // Free the owned regions of the mesh data buffer
         for (_, range) in skeleton.overridden_attribute_ranges {
@@ -162,6 +182,13 @@ impl SkeletonManager {
         skeleton.joint_matrices = joint_matrices;
     }
 
+    pub fn set_morph_weights(&mut self, handle: RawSkeletonHandle, mut morph_weights: Vec<f32>) {
+        let skeleton = self.data[handle.idx].as_mut().unwrap();
+        // Missing weights are 0, extra ones have no morph target to weigh.
+        morph_weights.resize(skeleton.morph_weights.len(), 0.0);
+        skeleton.morph_weights = morph_weights;
+    }
+
     pub fn internal_data(&self, handle: RawSkeletonHandle) -> &InternalSkeleton {
         self.data[handle.idx].as_ref().unwrap()
     }
@@ -174,6 +201,11 @@ impl SkeletonManager {
     pub fn global_joint_count(&self) -> usize {
         self.global_joint_count
     }
+
+    /// Get the skeleton manager's global morph weight count.
+    pub fn global_morph_weight_count(&self) -> usize {
+        self.global_morph_weight_count
+    }
 }
 
 impl Default for SkeletonManager {
@@ -0,0 +1,189 @@
+use encase::{ArrayLength, ShaderType};
+use glam::{Mat4, UVec2, Vec2, Vec3, Vec4};
+use rend3_types::{Camera, CameraProjection, Handedness, RawSpotLightHandle, SpotLight, SpotLightChange, ALL_LAYERS};
+use wgpu::{BufferUsages, Device, ShaderStages};
+
+use crate::{
+    managers::{CameraState, ShadowDesc, ShadowSource},
+    util::{
+        bind_merge::{BindGroupBuilder, BindGroupLayoutBuilder},
+        buffer::WrappedPotBuffer,
+    },
+    Renderer,
+};
+
+#[derive(Debug, Clone, ShaderType)]
+struct ShaderSpotLightBuffer {
+    count: ArrayLength,
+    #[size(runtime)]
+    array: Vec<ShaderSpotLight>,
+}
+
+#[derive(Debug, Copy, Clone, ShaderType)]
+struct ShaderSpotLight {
+    pub position: Vec4,
+    pub direction: Vec3,
+    pub range: f32,
+    pub color: Vec3,
+    /// Cosine of the inner cone angle.
+    pub cos_inner: f32,
+    /// View/Projection of the shadow camera.
+    pub shadow_view_proj: Mat4,
+    /// [0, 1] offset (xy) and size (zw) of the shadow map in the atlas.
+    pub shadow_atlas_rect: Vec4,
+    /// 1 / resolution of whole shadow map
+    pub inv_resolution: Vec2,
+    /// Cosine of the outer cone angle.
+    pub cos_outer: f32,
+    /// Non-zero if the light casts shadows.
+    pub shadowed: u32,
+    /// Offset added to the depth of fragments before the shadow comparison.
+    pub shadow_bias: f32,
+    /// World space distance fragments are moved along their normal before sampling the shadow map.
+    pub shadow_normal_offset: f32,
+    /// Offset added to the bias, scaled by the tangent of the angle between the surface and the light.
+    pub shadow_slope_bias: f32,
+}
+
+/// Near plane of the shadow camera as a fraction of the light's range.
+const SPOT_SHADOW_NEAR_FRACTION: f32 = 0.001;
+
+/// Widest field of view (in degrees) a shadow camera can have.
+const MAX_SPOT_SHADOW_FOV: f32 = 179.0;
+
+/// Manages spot lights and their associated shadow maps.
+pub struct SpotLightManager {
+    data: Vec<Option<SpotLight>>,
+    data_buffer: WrappedPotBuffer<ShaderSpotLightBuffer>,
+}
+
+impl SpotLightManager {
+    pub fn new(device: &Device) -> Self {
+        Self {
+            data: Vec::new(),
+            data_buffer: WrappedPotBuffer::new(device, BufferUsages::STORAGE, "spot light buffer"),
+        }
+    }
+
+    pub fn add(&mut self, handle: RawSpotLightHandle, light: SpotLight) {
+        if handle.idx >= self.data.len() {
+            self.data.resize(handle.idx + 1, None);
+        }
+
+        self.data[handle.idx] = Some(light);
+    }
+
+    pub fn update(&mut self, handle: RawSpotLightHandle, change: SpotLightChange) {
+        self.data[handle.idx].as_mut().unwrap().update_from_changes(change);
+    }
+
+    pub fn remove(&mut self, handle: RawSpotLightHandle) {
+        self.data[handle.idx].take().unwrap();
+    }
+
+    /// Shadow maps needed by all spot lights which cast shadows.
+    pub(super) fn shadow_maps(&self) -> impl Iterator<Item = (ShadowSource, u16)> + '_ {
+        self.data.iter().enumerate().filter_map(|(idx, light)| {
+            let resolution = light.as_ref()?.resolution;
+            (resolution != 0).then(|| (ShadowSource::Spot(RawSpotLightHandle::new(idx)), resolution))
+        })
+    }
+
+    /// Camera which renders the light's shadow map. Its frustum tightly bounds the outer cone.
+    pub(super) fn shadow_camera(&self, handle: RawSpotLightHandle, handedness: Handedness) -> CameraState {
+        let light = self.data[handle.idx].as_ref().unwrap();
+        let direction = light.direction.normalize();
+
+        let look_at = match handedness {
+            Handedness::Left => Mat4::look_at_lh,
+            Handedness::Right => Mat4::look_at_rh,
+        };
+        // The up vector must not be parallel to the direction.
+        let up = if direction.y.abs() > 0.99 { Vec3::Z } else { Vec3::Y };
+
+        CameraState::new(
+            Camera {
+                projection: CameraProjection::Perspective {
+                    vfov: (light.outer_angle * 2.0).min(MAX_SPOT_SHADOW_FOV),
+                    near: light.range * SPOT_SHADOW_NEAR_FRACTION,
+                },
+                view: look_at(light.position, light.position + direction, up),
+                layer_mask: ALL_LAYERS,
+            },
+            handedness,
+            None,
+        )
+    }
+
+    pub fn evaluate(&mut self, renderer: &Renderer, shadow_target_size: UVec2, shadows: &[ShadowDesc]) {
+        profiling::scope!("SpotLightManager::evaluate");
+
+        let shadow_target_size_f32 = shadow_target_size.as_vec2();
+
+        // Shadow map of each light, indexed by handle. Empty if no light casts shadows.
+        let mut light_shadows: Vec<Option<&ShadowDesc>> = Vec::new();
+        for desc in shadows {
+            if let ShadowSource::Spot(handle) = desc.map.handle {
+                if handle.idx >= light_shadows.len() {
+                    light_shadows.resize(handle.idx + 1, None);
+                }
+                light_shadows[handle.idx] = Some(desc);
+            }
+        }
+
+        let buffer = ShaderSpotLightBuffer {
+            count: ArrayLength,
+            array: self
+                .data
+                .iter()
+                .enumerate()
+                .filter_map(|(idx, light)| Some((light_shadows.get(idx).copied().flatten(), light.as_ref()?)))
+                .map(|(shadow, light)| {
+                    let mut shader_light = ShaderSpotLight {
+                        position: light.position.extend(1.0),
+                        direction: light.direction.normalize(),
+                        range: light.range,
+                        color: light.color * light.intensity,
+                        cos_inner: light.inner_angle.to_radians().cos(),
+                        shadow_view_proj: Mat4::IDENTITY,
+                        shadow_atlas_rect: Vec4::ZERO,
+                        inv_resolution: 1.0 / shadow_target_size_f32,
+                        cos_outer: light.outer_angle.to_radians().cos(),
+                        shadowed: 0,
+                        shadow_bias: light.shadow_bias,
+                        shadow_normal_offset: light.shadow_normal_offset,
+                        shadow_slope_bias: light.shadow_slope_bias,
+                    };
+
+                    if let Some(desc) = shadow {
+                        let offset = desc.map.offset.as_vec2() / shadow_target_size_f32;
+                        let size = desc.map.size as f32 / shadow_target_size_f32;
+                        shader_light.shadowed = 1;
+                        shader_light.shadow_view_proj = desc.camera.view_proj();
+                        shader_light.shadow_atlas_rect = Vec4::new(offset.x, offset.y, size.x, size.y);
+                    }
+
+                    shader_light
+                })
+                .collect(),
+        };
+
+        self.data_buffer.write_to_buffer(&renderer.device, &renderer.queue, &buffer);
+    }
+
+    pub fn add_to_bgl(bglb: &mut BindGroupLayoutBuilder) {
+        bglb.append(
+            ShaderStages::FRAGMENT,
+            wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: true },
+                has_dynamic_offset: false,
+                min_binding_size: Some(ShaderSpotLightBuffer::min_size()),
+            },
+            None,
+        );
+    }
+
+    pub fn add_to_bg<'a>(&'a self, bgb: &mut BindGroupBuilder<'a>) {
+        bgb.append_buffer(&self.data_buffer);
+    }
+}
@@ -5,8 +5,9 @@ use thiserror::Error;
 use wgpu::{
     util::DeviceExt, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
     BindGroupLayoutEntry, BindingResource, BindingType, CommandBuffer, CommandEncoder, CommandEncoderDescriptor,
-    Device, Extent3d, Features, ImageCopyTexture, ImageDataLayout, Origin3d, ShaderStages, Texture, TextureAspect,
-    TextureDescriptor, TextureDimension, TextureSampleType, TextureView, TextureViewDescriptor, TextureViewDimension,
+    Device, Extent3d, Features, ImageCopyTexture, ImageDataLayout, Origin3d, Queue, ShaderStages, Texture,
+    TextureAspect, TextureDescriptor, TextureDimension, TextureSampleType, TextureView, TextureViewDescriptor,
+    TextureViewDimension,
 };
 
 use crate::{
@@ -21,16 +22,123 @@ pub const STARTING_2D_TEXTURES: usize = 1 << 8;
 /// When using the GpuDriven profile, we start the Cubemap texture manager with a bind group
 /// with this many textures.
 pub const STARTING_CUBE_TEXTURES: usize = 1 << 3;
+/// When using the GpuDriven profile, we start the 3D texture manager with a bind group
+/// with this many textures.
+pub const STARTING_3D_TEXTURES: usize = 1 << 3;
 /// Largest amount of supported textures per type
 pub const MAX_TEXTURE_COUNT: u32 = 1 << 17;
+/// Amount of bytes of streaming textures uploaded every frame.
+pub const TEXTURE_STREAMING_BUDGET: u64 = 1 << 22;
 
 /// Internal representation of a Texture.
 pub struct InternalTexture {
-    pub texture: Texture,
+    pub texture: Arc<Texture>,
     pub view: TextureView,
     pub desc: TextureDescriptor<'static>,
 }
 
+/// Texture whose data is uploaded over several frames by
+/// [`TextureManager::stream`].
+pub struct TextureStream {
+    /// Position of the stream in the order streams were added.
+    id: u64,
+    texture: InternalTexture,
+    data: Vec<u8>,
+    generate_mipmaps: bool,
+    cursor: StreamCursor,
+}
+
+impl TextureStream {
+    /// Uploads rows of the texture until `budget` bytes are used up, always
+    /// making progress while there is some budget left. Returns true once all
+    /// data is uploaded.
+    fn upload(&mut self, queue: &Queue, budget: &mut u64) -> bool {
+        let desc = &self.texture.desc;
+        // Generated mipmaps are rendered from the first mip, so only that is uploaded.
+        let uploaded_mips = if self.generate_mipmaps { 1 } else { desc.mip_level_count };
+        let (_, block_height) = desc.format.block_dimensions();
+
+        while let Some(copy) = self.cursor.next_copy(desc, uploaded_mips, budget) {
+            let width = desc.mip_level_size(copy.mip).unwrap().physical_size(desc.format).width;
+            queue.write_texture(
+                ImageCopyTexture {
+                    texture: &self.texture.texture,
+                    mip_level: copy.mip,
+                    origin: Origin3d { x: 0, y: copy.row * block_height, z: 0 },
+                    aspect: TextureAspect::All,
+                },
+                &self.data[copy.offset..copy.offset + copy.len()],
+                ImageDataLayout { offset: 0, bytes_per_row: Some(copy.bytes_per_row), rows_per_image: None },
+                Extent3d { width, height: copy.rows * block_height, depth_or_array_layers: 1 },
+            );
+        }
+
+        self.cursor.mip >= uploaded_mips
+    }
+}
+
+/// Position of the next data of a [`TextureStream`] to upload.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct StreamCursor {
+    /// Mip the next rows are uploaded to.
+    mip: u32,
+    /// Next row of blocks of `mip` to upload.
+    row: u32,
+    /// Offset of that row in the texture's data.
+    offset: usize,
+}
+
+/// Rows of blocks of a single mip, uploaded with one copy.
+#[derive(Debug, PartialEq, Eq)]
+struct StreamCopy {
+    mip: u32,
+    /// First row of blocks copied.
+    row: u32,
+    rows: u32,
+    bytes_per_row: u32,
+    /// Offset of the first row in the texture's data.
+    offset: usize,
+}
+
+impl StreamCopy {
+    fn len(&self) -> usize {
+        (self.rows * self.bytes_per_row) as usize
+    }
+}
+
+impl StreamCursor {
+    /// Returns the next rows of the first `uploaded_mips` mips which fit in
+    /// `budget`, and moves past them, taking their size out of the budget.
+    /// Returns `None` once all mips are uploaded or the budget is used up.
+    fn next_copy(&mut self, desc: &TextureDescriptor<'_>, uploaded_mips: u32, budget: &mut u64) -> Option<StreamCopy> {
+        if self.mip >= uploaded_mips {
+            return None;
+        }
+
+        let (block_width, block_height) = desc.format.block_dimensions();
+        let block_size = desc.format.block_copy_size(None).unwrap();
+        let size = desc.mip_level_size(self.mip).unwrap().physical_size(desc.format);
+        let bytes_per_row = size.width / block_width * block_size;
+        let mip_rows = size.height / block_height;
+
+        let rows = stream_rows(bytes_per_row as u64, mip_rows - self.row, *budget);
+        if rows == 0 {
+            return None;
+        }
+
+        let copy = StreamCopy { mip: self.mip, row: self.row, rows, bytes_per_row, offset: self.offset };
+        *budget = budget.saturating_sub(copy.len() as u64);
+        self.offset += copy.len();
+        self.row += rows;
+        if self.row == mip_rows {
+            self.mip += 1;
+            self.row = 0;
+        }
+
+        Some(copy)
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum TextureCreationError {
     #[error("Failed to allocate texture")]
@@ -43,6 +151,14 @@ pub enum TextureCreationError {
     TextureFormatNotFilterable { format: TextureFormat, sample_type: TextureSampleType },
     #[error("Textures formats must be sample-able as floating point. {format:?} has sample type {sample_type:?}.")]
     TextureFormatNotFloat { format: TextureFormat, sample_type: Option<TextureSampleType> },
+    #[error("Texture format {format:?} requires device features {missing:?} which are not enabled")]
+    TextureFormatNotSupported { format: TextureFormat, missing: Features },
+    #[error("Texture format {format:?} can not be rendered to")]
+    TextureFormatNotRenderable { format: TextureFormat },
+    #[error("Texture size {size:?} must be at least 1 and at most {max} in every dimension")]
+    TextureSizeInvalid { size: Extent3d, max: u32 },
+    #[error("Texture data is {actual} bytes, but its size, format and mips need at least {expected} bytes")]
+    TextureDataSizeMismatch { expected: u64, actual: u64 },
     #[error("Mipmap creation failed")]
     MipmapCreationFailed(#[from] MipmapGenerationError),
 }
@@ -64,6 +180,13 @@ pub struct TextureManager<T> {
     null_view: TextureView,
 
     data: Vec<Option<InternalTexture>>,
+    /// Textures still being uploaded, in the order they were added.
+    streams: Vec<(RawResourceHandle<T>, TextureStream)>,
+    /// Id of the next stream added.
+    next_stream_id: u64,
+    /// Fences waiting for the streams added before them, with the id of the
+    /// first stream added after them.
+    upload_fences: Vec<(u64, flume::Sender<()>)>,
 
     dimension: TextureViewDimension,
 
@@ -90,6 +213,9 @@ impl<T: 'static> TextureManager<T> {
             group_dirty: profile.into_data(|| (), || false),
             null_view,
             data,
+            streams: Vec::new(),
+            next_stream_id: 0,
+            upload_fences: Vec::new(),
             dimension,
             _phantom: PhantomData,
         }
@@ -100,39 +226,11 @@ impl<T: 'static> TextureManager<T> {
         texture: crate::types::Texture,
         cube: bool,
     ) -> Result<(Option<CommandBuffer>, InternalTexture), TextureCreationError> {
-        validate_texture_format(texture.format, renderer.features)?;
-
-        let (block_x, block_y) = texture.format.block_dimensions();
-        let size = Extent3d {
-            width: round_up(texture.size.x, block_x),
-            height: round_up(texture.size.y, block_y),
-            depth_or_array_layers: match cube {
-                true => 6,
-                false => 1,
-            },
-        };
+        let (desc, generate_mipmaps) = texture_desc(renderer, &texture, cube)?;
+        let size = desc.size;
 
-        let mip_level_count = match texture.mip_count {
-            MipmapCount::Specific(v) => v.get(),
-            MipmapCount::Maximum => size.max_mips(match cube {
-                true => wgpu::TextureDimension::D3,
-                false => wgpu::TextureDimension::D2,
-            }),
-        };
-
-        let desc = TextureDescriptor {
-            label: None,
-            size,
-            mip_level_count,
-            sample_count: 1,
-            dimension: TextureDimension::D2,
-            format: texture.format,
-            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_SRC | TextureUsages::COPY_DST,
-            view_formats: &[],
-        };
-
-        let (buffer, tex) = match texture.mip_source {
-            MipmapSource::Uploaded => {
+        let (buffer, tex) = match generate_mipmaps {
+            false => {
                 let scope = AllocationErrorScope::new(&renderer.device);
                 let texture = renderer.device.create_texture_with_data(
                     &renderer.queue,
@@ -143,7 +241,7 @@ impl<T: 'static> TextureManager<T> {
                 scope.end().map_err(TextureCreationError::TextureAllocationFailed)?;
                 (None, texture)
             }
-            MipmapSource::Generated => {
+            true => {
                 assert!(!cube, "Cannot generate mipmaps from cubemaps currently");
 
                 let desc = TextureDescriptor { usage: desc.usage | TextureUsages::RENDER_ATTACHMENT, ..desc };
@@ -192,7 +290,85 @@ impl<T: 'static> TextureManager<T> {
         });
         scope.end().map_err(TextureCreationError::TextureViewCreationFailed)?;
 
-        Ok((buffer, InternalTexture { texture: tex, view, desc }))
+        Ok((buffer, InternalTexture { texture: Arc::new(tex), view, desc }))
+    }
+
+    /// Creates a 2D texture without uploading its data. The data is uploaded
+    /// by [`Self::stream`] once the stream is added with [`Self::fill_streaming`].
+    pub fn add_streaming(
+        renderer: &Renderer,
+        texture: crate::types::Texture,
+    ) -> Result<TextureStream, TextureCreationError> {
+        let (mut desc, generate_mipmaps) = texture_desc(renderer, &texture, false)?;
+        if generate_mipmaps {
+            desc.usage |= TextureUsages::RENDER_ATTACHMENT;
+        }
+
+        let scope = AllocationErrorScope::new(&renderer.device);
+        let tex = renderer.device.create_texture(&desc);
+        scope.end().map_err(TextureCreationError::TextureAllocationFailed)?;
+
+        let scope = AllocationErrorScope::new(&renderer.device);
+        let view =
+            tex.create_view(&TextureViewDescriptor { dimension: Some(TextureViewDimension::D2), ..Default::default() });
+        scope.end().map_err(TextureCreationError::TextureViewCreationFailed)?;
+
+        Ok(TextureStream {
+            id: 0,
+            texture: InternalTexture { texture: Arc::new(tex), view, desc },
+            data: texture.data,
+            generate_mipmaps,
+            cursor: StreamCursor::default(),
+        })
+    }
+
+    pub fn add_3d(
+        renderer: &Renderer,
+        texture: crate::types::Texture3D,
+    ) -> Result<InternalTexture, TextureCreationError> {
+        validate_texture_format(texture.format, renderer.features)?;
+
+        let (block_x, block_y) = texture.format.block_dimensions();
+        let size = Extent3d {
+            width: round_up(texture.size.x, block_x),
+            height: round_up(texture.size.y, block_y),
+            depth_or_array_layers: texture.size.z,
+        };
+        validate_texture_size(size, renderer.limits.max_texture_dimension_3d)?;
+
+        let mip_level_count = match texture.mip_count {
+            MipmapCount::Specific(v) => v.get(),
+            MipmapCount::Maximum => size.max_mips(TextureDimension::D3),
+        };
+
+        let desc = TextureDescriptor {
+            label: None,
+            size,
+            mip_level_count,
+            sample_count: 1,
+            dimension: TextureDimension::D3,
+            format: texture.format,
+            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_SRC | TextureUsages::COPY_DST,
+            view_formats: &[],
+        };
+
+        validate_texture_data_size(&desc, mip_level_count, &texture.data)?;
+
+        let scope = AllocationErrorScope::new(&renderer.device);
+        let tex = renderer.device.create_texture_with_data(
+            &renderer.queue,
+            &desc,
+            wgpu::util::TextureDataOrder::MipMajor,
+            &texture.data,
+        );
+        scope.end().map_err(TextureCreationError::TextureAllocationFailed)?;
+
+        let scope = AllocationErrorScope::new(&renderer.device);
+        let view =
+            tex.create_view(&TextureViewDescriptor { dimension: Some(TextureViewDimension::D3), ..Default::default() });
+        scope.end().map_err(TextureCreationError::TextureViewCreationFailed)?;
+
+        Ok(InternalTexture { texture: Arc::new(tex), view, desc })
     }
 
     pub fn fill_from_texture(
@@ -238,7 +414,7 @@ impl<T: 'static> TextureManager<T> {
             );
         }
 
-        self.fill(dst_handle, InternalTexture { texture: tex, view, desc })
+        self.fill(dst_handle, InternalTexture { texture: Arc::new(tex), view, desc })
     }
 
     pub fn fill(&mut self, handle: RawResourceHandle<T>, internal_texture: InternalTexture) {
@@ -250,10 +426,107 @@ impl<T: 'static> TextureManager<T> {
         self.data[handle.idx] = Some(internal_texture);
     }
 
+    /// Adds a texture which is bound once [`Self::stream`] uploaded all of its
+    /// data. Until then, the texture's view is the null view.
+    pub fn fill_streaming(&mut self, handle: RawResourceHandle<T>, mut stream: TextureStream) {
+        stream.id = self.next_stream_id;
+        self.next_stream_id += 1;
+        self.streams.push((handle, stream));
+    }
+
+    /// Holds back the fence until every texture streaming right now has
+    /// finished uploading, see [`Self::take_upload_fences`].
+    pub fn add_upload_fence(&mut self, sender: flume::Sender<()>) {
+        self.upload_fences.push((self.next_stream_id, sender));
+    }
+
+    /// Removes the fences whose streams have all finished uploading, which
+    /// can be signaled once the current frame is done.
+    pub fn take_upload_fences(&mut self) -> Vec<flume::Sender<()>> {
+        // Streams are kept in the order they were added, so the first one is the oldest.
+        let oldest = self.streams.first().map_or(u64::MAX, |(_, stream)| stream.id);
+        let (done, waiting) = self.upload_fences.drain(..).partition(|&(id, _)| id <= oldest);
+        self.upload_fences = waiting;
+        done.into_iter().map(|(_, sender)| sender).collect()
+    }
+
+    /// Uploads up to `budget` bytes of the streaming textures, in the order
+    /// they were added. Returns the textures which finished and are now bound.
+    pub fn stream(
+        &mut self,
+        renderer: &Renderer,
+        encoder: &mut CommandEncoder,
+        mut budget: u64,
+    ) -> Vec<RawResourceHandle<T>> {
+        profiling::scope!("TextureManager::stream");
+
+        let mut finished = Vec::new();
+        while let Some((_, stream)) = self.streams.first_mut() {
+            if !stream.upload(&renderer.queue, &mut budget) {
+                break;
+            }
+
+            let (handle, stream) = self.streams.remove(0);
+            self.finish_stream(renderer, encoder, handle, stream);
+            finished.push(handle);
+        }
+        finished
+    }
+
+    /// Uploads the rest of the texture's data at once if it is still
+    /// streaming, so its contents can be read. Returns true if it was.
+    pub fn finish_streaming(
+        &mut self,
+        renderer: &Renderer,
+        encoder: &mut CommandEncoder,
+        handle: RawResourceHandle<T>,
+    ) -> bool {
+        let Some(idx) = self.streams.iter().position(|&(stream_handle, _)| stream_handle == handle) else {
+            return false;
+        };
+
+        let (handle, mut stream) = self.streams.remove(idx);
+        let mut unlimited = u64::MAX;
+        stream.upload(&renderer.queue, &mut unlimited);
+        self.finish_stream(renderer, encoder, handle, stream);
+        true
+    }
+
+    fn finish_stream(
+        &mut self,
+        renderer: &Renderer,
+        encoder: &mut CommandEncoder,
+        handle: RawResourceHandle<T>,
+        stream: TextureStream,
+    ) {
+        if stream.generate_mipmaps {
+            let InternalTexture { ref texture, ref desc, .. } = stream.texture;
+            if let Err(e) = renderer.mipmap_generator.generate_mipmaps(&renderer.device, encoder, texture, desc) {
+                log::error!("Failed to generate mipmaps of streamed texture {handle:?}: {e}");
+            }
+        }
+
+        self.fill(handle, stream.texture);
+    }
+
+    /// Returns true if all of the texture's data is uploaded.
+    pub fn ready(&self, handle: RawResourceHandle<T>) -> bool {
+        self.data.get(handle.idx).is_some_and(Option::is_some)
+    }
+
+    /// Textures whose data is still being uploaded, in the order they will
+    /// finish.
+    pub fn streaming(&self) -> impl Iterator<Item = RawResourceHandle<T>> + '_ {
+        self.streams.iter().map(|&(handle, _)| handle)
+    }
+
     pub fn remove(&mut self, handle: RawResourceHandle<T>) {
         self.group_dirty = self.group_dirty.map_gpu(|_| true);
 
-        self.data[handle.idx] = None;
+        self.streams.retain(|&(stream_handle, _)| stream_handle != handle);
+        if let Some(texture) = self.data.get_mut(handle.idx) {
+            *texture = None;
+        }
     }
 
     pub fn evaluate(&mut self, device: &Device) -> TextureManagerEvaluateOutput {
@@ -278,8 +551,12 @@ impl<T: 'static> TextureManager<T> {
         self.data[handle.idx].as_ref().unwrap()
     }
 
+    /// Returns the null view for textures which are still streaming.
     pub fn get_view(&self, handle: RawResourceHandle<T>) -> &TextureView {
-        &self.data[handle.idx].as_ref().unwrap().view
+        match self.data.get(handle.idx) {
+            Some(Some(texture)) => &texture.view,
+            _ => &self.null_view,
+        }
     }
 
     pub fn get_null_view(&self) -> &TextureView {
@@ -366,7 +643,81 @@ fn create_null_tex_view(device: &Device, dimension: TextureViewDimension) -> Tex
         .create_view(&TextureViewDescriptor { dimension: Some(dimension), ..TextureViewDescriptor::default() })
 }
 
-fn validate_texture_format(format: TextureFormat, features: Features) -> Result<(), TextureCreationError> {
+/// Validates the texture, returning the descriptor of the texture to create
+/// and whether its mipmaps need to be generated.
+fn texture_desc(
+    renderer: &Renderer,
+    texture: &crate::types::Texture,
+    cube: bool,
+) -> Result<(TextureDescriptor<'static>, bool), TextureCreationError> {
+    validate_texture_format(texture.format, renderer.features)?;
+
+    let (block_x, block_y) = texture.format.block_dimensions();
+    let size = Extent3d {
+        width: round_up(texture.size.x, block_x),
+        height: round_up(texture.size.y, block_y),
+        depth_or_array_layers: match cube {
+            true => 6,
+            false => 1,
+        },
+    };
+
+    let mut mip_count = texture.mip_count.clone();
+    let generate_mipmaps = match texture.mip_source {
+        MipmapSource::Uploaded => false,
+        MipmapSource::Generated if can_generate_mipmaps(texture.format, renderer.features) => true,
+        MipmapSource::Generated => {
+            log::warn!(
+                "Cannot generate mipmaps for texture {:?} with format {:?}, only the first mip will be used",
+                texture.label,
+                texture.format
+            );
+            // Only the first mip was provided.
+            mip_count = MipmapCount::ONE;
+            false
+        }
+    };
+
+    let mip_level_count = match mip_count {
+        MipmapCount::Specific(v) => v.get(),
+        MipmapCount::Maximum => size.max_mips(match cube {
+            true => wgpu::TextureDimension::D3,
+            false => wgpu::TextureDimension::D2,
+        }),
+    };
+
+    let desc = TextureDescriptor {
+        label: None,
+        size,
+        mip_level_count,
+        sample_count: 1,
+        dimension: TextureDimension::D2,
+        format: texture.format,
+        usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_SRC | TextureUsages::COPY_DST,
+        view_formats: &[],
+    };
+
+    // Generated mipmaps are rendered from the first mip, so only that is uploaded.
+    let uploaded_mips = if generate_mipmaps { 1 } else { mip_level_count };
+    validate_texture_data_size(&desc, uploaded_mips, &texture.data)?;
+
+    Ok((desc, generate_mipmaps))
+}
+
+/// Mipmaps are generated by rendering to each mip, so the format must be
+/// renderable and uncompressed.
+fn can_generate_mipmaps(format: TextureFormat, features: Features) -> bool {
+    format.block_dimensions() == (1, 1)
+        && format.guaranteed_format_features(features).allowed_usages.contains(TextureUsages::RENDER_ATTACHMENT)
+}
+
+pub(crate) fn validate_texture_format(format: TextureFormat, features: Features) -> Result<(), TextureCreationError> {
+    // Block compressed formats each need their own feature.
+    let missing = format.required_features() - features;
+    if !missing.is_empty() {
+        return Err(TextureCreationError::TextureFormatNotSupported { format, missing });
+    }
+
     let sample_type = format.sample_type(None, Some(features));
     match sample_type {
         Some(TextureSampleType::Float { filterable: true }) => Ok(()),
@@ -376,3 +727,177 @@ fn validate_texture_format(format: TextureFormat, features: Features) -> Result<
         _ => Err(TextureCreationError::TextureFormatNotFloat { format, sample_type }),
     }
 }
+
+/// Validates that no dimension of the texture is 0 or larger than `max`.
+pub(crate) fn validate_texture_size(size: Extent3d, max: u32) -> Result<(), TextureCreationError> {
+    let dimensions = [size.width, size.height, size.depth_or_array_layers];
+    match dimensions.iter().all(|dimension| (1..=max).contains(dimension)) {
+        true => Ok(()),
+        false => Err(TextureCreationError::TextureSizeInvalid { size, max }),
+    }
+}
+
+/// Size in bytes of the tightly packed data of the first `mip_count` mips of
+/// every layer of the texture. Block compressed mips are rounded up to whole
+/// blocks.
+fn texture_data_size(desc: &TextureDescriptor<'_>, mip_count: u32) -> u64 {
+    let (block_width, block_height) = desc.format.block_dimensions();
+    // Only formats with a single aspect get past validation.
+    let block_size = desc.format.block_copy_size(None).unwrap();
+
+    (0..mip_count)
+        .map(|mip| {
+            let size = desc.mip_level_size(mip).unwrap().physical_size(desc.format);
+            let blocks = (size.width / block_width) as u64 * (size.height / block_height) as u64;
+            blocks * block_size as u64 * size.depth_or_array_layers as u64
+        })
+        .sum()
+}
+
+/// Amount of rows of `bytes_per_row` to upload out of the `remaining` rows of a
+/// mip. A single row is uploaded even if it doesn't fit in the budget.
+fn stream_rows(bytes_per_row: u64, remaining: u32, budget: u64) -> u32 {
+    match budget {
+        0 => 0,
+        _ => (budget / bytes_per_row).clamp(1, remaining as u64) as u32,
+    }
+}
+
+fn validate_texture_data_size(
+    desc: &TextureDescriptor<'_>,
+    mip_count: u32,
+    data: &[u8],
+) -> Result<(), TextureCreationError> {
+    let expected = texture_data_size(desc, mip_count);
+    match data.len() as u64 {
+        actual if actual < expected => Err(TextureCreationError::TextureDataSizeMismatch { expected, actual }),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wgpu::{Extent3d, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages};
+
+    use super::{
+        stream_rows, texture_data_size, validate_texture_size, StreamCopy, StreamCursor, TextureCreationError,
+    };
+
+    fn desc(
+        format: TextureFormat,
+        size: Extent3d,
+        dimension: TextureDimension,
+        mip_level_count: u32,
+    ) -> TextureDescriptor<'static> {
+        TextureDescriptor {
+            label: None,
+            size,
+            mip_level_count,
+            sample_count: 1,
+            dimension,
+            format,
+            usage: TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        }
+    }
+
+    #[test]
+    fn uncompressed_size() {
+        let size = Extent3d { width: 16, height: 8, depth_or_array_layers: 6 };
+        let desc = desc(TextureFormat::Rgba8Unorm, size, TextureDimension::D2, 2);
+        assert_eq!(texture_data_size(&desc, 1), 16 * 8 * 4 * 6);
+        assert_eq!(texture_data_size(&desc, 2), (16 * 8 + 8 * 4) * 4 * 6);
+    }
+
+    #[test]
+    fn block_compressed_size() {
+        let size = Extent3d { width: 16, height: 16, depth_or_array_layers: 1 };
+        let desc = desc(TextureFormat::Bc1RgbaUnorm, size, TextureDimension::D2, 5);
+        // 4x4 blocks of 8 bytes, mips smaller than a block still take a whole block.
+        assert_eq!(texture_data_size(&desc, 5), (16 + 4 + 1 + 1 + 1) * 8);
+    }
+
+    #[test]
+    fn volume_size() {
+        let size = Extent3d { width: 4, height: 4, depth_or_array_layers: 4 };
+        let desc = desc(TextureFormat::R8Unorm, size, TextureDimension::D3, 3);
+        assert_eq!(texture_data_size(&desc, 3), 64 + 8 + 1);
+    }
+
+    #[test]
+    fn texture_size() {
+        let face = |size| Extent3d { width: size, height: size, depth_or_array_layers: 6 };
+        assert!(validate_texture_size(face(1), 8192).is_ok());
+        assert!(validate_texture_size(face(8192), 8192).is_ok());
+        assert!(matches!(
+            validate_texture_size(face(0), 8192),
+            Err(TextureCreationError::TextureSizeInvalid { max: 8192, .. })
+        ));
+        assert!(validate_texture_size(face(8193), 8192).is_err());
+
+        // Volumes are limited in depth too.
+        let volume = Extent3d { width: 16, height: 16, depth_or_array_layers: 2049 };
+        assert!(validate_texture_size(volume, 2048).is_err());
+    }
+
+    #[test]
+    fn streamed_rows() {
+        assert_eq!(stream_rows(64, 16, 0), 0);
+        // At least one row is uploaded.
+        assert_eq!(stream_rows(64, 16, 10), 1);
+        assert_eq!(stream_rows(64, 16, 200), 3);
+        assert_eq!(stream_rows(64, 16, u64::MAX), 16);
+        // Never more than the rows left in the mip.
+        assert_eq!(stream_rows(64, 2, 1000), 2);
+    }
+
+    fn copy(mip: u32, row: u32, rows: u32, bytes_per_row: u32, offset: usize) -> Option<StreamCopy> {
+        Some(StreamCopy { mip, row, rows, bytes_per_row, offset })
+    }
+
+    #[test]
+    fn stream_progression() {
+        // Mip 0 has 4 rows of 32 bytes, mip 1 has 2 rows of 16 bytes.
+        let size = Extent3d { width: 8, height: 4, depth_or_array_layers: 1 };
+        let desc = desc(TextureFormat::Rgba8Unorm, size, TextureDimension::D2, 2);
+        let mut cursor = StreamCursor::default();
+
+        let mut budget = 64;
+        assert_eq!(cursor.next_copy(&desc, 2, &mut budget), copy(0, 0, 2, 32, 0));
+        assert_eq!(budget, 0);
+        assert_eq!(cursor.next_copy(&desc, 2, &mut budget), None);
+
+        // Finishing a mip continues at the start of the next one.
+        let mut budget = 64;
+        assert_eq!(cursor.next_copy(&desc, 2, &mut budget), copy(0, 2, 2, 32, 64));
+        assert_eq!(cursor, StreamCursor { mip: 1, row: 0, offset: 128 });
+        assert_eq!(cursor.next_copy(&desc, 2, &mut budget), None);
+
+        let mut budget = 64;
+        assert_eq!(cursor.next_copy(&desc, 2, &mut budget), copy(1, 0, 2, 16, 128));
+        assert_eq!(budget, 32);
+        assert_eq!(cursor.next_copy(&desc, 2, &mut budget), None);
+        assert_eq!(cursor.offset as u64, texture_data_size(&desc, 2));
+    }
+
+    #[test]
+    fn stream_block_rows() {
+        // 4 rows of 4 blocks of 8 bytes.
+        let size = Extent3d { width: 16, height: 16, depth_or_array_layers: 1 };
+        let desc = desc(TextureFormat::Bc1RgbaUnorm, size, TextureDimension::D2, 5);
+        let mut cursor = StreamCursor::default();
+
+        // A row is uploaded whenever any budget is left, even if it doesn't fit.
+        let mut budget = 40;
+        assert_eq!(cursor.next_copy(&desc, 1, &mut budget), copy(0, 0, 1, 32, 0));
+        assert_eq!(cursor.next_copy(&desc, 1, &mut budget), copy(0, 1, 1, 32, 32));
+        assert_eq!(budget, 0);
+        assert_eq!(cursor.next_copy(&desc, 1, &mut budget), None);
+
+        // Only the uploaded mips are streamed, like when the rest are generated.
+        let mut budget = u64::MAX;
+        assert_eq!(cursor.next_copy(&desc, 1, &mut budget), copy(0, 2, 2, 32, 64));
+        assert_eq!(cursor.next_copy(&desc, 1, &mut budget), None);
+        assert_eq!(cursor, StreamCursor { mip: 1, row: 0, offset: 128 });
+    }
+}
@@ -0,0 +1,138 @@
+use std::{mem, panic::Location, ptr, sync::Arc};
+
+use glam::Mat4;
+use rend3_types::{Material, ObjectChange};
+
+use crate::{
+    instruction::{Instruction, InstructionKind},
+    types::{MaterialHandle, Object, ObjectHandle},
+    Renderer,
+};
+
+/// Records world changes so they can be handed to the renderer all at once
+/// with [`Renderer::submit_batch`].
+///
+/// Every world-modifying function on [`Renderer`] locks the instruction
+/// buffer. When loading large scenes, recording the changes in a batch and
+/// submitting it only locks the buffer once.
+///
+/// Instructions within a batch are evaluated in the order they were recorded.
+/// The batch is inserted as a whole: no instructions recorded by other threads
+/// are interleaved with it. Changes made through the [`Renderer`] while the
+/// batch is being recorded are evaluated before the batch.
+///
+/// Handles returned by the batch can be used in the same batch right away, but
+/// must only be passed to the [`Renderer`] after the batch is submitted. The
+/// batch keeps the resources it added alive until then. A batch which is
+/// dropped without being submitted is submitted when dropped.
+pub struct InstructionBatch {
+    renderer: Arc<Renderer>,
+    instructions: Vec<Instruction>,
+    materials: Vec<MaterialHandle>,
+    objects: Vec<ObjectHandle>,
+}
+
+impl InstructionBatch {
+    pub(super) fn new(renderer: &Arc<Renderer>) -> Self {
+        Self { renderer: Arc::clone(renderer), instructions: Vec::new(), materials: Vec::new(), objects: Vec::new() }
+    }
+
+    /// Amount of instructions recorded in this batch.
+    pub fn len(&self) -> usize {
+        self.instructions.len()
+    }
+
+    /// Returns true if nothing has been recorded in this batch.
+    pub fn is_empty(&self) -> bool {
+        self.instructions.is_empty()
+    }
+
+    /// Batched version of [`Renderer::add_material`].
+    #[track_caller]
+    pub fn add_material<M: Material>(&mut self, material: M) -> MaterialHandle {
+        let handle = self.renderer.resource_handle_allocators.material.allocate(&self.renderer);
+        self.push(InstructionKind::AddMaterial {
+            handle: *handle,
+            fill_invoke: Box::new(move |material_manager, device, profile, d2_manager, mat_handle| {
+                material_manager.add(device, profile, d2_manager, mat_handle, material)
+            }),
+        });
+        self.materials.push(handle.clone());
+        handle
+    }
+
+    /// Batched version of [`Renderer::update_material`].
+    #[track_caller]
+    pub fn update_material<M: Material>(&mut self, handle: &MaterialHandle, material: M) {
+        self.push(InstructionKind::ChangeMaterial {
+            handle: **handle,
+            change_invoke: Box::new(move |material_manager, device, d2_manager, mat_handle| {
+                material_manager.update(device, d2_manager, mat_handle, material)
+            }),
+        })
+    }
+
+    /// Batched version of [`Renderer::add_object`].
+    #[track_caller]
+    pub fn add_object(&mut self, object: Object) -> ObjectHandle {
+        let handle = self.renderer.resource_handle_allocators.object.allocate(&self.renderer);
+        self.push(InstructionKind::AddObject { handle: *handle, object });
+        self.objects.push(handle.clone());
+        handle
+    }
+
+    /// Batched version of [`Renderer::duplicate_object`].
+    #[track_caller]
+    pub fn duplicate_object(&mut self, object_handle: &ObjectHandle, change: ObjectChange) -> ObjectHandle {
+        let dst_handle = self.renderer.resource_handle_allocators.object.allocate(&self.renderer);
+        self.push(InstructionKind::DuplicateObject { src_handle: **object_handle, dst_handle: *dst_handle, change });
+        self.objects.push(dst_handle.clone());
+        dst_handle
+    }
+
+    /// Batched version of [`Renderer::set_object_transform`].
+    #[track_caller]
+    pub fn set_object_transform(&mut self, handle: &ObjectHandle, transform: Mat4) {
+        self.push(InstructionKind::SetObjectTransform { handle: handle.get_raw(), transform })
+    }
+
+    /// Batched version of [`Renderer::set_object_visibility`].
+    #[track_caller]
+    pub fn set_object_visibility(&mut self, handle: &ObjectHandle, visible: bool) {
+        self.push(InstructionKind::SetObjectVisibility { handle: handle.get_raw(), visible })
+    }
+
+    /// Batched version of [`Renderer::set_object_parent`].
+    #[track_caller]
+    pub fn set_object_parent(&mut self, handle: &ObjectHandle, parent: Option<&ObjectHandle>) {
+        self.push(InstructionKind::SetObjectParent {
+            child: handle.get_raw(),
+            parent: parent.map(|parent| parent.get_raw()),
+        })
+    }
+
+    #[track_caller]
+    fn push(&mut self, kind: InstructionKind) {
+        self.instructions.push(Instruction { kind, location: *Location::caller() })
+    }
+
+    pub(super) fn is_for(&self, renderer: &Renderer) -> bool {
+        ptr::eq(&*self.renderer, renderer)
+    }
+
+    pub(super) fn submit(&mut self) {
+        self.renderer.instructions.push_batch(mem::take(&mut self.instructions));
+        // Only release the batch's references once the adds are in the instruction buffer, so their
+        // deletions can't overtake them.
+        self.materials.clear();
+        self.objects.clear();
+    }
+}
+
+impl Drop for InstructionBatch {
+    fn drop(&mut self) {
+        if !self.instructions.is_empty() {
+            self.submit();
+        }
+    }
+}
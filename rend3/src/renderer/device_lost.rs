@@ -0,0 +1,72 @@
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+use wgpu::{Backend, Device, DeviceLostReason};
+
+/// Information about the loss of the renderer's device.
+///
+/// Once the device is lost, the renderer and every resource created with it
+/// are unusable. Frames are no longer submitted, and the application has to
+/// drop the renderer and create a new one with a fresh device, re-adding all
+/// of its resources.
+#[derive(Debug, Clone)]
+pub struct DeviceLost {
+    /// Why the device was lost, as reported by wgpu.
+    pub reason: DeviceLostReason,
+    /// Message describing the loss, as reported by the driver.
+    pub message: String,
+}
+
+type DeviceLostCallback = Box<dyn Fn(&DeviceLost) + Send + 'static>;
+
+/// State shared between the renderer and the callback registered on the
+/// device.
+#[derive(Default)]
+pub(crate) struct DeviceLostState {
+    lost: Mutex<Option<DeviceLost>>,
+    callback: Mutex<Option<DeviceLostCallback>>,
+}
+
+impl DeviceLostState {
+    /// Registers the callback on the device which records its loss and
+    /// notifies the user's callback.
+    pub fn new(device: &Device, backend: Backend) -> Arc<Self> {
+        let state = Arc::new(Self::default());
+
+        // The browser backend doesn't implement device lost callbacks yet.
+        if backend != Backend::BrowserWebGpu {
+            let weak = Arc::downgrade(&state);
+            device.set_device_lost_callback(move |reason, message| {
+                // Dropping the device or replacing the callback are not losses.
+                if !matches!(reason, DeviceLostReason::Unknown | DeviceLostReason::Destroyed) {
+                    return;
+                }
+                if let Some(state) = weak.upgrade() {
+                    state.mark_lost(DeviceLost { reason, message });
+                }
+            });
+        }
+
+        state
+    }
+
+    pub fn mark_lost(&self, lost: DeviceLost) {
+        log::error!("Device lost ({:?}): {}", lost.reason, lost.message);
+
+        *self.lost.lock() = Some(lost.clone());
+        if let Some(callback) = &*self.callback.lock() {
+            callback(&lost);
+        }
+    }
+
+    pub fn get(&self) -> Option<DeviceLost> {
+        self.lost.lock().clone()
+    }
+
+    pub fn set_callback(&self, callback: DeviceLostCallback) {
+        if let Some(lost) = self.get() {
+            callback(&lost);
+        }
+        *self.callback.lock() = Some(callback);
+    }
+}
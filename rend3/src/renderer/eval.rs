@@ -1,8 +1,15 @@
+use std::{panic::Location, sync::atomic::Ordering};
+
+use parking_lot::Mutex;
+use rend3_types::RawResourceHandle;
+use web_time::Instant;
 use wgpu::CommandEncoderDescriptor;
 
 use crate::{
     graph::InstructionEvaluationOutput,
-    instruction::{Instruction, InstructionKind},
+    instruction::{DeletableRawResourceHandle, Instruction, InstructionKind},
+    managers::{HandleAllocator, ViewportDesc, TEXTURE_STREAMING_BUDGET},
+    util::stats::FrameStatistics,
     Renderer,
 };
 
@@ -22,9 +29,14 @@ pub fn evaluate_instructions(renderer: &Renderer) -> InstructionEvaluationOutput
     let mut data_core = renderer.data_core.lock();
     let data_core = &mut *data_core;
 
+    let mut upload_fences = Vec::new();
+    // Textures whose views changed from the null view.
+    let mut streamed_textures = Vec::new();
+
+    let instruction_start = Instant::now();
     {
         profiling::scope!("Instruction Processing");
-        for Instruction { kind, location: _ } in instructions.drain(..) {
+        for Instruction { kind, location } in instructions.drain(..) {
             match kind {
                 InstructionKind::AddSkeleton { handle, skeleton } => {
                     profiling::scope!("Add Skeleton");
@@ -40,13 +52,36 @@ pub fn evaluate_instructions(renderer: &Renderer) -> InstructionEvaluationOutput
                     cmd_bufs.extend(cmd_buf);
                     data_core.d2_texture_manager.fill(handle, internal_texture);
                 }
+                InstructionKind::AddTexture2DStreaming { handle, stream } => {
+                    data_core.d2_texture_manager.fill_streaming(handle, stream);
+                }
                 InstructionKind::AddTexture2DFromTexture { handle, texture } => {
+                    // The source must be complete to be copied from.
+                    if data_core.d2_texture_manager.finish_streaming(renderer, &mut encoder, texture.src) {
+                        streamed_textures.push(texture.src);
+                    }
                     data_core.d2_texture_manager.fill_from_texture(&renderer.device, &mut encoder, handle, texture)
                 }
                 InstructionKind::AddTextureCube { handle, internal_texture, cmd_buf } => {
                     cmd_bufs.extend(cmd_buf);
                     data_core.d2c_texture_manager.fill(handle, internal_texture);
                 }
+                InstructionKind::AddTexture3D { handle, internal_texture } => {
+                    data_core.d3_texture_manager.fill(handle, internal_texture);
+                }
+                InstructionKind::AddTextureCubeFromEquirect { handle, src, face_size } => {
+                    profiling::scope!("Add Texture Cube From Equirect");
+                    if data_core.d2_texture_manager.finish_streaming(renderer, &mut encoder, src) {
+                        streamed_textures.push(src);
+                    }
+                    let internal_texture = renderer.equirect_converter.convert(
+                        &renderer.device,
+                        &mut encoder,
+                        data_core.d2_texture_manager.get_internal(src),
+                        face_size,
+                    );
+                    data_core.d2c_texture_manager.fill(handle, internal_texture);
+                }
                 InstructionKind::AddMaterial { handle, fill_invoke } => {
                     profiling::scope!("Add Material");
                     fill_invoke(
@@ -61,6 +96,9 @@ pub fn evaluate_instructions(renderer: &Renderer) -> InstructionEvaluationOutput
                     add_invoke(&mut data_core.graph_storage);
                 }
                 InstructionKind::ChangeMaterial { handle, change_invoke } => {
+                    if !is_alive(&renderer.resource_handle_allocators.material, handle, &location) {
+                        continue;
+                    }
                     profiling::scope!("Change Material");
 
                     change_invoke(
@@ -81,30 +119,96 @@ pub fn evaluate_instructions(renderer: &Renderer) -> InstructionEvaluationOutput
                     );
                 }
                 InstructionKind::SetObjectTransform { handle, transform } => {
+                    if !is_alive(&renderer.resource_handle_allocators.object, handle, &location) {
+                        continue;
+                    }
                     data_core.object_manager.set_object_transform(handle, transform);
                 }
+                InstructionKind::SetObjectVisibility { handle, visible } => {
+                    if !is_alive(&renderer.resource_handle_allocators.object, handle, &location) {
+                        continue;
+                    }
+                    data_core.object_manager.set_object_visibility(handle, visible);
+                }
+                InstructionKind::SetObjectMaterialOverride { handle, material_override } => {
+                    if !is_alive(&renderer.resource_handle_allocators.object, handle, &location) {
+                        continue;
+                    }
+                    data_core.object_manager.set_object_material_override(handle, material_override);
+                }
+                InstructionKind::SetObjectParent { child, parent } => {
+                    let allocator = &renderer.resource_handle_allocators.object;
+                    if !is_alive(allocator, child, &location)
+                        || !parent.map_or(true, |parent| is_alive(allocator, parent, &location))
+                    {
+                        continue;
+                    }
+                    data_core.object_manager.set_object_parent(child, parent);
+                }
                 InstructionKind::SetSkeletonJointDeltas { handle, joint_matrices } => {
+                    if !is_alive(&renderer.resource_handle_allocators.skeleton, handle, &location) {
+                        continue;
+                    }
                     data_core.skeleton_manager.set_joint_matrices(handle, joint_matrices);
                 }
+                InstructionKind::SetSkeletonMorphWeights { handle, morph_weights } => {
+                    if !is_alive(&renderer.resource_handle_allocators.skeleton, handle, &location) {
+                        continue;
+                    }
+                    data_core.skeleton_manager.set_morph_weights(handle, morph_weights);
+                }
                 InstructionKind::AddDirectionalLight { handle, light } => {
                     data_core.directional_light_manager.add(handle, light);
                 }
                 InstructionKind::ChangeDirectionalLight { handle, change } => {
+                    if !is_alive(&renderer.resource_handle_allocators.directional_light, handle, &location) {
+                        continue;
+                    }
                     data_core.directional_light_manager.update(handle, change);
                 }
                 InstructionKind::AddPointLight { handle, light } => {
                     data_core.point_light_manager.add(handle, light);
                 }
                 InstructionKind::ChangePointLight { handle, change } => {
+                    if !is_alive(&renderer.resource_handle_allocators.point_light, handle, &location) {
+                        continue;
+                    }
                     data_core.point_light_manager.update(handle, change);
                 }
+                InstructionKind::AddSpotLight { handle, light } => {
+                    data_core.spot_light_manager.add(handle, light);
+                }
+                InstructionKind::ChangeSpotLight { handle, change } => {
+                    if !is_alive(&renderer.resource_handle_allocators.spot_light, handle, &location) {
+                        continue;
+                    }
+                    data_core.spot_light_manager.update(handle, change);
+                }
+                InstructionKind::AddDecal { handle, decal } => {
+                    data_core.decal_manager.add(handle, decal);
+                }
+                InstructionKind::ChangeDecal { handle, change } => {
+                    if !is_alive(&renderer.resource_handle_allocators.decal, handle, &location) {
+                        continue;
+                    }
+                    data_core.decal_manager.update(handle, change);
+                }
                 InstructionKind::SetAspectRatio { ratio } => {
                     data_core.viewport_camera_state.set_aspect_ratio(Some(ratio))
                 }
                 InstructionKind::SetCameraData { data } => {
                     data_core.viewport_camera_state.set_data(data);
                 }
+                InstructionKind::SetViewports { viewports } => {
+                    data_core.viewports = viewports;
+                }
+                InstructionKind::UploadFence { sender } => {
+                    data_core.d2_texture_manager.add_upload_fence(sender);
+                }
                 InstructionKind::DuplicateObject { src_handle, dst_handle, change } => {
+                    if !is_alive(&renderer.resource_handle_allocators.object, src_handle, &location) {
+                        continue;
+                    }
                     data_core.object_manager.duplicate_object(
                         &renderer.device,
                         src_handle,
@@ -131,6 +235,10 @@ pub fn evaluate_instructions(renderer: &Renderer) -> InstructionEvaluationOutput
                     renderer.resource_handle_allocators.d2c_texture.deallocate(handle);
                     data_core.d2c_texture_manager.remove(handle)
                 }
+                InstructionKind::DeleteTexture3D { handle } => {
+                    renderer.resource_handle_allocators.d3_texture.deallocate(handle);
+                    data_core.d3_texture_manager.remove(handle)
+                }
                 InstructionKind::DeleteMaterial { handle } => {
                     renderer.resource_handle_allocators.material.deallocate(handle);
                     data_core.material_manager.remove(handle)
@@ -147,6 +255,14 @@ pub fn evaluate_instructions(renderer: &Renderer) -> InstructionEvaluationOutput
                     renderer.resource_handle_allocators.point_light.deallocate(handle);
                     data_core.point_light_manager.remove(handle);
                 }
+                InstructionKind::DeleteSpotLight { handle } => {
+                    renderer.resource_handle_allocators.spot_light.deallocate(handle);
+                    data_core.spot_light_manager.remove(handle);
+                }
+                InstructionKind::DeleteDecal { handle } => {
+                    renderer.resource_handle_allocators.decal.deallocate(handle);
+                    data_core.decal_manager.remove(handle);
+                }
                 InstructionKind::DeleteGraphData { handle } => {
                     renderer.resource_handle_allocators.graph_storage.deallocate(handle);
                     data_core.graph_storage.remove(&handle);
@@ -155,11 +271,23 @@ pub fn evaluate_instructions(renderer: &Renderer) -> InstructionEvaluationOutput
         }
     }
 
+    let instruction_time = instruction_start.elapsed();
+    let resource_update_start = Instant::now();
+
     // Do these in dependency order
     // Level 3
-    data_core.object_manager.evaluate(&renderer.device, &mut encoder, &renderer.scatter, &delayed_object_handles);
+    data_core.object_manager.evaluate(
+        &renderer.device,
+        &mut encoder,
+        &renderer.scatter,
+        &delayed_object_handles,
+        data_core.viewport_camera_state.location().into(),
+    );
 
     // Level 2
+    streamed_textures.extend(data_core.d2_texture_manager.stream(renderer, &mut encoder, TEXTURE_STREAMING_BUDGET));
+    data_core.material_manager.refresh_textures(&renderer.device, &data_core.d2_texture_manager, &streamed_textures);
+    upload_fences.extend(data_core.d2_texture_manager.take_upload_fences());
     let d2_texture = data_core.d2_texture_manager.evaluate(&renderer.device);
 
     // Level 1
@@ -175,13 +303,53 @@ pub fn evaluate_instructions(renderer: &Renderer) -> InstructionEvaluationOutput
 
     // Level 0
     let d2c_texture = data_core.d2c_texture_manager.evaluate(&renderer.device);
-    let (shadow_target_size, shadows) =
-        data_core.directional_light_manager.evaluate(renderer, &data_core.viewport_camera_state);
-    data_core.point_light_manager.evaluate(renderer);
+    let d3_texture = data_core.d3_texture_manager.evaluate(&renderer.device);
+    let (shadow_target_size, shadows) = data_core.directional_light_manager.evaluate(
+        renderer,
+        &data_core.viewport_camera_state,
+        &data_core.point_light_manager,
+        &data_core.spot_light_manager,
+    );
+    data_core.point_light_manager.evaluate(renderer, shadow_target_size, &shadows);
+    data_core.spot_light_manager.evaluate(renderer, shadow_target_size, &shadows);
+    data_core.decal_manager.evaluate(renderer);
     let (mesh_buffer, mesh_cmd_buf) = renderer.mesh_manager.evaluate(&renderer.device);
+    let viewports =
+        data_core.viewports.iter().map(|&viewport| ViewportDesc::new(viewport, renderer.coordinate_system)).collect();
 
     cmd_bufs.push(mesh_cmd_buf);
     cmd_bufs.push(encoder.finish());
 
-    InstructionEvaluationOutput { cmd_bufs, d2_texture, d2c_texture, shadow_target_size, shadows, mesh_buffer }
+    let stats = Mutex::new(FrameStatistics {
+        instruction_time,
+        resource_update_time: resource_update_start.elapsed(),
+        ..FrameStatistics::default()
+    });
+
+    InstructionEvaluationOutput {
+        frame: renderer.frame_counter.fetch_add(1, Ordering::Relaxed),
+        cmd_bufs,
+        d2_texture,
+        d2c_texture,
+        d3_texture,
+        shadow_target_size,
+        shadows,
+        viewports,
+        mesh_buffer,
+        stats,
+        upload_fences,
+    }
+}
+
+/// Returns true if the resource behind `handle` was not removed, warning that
+/// the instruction issued at `location` is ignored otherwise.
+fn is_alive<T>(allocator: &HandleAllocator<T>, handle: RawResourceHandle<T>, location: &Location<'_>) -> bool
+where
+    RawResourceHandle<T>: DeletableRawResourceHandle,
+{
+    let alive = allocator.is_alive(handle);
+    if !alive {
+        log::warn!("Ignoring instruction issued at {location} for removed resource {handle:?}");
+    }
+    alive
 }
@@ -1,37 +1,73 @@
-use std::{marker::PhantomData, panic::Location, sync::Arc};
+use std::{
+    future::Future,
+    marker::PhantomData,
+    panic::Location,
+    sync::{
+        atomic::{AtomicU32, AtomicU64, Ordering},
+        Arc,
+    },
+};
 
-use glam::Mat4;
+use glam::{Mat4, UVec2};
 use parking_lot::Mutex;
 use rend3_types::{
-    GraphDataHandle, GraphDataTag, Handedness, Material, MaterialTag, ObjectChange, PointLight, PointLightChange,
-    PointLightHandle, Skeleton, SkeletonHandle, Texture2DTag, TextureCubeHandle, TextureCubeTag, TextureFromTexture,
-    WasmNotSend,
+    CoordinateSystem, Decal, DecalChange, DecalHandle, GraphDataHandle, GraphDataTag, Handedness, Material,
+    MaterialOverride, MaterialTag, ObjectChange, PointLight, PointLightChange, PointLightHandle, RawMaterialHandle,
+    RawObjectHandle, RawResourceHandle, RawTexture2DHandle, SampleCount, Skeleton, SkeletonHandle, SpotLight,
+    SpotLightChange, SpotLightHandle, Texture2DTag, Texture3D, Texture3DHandle, Texture3DTag, TextureCubeHandle,
+    TextureCubeTag, TextureFormat, TextureFromTexture, WasmNotSend,
 };
-use wgpu::{Device, DownlevelCapabilities, Features, Limits, Queue};
+use wgpu::{Device, DownlevelCapabilities, Extent3d, Features, Limits, Queue};
 use wgpu_profiler::GpuProfiler;
 
 use crate::{
     graph::{GraphTextureStore, InstructionEvaluationOutput},
     instruction::{InstructionKind, InstructionStreamPair},
     managers::{
-        CameraState, DirectionalLightManager, GraphStorage, HandleAllocator, MaterialManager, MeshCreationError,
-        MeshManager, ObjectManager, PointLightManager, SkeletonCreationError, SkeletonManager, TextureCreationError,
-        TextureManager,
+        validate_texture_size, CameraState, DecalManager, DirectionalLightManager, GraphStorage, HandleAllocator,
+        MaterialManager, MaterialSnapshot, MeshCreationError, MeshManager, ObjectManager, ObjectSnapshot,
+        PointLightManager, ShadowCascadeInfo, SkeletonCreationError, SkeletonManager, SpotLightManager,
+        TextureCreationError, TextureManager, Viewport,
     },
+    renderer::device_lost::{DeviceLost, DeviceLostState},
     types::{
         Camera, DirectionalLight, DirectionalLightChange, DirectionalLightHandle, MaterialHandle, Mesh, MeshHandle,
         Object, ObjectHandle, Texture, Texture2DHandle,
     },
-    util::{mipmap::MipmapGenerator, scatter_copy::ScatterCopy},
+    util::{
+        equirect::EquirectConverter,
+        frustum::Aabb,
+        mipmap::MipmapGenerator,
+        readback::{PendingReadback, ReadbackError},
+        render_target::RenderTarget,
+        scatter_copy::ScatterCopy,
+        stats::{AccumulatedStatistics, FrameStatistics, StatisticsAccumulator},
+    },
     ExtendedAdapterInfo, InstanceAdapterDevice, RendererInitializationError, RendererProfile,
 };
 
+mod batch;
+pub mod device_lost;
 pub mod error;
 mod eval;
 mod setup;
 
+pub use batch::InstructionBatch;
+
 /// Core struct which contains the renderer world. Primary way to interact with
 /// the world.
+///
+/// # Handle Allocation
+///
+/// Every renderer allocates its own handles, separately for each resource
+/// type. Indices start at zero and count up, and the most recently freed index
+/// is the first to be reused. An index is freed once the deletion of its handle
+/// is processed by [`Self::evaluate_instructions`]; object indices are freed
+/// one call later. A fixed sequence of calls on a single thread therefore
+/// always produces the same handles.
+///
+/// When resources of the same type are added from multiple threads at once,
+/// which call gets which index depends on the order the calls happen in.
 pub struct Renderer {
     pub(crate) instructions: InstructionStreamPair,
 
@@ -50,8 +86,14 @@ pub struct Renderer {
     pub limits: Limits,
     /// Downlevel limits of the device
     pub downlevel: DownlevelCapabilities,
-    /// Handedness of all parts of this renderer.
+    /// Handedness of all parts of this renderer, the same as
+    /// `coordinate_system.handedness`.
     pub handedness: Handedness,
+    /// Conventions of the transforms given to this renderer.
+    pub coordinate_system: CoordinateSystem,
+    /// Highest sample count the formats rend3 renders to support on this
+    /// adapter.
+    pub max_sample_count: SampleCount,
 
     /// Allocators for resource handles
     resource_handle_allocators: HandleAllocators,
@@ -62,8 +104,20 @@ pub struct Renderer {
 
     /// Tool which generates mipmaps from a texture.
     pub mipmap_generator: MipmapGenerator,
+    /// Tool which converts equirectangular textures into cubemaps.
+    pub equirect_converter: EquirectConverter,
     /// Tool which allows scatter uploads to happen.
     pub scatter: ScatterCopy,
+
+    /// Amount of frames which have been submitted but have not finished executing on the gpu.
+    pub(crate) frames_in_flight: Arc<AtomicU32>,
+    /// Index of the next frame evaluated.
+    pub(crate) frame_counter: AtomicU64,
+    /// Statistics of all frames since the last reset.
+    pub(crate) stats_accumulator: Mutex<StatisticsAccumulator>,
+    pub(crate) last_frame_stats: Mutex<FrameStatistics>,
+    /// Whether the device has been lost, and the callback notified when it is.
+    pub(crate) device_lost: Arc<DeviceLostState>,
 }
 
 /// Handle allocators
@@ -72,10 +126,13 @@ struct HandleAllocators {
     pub skeleton: HandleAllocator<Skeleton>,
     pub d2_texture: HandleAllocator<Texture2DTag>,
     pub d2c_texture: HandleAllocator<TextureCubeTag>,
+    pub d3_texture: HandleAllocator<Texture3DTag>,
     pub material: HandleAllocator<MaterialTag>,
     pub object: HandleAllocator<Object>,
     pub directional_light: HandleAllocator<DirectionalLight>,
     pub point_light: HandleAllocator<PointLight>,
+    pub spot_light: HandleAllocator<SpotLight>,
+    pub decal: HandleAllocator<Decal>,
     pub graph_storage: HandleAllocator<GraphDataTag>,
 }
 
@@ -86,10 +143,13 @@ impl Default for HandleAllocators {
             skeleton: HandleAllocator::new(false),
             d2_texture: HandleAllocator::new(false),
             d2c_texture: HandleAllocator::new(false),
+            d3_texture: HandleAllocator::new(false),
             material: HandleAllocator::new(false),
             object: HandleAllocator::new(true),
             directional_light: HandleAllocator::new(false),
             point_light: HandleAllocator::new(false),
+            spot_light: HandleAllocator::new(false),
+            decal: HandleAllocator::new(false),
             graph_storage: HandleAllocator::new(false),
         }
     }
@@ -99,10 +159,15 @@ impl Default for HandleAllocators {
 pub struct RendererDataCore {
     /// Position and settings of the viewport camera.
     pub viewport_camera_state: CameraState,
+    /// Cameras rendering into rectangles of the output, in addition to the
+    /// viewport camera.
+    pub viewports: Vec<Viewport>,
     /// Manages all 2D textures, including bindless bind group.
     pub d2_texture_manager: TextureManager<Texture2DTag>,
     /// Manages all Cube textures, including bindless bind groups.
     pub d2c_texture_manager: TextureManager<TextureCubeTag>,
+    /// Manages all 3D textures, including bindless bind groups.
+    pub d3_texture_manager: TextureManager<Texture3DTag>,
     /// Manages all materials, including material bind groups when CpuDriven.
     pub material_manager: MaterialManager,
     /// Manages all objects.
@@ -111,6 +176,10 @@ pub struct RendererDataCore {
     pub directional_light_manager: DirectionalLightManager,
     /// Manages all point lights, including their shadow maps.
     pub point_light_manager: PointLightManager,
+    /// Manages all spot lights, including their shadow maps.
+    pub spot_light_manager: SpotLightManager,
+    /// Manages all decals projected onto the opaque geometry.
+    pub decal_manager: DecalManager,
     /// Manages skeletons, and their owned portion of the MeshManager's buffers
     pub skeleton_manager: SkeletonManager,
     /// Managed long term storage of data for the graph and it's routines
@@ -128,14 +197,21 @@ impl Renderer {
     ///
     /// You can create your own IAD or call [`create_iad`](crate::create_iad).
     ///
+    /// The coordinate system is usually just a [`Handedness`], with +Y up.
+    ///
     /// The aspect ratio is that of the window. This automatically configures
     /// the camera. If None is passed, an aspect ratio of 1.0 is assumed.
+    ///
+    /// The renderer doesn't need a surface. To render headless, for servers or
+    /// image tests, render the graph into a texture with
+    /// [`TextureUsages::COPY_SRC`](wgpu::TextureUsages::COPY_SRC) and read it
+    /// back with [`Self::read_texture`], like rend3-test does.
     pub fn new(
         iad: InstanceAdapterDevice,
-        handedness: Handedness,
+        coordinate_system: impl Into<CoordinateSystem>,
         aspect_ratio: Option<f32>,
     ) -> Result<Arc<Self>, RendererInitializationError> {
-        setup::create_renderer(iad, handedness, aspect_ratio)
+        setup::create_renderer(iad, coordinate_system.into(), aspect_ratio)
     }
 
     /// Adds a 3D mesh to the renderer. This doesn't instantiate it to world. To
@@ -194,6 +270,34 @@ impl Renderer {
         Ok(handle)
     }
 
+    /// Add a 2D texture to the renderer without waiting for its data to be
+    /// uploaded. The data is uploaded over the next frames, a few megabytes per
+    /// frame, so large textures don't cause frame time spikes.
+    ///
+    /// Until the upload completes, materials using the texture sample a 1x1
+    /// black placeholder instead. Use [`Self::texture_2d_ready`] to check if
+    /// it completed. Copying from the texture or converting it to a cubemap
+    /// uploads the rest of its data at once.
+    ///
+    /// The handle will keep the texture alive. All materials created with this
+    /// texture will also keep the texture alive.
+    #[track_caller]
+    pub fn add_texture_2d_streaming(
+        self: &Arc<Self>,
+        texture: Texture,
+    ) -> Result<Texture2DHandle, TextureCreationError> {
+        profiling::scope!("Add Texture 2D Streaming");
+
+        let stream = TextureManager::<Texture2DTag>::add_streaming(self, texture)?;
+
+        // Handle allocation must be done _after_ any validation to prevent deletion of a handle that never gets fully added.
+        let handle = self.resource_handle_allocators.d2_texture.allocate(self);
+
+        self.instructions.push(InstructionKind::AddTexture2DStreaming { handle: *handle, stream }, *Location::caller());
+
+        Ok(handle)
+    }
+
     /// Add a 2D texture to the renderer by copying a set of mipmaps from an
     /// existing texture. This new can be used in a [`Material`].
     ///
@@ -211,6 +315,35 @@ impl Renderer {
         handle
     }
 
+    /// Add a texture the render graph can render into. Its
+    /// [`handle`](RenderTarget::handle) can be used in a [`Material`] like any
+    /// other 2D texture.
+    ///
+    /// `format` must be renderable and filterable. When rendering the scene
+    /// into it with the base render graph, it must match the output format of
+    /// the tonemapping routine.
+    #[track_caller]
+    pub fn add_render_target(
+        self: &Arc<Self>,
+        size: UVec2,
+        format: TextureFormat,
+        samples: SampleCount,
+    ) -> Result<RenderTarget, TextureCreationError> {
+        profiling::scope!("Add Render Target");
+
+        // Handle allocation must be done _after_ any validation to prevent deletion of a handle that never gets fully added.
+        let (target, internal_texture) = RenderTarget::new(self, size, format, samples, || {
+            self.resource_handle_allocators.d2_texture.allocate(self)
+        })?;
+
+        self.instructions.push(
+            InstructionKind::AddTexture2D { handle: **target.handle(), internal_texture, cmd_buf: None },
+            *Location::caller(),
+        );
+
+        Ok(target)
+    }
+
     /// Adds a Cube texture to the renderer. This can be used as a cube
     /// environment map by a render routine.
     ///
@@ -230,6 +363,64 @@ impl Renderer {
         Ok(handle)
     }
 
+    /// Adds a 3D texture to the renderer. This can be used for volumes or
+    /// color lookup tables by a render routine.
+    ///
+    /// Every dimension must be between 1 and the device's
+    /// [`max_texture_dimension_3d`](wgpu::Limits::max_texture_dimension_3d).
+    ///
+    /// The handle will keep the texture alive.
+    #[track_caller]
+    pub fn add_texture_3d(self: &Arc<Self>, texture: Texture3D) -> Result<Texture3DHandle, TextureCreationError> {
+        profiling::scope!("Add Texture 3D");
+
+        let internal_texture = TextureManager::<Texture3DTag>::add_3d(self, texture)?;
+
+        // Handle allocation must be done _after_ any validation to prevent deletion of a handle that never gets fully added.
+        let handle = self.resource_handle_allocators.d3_texture.allocate(self);
+
+        self.instructions
+            .push(InstructionKind::AddTexture3D { handle: *handle, internal_texture }, *Location::caller());
+
+        Ok(handle)
+    }
+
+    /// Adds a Cube texture to the renderer by rendering an equirectangular 2D
+    /// texture into each face of a new cubemap. This can be used as a cube
+    /// environment map by a render routine.
+    ///
+    /// The resulting cubemap is [`EQUIRECT_CUBEMAP_FORMAT`](crate::util::equirect::EQUIRECT_CUBEMAP_FORMAT)
+    /// with faces of `face_size` by `face_size` texels and a single mip level.
+    /// `face_size` must be between 1 and the device's
+    /// [`max_texture_dimension_2d`](wgpu::Limits::max_texture_dimension_2d).
+    /// Sources which can't be filtered, like 32 bit float textures without
+    /// `FLOAT32_FILTERABLE`, are sampled without filtering.
+    ///
+    /// The handle will keep the texture alive.
+    #[track_caller]
+    pub fn equirect_to_cubemap(
+        self: &Arc<Self>,
+        equirect: &Texture2DHandle,
+        face_size: u32,
+    ) -> Result<TextureCubeHandle, TextureCreationError> {
+        profiling::scope!("Equirect To Cubemap");
+
+        validate_texture_size(
+            Extent3d { width: face_size, height: face_size, depth_or_array_layers: 6 },
+            self.limits.max_texture_dimension_2d,
+        )?;
+
+        // Handle allocation must be done _after_ any validation to prevent deletion of a handle that never gets fully added.
+        let handle = self.resource_handle_allocators.d2c_texture.allocate(self);
+
+        self.instructions.push(
+            InstructionKind::AddTextureCubeFromEquirect { handle: *handle, src: equirect.get_raw(), face_size },
+            *Location::caller(),
+        );
+
+        Ok(handle)
+    }
+
     /// Adds a material to the renderer. This can be used in an [`Object`].
     ///
     /// The handle will keep the material alive. All objects created with this
@@ -292,13 +483,71 @@ impl Renderer {
         dst_handle
     }
 
-    /// Move the given object to a new transform location.
+    /// Move the given object to a new transform location. If the object has a
+    /// parent, the transform is relative to the parent.
+    ///
+    /// The matrix is used as is, so it can be any affine transform, including
+    /// ones with shear or non-uniform scale which can't be decomposed into a
+    /// translation, rotation and scale.
     #[track_caller]
     pub fn set_object_transform(&self, handle: &ObjectHandle, transform: Mat4) {
         self.instructions
             .push(InstructionKind::SetObjectTransform { handle: handle.get_raw(), transform }, *Location::caller());
     }
 
+    /// Attaches the given object to `parent`, so moving the parent moves the
+    /// object and all its descendants along with it. Passing `None` detaches
+    /// the object.
+    ///
+    /// The object's current transform becomes its transform relative to the
+    /// new parent. Once a parent is removed, its children keep their last
+    /// world transform and no longer have a parent. Parenting an object to
+    /// itself or one of its descendants is ignored with a warning.
+    ///
+    /// Duplicates of an object don't share its parent.
+    #[track_caller]
+    pub fn set_object_parent(&self, handle: &ObjectHandle, parent: Option<&ObjectHandle>) {
+        self.instructions.push(
+            InstructionKind::SetObjectParent { child: handle.get_raw(), parent: parent.map(|parent| parent.get_raw()) },
+            *Location::caller(),
+        );
+    }
+
+    /// Shows or hides the given object. Hidden objects are skipped when culling,
+    /// so they aren't drawn or counted in [`FrameStatistics`], but stay
+    /// resident on the gpu. Objects are visible when added.
+    #[track_caller]
+    pub fn set_object_visibility(&self, handle: &ObjectHandle, visible: bool) {
+        self.instructions
+            .push(InstructionKind::SetObjectVisibility { handle: handle.get_raw(), visible }, *Location::caller());
+    }
+
+    /// Sets the adjustments applied on top of the object's material. Objects
+    /// start out with [`MaterialOverride::IDENTITY`].
+    #[track_caller]
+    pub fn set_object_material_override(&self, handle: &ObjectHandle, material_override: MaterialOverride) {
+        self.instructions.push(
+            InstructionKind::SetObjectMaterialOverride { handle: handle.get_raw(), material_override },
+            *Location::caller(),
+        );
+    }
+
+    /// Returns true if the resource behind the handle has not been removed.
+    ///
+    /// A resource is removed once its last owning handle is dropped and the
+    /// deletion is evaluated. Its index may then be reused by a new resource,
+    /// but raw handles to the old one stay distinguishable from it. Changes
+    /// made through stale raw handles are ignored with a warning.
+    pub fn is_alive<T: AllocatedResource>(&self, handle: RawResourceHandle<T>) -> bool {
+        T::live_handle(self, handle.idx).is_some_and(|live| live.generation == handle.generation)
+    }
+
+    /// Returns the handle of the resource currently using the given index, if
+    /// any. Useful to turn indices read back from the gpu into handles.
+    pub fn live_handle<T: AllocatedResource>(&self, idx: usize) -> Option<RawResourceHandle<T>> {
+        T::live_handle(self, idx)
+    }
+
     /// Sets the joint positions for a skeleton. See
     /// [Renderer::set_skeleton_joint_matrices] to set the vertex
     /// transformations directly, without having to supply two separate
@@ -336,6 +585,18 @@ impl Renderer {
         )
     }
 
+    /// Sets the weight of every morph target of the mesh deformed by a
+    /// skeleton. Missing weights are 0.
+    ///
+    /// Weights are uploaded every frame, so they are cheap to animate.
+    #[track_caller]
+    pub fn set_skeleton_morph_weights(&self, handle: &SkeletonHandle, morph_weights: Vec<f32>) {
+        self.instructions.push(
+            InstructionKind::SetSkeletonMorphWeights { handle: handle.get_raw(), morph_weights },
+            *Location::caller(),
+        )
+    }
+
     /// Add a sun-like light into the world.
     ///
     /// The handle will keep the light alive.
@@ -364,7 +625,43 @@ impl Renderer {
         handle
     }
 
+    /// Add a spot light into the world.
+    ///
+    /// **WARNING**: like point lights, every fragment in the forward pass is
+    /// shaded with every spot light in the world.
+    ///
+    /// The handle will keep the light alive.
+    #[track_caller]
+    pub fn add_spot_light(self: &Arc<Self>, light: SpotLight) -> SpotLightHandle {
+        let handle = self.resource_handle_allocators.spot_light.allocate(self);
+
+        self.instructions.push(InstructionKind::AddSpotLight { handle: *handle, light }, *Location::caller());
+
+        handle
+    }
+
+    /// Add a decal, projecting a texture onto the opaque geometry inside its
+    /// box.
+    ///
+    /// Every decal is drawn separately, so prefer a few large decals over many
+    /// small ones.
+    ///
+    /// The handle will keep the decal alive, and the decal keeps its texture
+    /// alive.
+    #[track_caller]
+    pub fn add_decal(self: &Arc<Self>, decal: Decal) -> DecalHandle {
+        let handle = self.resource_handle_allocators.decal.allocate(self);
+
+        self.instructions.push(InstructionKind::AddDecal { handle: *handle, decal }, *Location::caller());
+
+        handle
+    }
+
     /// Updates the settings for given directional light.
+    ///
+    /// Fields left as `None` in `change` keep their current value. Only the
+    /// parts of the light data which changed are uploaded, so changing the
+    /// color or intensity every frame is cheap.
     #[track_caller]
     pub fn update_directional_light(&self, handle: &DirectionalLightHandle, change: DirectionalLightChange) {
         self.instructions
@@ -378,6 +675,19 @@ impl Renderer {
             .push(InstructionKind::ChangePointLight { handle: handle.get_raw(), change }, *Location::caller())
     }
 
+    /// Updates the settings for given spot light.
+    #[track_caller]
+    pub fn update_spot_light(&self, handle: &SpotLightHandle, change: SpotLightChange) {
+        self.instructions
+            .push(InstructionKind::ChangeSpotLight { handle: handle.get_raw(), change }, *Location::caller())
+    }
+
+    /// Updates the settings for given decal.
+    #[track_caller]
+    pub fn update_decal(&self, handle: &DecalHandle, change: DecalChange) {
+        self.instructions.push(InstructionKind::ChangeDecal { handle: handle.get_raw(), change }, *Location::caller())
+    }
+
     /// Adds a piece of data for long term storage and convienient use in the RenderGraph
     ///
     /// The handle will keep the data alive.
@@ -405,6 +715,53 @@ impl Renderer {
         self.instructions.push(InstructionKind::SetCameraData { data }, *Location::caller())
     }
 
+    /// Replaces the cameras rendering into rectangles of the output, for
+    /// split screen or picture in picture views. They are rendered after the
+    /// viewport camera, in order, on top of it.
+    ///
+    /// All viewports share the shadow maps of the viewport camera.
+    #[track_caller]
+    pub fn set_viewports(&self, viewports: Vec<Viewport>) {
+        self.instructions.push(InstructionKind::SetViewports { viewports }, *Location::caller())
+    }
+
+    /// Returns a future which resolves once the gpu has finished uploading
+    /// every mesh and texture added before this call. Streaming loaders can
+    /// use it to limit how much data is in flight at once.
+    ///
+    /// Uploads are submitted with the next rendered frame, or for textures
+    /// added with [`Self::add_texture_2d_streaming`], with the frame uploading
+    /// the last of their data. The future resolves once that frame's work has
+    /// completed on the gpu, which is noticed when
+    /// the device is polled or a later frame is submitted. It also resolves if
+    /// the instructions are evaluated but the frame is never rendered.
+    #[track_caller]
+    pub fn uploads_done(&self) -> impl Future<Output = ()> + 'static {
+        let (sender, receiver) = flume::bounded(1);
+        self.instructions.push(InstructionKind::UploadFence { sender }, *Location::caller());
+        async move {
+            // A disconnected sender means the frame was dropped, so there is nothing left to wait for.
+            let _ = receiver.recv_async().await;
+        }
+    }
+
+    /// Starts recording an [`InstructionBatch`], which submits many world
+    /// changes with a single lock of the instruction buffer.
+    pub fn batch(self: &Arc<Self>) -> InstructionBatch {
+        InstructionBatch::new(self)
+    }
+
+    /// Appends all instructions recorded in the batch to the instruction
+    /// buffer at once, in the order they were recorded.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the batch was recorded for a different renderer.
+    pub fn submit_batch(&self, mut batch: InstructionBatch) {
+        assert!(batch.is_for(self), "InstructionBatch submitted to a different renderer than it was created by");
+        batch.submit();
+    }
+
     /// Swaps the front and back instruction buffer. Any world-modifiying functions
     /// called after this will be recorded for the next frame.
     ///
@@ -421,4 +778,230 @@ impl Renderer {
     pub fn evaluate_instructions(&self) -> InstructionEvaluationOutput {
         eval::evaluate_instructions(self)
     }
+
+    /// Returns the amount of frames which have been submitted to the gpu, but
+    /// have not yet finished executing.
+    ///
+    /// If this stays at 0 or 1, the application is likely cpu-bound. If it is
+    /// consistently at the maximum amount the surface allows, the application
+    /// is likely gpu-bound.
+    pub fn frames_in_flight(&self) -> u32 {
+        self.frames_in_flight.load(Ordering::Acquire)
+    }
+
+    /// Device all of the renderer's resources are created with.
+    ///
+    /// Use it to create your own resources and pipelines, like custom compute
+    /// passes or those of other wgpu based crates. The renderer doesn't track
+    /// them, so they have to be kept alive for as long as the gpu uses them.
+    pub fn device(&self) -> &Arc<Device> {
+        &self.device
+    }
+
+    /// Queue the renderer submits all of its work to.
+    ///
+    /// Submissions run on the gpu in the order they are made. The uploads of
+    /// [`Self::evaluate_instructions`] are recorded into the frame, and
+    /// [`RenderGraph::execute`](crate::graph::RenderGraph::execute) submits all
+    /// of it at once. Work submitted before that call finishes before the frame
+    /// starts, and work submitted after it sees the finished frame. Writes
+    /// with [`Queue::write_buffer`] and [`Queue::write_texture`] take effect at
+    /// the next submission, whichever submits it.
+    ///
+    /// The queue may be used from any thread, but the buffers and textures of
+    /// the renderer are rewritten every frame, so they shouldn't be written to
+    /// directly.
+    pub fn queue(&self) -> &Arc<Queue> {
+        &self.queue
+    }
+
+    /// Returns why the device was lost, or `None` while it is still usable.
+    ///
+    /// After the device is lost, the renderer is unusable: render graphs are
+    /// no longer executed and resources can't be created on the gpu. Drop the
+    /// renderer and everything created with it, then create a new renderer
+    /// from a new [`InstanceAdapterDevice`].
+    pub fn device_lost(&self) -> Option<DeviceLost> {
+        self.device_lost.get()
+    }
+
+    /// Sets the callback called once the device is lost, replacing the
+    /// previous one. It is called right away if the device is already lost.
+    ///
+    /// The callback may be called from any thread, possibly while the renderer
+    /// is in the middle of a frame, so it shouldn't call into the renderer.
+    /// Recreate the renderer on the thread which renders instead, see
+    /// [`Self::device_lost`].
+    pub fn set_device_lost_callback(&self, callback: impl Fn(&DeviceLost) + Send + 'static) {
+        self.device_lost.set_callback(Box::new(callback));
+    }
+
+    /// Returns a summary of the statistics of every frame since the renderer
+    /// was created or [`Self::reset_accumulated_stats`] was last called.
+    ///
+    /// Frame times only cover the most recent frames, see
+    /// [`Self::set_accumulated_stats_window`]. They are only available when the
+    /// device supports timestamp queries, and arrive a few frames after the
+    /// frame was rendered.
+    pub fn accumulated_stats(&self) -> AccumulatedStatistics {
+        self.stats_accumulator.lock().summary()
+    }
+
+    /// Clears all accumulated statistics.
+    pub fn reset_accumulated_stats(&self) {
+        self.stats_accumulator.lock().reset();
+    }
+
+    /// Sets the amount of most recent frames whose frame times are kept for
+    /// [`Self::accumulated_stats`]. Defaults to
+    /// [`DEFAULT_STATS_WINDOW`](crate::util::stats::DEFAULT_STATS_WINDOW).
+    pub fn set_accumulated_stats_window(&self, frames: usize) {
+        self.stats_accumulator.lock().set_window(frames);
+    }
+
+    /// Returns the object counts and cpu timings of the most recently
+    /// submitted frame.
+    pub fn last_frame_stats(&self) -> FrameStatistics {
+        self.last_frame_stats.lock().clone()
+    }
+
+    /// Returns the split distances and world space coverage of every cascade
+    /// of every directional light's shadow.
+    ///
+    /// This reflects the camera and lights as of the last call to
+    /// [`Self::evaluate_instructions`].
+    pub fn shadow_cascade_info(&self) -> Vec<ShadowCascadeInfo> {
+        self.data_core.lock().directional_light_manager.cascade_info().to_vec()
+    }
+
+    /// Returns true once all data of the 2D texture is uploaded, see
+    /// [`Self::add_texture_2d_streaming`]. Textures added any other way are
+    /// ready as soon as the frame they were added in is rendered.
+    ///
+    /// Like [`Self::shadow_cascade_info`], this reflects the textures as of the
+    /// last call to [`Self::evaluate_instructions`].
+    pub fn texture_2d_ready(&self, handle: &Texture2DHandle) -> bool {
+        self.data_core.lock().d2_texture_manager.ready(handle.get_raw())
+    }
+
+    /// Returns the 2D textures whose data is still being uploaded, in the
+    /// order they will finish.
+    pub fn streaming_textures_2d(&self) -> Vec<RawTexture2DHandle> {
+        self.data_core.lock().d2_texture_manager.streaming().collect()
+    }
+
+    /// Returns the world space bounding box of an object's mesh.
+    ///
+    /// Like [`Self::shadow_cascade_info`], this reflects the object as of the
+    /// last call to [`Self::evaluate_instructions`], so transform changes only
+    /// show up once the frame they were issued in is rendered.
+    pub fn object_bounds(&self, handle: &ObjectHandle) -> Option<Aabb> {
+        self.data_core.lock().object_manager.bounds(handle.get_raw())
+    }
+
+    /// Returns the amount of objects which weren't removed.
+    ///
+    /// Like [`Self::object_bounds`], this reflects the objects as of the last
+    /// call to [`Self::evaluate_instructions`].
+    pub fn object_count(&self) -> usize {
+        self.data_core.lock().object_manager.handles().count()
+    }
+
+    /// Returns the handles of all objects which weren't removed, in no
+    /// particular order, as of the last call to [`Self::evaluate_instructions`].
+    ///
+    /// The handles don't keep the objects alive.
+    pub fn objects(&self) -> Vec<RawObjectHandle> {
+        self.data_core.lock().object_manager.handles().collect()
+    }
+
+    /// Returns a copy of the object's state, or `None` if it was removed, as of
+    /// the last call to [`Self::evaluate_instructions`].
+    pub fn object(&self, handle: RawObjectHandle) -> Option<ObjectSnapshot> {
+        self.data_core.lock().object_manager.snapshot(handle)
+    }
+
+    /// Returns a copy of the properties of the material shared by all
+    /// material types, or `None` if it was removed, as of the last call to
+    /// [`Self::evaluate_instructions`].
+    pub fn material(&self, handle: RawMaterialHandle) -> Option<MaterialSnapshot> {
+        self.data_core.lock().material_manager.snapshot(handle)
+    }
+
+    /// Copies the first mip level and array layer of a texture back to cpu
+    /// memory. Use this after rendering a frame to take a screenshot of the
+    /// texture it was rendered to.
+    ///
+    /// The texture must have [`TextureUsages::COPY_SRC`](wgpu::TextureUsages::COPY_SRC).
+    /// The copy is submitted immediately, and the future resolves to the
+    /// texels in the texture's format, with rows tightly packed. On native,
+    /// polling the future blocks until the gpu has finished the copy.
+    pub fn read_texture(
+        &self,
+        texture: &wgpu::Texture,
+    ) -> impl Future<Output = Result<Vec<u8>, ReadbackError>> + 'static {
+        let readback = PendingReadback::new(&self.device, &self.queue, texture);
+        let device = Arc::clone(&self.device);
+        async move { readback?.finish(&device).await }
+    }
+
+    /// Returns `samples` if the adapter supports multisampling the color and
+    /// depth targets with it. Otherwise warns and returns [`SampleCount::One`].
+    pub fn validate_sample_count(&self, samples: SampleCount) -> SampleCount {
+        if samples as u8 <= self.max_sample_count as u8 {
+            return samples;
+        }
+
+        log::warn!(
+            "Sample count {} is not supported by adapter {}, falling back to 1",
+            samples as u8,
+            self.adapter_info.name
+        );
+        SampleCount::One
+    }
+
+    /// Returns `wireframe` if the device supports rendering wireframes. Otherwise
+    /// warns and returns `false`, falling back to filled triangles.
+    pub fn validate_wireframe(&self, wireframe: bool) -> bool {
+        if !wireframe || self.features.contains(Features::POLYGON_MODE_LINE) {
+            return wireframe;
+        }
+
+        log::warn!(
+            "Wireframe rendering needs Features::POLYGON_MODE_LINE, which adapter {} does not support, falling back to filled triangles",
+            self.adapter_info.name
+        );
+        false
+    }
 }
+
+/// Resources whose handles can be checked with [`Renderer::is_alive`].
+pub trait AllocatedResource: Sized {
+    #[doc(hidden)]
+    fn live_handle(renderer: &Renderer, idx: usize) -> Option<RawResourceHandle<Self>>;
+}
+
+macro_rules! allocated_resource {
+    ($($ty:ty => $allocator:ident),* $(,)?) => {$(
+        impl AllocatedResource for $ty {
+            fn live_handle(renderer: &Renderer, idx: usize) -> Option<RawResourceHandle<Self>> {
+                renderer.resource_handle_allocators.$allocator.live_handle(idx)
+            }
+        }
+    )*};
+}
+
+allocated_resource!(
+    Mesh => mesh,
+    Skeleton => skeleton,
+    Texture2DTag => d2_texture,
+    TextureCubeTag => d2c_texture,
+    Texture3DTag => d3_texture,
+    MaterialTag => material,
+    Object => object,
+    DirectionalLight => directional_light,
+    PointLight => point_light,
+    SpotLight => spot_light,
+    Decal => decal,
+    GraphDataTag => graph_storage,
+);
@@ -1,25 +1,33 @@
-use std::sync::Arc;
+use std::sync::{
+    atomic::{AtomicU32, AtomicU64},
+    Arc,
+};
 
 use parking_lot::Mutex;
-use rend3_types::{Camera, Handedness, TextureFormat};
-use wgpu::TextureViewDimension;
+use rend3_types::{Camera, CoordinateSystem, SampleCount, TextureFormat};
+use wgpu::{Adapter, Features, TextureViewDimension};
 use wgpu_profiler::GpuProfilerSettings;
 
 use crate::{
     graph::GraphTextureStore,
     instruction::InstructionStreamPair,
     managers::{
-        CameraState, DirectionalLightManager, GraphStorage, MaterialManager, MeshManager, ObjectManager,
-        PointLightManager, SkeletonManager, TextureManager,
+        CameraState, DecalManager, DirectionalLightManager, GraphStorage, MaterialManager, MeshManager, ObjectManager,
+        PointLightManager, SkeletonManager, SpotLightManager, TextureManager,
+    },
+    renderer::{device_lost::DeviceLostState, HandleAllocators, RendererDataCore},
+    util::{
+        equirect::EquirectConverter,
+        mipmap::MipmapGenerator,
+        scatter_copy::ScatterCopy,
+        stats::{FrameStatistics, StatisticsAccumulator},
     },
-    renderer::{HandleAllocators, RendererDataCore},
-    util::{mipmap::MipmapGenerator, scatter_copy::ScatterCopy},
     InstanceAdapterDevice, Renderer, RendererInitializationError,
 };
 
 pub fn create_renderer(
     iad: InstanceAdapterDevice,
-    handedness: Handedness,
+    coordinate_system: CoordinateSystem,
     aspect_ratio: Option<f32>,
 ) -> Result<Arc<Renderer>, RendererInitializationError> {
     profiling::scope!("Renderer::new");
@@ -27,8 +35,9 @@ pub fn create_renderer(
     let features = iad.device.features();
     let limits = iad.device.limits();
     let downlevel = iad.adapter.get_downlevel_capabilities();
+    let max_sample_count = max_sample_count(&iad.adapter, features);
 
-    let camera_state = CameraState::new(Camera::default(), handedness, aspect_ratio);
+    let camera_state = CameraState::new(Camera::default(), coordinate_system, aspect_ratio);
 
     let d2_texture_manager = TextureManager::new(
         &iad.device,
@@ -42,11 +51,19 @@ pub fn create_renderer(
         limits.max_sampled_textures_per_shader_stage,
         TextureViewDimension::Cube,
     );
+    let d3_texture_manager = TextureManager::new(
+        &iad.device,
+        iad.profile,
+        limits.max_sampled_textures_per_shader_stage,
+        TextureViewDimension::D3,
+    );
     let mesh_manager = MeshManager::new(&iad.device);
     let material_manager = MaterialManager::new(&iad.device);
     let object_manager = ObjectManager::new();
     let directional_light_manager = DirectionalLightManager::new(&iad.device);
     let point_light_manager = PointLightManager::new(&iad.device);
+    let spot_light_manager = SpotLightManager::new(&iad.device);
+    let decal_manager = DecalManager::new(&iad.device);
     let skeleton_manager = SkeletonManager::new();
     let graph_storage = GraphStorage::new();
 
@@ -70,8 +87,12 @@ pub fn create_renderer(
         .map_err(RendererInitializationError::GpuProfilerCreation)?,
     );
 
+    let equirect_converter = EquirectConverter::new(&iad.device);
+
     let scatter = ScatterCopy::new(&iad.device);
 
+    let device_lost = DeviceLostState::new(&iad.device, iad.info.backend);
+
     Ok(Arc::new(Renderer {
         instructions: InstructionStreamPair::new(),
 
@@ -83,18 +104,24 @@ pub fn create_renderer(
         features,
         limits,
         downlevel,
-        handedness,
+        handedness: coordinate_system.handedness,
+        coordinate_system,
+        max_sample_count,
 
         resource_handle_allocators: HandleAllocators::default(),
         mesh_manager,
         data_core: Mutex::new(RendererDataCore {
             viewport_camera_state: camera_state,
+            viewports: Vec::new(),
             d2_texture_manager,
             d2c_texture_manager,
+            d3_texture_manager,
             material_manager,
             object_manager,
             directional_light_manager,
             point_light_manager,
+            spot_light_manager,
+            decal_manager,
             skeleton_manager,
             graph_storage,
             profiler,
@@ -102,6 +129,34 @@ pub fn create_renderer(
         }),
 
         mipmap_generator,
+        equirect_converter,
         scatter,
+
+        frames_in_flight: Arc::new(AtomicU32::new(0)),
+        frame_counter: AtomicU64::new(0),
+        stats_accumulator: Mutex::new(StatisticsAccumulator::new()),
+        last_frame_stats: Mutex::new(FrameStatistics::default()),
+        device_lost,
     }))
 }
+
+/// Finds the highest sample count both the hdr color and depth targets can be
+/// multisampled with.
+fn max_sample_count(adapter: &Adapter, features: Features) -> SampleCount {
+    let supports = |format: TextureFormat, samples: SampleCount| {
+        let format_features = if features.contains(Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES) {
+            adapter.get_texture_format_features(format)
+        } else {
+            format.guaranteed_format_features(features)
+        };
+        format_features.flags.sample_count_supported(samples as u32)
+    };
+
+    SampleCount::ARRAY
+        .into_iter()
+        .rev()
+        .find(|&samples| {
+            supports(TextureFormat::Rgba16Float, samples) && supports(TextureFormat::Depth32Float, samples)
+        })
+        .unwrap_or(SampleCount::One)
+}
@@ -4,7 +4,7 @@ use std::sync::Arc;
 
 use wgpu::{
     Adapter, AdapterInfo, Backend, Backends, BufferAddress, Device, DeviceDescriptor, DeviceType, Features,
-    Gles3MinorVersion, Instance, InstanceFlags, Limits, Queue,
+    Gles3MinorVersion, Instance, InstanceFlags, Limits, Queue, RequestDeviceError,
 };
 
 #[allow(unused_imports)]
@@ -43,7 +43,9 @@ pub const OPTIONAL_FEATURES: Features = Features::from_bits_truncate(
         | Features::TEXTURE_COMPRESSION_ETC2.bits()
         | Features::TEXTURE_COMPRESSION_ASTC.bits()
         | Features::TIMESTAMP_QUERY.bits()
-        | Features::TIMESTAMP_QUERY_INSIDE_PASSES.bits(),
+        | Features::TIMESTAMP_QUERY_INSIDE_PASSES.bits()
+        | Features::POLYGON_MODE_LINE.bits()
+        | Features::DEPTH32FLOAT_STENCIL8.bits(),
 );
 
 /// Check that all required features for a given profile are present in the feature
@@ -414,6 +416,12 @@ pub struct InstanceAdapterDevice {
 /// Creates an Instance/Adapter/Device/Queue using the given choices. Tries to
 /// get the best combination.
 ///
+/// If `desired_profile` is `None`, the best profile the adapter supports is
+/// used. If the adapter advertises everything the GpuDriven profile needs, but
+/// creating a device with them fails anyway, the device is created again in
+/// the CpuDriven profile. The chosen profile is reported in
+/// [`InstanceAdapterDevice::profile`].
+///
 /// **NOTE:** Some adapters will not advertise all of its supported features.
 /// The `additional_features` parameter can be used to explicitly request
 /// additional features during device creation.
@@ -504,25 +512,33 @@ pub async fn create_iad(
         let adapter: Option<PotentialAdapter<Adapter>> =
             valid_adapters.remove(backend).and_then(|arr| arr.into_iter().next());
 
-        if let Some(adapter) = adapter {
+        if let Some(mut adapter) = adapter {
             log::debug!("Chosen adapter: {:#?}", adapter.info);
             log::debug!("Chosen backend: {:?}", backend);
             log::debug!("Chosen features: {:#?}", adapter.features);
             log::debug!("Chosen limits: {:#?}", adapter.limits);
             log::debug!("Chosen profile: {:#?}", adapter.profile);
 
-            let (device, queue) = adapter
-                .inner
-                .request_device(
-                    &DeviceDescriptor {
-                        label: None,
-                        required_features: adapter.features.union(additional_features.unwrap_or_else(Features::empty)),
-                        required_limits: adapter.limits,
-                    },
-                    None,
-                )
-                .await
-                .map_err(|_| RendererInitializationError::RequestDeviceFailed)?;
+            let additional_features = additional_features.unwrap_or_else(Features::empty);
+
+            let (device, queue) =
+                match request_device(&adapter.inner, adapter.features | additional_features, adapter.limits.clone())
+                    .await
+                {
+                    Ok(device_queue) => device_queue,
+                    Err(error) if adapter.profile == RendererProfile::GpuDriven && desired_profile.is_none() => {
+                        log::warn!("Requesting a GpuDriven device failed with {error}, falling back to CpuDriven");
+
+                        adapter.features = check_features(RendererProfile::CpuDriven, adapter.inner.features())?;
+                        adapter.limits = check_limits(RendererProfile::CpuDriven, &adapter.inner.limits())?;
+                        adapter.profile = RendererProfile::CpuDriven;
+
+                        request_device(&adapter.inner, adapter.features | additional_features, adapter.limits.clone())
+                            .await
+                            .map_err(|_| RendererInitializationError::RequestDeviceFailed)?
+                    }
+                    Err(_) => return Err(RendererInitializationError::RequestDeviceFailed),
+                };
 
             return Ok(InstanceAdapterDevice {
                 instance: Arc::new(instance),
@@ -537,3 +553,11 @@ pub async fn create_iad(
 
     Err(RendererInitializationError::MissingAdapter)
 }
+
+async fn request_device(
+    adapter: &Adapter,
+    required_features: Features,
+    required_limits: Limits,
+) -> Result<(Device, Queue), RequestDeviceError> {
+    adapter.request_device(&DeviceDescriptor { label: None, required_features, required_limits }, None).await
+}
@@ -3,6 +3,8 @@
 use std::{
     collections::{HashMap, HashSet},
     fmt::Write,
+    io,
+    path::Path,
 };
 
 use handlebars::{Context, Handlebars, Helper, HelperDef, Output, RenderContext, RenderError, RenderErrorReason};
@@ -63,6 +65,25 @@ impl ShaderPreProcessor {
         }
     }
 
+    /// Registers all files in the directory at `path` and its subdirectories
+    /// under `prefix`, replacing shaders of the same name.
+    ///
+    /// Reading the shaders of a crate from its source directory, like
+    /// `add_shaders_directory("rend3-routine", "rend3-routine/shaders/src")`,
+    /// lets them be edited and reloaded without restarting.
+    pub fn add_shaders_directory(&mut self, prefix: &str, path: impl AsRef<Path>) -> io::Result<()> {
+        for entry in std::fs::read_dir(path)? {
+            let entry = entry?;
+            let name = format!("{prefix}/{}", entry.file_name().to_string_lossy());
+            if entry.file_type()?.is_dir() {
+                self.add_shaders_directory(&name, entry.path())?;
+            } else {
+                self.files.insert(name, std::fs::read_to_string(entry.path())?);
+            }
+        }
+        Ok(())
+    }
+
     pub fn add_shader(&mut self, name: &str, contents: &str) {
         self.files.insert(name.to_owned(), contents.to_owned());
     }
@@ -1,6 +1,6 @@
 use glam::UVec2;
 use rend3_types::{TextureFormat, TextureUsages};
-use wgpu::{CompositeAlphaMode, Device, SurfaceConfiguration};
+use wgpu::{Adapter, CompositeAlphaMode, Device, SurfaceConfiguration};
 
 use crate::types::{PresentMode, Surface};
 
@@ -26,3 +26,18 @@ pub fn configure_surface(
         },
     )
 }
+
+/// Returns `present_mode` if the surface supports it on the given adapter.
+/// Otherwise warns and returns [`PresentMode::Fifo`], which every surface
+/// supports.
+pub fn validate_present_mode(surface: &Surface, adapter: &Adapter, present_mode: PresentMode) -> PresentMode {
+    // The automatic modes always resolve to a supported mode.
+    if matches!(present_mode, PresentMode::AutoVsync | PresentMode::AutoNoVsync)
+        || surface.get_capabilities(adapter).present_modes.contains(&present_mode)
+    {
+        return present_mode;
+    }
+
+    log::warn!("Present mode {present_mode:?} is not supported by the surface, falling back to Fifo");
+    PresentMode::Fifo
+}
@@ -0,0 +1,196 @@
+//! Bind groups reused from frame to frame.
+
+use std::{num::NonZeroU64, sync::Arc};
+
+use parking_lot::Mutex;
+use wgpu::{
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindingResource, Buffer, BufferBinding, Device,
+    Id, Sampler, TextureView,
+};
+
+use crate::util::typedefs::FastHashMap;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum ResourceKey {
+    Buffer(BufferKey),
+    BufferArray(Vec<BufferKey>),
+    Sampler(Id<Sampler>),
+    SamplerArray(Vec<Id<Sampler>>),
+    TextureView(Id<TextureView>),
+    TextureViewArray(Vec<Id<TextureView>>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct BufferKey {
+    buffer: Id<Buffer>,
+    offset: u64,
+    size: Option<NonZeroU64>,
+}
+
+impl BufferKey {
+    fn new(binding: &BufferBinding<'_>) -> Self {
+        Self { buffer: binding.buffer.global_id(), offset: binding.offset, size: binding.size }
+    }
+}
+
+impl ResourceKey {
+    /// `None` for resources added in newer wgpu versions, which aren't cached.
+    fn new(resource: &BindingResource<'_>) -> Option<Self> {
+        Some(match resource {
+            BindingResource::Buffer(binding) => Self::Buffer(BufferKey::new(binding)),
+            BindingResource::BufferArray(bindings) => Self::BufferArray(bindings.iter().map(BufferKey::new).collect()),
+            BindingResource::Sampler(sampler) => Self::Sampler(sampler.global_id()),
+            BindingResource::SamplerArray(samplers) => {
+                Self::SamplerArray(samplers.iter().map(|sampler| sampler.global_id()).collect())
+            }
+            BindingResource::TextureView(view) => Self::TextureView(view.global_id()),
+            BindingResource::TextureViewArray(views) => {
+                Self::TextureViewArray(views.iter().map(|view| view.global_id()).collect())
+            }
+            _ => return None,
+        })
+    }
+}
+
+/// Identifies a bind group by its layout and the resources bound to it.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct BindGroupKey {
+    layout: Id<BindGroupLayout>,
+    entries: Vec<(u32, ResourceKey)>,
+}
+
+struct CachedBindGroup {
+    bind_group: Arc<BindGroup>,
+    /// Last frame the bind group was handed out in.
+    frame: u64,
+}
+
+#[derive(Default)]
+struct CacheState {
+    /// Frame of the most recent call to [`BindGroupCache::get`].
+    frame: u64,
+    bind_groups: FastHashMap<BindGroupKey, CachedBindGroup>,
+}
+
+/// Cache of bind groups built every frame from the same resources, like the
+/// per-camera bind groups of every shadow casting light, handing out the bind
+/// group built in an earlier frame instead of creating a new one.
+///
+/// Bind groups are identified by their layout and the ids of their resources.
+/// A cached bind group keeps its resources alive, so their ids can't be reused
+/// by other resources while it's cached. Bind groups which weren't handed out
+/// in the previous frame are dropped once a new frame starts using the cache.
+#[derive(Default)]
+pub struct BindGroupCache {
+    state: Mutex<CacheState>,
+}
+
+impl BindGroupCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Gets the bind group with the given layout and entries, creating it if
+    /// it isn't cached. `frame` is the index of the current frame, usually
+    /// [`InstructionEvaluationOutput::frame`](crate::graph::InstructionEvaluationOutput::frame).
+    pub fn get(
+        &self,
+        device: &Device,
+        frame: u64,
+        label: Option<&str>,
+        layout: &BindGroupLayout,
+        entries: &[BindGroupEntry<'_>],
+    ) -> Arc<BindGroup> {
+        let create = || Arc::new(device.create_bind_group(&BindGroupDescriptor { label, layout, entries }));
+
+        let Some(entries) =
+            entries.iter().map(|entry| Some((entry.binding, ResourceKey::new(&entry.resource)?))).collect()
+        else {
+            return create();
+        };
+        let key = BindGroupKey { layout: layout.global_id(), entries };
+
+        let mut state = self.state.lock();
+        if frame != state.frame {
+            let previous = state.frame;
+            state.bind_groups.retain(|_, cached| cached.frame >= previous);
+            state.frame = frame;
+        }
+
+        let cached = state.bind_groups.entry(key).or_insert_with(|| {
+            profiling::scope!("BindGroupCache::get create");
+            CachedBindGroup { bind_group: create(), frame }
+        });
+        cached.frame = frame;
+        Arc::clone(&cached.bind_group)
+    }
+
+    /// Amount of bind groups in the cache.
+    pub fn len(&self) -> usize {
+        self.state.lock().bind_groups.len()
+    }
+
+    /// Returns true if the cache holds no bind groups.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use wgpu::{BindingType, BufferBindingType, BufferDescriptor, BufferUsages, ShaderStages};
+
+    use super::BindGroupCache;
+    use crate::util::bind_merge::{BindGroupBuilder, BindGroupLayoutBuilder};
+
+    #[test]
+    fn reuses() {
+        let backends = wgpu::util::backend_bits_from_env().unwrap_or(wgpu::Backends::all());
+        let instance =
+            wgpu::Instance::new(wgpu::InstanceDescriptor { backends, ..wgpu::InstanceDescriptor::default() });
+        let Some(adapter) = pollster::block_on(wgpu::util::initialize_adapter_from_env_or_default(&instance, None))
+        else {
+            return;
+        };
+        let Ok((device, _queue)) = pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor::default(), None))
+        else {
+            return;
+        };
+
+        let bgl = BindGroupLayoutBuilder::new()
+            .append(
+                ShaderStages::COMPUTE,
+                BindingType::Buffer {
+                    ty: BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                None,
+            )
+            .build(&device, None);
+        let buffer = |size| {
+            device.create_buffer(&BufferDescriptor {
+                label: None,
+                size,
+                usage: BufferUsages::UNIFORM,
+                mapped_at_creation: false,
+            })
+        };
+        let (first, second) = (buffer(256), buffer(256));
+
+        let cache = BindGroupCache::new();
+        let a = BindGroupBuilder::new().append_buffer(&first).build_cached(&cache, &device, 0, None, &bgl);
+        let b = BindGroupBuilder::new().append_buffer(&first).build_cached(&cache, &device, 0, None, &bgl);
+        assert!(Arc::ptr_eq(&a, &b));
+        let c = BindGroupBuilder::new().append_buffer(&second).build_cached(&cache, &device, 1, None, &bgl);
+        assert!(!Arc::ptr_eq(&a, &c));
+        assert_eq!(cache.len(), 2);
+
+        // Bind groups unused in the previous frame are dropped.
+        BindGroupBuilder::new().append_buffer(&second).build_cached(&cache, &device, 2, None, &bgl);
+        BindGroupBuilder::new().append_buffer(&second).build_cached(&cache, &device, 3, None, &bgl);
+        assert_eq!(cache.len(), 1);
+    }
+}
@@ -1,13 +1,18 @@
 //! Builders for BindGroup and BindGroupLayouts.
 //!
 //! Automates some boilerplate including index generation.
-use std::num::{NonZeroU32, NonZeroU64};
+use std::{
+    num::{NonZeroU32, NonZeroU64},
+    sync::Arc,
+};
 
 use wgpu::{
     BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry,
     BindingResource, BindingType, Buffer, BufferBinding, BufferBindingType, Device, Sampler, ShaderStages, TextureView,
 };
 
+use crate::util::bind_group_cache::BindGroupCache;
+
 /// Builder for BindGroupLayouts.
 pub struct BindGroupLayoutBuilder {
     bgl_entries: Vec<BindGroupLayoutEntry>,
@@ -100,6 +105,19 @@ impl<'a> BindGroupBuilder<'a> {
     pub fn build(&self, device: &Device, label: Option<&str>, bgl: &BindGroupLayout) -> BindGroup {
         device.create_bind_group(&BindGroupDescriptor { label, layout: bgl, entries: &self.bg_entries })
     }
+
+    /// Builds the bind group through `cache`, reusing the bind group built with
+    /// the same resources in an earlier frame. See [`BindGroupCache::get`].
+    pub fn build_cached(
+        &self,
+        cache: &BindGroupCache,
+        device: &Device,
+        frame: u64,
+        label: Option<&str>,
+        bgl: &BindGroupLayout,
+    ) -> Arc<BindGroup> {
+        cache.get(device, frame, label, bgl, &self.bg_entries)
+    }
 }
 
 impl<'a> Default for BindGroupBuilder<'a> {
@@ -1,17 +1,28 @@
 //! Automatic management of Power-of-Two sized buffers.
 
-use std::{marker::PhantomData, ops::Deref, sync::Arc};
+use std::{
+    marker::PhantomData,
+    ops::{Deref, Range},
+    sync::Arc,
+};
 
 use encase::{private::WriteInto, ShaderType};
-use wgpu::{Buffer, BufferAddress, BufferDescriptor, BufferUsages, Device, Queue};
+use wgpu::{Buffer, BufferAddress, BufferDescriptor, BufferUsages, Device, Queue, COPY_BUFFER_ALIGNMENT};
 
 use crate::util::typedefs::SsoString;
 
+/// Changed ranges closer together than this many bytes are uploaded as one
+/// write, as every write has a fixed cost.
+const MERGE_DISTANCE: usize = 64;
+
 /// Creates, fills, and automatically resizes a power-of-two sized buffer.
 #[derive(Debug)]
 pub struct WrappedPotBuffer<T> {
     inner: Arc<Buffer>,
     size: BufferAddress,
+    /// Contents of the buffer as of the last call to
+    /// [`WrappedPotBuffer::write_changed_to_buffer`], empty if it is unknown.
+    written: Vec<u8>,
     // This field is assumed to be a power of 2.
     minimum: BufferAddress,
     usage: BufferUsages,
@@ -38,6 +49,7 @@ where
                 mapped_at_creation: false,
             })),
             size: minimum,
+            written: Vec::new(),
             minimum,
             usage,
             label: SsoString::from(label),
@@ -49,6 +61,7 @@ where
         let resize = resize_po2(self.size, desired, self.minimum);
         if let Some(size) = resize {
             self.size = size;
+            self.written.clear();
             self.inner = Arc::new(device.create_buffer(&BufferDescriptor {
                 label: Some(&self.label),
                 size,
@@ -65,6 +78,21 @@ where
         let mut mapped = queue.write_buffer_with(&self.inner, 0, size).unwrap();
         encase::StorageBuffer::new(&mut *mapped).write(data).unwrap();
         drop(mapped);
+        self.written.clear();
+    }
+
+    /// Like [`WrappedPotBuffer::write_to_buffer`], but only uploads the bytes
+    /// which changed since the last call. Everything is uploaded if the buffer
+    /// got reallocated or was written to in any other way since.
+    pub fn write_changed_to_buffer(&mut self, device: &Device, queue: &Queue, data: &T) {
+        let mut bytes = Vec::with_capacity(data.size().get() as usize);
+        encase::StorageBuffer::new(&mut bytes).write(data).unwrap();
+        self.ensure_size(device, bytes.len() as BufferAddress);
+
+        for range in changed_ranges(&self.written, &bytes) {
+            queue.write_buffer(&self.inner, range.start as BufferAddress, &bytes[range]);
+        }
+        self.written = bytes;
     }
 }
 
@@ -76,6 +104,24 @@ impl<T> Deref for WrappedPotBuffer<T> {
     }
 }
 
+/// Ranges of `new` which differ from `old`, aligned to what
+/// [`Queue::write_buffer`] accepts. Bytes past the end of `old` always differ.
+fn changed_ranges(old: &[u8], new: &[u8]) -> Vec<Range<usize>> {
+    let align = COPY_BUFFER_ALIGNMENT as usize;
+    let mut ranges: Vec<Range<usize>> = Vec::new();
+    for start in (0..new.len()).step_by(align) {
+        let end = (start + align).min(new.len());
+        if old.get(start..end) == Some(&new[start..end]) {
+            continue;
+        }
+        match ranges.last_mut() {
+            Some(last) if start - last.end < MERGE_DISTANCE => last.end = end,
+            _ => ranges.push(start..end),
+        }
+    }
+    ranges
+}
+
 fn resize_po2(current: BufferAddress, desired: BufferAddress, minimum: BufferAddress) -> Option<BufferAddress> {
     assert!(current.is_power_of_two());
     if current == minimum && desired <= minimum {
@@ -91,7 +137,7 @@ fn resize_po2(current: BufferAddress, desired: BufferAddress, minimum: BufferAdd
 
 #[cfg(test)]
 mod test {
-    use super::resize_po2;
+    use super::{changed_ranges, resize_po2};
 
     #[test]
     fn automated_buffer_resize() {
@@ -109,4 +155,23 @@ mod test {
         assert_eq!(resize_po2(16, 8, 0), None);
         assert_eq!(resize_po2(16, 4, 0), Some(8));
     }
+
+    #[test]
+    fn changed_byte_ranges() {
+        let old = [0_u8; 256];
+        assert_eq!(changed_ranges(&old, &old), []);
+        assert_eq!(changed_ranges(&[], &old), vec![0..256]);
+
+        let mut new = old;
+        new[5] = 1;
+        new[200] = 1;
+        assert_eq!(changed_ranges(&old, &new), [4..8, 200..204]);
+
+        // Close changes are uploaded together.
+        new[40] = 1;
+        assert_eq!(changed_ranges(&old, &new), [4..44, 200..204]);
+
+        // Growing uploads the new tail.
+        assert_eq!(changed_ranges(&old[..128], &old), vec![128..256]);
+    }
 }
@@ -0,0 +1,121 @@
+//! Buffers recycled from frame to frame.
+
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+use wgpu::{Buffer, BufferAddress, BufferDescriptor, BufferUsages, Device};
+
+use crate::util::typedefs::SsoString;
+
+/// Smallest buffer the pool creates.
+const MINIMUM_SIZE: BufferAddress = 256;
+
+/// Pool of buffers which are filled and used within a single frame, handing
+/// the same buffers out again in later frames instead of creating new ones
+/// every frame.
+///
+/// A buffer is handed out again once all the [`Arc`]s returned for it are
+/// dropped, so keep one alive until the commands using it are submitted, for
+/// example by adding it to the temporaries of the render graph node using it.
+/// Buffers are created with [`BufferUsages::COPY_DST`] and should be filled
+/// with [`Queue::write_buffer`](wgpu::Queue::write_buffer).
+pub struct BufferPool {
+    label: SsoString,
+    usage: BufferUsages,
+    buffers: Mutex<Vec<Arc<Buffer>>>,
+}
+
+impl BufferPool {
+    pub fn new(label: &str, usage: BufferUsages) -> Self {
+        Self { label: SsoString::from(label), usage: usage | BufferUsages::COPY_DST, buffers: Mutex::default() }
+    }
+
+    /// Gets an unused buffer of at least `size` bytes, creating one if there
+    /// is none. Sizes are rounded up to a power of two, so buffers of
+    /// slightly different sizes can be reused.
+    pub fn get(&self, device: &Device, size: BufferAddress) -> Arc<Buffer> {
+        let size = pool_size(size);
+
+        let mut buffers = self.buffers.lock();
+        // Only the pool holds unused buffers. Nobody else can clone them, so
+        // they stay unused until they are handed out here.
+        if let Some(buffer) = buffers.iter().find(|buffer| buffer.size() == size && Arc::strong_count(buffer) == 1) {
+            return Arc::clone(buffer);
+        }
+
+        profiling::scope!("BufferPool::get create");
+        let buffer = Arc::new(device.create_buffer(&BufferDescriptor {
+            label: Some(&self.label),
+            size,
+            usage: self.usage,
+            mapped_at_creation: false,
+        }));
+        buffers.push(Arc::clone(&buffer));
+        buffer
+    }
+
+    /// Amount of buffers the pool has created.
+    pub fn len(&self) -> usize {
+        self.buffers.lock().len()
+    }
+
+    /// Returns true if the pool hasn't created any buffers.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+fn pool_size(size: BufferAddress) -> BufferAddress {
+    size.next_power_of_two().max(MINIMUM_SIZE)
+}
+
+#[cfg(test)]
+mod test {
+    use wgpu::BufferUsages;
+
+    use super::{pool_size, BufferPool};
+
+    #[test]
+    fn sizes() {
+        assert_eq!(pool_size(1), 256);
+        assert_eq!(pool_size(256), 256);
+        assert_eq!(pool_size(257), 512);
+        assert_eq!(pool_size(3000), 4096);
+    }
+
+    #[test]
+    fn recycles() {
+        let backends = wgpu::util::backend_bits_from_env().unwrap_or(wgpu::Backends::all());
+        let instance =
+            wgpu::Instance::new(wgpu::InstanceDescriptor { backends, ..wgpu::InstanceDescriptor::default() });
+        let Some(adapter) = pollster::block_on(wgpu::util::initialize_adapter_from_env_or_default(&instance, None))
+        else {
+            return;
+        };
+        let Ok((device, _queue)) = pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor::default(), None))
+        else {
+            return;
+        };
+
+        let pool = BufferPool::new("test pool", BufferUsages::UNIFORM);
+
+        let first = pool.get(&device, 100);
+        let second = pool.get(&device, 200);
+        assert_eq!(first.size(), 256);
+        assert_eq!(first.usage(), BufferUsages::UNIFORM | BufferUsages::COPY_DST);
+        assert_eq!(pool.len(), 2);
+        drop((first, second));
+
+        // Both buffers are handed out again.
+        let first = pool.get(&device, 50);
+        let second = pool.get(&device, 256);
+        assert_eq!(pool.len(), 2);
+
+        // Buffers in use, or of other sizes, aren't.
+        let third = pool.get(&device, 256);
+        let large = pool.get(&device, 1000);
+        assert_eq!(large.size(), 1024);
+        assert_eq!(pool.len(), 4);
+        drop((first, second, third, large));
+    }
+}
@@ -0,0 +1,209 @@
+//! Conversion of equirectangular environment maps into cubemaps.
+
+use std::sync::Arc;
+
+use wgpu::{
+    AddressMode, BindGroup, BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingType, Color,
+    ColorTargetState, ColorWrites, CommandEncoder, Device, Extent3d, FilterMode, FragmentState, FrontFace, LoadOp,
+    MultisampleState, Operations, PipelineLayoutDescriptor, PolygonMode, PrimitiveState, PrimitiveTopology,
+    RenderPassColorAttachment, RenderPassDescriptor, RenderPipeline, RenderPipelineDescriptor, SamplerBindingType,
+    SamplerDescriptor, ShaderModule, ShaderStages, StoreOp, TextureDescriptor, TextureDimension, TextureFormat,
+    TextureSampleType, TextureUsages, TextureViewDescriptor, TextureViewDimension, VertexState,
+};
+
+use crate::{managers::InternalTexture, util::bind_merge::BindGroupBuilder};
+
+/// Format of all cubemaps created by the [`EquirectConverter`].
+pub const EQUIRECT_CUBEMAP_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+
+/// Renders equirectangular 2D textures into the six faces of a cubemap.
+pub struct EquirectConverter {
+    /// Samples sources with a linear filter.
+    filtering: EquirectPipeline,
+    /// Samples the nearest texel of sources which can't be filtered, like
+    /// 32 bit float textures without `FLOAT32_FILTERABLE`.
+    non_filtering: EquirectPipeline,
+}
+
+/// Pipeline converting sources of a single sample type.
+struct EquirectPipeline {
+    texture_bgl: BindGroupLayout,
+    sampler_bg: BindGroup,
+    pipeline: RenderPipeline,
+}
+
+impl EquirectConverter {
+    pub fn new(device: &Device) -> Self {
+        profiling::scope!("EquirectConverter::new");
+
+        let sm = device.create_shader_module(wgpu::include_wgsl!("../../shaders/equirect.wgsl"));
+
+        Self {
+            filtering: EquirectPipeline::new(device, &sm, true),
+            non_filtering: EquirectPipeline::new(device, &sm, false),
+        }
+    }
+
+    /// Records rendering the equirectangular texture behind `src` into a new
+    /// single-mip cubemap with faces of `face_size` by `face_size` texels.
+    pub fn convert(
+        &self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        src: &InternalTexture,
+        face_size: u32,
+    ) -> InternalTexture {
+        profiling::scope!("equirect to cubemap");
+
+        let pipeline = match src.desc.format.sample_type(None, Some(device.features())) {
+            Some(TextureSampleType::Float { filterable: false }) => &self.non_filtering,
+            _ => &self.filtering,
+        };
+
+        let desc = TextureDescriptor {
+            label: None,
+            size: Extent3d { width: face_size, height: face_size, depth_or_array_layers: 6 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: EQUIRECT_CUBEMAP_FORMAT,
+            usage: TextureUsages::TEXTURE_BINDING
+                | TextureUsages::COPY_SRC
+                | TextureUsages::COPY_DST
+                | TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        };
+        let texture = device.create_texture(&desc);
+
+        let bg = BindGroupBuilder::new().append_texture_view(&src.view).build(
+            device,
+            Some("equirect converter src bg"),
+            &pipeline.texture_bgl,
+        );
+
+        for face in 0..6 {
+            let face_view = texture.create_view(&TextureViewDescriptor {
+                dimension: Some(TextureViewDimension::D2),
+                base_array_layer: face,
+                array_layer_count: Some(1),
+                ..Default::default()
+            });
+
+            let mut rpass = encoder.begin_render_pass(&RenderPassDescriptor {
+                label: None,
+                color_attachments: &[Some(RenderPassColorAttachment {
+                    view: &face_view,
+                    resolve_target: None,
+                    ops: Operations { load: LoadOp::Clear(Color::BLACK), store: StoreOp::Store },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+
+            rpass.set_pipeline(&pipeline.pipeline);
+            rpass.set_bind_group(0, &bg, &[]);
+            rpass.set_bind_group(1, &pipeline.sampler_bg, &[]);
+            // The face index is passed through the instance index.
+            rpass.draw(0..3, face..face + 1);
+        }
+
+        let view = texture
+            .create_view(&TextureViewDescriptor { dimension: Some(TextureViewDimension::Cube), ..Default::default() });
+
+        InternalTexture { texture: Arc::new(texture), view, desc }
+    }
+}
+
+impl EquirectPipeline {
+    fn new(device: &Device, sm: &ShaderModule, filterable: bool) -> Self {
+        let (label, filter) = match filterable {
+            true => ("filtering", FilterMode::Linear),
+            false => ("non-filtering", FilterMode::Nearest),
+        };
+
+        let texture_bgl = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some(&format!("equirect converter {label} texture bgl")),
+            entries: &[BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Texture {
+                    sample_type: TextureSampleType::Float { filterable },
+                    view_dimension: TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            }],
+        });
+
+        let sampler_bgl = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some(&format!("equirect converter {label} sampler bgl")),
+            entries: &[BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Sampler(match filterable {
+                    true => SamplerBindingType::Filtering,
+                    false => SamplerBindingType::NonFiltering,
+                }),
+                count: None,
+            }],
+        });
+
+        // Horizontally the equirect wraps around, vertically it ends at the poles.
+        let sampler = device.create_sampler(&SamplerDescriptor {
+            label: Some(&format!("equirect converter {label} sampler")),
+            address_mode_u: AddressMode::Repeat,
+            address_mode_v: AddressMode::ClampToEdge,
+            address_mode_w: AddressMode::ClampToEdge,
+            mag_filter: filter,
+            min_filter: filter,
+            mipmap_filter: filter,
+            lod_min_clamp: 0.0,
+            lod_max_clamp: 100.0,
+            compare: None,
+            anisotropy_clamp: 1,
+            border_color: None,
+        });
+
+        let sampler_bg = BindGroupBuilder::new().append_sampler(&sampler).build(
+            device,
+            Some(&format!("equirect converter {label} sampler bg")),
+            &sampler_bgl,
+        );
+
+        let pll = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some(&format!("equirect converter {label} pipeline layout")),
+            bind_group_layouts: &[&texture_bgl, &sampler_bgl],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some(&format!("equirect converter {label} pipeline")),
+            layout: Some(&pll),
+            vertex: VertexState { module: sm, entry_point: "vs_main", buffers: &[] },
+            primitive: PrimitiveState {
+                topology: PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: FrontFace::Cw,
+                cull_mode: None,
+                unclipped_depth: false,
+                polygon_mode: PolygonMode::Fill,
+                conservative: false,
+            },
+            depth_stencil: None,
+            multisample: MultisampleState::default(),
+            fragment: Some(FragmentState {
+                module: sm,
+                entry_point: "fs_main",
+                targets: &[Some(ColorTargetState {
+                    format: EQUIRECT_CUBEMAP_FORMAT,
+                    blend: None,
+                    write_mask: ColorWrites::all(),
+                })],
+            }),
+            multiview: None,
+        });
+
+        Self { texture_bgl, sampler_bg, pipeline }
+    }
+}
@@ -1,4 +1,4 @@
-//! Frustums and bounding spheres.
+//! Frustums and bounding volumes.
 //!
 //! This entire module only exists because of <https://www.gamedevs.org/uploads/fast-extraction-viewing-frustum-planes-from-world-view-projection-matrix.pdf>.
 
@@ -20,18 +20,59 @@ impl BoundingSphere {
     }
 
     pub fn apply_transform(self, matrix: Mat4) -> Self {
-        let max_scale = matrix
-            .x_axis
-            .xyz()
-            .length_squared()
-            .max(matrix.y_axis.xyz().length_squared().max(matrix.z_axis.xyz().length_squared()))
-            .sqrt();
+        let x = matrix.x_axis.xyz();
+        let y = matrix.y_axis.xyz();
+        let z = matrix.z_axis.xyz();
+        let lengths_sq = [x.length_squared(), y.length_squared(), z.length_squared()];
+        let max_length_sq = lengths_sq[0].max(lengths_sq[1]).max(lengths_sq[2]);
+        // Without shear the longest axis is the largest stretch. Sheared axes can stretch
+        // further along their sum, so bound it by the sum of all squared axis lengths.
+        let sheared = [x.dot(y), y.dot(z), z.dot(x)].iter().any(|d| d.abs() > max_length_sq * 1e-5);
+        let max_scale = if sheared { lengths_sq.iter().sum::<f32>() } else { max_length_sq }.sqrt();
         let center = matrix * self.center.extend(1.0);
 
         Self { center: center.truncate(), radius: max_scale * self.radius }
     }
 }
 
+/// Axis aligned bounding box.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+impl Aabb {
+    pub fn from_mesh(mesh: &[Vec3]) -> Self {
+        let Some(&first) = mesh.first() else {
+            return Self::default();
+        };
+
+        mesh.iter()
+            .skip(1)
+            .fold(Self { min: first, max: first }, |aabb, &pos| Self { min: aabb.min.min(pos), max: aabb.max.max(pos) })
+    }
+
+    /// Smallest box containing this box after it's transformed by `matrix`.
+    pub fn apply_transform(self, matrix: Mat4) -> Self {
+        let center = matrix.transform_point3(self.center());
+        let half_extents = self.half_extents();
+        // Each world axis extends by the absolute contribution of every local axis.
+        let extents = matrix.x_axis.xyz().abs() * half_extents.x
+            + matrix.y_axis.xyz().abs() * half_extents.y
+            + matrix.z_axis.xyz().abs() * half_extents.z;
+
+        Self { min: center - extents, max: center + extents }
+    }
+
+    pub fn center(self) -> Vec3 {
+        (self.min + self.max) / 2.0
+    }
+
+    pub fn half_extents(self) -> Vec3 {
+        (self.max - self.min) / 2.0
+    }
+}
+
 fn find_mesh_center(mesh: &[Vec3]) -> Vec3A {
     let first = if let Some(first) = mesh.first() {
         *first
@@ -160,3 +201,60 @@ impl Frustum {
         true
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use glam::{Mat4, Vec3, Vec4};
+
+    use super::{Aabb, BoundingSphere, Frustum};
+
+    fn frustum() -> Frustum {
+        // Camera at the origin, looking down -Z.
+        let proj = Mat4::perspective_infinite_reverse_rh(60_f32.to_radians(), 1.0, 0.1);
+        let view = Mat4::look_at_rh(Vec3::ZERO, Vec3::NEG_Z, Vec3::Y);
+        Frustum::from_matrix(proj * view)
+    }
+
+    #[test]
+    fn behind_camera() {
+        let frustum = frustum();
+
+        assert!(frustum.contains_sphere(BoundingSphere { center: Vec3::new(0.0, 0.0, -10.0), radius: 1.0 }));
+        assert!(!frustum.contains_sphere(BoundingSphere { center: Vec3::new(0.0, 0.0, 10.0), radius: 1.0 }));
+        // Just behind the near plane, but the radius reaches past it.
+        assert!(frustum.contains_sphere(BoundingSphere { center: Vec3::new(0.0, 0.0, 0.5), radius: 1.0 }));
+        assert!(!frustum.contains_sphere(BoundingSphere { center: Vec3::new(0.0, 0.0, 0.5), radius: 0.5 }));
+    }
+
+    #[test]
+    fn sheared_bounds() {
+        let sphere = BoundingSphere { center: Vec3::ZERO, radius: 1.0 };
+
+        let scaled = sphere.apply_transform(Mat4::from_scale(Vec3::new(1.0, 3.0, 2.0)));
+        assert_eq!(scaled.radius, 3.0);
+
+        // Leans the y axis towards x, stretching the unit sphere by the golden ratio, which is
+        // more than the length of any axis.
+        let shear = Mat4::from_cols(Vec4::X, Vec4::new(1.0, 1.0, 0.0, 0.0), Vec4::Z, Vec4::W);
+        let sheared = sphere.apply_transform(shear);
+        assert!(sheared.radius >= 1.618);
+    }
+
+    #[test]
+    fn transformed_aabb() {
+        let aabb = Aabb::from_mesh(&[Vec3::new(-1.0, 0.0, 2.0), Vec3::new(1.0, 4.0, 0.0), Vec3::new(0.0, 1.0, 1.0)]);
+        assert_eq!(aabb, Aabb { min: Vec3::new(-1.0, 0.0, 0.0), max: Vec3::new(1.0, 4.0, 2.0) });
+
+        let moved = aabb.apply_transform(Mat4::from_scale_rotation_translation(
+            Vec3::splat(2.0),
+            glam::Quat::IDENTITY,
+            Vec3::new(1.0, 0.0, 0.0),
+        ));
+        assert_eq!(moved, Aabb { min: Vec3::new(-1.0, 0.0, 0.0), max: Vec3::new(3.0, 8.0, 4.0) });
+
+        // A quarter turn around z swaps the x and y extents.
+        let rotated = aabb.apply_transform(Mat4::from_rotation_z(std::f32::consts::FRAC_PI_2));
+        assert!(rotated.min.abs_diff_eq(Vec3::new(-4.0, -1.0, 0.0), 1e-5));
+        assert!(rotated.max.abs_diff_eq(Vec3::new(0.0, 1.0, 2.0), 1e-5));
+    }
+}
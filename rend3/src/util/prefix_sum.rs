@@ -0,0 +1,218 @@
+use wgpu::{
+    BindGroupLayout, Buffer, BufferBindingType, BufferDescriptor, BufferUsages, CommandEncoder, ComputePassDescriptor,
+    ComputePipeline, ComputePipelineDescriptor, Device, PipelineLayoutDescriptor, ShaderStages,
+};
+
+use crate::util::{
+    bind_merge::{BindGroupBuilder, BindGroupLayoutBuilder},
+    math::div_round_up,
+};
+
+const BLOCK_SIZE: u32 = 256;
+
+/// Largest amount of values a [`PrefixSum`] can scan, as every block of 256
+/// values is scanned by its own workgroup.
+pub const MAX_PREFIX_SUM_COUNT: u32 = 65535 * BLOCK_SIZE;
+
+/// Parallel exclusive prefix sum (scan) of a buffer of u32s on the gpu.
+///
+/// Every output value is the sum of all input values before it, so the first
+/// one is 0. Useful for compacting the results of a compute pass, like
+/// finding where every live particle goes.
+pub struct PrefixSum {
+    bgl: BindGroupLayout,
+    scan_pipeline: ComputePipeline,
+    add_pipeline: ComputePipeline,
+}
+impl PrefixSum {
+    pub fn new(device: &Device) -> Self {
+        let sm = device.create_shader_module(wgpu::include_wgsl!("../../shaders/prefix_sum.wgsl"));
+
+        let bgl = BindGroupLayoutBuilder::new()
+            .append_buffer(ShaderStages::COMPUTE, BufferBindingType::Storage { read_only: true }, false, 4)
+            .append_buffer(ShaderStages::COMPUTE, BufferBindingType::Storage { read_only: false }, false, 4)
+            .append_buffer(ShaderStages::COMPUTE, BufferBindingType::Storage { read_only: false }, false, 4)
+            .build(device, Some("PrefixSum bgl"));
+
+        let pll = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("PrefixSum pll"),
+            bind_group_layouts: &[&bgl],
+            push_constant_ranges: &[],
+        });
+
+        let scan_pipeline = device.create_compute_pipeline(&ComputePipelineDescriptor {
+            label: Some("PrefixSum scan compute pipeline"),
+            layout: Some(&pll),
+            module: &sm,
+            entry_point: "scan_blocks",
+        });
+
+        let add_pipeline = device.create_compute_pipeline(&ComputePipelineDescriptor {
+            label: Some("PrefixSum add compute pipeline"),
+            layout: Some(&pll),
+            module: &sm,
+            entry_point: "add_block_offsets",
+        });
+
+        Self { bgl, scan_pipeline, add_pipeline }
+    }
+
+    /// Writes the exclusive prefix sum of all u32s in `input` to the start of
+    /// `output`. Both need [`BufferUsages::STORAGE`] and must not overlap.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `output` is smaller than `input` or `input` holds more than
+    /// [`MAX_PREFIX_SUM_COUNT`] values.
+    pub fn run(&self, device: &Device, encoder: &mut CommandEncoder, input: &Buffer, output: &Buffer) {
+        assert!(output.size() >= input.size(), "prefix sum output is smaller than its input");
+        let count = input.size() / 4;
+        assert!(count <= MAX_PREFIX_SUM_COUNT as u64, "prefix sum of {count} values is too large");
+
+        if count != 0 {
+            self.scan(device, encoder, input, output, count as u32);
+        }
+    }
+
+    fn scan(&self, device: &Device, encoder: &mut CommandEncoder, input: &Buffer, output: &Buffer, count: u32) {
+        let blocks = div_round_up(count, BLOCK_SIZE);
+
+        let block_sums = temporary_buffer(device, "PrefixSum temporary block sums", blocks);
+
+        let bg = BindGroupBuilder::new()
+            .append_buffer_with_size(input, count as u64 * 4)
+            .append_buffer_with_size(output, count as u64 * 4)
+            .append_buffer(&block_sums)
+            .build(device, Some("PrefixSum temporary scan bind group"), &self.bgl);
+
+        let mut cpass = encoder
+            .begin_compute_pass(&ComputePassDescriptor { label: Some("PrefixSum scan cpass"), timestamp_writes: None });
+        cpass.set_pipeline(&self.scan_pipeline);
+        cpass.set_bind_group(0, &bg, &[]);
+        cpass.dispatch_workgroups(blocks, 1, 1);
+        drop(cpass);
+
+        if blocks == 1 {
+            return;
+        }
+
+        // Every block needs the sum of all blocks before it added.
+        let block_offsets = temporary_buffer(device, "PrefixSum temporary block offsets", blocks);
+        self.scan(device, encoder, &block_sums, &block_offsets, blocks);
+
+        let bg = BindGroupBuilder::new()
+            .append_buffer(&block_offsets)
+            .append_buffer_with_size(output, count as u64 * 4)
+            .append_buffer(&block_sums)
+            .build(device, Some("PrefixSum temporary add bind group"), &self.bgl);
+
+        let mut cpass = encoder
+            .begin_compute_pass(&ComputePassDescriptor { label: Some("PrefixSum add cpass"), timestamp_writes: None });
+        cpass.set_pipeline(&self.add_pipeline);
+        cpass.set_bind_group(0, &bg, &[]);
+        cpass.dispatch_workgroups(blocks, 1, 1);
+        drop(cpass);
+    }
+}
+
+fn temporary_buffer(device: &Device, label: &str, count: u32) -> Buffer {
+    device.create_buffer(&BufferDescriptor {
+        label: Some(label),
+        size: count as u64 * 4,
+        usage: BufferUsages::STORAGE,
+        mapped_at_creation: false,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use wgpu::util::DeviceExt;
+
+    use crate::util::prefix_sum::PrefixSum;
+
+    struct TestContext {
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+    }
+
+    impl TestContext {
+        fn new() -> Option<Self> {
+            let backends = wgpu::util::backend_bits_from_env().unwrap_or(wgpu::Backends::all());
+            let instance =
+                wgpu::Instance::new(wgpu::InstanceDescriptor { backends, ..wgpu::InstanceDescriptor::default() });
+            let adapter = pollster::block_on(wgpu::util::initialize_adapter_from_env_or_default(&instance, None))?;
+            let (device, queue) = pollster::block_on(adapter.request_device(
+                &wgpu::DeviceDescriptor {
+                    label: None,
+                    required_features: wgpu::Features::empty(),
+                    required_limits: wgpu::Limits::default(),
+                },
+                None,
+            ))
+            .ok()?;
+
+            Some(Self { device, queue })
+        }
+
+        fn prefix_sum(&self, prefix_sum: &PrefixSum, input: &[u32]) -> Vec<u32> {
+            let bytes = (input.len() * 4) as u64;
+
+            let input = self.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("input buffer"),
+                contents: bytemuck::cast_slice(input),
+                usage: wgpu::BufferUsages::STORAGE,
+            });
+            let output = self.device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("output buffer"),
+                size: bytes,
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            });
+            let staging = self.device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("staging"),
+                size: bytes,
+                usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+
+            let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+            prefix_sum.run(&self.device, &mut encoder, &input, &output);
+            encoder.copy_buffer_to_buffer(&output, 0, &staging, 0, bytes);
+
+            self.queue.submit(Some(encoder.finish()));
+
+            staging.slice(..).map_async(wgpu::MapMode::Read, |_| ());
+            self.device.poll(wgpu::Maintain::Wait);
+
+            let res = bytemuck::cast_slice(&staging.slice(..).get_mapped_range()).to_vec();
+
+            res
+        }
+    }
+
+    fn cpu_prefix_sum(input: &[u32]) -> Vec<u32> {
+        input
+            .iter()
+            .scan(0, |sum, &value| {
+                let previous = *sum;
+                *sum += value;
+                Some(previous)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn matches_cpu() {
+        let Some(ctx) = TestContext::new() else {
+            return;
+        };
+
+        let prefix_sum = PrefixSum::new(&ctx.device);
+
+        // Covers one, two and three levels of block sums.
+        for count in [1, 7, 255, 256, 257, 1000, 65536, 65536 + 300] {
+            let input: Vec<u32> = (0..count).map(|i| (i * 7 + 3) % 13).collect();
+            assert_eq!(ctx.prefix_sum(&prefix_sum, &input), cpu_prefix_sum(&input), "count {count}");
+        }
+    }
+}
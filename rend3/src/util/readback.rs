@@ -0,0 +1,163 @@
+//! Reading textures back to cpu memory.
+//!
+//! Used for screenshots and headless rendering. See
+//! [`Renderer::read_texture`](crate::Renderer::read_texture). Buffers filled
+//! by the render graph can be read with [`read_buffer`].
+
+use thiserror::Error;
+use wgpu::{
+    Buffer, BufferAsyncError, BufferDescriptor, BufferUsages, CommandEncoderDescriptor, Device, Extent3d,
+    ImageCopyBuffer, ImageDataLayout, Maintain, MapMode, Queue, SubmissionIndex, Texture, TextureFormat, TextureUsages,
+    COPY_BYTES_PER_ROW_ALIGNMENT,
+};
+
+/// Error returned when reading a texture back fails.
+#[derive(Debug, Error)]
+pub enum ReadbackError {
+    #[error("Texture format {0:?} can not be copied to a buffer")]
+    UnsupportedFormat(TextureFormat),
+    #[error("Texture was not created with TextureUsages::COPY_SRC")]
+    MissingCopySrc,
+    #[error("Mapping the readback buffer failed")]
+    MapFailed(#[source] BufferAsyncError),
+}
+
+/// Rounds the size of a row of texels up to what `copy_texture_to_buffer` requires.
+pub fn padded_bytes_per_row(unpadded_bytes_per_row: u32) -> u32 {
+    let align = COPY_BYTES_PER_ROW_ALIGNMENT;
+    (unpadded_bytes_per_row + align - 1) / align * align
+}
+
+/// Removes the padding at the end of every row of `data`.
+pub fn unpad_rows(data: &[u8], unpadded_bytes_per_row: u32, padded_bytes_per_row: u32) -> Vec<u8> {
+    if unpadded_bytes_per_row == padded_bytes_per_row {
+        return data.to_vec();
+    }
+
+    data.chunks(padded_bytes_per_row as usize)
+        .flat_map(|row| &row[..unpadded_bytes_per_row as usize])
+        .copied()
+        .collect()
+}
+
+/// Maps a buffer created with [`BufferUsages::MAP_READ`] and copies out its
+/// contents.
+///
+/// Everything writing to the buffer must already be submitted. On native, this
+/// blocks until the gpu has finished all submitted work.
+pub async fn read_buffer(device: &Device, buffer: &Buffer) -> Result<Vec<u8>, ReadbackError> {
+    profiling::scope!("read_buffer");
+
+    let (sender, receiver) = flume::bounded(1);
+    buffer.slice(..).map_async(MapMode::Read, move |res| {
+        // The receiver is gone if the future was dropped.
+        let _ = sender.send(res);
+    });
+
+    device.poll(Maintain::Wait);
+
+    receiver.recv_async().await.expect("readback map callback dropped").map_err(ReadbackError::MapFailed)?;
+
+    let data = buffer.slice(..).get_mapped_range().to_vec();
+    buffer.unmap();
+
+    Ok(data)
+}
+
+/// A copy of the first mip of a texture to cpu memory which has been
+/// submitted, but may not yet have completed.
+pub struct PendingReadback {
+    buffer: Buffer,
+    submission: SubmissionIndex,
+    receiver: flume::Receiver<Result<(), BufferAsyncError>>,
+    unpadded_bytes_per_row: u32,
+    padded_bytes_per_row: u32,
+}
+
+impl PendingReadback {
+    /// Submits a copy of the first mip level and array layer of `texture`.
+    pub fn new(device: &Device, queue: &Queue, texture: &Texture) -> Result<Self, ReadbackError> {
+        profiling::scope!("PendingReadback::new");
+
+        let format = texture.format();
+        if !texture.usage().contains(TextureUsages::COPY_SRC) {
+            return Err(ReadbackError::MissingCopySrc);
+        }
+        // Compressed and combined depth-stencil formats can't be copied as a whole.
+        let block_size = match format.block_copy_size(None) {
+            Some(size) if format.block_dimensions() == (1, 1) => size,
+            _ => return Err(ReadbackError::UnsupportedFormat(format)),
+        };
+
+        let unpadded_bytes_per_row = texture.width() * block_size;
+        let padded_bytes_per_row = padded_bytes_per_row(unpadded_bytes_per_row);
+
+        let buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("readback buffer"),
+            size: padded_bytes_per_row as u64 * texture.height() as u64,
+            usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor { label: Some("readback encoder") });
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            ImageCopyBuffer {
+                buffer: &buffer,
+                layout: ImageDataLayout { offset: 0, bytes_per_row: Some(padded_bytes_per_row), rows_per_image: None },
+            },
+            Extent3d { width: texture.width(), height: texture.height(), depth_or_array_layers: 1 },
+        );
+        let submission = queue.submit(Some(encoder.finish()));
+
+        let (sender, receiver) = flume::bounded(1);
+        buffer.slice(..).map_async(MapMode::Read, move |res| {
+            // The receiver is gone if the readback was dropped.
+            let _ = sender.send(res);
+        });
+
+        Ok(Self { buffer, submission, receiver, unpadded_bytes_per_row, padded_bytes_per_row })
+    }
+
+    /// Waits for the copy to finish, returning the tightly packed texels.
+    ///
+    /// On native, this blocks until the gpu has finished the copy.
+    pub async fn finish(self, device: &Device) -> Result<Vec<u8>, ReadbackError> {
+        profiling::scope!("PendingReadback::finish");
+
+        device.poll(Maintain::WaitForSubmissionIndex(self.submission));
+
+        // The sender is only dropped without sending if the buffer is destroyed, which we never do.
+        self.receiver.recv_async().await.expect("readback map callback dropped").map_err(ReadbackError::MapFailed)?;
+
+        let data = {
+            let mapping = self.buffer.slice(..).get_mapped_range();
+            unpad_rows(&mapping, self.unpadded_bytes_per_row, self.padded_bytes_per_row)
+        };
+        self.buffer.unmap();
+
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{padded_bytes_per_row, unpad_rows};
+
+    #[test]
+    fn padding() {
+        assert_eq!(padded_bytes_per_row(4), 256);
+        assert_eq!(padded_bytes_per_row(256), 256);
+        assert_eq!(padded_bytes_per_row(260), 512);
+    }
+
+    #[test]
+    fn unpadding() {
+        let mut data = vec![0; 512];
+        data[..4].copy_from_slice(&[1, 2, 3, 4]);
+        data[256..260].copy_from_slice(&[5, 6, 7, 8]);
+
+        assert_eq!(unpad_rows(&data, 4, 256), [1, 2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(unpad_rows(&data[..8], 8, 8), data[..8]);
+    }
+}
@@ -0,0 +1,152 @@
+//! Offscreen textures which can be rendered to and then sampled.
+//!
+//! Useful for mirrors, security cameras and anything else that shows the
+//! scene on a surface within the scene. See
+//! [`Renderer::add_render_target`](crate::Renderer::add_render_target).
+
+use std::{panic::Location, sync::Arc};
+
+use glam::UVec2;
+use rend3_types::{SampleCount, Texture2DHandle, TextureFormat, TextureUsages};
+use wgpu::{Extent3d, Texture, TextureDescriptor, TextureDimension, TextureView, TextureViewDescriptor};
+
+use crate::{
+    graph::{RenderGraph, RenderTargetHandle, ViewportRect},
+    instruction::InstructionKind,
+    managers::{validate_texture_format, InternalTexture, TextureCreationError},
+    util::error_scope::AllocationErrorScope,
+    Renderer,
+};
+
+/// Texture the render graph can render into, which is also registered as a 2D
+/// texture so materials can sample it through [`RenderTarget::handle`].
+///
+/// The texture itself is never multisampled. The depth buffer, and the
+/// multisampled color buffer when `samples` is above one, are allocated by the
+/// render graph at the size and sample count of the target every frame, then
+/// resolved into this texture.
+///
+/// A render target can not be sampled by materials used while rendering into
+/// it.
+pub struct RenderTarget {
+    handle: Texture2DHandle,
+    texture: Arc<Texture>,
+    view: TextureView,
+    size: UVec2,
+    format: TextureFormat,
+    samples: SampleCount,
+}
+
+impl RenderTarget {
+    pub(crate) fn new(
+        renderer: &Renderer,
+        size: UVec2,
+        format: TextureFormat,
+        samples: SampleCount,
+        allocate_handle: impl FnOnce() -> Texture2DHandle,
+    ) -> Result<(Self, InternalTexture), TextureCreationError> {
+        validate_texture_format(format, renderer.features)?;
+        if !format
+            .guaranteed_format_features(renderer.features)
+            .allowed_usages
+            .contains(TextureUsages::RENDER_ATTACHMENT)
+        {
+            return Err(TextureCreationError::TextureFormatNotRenderable { format });
+        }
+
+        let internal_texture = create_texture(renderer, size, format)?;
+        let target = Self {
+            handle: allocate_handle(),
+            texture: internal_texture.texture.clone(),
+            view: internal_texture.texture.create_view(&TextureViewDescriptor::default()),
+            size,
+            format,
+            samples,
+        };
+        Ok((target, internal_texture))
+    }
+
+    /// Reallocates the texture at a new size, keeping the format, sample count
+    /// and handle. Does nothing if the size did not change.
+    ///
+    /// Like any other texture change, materials sample the new texture once
+    /// the instructions are evaluated. The contents of the old texture are not
+    /// kept.
+    #[track_caller]
+    pub fn resize(&mut self, renderer: &Renderer, size: UVec2) -> Result<(), TextureCreationError> {
+        if size == self.size {
+            return Ok(());
+        }
+
+        let internal_texture = create_texture(renderer, size, self.format)?;
+        self.texture = internal_texture.texture.clone();
+        self.view = self.texture.create_view(&TextureViewDescriptor::default());
+        self.size = size;
+
+        renderer.instructions.push(
+            InstructionKind::AddTexture2D { handle: *self.handle, internal_texture, cmd_buf: None },
+            *Location::caller(),
+        );
+
+        Ok(())
+    }
+
+    /// Handle materials can use to sample the target.
+    pub fn handle(&self) -> &Texture2DHandle {
+        &self.handle
+    }
+
+    pub fn texture(&self) -> &Texture {
+        &self.texture
+    }
+
+    /// View of the whole texture.
+    pub fn view(&self) -> &TextureView {
+        &self.view
+    }
+
+    pub fn size(&self) -> UVec2 {
+        self.size
+    }
+
+    pub fn format(&self) -> TextureFormat {
+        self.format
+    }
+
+    /// Sample count used while rendering into the target.
+    pub fn samples(&self) -> SampleCount {
+        self.samples
+    }
+
+    /// Imports the texture into the graph so it can be rendered to.
+    pub fn add_to_graph<'node>(&'node self, graph: &mut RenderGraph<'node>) -> RenderTargetHandle {
+        graph.add_imported_render_target(&*self.texture, 0..1, 0..1, ViewportRect::from_size(self.size))
+    }
+}
+
+fn create_texture(
+    renderer: &Renderer,
+    size: UVec2,
+    format: TextureFormat,
+) -> Result<InternalTexture, TextureCreationError> {
+    let desc = TextureDescriptor {
+        label: Some("render target"),
+        size: Extent3d { width: size.x, height: size.y, depth_or_array_layers: 1 },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: TextureDimension::D2,
+        format,
+        usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_SRC,
+        view_formats: &[],
+    };
+
+    let scope = AllocationErrorScope::new(&renderer.device);
+    let texture = renderer.device.create_texture(&desc);
+    scope.end().map_err(TextureCreationError::TextureAllocationFailed)?;
+
+    let scope = AllocationErrorScope::new(&renderer.device);
+    let view = texture.create_view(&TextureViewDescriptor::default());
+    scope.end().map_err(TextureCreationError::TextureViewCreationFailed)?;
+
+    Ok(InternalTexture { texture: Arc::new(texture), view, desc })
+}
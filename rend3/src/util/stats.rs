@@ -0,0 +1,337 @@
+//! Accumulation of [`RendererStatistics`] over multiple frames, and cpu side
+//! statistics of single frames.
+//!
+//! Single frame timings are noisy, so the renderer feeds every frame's
+//! statistics into a [`StatisticsAccumulator`]. The summary can be retrieved
+//! with [`Renderer::accumulated_stats`](crate::Renderer::accumulated_stats).
+//! Frame times are only kept for the most recent frames, see
+//! [`Renderer::set_accumulated_stats_window`](crate::Renderer::set_accumulated_stats_window).
+//!
+//! Counts and cpu timings of the most recent frame are collected into
+//! [`FrameStatistics`], retrieved with
+//! [`Renderer::last_frame_stats`](crate::Renderer::last_frame_stats).
+
+use std::{collections::VecDeque, time::Duration};
+
+use crate::util::typedefs::{FastHashMap, RendererStatistics};
+
+/// Counts and cpu timings of a single frame.
+///
+/// Object, draw call and triangle counts are summed over every camera the
+/// objects are culled for, including shadow cameras. Hidden objects are not
+/// counted. They are filled in by the culling nodes of the render graph, so
+/// they stay 0 if the graph has none.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FrameStatistics {
+    /// Objects inside a camera's frustum, which were sent to the gpu. All
+    /// objects when culling is disabled.
+    pub objects_submitted: u32,
+    /// Objects skipped for being outside a camera's frustum. Always 0 when
+    /// culling is disabled.
+    pub objects_culled: u32,
+    /// Indirect draw calls recorded.
+    pub draw_calls: u32,
+    /// Triangles of all submitted objects, before they are culled on the gpu.
+    pub triangles: u64,
+    /// Time spent processing instructions in
+    /// [`Renderer::evaluate_instructions`](crate::Renderer::evaluate_instructions).
+    pub instruction_time: Duration,
+    /// Time spent uploading the changes of all managers in
+    /// [`Renderer::evaluate_instructions`](crate::Renderer::evaluate_instructions).
+    pub resource_update_time: Duration,
+    /// Time spent in the culling nodes of the render graph.
+    pub culling_time: Duration,
+    /// Time spent setting up the render graph and running its nodes, including culling.
+    pub record_time: Duration,
+    /// Time spent submitting the frame to the gpu.
+    pub submit_time: Duration,
+}
+
+/// Timing of a single profiler scope, accumulated over all frames it appeared in.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AccumulatedScope {
+    pub label: String,
+    /// Amount of times the scope was recorded.
+    pub count: u32,
+    /// Sum of the time spent in the scope, in seconds.
+    pub total_time: f64,
+    /// Average time spent in the scope, in seconds.
+    pub avg_time: f64,
+}
+
+/// Default amount of frames [`StatisticsAccumulator`] keeps frame times for.
+pub const DEFAULT_STATS_WINDOW: usize = 1000;
+
+/// Summary of all statistics accumulated since the last reset.
+///
+/// Frame times are gpu times in seconds, covering the most recent frames of the
+/// window. If timestamp queries are not supported, no frame times are ever
+/// accumulated. Scope timings and counts cover every frame since the last reset.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AccumulatedStatistics {
+    /// Amount of frames the frame times cover.
+    pub frames: u32,
+    pub min_frame_time: f64,
+    pub max_frame_time: f64,
+    pub avg_frame_time: f64,
+    /// 95th percentile frame time.
+    pub p95_frame_time: f64,
+    /// 99th percentile frame time.
+    pub p99_frame_time: f64,
+    /// Timing of every top level scope, sorted by label.
+    pub scopes: Vec<AccumulatedScope>,
+    /// Sum of [`FrameStatistics::objects_submitted`] of every frame.
+    pub objects_submitted: u64,
+    /// Sum of [`FrameStatistics::objects_culled`] of every frame.
+    pub objects_culled: u64,
+    /// Sum of [`FrameStatistics::draw_calls`] of every frame.
+    pub draw_calls: u64,
+    /// Sum of [`FrameStatistics::triangles`] of every frame.
+    pub triangles: u64,
+}
+
+#[derive(Debug, Default)]
+struct ScopeAccumulator {
+    count: u32,
+    total_time: f64,
+}
+
+#[derive(Debug, Default)]
+struct FrameTotals {
+    objects_submitted: u64,
+    objects_culled: u64,
+    draw_calls: u64,
+    triangles: u64,
+}
+
+/// Accumulates the statistics of many frames.
+///
+/// The frame times of the last `window` frames are kept in a ring buffer, so
+/// that percentiles can be computed exactly without memory growing over time.
+#[derive(Debug)]
+pub struct StatisticsAccumulator {
+    window: usize,
+    frame_times: VecDeque<f64>,
+    scopes: FastHashMap<String, ScopeAccumulator>,
+    totals: FrameTotals,
+}
+
+impl Default for StatisticsAccumulator {
+    fn default() -> Self {
+        Self::with_window(DEFAULT_STATS_WINDOW)
+    }
+}
+
+impl StatisticsAccumulator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates an accumulator keeping the frame times of the last `window`
+    /// frames. A window of 0 is treated as 1.
+    pub fn with_window(window: usize) -> Self {
+        let window = window.max(1);
+        Self {
+            window,
+            frame_times: VecDeque::with_capacity(window),
+            scopes: FastHashMap::default(),
+            totals: FrameTotals::default(),
+        }
+    }
+
+    /// Amount of frames frame times are kept for.
+    pub fn window(&self) -> usize {
+        self.window
+    }
+
+    /// Changes the amount of frames frame times are kept for, dropping the
+    /// oldest frame times if there are more. A window of 0 is treated as 1.
+    pub fn set_window(&mut self, window: usize) {
+        self.window = window.max(1);
+        while self.frame_times.len() > self.window {
+            self.frame_times.pop_front();
+        }
+    }
+
+    /// Adds the gpu timings of a single frame.
+    pub fn accumulate(&mut self, stats: &RendererStatistics) {
+        // The frame is the span from the first scope starting to the last scope ending.
+        let start = stats.iter().map(|query| query.time.start).reduce(f64::min);
+        let end = stats.iter().map(|query| query.time.end).reduce(f64::max);
+        let (Some(start), Some(end)) = (start, end) else {
+            return;
+        };
+        if self.frame_times.len() == self.window {
+            self.frame_times.pop_front();
+        }
+        self.frame_times.push_back(end - start);
+
+        for query in stats {
+            let scope = match self.scopes.get_mut(&query.label) {
+                Some(scope) => scope,
+                None => self.scopes.entry(query.label.clone()).or_default(),
+            };
+            scope.count += 1;
+            scope.total_time += query.time.end - query.time.start;
+        }
+    }
+
+    /// Adds the counts of a single frame to the totals.
+    pub fn accumulate_frame(&mut self, stats: &FrameStatistics) {
+        self.totals.objects_submitted += stats.objects_submitted as u64;
+        self.totals.objects_culled += stats.objects_culled as u64;
+        self.totals.draw_calls += stats.draw_calls as u64;
+        self.totals.triangles += stats.triangles;
+    }
+
+    /// Clears all accumulated statistics. The window is kept.
+    pub fn reset(&mut self) {
+        self.frame_times.clear();
+        self.scopes.clear();
+        self.totals = FrameTotals::default();
+    }
+
+    /// Summarizes all statistics accumulated since the last reset.
+    pub fn summary(&self) -> AccumulatedStatistics {
+        let totals = AccumulatedStatistics {
+            objects_submitted: self.totals.objects_submitted,
+            objects_culled: self.totals.objects_culled,
+            draw_calls: self.totals.draw_calls,
+            triangles: self.totals.triangles,
+            ..AccumulatedStatistics::default()
+        };
+        if self.frame_times.is_empty() {
+            return totals;
+        }
+
+        // At most `window` frame times, so sorting a copy stays cheap.
+        let mut sorted: Vec<f64> = self.frame_times.iter().copied().collect();
+        sorted.sort_by(f64::total_cmp);
+
+        // Nearest-rank percentile.
+        let percentile = |p: f64| {
+            let rank = (p * sorted.len() as f64).ceil() as usize;
+            sorted[rank.clamp(1, sorted.len()) - 1]
+        };
+
+        let mut scopes: Vec<_> = self
+            .scopes
+            .iter()
+            .map(|(label, scope)| AccumulatedScope {
+                label: label.clone(),
+                count: scope.count,
+                total_time: scope.total_time,
+                avg_time: scope.total_time / scope.count as f64,
+            })
+            .collect();
+        scopes.sort_by(|a, b| a.label.cmp(&b.label));
+
+        AccumulatedStatistics {
+            frames: sorted.len() as u32,
+            min_frame_time: sorted[0],
+            max_frame_time: sorted[sorted.len() - 1],
+            avg_frame_time: sorted.iter().sum::<f64>() / sorted.len() as f64,
+            p95_frame_time: percentile(0.95),
+            p99_frame_time: percentile(0.99),
+            scopes,
+            ..totals
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Range;
+
+    use wgpu_profiler::GpuTimerQueryResult;
+
+    use super::{FrameStatistics, StatisticsAccumulator};
+
+    fn query(label: &str, time: Range<f64>) -> GpuTimerQueryResult {
+        GpuTimerQueryResult {
+            label: label.into(),
+            pid: 0,
+            tid: std::thread::current().id(),
+            time,
+            nested_queries: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn empty() {
+        let mut acc = StatisticsAccumulator::new();
+        acc.accumulate(&Vec::new());
+
+        assert_eq!(acc.summary().frames, 0);
+    }
+
+    #[test]
+    fn frame_times() {
+        let mut acc = StatisticsAccumulator::new();
+        for frame in 1..=100 {
+            let frame_time = frame as f64;
+            acc.accumulate(&vec![query("a", 10.0..11.0), query("b", 11.0..10.0 + frame_time)]);
+        }
+
+        let summary = acc.summary();
+        assert_eq!(summary.frames, 100);
+        assert_eq!(summary.min_frame_time, 1.0);
+        assert_eq!(summary.max_frame_time, 100.0);
+        assert_eq!(summary.avg_frame_time, 50.5);
+        assert_eq!(summary.p95_frame_time, 95.0);
+        assert_eq!(summary.p99_frame_time, 99.0);
+
+        assert_eq!(summary.scopes.len(), 2);
+        assert_eq!(summary.scopes[0].label, "a");
+        assert_eq!(summary.scopes[0].count, 100);
+        assert_eq!(summary.scopes[0].avg_time, 1.0);
+
+        acc.reset();
+        assert_eq!(acc.summary(), Default::default());
+    }
+
+    #[test]
+    fn window() {
+        let mut acc = StatisticsAccumulator::with_window(10);
+        for frame in 1..=100 {
+            acc.accumulate(&vec![query("a", 0.0..frame as f64)]);
+        }
+
+        // Only the last 10 frames are kept.
+        let summary = acc.summary();
+        assert_eq!(summary.frames, 10);
+        assert_eq!(summary.min_frame_time, 91.0);
+        assert_eq!(summary.max_frame_time, 100.0);
+        assert_eq!(summary.scopes[0].count, 100);
+
+        acc.set_window(4);
+        let summary = acc.summary();
+        assert_eq!(summary.frames, 4);
+        assert_eq!(summary.min_frame_time, 97.0);
+    }
+
+    #[test]
+    fn totals() {
+        let mut acc = StatisticsAccumulator::new();
+        let frame = FrameStatistics {
+            objects_submitted: 3,
+            objects_culled: 2,
+            draw_calls: 4,
+            triangles: 1 << 32,
+            ..Default::default()
+        };
+        acc.accumulate_frame(&frame);
+        acc.accumulate_frame(&frame);
+
+        // Counts are accumulated even without gpu timings.
+        let summary = acc.summary();
+        assert_eq!(summary.frames, 0);
+        assert_eq!(summary.objects_submitted, 6);
+        assert_eq!(summary.objects_culled, 4);
+        assert_eq!(summary.draw_calls, 8);
+        assert_eq!(summary.triangles, 1 << 33);
+
+        acc.reset();
+        assert_eq!(acc.summary(), Default::default());
+    }
+}